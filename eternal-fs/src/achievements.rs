@@ -0,0 +1,224 @@
+//! Achievements earned along the journey.
+//!
+//! Every player earns their own. They are shown in the read-only
+//! `achievements/` directory at the root of the world, one file each,
+//! dated by the time they were earned.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{Local, Timelike};
+use tracing::info;
+
+use crate::engine::GeneratedFile;
+use crate::packs::World;
+use crate::philosophy::{GameStage, Philosophy};
+use crate::players::PlayerState;
+
+/// The generated directory the achievements are shown in
+pub(crate) const ACHIEVEMENTS_DIR: &str = "achievements";
+
+/// Something a player can earn
+struct Achievement {
+    id: &'static str,
+    title: &'static str,
+    description: &'static str,
+}
+
+const FIRST_ANSWER: &str = "first-answer";
+const THOUSAND_WORDS: &str = "thousand-words";
+const NIGHT_OWL: &str = "night-owl";
+const CARTOGRAPHER: &str = "cartographer";
+const ENLIGHTENED: &str = "enlightened";
+const DAILY: &str = "daily";
+const SEVEN_DAYS: &str = "seven-days";
+const SIDE_QUEST: &str = "side-quest";
+const EVERY_QUEST: &str = "every-quest";
+const EASTER_EGG: &str = "easter-egg";
+const EVERY_EGG: &str = "every-egg";
+
+const ACHIEVEMENTS: &[Achievement] = &[
+    Achievement {
+        id: FIRST_ANSWER,
+        title: "First Steps",
+        description: "The world accepted your first answer.",
+    },
+    Achievement {
+        id: THOUSAND_WORDS,
+        title: "A Thousand Words",
+        description: "You wrote an answer of a thousand words or more.",
+    },
+    Achievement {
+        id: NIGHT_OWL,
+        title: "Night Owl",
+        description: "You answered a question at three in the morning.",
+    },
+    Achievement {
+        id: CARTOGRAPHER,
+        title: "Cartographer",
+        description: "You visited the directory of every stage.",
+    },
+    Achievement {
+        id: ENLIGHTENED,
+        title: "Enlightened",
+        description: "All paths converged in understanding.",
+    },
+    Achievement {
+        id: DAILY,
+        title: "Daily Devotion",
+        description: "You met a daily challenge.",
+    },
+    Achievement {
+        id: SEVEN_DAYS,
+        title: "Seven Days",
+        description: "You met the daily challenges of seven days.",
+    },
+    Achievement {
+        id: SIDE_QUEST,
+        title: "Off the Path",
+        description: "You completed a side quest.",
+    },
+    Achievement {
+        id: EVERY_QUEST,
+        title: "Wanderer",
+        description: "You completed every side quest of the world.",
+    },
+    Achievement {
+        id: EASTER_EGG,
+        title: "Curious Mind",
+        description: "You found an easter egg.",
+    },
+    Achievement {
+        id: EVERY_EGG,
+        title: "Nothing Left Unturned",
+        description: "You found every easter egg of the world.",
+    },
+];
+
+/// Daily challenges met for [`SEVEN_DAYS`]
+const WEEK: usize = 7;
+
+/// Words an answer needs for [`THOUSAND_WORDS`]
+const THOUSAND: usize = 1000;
+
+impl PlayerState {
+    /// Awards the achievement `id` unless the player has it already
+    fn award(&mut self, id: &str) {
+        if self.achievements.contains_key(id) {
+            return;
+        }
+        let now = SystemTime::now();
+        self.achievements.insert(id.to_string(), now);
+        self.philosophical_state
+            .note_event(now, format!("Achievement earned: {}", id));
+        info!("Achievement earned: {}", id);
+    }
+
+    /// Awards what an answer earned. `accepted` tells whether the world
+    /// took the answer.
+    pub(crate) fn award_answer(&mut self, response: &str, accepted: bool) {
+        if accepted {
+            self.award(FIRST_ANSWER);
+        }
+        if response.split_whitespace().count() >= THOUSAND {
+            self.award(THOUSAND_WORDS);
+        }
+        if Local::now().hour() == 3 {
+            self.award(NIGHT_OWL);
+        }
+        if matches!(self.current_stage, GameStage::Enlightened) {
+            self.award(ENLIGHTENED);
+        }
+    }
+
+    /// Awards what meeting a daily challenge earned
+    pub(crate) fn award_daily(&mut self) {
+        self.award(DAILY);
+        if self.daily_completed.len() >= WEEK {
+            self.award(SEVEN_DAYS);
+        }
+    }
+
+    /// Awards what completing a side quest of `world` earned
+    pub(crate) fn award_quest(&mut self, world: &World) {
+        self.award(SIDE_QUEST);
+        if world
+            .quests
+            .iter()
+            .all(|quest| self.quests_completed.contains_key(&quest.dir))
+        {
+            self.award(EVERY_QUEST);
+        }
+    }
+
+    /// Awards what finding an easter egg of `world` earned
+    pub(crate) fn award_egg(&mut self, world: &World) {
+        self.award(EASTER_EGG);
+        if world
+            .eggs
+            .iter()
+            .all(|egg| self.eggs_found.contains(&egg.name))
+        {
+            self.award(EVERY_EGG);
+        }
+    }
+
+    /// Notes a visit to the stage directory `dir`
+    fn visit(&mut self, world: &World, dir: &str) {
+        if !self.visited.insert(dir.to_string()) {
+            return;
+        }
+        if world
+            .stages
+            .iter()
+            .all(|stage| self.visited.contains(&stage.dir))
+        {
+            self.award(CARTOGRAPHER);
+        }
+    }
+
+    /// The files of `achievements/`, one for every achievement earned
+    fn achievement_files(&self) -> Vec<GeneratedFile> {
+        ACHIEVEMENTS
+            .iter()
+            .filter_map(|achievement| {
+                let earned = *self.achievements.get(achievement.id)?;
+                let contents = format!(
+                    "{}\n{}\n\n{}\n\nEarned: {}\n",
+                    achievement.title,
+                    "=".repeat(achievement.title.len()),
+                    achievement.description,
+                    self.describe_time(earned)
+                );
+                Some(GeneratedFile {
+                    name: format!("{}.txt", achievement.id),
+                    contents: contents.into_bytes(),
+                    mtime: earned,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Philosophy {
+    /// Notes that the player making the call looked up `path`
+    pub(crate) fn record_visit(&mut self, path: &Path) {
+        let Some(dir) = path.to_str() else {
+            return;
+        };
+        if self.world.stage(dir).is_none() {
+            return;
+        }
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        player.visit(&self.world, dir);
+    }
+
+    /// The achievements of the player making the call, as files
+    pub(crate) fn achievements(&self) -> Vec<GeneratedFile> {
+        self.players
+            .get(&self.player_identity.current())
+            .map(PlayerState::achievement_files)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,583 @@
+//! Worlds shipped as a single archive, served read-only.
+//!
+//! `eternal_fs world.zip`, or `--export NAME=world.tar.gz`, serves what a
+//! `.zip`, `.tar.gz`, `.tgz` or `.tar` holds as a read-only tree. The
+//! archive is indexed once as it opens, from the central directory of a
+//! zip or the headers of a tar, and lookups and listings are answered
+//! from the index. Files are decompressed as they are read: what is
+//! stored as it is, is read in place, and compressed data through a few
+//! streams kept open where the last reads stopped, so that a file, or a
+//! `.tar.gz` from front to back, is only decompressed once when it is
+//! read in order. Nothing can be changed, and the game is not played in
+//! an archive.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::NaiveDate;
+use flate2::read::{DeflateDecoder, GzDecoder};
+use tracing::{debug, warn};
+
+use nfsserve::nfs::*;
+use nfsserve::vfs::{DirEntry, NFSFileSystem, ReadDirResult, VFSCapabilities};
+
+use crate::generated::to_nfstime;
+use crate::xattrs::paginate;
+
+/// Decompressing streams kept open at most
+const MAX_STREAMS: usize = 8;
+
+/// Where the bytes of a file are in the archive
+#[derive(Debug, Clone, Copy)]
+enum Data {
+    /// Stored as they are, from this offset of the archive on
+    Stored(u64),
+    /// A deflated zip entry, from this offset of the archive on, this many
+    /// bytes long
+    Deflated(u64, u64),
+    /// At this offset of the decompressed `.tar.gz`
+    Gzipped(u64),
+    /// Compressed in a way which can not be read
+    Unsupported,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Dir(BTreeMap<Vec<u8>, fileid3>),
+    File(Data),
+    Symlink(Vec<u8>),
+}
+
+/// A file, directory or symlink of the archive
+#[derive(Debug)]
+struct Node {
+    parent: fileid3,
+    attr: fattr3,
+    kind: Kind,
+}
+
+/// What the archive holds, by fileid. The root is fileid 0.
+#[derive(Debug)]
+struct Index {
+    nodes: Vec<Node>,
+    /// Attributes of the directories only implied by the paths below them
+    base: fattr3,
+}
+
+impl Index {
+    fn new(base: fattr3) -> Index {
+        Index {
+            nodes: vec![Node {
+                parent: 0,
+                attr: fattr3 { fileid: 0, ..base },
+                kind: Kind::Dir(BTreeMap::new()),
+            }],
+            base,
+        }
+    }
+
+    /// Adds what the archive holds at `path`, and the directories above
+    /// it. Whatever comes later in the archive takes the place of what was
+    /// at the same path before, as tar does.
+    fn add(&mut self, path: &[u8], mut attr: fattr3, kind: Kind) -> Option<fileid3> {
+        let path = Path::new(OsStr::from_bytes(path));
+        let mut names = Vec::new();
+        for part in path.components() {
+            match part {
+                Component::Normal(name) => names.push(name.as_bytes().to_vec()),
+                Component::CurDir | Component::RootDir => {}
+                // nothing is served above the root
+                _ => return None,
+            }
+        }
+        let (last, dirs) = names.split_last()?;
+        let mut dirid = 0;
+        for name in dirs {
+            dirid = match self.child(dirid, name) {
+                Some(id) if matches!(self.nodes[id as usize].kind, Kind::Dir(_)) => id,
+                _ => self.insert(dirid, name, self.base, Kind::Dir(BTreeMap::new())),
+            };
+        }
+        match (self.child(dirid, last), &kind) {
+            // a directory entry after the files in it only sets its attributes
+            (Some(id), Kind::Dir(_)) if matches!(self.nodes[id as usize].kind, Kind::Dir(_)) => {
+                attr.fileid = id;
+                self.nodes[id as usize].attr = attr;
+                Some(id)
+            }
+            _ => Some(self.insert(dirid, last, attr, kind)),
+        }
+    }
+
+    fn child(&self, dirid: fileid3, name: &[u8]) -> Option<fileid3> {
+        match &self.nodes[dirid as usize].kind {
+            Kind::Dir(children) => children.get(name).copied(),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, dirid: fileid3, name: &[u8], mut attr: fattr3, kind: Kind) -> fileid3 {
+        let id = self.nodes.len() as fileid3;
+        attr.fileid = id;
+        self.nodes.push(Node {
+            parent: dirid,
+            attr,
+            kind,
+        });
+        if let Kind::Dir(children) = &mut self.nodes[dirid as usize].kind {
+            children.insert(name.to_vec(), id);
+        }
+        id
+    }
+
+    fn node(&self, id: fileid3) -> Result<&Node, nfsstat3> {
+        self.nodes
+            .get(usize::try_from(id).map_err(|_| nfsstat3::NFS3ERR_STALE)?)
+            .ok_or(nfsstat3::NFS3ERR_STALE)
+    }
+}
+
+/// Attributes of an object of the archive. Nothing in it can be written.
+fn archive_attr(base: fattr3, ftype: ftype3, mode: u32, size: u64, mtime: SystemTime) -> fattr3 {
+    let time = to_nfstime(mtime);
+    fattr3 {
+        ftype,
+        mode: mode & 0o7555,
+        nlink: match ftype {
+            ftype3::NF3DIR => 2,
+            _ => 1,
+        },
+        size,
+        used: size,
+        atime: time,
+        mtime: time,
+        ctime: time,
+        ..base
+    }
+}
+
+/// The time a zip entry was last modified
+fn zip_time(time: zip::DateTime) -> SystemTime {
+    NaiveDate::from_ymd_opt(time.year() as i32, time.month() as u32, time.day() as u32)
+        .and_then(|date| {
+            date.and_hms_opt(
+                time.hour() as u32,
+                time.minute() as u32,
+                time.second() as u32,
+            )
+        })
+        .and_then(|time| u64::try_from(time.and_utc().timestamp()).ok())
+        .map_or(SystemTime::UNIX_EPOCH, |secs| {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+        })
+}
+
+/// Indexes the central directory of a zip
+fn index_zip(file: &File, base: fattr3) -> io::Result<Index> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(io::Error::other)?;
+    let mut index = Index::new(base);
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index_raw(i).map_err(io::Error::other)?;
+        let mtime = entry
+            .last_modified()
+            .map_or(SystemTime::UNIX_EPOCH, zip_time);
+        let mode = entry.unix_mode().unwrap_or(0o644);
+        let (attr, kind) = if entry.is_dir() {
+            let mode = entry.unix_mode().unwrap_or(0o755);
+            let attr = archive_attr(base, ftype3::NF3DIR, mode, 0, mtime);
+            (attr, Kind::Dir(BTreeMap::new()))
+        } else if mode & 0o170000 == 0o120000
+            && entry.compression() == zip::CompressionMethod::Stored
+        {
+            let mut target = Vec::new();
+            entry.read_to_end(&mut target)?;
+            let attr = archive_attr(base, ftype3::NF3LNK, 0o777, target.len() as u64, mtime);
+            (attr, Kind::Symlink(target))
+        } else {
+            let data = match entry.compression() {
+                zip::CompressionMethod::Stored => Data::Stored(entry.data_start()),
+                zip::CompressionMethod::Deflated => {
+                    Data::Deflated(entry.data_start(), entry.compressed_size())
+                }
+                method => {
+                    warn!(
+                        "{:?} is compressed with {}, which can not be read",
+                        entry.name(),
+                        method
+                    );
+                    Data::Unsupported
+                }
+            };
+            let attr = archive_attr(base, ftype3::NF3REG, mode, entry.size(), mtime);
+            (attr, Kind::File(data))
+        };
+        let name = entry.name_raw().to_vec();
+        if index.add(&name, attr, kind).is_none() {
+            warn!(
+                "Leaving out {:?}, which is not below the root",
+                entry.name()
+            );
+        }
+    }
+    Ok(index)
+}
+
+/// Indexes the headers of a tar, read from `reader`. Data is found at its
+/// offset in `reader` with `at`.
+fn index_tar(reader: impl Read, base: fattr3, at: fn(u64) -> Data) -> io::Result<Index> {
+    let mut archive = tar::Archive::new(reader);
+    let mut index = Index::new(base);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let path = entry.path_bytes().into_owned();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(header.mtime().unwrap_or(0));
+        let mode = header.mode().unwrap_or(0o644);
+        let (attr, kind) = match header.entry_type() {
+            tar::EntryType::Directory => (
+                archive_attr(base, ftype3::NF3DIR, mode, 0, mtime),
+                Kind::Dir(BTreeMap::new()),
+            ),
+            tar::EntryType::Regular | tar::EntryType::Continuous => (
+                archive_attr(base, ftype3::NF3REG, mode, entry.size(), mtime),
+                Kind::File(at(entry.raw_file_position())),
+            ),
+            tar::EntryType::Symlink => {
+                let target = entry.link_name_bytes().unwrap_or_default().into_owned();
+                let attr = archive_attr(base, ftype3::NF3LNK, 0o777, target.len() as u64, mtime);
+                (attr, Kind::Symlink(target))
+            }
+            tar::EntryType::Link => {
+                // a hard link reads as the file it links to
+                let target = entry.link_name_bytes().unwrap_or_default().into_owned();
+                let found = Path::new(OsStr::from_bytes(&target))
+                    .components()
+                    .filter_map(|part| match part {
+                        Component::Normal(name) => Some(name.as_bytes()),
+                        _ => None,
+                    })
+                    .try_fold(0, |dirid, name| index.child(dirid, name));
+                let Some(Node {
+                    attr,
+                    kind: Kind::File(data),
+                    ..
+                }) = found.map(|id| &index.nodes[id as usize])
+                else {
+                    debug!(
+                        "Leaving out the hard link {:?}",
+                        String::from_utf8_lossy(&path)
+                    );
+                    continue;
+                };
+                (*attr, Kind::File(*data))
+            }
+            other => {
+                debug!(
+                    "Leaving out {:?}, a {:?}",
+                    String::from_utf8_lossy(&path),
+                    other
+                );
+                continue;
+            }
+        };
+        if index.add(&path, attr, kind).is_none() {
+            warn!(
+                "Leaving out {:?}, which is not below the root",
+                String::from_utf8_lossy(&path)
+            );
+        }
+    }
+    Ok(index)
+}
+
+/// A decompressing stream, and how far into it it was read
+struct Stream {
+    /// The entry the stream is of, or 0 for a whole `.tar.gz`
+    of: fileid3,
+    pos: u64,
+    reader: Box<dyn Read + Send>,
+}
+
+/// Serves the contents of an archive as a read-only file system
+#[derive(Clone)]
+pub struct ArchiveFS {
+    path: PathBuf,
+    file: Arc<File>,
+    index: Arc<Index>,
+    streams: Arc<Mutex<Vec<Stream>>>,
+}
+
+impl std::fmt::Debug for ArchiveFS {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArchiveFS")
+            .field("path", &self.path)
+            .field("objects", &self.index.nodes.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ArchiveFS {
+    /// Opens and indexes the archive at `path`
+    pub fn open(path: &Path) -> io::Result<ArchiveFS> {
+        let file = File::open(path)?;
+        let meta = file.metadata()?;
+        let time = to_nfstime(meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+        let base = fattr3 {
+            ftype: ftype3::NF3DIR,
+            mode: 0o555,
+            nlink: 2,
+            uid: meta.uid(),
+            gid: meta.gid(),
+            size: 0,
+            used: 0,
+            rdev: specdata3::default(),
+            fsid: 0,
+            fileid: 0,
+            atime: time,
+            mtime: time,
+            ctime: time,
+        };
+        let name = path.to_string_lossy();
+        let index = if name.ends_with(".zip") {
+            index_zip(&file, base)?
+        } else if name.ends_with(".tar") {
+            index_tar(BufReader::new(&file), base, Data::Stored)?
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            index_tar(GzDecoder::new(BufReader::new(&file)), base, Data::Gzipped)?
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not a .zip, .tar.gz, .tgz or .tar", path),
+            ));
+        };
+        debug!("Indexed {} objects of {:?}", index.nodes.len(), path);
+        Ok(ArchiveFS {
+            path: path.to_path_buf(),
+            file: Arc::new(file),
+            index: Arc::new(index),
+            streams: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// A new stream of the entry `id`, or of the whole `.tar.gz`
+    fn open_stream(&self, id: fileid3, data: Data) -> io::Result<Stream> {
+        let mut file = File::open(&self.path)?;
+        let reader: Box<dyn Read + Send> = match data {
+            Data::Deflated(start, len) => {
+                file.seek(SeekFrom::Start(start))?;
+                Box::new(DeflateDecoder::new(BufReader::new(file.take(len))))
+            }
+            _ => Box::new(GzDecoder::new(BufReader::new(file))),
+        };
+        Ok(Stream {
+            of: id,
+            pos: 0,
+            reader,
+        })
+    }
+
+    /// Reads `count` bytes from `offset` of the decompressed stream of the
+    /// entry `id`, or of the whole `.tar.gz`, going on from where an open
+    /// stream stopped if one stopped before `offset`
+    fn read_stream(
+        &self,
+        id: fileid3,
+        data: Data,
+        offset: u64,
+        count: usize,
+    ) -> io::Result<Vec<u8>> {
+        let taken = {
+            let mut streams = self.streams.lock().unwrap();
+            let found = streams
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.of == id && s.pos <= offset)
+                .max_by_key(|(_, s)| s.pos)
+                .map(|(i, _)| i);
+            found.map(|i| streams.remove(i))
+        };
+        let mut stream = match taken {
+            Some(stream) => stream,
+            None => self.open_stream(id, data)?,
+        };
+        let skipped = io::copy(
+            &mut stream.reader.by_ref().take(offset - stream.pos),
+            &mut io::sink(),
+        )?;
+        stream.pos += skipped;
+        let mut buf = Vec::with_capacity(count);
+        stream
+            .reader
+            .by_ref()
+            .take(count as u64)
+            .read_to_end(&mut buf)?;
+        stream.pos += buf.len() as u64;
+        let mut streams = self.streams.lock().unwrap();
+        if streams.len() >= MAX_STREAMS {
+            streams.remove(0);
+        }
+        streams.push(stream);
+        Ok(buf)
+    }
+
+    fn read_file(&self, id: fileid3, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let node = self.index.node(id)?;
+        let data = match node.kind {
+            Kind::File(data) => data,
+            Kind::Dir(_) => return Err(nfsstat3::NFS3ERR_ISDIR),
+            Kind::Symlink(_) => return Err(nfsstat3::NFS3ERR_INVAL),
+        };
+        let size = node.attr.size;
+        if offset >= size {
+            return Ok((Vec::new(), true));
+        }
+        let count = (count as u64).min(size - offset) as usize;
+        let read = match data {
+            Data::Stored(start) => {
+                let mut buf = vec![0; count];
+                self.file
+                    .read_exact_at(&mut buf, start + offset)
+                    .map(|_| buf)
+            }
+            Data::Deflated(..) => self.read_stream(id, data, offset, count),
+            Data::Gzipped(start) => self.read_stream(0, data, start + offset, count),
+            Data::Unsupported => return Err(nfsstat3::NFS3ERR_IO),
+        };
+        let buf = read.map_err(|e| {
+            warn!("Unable to read fileid {} of {:?}: {:?}", id, self.path, e);
+            nfsstat3::NFS3ERR_IO
+        })?;
+        let eof = offset + buf.len() as u64 >= size;
+        Ok((buf, eof))
+    }
+}
+
+#[async_trait]
+impl NFSFileSystem for ArchiveFS {
+    fn capabilities(&self) -> VFSCapabilities {
+        VFSCapabilities::ReadOnly
+    }
+
+    fn root_dir(&self) -> fileid3 {
+        0
+    }
+
+    async fn lookup(&self, dirid: fileid3, filename: &filename3) -> Result<fileid3, nfsstat3> {
+        let dir = self.index.node(dirid)?;
+        if !matches!(dir.kind, Kind::Dir(_)) {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        match &filename[..] {
+            b"." => Ok(dirid),
+            b".." => Ok(dir.parent),
+            name => self.index.child(dirid, name).ok_or(nfsstat3::NFS3ERR_NOENT),
+        }
+    }
+
+    async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
+        Ok(self.index.node(id)?.attr)
+    }
+
+    async fn setattr(&self, _id: fileid3, _setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn read(&self, id: fileid3, offset: u64, count: u32) -> Result<(Bytes, bool), nfsstat3> {
+        let fs = self.clone();
+        let (data, eof) = tokio::task::spawn_blocking(move || fs.read_file(id, offset, count))
+            .await
+            .map_err(|_| nfsstat3::NFS3ERR_IO)??;
+        Ok((data.into(), eof))
+    }
+
+    async fn write(&self, _id: fileid3, _offset: u64, _data: &[u8]) -> Result<fattr3, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn create(
+        &self,
+        _dirid: fileid3,
+        _filename: &filename3,
+        _attr: sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn create_exclusive(
+        &self,
+        _dirid: fileid3,
+        _filename: &filename3,
+    ) -> Result<fileid3, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn mkdir(
+        &self,
+        _dirid: fileid3,
+        _dirname: &filename3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn remove(&self, _dirid: fileid3, _filename: &filename3) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn rename(
+        &self,
+        _from_dirid: fileid3,
+        _from_filename: &filename3,
+        _to_dirid: fileid3,
+        _to_filename: &filename3,
+    ) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn readdir(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<ReadDirResult, nfsstat3> {
+        let Kind::Dir(children) = &self.index.node(dirid)?.kind else {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        };
+        let entries = children.iter().map(|(name, id)| (*id, name)).collect();
+        let (page, end) = paginate(entries, start_after, max_entries);
+        let entries = page
+            .into_iter()
+            .map(|(fileid, name)| DirEntry {
+                fileid,
+                name: name.as_slice().into(),
+                attr: self.index.nodes[fileid as usize].attr,
+            })
+            .collect();
+        Ok(ReadDirResult { entries, end })
+    }
+
+    async fn symlink(
+        &self,
+        _dirid: fileid3,
+        _linkname: &filename3,
+        _symlink: &nfspath3,
+        _attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn readlink(&self, id: fileid3) -> Result<nfspath3, nfsstat3> {
+        match &self.index.node(id)?.kind {
+            Kind::Symlink(target) => Ok(target.as_slice().into()),
+            _ => Err(nfsstat3::NFS3ERR_INVAL),
+        }
+    }
+}
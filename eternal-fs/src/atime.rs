@@ -0,0 +1,126 @@
+//! When reading a file changes its access time.
+//!
+//! As with the mount options of the same names, a world is served with
+//! `noatime`, where reads leave access times alone, `relatime`, where a
+//! read changes the access time only if it is older than the last change
+//! of the file or a day old, or `strictatime`, where every read does.
+//! Updating access times turns every read into a write to the host, so
+//! worlds are served with `noatime` unless asked otherwise.
+//!
+//! Host files are opened for clients without changing their access time,
+//! where the host lets the server do so, which is for the files it owns,
+//! so that the access time only changes as the policy says and not as
+//! the host mounts the world. Pages read ahead and the holes of sparse
+//! files are served without reading the host at all, so the access time
+//! is set explicitly after a read instead.
+
+use std::fs::Metadata;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(target_os = "linux")]
+use nix::fcntl::OFlag;
+
+/// How long `relatime` leaves an access time which is newer than the
+/// last change of its file alone
+const RELATIME_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// When reading a file changes its access time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AtimePolicy {
+    /// Reads never change it
+    #[default]
+    NoAtime,
+    /// Reads change it if it is older than the last change of the file,
+    /// or a day old
+    RelAtime,
+    /// Every read changes it
+    StrictAtime,
+}
+
+impl FromStr for AtimePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "noatime" => Ok(AtimePolicy::NoAtime),
+            "relatime" => Ok(AtimePolicy::RelAtime),
+            "strictatime" => Ok(AtimePolicy::StrictAtime),
+            _ => Err(format!("unknown atime policy {:?}", s)),
+        }
+    }
+}
+
+impl AtimePolicy {
+    /// Whether reading the file with `meta` now changes its access time
+    pub(crate) fn updates(&self, meta: &Metadata) -> bool {
+        match self {
+            AtimePolicy::NoAtime => false,
+            AtimePolicy::StrictAtime => true,
+            AtimePolicy::RelAtime => {
+                // setting the access time changes the ctime to the same
+                // time, which is then not a change of the file
+                let atime = (meta.atime(), meta.atime_nsec());
+                if atime <= (meta.mtime(), meta.mtime_nsec())
+                    || atime < (meta.ctime(), meta.ctime_nsec())
+                {
+                    return true;
+                }
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                now.as_secs() as i64 - meta.atime() >= RELATIME_INTERVAL.as_secs() as i64
+            }
+        }
+    }
+}
+
+/// Opens `path` as `options` say, without changing its access time as
+/// it is read if the host lets the server, or as usual if it does not
+pub(crate) fn open(options: &std::fs::OpenOptions, path: &Path) -> io::Result<std::fs::File> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        match options
+            .clone()
+            .custom_flags(OFlag::O_NOATIME.bits())
+            .open(path)
+        {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+            opened => return opened,
+        }
+    }
+    options.open(path)
+}
+
+/// Like `open`, for the handles of the handle pool
+pub(crate) async fn open_async(
+    options: &tokio::fs::OpenOptions,
+    path: &Path,
+) -> io::Result<tokio::fs::File> {
+    #[cfg(target_os = "linux")]
+    {
+        match options
+            .clone()
+            .custom_flags(OFlag::O_NOATIME.bits())
+            .open(path)
+            .await
+        {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+            opened => return opened,
+        }
+    }
+    options.open(path).await
+}
+
+/// Sets the access time of the open file `fd` to now, leaving its
+/// modification time alone
+pub(crate) fn touch(fd: impl std::os::fd::AsFd) -> io::Result<()> {
+    use nix::sys::stat::futimens;
+    use nix::sys::time::TimeSpec;
+    futimens(fd, &TimeSpec::UTIME_NOW, &TimeSpec::UTIME_OMIT)?;
+    Ok(())
+}
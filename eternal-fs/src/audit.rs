@@ -0,0 +1,257 @@
+//! An audit log of every NFS call.
+//!
+//! With `--audit-log FILE` (or `audit_log = "FILE"` under `[server]`) every
+//! NFS call is appended to `FILE` as a line of JSON once it is answered:
+//! when it was made, the operation, the client address and uid, the fileid
+//! and the path it concerned, the status it was answered with and how long
+//! it took. The log is only ever appended to. As it grows beyond
+//! `--audit-max-mb` MiB it is rotated: `FILE` becomes `FILE.1`, `FILE.1`
+//! becomes `FILE.2`, and so on, and the oldest beyond [`ROTATED_KEPT`] is
+//! removed.
+//!
+//! The calls are taken from the spans nfsserve serves them in, so the log
+//! is kept by a [`tracing`] layer, [`AuditLayer`], rather than by the file
+//! system. `--replay-audit FILE` replays a log, the rotated ones first, as
+//! text.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::{Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Size the log is rotated at, unless configured otherwise
+pub const DEFAULT_AUDIT_MAX_MB: u64 = 64;
+
+/// Rotated logs kept besides the one written to
+pub const ROTATED_KEPT: usize = 5;
+
+/// A call, as it is logged
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// When the call was made, in RFC 3339
+    pub time: String,
+    /// The NFS procedure, such as `lookup` or `write`
+    pub op: String,
+    pub client: String,
+    pub uid: u32,
+    pub fileid: Option<u64>,
+    /// The host path the call concerned, if the file system named it
+    pub path: Option<String>,
+    /// The status the call was answered with, such as `NFS3_OK`
+    pub status: Option<String>,
+    pub latency_us: u64,
+}
+
+impl Visit for AuditRecord {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "uid" => self.uid = value as u32,
+            "fileid" => self.fileid = Some(value),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let value = format!("{:?}", value);
+        match field.name() {
+            "op" => self.op = value.trim_start_matches("NFSPROC3_").to_ascii_lowercase(),
+            "client" => self.client = value,
+            "path" => self.path = Some(value),
+            "status" => self.status = Some(value),
+            _ => {}
+        }
+    }
+}
+
+/// A call being served
+struct Call {
+    record: AuditRecord,
+    started: Instant,
+}
+
+/// The file calls are appended to
+#[derive(Debug)]
+struct AuditLog {
+    path: PathBuf,
+    file: File,
+    /// Bytes in the file
+    written: u64,
+    max_bytes: u64,
+}
+
+/// `<path>.<n>`
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn open_log(path: &Path) -> io::Result<(File, u64)> {
+    let file = File::options().create(true).append(true).open(path)?;
+    let written = file.metadata()?.len();
+    Ok((file, written))
+}
+
+impl AuditLog {
+    fn append(&mut self, record: &AuditRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        // a line at once, so that lines are never mixed up
+        self.file.write_all(&line)?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    /// Moves the log aside, and starts another
+    fn rotate(&mut self) -> io::Result<()> {
+        let _ = std::fs::remove_file(rotated(&self.path, ROTATED_KEPT));
+        for n in (1..ROTATED_KEPT).rev() {
+            let _ = std::fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1));
+        }
+        std::fs::rename(&self.path, rotated(&self.path, 1))?;
+        (self.file, self.written) = open_log(&self.path)?;
+        Ok(())
+    }
+}
+
+/// Appends every NFS call to the audit log as it is answered
+#[derive(Debug)]
+pub struct AuditLayer {
+    log: Mutex<AuditLog>,
+}
+
+impl AuditLayer {
+    /// Appends to the log at `path`, rotated as it grows beyond
+    /// `max_mb` MiB
+    pub fn open(path: &Path, max_mb: u64) -> io::Result<AuditLayer> {
+        let (file, written) = open_log(path)?;
+        Ok(AuditLayer {
+            log: Mutex::new(AuditLog {
+                path: path.to_path_buf(),
+                file,
+                written,
+                max_bytes: max_mb.max(1) * 1024 * 1024,
+            }),
+        })
+    }
+}
+
+impl<S> Layer<S> for AuditLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let metadata = attrs.metadata();
+        if metadata.name() != "nfs" || !metadata.target().starts_with("nfsserve") {
+            return;
+        }
+        let mut record = AuditRecord {
+            time: Local::now().to_rfc3339_opts(SecondsFormat::Micros, false),
+            ..Default::default()
+        };
+        attrs.record(&mut record);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Call {
+                record,
+                started: Instant::now(),
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(call) = extensions.get_mut::<Call>() {
+            values.record(&mut call.record);
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(mut call) = span.extensions_mut().remove::<Call>() else {
+            return;
+        };
+        call.record.latency_us = call.started.elapsed().as_micros() as u64;
+        let mut log = self.log.lock().unwrap();
+        if let Err(e) = log.append(&call.record) {
+            // not logged with tracing, which would come back here
+            eprintln!("Unable to append to the audit log {:?}: {}", log.path, e);
+        }
+    }
+}
+
+/// Writes the calls of the log at `path` to `out` as text, the rotated
+/// logs first, and what they came to
+pub fn replay(path: &Path, out: &mut impl Write) -> io::Result<()> {
+    let logs = (1..=ROTATED_KEPT)
+        .rev()
+        .map(|n| rotated(path, n))
+        .chain([path.to_path_buf()])
+        .filter(|log| log.exists());
+    let mut calls = 0;
+    // by operation: the calls, those which failed, and their latency
+    let mut ops: BTreeMap<String, (usize, usize, u64)> = BTreeMap::new();
+    for log in logs {
+        for line in BufReader::new(File::open(&log)?).lines() {
+            let line = line?;
+            let Ok(record) = serde_json::from_str::<AuditRecord>(&line) else {
+                writeln!(out, "? {}", line)?;
+                continue;
+            };
+            let status = record.status.as_deref().unwrap_or("-");
+            let concerned = match (&record.path, record.fileid) {
+                (Some(path), _) => path.clone(),
+                (None, Some(fileid)) => format!("fileid {}", fileid),
+                (None, None) => "-".to_string(),
+            };
+            writeln!(
+                out,
+                "{} {} uid {} {} {} -> {} in {}us",
+                record.time,
+                record.client,
+                record.uid,
+                record.op,
+                concerned,
+                status,
+                record.latency_us
+            )?;
+            calls += 1;
+            let op = ops.entry(record.op).or_default();
+            op.0 += 1;
+            if status != "NFS3_OK" && status != "-" {
+                op.1 += 1;
+            }
+            op.2 += record.latency_us;
+        }
+    }
+    writeln!(out, "{} calls", calls)?;
+    for (op, (calls, failed, latency)) in ops {
+        writeln!(
+            out,
+            "  {}: {} calls, {} failed, {}us on average",
+            op,
+            calls,
+            failed,
+            latency / calls as u64
+        )?;
+    }
+    Ok(())
+}
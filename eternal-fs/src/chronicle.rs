@@ -0,0 +1,149 @@
+//! The chronicle of a journey, written once it reaches enlightenment.
+//!
+//! `chronicle.md` at the root tells the journey as a single story: every
+//! path in the order it was walked, with its question, the answer which
+//! opened the way on, how the answer was revised later and what the world
+//! replied, followed by the events along the way. It is written once, when
+//! the player reaches enlightenment, and kept with their journey. Until
+//! then it is not there.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use tracing::{info, warn};
+
+use crate::history::HISTORY_DIR;
+use crate::packs::World;
+use crate::philosophy::{ArchivedAnswer, GameStage, Philosophy, ENLIGHTENMENT};
+use crate::players::PlayerState;
+
+/// The file the chronicle is written to
+pub(crate) const CHRONICLE_FILE: &str = "chronicle.md";
+
+/// `at` as the chronicle tells it
+fn date(at: SystemTime) -> String {
+    DateTime::<Local>::from(at)
+        .format("%Y-%m-%d at %H:%M")
+        .to_string()
+}
+
+/// `text` as a Markdown quote
+fn quote(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(|line| format!("> {}", line).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl PlayerState {
+    /// The chronicle of the journey of the player, as it stands
+    /// `drafts` are the earlier versions of the answers kept, by stage
+    fn chronicle(
+        &self,
+        world: &World,
+        drafts: &BTreeMap<String, usize>,
+        now: SystemTime,
+    ) -> String {
+        // the paths in the order they were walked, by their first answer
+        let mut paths: Vec<(&String, &Vec<ArchivedAnswer>)> = self
+            .answer_archive
+            .iter()
+            .filter(|(_, answers)| !answers.is_empty())
+            .collect();
+        paths.sort_by_key(|(location, answers)| (answers[0].archived_at, location.as_str()));
+
+        let mut out = format!(
+            "# The Chronicle of a Journey\n\n\
+             *Begun on {}, and ended in enlightenment on {} after {} paths.*\n",
+            date(self.started),
+            date(now),
+            paths.len()
+        );
+        for (i, (location, answers)) in paths.iter().enumerate() {
+            let first = &answers[0];
+            let title = match location.as_str() {
+                ENLIGHTENMENT => "Enlightenment".to_string(),
+                location => location.to_string(),
+            };
+            out.push_str(&format!("\n## {}. {}\n\n", i + 1, title));
+            if let Some(stage) = world.stage(location) {
+                out.push_str(&format!("{}\n\n", quote(&stage.def.question)));
+            }
+            out.push_str(&format!(
+                "On {} you answered, and the way opened:\n\n{}\n",
+                date(first.archived_at),
+                quote(&first.text)
+            ));
+            if let Some(last) = answers.last().filter(|_| answers.len() > 1) {
+                out.push_str(&format!(
+                    "\nYou returned to it {} time{}, and on {} wrote at last:\n\n{}\n",
+                    answers.len() - 1,
+                    if answers.len() == 2 { "" } else { "s" },
+                    date(last.archived_at),
+                    quote(&last.text)
+                ));
+            }
+            if let Some(&count) = drafts.get(location.as_str()).filter(|&&count| count > 0) {
+                out.push_str(&format!(
+                    "\n{} earlier draft{} of your answer {} kept in {}/{}/.\n",
+                    count,
+                    if count == 1 { "" } else { "s" },
+                    if count == 1 { "is" } else { "are" },
+                    location,
+                    HISTORY_DIR
+                ));
+            }
+            if let Some(stage) = world.stage(location) {
+                out.push_str(&format!(
+                    "\nThe world replied: *{}*\n",
+                    stage.def.reply.trim()
+                ));
+            }
+        }
+        let events = &self.philosophical_state.timeline_events;
+        if !events.is_empty() {
+            out.push_str("\n## Along the Way\n\n");
+            for (at, event) in events {
+                out.push_str(&format!("- {}: {}\n", date(*at), event));
+            }
+        }
+        out
+    }
+}
+
+impl Philosophy {
+    /// Writes the chronicle of the player making the call, if they just
+    /// reached enlightenment
+    pub(crate) fn write_chronicle(&mut self) {
+        let drafts: BTreeMap<String, usize> = self
+            .world
+            .stages
+            .iter()
+            .map(|stage| (stage.dir.clone(), self.drafts(&stage.dir)))
+            .collect();
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        if player.chronicle.is_some() || !matches!(player.current_stage, GameStage::Enlightened) {
+            return;
+        }
+        let chronicle = player.chronicle(&self.world, &drafts, SystemTime::now());
+        player.chronicle = Some(chronicle.clone());
+        info!("The chronicle of the journey is written");
+        if let Err(e) = self.create_special_file(CHRONICLE_FILE, &chronicle) {
+            warn!("Unable to write the chronicle: {:?}", e);
+        }
+    }
+
+    /// Whether `path` is the chronicle and the player making the call has
+    /// none yet
+    pub(crate) fn chronicle_hidden(&self, path: &Path) -> bool {
+        path == Path::new(CHRONICLE_FILE)
+            && self
+                .players
+                .get(&self.player_identity.current())
+                .is_none_or(|player| player.chronicle.is_none())
+    }
+}
@@ -0,0 +1,231 @@
+//! Worlds cloned from a running one, to play alternate timelines.
+//!
+//! `clone <name>` in `.control` saves the game and clones the world, with
+//! every journey in it, into `<name>` beside its directory. Played from
+//! there, the clone leaves the original as it was. When the server serves
+//! exports, the clone is exported as `<name>` at once.
+//!
+//! A clone shares what it can with the world it was cloned from. Small
+//! files, which the game rewrites itself, and the state of the server are
+//! copied. Larger files are reflinked where the host file system can, and
+//! else hard linked: a hard linked file is only shared until a client
+//! changes it in place in either world, as it is copied apart first. Both
+//! worlds list the other in `.eternal/clones` to tell which hard links
+//! are theirs.
+
+use std::fs::{File, Metadata};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use intaglio::Symbol;
+use nfsserve::nfs::fileid3;
+use tracing::{debug, warn};
+
+use crate::events::EventKind;
+use crate::objects;
+use crate::philosophy::Philosophy;
+use crate::trash::TRASH_DIR;
+use crate::{FSMap, STATE_DIR};
+
+/// The worlds a world was cloned from or to, one directory per line,
+/// within the state directory
+const CLONES_FILE: &str = "clones";
+
+/// Files up to this size are copied rather than shared
+const COPY_AT_ONCE: u64 = 64 * 1024;
+
+/// What cloning did with the files of the world
+#[derive(Debug, Default)]
+struct Cloned {
+    copied: usize,
+    reflinked: usize,
+    linked: usize,
+}
+
+/// Makes `to` share the contents of the file `from` without copying them,
+/// where the host file system can
+#[cfg(target_os = "linux")]
+fn reflink(from: &Path, to: &Path, meta: &Metadata) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source = File::open(from)?;
+    let target = File::options().write(true).create_new(true).open(to)?;
+    // SAFETY: both are open file descriptors for the duration of the call
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } != 0 {
+        let e = io::Error::last_os_error();
+        drop(target);
+        let _ = std::fs::remove_file(to);
+        return Err(e);
+    }
+    target.set_permissions(meta.permissions())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_from: &Path, _to: &Path, _meta: &Metadata) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Clones the directory `from`, at `at` within the world, into `to`,
+/// which must not exist yet. What is private to the world is skipped.
+fn clone_dir(from: &Path, to: &Path, at: &Path, cloned: &mut Cloned) -> io::Result<()> {
+    std::fs::create_dir(to)?;
+    std::fs::set_permissions(to, from.metadata()?.permissions())?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let path = at.join(entry.file_name());
+        if private(&path) {
+            continue;
+        }
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            clone_dir(&source, &target, &path, cloned)?;
+        } else if meta.is_symlink() {
+            std::os::unix::fs::symlink(std::fs::read_link(&source)?, &target)?;
+        } else if !meta.is_file() {
+            debug!("Not cloning the special file {:?}", source);
+        } else if meta.len() <= COPY_AT_ONCE || path.starts_with(STATE_DIR) {
+            std::fs::copy(&source, &target)?;
+            cloned.copied += 1;
+        } else if reflink(&source, &target, &meta).is_ok() {
+            cloned.reflinked += 1;
+        } else {
+            std::fs::hard_link(&source, &target)?;
+            cloned.linked += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` of a world is its own and not cloned: the git
+/// repository, its trash, and what the server keeps of the world rather
+/// than of the game
+fn private(path: &Path) -> bool {
+    let state = Path::new(STATE_DIR);
+    path == Path::new(".git")
+        || path == state.join(CLONES_FILE)
+        || path == state.join("snapshots")
+        || path == state.join("objects")
+        || path == state.join("versions")
+        || path == Path::new(TRASH_DIR)
+        || path.parent() == Some(state)
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("metadata."))
+}
+
+/// The worlds cloned from or to the world at `root`
+fn clones_of(root: &Path) -> Vec<PathBuf> {
+    std::fs::read_to_string(root.join(STATE_DIR).join(CLONES_FILE))
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Notes in the world at `root` that it shares files with `other`
+fn note_clone(root: &Path, other: &Path) -> io::Result<()> {
+    use std::io::Write;
+    let dir = root.join(STATE_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let mut file = File::options()
+        .create(true)
+        .append(true)
+        .open(dir.join(CLONES_FILE))?;
+    writeln!(file, "{}", other.display())
+}
+
+impl Philosophy {
+    /// Clones the world into `name` beside it, and returns what was done
+    pub(crate) fn clone_world(&mut self, name: &str) -> String {
+        if name.is_empty() || name.starts_with('.') || name.contains('/') {
+            return format!("{:?} can not name a world.", name);
+        }
+        let root = self
+            .root
+            .canonicalize()
+            .unwrap_or_else(|_| self.root.clone());
+        let Some(to) = root.parent().map(|parent| parent.join(name)) else {
+            return "The world has no directory beside it to be cloned into.".to_string();
+        };
+        if to.symlink_metadata().is_ok() {
+            return format!("There is already a {:?}.", to);
+        }
+        if let Err(e) = self.save_game() {
+            return format!("Unable to save the game before cloning it: {}", e);
+        }
+        let mut cloned = Cloned::default();
+        if let Err(e) = clone_dir(&root, &to, Path::new(""), &mut cloned) {
+            warn!("Unable to clone the world into {:?}: {:?}", to, e);
+            let _ = std::fs::remove_dir_all(&to);
+            return format!("Unable to clone the world into {:?}: {}", to, e);
+        }
+        if cloned.linked > 0 {
+            for (world, other) in [(&root, &to), (&to, &root)] {
+                if let Err(e) = note_clone(world, other) {
+                    warn!("Unable to note the clone in {:?}: {:?}", world, e);
+                }
+            }
+        }
+        self.events.publish(EventKind::WorldCloned {
+            name: name.to_string(),
+            dir: to.to_string_lossy().into_owned(),
+        });
+        format!(
+            "Cloned the world into {:?}: {} files copied, {} reflinked, {} hard linked.",
+            to, cloned.copied, cloned.reflinked, cloned.linked
+        )
+    }
+}
+
+impl FSMap {
+    /// Copies the file of the entry named `name` apart if it is hard linked
+    /// with a world cloned from or to this one, or with what is kept once
+    /// (see `objects.rs`), before it is changed in place. Returns the
+    /// fileid of the entry if it was, whose host file is no longer the one
+    /// open.
+    pub(crate) fn unshare(&mut self, name: &[Symbol]) -> Option<fileid3> {
+        let path = self.game_path(name);
+        let host = self.root.join(&path);
+        let meta = host.symlink_metadata().ok()?;
+        if !meta.is_file() || meta.nlink() < 2 {
+            return None;
+        }
+        let shared = objects::object_of(&self.root, &host, &meta).is_some()
+            || clones_of(&self.root).iter().any(|world| {
+                world
+                    .join(&path)
+                    .symlink_metadata()
+                    .is_ok_and(|other| other.dev() == meta.dev() && other.ino() == meta.ino())
+            });
+        if !shared {
+            return None;
+        }
+        let file_name = host.file_name()?.to_string_lossy();
+        let apart = host.with_file_name(format!(".{}.unshared", file_name));
+        let copied = std::fs::copy(&host, &apart)
+            .and_then(|_| std::fs::rename(&apart, &host))
+            .and_then(|_| host.symlink_metadata());
+        let copied = match copied {
+            Ok(copied) => copied,
+            Err(e) => {
+                warn!("Unable to copy {:?} apart from its clones: {:?}", host, e);
+                let _ = std::fs::remove_file(&apart);
+                return None;
+            }
+        };
+        debug!("Copied {:?} apart from its clones", host);
+        // the entry lives on in the copy
+        let id = self.path_to_id.get(name).as_deref().copied()?;
+        if self.ino_to_id.get(&meta.ino()) == Some(&id) {
+            self.ino_to_id.remove(&meta.ino());
+        }
+        self.ino_to_id.insert(copied.ino(), id);
+        if let Some(entry) = self.id_to_path.get_mut(&id) {
+            entry.ino = copied.ino();
+        }
+        Some(id)
+    }
+}
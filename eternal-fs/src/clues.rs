@@ -0,0 +1,124 @@
+//! Clues hidden in the attributes of files.
+//!
+//! A stage of a pack can hide a clue, see
+//! [`StageDef::clues`](crate::packs::StageDef::clues). The files of its
+//! `clues/` directory hold nothing: the clue is told only by what the game
+//! makes up of their attributes, in place of those of the empty files on
+//! the host. The numbered files spell a word, every one as large as the
+//! ASCII code of its letter, and the files named after hemispheres were
+//! last modified as many seconds after the epoch as ten thousand times
+//! their coordinate, in degrees. The core pack hides the passphrase of the
+//! tablet in `myth/` that way.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use tracing::warn;
+
+use crate::engine::SyntheticAttrs;
+use crate::packs::ClueDef;
+use crate::philosophy::Philosophy;
+
+/// The directory of a stage its clue is hidden in
+pub(crate) const CLUES_DIR: &str = "clues";
+
+/// What a coordinate is multiplied by to be told in seconds
+const COORDINATE_SCALE: f64 = 10_000.0;
+
+/// The numbered files of `clues`, one by letter of its word, so that they
+/// list in order
+fn letter_files(clues: &ClueDef) -> Vec<(String, u8)> {
+    let width = clues.word.len().to_string().len();
+    clues
+        .word
+        .bytes()
+        .enumerate()
+        .map(|(i, letter)| (format!("{:0width$}", i + 1, width = width), letter))
+        .collect()
+}
+
+/// The files named after the hemispheres the place of `clues` lies in,
+/// with its latitude and longitude
+fn hemisphere_files(clues: &ClueDef) -> Vec<(&'static str, f64)> {
+    let Some((latitude, longitude)) = clues.place else {
+        return Vec::new();
+    };
+    vec![
+        (if latitude < 0.0 { "south" } else { "north" }, latitude),
+        (if longitude < 0.0 { "west" } else { "east" }, longitude),
+    ]
+}
+
+impl Philosophy {
+    /// The clue hidden in the directory `dir`, if it is the clues
+    /// directory of a stage
+    fn clues_in(&self, dir: &Path) -> Option<&ClueDef> {
+        if dir.file_name()? != CLUES_DIR {
+            return None;
+        }
+        self.world
+            .stage(dir.parent()?.to_str()?)?
+            .def
+            .clues
+            .as_ref()
+    }
+
+    /// Creates the clues directory of every stage hiding a clue, with the
+    /// empty files the clue is told by
+    pub(crate) fn create_clue_files(&mut self) {
+        for stage in self.world.stages.iter() {
+            let Some(clues) = &stage.def.clues else {
+                continue;
+            };
+            let dir = self.root.join(&stage.dir).join(CLUES_DIR);
+            let mut names: Vec<String> = letter_files(clues)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            names.extend(
+                hemisphere_files(clues)
+                    .into_iter()
+                    .map(|(name, _)| name.to_string()),
+            );
+            let created = std::fs::create_dir_all(&dir)
+                .and_then(|_| {
+                    std::fs::write(
+                        dir.join("README.txt"),
+                        "Nothing in this directory holds a word.\n\
+                         Look at the files, not into them: how large they are, and when\n\
+                         they were last touched.\n",
+                    )
+                })
+                .and_then(|_| {
+                    names
+                        .iter()
+                        .map(|name| dir.join(name))
+                        .filter(|file| !file.exists())
+                        .try_for_each(|file| std::fs::write(file, ""))
+                });
+            if let Err(e) = created {
+                warn!("Unable to create the clues {:?}: {:?}", dir, e);
+            }
+        }
+    }
+
+    /// The attributes the clue hidden in the file at `path` is told by
+    pub(crate) fn clue_attrs(&self, path: &Path) -> Option<SyntheticAttrs> {
+        let clues = self.clues_in(path.parent()?)?;
+        let name = path.file_name()?.to_str()?;
+        if let Some((_, letter)) = letter_files(clues).into_iter().find(|(n, _)| n == name) {
+            return Some(SyntheticAttrs {
+                size: Some(letter as u64),
+                mtime: None,
+            });
+        }
+        let (_, degrees) = hemisphere_files(clues)
+            .into_iter()
+            .find(|(n, _)| *n == name)?;
+        let secs = (degrees.abs() * COORDINATE_SCALE).round() as u64;
+        Some(SyntheticAttrs {
+            size: None,
+            mtime: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+        })
+    }
+}
@@ -0,0 +1,152 @@
+//! Archived game data kept compressed.
+//!
+//! With `--compress` (or `compress = true` under `[server]`) the earlier
+//! versions of answers (see `history.rs`) and of files (see `versions.rs`),
+//! the copies snapshots take (see `snapshots.rs`) and the archive of the
+//! journal are kept compressed with zstd, `.zst` after their names. They
+//! are read decompressed, so that clients never see the difference.
+//! Whatever was kept before, or is larger than [`COMPRESS_UP_TO`], is kept
+//! as it is, and still read as it is.
+//!
+//! Compressing needs eternal-fs to be built with the `compress` feature.
+//! Without it, whatever was kept compressed can no longer be read.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::encryption::{self, StateKey};
+use crate::objects;
+
+/// What comes after the name of a file kept compressed
+pub(crate) const COMPRESSED_EXTENSION: &str = ".zst";
+
+/// Files larger than this are kept as they are, as they are decompressed
+/// into memory to be read
+pub(crate) const COMPRESS_UP_TO: u64 = 16 * 1024 * 1024;
+
+/// `<path>.zst`
+pub(crate) fn compressed(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(COMPRESSED_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// The name a file kept as `name` is read under, without `.zst`
+pub(crate) fn uncompressed(name: &str) -> &str {
+    name.strip_suffix(COMPRESSED_EXTENSION).unwrap_or(name)
+}
+
+/// Whichever of `path` and `path.zst` is kept, and whether it is the
+/// compressed one
+pub(crate) fn find(path: &Path) -> Option<(PathBuf, bool)> {
+    if path.is_file() {
+        return Some((path.to_path_buf(), false));
+    }
+    let path = compressed(path);
+    path.is_file().then_some((path, true))
+}
+
+#[cfg(feature = "compress")]
+pub(crate) fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+}
+
+#[cfg(not(feature = "compress"))]
+pub(crate) fn compress(_data: &[u8]) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "eternal-fs is built without the compress feature",
+    ))
+}
+
+#[cfg(feature = "compress")]
+pub(crate) fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::decode_all(data)
+}
+
+#[cfg(not(feature = "compress"))]
+pub(crate) fn decompress(_data: &[u8]) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "eternal-fs is built without the compress feature",
+    ))
+}
+
+/// Keeps `contents` as the file `to` of the world at `root`, see
+/// [`objects::keep`], or compressed as `to.zst` if `compress`, and then
+/// encrypted with `key` if there is one, see `encryption.rs`. Returns
+/// whether it was compressed.
+pub(crate) fn keep(
+    root: &Path,
+    contents: &[u8],
+    to: &Path,
+    mtime: Option<SystemTime>,
+    compress: bool,
+    key: Option<&StateKey>,
+) -> io::Result<bool> {
+    if compress && contents.len() as u64 <= COMPRESS_UP_TO {
+        let packed = encryption::seal(key, &self::compress(contents)?);
+        objects::keep(root, &packed, &compressed(to), mtime)?;
+        return Ok(true);
+    }
+    objects::keep(root, &encryption::seal(key, contents), to, mtime)?;
+    Ok(false)
+}
+
+/// Keeps what the file `from` holds as the file `to` of the world at
+/// `root`, see [`objects::keep_file`], or compressed as `to.zst` if
+/// `compress` and it is small enough. Returns whether it was compressed.
+pub(crate) fn keep_file(
+    root: &Path,
+    from: &Path,
+    to: &Path,
+    mtime: Option<SystemTime>,
+    compress: bool,
+) -> io::Result<bool> {
+    if compress && from.metadata()?.len() <= COMPRESS_UP_TO {
+        return keep(root, &std::fs::read(from)?, to, mtime, true, None);
+    }
+    objects::keep_file(root, from, to, mtime)?;
+    Ok(false)
+}
+
+/// What is kept of a file, read from the host or decompressed
+pub(crate) enum Kept {
+    File(File),
+    Decompressed(Vec<u8>),
+}
+
+impl Kept {
+    /// Opens what is kept at `path`, decompressing it if it is kept
+    /// `compressed`
+    pub(crate) fn open(path: &Path, compressed: bool) -> io::Result<Kept> {
+        if compressed {
+            return Ok(Kept::Decompressed(decompress(&std::fs::read(path)?)?));
+        }
+        Ok(Kept::File(File::open(path)?))
+    }
+
+    /// Up to `count` bytes from `offset` on
+    pub(crate) fn read(&mut self, offset: u64, count: u32) -> io::Result<Vec<u8>> {
+        match self {
+            Kept::File(file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                let mut buf = Vec::new();
+                file.take(count as u64).read_to_end(&mut buf)?;
+                Ok(buf)
+            }
+            Kept::Decompressed(contents) => Ok(crate::engine::read_at(contents, offset, count).0),
+        }
+    }
+
+    /// The size of what is kept, decompressed
+    pub(crate) fn size(&self) -> io::Result<u64> {
+        match self {
+            Kept::File(file) => Ok(file.metadata()?.len()),
+            Kept::Decompressed(contents) => Ok(contents.len() as u64),
+        }
+    }
+}
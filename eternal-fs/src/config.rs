@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nfsserve::access::{AccessList, Cidr};
+use nfsserve::auth::IdMapping;
+use nfsserve::throttle::RateLimit;
+use serde::Deserialize;
+
+use crate::evaluator::EvaluatorConfig;
+use crate::packs::World;
+use crate::scoring::{Difficulty, Threshold};
+use crate::store::StoreConfig;
+
+/// Settings read from a TOML file, for instance:
+///
+/// ```text
+/// [server]
+/// bind = "0.0.0.0"
+/// port = 2049
+/// handle_idle_secs = 10
+/// read_ahead_kib = 8192
+/// acttl = "3s"
+/// atime = "relatime"
+///
+/// [game]
+/// min_answer_length = 80
+/// difficulty = "hard"
+/// hint_tokens = 5
+/// order = ["emotion", "logic"]
+///
+/// [game.stages.logic]
+/// question = "Can a statement be both true and false?"
+/// keywords = ["paradox"]
+///
+/// [game.evaluator]
+/// kind = "ollama"
+///
+/// [game.store]
+/// kind = "sqlite"
+/// ```
+///
+/// Every setting is optional. Settings given on the command line take
+/// precedence over the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    /// Serve every call with the credentials of its client. Without this
+    /// section everyone is served with the server's own permissions.
+    pub auth: Option<AuthConfig>,
+    pub game: GameConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address to listen on
+    pub bind: Option<String>,
+    pub port: Option<u16>,
+    /// `tcp`, `udp` or `both`
+    pub transport: Option<String>,
+    /// Serve on port 111 as well, where the lock manager of a client asks
+    /// rpcbind which port to take locks on
+    pub portmap: Option<bool>,
+    /// `nfs`, or `fuse` to mount the world on this machine in place of
+    /// serving it over the network
+    pub frontend: Option<String>,
+    /// Where the world is mounted with the `fuse` frontend
+    pub mountpoint: Option<PathBuf>,
+    /// Networks which may mount, e.g. `["10.0.0.0/8"]`. Everyone may if
+    /// none are given.
+    pub allow: Vec<String>,
+    /// Networks which may not mount, even if allowed
+    pub deny: Vec<String>,
+    /// Requests per second each client may make
+    pub ops_per_sec: Option<u32>,
+    /// Bytes per second each client may read and write
+    pub bytes_per_sec: Option<u64>,
+    /// Seconds an unused host file handle is kept open
+    pub handle_idle_secs: Option<u64>,
+    /// KiB read ahead of clients reading a file in order, none if 0, see
+    /// `read_ahead.rs`
+    pub read_ahead_kib: Option<u64>,
+    /// How long GETATTR serves the attributes of an object as they were
+    /// last read from the host, e.g. `3s` or `500ms`. They are read
+    /// afresh every time if `0`, as by default.
+    pub acttl: Option<String>,
+    /// When reading a file changes its access time: `noatime`, as by
+    /// default, `relatime` or `strictatime`, see `atime.rs`
+    pub atime: Option<String>,
+    /// How fileids are given: counted up, as by default, or hashed from
+    /// the `path` or the `inode` of an object, see `fileids.rs`
+    pub fileids: Option<String>,
+    pub stale_grace: Option<bool>,
+    pub read_only: Option<bool>,
+    /// Play a world of its own, laid out in memory and gone once the
+    /// server stops, in place of a directory
+    pub ephemeral: Option<bool>,
+    /// A pristine world the directory served, and every one exported, is
+    /// laid over, see `overlay.rs`
+    pub lower: Option<PathBuf>,
+    /// A directory whose `worlds/` holds a world in every directory, each
+    /// exported under its name and played on its own
+    pub worlds: Option<PathBuf>,
+    /// Read-only exports of worlds for spectators, `NAME=WORLD` or
+    /// `NAME=WORLD@PLAYER`, see `spectator.rs`
+    pub spectate: Vec<String>,
+    /// Address the HTTP inspection API listens on, see `http.rs`
+    pub http: Option<String>,
+    /// Snapshots of the world kept, taken as players reach another stage,
+    /// see `snapshots.rs`
+    pub snapshots: Option<usize>,
+    /// Days removed files are kept in `.trash/` rather than unlinked, see
+    /// `trash.rs`
+    pub trash_days: Option<u64>,
+    /// Versions kept of every file as clients write over it, see
+    /// `versions.rs`
+    pub versions: Option<usize>,
+    /// Keep earlier answers, versions, snapshots and the journal archive
+    /// compressed, see `compression.rs`
+    pub compress: Option<bool>,
+    /// `text`, or `json` for one JSON object per log line
+    pub log_format: Option<String>,
+    /// File every NFS call is appended to, see `audit.rs`
+    pub audit_log: Option<PathBuf>,
+    /// MiB the audit log is rotated at
+    pub audit_max_mb: Option<u64>,
+    /// Where what is known of the objects of the host is kept: `memory`,
+    /// or `sled` or `sqlite` for exports too large to keep it all in
+    /// memory
+    pub metadata_store: Option<String>,
+    /// Objects whose metadata stays in memory with a `sled` or `sqlite`
+    /// metadata store
+    pub metadata_cache: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Serve root as the anonymous user
+    pub root_squash: bool,
+    /// Serve everyone as the anonymous user
+    pub all_squash: bool,
+    pub anon_uid: Option<u32>,
+    pub anon_gid: Option<u32>,
+    /// Pairs of client and host uid, e.g. `[[1000, 1001]]`
+    pub uid_map: Vec<(u32, u32)>,
+    /// Pairs of client and host gid
+    pub gid_map: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    /// Answers have to be longer than this to be considered
+    pub min_answer_length: Option<usize>,
+    /// The file every stage is answered in, `answer.txt` unless given
+    pub answer_file: Option<String>,
+    /// The file at the root of the world which collapses into another
+    /// state whenever it is written, `quantum_state.txt` unless given
+    pub quantum_state_file: Option<String>,
+    /// Which thresholds answers are held to, `normal` unless given
+    pub difficulty: Option<Difficulty>,
+    /// Stage directories in the order they are laid out and, for the core
+    /// stages, answered
+    pub order: Vec<String>,
+    /// Overrides for the stages of the world, by directory
+    pub stages: BTreeMap<String, StageConfig>,
+    /// `shared`, or `address` or `uid` to give every client address or
+    /// uid a journey of its own
+    pub players: Option<String>,
+    /// How answers are judged, by their keywords unless configured
+    pub evaluator: EvaluatorConfig,
+    /// Hints every player can ask for, see `hints/request.txt`
+    pub hint_tokens: Option<u32>,
+    /// Seconds `meditation.txt` takes to reveal a clue
+    pub meditation_secs: Option<u64>,
+    /// Earlier versions of every answer, served in `.history/` beside it
+    pub answer_history: Option<usize>,
+    /// Seconds every time-locked file stays sealed once written, by path
+    pub time_locks: Option<BTreeMap<String, u64>>,
+    /// Distinct clients whose answers have to pass a stage before it
+    /// advances, see `coop.rs`
+    pub coop: Option<usize>,
+    /// Seconds the answers of a stage answered together have to come
+    /// together in
+    pub coop_window_secs: Option<u64>,
+    /// Whether the players race each other to enlightenment, see
+    /// `race.rs`
+    pub race: Option<bool>,
+    /// Whether every journey is timed as a speedrun, see `.status/splits`
+    pub speedrun: Option<bool>,
+    /// Whether the question of the perception path changes as it is read
+    pub observer_effect: Option<bool>,
+    /// Where the progress of the players is kept, see `store.rs`
+    pub store: StoreConfig,
+    /// Whether every accepted answer is committed to a git repository at
+    /// the root, see `versioning.rs`
+    pub git: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StageConfig {
+    pub question: Option<String>,
+    /// Stages of the same pack, by name, to be answered first
+    pub after: Option<Vec<String>>,
+    pub keywords: Option<Vec<String>>,
+    pub threshold: Option<Threshold>,
+    pub reply: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read {:?}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("in {:?}: {}", path, e))
+    }
+}
+
+impl ServerConfig {
+    pub fn handle_idle_timeout(&self) -> Option<Duration> {
+        self.handle_idle_secs.map(Duration::from_secs)
+    }
+
+    /// Bytes read ahead of clients, if configured
+    pub fn read_ahead(&self) -> Option<u64> {
+        self.read_ahead_kib.map(|kib| kib * 1024)
+    }
+
+    /// How long attributes are cached, if configured
+    pub fn attr_ttl(&self) -> Result<Option<Duration>, String> {
+        self.acttl.as_deref().map(parse_duration).transpose()
+    }
+
+    /// How long removed files are kept in the trash, if they are
+    pub fn trash_retention(&self) -> Option<Duration> {
+        self.trash_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60))
+    }
+
+    /// The rate each client is held to
+    pub fn rate_limit(&self) -> RateLimit {
+        RateLimit {
+            ops_per_sec: self.ops_per_sec,
+            bytes_per_sec: self.bytes_per_sec,
+        }
+    }
+
+    /// The networks which may and may not use the server
+    pub fn access_list(&self) -> Result<AccessList, String> {
+        let parse = |nets: &[String]| -> Result<Vec<Cidr>, String> {
+            nets.iter().map(|net| net.parse()).collect()
+        };
+        Ok(AccessList {
+            allow: parse(&self.allow)?,
+            deny: parse(&self.deny)?,
+        })
+    }
+}
+
+/// Parses a duration such as `3s`, `500ms` or `2m`, in seconds if it
+/// has no unit
+pub fn parse_duration(spec: &str) -> Result<Duration, String> {
+    let split = spec
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(spec.len());
+    let (value, unit) = spec.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration {:?}", spec))?;
+    match unit {
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "ms" => Ok(Duration::from_millis(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        _ => Err(format!("unknown unit of duration {:?}", spec)),
+    }
+}
+
+impl AuthConfig {
+    /// The mapping applied to the credentials of every call
+    pub fn id_mapping(&self) -> IdMapping {
+        let defaults = IdMapping::default();
+        IdMapping {
+            root_squash: self.root_squash,
+            all_squash: self.all_squash,
+            anon_uid: self.anon_uid.unwrap_or(defaults.anon_uid),
+            anon_gid: self.anon_gid.unwrap_or(defaults.anon_gid),
+            uids: self.uid_map.iter().copied().collect(),
+            gids: self.gid_map.iter().copied().collect(),
+        }
+    }
+}
+
+impl GameConfig {
+    /// Composes the world of the packs in `statedir` and applies the game
+    /// settings to it
+    pub fn compose(&self, statedir: &Path) -> Result<World, String> {
+        let mut world = World::load(statedir)?;
+        self.apply(&mut world)?;
+        Ok(world)
+    }
+
+    /// Applies the game settings to a composed world
+    pub fn apply(&self, world: &mut World) -> Result<(), String> {
+        for name in [&self.answer_file, &self.quantum_state_file]
+            .into_iter()
+            .flatten()
+        {
+            if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                return Err(format!("{:?} can not name a file of the world", name));
+            }
+        }
+        if let Some(stage) = self
+            .quantum_state_file
+            .as_deref()
+            .and_then(|name| world.stage(name))
+        {
+            return Err(format!(
+                "the quantum state file {:?} is a stage of the world",
+                stage.dir
+            ));
+        }
+        if let Some(len) = self.min_answer_length {
+            world.min_answer_length = len;
+        }
+        for (dir, overrides) in self.stages.iter() {
+            let stage = world
+                .stages
+                .iter_mut()
+                .find(|s| &s.dir == dir)
+                .ok_or_else(|| format!("configured stage {:?} is not in the world", dir))?;
+            if let Some(ref question) = overrides.question {
+                stage.def.question = question.clone();
+            }
+            if let Some(ref after) = overrides.after {
+                stage.def.after = Some(after.clone());
+            }
+            if let Some(ref keywords) = overrides.keywords {
+                stage.def.keywords = keywords.clone();
+            }
+            if let Some(threshold) = overrides.threshold {
+                stage.def.threshold = Some(threshold);
+            }
+            if let Some(ref reply) = overrides.reply {
+                stage.def.reply = reply.clone();
+            }
+        }
+        world.set_difficulty(self.difficulty.unwrap_or_default());
+        world.reorder(&self.order)
+    }
+}
@@ -0,0 +1,186 @@
+//! The control file, through which operators administer a running world.
+//!
+//! Writing commands to `.control` at the root, one per line, changes the
+//! state of the game without restarting the server. Reading the file back
+//! shows what the last commands did. Only root may use it, as the caller
+//! is seen after the uid mapping of the export.
+//!
+//! ```text
+//! reset [player]             the journey starts anew
+//! skip-stage [player]        the stage at hand counts as answered
+//! set-stage <stage> [player] the stages before <stage> count as answered
+//! save                       the game is saved to its store
+//! rollback <commit>          the world is put back as it was at <commit>
+//! clone <name>               the world is cloned into <name> beside it
+//! restore <path>             the file removed from <path> is put back
+//! begin again                the world starts over, see `rebirth.rs`
+//! ```
+//!
+//! Commands act on the journey of `player`, named as in the leaderboard,
+//! or else on that of the caller.
+
+use std::time::SystemTime;
+
+use nfsserve::auth::caller;
+use tracing::info;
+
+use crate::packs::{Stage, World};
+use crate::philosophy::{GameStage, Philosophy};
+use crate::players::PlayerState;
+
+/// The file operators write commands to
+pub(crate) const CONTROL_FILE: &str = ".control";
+
+/// Commands whose results .control shows, the most recent ones
+const RECENT_COMMANDS: usize = 20;
+
+const USAGE: &str = "\
+    Commands, one per line:\n\
+    \x20 reset [player]\n\
+    \x20 skip-stage [player]\n\
+    \x20 set-stage <stage> [player]\n\
+    \x20 save\n\
+    \x20 rollback <commit>\n\
+    \x20 clone <name>\n\
+    \x20 restore <path>\n\
+    \x20 begin again\n";
+
+/// Whether the caller may use the control file
+pub(crate) fn is_operator() -> bool {
+    caller().is_some_and(|caller| caller.uid == 0)
+}
+
+impl PlayerState {
+    /// Counts the stage at hand as answered, or enlightenment once every
+    /// stage is
+    fn skip_stage(&mut self, world: &World) -> String {
+        if matches!(self.current_stage, GameStage::Enlightened) {
+            return "The journey is complete, there is no stage to skip.".to_string();
+        }
+        let before = self.snapshot();
+        let skipped = match self.open_stages(world).first() {
+            Some(stage) => {
+                self.completed_questions.insert(stage.dir.clone());
+                if let Some(reached) = GameStage::reached_by(&stage.def.name) {
+                    self.current_stage = reached;
+                }
+                stage.dir.clone()
+            }
+            None => {
+                self.completed_questions.insert("enlightenment".to_string());
+                self.current_stage = GameStage::Enlightened;
+                "enlightenment".to_string()
+            }
+        };
+        self.philosophical_state.snapshots.push(before);
+        self.last_completed = Some(SystemTime::now());
+        self.note(&format!("An operator skipped {}", skipped));
+        format!("Skipped {}.", skipped)
+    }
+
+    /// Counts the core stages laid out before `dir` as answered and those
+    /// from it on as not. Answers to the stages no longer answered are
+    /// forgotten.
+    fn set_stage(&mut self, world: &World, dir: &str) -> String {
+        let stages: Vec<&Stage> = world.core_stages().collect();
+        let Some(at) = stages.iter().position(|stage| stage.dir == dir) else {
+            return format!("There is no stage {}.", dir);
+        };
+        let (answered, open) = stages.split_at(at);
+        for stage in open {
+            self.completed_questions.remove(&stage.dir);
+            self.answer_archive.remove(&stage.dir);
+        }
+        self.completed_questions.remove("enlightenment");
+        // the journey is another now, there is nothing to rewind to
+        self.philosophical_state.snapshots.clear();
+        for stage in answered {
+            self.completed_questions.insert(stage.dir.clone());
+        }
+        self.current_stage = answered
+            .last()
+            .and_then(|stage| GameStage::reached_by(&stage.def.name))
+            .unwrap_or(GameStage::Beginning);
+        self.note(&format!("An operator set the journey to {}", dir));
+        format!("Set the journey to {}.", dir)
+    }
+
+    fn note(&mut self, event: &str) {
+        self.philosophical_state
+            .note_event(SystemTime::now(), event.to_string());
+    }
+}
+
+impl Philosophy {
+    /// The player a command names, or else the caller
+    fn commanded_player(&self, named: Option<&str>) -> Result<String, String> {
+        match named {
+            Some(id) if self.players.contains_key(id) => Ok(id.to_string()),
+            Some(id) => Err(format!("There is no player {}.", id)),
+            None => Ok(self.player_identity.current()),
+        }
+    }
+
+    /// Runs one command and returns what it did
+    fn run_command(&mut self, command: &str) -> String {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let (action, stage, named) = match words[..] {
+            ["save"] => {
+                return match self.save_game() {
+                    Ok(()) => "Saved the game.".to_string(),
+                    Err(e) => format!("Unable to save the game: {}", e),
+                };
+            }
+            [action @ ("reset" | "skip-stage"), ref rest @ ..] if rest.len() <= 1 => {
+                (action, None, rest.first().copied())
+            }
+            [action @ "set-stage", stage, ref rest @ ..] if rest.len() <= 1 => {
+                (action, Some(stage), rest.first().copied())
+            }
+            ["rollback", rev] => return self.rollback(rev),
+            ["clone", name] => return self.clone_world(name),
+            ["restore", path] => return self.restore(path),
+            ["begin", "again"] => return self.rebirth(),
+            _ => return format!("Unknown command.\n{}", USAGE),
+        };
+        let id = match self.commanded_player(named) {
+            Ok(id) => id,
+            Err(e) => return e,
+        };
+        if action == "reset" {
+            let reply = format!("The journey of {} starts anew.", id);
+            self.players.insert(id, PlayerState::default());
+            return reply;
+        }
+        let player = self.players.entry(id).or_default();
+        match stage {
+            Some(stage) => player.set_stage(&self.world, stage),
+            None => player.skip_stage(&self.world),
+        }
+    }
+
+    /// Runs the commands in `text`, one per line
+    pub(crate) fn process_control(&mut self, text: &str) {
+        for command in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let result = self.run_command(command);
+            info!("Control command {:?}: {}", command, result);
+            self.commit_journey("operator", &format!("{}\n\n{}\n", command, result), &[]);
+            self.control_log
+                .push(format!("> {}\n{}\n", command, result));
+        }
+        let excess = self.control_log.len().saturating_sub(RECENT_COMMANDS);
+        self.control_log.drain(..excess);
+    }
+
+    /// What .control shows
+    pub(crate) fn control(&self) -> String {
+        format!(
+            "Control\n\
+             =======\n\
+             {}\n\
+             {}",
+            USAGE,
+            self.control_log.concat()
+        )
+    }
+}
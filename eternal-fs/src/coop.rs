@@ -0,0 +1,147 @@
+//! Stages answered together.
+//!
+//! With `--coop K`, or `coop = K` under `[game]`, a stage only advances
+//! once answers of at least `K` distinct clients passed it within
+//! `coop_window_secs` of each other, ten minutes unless configured. A
+//! client is told apart by its address and, with AUTH_SYS, its uid, so
+//! two users of the same machine count as two. A passing answer joins the
+//! round of its stage with the points it scored, older ones drop out of
+//! it, and `system_response.txt` sums up what every contributor to the
+//! round scored until the last one needed answers and the stage advances
+//! for the journey they play together. Answers which do not pass are
+//! replied to as ever and join no round, and the final answer, which is
+//! given in no stage of its own, needs no one else.
+
+use std::time::{Duration, SystemTime};
+
+use nfsserve::auth::{caller, client_ip};
+use serde::{Deserialize, Serialize};
+
+use crate::packs::World;
+use crate::philosophy::Philosophy;
+use crate::players::{PlayerState, SHARED_PLAYER};
+
+/// How long the answers of a round have to come together in, unless
+/// configured
+const DEFAULT_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// How many clients have to answer a stage, and how close together
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Coop {
+    quorum: usize,
+    window: Duration,
+}
+
+/// A passing answer to a stage, in the round it is waiting in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Contribution {
+    contributor: String,
+    points: f64,
+    at: SystemTime,
+}
+
+/// What became of an answer which passed a stage played together
+pub(crate) enum Round {
+    /// The stage waits for more contributors, with the reply telling so
+    Waiting(String),
+    /// Enough contributors answered, with the summary of the round
+    Complete(String),
+}
+
+/// The client making the call, by its address and uid
+fn contributor() -> String {
+    let address = client_ip().map_or_else(|| SHARED_PLAYER.to_string(), |ip| ip.to_string());
+    match caller() {
+        Some(caller) => format!("uid:{}@{}", caller.uid, address),
+        None => address,
+    }
+}
+
+impl Coop {
+    /// What the contributions of `round` scored, best first
+    fn summary(&self, round: &[Contribution]) -> String {
+        let mut standings: Vec<&Contribution> = round.iter().collect();
+        standings.sort_by(|a, b| b.points.total_cmp(&a.points).then(a.at.cmp(&b.at)));
+        let mut summary = format!("Contributors ({} of {}):\n", round.len(), self.quorum);
+        for contribution in standings {
+            summary.push_str(&format!(
+                "  {:<32} {} points\n",
+                contribution.contributor, contribution.points
+            ));
+        }
+        summary
+    }
+}
+
+impl Philosophy {
+    /// How stages are answered together, unless every player answers on
+    /// their own
+    pub(crate) fn coop(&self) -> Option<Coop> {
+        match self.config.coop {
+            Some(quorum) if quorum > 1 => Some(Coop {
+                quorum,
+                window: self
+                    .config
+                    .coop_window_secs
+                    .map_or(DEFAULT_WINDOW, Duration::from_secs),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl PlayerState {
+    /// Takes `response` of the client making the call into the round of
+    /// the stage at `location`, if it passed the stage and the stage is
+    /// open to the journey. None for answers the journey takes up as
+    /// ever.
+    pub(crate) fn answer_together(
+        &mut self,
+        world: &World,
+        coop: &Coop,
+        location: &str,
+        response: &str,
+        passed: bool,
+    ) -> Option<Round> {
+        let stage = world.stage(location)?;
+        if !passed
+            || self.completed_questions.contains(&stage.dir)
+            || !self.missing_prerequisites(world, stage).is_empty()
+        {
+            return None;
+        }
+        let now = SystemTime::now();
+        let round = self.coop.entry(stage.dir.clone()).or_default();
+        round.retain(|contribution| {
+            now.duration_since(contribution.at)
+                .is_ok_and(|age| age <= coop.window)
+        });
+        let contributor = contributor();
+        let points = stage.def.score(response);
+        // answering again counts once, with the best answer
+        match round.iter_mut().find(|c| c.contributor == contributor) {
+            Some(contribution) => {
+                contribution.points = contribution.points.max(points);
+                contribution.at = now;
+            }
+            None => round.push(Contribution {
+                contributor,
+                points,
+                at: now,
+            }),
+        }
+        let summary = coop.summary(round);
+        if round.len() >= coop.quorum {
+            self.coop.remove(&stage.dir);
+            return Some(Round::Complete(summary));
+        }
+        Some(Round::Waiting(format!(
+            "Your answer passes, but the {} stage is answered together: it advances once \
+             {} clients have answered it within {} minutes of each other.\n\n{}",
+            stage.dir,
+            coop.quorum,
+            coop.window.as_secs().div_ceil(60),
+            summary
+        )))
+    }
+}
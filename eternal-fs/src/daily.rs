@@ -0,0 +1,137 @@
+//! The daily challenge in `daily_challenge.txt`.
+//!
+//! Every midnight the scheduler draws a new challenge, a prompt beside the
+//! paths which is only open until the next one. Writing an answer meeting
+//! it to the file earns a bonus hint token and an achievement, once a day.
+//! The days a player met the challenge of are kept with their journey.
+
+use chrono::Local;
+use rand::Rng;
+use tracing::info;
+
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The file the daily challenge is shown in and answered through
+pub(crate) const DAILY_FILE: &str = "daily_challenge.txt";
+
+/// The key of the day of the current challenge in the game state
+const DATE_KEY: &str = "daily_date";
+
+/// The key of the current challenge in the game state
+const INDEX_KEY: &str = "daily_index";
+
+/// The challenges drawn from, with the words an answer has to contain
+const CHALLENGES: &[(&str, &[&str])] = &[
+    (
+        "What did you notice today that you had never noticed before?",
+        &["notice"],
+    ),
+    (
+        "Name something you are grateful for today, and say why.",
+        &["grateful", "because"],
+    ),
+    (
+        "What would you tell the one you were a year ago? Speak to your past self.",
+        &["past"],
+    ),
+    ("Describe a small kindness, given or received.", &["kind"]),
+    (
+        "What is one question you can not answer? Speak of the question itself.",
+        &["question"],
+    ),
+    ("Describe silence without breaking it.", &["silence"]),
+    (
+        "Where does today end and tomorrow begin?",
+        &["today", "tomorrow"],
+    ),
+    ("What will you let go of before night falls?", &["let go"]),
+];
+
+/// Whether `answer` contains every word in `words`, in any case
+fn meets(answer: &str, words: &[&str]) -> bool {
+    let answer = answer.to_lowercase();
+    words.iter().all(|word| answer.contains(word))
+}
+
+impl PlayerState {
+    /// Hint tokens earned on top of those every player has
+    pub(crate) fn bonus_hint_tokens(&self) -> u32 {
+        self.daily_completed.len() as u32 + self.quest_hint_tokens()
+    }
+}
+
+impl Philosophy {
+    /// The current challenge and the day it was drawn, if one was
+    fn daily_challenge(&self) -> Option<(&'static str, &'static [&'static str], String)> {
+        let index: usize = self.game_state.get(INDEX_KEY)?.parse().ok()?;
+        let (prompt, words) = CHALLENGES.get(index)?;
+        Some((prompt, words, self.game_state.get(DATE_KEY)?.clone()))
+    }
+
+    /// Draws the challenge of today, another than the one before
+    pub(crate) fn rotate_daily_challenge(&mut self) {
+        let previous = self.daily_challenge().map(|(prompt, _, _)| prompt);
+        let mut index = self.rng.gen_range(0..CHALLENGES.len());
+        if previous == Some(CHALLENGES[index].0) {
+            index = (index + 1) % CHALLENGES.len();
+        }
+        let date = Local::now().format("%Y-%m-%d").to_string();
+        info!("The daily challenge of {} is drawn", date);
+        self.game_state.insert(DATE_KEY.to_string(), date);
+        self.game_state
+            .insert(INDEX_KEY.to_string(), index.to_string());
+    }
+
+    /// Draws a challenge unless there is one, for a world started after
+    /// midnight to have one already
+    pub(crate) fn create_daily_challenge(&mut self) {
+        if self.daily_challenge().is_none() {
+            self.rotate_daily_challenge();
+        }
+    }
+
+    /// What `daily_challenge.txt` shows the player making the call
+    pub(crate) fn daily(&self) -> String {
+        let Some((prompt, _, date)) = self.daily_challenge() else {
+            return String::new();
+        };
+        let newcomer = PlayerState::default();
+        let player = self
+            .players
+            .get(&self.player_identity.current())
+            .unwrap_or(&newcomer);
+        let title = format!("Daily Challenge of {}", date);
+        let status = match player.daily_completed.contains(&date) {
+            true => "You met today's challenge, and earned a bonus hint token.",
+            false => "Write your answer to this file before midnight.",
+        };
+        format!(
+            "{}\n{}\n{}\n\n{}\n\nDays met: {}\n",
+            title,
+            "=".repeat(title.len()),
+            prompt,
+            status,
+            player.daily_completed.len()
+        )
+    }
+
+    /// Takes `answer` as the answer of the player making the call to the
+    /// current challenge
+    pub(crate) fn answer_daily(&mut self, answer: &str) {
+        let Some((_, words, date)) = self.daily_challenge() else {
+            return;
+        };
+        if answer.trim().len() < self.world.min_answer_length || !meets(answer, words) {
+            return;
+        }
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        if player.daily_completed.insert(date.clone()) {
+            info!("The daily challenge of {} was met", date);
+            player.award_daily();
+            // the bonus token shows in hints/latest.txt
+            self.update_latest_hint();
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Conversations with the characters of the stages.
+//!
+//! A stage of a pack can have characters, see
+//! [`StageDef::npcs`](crate::packs::StageDef::npcs), each talked to through
+//! a file in the stage directory: `history/the_archivist.txt` for the
+//! Archivist of the core pack. Reading it shows what the character says,
+//! writing to it answers, and the answer takes the conversation down one
+//! of the branches of its node. Which one depends on the words of the
+//! answer and on the emotional state of the player, which follows the
+//! feelings they put into their words. Every player holds conversations
+//! of their own.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use nfsserve::nfs::nfsstat3;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::engine::{read_at, VirtualAttrs, VirtualFile};
+use crate::packs::{DialogueNode, NpcDef};
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// Emotional states, by the words which put the player in them
+const MOODS: &[(&str, &[&str])] = &[
+    (
+        "joyful",
+        &["happy", "joy", "glad", "delight", "love", "grateful"],
+    ),
+    (
+        "melancholy",
+        &["sad", "grief", "loss", "lonely", "miss", "regret"],
+    ),
+    (
+        "angry",
+        &["angry", "anger", "rage", "hate", "furious", "bitter"],
+    ),
+    ("fearful", &["afraid", "fear", "scared", "anxious", "dread"]),
+    ("calm", &["calm", "peace", "still", "serene", "accept"]),
+];
+
+/// Where the player stands in a conversation with a character
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Conversation {
+    /// The node the conversation is at, by id
+    node: String,
+    /// The player's last answer
+    said: Option<String>,
+}
+
+/// The emotional state `text` speaks of, if any
+fn mood_of(text: &str) -> Option<&'static str> {
+    let lowered = text.to_lowercase();
+    let words: Vec<&str> = lowered.split(|c: char| !c.is_alphabetic()).collect();
+    MOODS
+        .iter()
+        .find(|(_, cues)| cues.iter().any(|cue| words.contains(cue)))
+        .map(|(mood, _)| *mood)
+}
+
+impl PlayerState {
+    /// Takes the player's emotional state from the feelings in `text`,
+    /// if it speaks of any
+    pub(crate) fn feel(&mut self, text: &str) {
+        if let Some(mood) = mood_of(text) {
+            debug!("The player feels {}", mood);
+            self.philosophical_state.emotional_state = mood.to_string();
+        }
+    }
+
+    /// The node the conversation with `npc` at `path` is at
+    fn dialogue_node<'n>(&self, path: &str, npc: &'n NpcDef) -> Option<&'n DialogueNode> {
+        let id = self
+            .conversations
+            .get(path)
+            .map_or(&npc.start, |conversation| &conversation.node);
+        npc.node(id).or_else(|| npc.node(&npc.start))
+    }
+
+    /// Answers `npc` at `path` with `text`
+    fn answer_npc(&mut self, path: &str, npc: &NpcDef, text: &str) {
+        self.feel(text);
+        let Some(node) = self.dialogue_node(path, npc) else {
+            return;
+        };
+        let lowered = text.to_lowercase();
+        let mood = &self.philosophical_state.emotional_state;
+        let next = match node.replies.is_empty() {
+            // the conversation is over, it starts anew
+            true => Some(&npc.start),
+            false => node
+                .replies
+                .iter()
+                .find(|branch| {
+                    (branch.keywords.is_empty()
+                        || branch
+                            .keywords
+                            .iter()
+                            .any(|word| lowered.contains(&word.to_lowercase())))
+                        && branch.mood.as_ref().is_none_or(|wanted| wanted == mood)
+                })
+                .map(|branch| &branch.next),
+        };
+        let node = next.unwrap_or(&node.id).clone();
+        debug!("The conversation with {} went on to {}", npc.name, node);
+        self.conversations.insert(
+            path.to_string(),
+            Conversation {
+                node,
+                said: Some(text.to_string()),
+            },
+        );
+    }
+
+    /// What the file of `npc` at `path` shows the player
+    fn npc_file(&self, path: &str, npc: &NpcDef) -> String {
+        let mut contents = format!("{}\n{}\n", npc.name, "=".repeat(npc.name.chars().count()));
+        if let Some(said) = self
+            .conversations
+            .get(path)
+            .and_then(|conversation| conversation.said.as_ref())
+        {
+            contents.push_str(&format!("You said: \"{}\"\n\n", said));
+        }
+        let Some(node) = self.dialogue_node(path, npc) else {
+            return contents;
+        };
+        contents.push_str(&format!("{} says:\n\"{}\"\n\n", npc.name, node.says));
+        contents.push_str(match node.replies.is_empty() {
+            true => "The conversation is over. Write to this file to start it anew.\n",
+            false => "Write your reply to this file.\n",
+        });
+        contents
+    }
+}
+
+impl Philosophy {
+    /// The files the characters of the world are talked to through
+    pub(crate) fn npc_paths(&self) -> Vec<PathBuf> {
+        self.world
+            .stages
+            .iter()
+            .flat_map(|stage| {
+                stage
+                    .def
+                    .npcs
+                    .iter()
+                    .map(|npc| Path::new(&stage.dir).join(npc.file_name()))
+            })
+            .collect()
+    }
+
+    /// The character talked to through the file at `path`, if any
+    fn npc_at(&self, path: &Path) -> Option<NpcDef> {
+        let stage = self.world.stage(path.parent()?.to_str()?)?;
+        let name = path.file_name()?.to_str()?;
+        stage
+            .def
+            .npcs
+            .iter()
+            .find(|npc| npc.file_name() == name)
+            .cloned()
+    }
+
+    /// The file of the character at `path`, as the player making the
+    /// call sees it
+    pub(crate) fn open_npc(&mut self, path: &Path) -> Option<Box<dyn VirtualFile + '_>> {
+        let npc = self.npc_at(path)?;
+        Some(Box::new(NpcFile {
+            game: self,
+            path: path.to_str()?.to_string(),
+            npc,
+        }))
+    }
+}
+
+/// The file of a character, as the player making the call sees it
+struct NpcFile<'a> {
+    game: &'a mut Philosophy,
+    path: String,
+    npc: NpcDef,
+}
+
+impl NpcFile<'_> {
+    fn contents(&self) -> String {
+        let newcomer = PlayerState::default();
+        self.game
+            .players
+            .get(&self.game.player_identity.current())
+            .unwrap_or(&newcomer)
+            .npc_file(&self.path, &self.npc)
+    }
+}
+
+impl VirtualFile for NpcFile<'_> {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        Ok(read_at(self.contents().as_bytes(), offset, count))
+    }
+
+    fn write(&mut self, _offset: u64, data: &[u8]) -> Result<(), nfsstat3> {
+        let text = String::from_utf8_lossy(data);
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+        let id = self.game.player_identity.current();
+        let player = self.game.players.entry(id).or_default();
+        player.answer_npc(&self.path, &self.npc, text);
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.contents().len() as u64
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o644,
+            mtime: SystemTime::now(),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! The dreams, dreamt anew while the world is served.
+//!
+//! Once the dream directory has opened, every `shift-dreams` event of the
+//! schedule dreams a new dream out of fragments of the answers players
+//! have given. Older dreams fade a little with every new one, losing
+//! words, until they are gone.
+
+use std::path::PathBuf;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use tracing::{debug, warn};
+
+use crate::philosophy::Philosophy;
+
+/// The dream directory at the root of the world
+pub(crate) const DREAMS_DIR: &str = "dreams";
+
+/// Dreams kept at once, the oldest fade away beyond them
+const MAX_DREAMS: usize = 5;
+
+/// What stands in for a word a dream has lost
+const FADED: &str = "...";
+
+/// Words of a fragment of an answer, at most
+const FRAGMENT_WORDS: usize = 6;
+
+const CONNECTORS: &[&str] = &[
+    "and then",
+    "but somehow",
+    "while far away",
+    "until suddenly",
+    "as if",
+    "and beneath it all",
+];
+
+impl Philosophy {
+    /// Everything a dream can be made of: the words of every accepted
+    /// answer, or the questions if no one has answered yet
+    fn dream_material(&self) -> Vec<String> {
+        let mut material: Vec<String> = self
+            .players
+            .values()
+            .flat_map(|player| player.answer_archive.values().flatten())
+            .map(|answer| answer.text.clone())
+            .collect();
+        if material.is_empty() {
+            material = self
+                .world
+                .stages
+                .iter()
+                .map(|stage| stage.def.question.clone())
+                .collect();
+        }
+        material
+    }
+
+    /// A dream of a few fragments of `material`
+    fn dream(&mut self, material: &[String]) -> String {
+        let mut dream = String::new();
+        for i in 0..self.rng.gen_range(3..=5) {
+            let Some(source) = material.choose(&mut self.rng) else {
+                break;
+            };
+            let words: Vec<&str> = source.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let len = self.rng.gen_range(1..=FRAGMENT_WORDS.min(words.len()));
+            let start = self.rng.gen_range(0..=words.len() - len);
+            if i > 0 {
+                let connector = CONNECTORS.choose(&mut self.rng).unwrap_or(&"and");
+                dream.push_str(&format!(" {} ", connector));
+            }
+            dream.push_str(&words[start..start + len].join(" "));
+        }
+        dream.push_str("...\n");
+        dream
+    }
+
+    /// Fades `dream`: a third of the words it has left are lost
+    fn fade(&mut self, dream: &str) -> String {
+        let mut words: Vec<&str> = dream.split_whitespace().collect();
+        let mut remaining: Vec<usize> = (0..words.len()).filter(|i| words[*i] != FADED).collect();
+        remaining.shuffle(&mut self.rng);
+        for i in remaining.iter().take(remaining.len().div_ceil(3)) {
+            words[*i] = FADED;
+        }
+        format!("{}\n", words.join(" "))
+    }
+
+    /// Dreams a new dream and lets the older ones fade, if the dream
+    /// directory is open
+    pub(crate) async fn shift_dreams(&mut self) {
+        let dir = self.root.join(DREAMS_DIR);
+        if !dir.is_dir() {
+            debug!("The dreams are closed, nothing to dream");
+            return;
+        }
+        let mut dreams: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+            Ok(listing) => listing
+                .filter_map(|e| Some(e.ok()?.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("dream-"))
+                })
+                .collect(),
+            Err(e) => {
+                warn!("Unable to list the dreams: {:?}", e);
+                return;
+            }
+        };
+        // dreams are named by when they were dreamt, newest last
+        dreams.sort();
+        let fading = dreams.len().saturating_sub(MAX_DREAMS - 1);
+        for path in dreams.drain(..fading) {
+            let _ = tokio::fs::remove_file(&path).await;
+        }
+        for path in dreams {
+            if let Ok(dream) = tokio::fs::read_to_string(&path).await {
+                let faded = self.fade(&dream);
+                let _ = tokio::fs::write(&path, faded).await;
+            }
+        }
+
+        let material = self.dream_material();
+        let dream = self.dream(&material);
+        let name = format!("dream-{}.txt", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let _ = tokio::fs::write(dir.join(&name), dream).await;
+        debug!("Dreamt {}", name);
+    }
+}
@@ -0,0 +1,49 @@
+//! Easter eggs, found by creating files of the right names.
+//!
+//! A pack can hide easter eggs, see
+//! [`WorldPack::eggs`](crate::packs::WorldPack::eggs). Creating a file or
+//! directory of the name of one, anywhere in the world, finds it: the new
+//! file holds what the egg says, or the new directory does in its
+//! `README.txt`. The first time a player finds an egg it is noted on their
+//! timeline and earns achievements. Nothing tells the names of the eggs.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use tracing::{info, warn};
+
+use crate::philosophy::Philosophy;
+
+impl Philosophy {
+    /// Fills the file, or directory if `dir`, the player making the call
+    /// just created at `path` with the easter egg of its name, if there is
+    /// one
+    pub(crate) fn find_egg(&mut self, path: &Path, dir: bool) {
+        let Some(egg) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| self.world.egg(name))
+            .cloned()
+        else {
+            return;
+        };
+        let host = match dir {
+            true => self.root.join(path).join("README.txt"),
+            false => self.root.join(path),
+        };
+        if let Err(e) = std::fs::write(&host, &egg.says) {
+            warn!("Unable to hatch the easter egg {:?}: {:?}", host, e);
+            return;
+        }
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        if player.eggs_found.insert(egg.name.clone()) {
+            info!("The easter egg {:?} was found", egg.name);
+            player.philosophical_state.note_event(
+                SystemTime::now(),
+                format!("Found the easter egg {}", egg.name),
+            );
+            player.award_egg(&self.world);
+        }
+    }
+}
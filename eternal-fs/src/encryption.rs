@@ -0,0 +1,194 @@
+//! What is saved of the players kept encrypted.
+//!
+//! With a key in the environment, `ETERNAL_FS_KEY` unless `key_env` under
+//! `[game.store]` names another variable, the saved game, the earlier
+//! versions of answers (see `history.rs`) and the archive of the journal
+//! are encrypted with ChaCha20-Poly1305 before they are written to the
+//! host, so that a backup of the world tells nothing of what the players
+//! wrote. The variable holds the key itself, 32 random bytes written in
+//! base64 or hex: a passphrase would be as easy to guess as it is to
+//! remember, so none is taken.
+//!
+//! What was written before a key was given is still read, and encrypted as
+//! it is written again. What was encrypted can not be read without the
+//! key, or with another one: the game is then not resumed, and the saved
+//! game is left as it is rather than written over.
+//!
+//! The nonce of what is encrypted is derived from the key and what it
+//! encrypts, so that the same answer is encrypted the same way and still
+//! kept once, see `objects.rs`. That only tells whether two answers are
+//! the same.
+
+use std::fmt;
+use std::io;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::{Digest, Sha256};
+
+/// The variable the key is taken from, unless configured otherwise
+pub const DEFAULT_KEY_ENV: &str = "ETERNAL_FS_KEY";
+
+/// What everything encrypted starts with
+const MAGIC: &[u8] = b"eternal-fs encrypted\n";
+
+/// What a record encrypted as text starts with, see [`StateKey::seal_text`]
+const TEXT_PREFIX: &str = "encrypted:";
+
+/// What the nonce is derived from, besides the key and what it encrypts
+const NONCE_CONTEXT: &[u8] = b"eternal-fs nonce\0";
+
+/// Bytes of the nonce, after [`MAGIC`]
+const NONCE_LEN: usize = 12;
+
+/// The key what is saved of the players is encrypted with
+#[derive(Clone)]
+pub struct StateKey {
+    key: [u8; 32],
+}
+
+impl fmt::Debug for StateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never the key itself
+        f.write_str("StateKey(..)")
+    }
+}
+
+/// Whether `data` was encrypted
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+impl StateKey {
+    /// The key written in `encoded`, 32 bytes in base64 or hex
+    pub fn new(encoded: &str) -> Result<StateKey, String> {
+        let encoded = encoded.trim();
+        let bytes = match decode_hex(encoded) {
+            Some(bytes) => bytes,
+            None => STANDARD
+                .decode(encoded)
+                .map_err(|_| "the key is neither base64 nor hex".to_string())?,
+        };
+        let key = <[u8; 32]>::try_from(bytes.as_slice())
+            .map_err(|_| format!("the key is {} bytes long rather than 32", bytes.len()))?;
+        Ok(StateKey { key })
+    }
+
+    /// The key in the variable `var`, or in [`DEFAULT_KEY_ENV`] if none is
+    /// named. Nothing is encrypted if that one is not set, but a variable
+    /// named has to be.
+    pub fn from_env(var: Option<&str>) -> Result<Option<StateKey>, String> {
+        let name = var.unwrap_or(DEFAULT_KEY_ENV);
+        match std::env::var(name).ok().filter(|secret| !secret.is_empty()) {
+            Some(secret) => StateKey::new(&secret)
+                .map(Some)
+                .map_err(|e| format!("{}: {}", name, e)),
+            None if var.is_some() => Err(format!("{} is not set", name)),
+            None => Ok(None),
+        }
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.key))
+    }
+
+    /// `plain`, encrypted
+    pub fn encrypt(&self, plain: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(NONCE_CONTEXT);
+        hasher.update(self.key);
+        hasher.update(plain);
+        let nonce = &hasher.finalize()[..NONCE_LEN];
+        let mut sealed = MAGIC.to_vec();
+        sealed.extend_from_slice(nonce);
+        // encrypting a plain buffer in memory can not fail
+        sealed.extend(
+            self.cipher()
+                .encrypt(Nonce::from_slice(nonce), plain)
+                .unwrap_or_default(),
+        );
+        sealed
+    }
+
+    /// What `data` holds, decrypted if it was encrypted
+    pub fn decrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let Some(sealed) = data.strip_prefix(MAGIC) else {
+            return Ok(data.to_vec());
+        };
+        if sealed.len() < NONCE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "encrypted with another key"))
+    }
+
+    /// `text` encrypted, as text, for a record of a database
+    pub fn seal_text(&self, text: &str) -> String {
+        format!(
+            "{}{}",
+            TEXT_PREFIX,
+            STANDARD.encode(self.encrypt(text.as_bytes()))
+        )
+    }
+
+    /// What the record `text` holds, decrypted if it was encrypted
+    pub fn open_text(&self, text: &str) -> io::Result<String> {
+        let Some(encoded) = text.strip_prefix(TEXT_PREFIX) else {
+            return Ok(text.to_string());
+        };
+        let sealed = STANDARD
+            .decode(encoded)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        String::from_utf8(self.decrypt(&sealed)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// The bytes written in hex in `text`, if it is hex
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `data` encrypted with `key`, if there is one
+pub fn seal(key: Option<&StateKey>, data: &[u8]) -> Vec<u8> {
+    match key {
+        Some(key) => key.encrypt(data),
+        None => data.to_vec(),
+    }
+}
+
+/// What `data` holds, decrypted with `key` if it was encrypted. Fails if
+/// it was and there is no key.
+pub fn open(key: Option<&StateKey>, data: &[u8]) -> io::Result<Vec<u8>> {
+    match key {
+        Some(key) => key.decrypt(data),
+        None if is_encrypted(data) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "encrypted, and no key was given",
+        )),
+        None => Ok(data.to_vec()),
+    }
+}
+
+/// What the record `text` holds, decrypted with `key` if it was encrypted.
+/// Fails if it was and there is no key.
+pub fn open_text(key: Option<&StateKey>, text: &str) -> io::Result<String> {
+    match key {
+        Some(key) => key.open_text(text),
+        None if text.starts_with(TEXT_PREFIX) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "encrypted, and no key was given",
+        )),
+        None => Ok(text.to_string()),
+    }
+}
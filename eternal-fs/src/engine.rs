@@ -0,0 +1,240 @@
+//! The game played in the mirrored directory.
+//!
+//! [`EternalFS`](crate::EternalFS) serves a host directory over NFS and
+//! tells a [`GameEngine`] what the players do in it. The engine decides
+//! what that means: it lays out the world, judges what is written, and
+//! may show every player something else than what is on disk. The
+//! philosophical journey is one such game, see [`Philosophy`](crate::philosophy::Philosophy).
+//!
+//! Paths given to the engine are relative to the root of the world.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use nfsserve::nfs::nfsstat3;
+
+use crate::events::Events;
+use crate::physics::Physics;
+use crate::scheduler::WorldEvent;
+
+/// What becomes of a write once the game has seen it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteAction {
+    /// The data is written to the host file as usual
+    Write,
+    /// The game took care of the write, nothing is written
+    Consumed,
+}
+
+/// How far the player making the call has come
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReport {
+    /// Questions answered so far
+    pub answered: u64,
+    /// Questions in the whole game
+    pub total: u64,
+    /// The report as shown to the player
+    pub text: String,
+}
+
+/// A file of a directory the game generates
+#[derive(Debug, Clone)]
+pub struct GeneratedFile {
+    pub name: String,
+    pub contents: Vec<u8>,
+    pub mtime: SystemTime,
+}
+
+/// The attributes of a [`VirtualFile`] beyond its size. Everything else
+/// is taken from the root of the world.
+#[derive(Debug, Clone, Copy)]
+pub struct VirtualAttrs {
+    /// Permission bits, `0o444` for a file which can only be read
+    pub mode: u32,
+    pub mtime: SystemTime,
+}
+
+/// Attributes of an entry the game makes up in place of those of its host
+/// file, see [`GameEngine::synthetic_attrs`]. Those left out are the
+/// host's.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyntheticAttrs {
+    pub size: Option<u64>,
+    pub mtime: Option<SystemTime>,
+}
+
+/// A file the game serves itself, from memory, in place of a host file.
+///
+/// The game hands one out for every call on the file, see
+/// [`open_virtual`](GameEngine::open_virtual), so its contents can be
+/// made up for the player making the call.
+pub trait VirtualFile {
+    /// Reads up to `count` bytes from `offset`. Returns them and whether
+    /// the end of the file was reached.
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3>;
+
+    /// Writes `data` at `offset`. Files can only be read unless this is
+    /// implemented.
+    fn write(&mut self, _offset: u64, _data: &[u8]) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    /// The size of the file in bytes
+    fn size(&self) -> u64;
+
+    fn attrs(&self) -> VirtualAttrs;
+}
+
+/// `count` bytes of `contents` from `offset`, and whether they reach the
+/// end, for a [`VirtualFile`] to read from
+pub fn read_at(contents: &[u8], offset: u64, count: u32) -> (Vec<u8>, bool) {
+    let start = offset.min(contents.len() as u64) as usize;
+    let end = start.saturating_add(count as usize).min(contents.len());
+    (contents[start..end].to_vec(), end >= contents.len())
+}
+
+/// A game played in a mirrored directory.
+///
+/// Hooks are called with the world locked, so an engine never sees two
+/// calls at once. Calls are made on behalf of a client, see
+/// [`nfsserve::auth::caller`], except for [`setup`](GameEngine::setup),
+/// [`on_event`](GameEngine::on_event) and [`save`](GameEngine::save).
+#[async_trait]
+pub trait GameEngine: Debug + Send + Sync {
+    /// Lays out the world in the host directory at `root`, once when the
+    /// file system is opened
+    fn setup(&mut self, root: &Path);
+
+    /// Hands the game the events of the world to publish its own on,
+    /// before it is set up
+    fn set_events(&mut self, _events: Events) {}
+
+    /// Called before `data` is written to `path` at `offset`
+    async fn on_write(&mut self, _path: &Path, _offset: u64, _data: &[u8]) -> WriteAction {
+        WriteAction::Write
+    }
+
+    /// Called before the host file at `path` is truncated or extended to
+    /// `size` bytes
+    fn on_truncate(&mut self, _path: &Path, _size: u64) {}
+
+    /// Called once the player created a new file at `path`, or a
+    /// directory if `dir`
+    fn on_create(&mut self, _path: &Path, _dir: bool) {}
+
+    /// The contents the player sees in place of the host file at `path`,
+    /// if they are not the host file's
+    fn on_read(&self, _path: &Path) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Called when the player reads `path`, before its contents are
+    /// asked for with [`on_read`](GameEngine::on_read)
+    fn on_observe(&mut self, _path: &Path) {}
+
+    /// Called when `path` is looked up. An error keeps the player from
+    /// finding it.
+    fn on_lookup(&mut self, _path: &Path) -> Result<(), nfsstat3> {
+        Ok(())
+    }
+
+    /// The target the player sees the symlink at `path` lead to, if it is
+    /// not the one of the host symlink
+    fn on_readlink(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// Called before the directory at `dir` is listed for the player, so
+    /// that the game can lay out on the host what they are to find in it
+    fn on_list(&mut self, _dir: &Path) {}
+
+    /// Whether `path` is hidden from the player making the call. Hidden
+    /// objects are left out of listings and can not be looked up.
+    fn is_hidden(&self, _path: &Path) -> bool {
+        false
+    }
+
+    /// Whether the player making the call sees the true size of `path`
+    /// where the physics of its stage would blur it
+    fn sees_true_size(&self, _path: &Path) -> bool {
+        false
+    }
+
+    /// Attributes of the entry at `path` which the game makes up rather
+    /// than take from the host, to hide clues in them
+    fn synthetic_attrs(&self, _path: &Path) -> Option<SyntheticAttrs> {
+        None
+    }
+
+    /// Read-only directories at the root of the world which are made up
+    /// by the game rather than found on the host, by name
+    fn generated_dirs(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The files of the generated directory `dir`, as the player making
+    /// the call sees them
+    fn generated_files(&self, _dir: &str) -> Vec<GeneratedFile> {
+        Vec::new()
+    }
+
+    /// Files the game serves itself rather than from the host, by path.
+    /// A host file of the same path is hidden behind it.
+    fn virtual_files(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// The virtual file at `path`, as the player making the call sees it
+    fn open_virtual(&mut self, _path: &Path) -> Option<Box<dyn VirtualFile + '_>> {
+        None
+    }
+
+    /// How far the player making the call has come
+    fn progress_report(&self) -> ProgressReport;
+
+    /// Where the game as a whole stands, as `.status/game` tells
+    /// operators
+    fn status_report(&self) -> String {
+        String::new()
+    }
+
+    /// The state of the whole game, as the HTTP API shows it
+    fn state(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// Where every player stands, as the web dashboard shows it
+    fn dashboard(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// The split table of the speedrun of the player making the call, as
+    /// `.status/splits` tells
+    fn splits_report(&self) -> String {
+        String::new()
+    }
+
+    /// Applies an event from the world's schedule
+    async fn on_event(&mut self, _event: WorldEvent) {}
+
+    /// Called when the content packs of the world changed on disk, so
+    /// that the game can take them up while it is played
+    fn reload(&mut self) {}
+
+    /// The physics of every stage which declares any, by the name of its
+    /// directory, asked for once the world is set up and whenever it is
+    /// reloaded
+    fn stage_physics(&self) -> HashMap<OsString, Vec<Physics>> {
+        HashMap::new()
+    }
+
+    /// Saves the state of the game, before the server stops
+    fn save(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Entangled files on the quantum path.
+//!
+//! `quantum/particle.txt` and `quantum/wave.txt` are entangled: until one
+//! of them is read, neither has a state. Reading either collapses both,
+//! into opposite spins, and they keep them until a write to either puts
+//! the pair back into superposition. Every player observes a pair of their
+//! own, kept in their [`quantum_states`](crate::philosophy::PhilosophicalState).
+//! The quantum path can only be answered once the pair has been observed.
+
+use std::path::{Path, PathBuf};
+
+use rand::Rng;
+use tracing::debug;
+
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The stage directory the entangled pair is in
+pub(crate) const QUANTUM_DIR: &str = "quantum";
+
+/// The entangled files, by name
+const ENTANGLED: [&str; 2] = ["particle.txt", "wave.txt"];
+
+impl PlayerState {
+    /// Whether the player ever collapsed the entangled pair
+    pub(crate) fn observed_entanglement(&self) -> bool {
+        self.philosophical_state
+            .solved_puzzles
+            .contains("entanglement")
+    }
+
+    /// The spin of the entangled file `name`, if the pair collapsed
+    fn spin(&self, name: &str) -> Option<bool> {
+        let key = format!("{}/{}", QUANTUM_DIR, name);
+        self.philosophical_state.quantum_states.get(&key).copied()
+    }
+
+    /// What the entangled file `name` shows the player
+    fn entangled_contents(&self, name: &str) -> String {
+        let partner = ENTANGLED.iter().find(|n| **n != name).unwrap_or(&name);
+        let state = match self.spin(name) {
+            None => "SUPERPOSED",
+            Some(true) => "SPIN UP",
+            Some(false) => "SPIN DOWN",
+        };
+        // every state takes the same room, the size does not give it away
+        format!(
+            "Entangled Pair\n\
+             ==============\n\
+             This: {}\n\
+             Entangled with: {}\n\
+             State: {:<10}\n",
+            name, partner, state
+        )
+    }
+}
+
+/// The entangled file at `path`, by name, if it is one
+fn entangled_name(path: &Path) -> Option<&str> {
+    if path.parent()? != Path::new(QUANTUM_DIR) {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    ENTANGLED.contains(&name).then_some(name)
+}
+
+impl Philosophy {
+    /// Puts the entangled pair in the quantum stage, if the world has one
+    pub(crate) fn create_entangled_pair(&mut self) {
+        if self.world.stage(QUANTUM_DIR).is_none() {
+            return;
+        }
+        let newcomer = PlayerState::default();
+        for name in ENTANGLED {
+            let path: PathBuf = [QUANTUM_DIR, name].iter().collect();
+            let _ = std::fs::write(self.root.join(path), newcomer.entangled_contents(name));
+        }
+    }
+
+    /// Collapses the entangled pair of the player making the call if
+    /// `path` is one of it and it is in superposition
+    pub(crate) fn observe_entangled(&mut self, path: &Path) {
+        let Some(name) = entangled_name(path) else {
+            return;
+        };
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        if player.spin(name).is_some() {
+            return;
+        }
+        let spin = self.rng.gen_bool(0.5);
+        let state = &mut player.philosophical_state;
+        for other in ENTANGLED {
+            // the partners always collapse into opposite spins
+            let key = format!("{}/{}", QUANTUM_DIR, other);
+            state.quantum_states.insert(key, (other == name) == spin);
+        }
+        state.solved_puzzles.insert("entanglement".to_string());
+        debug!("The entangled pair collapsed as {} was observed", name);
+    }
+
+    /// Puts the entangled pair of the player making the call back into
+    /// superposition if `path` is one of it. Returns whether it was.
+    pub(crate) fn disentangle(&mut self, path: &Path) -> bool {
+        if entangled_name(path).is_none() {
+            return false;
+        }
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        for name in ENTANGLED {
+            let key = format!("{}/{}", QUANTUM_DIR, name);
+            player.philosophical_state.quantum_states.remove(&key);
+        }
+        true
+    }
+
+    /// What the entangled file at `path` shows the player making the call,
+    /// if it is one
+    pub(crate) fn entangled_contents(&self, path: &Path) -> Option<Vec<u8>> {
+        let name = entangled_name(path)?;
+        let newcomer = PlayerState::default();
+        let player = self
+            .players
+            .get(&self.player_identity.current())
+            .unwrap_or(&newcomer);
+        Some(player.entangled_contents(name).into_bytes())
+    }
+}
@@ -0,0 +1,67 @@
+//! Worlds which leave nothing behind.
+//!
+//! With `--ephemeral` (or `ephemeral = true` under `[server]`) the server
+//! needs no directory: it lays the world out in one of its own, in
+//! `/dev/shm` so that it lives in memory where there is one, or else in
+//! the temporary directory, and removes it as it stops. The journeys
+//! played in it are saved there too, and so end with the server.
+//!
+//! Integrations and tests can play a world the same way:
+//!
+//! ```ignore
+//! let root = EphemeralRoot::create()?;
+//! let fs = EternalFS::new(root.path().to_path_buf(), world);
+//! ```
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rand::Rng;
+use tracing::{debug, warn};
+
+/// Where the directory is made if it can be, as files there live in memory
+const SHARED_MEMORY: &str = "/dev/shm";
+
+/// A directory of its own for a world, removed once dropped
+#[derive(Debug)]
+pub struct EphemeralRoot {
+    path: PathBuf,
+}
+
+impl EphemeralRoot {
+    /// Makes a new, empty directory in shared memory, or in the temporary
+    /// directory if there is none
+    pub fn create() -> io::Result<EphemeralRoot> {
+        let name = format!(
+            "eternal-fs-{}-{:08x}",
+            std::process::id(),
+            rand::thread_rng().gen::<u32>()
+        );
+        let shm = Path::new(SHARED_MEMORY);
+        if shm.is_dir() {
+            // it may be there and still not be writable
+            if let Ok(root) = Self::create_in(shm.join(&name)) {
+                return Ok(root);
+            }
+        }
+        Self::create_in(std::env::temp_dir().join(&name))
+    }
+
+    fn create_in(path: PathBuf) -> io::Result<EphemeralRoot> {
+        std::fs::create_dir(&path)?;
+        debug!("Playing an ephemeral world in {:?}", path);
+        Ok(EphemeralRoot { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for EphemeralRoot {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            warn!("Unable to remove {:?}: {:?}", self.path, e);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! How answers are judged.
+//!
+//! A [`ResponseEvaluator`] decides whether an answer to a stage passes and
+//! may reply to it in its own words. Out of the box answers are judged by
+//! the keywords of their stage with [`KeywordEvaluator`].
+//! [`EmbeddingEvaluator`] compares them with example answers by meaning,
+//! using word vectors read from disk, and built with the `llm` feature
+//! [`LlmEvaluator`] has a language model served by Ollama or an OpenAI
+//! compatible API score how deep an answer goes.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tracing::{debug, info};
+
+use crate::packs::Stage;
+
+/// What an evaluator made of an answer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+    pub passed: bool,
+    /// The reply to the answer, if not the stage's own
+    pub reply: Option<String>,
+}
+
+/// Judges the answers given to the stages of a world
+#[async_trait]
+pub trait ResponseEvaluator: Debug + Send + Sync {
+    /// Judges `response`, given to `stage`. The response is long enough
+    /// to be considered.
+    async fn evaluate(&self, stage: &Stage, response: &str) -> Evaluation;
+}
+
+/// Passes answers which contain every keyword of their stage
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeywordEvaluator;
+
+#[async_trait]
+impl ResponseEvaluator for KeywordEvaluator {
+    async fn evaluate(&self, stage: &Stage, response: &str) -> Evaluation {
+        Evaluation {
+            passed: stage.accepts(response),
+            reply: None,
+        }
+    }
+}
+
+/// Similarity an answer needs to an example answer, unless configured
+const DEFAULT_SIMILARITY: f32 = 0.8;
+
+/// Word vectors, read from a text file in the word2vec or GloVe format:
+/// one word per line followed by its vector. A word2vec header line
+/// giving the number of words and dimensions is skipped.
+#[derive(Debug)]
+pub struct WordVectors {
+    dimensions: usize,
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl WordVectors {
+    pub fn load(path: &Path) -> Result<WordVectors, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read {:?}: {}", path, e))?;
+        let mut dimensions = 0;
+        let mut vectors = HashMap::new();
+        for (n, line) in contents.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let Some(word) = fields.next() else {
+                continue;
+            };
+            let vector: Vec<f32> = fields
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("invalid vector on line {} of {:?}", n + 1, path))?;
+            if n == 0 && vector.len() == 1 {
+                // the word2vec header
+                continue;
+            }
+            if dimensions == 0 {
+                dimensions = vector.len();
+            }
+            if vector.len() != dimensions {
+                return Err(format!(
+                    "line {} of {:?} has {} dimensions instead of {}",
+                    n + 1,
+                    path,
+                    vector.len(),
+                    dimensions
+                ));
+            }
+            vectors.insert(word.to_lowercase(), vector);
+        }
+        if vectors.is_empty() {
+            return Err(format!("{:?} holds no word vectors", path));
+        }
+        info!("Loaded {} word vectors from {:?}", vectors.len(), path);
+        Ok(WordVectors {
+            dimensions,
+            vectors,
+        })
+    }
+
+    /// The mean of the vectors of the known words in `text`, if it has any
+    pub fn embed(&self, text: &str) -> Option<Vec<f32>> {
+        let mut sum = vec![0.0; self.dimensions];
+        let mut known = 0;
+        let lowered = text.to_lowercase();
+        for word in lowered.split(|c: char| !c.is_alphanumeric() && c != '\'') {
+            if let Some(vector) = self.vectors.get(word) {
+                sum.iter_mut().zip(vector).for_each(|(s, v)| *s += v);
+                known += 1;
+            }
+        }
+        (known > 0).then(|| sum.into_iter().map(|s| s / known as f32).collect())
+    }
+}
+
+/// Cosine of the angle between two vectors
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// Passes answers which mean about the same as one of the example answers
+/// of their stage, by the cosine similarity of their embeddings. Stages
+/// without examples are judged by their keywords.
+#[derive(Debug)]
+pub struct EmbeddingEvaluator {
+    model: WordVectors,
+    /// Similarity needed unless the stage asks for another
+    similarity: f32,
+}
+
+impl EmbeddingEvaluator {
+    pub fn new(model: WordVectors, similarity: f32) -> Self {
+        EmbeddingEvaluator { model, similarity }
+    }
+}
+
+#[async_trait]
+impl ResponseEvaluator for EmbeddingEvaluator {
+    async fn evaluate(&self, stage: &Stage, response: &str) -> Evaluation {
+        if stage.def.exemplars.is_empty() {
+            return KeywordEvaluator.evaluate(stage, response).await;
+        }
+        let best = self.model.embed(response).map_or(0.0, |answer| {
+            stage
+                .def
+                .exemplars
+                .iter()
+                .filter_map(|exemplar| self.model.embed(exemplar))
+                .map(|exemplar| cosine_similarity(&answer, &exemplar))
+                .fold(0.0, f32::max)
+        });
+        let needed = stage.def.similarity.unwrap_or(self.similarity);
+        debug!(
+            "An answer to {} is {:.3} similar to its examples, {:.3} needed",
+            stage.dir, best, needed
+        );
+        Evaluation {
+            passed: best >= needed,
+            reply: None,
+        }
+    }
+}
+
+/// Which evaluator judges the answers, from the `[game.evaluator]` section
+/// of the configuration:
+///
+/// ```text
+/// [game.evaluator]
+/// kind = "ollama"
+/// url = "http://localhost:11434"
+/// model = "llama3"
+/// pass_score = 6
+/// ```
+///
+/// or, to compare answers with the examples of their stages:
+///
+/// ```text
+/// [game.evaluator]
+/// kind = "embedding"
+/// model = "/usr/share/glove/glove.6B.100d.txt"
+/// similarity = 0.8
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvaluatorConfig {
+    /// `keywords`, `embedding`, `ollama` or `openai`
+    pub kind: Option<String>,
+    /// Base URL of the API
+    pub url: Option<String>,
+    /// The model to ask, or the file of word vectors to embed answers with
+    pub model: Option<String>,
+    /// Environment variable holding the API key, `OPENAI_API_KEY` unless
+    /// given
+    pub api_key_env: Option<String>,
+    /// Score out of 10 an answer needs to pass
+    pub pass_score: Option<u8>,
+    /// Seconds to wait for the model before falling back to the keywords
+    pub timeout_secs: Option<u64>,
+    /// Cosine similarity to an example answer an answer needs to pass
+    pub similarity: Option<f32>,
+}
+
+impl EvaluatorConfig {
+    /// Builds the configured evaluator
+    pub fn build(&self) -> Result<Box<dyn ResponseEvaluator>, String> {
+        match self.kind.as_deref().unwrap_or("keywords") {
+            "keywords" => Ok(Box::new(KeywordEvaluator)),
+            "embedding" => {
+                let model = self
+                    .model
+                    .as_deref()
+                    .ok_or("the embedding evaluator needs a model of word vectors")?;
+                Ok(Box::new(EmbeddingEvaluator::new(
+                    WordVectors::load(Path::new(model))?,
+                    self.similarity.unwrap_or(DEFAULT_SIMILARITY),
+                )))
+            }
+            #[cfg(feature = "llm")]
+            kind @ ("ollama" | "openai") => Ok(Box::new(llm::LlmEvaluator::new(kind, self)?)),
+            #[cfg(not(feature = "llm"))]
+            kind @ ("ollama" | "openai") => Err(format!(
+                "the {} evaluator needs eternal-fs to be built with the llm feature",
+                kind
+            )),
+            kind => Err(format!("unknown evaluator {:?}", kind)),
+        }
+    }
+}
+
+#[cfg(feature = "llm")]
+pub use llm::LlmEvaluator;
+
+#[cfg(feature = "llm")]
+mod llm {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use serde_json::json;
+    use tracing::{debug, warn};
+
+    use super::{Evaluation, EvaluatorConfig, KeywordEvaluator, ResponseEvaluator};
+    use crate::packs::Stage;
+
+    const DEFAULT_PASS_SCORE: u8 = 6;
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(20);
+
+    /// What the model is asked to do with every answer
+    const INSTRUCTIONS: &str = "You are the voice of a philosophical world a player \
+        explores. Judge how deeply the player's answer engages with the question, \
+        not whether it uses particular words. Reply with a JSON object only: \
+        {\"score\": <0 to 10>, \"reply\": \"<two or three sentences spoken by the \
+        world to the player about their answer>\"}";
+
+    /// Which API the model is served with
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Api {
+        /// `/api/chat` of Ollama
+        Ollama,
+        /// `/v1/chat/completions`, of OpenAI or a compatible server
+        OpenAi,
+    }
+
+    /// Scores answers with a language model. Answers are judged by their
+    /// keywords whenever the model can not be reached or makes no sense.
+    #[derive(Debug, Clone)]
+    pub struct LlmEvaluator {
+        api: Api,
+        url: String,
+        model: String,
+        api_key: Option<String>,
+        pass_score: u8,
+        agent: ureq::Agent,
+    }
+
+    /// The verdict the model is asked for
+    #[derive(Debug, Deserialize)]
+    struct Verdict {
+        score: f64,
+        reply: String,
+    }
+
+    impl LlmEvaluator {
+        pub(super) fn new(kind: &str, config: &EvaluatorConfig) -> Result<Self, String> {
+            let (api, url, model) = match kind {
+                "ollama" => (Api::Ollama, "http://localhost:11434", "llama3"),
+                _ => (Api::OpenAi, "https://api.openai.com", "gpt-4o-mini"),
+            };
+            let api_key = match api {
+                Api::Ollama => None,
+                Api::OpenAi => {
+                    let var = config.api_key_env.as_deref().unwrap_or("OPENAI_API_KEY");
+                    Some(std::env::var(var).map_err(|_| format!("{} is not set", var))?)
+                }
+            };
+            let timeout = config
+                .timeout_secs
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_TIMEOUT);
+            Ok(LlmEvaluator {
+                api,
+                url: config
+                    .url
+                    .as_deref()
+                    .unwrap_or(url)
+                    .trim_end_matches('/')
+                    .to_string(),
+                model: config.model.clone().unwrap_or_else(|| model.to_string()),
+                api_key,
+                pass_score: config.pass_score.unwrap_or(DEFAULT_PASS_SCORE),
+                agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+            })
+        }
+
+        /// Asks the model for its verdict, blocking until it has one
+        fn ask(&self, stage: &Stage, response: &str) -> Result<Verdict, String> {
+            let prompt = format!(
+                "Question: {}\nIdeas a good answer touches: {}\n\nThe player's answer:\n{}",
+                stage.def.question,
+                stage.def.keywords.join(", "),
+                response
+            );
+            let messages = json!([
+                {"role": "system", "content": INSTRUCTIONS},
+                {"role": "user", "content": prompt},
+            ]);
+            let content = match self.api {
+                Api::Ollama => {
+                    let body: serde_json::Value = self
+                        .agent
+                        .post(&format!("{}/api/chat", self.url))
+                        .send_json(json!({
+                            "model": self.model,
+                            "messages": messages,
+                            "format": "json",
+                            "stream": false,
+                        }))
+                        .map_err(|e| e.to_string())?
+                        .into_json()
+                        .map_err(|e| e.to_string())?;
+                    body["message"]["content"].as_str().map(String::from)
+                }
+                Api::OpenAi => {
+                    let mut request = self
+                        .agent
+                        .post(&format!("{}/v1/chat/completions", self.url));
+                    if let Some(key) = &self.api_key {
+                        request = request.set("Authorization", &format!("Bearer {}", key));
+                    }
+                    let body: serde_json::Value = request
+                        .send_json(json!({
+                            "model": self.model,
+                            "messages": messages,
+                            "response_format": {"type": "json_object"},
+                        }))
+                        .map_err(|e| e.to_string())?
+                        .into_json()
+                        .map_err(|e| e.to_string())?;
+                    body["choices"][0]["message"]["content"]
+                        .as_str()
+                        .map(String::from)
+                }
+            };
+            let content = content.ok_or("the reply holds no message")?;
+            serde_json::from_str(&content).map_err(|e| format!("{}: {:?}", e, content))
+        }
+    }
+
+    #[async_trait]
+    impl ResponseEvaluator for LlmEvaluator {
+        async fn evaluate(&self, stage: &Stage, response: &str) -> Evaluation {
+            let (evaluator, asked, answer) = (self.clone(), stage.clone(), response.to_string());
+            let verdict = tokio::task::spawn_blocking(move || evaluator.ask(&asked, &answer))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|verdict| verdict);
+            match verdict {
+                Ok(verdict) => {
+                    debug!(
+                        "The model scored an answer to {} {}",
+                        stage.dir, verdict.score
+                    );
+                    Evaluation {
+                        passed: verdict.score >= self.pass_score as f64,
+                        reply: Some(verdict.reply).filter(|reply| !reply.trim().is_empty()),
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Judging the answer by its keywords, the model failed: {}",
+                        e
+                    );
+                    KeywordEvaluator.evaluate(stage, response).await
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! What happens in the world, as it happens.
+//!
+//! The file system and the game publish their events on the [`Events`] of
+//! the world, for whoever listens: the HTTP API streams them to external
+//! tools over a WebSocket, see `http.rs`. Nobody has to, and an event
+//! nobody listens to is dropped. A listener too slow to keep up misses
+//! the oldest events it has not taken yet.
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events kept for every listener before the oldest are dropped
+const BACKLOG: usize = 1024;
+
+/// Something that happened in the world
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// When it happened, in RFC 3339
+    pub at: String,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// What happened. Paths are relative to the root of the world.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum EventKind {
+    FileCreated {
+        path: String,
+        dir: bool,
+    },
+    FileRemoved {
+        path: String,
+    },
+    FileRenamed {
+        from: String,
+        to: String,
+    },
+    /// A player's answer to a stage or side quest was accepted
+    AnswerAccepted {
+        player: String,
+        location: String,
+    },
+    /// A player moved on to another stage of the journey
+    StageAdvanced {
+        player: String,
+        from: String,
+        to: String,
+    },
+    /// The quantum state was observed and collapsed into `state`
+    QuantumCollapse {
+        state: String,
+    },
+    /// An operator cloned the world into `dir` on the host, to be played
+    /// as `name`, see `clones.rs`
+    WorldCloned {
+        name: String,
+        dir: String,
+    },
+}
+
+/// The events of a world, published to everyone listening
+#[derive(Debug, Clone)]
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            sender: broadcast::channel(BACKLOG).0,
+        }
+    }
+}
+
+impl Events {
+    /// Tells everyone listening that `kind` happened just now
+    pub fn publish(&self, kind: EventKind) {
+        let event = Event {
+            at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            kind,
+        };
+        // nobody listening is fine
+        let _ = self.sender.send(event);
+    }
+
+    /// Listens to the events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
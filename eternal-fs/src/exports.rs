@@ -0,0 +1,497 @@
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tracing::debug;
+
+use nfsserve::nfs::*;
+use nfsserve::vfs::{
+    DirEntry, DirEntrySimple, NFSFileSystem, ReadDirResult, ReadDirSimpleResult, VFSCapabilities,
+};
+
+use crate::FILEID_BITS;
+
+/// Fileid of the directory listing the exports
+const ROOT_ID: fileid3 = 0;
+
+/// Most exports a server can carry, bounded by the bits left in a fileid
+const MAX_EXPORTS: usize = (1 << (64 - FILEID_BITS)) - 1;
+
+/// A world, or another file system such as a bucket, exported as a top
+/// level directory
+struct Export {
+    name: Vec<u8>,
+    fs: Arc<dyn NFSFileSystem + Send + Sync>,
+}
+
+impl fmt::Debug for Export {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Export")
+            .field("name", &String::from_utf8_lossy(&self.name))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serves several worlds from one server, each as a directory under the
+/// NFS root.
+///
+/// Every export keeps its own fileid namespace: the fileids of an export
+/// are tagged with its position in the top bits, so handles of one export
+/// can never be mistaken for handles of another. An export has its own
+/// game state unless it shares its [`EternalFS`](crate::EternalFS) with
+/// another export. Exports can be added while they are served.
+#[derive(Debug)]
+pub struct Exports {
+    exports: RwLock<Vec<Arc<Export>>>,
+    created: nfstime3,
+}
+
+impl Default for Exports {
+    fn default() -> Exports {
+        Exports::new()
+    }
+}
+
+impl Exports {
+    pub fn new() -> Exports {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        Exports {
+            exports: RwLock::new(Vec::new()),
+            created: nfstime3 {
+                seconds: now.as_secs() as u32,
+                nseconds: now.subsec_nanos(),
+            },
+        }
+    }
+
+    /// Exports a world, or any other file system, under `name`. Several
+    /// names may export the same world, in which case they share its game
+    /// state.
+    pub fn add(&self, name: &str, fs: Arc<dyn NFSFileSystem + Send + Sync>) -> Result<(), String> {
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return Err(format!("{:?} is not a valid export name", name));
+        }
+        let mut exports = self.exports.write().unwrap();
+        if exports.iter().any(|e| e.name == name.as_bytes()) {
+            return Err(format!("export {:?} is defined twice", name));
+        }
+        if exports.len() >= MAX_EXPORTS {
+            return Err(format!(
+                "no more than {} exports are supported",
+                MAX_EXPORTS
+            ));
+        }
+        exports.push(Arc::new(Export {
+            name: name.as_bytes().to_vec(),
+            fs,
+        }));
+        Ok(())
+    }
+
+    /// The exports as they are now
+    fn exports(&self) -> Vec<Arc<Export>> {
+        self.exports.read().unwrap().clone()
+    }
+
+    fn tag(index: usize, id: fileid3) -> fileid3 {
+        ((index as fileid3 + 1) << FILEID_BITS) | id
+    }
+
+    /// Finds the export a fileid belongs to, returning its position, the
+    /// export and the fileid within the export
+    fn untag(&self, id: fileid3) -> Result<(usize, Arc<Export>, fileid3), nfsstat3> {
+        let index = ((id >> FILEID_BITS) as usize)
+            .checked_sub(1)
+            .ok_or(nfsstat3::NFS3ERR_STALE)?;
+        let export = self
+            .exports
+            .read()
+            .unwrap()
+            .get(index)
+            .cloned()
+            .ok_or(nfsstat3::NFS3ERR_STALE)?;
+        Ok((index, export, id & ((1 << FILEID_BITS) - 1)))
+    }
+
+    /// Resolves two fileids which must be in the same export
+    fn untag_pair(
+        &self,
+        a: fileid3,
+        b: fileid3,
+    ) -> Result<(usize, Arc<Export>, fileid3, fileid3), nfsstat3> {
+        let (index, export, a) = self.untag(a)?;
+        let (other, _, b) = self.untag(b)?;
+        if index != other {
+            return Err(nfsstat3::NFS3ERR_XDEV);
+        }
+        Ok((index, export, a, b))
+    }
+
+    fn retag_attr(index: usize, mut attr: fattr3) -> fattr3 {
+        attr.fileid = Self::tag(index, attr.fileid);
+        attr
+    }
+
+    fn root_attr(&self) -> fattr3 {
+        fattr3 {
+            ftype: ftype3::NF3DIR,
+            mode: 0o555,
+            nlink: 2 + self.exports.read().unwrap().len() as u32,
+            fileid: ROOT_ID,
+            atime: self.created,
+            mtime: self.created,
+            ctime: self.created,
+            ..fattr3::default()
+        }
+    }
+
+    /// Lists the exports as the entries of the root directory
+    async fn list_root(&self, start_after: fileid3, max_entries: usize) -> (Vec<DirEntry>, bool) {
+        let mut entries = Vec::new();
+        for (index, export) in self.exports().iter().enumerate() {
+            let fileid = Self::tag(index, export.fs.root_dir());
+            if fileid <= start_after {
+                continue;
+            }
+            if entries.len() >= max_entries {
+                return (entries, false);
+            }
+            let attr = match export.fs.getattr(export.fs.root_dir()).await {
+                Ok(attr) => Self::retag_attr(index, attr),
+                Err(_) => fattr3 {
+                    fileid,
+                    ..self.root_attr()
+                },
+            };
+            entries.push(DirEntry {
+                fileid,
+                name: export.name.as_slice().into(),
+                attr,
+            });
+        }
+        (entries, true)
+    }
+}
+
+#[async_trait]
+impl NFSFileSystem for Exports {
+    fn root_dir(&self) -> fileid3 {
+        ROOT_ID
+    }
+    fn capabilities(&self) -> VFSCapabilities {
+        // exports which are read-only refuse modifications themselves
+        if self
+            .exports()
+            .iter()
+            .any(|e| matches!(e.fs.capabilities(), VFSCapabilities::ReadWrite))
+        {
+            VFSCapabilities::ReadWrite
+        } else {
+            VFSCapabilities::ReadOnly
+        }
+    }
+
+    async fn lookup(&self, dirid: fileid3, filename: &filename3) -> Result<fileid3, nfsstat3> {
+        if dirid == ROOT_ID {
+            let (index, export) = self
+                .exports()
+                .into_iter()
+                .enumerate()
+                .find(|(_, e)| e.name == filename.as_ref())
+                .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+            return Ok(Self::tag(index, export.fs.root_dir()));
+        }
+        let (index, export, dirid) = self.untag(dirid)?;
+        Ok(Self::tag(index, export.fs.lookup(dirid, filename).await?))
+    }
+
+    async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
+        if id == ROOT_ID {
+            return Ok(self.root_attr());
+        }
+        let (index, export, id) = self.untag(id)?;
+        Ok(Self::retag_attr(index, export.fs.getattr(id).await?))
+    }
+
+    async fn wcc_attr(&self, id: fileid3) -> Result<wcc_attr, nfsstat3> {
+        if id == ROOT_ID {
+            let attr = self.root_attr();
+            return Ok(wcc_attr {
+                size: attr.size,
+                mtime: attr.mtime,
+                ctime: attr.ctime,
+            });
+        }
+        let (_, export, id) = self.untag(id)?;
+        export.fs.wcc_attr(id).await
+    }
+
+    async fn setattr(&self, id: fileid3, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        if id == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (index, export, id) = self.untag(id)?;
+        Ok(Self::retag_attr(
+            index,
+            export.fs.setattr(id, setattr).await?,
+        ))
+    }
+
+    async fn read(&self, id: fileid3, offset: u64, count: u32) -> Result<(Bytes, bool), nfsstat3> {
+        if id == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        }
+        let (_, export, id) = self.untag(id)?;
+        export.fs.read(id, offset, count).await
+    }
+
+    async fn write(&self, id: fileid3, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        if id == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        }
+        let (index, export, id) = self.untag(id)?;
+        Ok(Self::retag_attr(
+            index,
+            export.fs.write(id, offset, data).await?,
+        ))
+    }
+
+    async fn write_unstable(
+        &self,
+        id: fileid3,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(fattr3, stable_how), nfsstat3> {
+        if id == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        }
+        let (index, export, id) = self.untag(id)?;
+        let (attr, committed) = export.fs.write_unstable(id, offset, data).await?;
+        Ok((Self::retag_attr(index, attr), committed))
+    }
+
+    async fn commit(&self, id: fileid3, offset: u64, count: u32) -> Result<fattr3, nfsstat3> {
+        if id == ROOT_ID {
+            return self.getattr(id).await;
+        }
+        let (index, export, id) = self.untag(id)?;
+        Ok(Self::retag_attr(
+            index,
+            export.fs.commit(id, offset, count).await?,
+        ))
+    }
+
+    async fn create(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+        attr: sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        if dirid == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (index, export, dirid) = self.untag(dirid)?;
+        let (id, attr) = export.fs.create(dirid, filename, attr).await?;
+        Ok((Self::tag(index, id), Self::retag_attr(index, attr)))
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+    ) -> Result<fileid3, nfsstat3> {
+        if dirid == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (index, export, dirid) = self.untag(dirid)?;
+        Ok(Self::tag(
+            index,
+            export.fs.create_exclusive(dirid, filename).await?,
+        ))
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.mkdir_with_attrs(dirid, dirname, &sattr3::default())
+            .await
+    }
+
+    async fn mkdir_with_attrs(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        if dirid == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (index, export, dirid) = self.untag(dirid)?;
+        let (id, attr) = export.fs.mkdir_with_attrs(dirid, dirname, attr).await?;
+        Ok((Self::tag(index, id), Self::retag_attr(index, attr)))
+    }
+
+    async fn remove(&self, dirid: fileid3, filename: &filename3) -> Result<(), nfsstat3> {
+        if dirid == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (_, export, dirid) = self.untag(dirid)?;
+        export.fs.remove(dirid, filename).await
+    }
+
+    async fn rename(
+        &self,
+        from_dirid: fileid3,
+        from_filename: &filename3,
+        to_dirid: fileid3,
+        to_filename: &filename3,
+    ) -> Result<(), nfsstat3> {
+        if from_dirid == ROOT_ID || to_dirid == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (_, export, from_dirid, to_dirid) = self.untag_pair(from_dirid, to_dirid)?;
+        export
+            .fs
+            .rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await
+    }
+
+    async fn link(
+        &self,
+        fileid: fileid3,
+        linkdirid: fileid3,
+        linkname: &filename3,
+    ) -> Result<(), nfsstat3> {
+        if fileid == ROOT_ID || linkdirid == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (_, export, fileid, linkdirid) = self.untag_pair(fileid, linkdirid)?;
+        export.fs.link(fileid, linkdirid, linkname).await
+    }
+
+    async fn readdir(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<ReadDirResult, nfsstat3> {
+        if dirid == ROOT_ID {
+            let (entries, end) = self.list_root(start_after, max_entries).await;
+            return Ok(ReadDirResult { entries, end });
+        }
+        let (index, export, dirid) = self.untag(dirid)?;
+        // the cookie is a fileid of this export, or 0 to start over
+        let start_after = self.untag(start_after).map_or(0, |(_, _, id)| id);
+        let mut result = export.fs.readdir(dirid, start_after, max_entries).await?;
+        for entry in result.entries.iter_mut() {
+            entry.fileid = Self::tag(index, entry.fileid);
+            entry.attr = Self::retag_attr(index, entry.attr);
+        }
+        debug!(
+            "readdir of export {:?}: {} entries",
+            String::from_utf8_lossy(&export.name),
+            result.entries.len()
+        );
+        Ok(result)
+    }
+
+    async fn readdir_simple_paged(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        count: usize,
+    ) -> Result<ReadDirSimpleResult, nfsstat3> {
+        if dirid == ROOT_ID {
+            let (entries, end) = self.list_root(start_after, count).await;
+            let entries = entries
+                .into_iter()
+                .map(|e| DirEntrySimple {
+                    fileid: e.fileid,
+                    name: e.name,
+                })
+                .collect();
+            return Ok(ReadDirSimpleResult { entries, end });
+        }
+        let (index, export, dirid) = self.untag(dirid)?;
+        let start_after = self.untag(start_after).map_or(0, |(_, _, id)| id);
+        let mut result = export
+            .fs
+            .readdir_simple_paged(dirid, start_after, count)
+            .await?;
+        for entry in result.entries.iter_mut() {
+            entry.fileid = Self::tag(index, entry.fileid);
+        }
+        Ok(result)
+    }
+
+    async fn symlink(
+        &self,
+        dirid: fileid3,
+        linkname: &filename3,
+        symlink: &nfspath3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        if dirid == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (index, export, dirid) = self.untag(dirid)?;
+        let (id, attr) = export.fs.symlink(dirid, linkname, symlink, attr).await?;
+        Ok((Self::tag(index, id), Self::retag_attr(index, attr)))
+    }
+
+    async fn mknod(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+        ftype: ftype3,
+        spec: specdata3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        if dirid == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let (index, export, dirid) = self.untag(dirid)?;
+        let (id, attr) = export.fs.mknod(dirid, filename, ftype, spec, attr).await?;
+        Ok((Self::tag(index, id), Self::retag_attr(index, attr)))
+    }
+
+    async fn readlink(&self, id: fileid3) -> Result<nfspath3, nfsstat3> {
+        if id == ROOT_ID {
+            return Err(nfsstat3::NFS3ERR_BADTYPE);
+        }
+        let (_, export, id) = self.untag(id)?;
+        export.fs.readlink(id).await
+    }
+
+    async fn fsstat(&self, fileid: fileid3) -> Result<fsstat3, nfsstat3> {
+        if fileid == ROOT_ID {
+            // the root reports the space of the first world
+            let export = self
+                .exports()
+                .first()
+                .cloned()
+                .ok_or(nfsstat3::NFS3ERR_IO)?;
+            let mut stat = export.fs.fsstat(export.fs.root_dir()).await?;
+            stat.obj_attributes = post_op_attr::attributes(self.root_attr());
+            return Ok(stat);
+        }
+        let (index, export, fileid) = self.untag(fileid)?;
+        let mut stat = export.fs.fsstat(fileid).await?;
+        if let post_op_attr::attributes(attr) = stat.obj_attributes {
+            stat.obj_attributes = post_op_attr::attributes(Self::retag_attr(index, attr));
+        }
+        Ok(stat)
+    }
+
+    async fn shutdown(&self) {
+        for export in self.exports() {
+            export.fs.shutdown().await;
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! How the objects of the host are given fileids.
+//!
+//! By default fileids are counted up as objects are first seen, so that
+//! the same file has another fileid after every restart, and in every
+//! server serving the same directory. Tools which remember files by
+//! fileid, such as backup and sync tools, then take every file for a new
+//! one. With `path` a file's fileid is a hash of its path below the root
+//! of the world instead, and with `inode` a hash of the device and inode
+//! it is on the host, which also holds as it is renamed, so that fileids
+//! are the same however often and by however many servers it is served.
+//!
+//! Hashed fileids lie above those counted up for the objects the game
+//! makes up, so the two never meet, and stages which shuffle the order
+//! objects are created in need no random fileids of their own, as hashed
+//! ones tell nothing of it. Should two objects hash to the same fileid,
+//! the one seen later takes the next one free, which is then only as
+//! stable as the order the two are seen in.
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use sha2::{Digest, Sha256};
+
+use nfsserve::nfs::fileid3;
+
+use crate::FILEID_BITS;
+
+/// Hashed fileids start here, above the ones counted up
+const HASHED_FROM: fileid3 = 1 << 32;
+
+/// How the objects of the host are given fileids
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileidStrategy {
+    /// Counted up as objects are first seen
+    #[default]
+    Counter,
+    /// A hash of the path of the object below the root of the world
+    Path,
+    /// A hash of the device and inode of the object on the host
+    Inode,
+}
+
+impl FromStr for FileidStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "counter" => Ok(FileidStrategy::Counter),
+            "path" => Ok(FileidStrategy::Path),
+            "inode" => Ok(FileidStrategy::Inode),
+            _ => Err(format!("unknown fileid strategy {:?}", s)),
+        }
+    }
+}
+
+impl FileidStrategy {
+    /// The fileid of the object at `path` below the root of the world,
+    /// which is `ino` on `dev` of the host, unless fileids are counted up
+    pub(crate) fn hashed(&self, path: &Path, dev: u64, ino: u64) -> Option<fileid3> {
+        let mut hasher = Sha256::new();
+        match self {
+            FileidStrategy::Counter => return None,
+            FileidStrategy::Path => hasher.update(path.as_os_str().as_bytes()),
+            FileidStrategy::Inode => {
+                hasher.update(dev.to_le_bytes());
+                hasher.update(ino.to_le_bytes());
+            }
+        }
+        let digest = hasher.finalize();
+        let hash = u64::from_le_bytes(digest[..8].try_into().unwrap());
+        Some(HASHED_FROM + hash % ((1 << FILEID_BITS) - HASHED_FROM))
+    }
+}
+
+/// The fileid after `id` to try as `id` is taken, among the hashed ones
+pub(crate) fn next_hashed(id: fileid3) -> fileid3 {
+    match id + 1 {
+        id if id >= 1 << FILEID_BITS => HASHED_FROM,
+        id => id,
+    }
+}
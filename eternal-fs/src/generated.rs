@@ -0,0 +1,239 @@
+//! Read-only directories made up by the game.
+//!
+//! A [`GameEngine`](crate::engine::GameEngine) can name directories at
+//! the root of the world which are not on the host, see
+//! [`generated_dirs`](crate::engine::GameEngine::generated_dirs). They are
+//! listed after what is on the host, and their files are asked of the game
+//! whenever they are looked up, listed or read, so every player sees their
+//! own. Nothing in them can be changed.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nfsserve::nfs::*;
+
+use crate::engine::GeneratedFile;
+use crate::xattrs::paginate;
+use crate::FSMap;
+
+/// An object of a generated directory
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum GeneratedNode {
+    /// A generated directory, by name
+    Dir(String),
+    /// A file of a generated directory
+    File(String, String),
+}
+
+/// Fileids handed out for generated objects. They are drawn from the same
+/// counter as real fileids and, like them, never reused.
+#[derive(Debug, Default)]
+pub(crate) struct GeneratedNodes {
+    by_id: HashMap<fileid3, GeneratedNode>,
+    by_node: HashMap<GeneratedNode, fileid3>,
+}
+
+impl GeneratedNodes {
+    /// The generated object behind a fileid, if it is one
+    pub(crate) fn get(&self, id: fileid3) -> Option<GeneratedNode> {
+        self.by_id.get(&id).cloned()
+    }
+
+    fn id_of(&mut self, node: GeneratedNode, next_fileid: &AtomicU64) -> fileid3 {
+        if let Some(id) = self.by_node.get(&node) {
+            return *id;
+        }
+        let id = next_fileid.fetch_add(1, Ordering::Relaxed);
+        self.by_id.insert(id, node.clone());
+        self.by_node.insert(node, id);
+        id
+    }
+}
+
+pub(crate) fn to_nfstime(time: SystemTime) -> nfstime3 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    nfstime3 {
+        seconds: since_epoch.as_secs() as u32,
+        nseconds: since_epoch.subsec_nanos(),
+    }
+}
+
+/// A page of a listing, and whether it is the last
+pub(crate) type Listing = (Vec<(fileid3, String, fattr3)>, bool);
+
+/// Derives the attributes of a generated object from those of the root.
+/// Files are the size of their contents, everything else is a directory.
+/// Nothing can be written.
+fn generated_fattr(
+    base: fattr3,
+    id: fileid3,
+    file: Option<&GeneratedFile>,
+    mtime: nfstime3,
+) -> fattr3 {
+    let (ftype, mode, nlink, size) = match file {
+        Some(file) => (ftype3::NF3REG, 0o444, 1, file.contents.len() as u64),
+        None => (ftype3::NF3DIR, 0o555, 2, 0),
+    };
+    fattr3 {
+        ftype,
+        mode,
+        nlink,
+        size,
+        used: size,
+        rdev: specdata3::default(),
+        fileid: id,
+        atime: mtime,
+        mtime,
+        ctime: mtime,
+        ..base
+    }
+}
+
+impl FSMap {
+    /// The file `name` of the generated directory `dir`, as the calling
+    /// player sees it
+    fn generated_file(&self, dir: &str, name: &str) -> Result<GeneratedFile, nfsstat3> {
+        self.game
+            .generated_files(dir)
+            .into_iter()
+            .find(|file| file.name == name)
+            .ok_or(nfsstat3::NFS3ERR_NOENT)
+    }
+
+    /// Whether `name` is a generated directory at the root
+    pub(crate) fn is_generated_dir(&self, name: &[u8]) -> bool {
+        let dirs = self.game.generated_dirs();
+        dirs.iter().any(|dir| dir.as_bytes() == name)
+    }
+
+    /// Looks up `filename` in `dirid` if the lookup leads into a generated
+    /// directory. Returns None for lookups of real objects.
+    pub(crate) fn generated_lookup(
+        &mut self,
+        dirid: fileid3,
+        filename: &[u8],
+    ) -> Result<Option<fileid3>, nfsstat3> {
+        let node = match self.generated.get(dirid) {
+            None if dirid == 0 && self.is_generated_dir(filename) => {
+                GeneratedNode::Dir(String::from_utf8_lossy(filename).into_owned())
+            }
+            None => return Ok(None),
+            Some(GeneratedNode::Dir(dir)) => {
+                let name = std::str::from_utf8(filename).or(Err(nfsstat3::NFS3ERR_NOENT))?;
+                self.generated_file(&dir, name)?;
+                GeneratedNode::File(dir, name.to_string())
+            }
+            Some(GeneratedNode::File(..)) => return Err(nfsstat3::NFS3ERR_NOTDIR),
+        };
+        Ok(Some(self.generated.id_of(node, &self.next_fileid)))
+    }
+
+    pub(crate) fn generated_getattr(
+        &self,
+        id: fileid3,
+        node: &GeneratedNode,
+    ) -> Result<fattr3, nfsstat3> {
+        let root = self.find_entry(0)?.fsmeta;
+        match node {
+            GeneratedNode::Dir(dir) => {
+                if !self.is_generated_dir(dir.as_bytes()) {
+                    return Err(nfsstat3::NFS3ERR_STALE);
+                }
+                // the directory changes whenever a file in it does
+                let mtime = self
+                    .game
+                    .generated_files(dir)
+                    .iter()
+                    .map(|file| file.mtime)
+                    .max()
+                    .map_or(root.mtime, to_nfstime);
+                Ok(generated_fattr(root, id, None, mtime))
+            }
+            GeneratedNode::File(dir, name) => {
+                let file = self
+                    .generated_file(dir, name)
+                    .or(Err(nfsstat3::NFS3ERR_STALE))?;
+                Ok(generated_fattr(
+                    root,
+                    id,
+                    Some(&file),
+                    to_nfstime(file.mtime),
+                ))
+            }
+        }
+    }
+
+    pub(crate) fn generated_read(
+        &self,
+        node: &GeneratedNode,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let GeneratedNode::File(dir, name) = node else {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        };
+        let file = self
+            .generated_file(dir, name)
+            .or(Err(nfsstat3::NFS3ERR_STALE))?;
+        let start = offset.min(file.contents.len() as u64) as usize;
+        let end = start
+            .saturating_add(count as usize)
+            .min(file.contents.len());
+        Ok((
+            file.contents[start..end].to_vec(),
+            end >= file.contents.len(),
+        ))
+    }
+
+    /// Lists a generated directory from the cookie `start_after`
+    pub(crate) fn generated_list(
+        &mut self,
+        node: &GeneratedNode,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<Listing, nfsstat3> {
+        let GeneratedNode::Dir(dir) = node else {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        };
+        let root = self.find_entry(0)?.fsmeta;
+        let mut entries = Vec::new();
+        for file in self.game.generated_files(dir) {
+            let id = self.generated.id_of(
+                GeneratedNode::File(dir.clone(), file.name.clone()),
+                &self.next_fileid,
+            );
+            let attr = generated_fattr(root, id, Some(&file), to_nfstime(file.mtime));
+            entries.push((id, (file.name, attr)));
+        }
+        let (page, end) = paginate(entries, start_after, max_entries);
+        Ok(flatten(page, end))
+    }
+
+    /// Lists the generated directories at the root from the cookie
+    /// `start_after`, to follow the host entries of the root
+    pub(crate) fn generated_root_list(
+        &mut self,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<Listing, nfsstat3> {
+        let mut entries = Vec::new();
+        for dir in self.game.generated_dirs() {
+            let node = GeneratedNode::Dir(dir.clone());
+            let id = self.generated.id_of(node.clone(), &self.next_fileid);
+            entries.push((id, (dir, self.generated_getattr(id, &node)?)));
+        }
+        // the host entries were listed before, whatever their cookies
+        let start_after = if self.generated.get(start_after).is_some() {
+            start_after
+        } else {
+            0
+        };
+        let (page, end) = paginate(entries, start_after, max_entries);
+        Ok(flatten(page, end))
+    }
+}
+
+pub(crate) fn flatten(page: Vec<(fileid3, (String, fattr3))>, end: bool) -> Listing {
+    (page.into_iter().map(|(a, (b, c))| (a, b, c)).collect(), end)
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::{Arc, Once, Weak};
+use std::time::{Duration, Instant};
+
+use tokio::fs::{File, OpenOptions};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+use nfsserve::nfs::fileid3;
+
+use crate::atime;
+
+/// An open host file shared between NFS requests on the same fileid.
+#[derive(Debug)]
+struct PooledHandle {
+    file: Arc<Mutex<File>>,
+    writable: bool,
+    dev: u64,
+    ino: u64,
+    last_used: Instant,
+}
+
+/// `HandlePool` keeps host files open across NFS requests so that
+/// sequential reads and streams of small writes do not pay for an
+/// open/close on every RPC.
+///
+/// Handles are keyed by fileid and closed once they have been idle for
+/// longer than the idle timeout, synced to disk first if writable, as
+/// unstable writes are only synced when a client commits them. Idle
+/// handles are swept every idle timeout by a task started with the first
+/// handle, so they are closed within twice the timeout, and synced
+/// without holding up requests on other files. A cached handle is only
+/// reused if the path still refers to the same inode, so files replaced
+/// behind our back are reopened rather than served stale.
+#[derive(Debug)]
+pub struct HandlePool {
+    idle_timeout: Duration,
+    handles: Arc<Mutex<HashMap<fileid3, PooledHandle>>>,
+    sweeper: Once,
+}
+
+impl HandlePool {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            idle_timeout,
+            handles: Arc::new(Mutex::new(HashMap::new())),
+            sweeper: Once::new(),
+        }
+    }
+
+    /// Returns an open handle to `path` for `id`, opening it if necessary.
+    /// If `writable` is set the handle is opened for reading and writing.
+    /// The file is never created, as one removed or renamed while it is
+    /// opened must not come back.
+    pub async fn get(
+        &self,
+        id: fileid3,
+        path: &Path,
+        writable: bool,
+    ) -> io::Result<Arc<Mutex<File>>> {
+        self.sweeper.call_once(|| {
+            tokio::spawn(sweep(Arc::downgrade(&self.handles), self.idle_timeout));
+        });
+        // the pool is only locked to look handles up and put them in, so
+        // requests on other files never wait for a stat, an open or a sync
+        let pooled = match self.handles.lock().await.get(&id) {
+            Some(handle) if !writable || handle.writable => {
+                Some((handle.file.clone(), handle.dev, handle.ino))
+            }
+            _ => None,
+        };
+        if let Some((file, dev, ino)) = pooled {
+            match tokio::fs::symlink_metadata(path).await {
+                Ok(meta) if meta.dev() == dev && meta.ino() == ino => {
+                    if let Some(handle) = self.handles.lock().await.get_mut(&id) {
+                        if Arc::ptr_eq(&handle.file, &file) {
+                            handle.last_used = Instant::now();
+                        }
+                    }
+                    return Ok(file);
+                }
+                _ => debug!("Pooled handle for {:?} is stale", id),
+            }
+        }
+
+        let mut options = OpenOptions::new();
+        options.read(true);
+        if writable {
+            options.write(true);
+        }
+        let file = atime::open_async(&options, path).await?;
+        let meta = file.metadata().await?;
+        let file = Arc::new(Mutex::new(file));
+        debug!("Pooling handle for {:?} writable:{}", id, writable);
+        self.handles.lock().await.insert(
+            id,
+            PooledHandle {
+                file: file.clone(),
+                writable,
+                dev: meta.dev(),
+                ino: meta.ino(),
+                last_used: Instant::now(),
+            },
+        );
+        Ok(file)
+    }
+
+    /// Drops the pooled handle for `id`, if any.
+    /// Should be called whenever the file behind the id is removed.
+    pub async fn evict(&self, id: fileid3) {
+        self.handles.lock().await.remove(&id);
+    }
+
+    /// Syncs what was written to `path` for `id` to disk, through the
+    /// pooled handle if there is one.
+    pub async fn sync(&self, id: fileid3, path: &Path) -> io::Result<()> {
+        let pooled = match self.handles.lock().await.get(&id) {
+            Some(handle) if handle.writable => Some(handle.file.clone()),
+            _ => None,
+        };
+        let Some(file) = pooled else {
+            // syncing the host file syncs whatever was written to it
+            return File::open(path).await?.sync_all().await;
+        };
+        let file = file.lock().await;
+        file.sync_all().await
+    }
+
+    /// Syncs every writable handle to disk and closes all handles.
+    pub async fn close_all(&self) {
+        let closed: Vec<_> = self.handles.lock().await.drain().collect();
+        for (id, handle) in closed {
+            close(id, handle).await;
+        }
+    }
+}
+
+/// Closes `handle`, syncing it to disk first if it is writable
+async fn close(id: fileid3, handle: PooledHandle) {
+    if handle.writable {
+        if let Err(e) = handle.file.lock().await.sync_all().await {
+            debug!("Unable to sync pooled handle for {:?}: {:?}", id, e);
+        }
+    }
+}
+
+/// Closes the idle handles of the pool every `max_idle`, until the pool is
+/// dropped. The idle handles are taken out of the pool under its lock and
+/// synced once it is released.
+async fn sweep(handles: Weak<Mutex<HashMap<fileid3, PooledHandle>>>, max_idle: Duration) {
+    loop {
+        tokio::time::sleep(max_idle).await;
+        let Some(handles) = handles.upgrade() else {
+            return;
+        };
+        let now = Instant::now();
+        let idle: Vec<(fileid3, PooledHandle)> = {
+            let mut handles = handles.lock().await;
+            let ids: Vec<fileid3> = handles
+                .iter()
+                .filter(|(_, v)| now.duration_since(v.last_used) >= max_idle)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.into_iter()
+                .filter_map(|id| handles.remove(&id).map(|handle| (id, handle)))
+                .collect()
+        };
+        for (id, handle) in idle {
+            close(id, handle).await;
+        }
+    }
+}
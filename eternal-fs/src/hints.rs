@@ -0,0 +1,130 @@
+//! Hints, bought with tokens.
+//!
+//! Every player has a few hint tokens, and earns one more for every daily
+//! challenge they meet. Writing anything to
+//! `hints/request.txt` spends one on the next hint for the stage the player
+//! is at, each more specific than the last, and shows it in
+//! `hints/latest.txt`. The hints of a stage are its
+//! [`hint_ladder`](crate::packs::StageDef::hint_ladder).
+
+use tracing::{info, warn};
+
+use crate::packs::World;
+use crate::philosophy::{GameStage, Philosophy};
+use crate::players::PlayerState;
+
+/// The directory hints are asked for in
+pub(crate) const HINTS_DIR: &str = "hints";
+
+/// Hint tokens every player has, unless configured otherwise
+pub const DEFAULT_HINT_TOKENS: u32 = 3;
+
+/// The hints for the final answer, once every stage is answered
+const ENLIGHTENMENT_HINTS: &[&str] = &[
+    "All paths converge. What have they taught you?",
+    "Your answer should speak of understanding and of wisdom.",
+];
+
+const README: &str = "\
+    Hints\n\
+    =====\n\
+    Write anything to request.txt to spend a hint token on the stage you are at.\n\
+    Every hint you buy for a stage is more specific than the one before.\n\
+    The hint appears in latest.txt.\n";
+
+impl PlayerState {
+    /// The stage the player is at, with its hints
+    pub(crate) fn hints_at<'w>(&self, world: &'w World) -> (String, Vec<&'w str>) {
+        match self.open_stages(world).first() {
+            Some(stage) => (stage.dir.clone(), stage.def.hint_ladder()),
+            None => ("enlightenment".to_string(), ENLIGHTENMENT_HINTS.to_vec()),
+        }
+    }
+
+    /// Spends a token on the next hint for the stage the player is at, if
+    /// there is one left to give, and returns what latest.txt shows
+    fn request_hint(&mut self, world: &World, tokens: u32) -> String {
+        let tokens = tokens + self.bonus_hint_tokens();
+        if matches!(self.current_stage, GameStage::Enlightened) {
+            return "Your journey is complete. No hint remains to be given.\n".to_string();
+        }
+        let (stage, ladder) = self.hints_at(world);
+        if ladder.is_empty() {
+            return format!("There are no hints for {}.\n", stage);
+        }
+        let taken = self.hints_taken.get(&stage).copied().unwrap_or(0);
+        if taken >= ladder.len() {
+            return format!(
+                "You have every hint there is for {}.\n\n{}\n",
+                stage,
+                ladder[ladder.len() - 1]
+            );
+        }
+        if self.hint_tokens_spent >= tokens {
+            return "You have no hint tokens left. The path must be found alone.\n".to_string();
+        }
+        self.hint_tokens_spent += 1;
+        self.hints_taken.insert(stage.clone(), taken + 1);
+        info!("Hint {} of {} taken for {}", taken + 1, ladder.len(), stage);
+        let title = format!("Hint for {} ({} of {})", stage, taken + 1, ladder.len());
+        format!(
+            "{}\n{}\n\n{}\n\nHint tokens left: {}\n",
+            title,
+            "=".repeat(title.len()),
+            ladder[taken],
+            tokens - self.hint_tokens_spent
+        )
+    }
+
+    /// What hints/latest.txt shows the player
+    pub(crate) fn hint_text(&self, tokens: u32) -> String {
+        let tokens = tokens + self.bonus_hint_tokens();
+        self.latest_hint.clone().unwrap_or_else(|| {
+            format!(
+                "No hint taken yet.\n\n\
+                 Hint tokens left: {}\n\
+                 Write anything to request.txt to spend one.\n",
+                tokens.saturating_sub(self.hint_tokens_spent)
+            )
+        })
+    }
+}
+
+impl Philosophy {
+    /// Hint tokens every player has
+    pub(crate) fn hint_tokens(&self) -> u32 {
+        self.config.hint_tokens.unwrap_or(DEFAULT_HINT_TOKENS)
+    }
+
+    /// Lays out the hints directory
+    pub(crate) fn create_hints(&mut self) {
+        let dir = self.root.join(HINTS_DIR);
+        if std::fs::create_dir_all(&dir).is_err() {
+            warn!("Unable to create the hints directory {:?}", dir);
+            return;
+        }
+        let _ = std::fs::write(dir.join("README.txt"), README);
+        let _ = std::fs::write(dir.join("request.txt"), "");
+        self.update_latest_hint();
+    }
+
+    /// Gives the player making the call their next hint
+    pub(crate) fn process_hint_request(&mut self) {
+        let tokens = self.hint_tokens();
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        let hint = player.request_hint(&self.world, tokens);
+        player.latest_hint = Some(hint);
+        self.update_latest_hint();
+    }
+
+    /// Writes hints/latest.txt of the player making the call. When every
+    /// player has a journey of their own it is only written to tell
+    /// clients it changed, the contents are the player's.
+    pub(crate) fn update_latest_hint(&mut self) {
+        let tokens = self.hint_tokens();
+        let id = self.player_identity.current();
+        let contents = self.players.entry(id).or_default().hint_text(tokens);
+        let _ = std::fs::write(self.root.join(HINTS_DIR).join("latest.txt"), contents);
+    }
+}
@@ -0,0 +1,230 @@
+//! Earlier versions of answers, served under `.history/`.
+//!
+//! Whenever an `answer.txt` is overwritten, written again from its start or
+//! truncated, what it held is first kept as `.history/answer-<time>.txt` of
+//! its directory, named after the time it was written. That way the
+//! players, and the chronicle, can follow how their thinking changed. Only
+//! the most recent versions of every answer are kept, and what they hold
+//! only once, see `objects.rs`, compressed with `--compress`, see
+//! `compression.rs`, and encrypted with a key, see `encryption.rs`.
+//!
+//! The versions are kept under `.eternal/history/`, by the directory of
+//! their answer, and served from there as read-only
+//! [virtual files](crate::virtual_files). Those a world kept in its
+//! directories before are moved there as it is set up.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use nfsserve::nfs::nfsstat3;
+use tracing::{debug, warn};
+
+use crate::compression::{self, uncompressed};
+use crate::encryption;
+use crate::engine::{read_at, VirtualAttrs, VirtualFile};
+use crate::objects;
+use crate::philosophy::Philosophy;
+use crate::STATE_DIR;
+
+/// The directory earlier versions are served in, within that of the answer
+pub(crate) const HISTORY_DIR: &str = ".history";
+
+/// Where earlier versions are kept, within the state directory
+const HISTORY_STORE: &str = "history";
+
+/// How the time a version was written is written in its name
+const TIME_FORMAT: &str = "%Y-%m-%dT%H-%M-%S%.3f";
+
+/// Versions of an answer kept, unless configured otherwise
+pub const DEFAULT_ANSWER_HISTORY: usize = 10;
+
+/// An earlier version of an answer
+struct HistoryFile {
+    /// None if it can not be read, encrypted with another key than the
+    /// one given
+    contents: Option<Vec<u8>>,
+    mtime: SystemTime,
+}
+
+impl VirtualFile for HistoryFile {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let contents = self.contents.as_ref().ok_or(nfsstat3::NFS3ERR_ACCES)?;
+        Ok(read_at(contents, offset, count))
+    }
+
+    fn size(&self) -> u64 {
+        self.contents
+            .as_ref()
+            .map_or(0, |contents| contents.len() as u64)
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o444,
+            mtime: self.mtime,
+        }
+    }
+}
+
+/// The names of the versions kept in `dir`, the oldest first
+fn versions_in(dir: &Path) -> Vec<String> {
+    let mut versions: Vec<String> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with("answer-"))
+        .collect();
+    // the names sort by the time the versions were written
+    versions.sort();
+    versions
+}
+
+/// When the version named `name` was written, if its name tells
+fn written_at(name: &str) -> Option<SystemTime> {
+    let time = name.strip_prefix("answer-")?.strip_suffix(".txt")?;
+    let time = NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok()?;
+    Some(Local.from_local_datetime(&time).earliest()?.into())
+}
+
+impl Philosophy {
+    /// How many earlier versions of an answer are kept
+    fn answer_history(&self) -> usize {
+        self.config.answer_history.unwrap_or(DEFAULT_ANSWER_HISTORY)
+    }
+
+    /// The directory the versions of the answers in the directory `dir`
+    /// are kept in
+    fn history_store(&self, dir: &Path) -> PathBuf {
+        self.root.join(STATE_DIR).join(HISTORY_STORE).join(dir)
+    }
+
+    /// Keeps what the answer at `path` holds before `data` is written over
+    /// it, or before it is truncated if there is no data
+    pub(crate) fn keep_history(&self, path: &Path, data: Option<&[u8]>) {
+        if path.file_name() != Some(self.answer_file().as_ref()) {
+            return;
+        }
+        let host = self.root.join(path);
+        let Ok(previous) = std::fs::read(&host) else {
+            return;
+        };
+        // nothing to keep, or nothing which changes
+        if previous.trim_ascii().is_empty() || data.is_some_and(|data| data == previous) {
+            return;
+        }
+        let written = std::fs::metadata(&host)
+            .and_then(|meta| meta.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        let dir = self.history_store(path.parent().unwrap_or(Path::new("")));
+        let name = format!(
+            "answer-{}.txt",
+            DateTime::<Local>::from(written).format(TIME_FORMAT)
+        );
+        let kept = match versions_in(&dir)
+            .iter()
+            .any(|kept| uncompressed(kept) == name)
+        {
+            true => Ok(()),
+            false => {
+                let to = dir.join(&name);
+                let key = self.key.as_ref();
+                compression::keep(&self.root, &previous, &to, None, self.compress, key).map(|_| ())
+            }
+        };
+        if let Err(e) = kept {
+            warn!("Unable to keep the earlier answer {:?}: {:?}", host, e);
+            return;
+        }
+        debug!("Kept the earlier answer {:?} as {}", host, name);
+        self.prune_history(&dir);
+    }
+
+    /// Removes the oldest versions kept in `dir` beyond the limit
+    fn prune_history(&self, dir: &Path) {
+        let versions = versions_in(dir);
+        let excess = versions.len().saturating_sub(self.answer_history());
+        for version in versions.into_iter().take(excess) {
+            if let Err(e) = objects::release(&self.root, &dir.join(&version)) {
+                warn!("Unable to remove the earlier answer {:?}: {:?}", version, e);
+            }
+        }
+    }
+
+    /// How many earlier versions of the answer in the directory `dir` are
+    /// kept
+    pub(crate) fn drafts(&self, dir: &str) -> usize {
+        versions_in(&self.history_store(Path::new(dir))).len()
+    }
+
+    /// The paths the earlier versions of every answer are served at
+    pub(crate) fn history_paths(&self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            let store = self.history_store(&dir);
+            for entry in std::fs::read_dir(&store).into_iter().flatten().flatten() {
+                if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                    dirs.push(dir.join(entry.file_name()));
+                }
+            }
+            paths.extend(
+                versions_in(&store)
+                    .into_iter()
+                    .map(|name| dir.join(HISTORY_DIR).join(uncompressed(&name))),
+            );
+        }
+        paths
+    }
+
+    /// The earlier version of an answer at `path`, if it names one
+    pub(crate) fn open_history(&self, path: &Path) -> Option<Box<dyn VirtualFile + '_>> {
+        let name = path.file_name()?.to_str()?;
+        let dir = path.parent()?;
+        if dir.file_name()? != HISTORY_DIR || !name.starts_with("answer-") {
+            return None;
+        }
+        let kept = self.history_store(dir.parent()?).join(name);
+        let (kept, compressed) = compression::find(&kept)?;
+        let contents = std::fs::read(&kept).ok()?;
+        let contents = encryption::open(self.key.as_ref(), &contents)
+            .and_then(|contents| match compressed {
+                true => compression::decompress(&contents),
+                false => Ok(contents),
+            })
+            .inspect_err(|e| warn!("Unable to read the earlier answer {:?}: {}", kept, e))
+            .ok();
+        let mtime = written_at(name)
+            .or_else(|| kept.metadata().and_then(|meta| meta.modified()).ok())
+            .unwrap_or_else(SystemTime::now);
+        Some(Box::new(HistoryFile { contents, mtime }))
+    }
+
+    /// Moves the earlier versions a stage kept in its directory, as they
+    /// were kept before, to where they are kept now
+    pub(crate) fn move_history(&self) {
+        for stage in &self.world.stages {
+            let old = self.root.join(&stage.dir).join(HISTORY_DIR);
+            let versions = versions_in(&old);
+            if versions.is_empty() {
+                continue;
+            }
+            let dir = self.history_store(Path::new(&stage.dir));
+            for name in versions {
+                let moved = match dir.join(&name).exists() {
+                    true => Ok(()),
+                    false => {
+                        objects::keep_file(&self.root, &old.join(&name), &dir.join(&name), None)
+                    }
+                };
+                match moved.and_then(|_| std::fs::remove_file(old.join(&name))) {
+                    Ok(()) => debug!("Moved the earlier answer {:?} of {}", name, stage.dir),
+                    Err(e) => warn!("Unable to move the earlier answer {:?}: {:?}", name, e),
+                }
+            }
+            let _ = std::fs::remove_dir(&old);
+            self.prune_history(&dir);
+        }
+    }
+}
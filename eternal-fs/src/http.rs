@@ -0,0 +1,304 @@
+//! The HTTP inspection API.
+//!
+//! With `--http ADDR` (`http` under `[server]`), and eternal-fs built with
+//! the `http` feature, the server also answers HTTP on `ADDR` with what it
+//! knows of every world it serves, as JSON, for dashboards and debugging:
+//!
+//! - `GET /api/tree`: the objects known, nested from the root
+//! - `GET /api/entries/{fileid}`: one object, with its paths, attributes
+//!   and children
+//! - `GET /api/game/state`: the journey of every player, as it is saved
+//! - `GET /api/dashboard`: where every player stands on the map, with
+//!   their latest hint, the recent events of their timelines and how the
+//!   server is doing
+//! - `GET /api/events`: a WebSocket streaming the [events](crate::events)
+//!   of the world as they happen, one JSON object per message. A listener
+//!   too slow to keep up is told how many it missed with
+//!   `{"type": "lagged", "missed": N}`.
+//!
+//! `GET /` is a dashboard showing the latter as it changes, for a
+//! facilitator watching a group of players. Nothing can be changed
+//! through either. With several exports, `?world=NAME`
+//! picks the export inspected.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, Json, Response};
+use axum::routing::get;
+use axum::Router;
+use nfsserve::nfs::{fattr3, fileid3, ftype3};
+use nfsserve::vfs::NFSFileSystem;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::events::Event;
+use crate::{EternalFS, FSEntry, FSMap};
+
+/// The page of the dashboard, which asks `/api/dashboard` for what it shows
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// The worlds inspected, by export name
+#[derive(Debug, Clone, Default)]
+pub struct HttpApi {
+    worlds: BTreeMap<String, Arc<EternalFS>>,
+}
+
+/// The query of every call
+#[derive(Debug, Deserialize)]
+struct Selection {
+    /// The export inspected, which may be left out if there is one
+    world: Option<String>,
+    /// How many levels of the tree are shown, all if left out
+    depth: Option<usize>,
+}
+
+/// A reply of the API: JSON, or the status of a failed call with what
+/// went wrong
+type Reply = Result<Json<Value>, (StatusCode, Json<Value>)>;
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+fn type_name(ftype: ftype3) -> &'static str {
+    match ftype {
+        ftype3::NF3REG => "file",
+        ftype3::NF3DIR => "directory",
+        ftype3::NF3BLK => "block device",
+        ftype3::NF3CHR => "character device",
+        ftype3::NF3LNK => "symlink",
+        ftype3::NF3SOCK => "socket",
+        ftype3::NF3FIFO => "fifo",
+    }
+}
+
+fn attr_json(attr: &fattr3) -> Value {
+    json!({
+        "type": type_name(attr.ftype),
+        "mode": format!("{:04o}", attr.mode),
+        "nlink": attr.nlink,
+        "uid": attr.uid,
+        "gid": attr.gid,
+        "size": attr.size,
+        "used": attr.used,
+        "atime": attr.atime.seconds,
+        "mtime": attr.mtime.seconds,
+        "ctime": attr.ctime.seconds,
+    })
+}
+
+impl FSMap {
+    /// The path of an entry relative to the root, `/` for the root
+    fn display_path(&self, name: &[intaglio::Symbol]) -> String {
+        format!("/{}", self.game_path(name).display())
+    }
+
+    fn entry_json(&self, id: fileid3, entry: &FSEntry) -> Value {
+        json!({
+            "fileid": id,
+            "name": self.sym_to_fname(&entry.name).to_string_lossy(),
+            "path": self.display_path(&entry.name),
+            "links": entry
+                .links
+                .iter()
+                .map(|link| self.display_path(link))
+                .collect::<Vec<_>>(),
+            "ino": entry.ino,
+            "attr": attr_json(&entry.fsmeta),
+            "children": entry.children.as_ref(),
+        })
+    }
+
+    /// The entry `id` with the entries known below it, `depth` levels deep
+    fn tree_json(&self, id: fileid3, depth: usize) -> Value {
+        let Some(entry) = self.id_to_path.get(&id) else {
+            return Value::Null;
+        };
+        let mut node = json!({
+            "fileid": id,
+            "name": self.sym_to_fname(&entry.name).to_string_lossy(),
+            "type": type_name(entry.fsmeta.ftype),
+            "size": entry.fsmeta.size,
+        });
+        // directories not listed yet have no children known
+        if let (Some(children), true) = (&entry.children, depth > 0) {
+            node["children"] = children
+                .iter()
+                .map(|child| self.tree_json(*child, depth - 1))
+                .filter(|child| !child.is_null())
+                .collect();
+        }
+        node
+    }
+
+    /// How often the known objects were enough to answer a call
+    fn cache_json(&self) -> Value {
+        let stats = &self.cache_stats;
+        json!({
+            "entries": self.id_to_path.len(),
+            "paths": self.path_to_id.len(),
+            "tombstones": self.tombstones.len(),
+            "symbols": self.intern.len(),
+            "symbols_in_use": self.intern.held(),
+            "lookup_hits": stats.lookup_hits,
+            "lookup_misses": stats.lookup_misses,
+            "refresh_hits": stats.refresh_hits,
+            "refresh_misses": stats.refresh_misses,
+            "relists": stats.relists,
+            "read_ahead_hits": self.read_ahead.hits.load(Ordering::Relaxed),
+            "read_ahead_misses": self.read_ahead.misses.load(Ordering::Relaxed),
+        })
+    }
+
+    /// What is known of `id`, and what became of it if it was deleted
+    fn lookup_json(&self, id: fileid3) -> Option<Value> {
+        if let Some(entry) = self.id_to_path.get(&id) {
+            return Some(self.entry_json(id, &entry));
+        }
+        let tombstone = self.tombstones.get(&id)?;
+        Some(json!({
+            "fileid": id,
+            "path": self.display_path(&tombstone.name),
+            "type": type_name(tombstone.ftype),
+            "deleted": true,
+        }))
+    }
+}
+
+impl HttpApi {
+    pub fn new() -> HttpApi {
+        HttpApi::default()
+    }
+
+    /// Inspects `world` as the export `name`
+    pub fn add(&mut self, name: &str, world: Arc<EternalFS>) {
+        self.worlds.insert(name.to_string(), world);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.worlds.is_empty()
+    }
+
+    fn world(&self, selection: &Selection) -> Result<&EternalFS, (StatusCode, Json<Value>)> {
+        match &selection.world {
+            Some(name) => self
+                .worlds
+                .get(name)
+                .map(|world| &**world)
+                .ok_or_else(|| error(StatusCode::NOT_FOUND, "no such world")),
+            None if self.worlds.len() == 1 => {
+                Ok(self.worlds.values().next().expect("there is a world"))
+            }
+            None => Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "pick a world with ?world=NAME",
+                    "worlds": self.worlds.keys().collect::<Vec<_>>(),
+                })),
+            )),
+        }
+    }
+
+    /// The routes of the API
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/api/tree", get(tree))
+            .route("/api/entries/{fileid}", get(entry))
+            .route("/api/game/state", get(game_state))
+            .route("/api/dashboard", get(dashboard))
+            .route("/api/events", get(events))
+            .route("/", get(Html(DASHBOARD)))
+            .with_state(Arc::new(self))
+    }
+
+    /// Answers on `addr` until `shutdown` completes
+    pub async fn serve<F>(self, addr: &str, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!("Inspection API listening on {}", listener.local_addr()?);
+        axum::serve(listener, self.router())
+            .with_graceful_shutdown(shutdown)
+            .await
+    }
+}
+
+async fn tree(State(api): State<Arc<HttpApi>>, Query(selection): Query<Selection>) -> Reply {
+    let world = api.world(&selection)?;
+    let fsmap = world.fsmap.lock().await;
+    let depth = selection.depth.unwrap_or(usize::MAX);
+    Ok(Json(fsmap.tree_json(world.root_dir(), depth)))
+}
+
+async fn entry(
+    State(api): State<Arc<HttpApi>>,
+    UrlPath(fileid): UrlPath<fileid3>,
+    Query(selection): Query<Selection>,
+) -> Reply {
+    let world = api.world(&selection)?;
+    let fsmap = world.fsmap.lock().await;
+    fsmap
+        .lookup_json(fileid)
+        .map(Json)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "no such entry"))
+}
+
+async fn game_state(State(api): State<Arc<HttpApi>>, Query(selection): Query<Selection>) -> Reply {
+    let world = api.world(&selection)?;
+    let fsmap = world.fsmap.lock().await;
+    Ok(Json(fsmap.game.state()))
+}
+
+async fn dashboard(State(api): State<Arc<HttpApi>>, Query(selection): Query<Selection>) -> Reply {
+    let world = api.world(&selection)?;
+    let fsmap = world.fsmap.lock().await;
+    Ok(Json(json!({
+        "game": fsmap.game.dashboard(),
+        "cache": fsmap.cache_json(),
+        "clients": fsmap.clients.len(),
+        "uptime": fsmap.started.elapsed().unwrap_or_default().as_secs(),
+    })))
+}
+
+async fn events(
+    State(api): State<Arc<HttpApi>>,
+    Query(selection): Query<Selection>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let events = api.world(&selection)?.events().subscribe();
+    Ok(upgrade.on_upgrade(|socket| stream_events(socket, events)))
+}
+
+/// Sends every event to `socket` until it is closed
+async fn stream_events(mut socket: WebSocket, mut events: broadcast::Receiver<Event>) {
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => json!(event),
+                Err(RecvError::Lagged(missed)) => json!({ "type": "lagged", "missed": missed }),
+                Err(RecvError::Closed) => return,
+            },
+            // listeners have nothing to say but goodbye
+            received = socket.recv() => match received {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket
+            .send(Message::Text(message.to_string().into()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
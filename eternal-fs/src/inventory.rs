@@ -0,0 +1,132 @@
+//! Artifacts, carried in the inventory.
+//!
+//! A stage of a pack can grant an artifact, see
+//! [`StageDef::grants`](crate::packs::StageDef::grants). Once the player
+//! answers it, the artifact is theirs and shows in the read-only
+//! `inventory/` directory at the root of the world. A later stage can ask
+//! for it to be used, see [`StageDef::uses`](crate::packs::StageDef::uses):
+//! it is used by copying it from the inventory into the stage directory,
+//! and the stage can not be answered before.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use tracing::info;
+
+use crate::engine::GeneratedFile;
+use crate::packs::{ArtifactDef, Stage, World};
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The generated directory the artifacts of the player are shown in
+pub(crate) const INVENTORY_DIR: &str = "inventory";
+
+/// What the file of `artifact`, granted by `stage`, holds
+fn artifact_contents(stage: &Stage, artifact: &ArtifactDef) -> String {
+    format!(
+        "{}\n\
+         {}\n\
+         {}\n\
+         \n\
+         Granted for answering {}.\n\
+         Copy it into the directory of a stage to use it there.\n",
+        artifact.name,
+        "=".repeat(artifact.name.chars().count()),
+        artifact.description,
+        stage.dir
+    )
+}
+
+impl PlayerState {
+    /// The artifacts the player carries, with the stages which granted
+    /// them: those of every stage they answered
+    fn artifacts<'w>(&self, world: &'w World) -> Vec<(&'w Stage, &'w ArtifactDef)> {
+        world
+            .stages
+            .iter()
+            .filter(|stage| self.completed_questions.contains(&stage.dir))
+            .filter_map(|stage| Some((stage, stage.def.grants.as_ref()?)))
+            .collect()
+    }
+
+    /// The artifact the player still has to use in the stage `dir` before
+    /// answering it, if any
+    pub(crate) fn missing_artifact<'w>(&self, world: &'w World, dir: &str) -> Option<&'w str> {
+        let artifact = world.stage(dir)?.def.uses.as_deref()?;
+        let used = self
+            .used_artifacts
+            .get(dir)
+            .is_some_and(|used| used.contains(artifact));
+        (!used).then_some(artifact)
+    }
+
+    /// The files of `inventory/`, one for every artifact carried
+    fn inventory_files(&self, world: &World) -> Vec<GeneratedFile> {
+        self.artifacts(world)
+            .into_iter()
+            .map(|(stage, artifact)| GeneratedFile {
+                name: artifact.file_name(),
+                contents: artifact_contents(stage, artifact).into_bytes(),
+                // granted with the first answer accepted
+                mtime: self
+                    .answer_archive
+                    .get(&stage.dir)
+                    .and_then(|answers| answers.first())
+                    .map_or(self.started, |answer| answer.archived_at),
+            })
+            .collect()
+    }
+}
+
+impl Philosophy {
+    /// Takes up the write of `data` at `offset` to `path` as the use of an
+    /// artifact, if it copies one the player making the call carries into
+    /// the directory of a stage
+    pub(crate) fn use_artifact(&mut self, path: &Path, offset: u64, data: &[u8]) {
+        let (Some(dir), Some(name)) = (
+            path.parent().and_then(Path::to_str),
+            path.file_name().and_then(|name| name.to_str()),
+        ) else {
+            return;
+        };
+        if offset != 0 || self.world.stage(dir).is_none() {
+            return;
+        }
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        let copied = String::from_utf8_lossy(data);
+        let Some((_, artifact)) =
+            player
+                .artifacts(&self.world)
+                .into_iter()
+                .find(|(stage, artifact)| {
+                    artifact.file_name() == name
+                        && copied.trim() == artifact_contents(stage, artifact).trim()
+                })
+        else {
+            return;
+        };
+        let artifact = artifact.name.clone();
+        if !player
+            .used_artifacts
+            .entry(dir.to_string())
+            .or_default()
+            .insert(artifact.clone())
+        {
+            return;
+        }
+        info!("The {} was used in {}", artifact, dir);
+        player.philosophical_state.note_event(
+            SystemTime::now(),
+            format!("Used the {} in {}", artifact, dir),
+        );
+    }
+
+    /// The artifacts of the player making the call, as files
+    pub(crate) fn inventory(&self) -> Vec<GeneratedFile> {
+        self.players
+            .get(&self.player_identity.current())
+            .map(|player| player.inventory_files(&self.world))
+            .unwrap_or_default()
+    }
+}
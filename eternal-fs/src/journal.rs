@@ -0,0 +1,142 @@
+//! The journal, a space for free writing beside the journey.
+//!
+//! Whatever a player writes to a file in `journal/` is kept as it is, a
+//! copy is archived under `.eternal/journal/` with the time it was written,
+//! and the world answers it with a short reflection at the end of the
+//! file. Nothing written there counts towards a stage. The archive is
+//! compressed with `--compress`, see `compression.rs`, and encrypted with a
+//! key, see `encryption.rs`.
+
+use std::io::SeekFrom;
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::{debug, warn};
+
+use crate::compression;
+use crate::encryption;
+use crate::philosophy::Philosophy;
+use crate::STATE_DIR;
+
+/// The journal at the root of the world
+pub(crate) const JOURNAL_DIR: &str = "journal";
+
+/// Where the entries are archived in the state directory
+const ARCHIVE_DIR: &str = "journal";
+
+/// Marks where an entry ends and the reflection on it begins
+const REFLECTION_MARK: &str = "\n\n~ ";
+
+const README: &str = "\
+    The Journal\n\
+    ===========\n\
+    Write whatever you like in any file of this directory.\n\
+    Nothing here is judged and nothing here moves you along the path.\n\
+    The world keeps every entry and leaves a thought at the end of it.\n";
+
+const REFLECTIONS: &[&str] = &[
+    "You keep returning to {}. Perhaps it is returning to you.",
+    "Of all your words, {} lingers longest.",
+    "There is more to {} than you have written yet.",
+    "What would change if {} were not so?",
+    "The page holds {} gently until you are ready to look again.",
+];
+
+/// Words too common to reflect on
+const COMMON_WORDS: &[&str] = &[
+    "about", "after", "again", "because", "before", "being", "could", "every", "their", "there",
+    "these", "thing", "things", "think", "those", "through", "where", "which", "while", "would",
+];
+
+/// A short reflection on `entry`, about the word it dwells on most
+fn reflect(entry: &str, rng: &mut impl rand::Rng) -> String {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for word in entry.split(|c: char| !c.is_alphabetic()) {
+        let word = word.to_lowercase();
+        if word.chars().count() < 5 || COMMON_WORDS.contains(&word.as_str()) {
+            continue;
+        }
+        match counts.iter_mut().find(|(w, _)| *w == word) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((word, 1)),
+        }
+    }
+    // the first of the most frequent words
+    let theme = counts
+        .iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map_or("silence".to_string(), |(word, _)| format!("\"{}\"", word));
+    let template = REFLECTIONS.choose(rng).unwrap_or(&REFLECTIONS[0]);
+    template.replace("{}", &theme)
+}
+
+impl Philosophy {
+    /// Lays out the journal directory
+    pub(crate) fn create_journal(&mut self) {
+        let dir = self.root.join(JOURNAL_DIR);
+        if std::fs::create_dir_all(&dir).is_err() {
+            warn!("Unable to create the journal {:?}", dir);
+            return;
+        }
+        let _ = std::fs::write(dir.join("README.txt"), README);
+    }
+
+    /// Whether `path` is an entry of the journal
+    pub(crate) fn is_journal_entry(path: &Path) -> bool {
+        path.parent() == Some(Path::new(JOURNAL_DIR))
+            && path.file_name().is_some_and(|name| name != "README.txt")
+    }
+
+    /// Writes `data` to the journal entry at `path`, archives the entry and
+    /// replaces the reflection at its end with a new one. The entry ends
+    /// with the data written, so the next write of a longer entry covers
+    /// the reflection again.
+    pub(crate) async fn write_journal(
+        &mut self,
+        path: &Path,
+        offset: u64,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let host_path = self.root.join(path);
+        let mut file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&host_path)
+            .await?;
+        let end = offset + data.len() as u64;
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.write_all(data).await?;
+        file.set_len(end).await?;
+
+        let mut entry = Vec::new();
+        file.seek(SeekFrom::Start(0)).await?;
+        file.read_to_end(&mut entry).await?;
+        let entry = String::from_utf8_lossy(&entry).into_owned();
+
+        let now = chrono::Local::now();
+        let reflection = format!(
+            "{}{} {}\n",
+            REFLECTION_MARK,
+            now.format("%Y-%m-%d %H:%M"),
+            reflect(&entry, &mut self.rng)
+        );
+        file.write_all(reflection.as_bytes()).await?;
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let player = self.player_identity.current().replace([':', '/'], "_");
+        let archive = self.root.join(STATE_DIR).join(ARCHIVE_DIR).join(player);
+        tokio::fs::create_dir_all(&archive).await?;
+        let mut archived = archive.join(format!("{}-{}", now.format("%Y%m%dT%H%M%S%.3f"), name));
+        let mut kept = entry.into_bytes();
+        if self.compress {
+            archived = compression::compressed(&archived);
+            kept = compression::compress(&kept)?;
+        }
+        let kept = encryption::seal(self.key.as_ref(), &kept);
+        tokio::fs::write(&archived, kept).await?;
+        debug!("Archived the journal entry {:?} as {:?}", path, archived);
+        Ok(())
+    }
+}
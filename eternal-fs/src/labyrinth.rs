@@ -0,0 +1,218 @@
+//! The labyrinth of the chaos path.
+//!
+//! Once a player reaches the chaos stage, `chaos/labyrinth` opens: a maze
+//! of passages, each a directory leading into further passages. It is laid
+//! out from a seed drawn when it first opens, and only dug out on the host
+//! as players walk into it, a passage at a time. One way through ends at
+//! the heart, which holds the true question of the stage. Every other
+//! dead end holds a question meant to lead astray.
+
+use std::path::{Path, PathBuf};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use tracing::{debug, warn};
+
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The stage the labyrinth is in
+const CHAOS_DIR: &str = "chaos";
+
+/// The entrance of the labyrinth, within the chaos stage
+const LABYRINTH_DIR: &str = "chaos/labyrinth";
+
+/// The key of the seed of the labyrinth in the game state
+const SEED_KEY: &str = "labyrinth_seed";
+
+/// How many passages deep the dead ends are
+const DEPTH: usize = 4;
+
+/// How many passages lead on from every junction
+const BRANCHES: usize = 3;
+
+/// The passages a junction can lead into, by name
+const PASSAGES: &[&str] = &[
+    "north",
+    "south",
+    "east",
+    "west",
+    "up",
+    "down",
+    "the_narrow_stair",
+    "the_dark_arch",
+    "the_spiral",
+    "the_flooded_hall",
+    "the_whispering_door",
+    "through_the_mirror",
+];
+
+/// What the dead ends which are not the heart hold
+const DECOYS: &[&str] = &[
+    "Is order merely chaos that has not yet been noticed? Answer in this very \
+     directory, the labyrinth will carry your words.",
+    "The true question was here a moment ago. It went back to the entrance. \
+     Follow it.",
+    "What is the colour of the wind? Only a colour will do.",
+    "Write nothing at all. Silence is the only answer the labyrinth accepts.",
+    "This is the heart of the labyrinth. Or it would be, had you turned left.",
+    "Why does the maze have walls? Answer with the number of passages you \
+     walked, and nothing else.",
+];
+
+/// The file every dead end holds
+const QUESTION_FILE: &str = "question.txt";
+
+/// A stable hash of `text`, to derive the layout of a passage from the
+/// seed, the same on every start
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The generator of what is found at the passage `node`, the passages
+/// leading to it from the entrance
+fn node_rng(seed: u64, node: &[String]) -> StdRng {
+    StdRng::seed_from_u64(seed ^ fnv1a(&node.join("/")))
+}
+
+/// The passages leading on from `node`, none at a dead end
+fn passages(seed: u64, node: &[String]) -> Vec<String> {
+    if node.len() >= DEPTH {
+        return Vec::new();
+    }
+    PASSAGES
+        .choose_multiple(&mut node_rng(seed, node), BRANCHES)
+        .map(|passage| passage.to_string())
+        .collect()
+}
+
+/// The one way from the entrance to the heart
+fn true_path(seed: u64) -> Vec<String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut path = Vec::new();
+    while path.len() < DEPTH {
+        let ahead = passages(seed, &path);
+        path.push(ahead[rng.gen_range(0..ahead.len())].clone());
+    }
+    path
+}
+
+impl Philosophy {
+    /// Whether the player making the call has reached the stage `dir`
+    pub(crate) fn has_reached(&self, dir: &str) -> bool {
+        let newcomer = PlayerState::default();
+        let player = self
+            .players
+            .get(&self.player_identity.current())
+            .unwrap_or(&newcomer);
+        self.world.stage(dir).is_some()
+            && (player.completed_questions.contains(dir)
+                || player
+                    .open_stages(&self.world)
+                    .iter()
+                    .any(|stage| stage.dir == dir))
+    }
+
+    /// Whether `path` is in the labyrinth and the player making the call
+    /// has not reached it yet
+    pub(crate) fn labyrinth_hidden(&self, path: &Path) -> bool {
+        path.starts_with(LABYRINTH_DIR) && !self.has_reached(CHAOS_DIR)
+    }
+
+    /// The seed the labyrinth is laid out from, drawn as it first opens
+    fn labyrinth_seed(&mut self) -> u64 {
+        if let Some(seed) = self
+            .game_state
+            .get(SEED_KEY)
+            .and_then(|seed| seed.parse().ok())
+        {
+            return seed;
+        }
+        let seed: u64 = self.rng.gen();
+        self.game_state
+            .insert(SEED_KEY.to_string(), seed.to_string());
+        seed
+    }
+
+    /// Digs out what the player finds at `path` on the host, as they walk
+    /// into it: the entrance in the chaos stage, or the passages and
+    /// question of a passage of the labyrinth
+    pub(crate) fn explore_labyrinth(&mut self, path: &Path) {
+        let node: Vec<String> = if path == Path::new(CHAOS_DIR) {
+            Vec::new()
+        } else if let Ok(within) = path.strip_prefix(LABYRINTH_DIR) {
+            within
+                .iter()
+                .map(|passage| passage.to_string_lossy().into_owned())
+                .collect()
+        } else {
+            return;
+        };
+        if !self.has_reached(CHAOS_DIR) {
+            return;
+        }
+        let seed = self.labyrinth_seed();
+        // only passages of the labyrinth are dug out, not any name asked for
+        for depth in 0..node.len() {
+            if !passages(seed, &node[..depth]).contains(&node[depth]) {
+                return;
+            }
+        }
+        if let Err(e) = self.dig(seed, &node) {
+            warn!("Unable to dig out the labyrinth at {:?}: {:?}", path, e);
+        }
+        if node == true_path(seed) {
+            let id = self.player_identity.current();
+            let player = self.players.entry(id).or_default();
+            if player
+                .philosophical_state
+                .solved_puzzles
+                .insert("labyrinth".to_string())
+            {
+                debug!("The heart of the labyrinth was found");
+            }
+        }
+    }
+
+    /// Lays out the passage `node` on the host, unless it already is
+    fn dig(&self, seed: u64, node: &[String]) -> std::io::Result<()> {
+        let dir: PathBuf = self.root.join(LABYRINTH_DIR).join(node.join("/"));
+        std::fs::create_dir_all(&dir)?;
+        for passage in passages(seed, node) {
+            let ahead = dir.join(passage);
+            if !ahead.exists() {
+                std::fs::create_dir(ahead)?;
+            }
+        }
+        let question = dir.join(QUESTION_FILE);
+        if node.len() < DEPTH || question.exists() {
+            return Ok(());
+        }
+        let contents = if node == true_path(seed) {
+            let asked = self
+                .world
+                .stage(CHAOS_DIR)
+                .map(|stage| stage.def.question.clone())
+                .unwrap_or_default();
+            format!(
+                "The Heart of the Labyrinth\n\
+                 ==========================\n\
+                 {}\n\
+                 \n\
+                 Answer it in {}/{}.\n",
+                asked,
+                CHAOS_DIR,
+                self.answer_file()
+            )
+        } else {
+            let decoy = DECOYS
+                .choose(&mut node_rng(seed, node))
+                .unwrap_or(&DECOYS[0]);
+            format!("A Question\n==========\n{}\n", decoy)
+        };
+        std::fs::write(question, contents)
+    }
+}
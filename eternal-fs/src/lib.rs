@@ -0,0 +1,2590 @@
+//! Eternal Filesystem: a philosophical journey served over NFS.
+//!
+//! [`EternalFS`] implements [`NFSFileSystem`] on top of a host directory
+//! and turns it into the world of a [`GameEngine`], by default the
+//! philosophical journey of [`Philosophy`]. Integrations will usually want
+//! to start from [`prelude`].
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::fs::Metadata;
+use std::io::SeekFrom;
+use std::net::IpAddr;
+use std::ops::Bound;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::stream::{self, StreamExt};
+use intaglio::Symbol;
+use nix::sys::stat::{mknod, Mode, SFlag};
+use nix::sys::statvfs::statvfs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::{debug, warn, Span};
+
+use nfsserve::auth::{caller, Credentials, MAY_EXEC, MAY_READ, MAY_WRITE};
+use nfsserve::fs_util::*;
+use nfsserve::nfs::*;
+use nfsserve::vfs::{
+    DirEntry, DirEntrySimple, NFSFileSystem, ReadDirResult, ReadDirSimpleResult, VFSCapabilities,
+};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+mod achievements;
+#[cfg(feature = "archive")]
+pub mod archive;
+mod atime;
+pub mod audit;
+mod chronicle;
+mod clones;
+mod clues;
+mod compression;
+pub mod config;
+mod control;
+mod coop;
+mod daily;
+mod dialogue;
+mod dreams;
+mod eggs;
+pub mod encryption;
+pub mod engine;
+mod entanglement;
+pub mod ephemeral;
+pub mod evaluator;
+pub mod events;
+pub mod exports;
+mod fileids;
+mod generated;
+mod handle_pool;
+mod hints;
+mod history;
+#[cfg(feature = "http")]
+pub mod http;
+mod inventory;
+mod journal;
+mod labyrinth;
+mod map;
+mod meditation;
+pub mod metadata;
+mod mirrors;
+mod objects;
+mod observer;
+mod oracle;
+pub mod overlay;
+pub mod packs;
+mod perception;
+pub mod philosophy;
+pub mod physics;
+mod players;
+pub mod prelude;
+mod quests;
+mod race;
+mod read_ahead;
+mod rebirth;
+mod riddles;
+#[cfg(feature = "s3")]
+pub mod s3;
+mod savegame;
+pub mod scheduler;
+pub mod scoring;
+mod seals;
+mod snapshots;
+mod sparse;
+pub mod spectator;
+mod speedrun;
+mod status;
+pub mod store;
+mod symbols;
+mod timeline;
+mod timelock;
+mod toolbox;
+mod trash;
+mod versioning;
+mod versions;
+mod virtual_files;
+mod xattrs;
+pub use atime::AtimePolicy;
+use engine::{GameEngine, ProgressReport, WriteAction};
+use events::{EventKind, Events};
+pub use fileids::FileidStrategy;
+use generated::{to_nfstime, GeneratedNodes};
+use handle_pool::HandlePool;
+use metadata::{CachedMap, MetadataStore};
+use packs::World;
+pub use philosophy::{GameStage, Philosophy};
+use physics::{ChaoticListing, DelayedWrites, StagePhysics, PHANTOMS, PHANTOM_CHANCE};
+pub use players::PlayerIdentity;
+use read_ahead::ReadAhead;
+use scheduler::Scheduler;
+use snapshots::Snapshots;
+use spectator::spectating;
+use status::{CacheStats, ClientSeen};
+use symbols::Symbols;
+use virtual_files::VirtualNodes;
+use xattrs::{XattrNodes, XATTR_DIR};
+
+/// How long an unused host file handle stays open
+const HANDLE_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the content packs are checked for changes
+const CONTENT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How many entries of a directory being listed are looked at at once
+const RELIST_CONCURRENCY: usize = 32;
+
+/// How many deleted fileids are remembered for stale handle recovery
+const MAX_TOMBSTONES: usize = 4096;
+
+/// Fileids use the low bits only, which leaves the top bits to tell
+/// exports apart
+const FILEID_BITS: u32 = 56;
+
+/// Directory under the root holding the server's own state
+pub const STATE_DIR: &str = ".eternal";
+
+/// Whether `path`, relative to the root, is the state directory or within
+/// it. It holds the saved game of every player and the packs the world is
+/// composed from, so clients neither see nor change it: only the server
+/// itself reads and writes there.
+fn is_state_path(path: &Path) -> bool {
+    path.starts_with(STATE_DIR)
+}
+
+#[derive(Debug, Clone)]
+struct FSEntry {
+    name: Vec<Symbol>,
+    /// host inode the fileid was issued for. If the path is found to refer
+    /// to a different inode the object was replaced and the fileid expires.
+    ino: u64,
+    /// Further paths naming the same file if it has hard links
+    links: Vec<Vec<Symbol>>,
+    fsmeta: fattr3,
+    children_meta: fattr3,
+    children: Option<BTreeSet<fileid3>>,
+}
+
+#[derive(Debug)]
+struct FSMap {
+    root: PathBuf,
+    next_fileid: AtomicU64,
+    /// How the objects of the host are given fileids, see `fileids.rs`
+    fileids: FileidStrategy,
+    /// The names paths are made of, see `symbols.rs`
+    intern: Symbols,
+    id_to_path: CachedMap<fileid3, FSEntry>,
+    path_to_id: CachedMap<Vec<Symbol>, fileid3>,
+    /// fileid of every known non-directory by host inode, so that hard
+    /// links to a file share its fileid
+    ino_to_id: HashMap<u64, fileid3>,
+    /// The game played in the world
+    game: Box<dyn GameEngine>,
+    physics: StagePhysics,
+    /// How long the attributes of an entry read from the host are served
+    /// as they are by GETATTR, read afresh every time if zero
+    attr_ttl: Duration,
+    /// When the attributes of entries were last read from the host, while
+    /// attributes are cached
+    attrs_read: HashMap<fileid3, Instant>,
+    /// When reading a file changes its access time, see `atime.rs`
+    atime: AtimePolicy,
+    /// Last known location of recently deleted fileids
+    tombstones: HashMap<fileid3, Tombstone>,
+    tombstone_order: VecDeque<fileid3>,
+    /// Re-resolve handles of deleted objects by path if an object of the
+    /// same type has since been created there
+    stale_grace: bool,
+    /// Fileids of the objects in the `.xattr` shadow namespace
+    xattrs: XattrNodes,
+    /// Fileids of the objects in the directories the game generates
+    generated: GeneratedNodes,
+    /// Fileids of the files the game serves from memory
+    virtual_files: VirtualNodes,
+    /// The world as it was, see `snapshots.rs`
+    snapshots: Snapshots,
+    /// How long removed files are kept in the trash, if they are, see
+    /// `trash.rs`
+    trash_retention: Option<Duration>,
+    /// Versions kept of every file, none if 0, see `versions.rs`
+    versions_kept: usize,
+    /// Whether what is kept of the game is compressed, see
+    /// `compression.rs`
+    compress: bool,
+    rng: Arc<Mutex<StdRng>>,
+    /// When the file system was opened
+    started: SystemTime,
+    cache_stats: CacheStats,
+    /// What is read ahead of clients, shown with the cache statistics
+    read_ahead: Arc<ReadAhead>,
+    /// The clients which called, by address
+    clients: HashMap<IpAddr, ClientSeen>,
+    /// Where files created, removed and renamed are published, and the
+    /// game publishes its events
+    events: Events,
+}
+
+/// What is remembered about a fileid after it has been deleted
+#[derive(Debug, Clone)]
+struct Tombstone {
+    name: Vec<Symbol>,
+    ftype: ftype3,
+}
+
+enum RefreshResult {
+    /// The fileid was deleted
+    Delete,
+    /// The fileid needs to be reloaded. mtime has been updated, caches
+    /// need to be evicted.
+    Reload,
+    /// Nothing has changed
+    Noop,
+}
+
+impl FSMap {
+    fn new(root: PathBuf, mut game: Box<dyn GameEngine>) -> FSMap {
+        let events = Events::default();
+        game.set_events(events.clone());
+        let mut map = FSMap {
+            root,
+            next_fileid: AtomicU64::new(1),
+            fileids: FileidStrategy::default(),
+            intern: Symbols::default(),
+            id_to_path: CachedMap::new(b'e'),
+            path_to_id: CachedMap::new(b'p'),
+            ino_to_id: HashMap::new(),
+            game,
+            physics: StagePhysics::default(),
+            attr_ttl: Duration::ZERO,
+            attrs_read: HashMap::new(),
+            atime: AtimePolicy::default(),
+            tombstones: HashMap::new(),
+            tombstone_order: VecDeque::new(),
+            stale_grace: false,
+            xattrs: XattrNodes::default(),
+            generated: GeneratedNodes::default(),
+            virtual_files: VirtualNodes::default(),
+            snapshots: Snapshots::default(),
+            trash_retention: None,
+            versions_kept: 0,
+            compress: false,
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
+            started: SystemTime::now(),
+            cache_stats: CacheStats::default(),
+            read_ahead: Arc::default(),
+            clients: HashMap::new(),
+            events,
+        };
+
+        map.initialize_game_world();
+        objects::sweep(&map.root);
+        map
+    }
+
+    fn initialize_game_world(&mut self) {
+        self.game.setup(&self.root);
+        self.physics.declare(self.game.stage_physics());
+        // everything below the root is listed from the host as it is
+        // first used, including what the game has laid out
+        let root_entry = FSEntry {
+            name: Vec::new(),
+            ino: self.root.metadata().unwrap().ino(),
+            links: Vec::new(),
+            fsmeta: metadata_to_fattr3(1, &self.root.metadata().unwrap()),
+            children_meta: metadata_to_fattr3(1, &self.root.metadata().unwrap()),
+            children: None,
+        };
+        self.insert_entry(0, root_entry);
+    }
+
+    fn collect_all_children(&self, id: fileid3, ret: &mut Vec<fileid3>) {
+        ret.push(id);
+        if let Some(entry) = self.id_to_path.get(&id) {
+            if let Some(ref ch) = entry.children {
+                for i in ch.iter() {
+                    self.collect_all_children(*i, ret);
+                }
+            }
+        }
+    }
+
+    fn insert_entry(&mut self, id: fileid3, entry: FSEntry) {
+        if !matches!(entry.fsmeta.ftype, ftype3::NF3DIR) {
+            self.ino_to_id.insert(entry.ino, id);
+        }
+        self.insert_path(entry.name.clone(), id);
+        self.id_to_path.insert(id, entry);
+    }
+
+    /// Maps `path` to `id`, counting the names it holds
+    fn insert_path(&mut self, path: Vec<Symbol>, id: fileid3) {
+        if !self.path_to_id.contains_key(&path) {
+            self.intern.retain(&path);
+        }
+        self.path_to_id.insert(path, id);
+    }
+
+    /// Forgets where `path` leads, and so the names it held
+    fn remove_path(&mut self, path: &[Symbol]) {
+        if self.path_to_id.remove(path).is_some() {
+            self.intern.release(path);
+        }
+    }
+
+    /// Compacts the table of names once most of it is names no path holds
+    /// any more, renumbering every path. Only while no path is held
+    /// outside the maps, see `symbols.rs`.
+    fn compact_symbols(&mut self) {
+        if !self.intern.wasteful() || self.path_to_id.has_store() || self.id_to_path.has_store() {
+            return;
+        }
+        let before = self.intern.len();
+        let mut renumbering = self.intern.compact();
+        let intern = &mut self.intern;
+        self.path_to_id.rewrite(|mut path, id| {
+            intern.renumber(&mut renumbering, &mut path);
+            intern.retain(&path);
+            (path, id)
+        });
+        self.id_to_path.rewrite(|id, mut entry| {
+            intern.renumber(&mut renumbering, &mut entry.name);
+            for link in entry.links.iter_mut() {
+                intern.renumber(&mut renumbering, link);
+            }
+            (id, entry)
+        });
+        for tombstone in self.tombstones.values_mut() {
+            intern.renumber(&mut renumbering, &mut tombstone.name);
+            intern.retain(&tombstone.name);
+        }
+        debug!(
+            "Compacted the names from {} to {}",
+            before,
+            self.intern.len()
+        );
+    }
+
+    /// Expires a fileid together with everything below it. Files below it
+    /// which are hard linked from outside the tree keep their other names.
+    fn delete_entry(&mut self, id: fileid3) {
+        let Some(prefix) = self.id_to_path.get(&id).map(|e| e.name.clone()) else {
+            return;
+        };
+        let mut children = Vec::new();
+        self.collect_all_children(id, &mut children);
+        for i in children.iter() {
+            let Some(ent) = self.id_to_path.get_mut(i) else {
+                continue;
+            };
+            if *i != id {
+                let (gone, mut kept): (Vec<_>, Vec<_>) = std::iter::once(ent.name.clone())
+                    .chain(ent.links.drain(..))
+                    .partition(|p| p.starts_with(&prefix));
+                if !kept.is_empty() {
+                    ent.name = kept.remove(0);
+                    ent.links = kept;
+                    for p in gone {
+                        self.remove_path(&p);
+                    }
+                    continue;
+                }
+                ent.links = gone;
+            }
+            if let Some(ent) = self.id_to_path.remove(i) {
+                for p in std::iter::once(&ent.name).chain(ent.links.iter()) {
+                    self.remove_path(p);
+                }
+                if self.ino_to_id.get(&ent.ino) == Some(i) {
+                    self.ino_to_id.remove(&ent.ino);
+                }
+                self.attrs_read.remove(i);
+                self.bury(*i, ent);
+            }
+        }
+    }
+
+    /// Forgets one name of an entry. The entry is only deleted once its
+    /// last name is gone.
+    fn unlink_path(&mut self, path: &[Symbol]) {
+        let Some(id) = self.path_to_id.get(path).as_deref().copied() else {
+            return;
+        };
+        let Some(ent) = self.id_to_path.get_mut(&id) else {
+            return;
+        };
+        if ent.links.is_empty() {
+            self.delete_entry(id);
+            return;
+        }
+        if ent.name == path {
+            ent.name = ent.links.remove(0);
+        } else {
+            ent.links.retain(|l| l != path);
+        }
+        self.remove_path(path);
+    }
+
+    /// Returns true if the entry has a name directly inside `dir`
+    fn has_name_in(&self, id: fileid3, dir: &[Symbol]) -> bool {
+        self.id_to_path.get(&id).is_some_and(|ent| {
+            std::iter::once(&ent.name)
+                .chain(ent.links.iter())
+                .any(|p| p.len() == dir.len() + 1 && p.starts_with(dir))
+        })
+    }
+
+    /// Returns the host path and metadata of an entry. If the primary name
+    /// no longer refers to the entry's inode, it is dropped in favour of the
+    /// next hard link. Returns None once no name of the entry is left.
+    async fn live_path(&mut self, id: fileid3) -> Option<(PathBuf, Metadata)> {
+        loop {
+            let entry = self.id_to_path.get(&id)?;
+            let (name, ino) = (entry.name.clone(), entry.ino);
+            let path = self.sym_to_path(&name).await;
+            match tokio::fs::symlink_metadata(&path).await {
+                Ok(meta) if meta.ino() == ino => return Some((path, meta)),
+                _ if entry.links.is_empty() => return None,
+                _ => {
+                    debug!("Link {:?} of {:?} is gone", path, id);
+                    self.unlink_path(&name);
+                }
+            }
+        }
+    }
+
+    /// Remembers where a deleted fileid used to live, so that requests
+    /// on it can be answered with NFS3ERR_STALE, or re-resolved.
+    fn bury(&mut self, id: fileid3, entry: FSEntry) {
+        let tombstone = Tombstone {
+            name: entry.name,
+            ftype: entry.fsmeta.ftype,
+        };
+        self.intern.retain(&tombstone.name);
+        match self.tombstones.insert(id, tombstone) {
+            Some(buried) => self.intern.release(&buried.name),
+            None => self.tombstone_order.push_back(id),
+        }
+        while self.tombstone_order.len() > MAX_TOMBSTONES {
+            if let Some(oldest) = self.tombstone_order.pop_front() {
+                if let Some(buried) = self.tombstones.remove(&oldest) {
+                    self.intern.release(&buried.name);
+                }
+            }
+        }
+    }
+
+    /// Maps a fileid from a client handle to a live fileid.
+    ///
+    /// Fileids are never reused, so a fileid whose object has been deleted
+    /// or replaced on the host is stale. With `stale_grace` set, a stale
+    /// fileid resolves to whatever now lives at its old path, provided it
+    /// is of the same type.
+    async fn resolve_id(&mut self, id: fileid3) -> Result<fileid3, nfsstat3> {
+        // the entry is used by the call, so keep it at hand
+        self.id_to_path.touch(&id);
+        if self.id_to_path.contains_key(&id) {
+            if let RefreshResult::Delete = self.refresh_entry(id).await? {
+                debug!("fileid {:?} went away", id);
+            } else {
+                return self.refuse_state(id);
+            }
+        }
+        let tombstone = match self.tombstones.get(&id) {
+            Some(t) if self.stale_grace => t.clone(),
+            _ => return Err(nfsstat3::NFS3ERR_STALE),
+        };
+        let newid = self
+            .resolve_path(&tombstone.name)
+            .await
+            .ok_or(nfsstat3::NFS3ERR_STALE)?;
+        let ftype = self.find_entry(newid)?.fsmeta.ftype;
+        if ftype as u32 != tombstone.ftype as u32 {
+            return Err(nfsstat3::NFS3ERR_STALE);
+        }
+        debug!("Resolved stale fileid {:?} to {:?}", id, newid);
+        self.refuse_state(newid)
+    }
+
+    /// Refuses `id` to clients if it is the state directory or within it,
+    /// however they came by its handle
+    fn refuse_state(&self, id: fileid3) -> Result<fileid3, nfsstat3> {
+        let first = self.id_to_path.get(&id).and_then(|ent| {
+            let first = *ent.name.first()?;
+            self.intern.get(first)
+        });
+        match first {
+            Some(first) if first == STATE_DIR => Err(nfsstat3::NFS3ERR_ACCES),
+            _ => Ok(id),
+        }
+    }
+
+    /// Resolves a fileid the call is about to change, whose attributes
+    /// are then read afresh by the next GETATTR
+    async fn resolve_to_change(&mut self, id: fileid3) -> Result<fileid3, nfsstat3> {
+        let resolved = self.resolve_id(id).await?;
+        self.attrs_read.remove(&resolved);
+        Ok(resolved)
+    }
+
+    /// Whether the attributes of `id` were read from the host within the
+    /// attribute cache TTL
+    fn attrs_fresh(&self, id: fileid3) -> bool {
+        self.attrs_read
+            .get(&id)
+            .is_some_and(|read| read.elapsed() < self.attr_ttl)
+    }
+
+    /// Keeps `meta`, just read from the host, as the attributes of `id`
+    fn set_attrs(&mut self, id: fileid3, meta: &Metadata) {
+        if let Some(entry) = self.id_to_path.get_mut(&id) {
+            entry.fsmeta = metadata_to_fattr3(id, meta);
+        }
+        if !self.attr_ttl.is_zero() {
+            self.attrs_read.insert(id, Instant::now());
+        }
+    }
+
+    /// Names the host path of `id` on the span of the NFS call being served.
+    /// getattr does not, as handlers call it for the attributes of every
+    /// reply and the path would be recorded over and over.
+    async fn record_path(&self, id: fileid3) {
+        let span = Span::current();
+        if span.is_disabled() {
+            return;
+        }
+        if let Some(entry) = self.id_to_path.get(&id) {
+            let path = self.sym_to_path(&entry.name).await;
+            span.record("path", tracing::field::display(path.display()));
+        }
+    }
+
+    /// Finds the fileid of a path, discovering any components which are
+    /// on the host but not yet known to us.
+    async fn resolve_path(&mut self, name: &[Symbol]) -> Option<fileid3> {
+        let mut dirid = 0;
+        for depth in 1..=name.len() {
+            self.refresh_entry(dirid).await.ok()?;
+            self.refresh_dir_list(dirid).await.ok()?;
+            dirid = *self.path_to_id.get(&name[..depth])?;
+        }
+        match self.refresh_entry(dirid).await.ok()? {
+            RefreshResult::Delete => None,
+            _ => Some(dirid),
+        }
+    }
+
+    fn find_entry(&self, id: fileid3) -> Result<FSEntry, nfsstat3> {
+        Ok(self
+            .id_to_path
+            .get(&id)
+            .ok_or(nfsstat3::NFS3ERR_STALE)?
+            .into_owned())
+    }
+    fn find_entry_mut(&mut self, id: fileid3) -> Result<&mut FSEntry, nfsstat3> {
+        self.id_to_path.get_mut(&id).ok_or(nfsstat3::NFS3ERR_STALE)
+    }
+    async fn find_child(&self, id: fileid3, filename: &[u8]) -> Result<fileid3, nfsstat3> {
+        let mut name = self
+            .id_to_path
+            .get(&id)
+            .ok_or(nfsstat3::NFS3ERR_STALE)?
+            .name
+            .clone();
+        name.push(
+            self.intern
+                .check_interned(OsStr::from_bytes(filename))
+                .ok_or(nfsstat3::NFS3ERR_NOENT)?,
+        );
+        Ok(*self.path_to_id.get(&name).ok_or(nfsstat3::NFS3ERR_NOENT)?)
+    }
+    async fn refresh_entry(&mut self, id: fileid3) -> Result<RefreshResult, nfsstat3> {
+        let fsmeta = self
+            .id_to_path
+            .get(&id)
+            .ok_or(nfsstat3::NFS3ERR_NOENT)?
+            .fsmeta;
+        // A name which is missing or now refers to a different object
+        // no longer belongs to this id. Once no name is left, handles
+        // belong to the old object, so expire the id.
+        let Some((path, meta)) = self.live_path(id).await else {
+            self.cache_stats.refresh_misses += 1;
+            self.delete_entry(id);
+            debug!("Deleting entry A {:?}. Meta: {:?}", id, fsmeta);
+            return Ok(RefreshResult::Delete);
+        };
+        if !self.attr_ttl.is_zero() {
+            self.attrs_read.insert(id, Instant::now());
+        }
+        let meta = metadata_to_fattr3(id, &meta);
+        if !fattr3_differ(&meta, &fsmeta) {
+            self.cache_stats.refresh_hits += 1;
+            return Ok(RefreshResult::Noop);
+        }
+        self.cache_stats.refresh_misses += 1;
+        // If we get here we have modifications
+        if fsmeta.ftype as u32 != meta.ftype as u32 {
+            // if the file type changed ex: file->dir or dir->file
+            // really the entire file has been replaced.
+            // we expire the entire id
+            debug!(
+                "File Type Mismatch FT {:?} : {:?} vs {:?}",
+                id, fsmeta.ftype, meta.ftype
+            );
+            debug!(
+                "File Type Mismatch META {:?} : {:?} vs {:?}",
+                id, fsmeta, meta
+            );
+            self.delete_entry(id);
+            debug!("Deleting entry B {:?}: {:?}. Meta: {:?}", id, path, fsmeta);
+            return Ok(RefreshResult::Delete);
+        }
+        // inplace modification.
+        // update metadata
+        self.id_to_path.get_mut(&id).unwrap().fsmeta = meta;
+        debug!("Reloading entry {:?}: {:?}. Meta: {:?}", id, path, fsmeta);
+        Ok(RefreshResult::Reload)
+    }
+    async fn refresh_dir_list(&mut self, id: fileid3) -> Result<(), nfsstat3> {
+        let entry = self.id_to_path.get(&id).ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        // if there are children and the metadata did not change
+        if entry.children.is_some() && !fattr3_differ(&entry.children_meta, &entry.fsmeta) {
+            return Ok(());
+        }
+        if !matches!(entry.fsmeta.ftype, ftype3::NF3DIR) {
+            return Ok(());
+        }
+        let listed_meta = entry.fsmeta;
+        let previous = entry.children.clone().unwrap_or_default();
+        let mut cur_path = entry.name.clone();
+        let path = self.sym_to_path(&cur_path).await;
+        let mut new_children: Vec<u64> = Vec::new();
+        self.cache_stats.relists += 1;
+        debug!(
+            "Relisting entry {:?}: {:?}. Meta: {:?}",
+            id, path, listed_meta
+        );
+        if let Ok(mut listing) = tokio::fs::read_dir(&path).await {
+            let mut dirents = Vec::new();
+            while let Some(entry) = listing
+                .next_entry()
+                .await
+                .map_err(|_| nfsstat3::NFS3ERR_IO)?
+            {
+                dirents.push(entry);
+            }
+            // the metadata of the entries is read all at once, and kept
+            // in the order they were listed in
+            let mut listed: Vec<_> =
+                stream::iter(dirents.into_iter().enumerate())
+                    .map(|(index, entry)| async move {
+                        (index, entry.file_name(), entry.metadata().await)
+                    })
+                    .buffer_unordered(RELIST_CONCURRENCY)
+                    .collect()
+                    .await;
+            listed.sort_unstable_by_key(|(index, _, _)| *index);
+            for (_, name, meta) in listed {
+                // gone since it was listed
+                let Ok(meta) = meta else {
+                    continue;
+                };
+                let sym = self.intern.intern(name).unwrap();
+                cur_path.push(sym);
+                let next_id = self.create_entry(&cur_path, meta).await;
+                new_children.push(next_id);
+                cur_path.pop();
+            }
+            let entry = self
+                .id_to_path
+                .get_mut(&id)
+                .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+            let new_children = BTreeSet::from_iter(new_children);
+            let gone: Vec<fileid3> = previous.difference(&new_children).copied().collect();
+            entry.children = Some(new_children);
+            // the listing is current as of this directory metadata
+            entry.children_meta = listed_meta;
+            // what is no longer listed was removed or renamed away on the
+            // host, and its names here are forgotten
+            for child in gone {
+                let Some(ent) = self.id_to_path.get(&child) else {
+                    continue;
+                };
+                let names: Vec<Vec<Symbol>> = std::iter::once(&ent.name)
+                    .chain(ent.links.iter())
+                    .filter(|p| p.len() == cur_path.len() + 1 && p.starts_with(&cur_path))
+                    .cloned()
+                    .collect();
+                for name in names {
+                    self.unlink_path(&name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn create_entry(&mut self, fullpath: &Vec<Symbol>, meta: Metadata) -> fileid3 {
+        if let Some(chid) = self.path_to_id.get(fullpath).as_deref().copied() {
+            match self.id_to_path.get_mut(&chid) {
+                Some(chent) if chent.ino == meta.ino() => {
+                    chent.fsmeta = metadata_to_fattr3(chid, &meta);
+                    return chid;
+                }
+                _ => {
+                    // a new object took the place of the old one
+                    debug!("{:?} was replaced, expiring fileid", chid);
+                    self.unlink_path(fullpath);
+                }
+            }
+        }
+        if !meta.is_dir() {
+            if let Some(id) = self.ino_to_id.get(&meta.ino()).copied() {
+                // another name of a file we already know
+                let known = self.id_to_path.get(&id).map(|e| e.name.clone());
+                if let Some(known) = known {
+                    let known_path = self.sym_to_path(&known).await;
+                    if known_path.symlink_metadata().map(|m| m.ino()).ok() == Some(meta.ino()) {
+                        debug!("{:?} is a hard link of {:?}", fullpath, id);
+                        let ent = self.id_to_path.get_mut(&id).unwrap();
+                        ent.links.push(fullpath.clone());
+                        ent.fsmeta = metadata_to_fattr3(id, &meta);
+                        self.insert_path(fullpath.clone(), id);
+                        return id;
+                    }
+                }
+            }
+        }
+        // path does not exist
+        let next_id = self.allocate_fileid(fullpath, &meta).await;
+        let metafattr = metadata_to_fattr3(next_id, &meta);
+        let new_entry = FSEntry {
+            name: fullpath.clone(),
+            ino: meta.ino(),
+            links: Vec::new(),
+            fsmeta: metafattr,
+            children_meta: metafattr,
+            children: None,
+        };
+        debug!("creating new entry {:?}: {:?}", next_id, meta);
+        self.insert_entry(next_id, new_entry);
+        next_id
+    }
+
+    /// Picks the fileid for a new entry, `meta` on the host. Stages with
+    /// shuffled creation draw random ids so that listings do not reveal
+    /// creation order, unless ids are hashed, which reveal nothing either.
+    async fn allocate_fileid(&self, fullpath: &[Symbol], meta: &Metadata) -> fileid3 {
+        let path = self.game_path(fullpath);
+        if let Some(mut id) = self.fileids.hashed(&path, meta.dev(), meta.ino()) {
+            while self.id_to_path.contains_key(&id) {
+                id = fileids::next_hashed(id);
+            }
+            return id;
+        }
+        if self.physics.shuffles_creation(&self.stage_of(fullpath)) {
+            let mut rng = self.rng.lock().await;
+            loop {
+                let id = rng.gen_range(1 << 48..1 << FILEID_BITS);
+                if !self.id_to_path.contains_key(&id) {
+                    return id;
+                }
+            }
+        }
+        self.next_fileid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Whether the directory `dir` is a stage directory listed chaotically
+    fn lists_chaotically(&self, dir: &[Symbol]) -> bool {
+        dir.len() == 1 && self.physics.chaotic_listings(&self.stage_of(dir))
+    }
+
+    /// Draws the order the calling client lists the directory `dirid`
+    /// in, as it starts listing it, if its stage lists directories
+    /// chaotically: its entries in a random order, now and then followed
+    /// by a phantom of a name no entry has
+    async fn start_chaotic_listing(
+        &mut self,
+        dirid: fileid3,
+        start_after: fileid3,
+    ) -> Result<(), nfsstat3> {
+        let entry = self.find_entry(dirid)?;
+        if start_after != 0 || !self.lists_chaotically(&entry.name) {
+            return Ok(());
+        }
+        let mut order: Vec<fileid3> = entry.children.iter().flatten().copied().collect();
+        let dir_path = self.sym_to_path(&entry.name).await;
+        let mut rng = self.rng.lock().await;
+        order.shuffle(&mut *rng);
+        let mut phantom = None;
+        if rng.gen_bool(PHANTOM_CHANCE) {
+            let name = PHANTOMS[rng.gen_range(0..PHANTOMS.len())];
+            if !exists_no_traverse(&dir_path.join(name)) {
+                phantom = Some((self.next_fileid.fetch_add(1, Ordering::Relaxed), name));
+            }
+        }
+        drop(rng);
+        self.physics
+            .start_listing(dirid, ChaoticListing { order, phantom });
+        Ok(())
+    }
+
+    /// The phantom the calling client lists at the end of the host entries
+    /// of the directory `dirid`, unless it was listed before `start_after`
+    fn chaotic_phantom(&self, dirid: fileid3, start_after: fileid3) -> Option<(fileid3, &OsStr)> {
+        if !self.lists_chaotically(&self.id_to_path.get(&dirid)?.name) {
+            return None;
+        }
+        let (id, name) = self.physics.listing(dirid)?.phantom?;
+        (id != start_after).then_some((id, OsStr::new(name)))
+    }
+
+    /// The stage directory an entry lives under, i.e. its top level component
+    fn stage_of(&self, symlist: &[Symbol]) -> OsString {
+        symlist
+            .first()
+            .and_then(|s| self.intern.get(*s))
+            .map(OsStr::to_os_string)
+            .unwrap_or_default()
+    }
+
+    async fn sym_to_path(&self, symlist: &[Symbol]) -> PathBuf {
+        let mut ret = self.root.clone();
+        for i in symlist.iter() {
+            ret.push(self.intern.get(*i).unwrap());
+        }
+        ret
+    }
+
+    /// The path of an entry relative to the root, as the game knows it
+    fn game_path(&self, symlist: &[Symbol]) -> PathBuf {
+        symlist.iter().filter_map(|s| self.intern.get(*s)).collect()
+    }
+
+    /// The contents the game shows in place of an entry's host file, if any
+    fn game_contents(&self, symlist: &[Symbol]) -> Option<Vec<u8>> {
+        self.game.on_read(&self.game_path(symlist))
+    }
+
+    /// Puts what the game shows of an entry in place of the attributes of
+    /// its host file, returning whether it made up any of them
+    fn game_attrs(&self, symlist: &[Symbol], attr: &mut fattr3) -> bool {
+        let mut made_up = false;
+        if let Some(contents) = self.game_contents(symlist) {
+            attr.size = contents.len() as u64;
+            attr.used = contents.len() as u64;
+            made_up = true;
+        }
+        if let Some(synthetic) = self.game.synthetic_attrs(&self.game_path(symlist)) {
+            if let Some(size) = synthetic.size {
+                attr.size = size;
+                attr.used = size;
+            }
+            if let Some(mtime) = synthetic.mtime {
+                attr.mtime = to_nfstime(mtime);
+            }
+            made_up = true;
+        }
+        made_up
+    }
+
+    fn sym_to_fname(&self, symlist: &[Symbol]) -> &OsStr {
+        symlist
+            .last()
+            .and_then(|x| self.intern.get(*x))
+            .unwrap_or_default()
+    }
+
+    /// Lists up to `max_entries` children of a directory, starting after the
+    /// child `start_after` (0 to start at the beginning), returning the
+    /// entries and whether the end of the directory was reached. Children
+    /// for which `make_entry` returns None are left out.
+    ///
+    /// Children are ordered by fileid and the fileid is used as the cookie,
+    /// so pagination stays stable even if entries are added or removed in
+    /// between calls. Entries are borrowed rather than cloned so that paging
+    /// through very large directories stays cheap.
+    fn list_dir<T>(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        max_entries: usize,
+        mut make_entry: impl FnMut(fileid3, &OsStr, &FSEntry) -> Option<T>,
+    ) -> Result<(Vec<T>, bool), nfsstat3> {
+        let entry = self.id_to_path.get(&dirid).ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        if !matches!(entry.fsmeta.ftype, ftype3::NF3DIR) {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        // we must have children here
+        let children = entry.children.as_ref().ok_or(nfsstat3::NFS3ERR_IO)?;
+
+        let range_start = if start_after > 0 {
+            Bound::Excluded(start_after)
+        } else {
+            Bound::Unbounded
+        };
+        // chaotic directories are listed in the order drawn for the client
+        let ids: Box<dyn Iterator<Item = &fileid3>> = match self.physics.listing(dirid) {
+            Some(listing) if self.lists_chaotically(&entry.name) => {
+                Box::new(listing.after(start_after).iter())
+            }
+            _ => Box::new(children.range((range_start, Bound::Unbounded))),
+        };
+        let mut remaining = ids.peekable();
+        let mut ret = Vec::new();
+        while let Some(fileid) = remaining.peek() {
+            let Some(child) = self.id_to_path.get(fileid) else {
+                // deleted since the directory was last listed
+                remaining.next();
+                continue;
+            };
+            // A file hard linked several times into this directory is
+            // listed under each name. The names share a cookie, so they
+            // must not be split across pages.
+            let names: Vec<&Vec<Symbol>> = std::iter::once(&child.name)
+                .chain(child.links.iter())
+                .filter(|p| p.len() == entry.name.len() + 1 && p.starts_with(&entry.name))
+                .collect();
+            if !ret.is_empty() && ret.len() + names.len() > max_entries {
+                break;
+            }
+            for name in names {
+                ret.extend(make_entry(**fileid, self.sym_to_fname(name), &child));
+            }
+            remaining.next();
+            if ret.len() >= max_entries {
+                break;
+            }
+        }
+        let end = remaining.peek().is_none();
+        Ok((ret, end))
+    }
+}
+
+#[derive(Debug)]
+pub struct EternalFS {
+    fsmap: Arc<tokio::sync::Mutex<FSMap>>,
+    handles: HandlePool,
+    read_ahead: Arc<ReadAhead>,
+    delayed_writes: Arc<DelayedWrites>,
+    /// Refuse every modification, so that the world can be shown to
+    /// visitors without letting them change it
+    read_only: AtomicBool,
+    /// Check the permissions of the calling client rather than serving
+    /// everyone with the permissions of the server
+    act_as_caller: AtomicBool,
+    events: Events,
+}
+
+/// Enumeration for the create_fs_object method
+enum CreateFSObject {
+    /// Creates a directory with a set of attributes
+    Directory(sattr3),
+    /// Creates a file with a set of attributes
+    File(sattr3),
+    /// Creates an exclusive file with a set of attributes
+    Exclusive,
+    /// Creates a symlink with a set of attributes to a target location
+    Symlink((sattr3, nfspath3)),
+    /// Creates a named pipe with a set of attributes
+    Fifo(sattr3),
+    /// Creates a unix socket node with a set of attributes
+    Socket(sattr3),
+}
+impl EternalFS {
+    /// Serves the philosophical journey through `world` in `root`
+    pub fn new(root: PathBuf, world: World) -> EternalFS {
+        Self::with_engine(root, Box::new(Philosophy::new(world)))
+    }
+
+    /// Serves the game played by `game` in `root`
+    pub fn with_engine(root: PathBuf, game: Box<dyn GameEngine>) -> EternalFS {
+        let fsmap = FSMap::new(root, game);
+        let events = fsmap.events.clone();
+        let read_ahead = fsmap.read_ahead.clone();
+        EternalFS {
+            fsmap: Arc::new(tokio::sync::Mutex::new(fsmap)),
+            handles: HandlePool::new(HANDLE_IDLE_TIMEOUT),
+            read_ahead,
+            delayed_writes: Arc::new(DelayedWrites::default()),
+            read_only: AtomicBool::new(false),
+            act_as_caller: AtomicBool::new(false),
+            events,
+        }
+    }
+
+    /// What happens in the world, as it happens
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    /// How far the player making the call has come
+    pub async fn progress_report(&self) -> ProgressReport {
+        self.fsmap.lock().await.game.progress_report()
+    }
+
+    /// Lets handles to deleted objects keep working if an object of the
+    /// same name and type has been created in their place
+    pub async fn set_stale_grace(&self, enabled: bool) {
+        self.fsmap.lock().await.stale_grace = enabled;
+    }
+
+    /// Serves the attributes of an object read from the host in the last
+    /// `ttl` as they were, rather than reading them again on every
+    /// GETATTR. They are read afresh every time if `ttl` is zero, as they
+    /// are by default, and after any call changing the object.
+    pub async fn set_attr_ttl(&self, ttl: Duration) {
+        let mut fsmap = self.fsmap.lock().await;
+        fsmap.attr_ttl = ttl;
+        fsmap.attrs_read.clear();
+    }
+
+    /// Sets when reading a file changes its access time, which it never
+    /// does by default, see `atime.rs`
+    pub async fn set_atime_policy(&self, policy: AtimePolicy) {
+        self.fsmap.lock().await.atime = policy;
+    }
+
+    /// Sets how the objects of the host are given fileids, counted up as
+    /// they are first seen by default, see `fileids.rs`. Objects already
+    /// seen keep theirs, so it is set before the world is served.
+    pub async fn set_fileid_strategy(&self, strategy: FileidStrategy) {
+        self.fsmap.lock().await.fileids = strategy;
+    }
+
+    /// Keeps the `keep` most recent snapshots of the world, taken whenever
+    /// a player reaches another stage, under `.snapshots/`
+    pub async fn set_snapshots(&self, keep: usize) {
+        self.fsmap.lock().await.set_snapshots(keep);
+    }
+
+    /// Moves removed files to `.trash/` rather than unlinking them, where
+    /// they are kept for `retention`, or unlinks them if there is none
+    pub async fn set_trash(&self, retention: Option<Duration>) {
+        let mut fsmap = self.fsmap.lock().await;
+        fsmap.trash_retention = retention;
+        fsmap.empty_trash();
+    }
+
+    /// Keeps the `keep` most recent versions of every file, kept as
+    /// clients write over them, as `<name>@v<n>`
+    pub async fn set_versions(&self, keep: usize) {
+        self.fsmap.lock().await.versions_kept = keep;
+    }
+
+    /// Keeps the versions of files and the copies snapshots take
+    /// compressed, see `compression.rs`
+    pub async fn set_compression(&self, compress: bool) {
+        self.fsmap.lock().await.compress = compress;
+    }
+
+    /// Keeps what the file system knows of the objects it has seen in
+    /// `store`, all but the `hot_entries` used last, rather than all in
+    /// memory
+    pub async fn set_metadata_store(&self, store: Arc<dyn MetadataStore>, hot_entries: usize) {
+        let mut fsmap = self.fsmap.lock().await;
+        fsmap.id_to_path.set_store(store.clone(), hot_entries);
+        fsmap.path_to_id.set_store(store, hot_entries);
+    }
+
+    /// Sets how long an unused host file handle stays open
+    pub fn set_handle_idle_timeout(&mut self, timeout: Duration) {
+        self.handles = HandlePool::new(timeout);
+    }
+
+    /// Reads up to `window` bytes ahead of clients reading a file in order,
+    /// see `read_ahead.rs`. Nothing is read ahead if 0.
+    pub fn set_read_ahead(&self, window: u64) {
+        self.read_ahead.set_window(window);
+    }
+
+    /// Exports the world read-only. The world itself keeps changing, only
+    /// clients can no longer modify it.
+    pub fn set_read_only(&self, enabled: bool) {
+        self.read_only.store(enabled, Ordering::Relaxed);
+    }
+
+    fn check_writable(&self) -> Result<(), nfsstat3> {
+        if self.read_only.load(Ordering::Relaxed) {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        Ok(())
+    }
+
+    /// Serves every call with the mapped AUTH_SYS credentials of its
+    /// client: the mode bits of files are checked against them and new
+    /// files are given to them. Giving files away needs the server to run
+    /// as root.
+    pub fn set_act_as_caller(&self, enabled: bool) {
+        self.act_as_caller.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The credentials a call is served with, if not with the server's own
+    fn acting_caller(&self) -> Option<Credentials> {
+        if !self.act_as_caller.load(Ordering::Relaxed) {
+            return None;
+        }
+        caller()
+    }
+
+    /// Fails unless the caller may access an object with `attr` for all of
+    /// `want`
+    fn check_access(&self, attr: &fattr3, want: u32) -> Result<(), nfsstat3> {
+        match self.acting_caller() {
+            Some(caller) if !caller.may(attr, want) => Err(nfsstat3::NFS3ERR_ACCES),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails unless the caller may change the attributes of an object with
+    /// `attr` as asked. Only the owner may change ownership, mode and times,
+    /// while changing the size needs write permission.
+    fn check_setattr(&self, attr: &fattr3, setattr: &sattr3) -> Result<(), nfsstat3> {
+        let Some(caller) = self.acting_caller() else {
+            return Ok(());
+        };
+        let owner = caller.uid == 0 || caller.uid == attr.uid;
+        let changes_owner = !matches!(setattr.uid, set_uid3::Void)
+            || !matches!(setattr.gid, set_gid3::Void)
+            || !matches!(setattr.mode, set_mode3::Void);
+        let changes_times = !matches!(setattr.atime, set_atime::DONT_CHANGE)
+            || !matches!(setattr.mtime, set_mtime::DONT_CHANGE);
+        if (changes_owner || changes_times) && !owner {
+            return Err(nfsstat3::NFS3ERR_PERM);
+        }
+        if !matches!(setattr.size, set_size3::Void) && !caller.may(attr, MAY_WRITE) {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        Ok(())
+    }
+
+    /// What of `setattr` the caller may ask of an object it creates. Unless
+    /// it is root it may not give the object to another user, nor to a
+    /// group it is not in, and the object is left to it then.
+    fn permitted_setattr(&self, setattr: &sattr3) -> sattr3 {
+        let mut setattr = *setattr;
+        let Some(caller) = self.acting_caller() else {
+            return setattr;
+        };
+        if caller.uid == 0 {
+            return setattr;
+        }
+        if matches!(setattr.uid, set_uid3::uid(uid) if uid != caller.uid) {
+            setattr.uid = set_uid3::Void;
+        }
+        if matches!(setattr.gid, set_gid3::gid(gid) if gid != caller.gid && !caller.gids.contains(&gid))
+        {
+            setattr.gid = set_gid3::Void;
+        }
+        setattr
+    }
+
+    /// Flushes pending and delayed writes to disk and saves the progress
+    /// of the game, so that the server can be stopped without losing any
+    /// of it
+    pub async fn flush(&self) {
+        self.delayed_writes.flush_all().await;
+        self.handles.close_all().await;
+        let mut fsmap = self.fsmap.lock().await;
+        if let Err(e) = fsmap.game.save() {
+            warn!("Unable to save the game {:?}", e);
+        }
+    }
+
+    /// Starts the world event scheduler. The operator schedule is read
+    /// from `.eternal/schedule` under the root.
+    pub async fn start_scheduler(&self) {
+        let statedir = self.fsmap.lock().await.root.join(STATE_DIR);
+        let scheduler = Scheduler::load(&statedir);
+        let (send, mut recv) = tokio::sync::mpsc::channel(16);
+        tokio::spawn(scheduler.run(send));
+        let fsmap = self.fsmap.clone();
+        tokio::spawn(async move {
+            while let Some(event) = recv.recv().await {
+                fsmap.lock().await.game.on_event(event).await;
+            }
+        });
+    }
+
+    /// Watches the content packs under `.eternal` of the root and lets the
+    /// game reload them whenever they change, while clients stay mounted
+    pub async fn watch_content(&self) {
+        let statedir = self.fsmap.lock().await.root.join(STATE_DIR);
+        let fsmap = self.fsmap.clone();
+        tokio::spawn(async move {
+            let mut stamp = World::stamp(&statedir);
+            loop {
+                tokio::time::sleep(CONTENT_POLL_INTERVAL).await;
+                let current = World::stamp(&statedir);
+                if current != stamp {
+                    debug!("Content packs in {:?} changed", statedir);
+                    stamp = current;
+                    let mut fsmap = fsmap.lock().await;
+                    fsmap.game.reload();
+                    let physics = fsmap.game.stage_physics();
+                    fsmap.physics.declare(physics);
+                }
+            }
+        });
+    }
+
+    /// Locks the world for a call of a client, and notes the call
+    async fn lock_for_call(&self) -> MutexGuard<'_, FSMap> {
+        let mut fsmap = self.fsmap.lock().await;
+        fsmap.note_client();
+        fsmap.take_due_snapshots();
+        fsmap.compact_symbols();
+        fsmap
+    }
+
+    /// creates a FS object in a given directory and of a given type
+    /// Updates as much metadata as we can in-place
+    async fn create_fs_object(
+        &self,
+        dirid: fileid3,
+        objectname: &filename3,
+        object: &CreateFSObject,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.check_writable()?;
+        check_filename(objectname)?;
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(node) = fsmap.xattrs.get(dirid) {
+            // only attributes can be created in the shadow namespace
+            return match object {
+                CreateFSObject::File(setattr) => {
+                    fsmap.xattr_create(&node, objectname, false, setattr).await
+                }
+                CreateFSObject::Exclusive => {
+                    fsmap
+                        .xattr_create(&node, objectname, true, &sattr3::default())
+                        .await
+                }
+                _ => Err(nfsstat3::NFS3ERR_NOTSUPP),
+            };
+        }
+        if fsmap.generated.get(dirid).is_some() || fsmap.virtual_files.get(dirid).is_some() {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        if objectname.as_ref() == XATTR_DIR.as_bytes()
+            || (dirid == 0 && fsmap.is_generated_dir(objectname))
+        {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        let dirid = fsmap.resolve_to_change(dirid).await?;
+        fsmap.record_path(dirid).await;
+        let ent = fsmap.find_entry(dirid)?;
+        self.check_access(&ent.fsmeta, MAY_WRITE | MAY_EXEC)?;
+        let game_path = fsmap
+            .game_path(&ent.name)
+            .join(OsStr::from_bytes(objectname));
+        if is_state_path(&game_path) {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        if let Some(id) = fsmap.virtual_lookup(&game_path) {
+            // creating a file which is there opens it
+            return match object {
+                CreateFSObject::File(_) => Ok((id, fsmap.virtual_getattr(id, &game_path)?)),
+                _ => Err(nfsstat3::NFS3ERR_EXIST),
+            };
+        }
+        let mut path = fsmap.sym_to_path(&ent.name).await;
+        let objectname_osstr = OsStr::from_bytes(objectname).to_os_string();
+        path.push(&objectname_osstr);
+
+        // whether a new file or directory is made for the game to see
+        let mut created = None;
+        match object {
+            CreateFSObject::Directory(_) => {
+                debug!("mkdir {:?}", path);
+                if exists_no_traverse(&path) {
+                    return Err(nfsstat3::NFS3ERR_EXIST);
+                }
+                tokio::fs::create_dir(&path)
+                    .await
+                    .map_err(io_error_to_nfsstat3)?;
+                created = Some(true);
+            }
+            CreateFSObject::File(_) => {
+                debug!("create {:?}", path);
+                if exists_no_traverse(&path) {
+                    // creating a file which is there truncates it
+                    let sym = fsmap.intern.intern(objectname_osstr.clone()).unwrap();
+                    let mut name = ent.name.clone();
+                    name.push(sym);
+                    if let Some(unshared) = fsmap.unshare(&name) {
+                        self.handles.evict(unshared).await;
+                    }
+                    fsmap.preserve_snapshots(&game_path);
+                    fsmap.keep_version(&game_path, 0);
+                    fsmap.game.on_truncate(&game_path, 0);
+                } else {
+                    created = Some(false);
+                }
+                std::fs::File::create(&path).map_err(io_error_to_nfsstat3)?;
+            }
+            CreateFSObject::Exclusive => {
+                debug!("create exclusive {:?}", path);
+                let _ = std::fs::File::options()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .map_err(io_error_to_nfsstat3)?;
+                created = Some(false);
+            }
+            CreateFSObject::Symlink((_, target)) => {
+                debug!("symlink {:?} {:?}", path, target);
+                if exists_no_traverse(&path) {
+                    return Err(nfsstat3::NFS3ERR_EXIST);
+                }
+                tokio::fs::symlink(OsStr::from_bytes(target), &path)
+                    .await
+                    .map_err(io_error_to_nfsstat3)?;
+            }
+            CreateFSObject::Fifo(setattr) | CreateFSObject::Socket(setattr) => {
+                debug!("mknod {:?}", path);
+                if exists_no_traverse(&path) {
+                    return Err(nfsstat3::NFS3ERR_EXIST);
+                }
+                let kind = match object {
+                    CreateFSObject::Fifo(_) => SFlag::S_IFIFO,
+                    _ => SFlag::S_IFSOCK,
+                };
+                let perm = match setattr.mode {
+                    set_mode3::mode(mode) => Mode::from_bits_truncate(mode),
+                    set_mode3::Void => Mode::from_bits_truncate(0o644),
+                };
+                mknod(&path, kind, perm, 0)
+                    .map_err(|e| io_error_to_nfsstat3(std::io::Error::from(e)))?;
+            }
+        }
+        if let Some(caller) = self.acting_caller() {
+            if let Err(e) = std::os::unix::fs::lchown(&path, Some(caller.uid), Some(caller.gid)) {
+                debug!("Unable to give {:?} to uid {}: {:?}", path, caller.uid, e);
+            }
+        }
+        // the object is there whether or not its attributes could be set,
+        // so the client is given it with the attributes it actually has,
+        // rather than told it was not created and finding it there as it
+        // tries again
+        let applied = match object {
+            CreateFSObject::Directory(setattr)
+            | CreateFSObject::File(setattr)
+            | CreateFSObject::Symlink((setattr, _))
+            | CreateFSObject::Fifo(setattr)
+            | CreateFSObject::Socket(setattr) => {
+                create_setattr(&path, &self.permitted_setattr(setattr))
+            }
+            CreateFSObject::Exclusive => Ok(()),
+        };
+        if let Err(stat) = applied {
+            debug!("Unable to set the attributes of {:?}: {:?}", path, stat);
+        }
+        if let Some(dir) = created {
+            fsmap.game.on_create(&game_path, dir);
+            self.events.publish(EventKind::FileCreated {
+                path: game_path.to_string_lossy().into_owned(),
+                dir,
+            });
+        }
+
+        let _ = fsmap.refresh_entry(dirid).await;
+
+        let sym = fsmap.intern.intern(objectname_osstr).unwrap();
+        let mut name = ent.name.clone();
+        name.push(sym);
+        let meta = path.symlink_metadata().map_err(io_error_to_nfsstat3)?;
+        let fileid = fsmap.create_entry(&name, meta.clone()).await;
+
+        // update the children list
+        if let Some(ref mut children) = fsmap
+            .id_to_path
+            .get_mut(&dirid)
+            .ok_or(nfsstat3::NFS3ERR_NOENT)?
+            .children
+        {
+            children.insert(fileid);
+        }
+        Ok((fileid, metadata_to_fattr3(fileid, &meta)))
+    }
+
+    /// The attributes clients are told `id` has. Unless `cached`, they are
+    /// read from the host whatever the attribute cache holds.
+    async fn attrs(&self, id: fileid3, cached: bool) -> Result<fattr3, nfsstat3> {
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(node) = fsmap.xattrs.get(id) {
+            return fsmap.xattr_getattr(id, &node).await;
+        }
+        if let Some(node) = fsmap.generated.get(id) {
+            return fsmap.generated_getattr(id, &node);
+        }
+        if let Some(path) = fsmap.virtual_files.get(id) {
+            return fsmap.virtual_getattr(id, &path);
+        }
+        // resolving refreshes the entry, unless its attributes are cached
+        let resolved = match cached && fsmap.attrs_fresh(id) {
+            true => fsmap.refuse_state(id)?,
+            false => fsmap.resolve_id(id).await?,
+        };
+        let ent = fsmap.find_entry(resolved)?;
+        let path = fsmap.sym_to_path(&ent.name).await;
+        debug!("Stat {:?}: {:?}", path, ent);
+        let mut attr = ent.fsmeta;
+        // the client must keep seeing the fileid it asked about
+        attr.fileid = id;
+        // what the game makes up of an entry is definite
+        if !fsmap.game_attrs(&ent.name, &mut attr)
+            && matches!(attr.ftype, ftype3::NF3REG)
+            && fsmap.physics.jitters_sizes(&fsmap.stage_of(&ent.name))
+            && !fsmap.physics.is_observed(resolved)
+            && !fsmap.game.sees_true_size(&fsmap.game_path(&ent.name))
+        {
+            // unobserved files have no definite size
+            let mut rng = fsmap.rng.lock().await;
+            attr.size = attr.size.saturating_add_signed(rng.gen_range(-1..=1));
+        }
+        Ok(attr)
+    }
+
+    /// Writes `data` at `offset` of the file `id`, on disk before
+    /// returning if `stable`. Returns how far it did reach.
+    async fn write_at(
+        &self,
+        id: fileid3,
+        offset: u64,
+        data: &[u8],
+        stable: bool,
+    ) -> Result<(fattr3, stable_how), nfsstat3> {
+        self.check_writable()?;
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(node) = fsmap.xattrs.get(id) {
+            let attr = fsmap.xattr_write(id, &node, offset, data).await?;
+            return Ok((attr, stable_how::FILE_SYNC));
+        }
+        if fsmap.generated.get(id).is_some() {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        if let Some(path) = fsmap.virtual_files.get(id) {
+            let attr = fsmap.virtual_write(id, &path, offset, data)?;
+            return Ok((attr, stable_how::FILE_SYNC));
+        }
+        // attributes are reported under the requested id, while the
+        // host file is the one the id currently resolves to
+        let resolved = fsmap.resolve_to_change(id).await?;
+        fsmap.record_path(resolved).await;
+        let ent = fsmap.find_entry(resolved)?;
+        self.check_access(&ent.fsmeta, MAY_WRITE)?;
+        let path = fsmap.sym_to_path(&ent.name).await;
+
+        self.read_ahead.forget(resolved);
+        // the game sees the write first
+        let game_path = fsmap.game_path(&ent.name);
+        fsmap.preserve_snapshots(&game_path);
+        fsmap.keep_version(&game_path, offset);
+        if let Some(unshared) = fsmap.unshare(&ent.name) {
+            self.handles.evict(unshared).await;
+        }
+        if let WriteAction::Consumed = fsmap.game.on_write(&game_path, offset, data).await {
+            let meta = path.symlink_metadata().map_err(io_error_to_nfsstat3)?;
+            // clients are told what they will see of it next
+            let mut attr = metadata_to_fattr3(id, &meta);
+            fsmap.game_attrs(&ent.name, &mut attr);
+            return Ok((attr, stable_how::FILE_SYNC));
+        }
+
+        let delay = fsmap.physics.write_delay(&fsmap.stage_of(&ent.name));
+        if let Some(delay) = delay.filter(|_| !stable) {
+            // the write happens, just not yet: it is unstable until it
+            // lands or the client commits it. Writes asked to be stable
+            // can not wait and land at once.
+            drop(fsmap);
+            self.delayed_writes
+                .schedule(resolved, path.clone(), offset, data, delay)
+                .await;
+            let meta = path.symlink_metadata().map_err(io_error_to_nfsstat3)?;
+            let mut attr = metadata_to_fattr3(id, &meta);
+            // the size the file has once the writes pending land
+            if let Some(end) = self.delayed_writes.pending_end(resolved).await {
+                attr.size = attr.size.max(end);
+            }
+            return Ok((attr, stable_how::UNSTABLE));
+        }
+
+        // Continue with normal write operation
+        drop(fsmap);
+        debug!("write to init {:?}", path);
+        let handle = self.handles.get(resolved, &path, true).await.map_err(|e| {
+            debug!("Unable to open {:?}", e);
+            io_error_to_nfsstat3(e)
+        })?;
+        let mut f = handle.lock().await;
+        f.seek(SeekFrom::Start(offset)).await.map_err(|e| {
+            debug!("Unable to seek {:?}", e);
+            io_error_to_nfsstat3(e)
+        })?;
+        f.write_all(data).await.map_err(|e| {
+            debug!("Unable to write {:?}", e);
+            io_error_to_nfsstat3(e)
+        })?;
+        debug!("write to {:?} {:?} {:?}", path, offset, data.len());
+        let _ = f.flush().await;
+        // left to the page cache until the client commits, or the handle
+        // is closed
+        let committed = if stable {
+            let _ = f.sync_all().await;
+            stable_how::FILE_SYNC
+        } else {
+            stable_how::UNSTABLE
+        };
+        let meta = f.metadata().await.map_err(io_error_to_nfsstat3)?;
+        Ok((metadata_to_fattr3(id, &meta), committed))
+    }
+
+    /// Reads up to `count` bytes at `offset` of the host file `f` of `id`,
+    /// as they were not read ahead
+    async fn read_host(
+        &self,
+        f: &mut tokio::fs::File,
+        id: fileid3,
+        path: &Path,
+        meta: &Metadata,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Bytes, bool), nfsstat3> {
+        let len = meta.len();
+        let mut start = offset;
+        let mut end = offset + count as u64;
+        let eof = end >= len;
+        if start >= len {
+            start = len;
+        }
+        if end > len {
+            end = len;
+        }
+        // read straight into the buffer the reply is sent from, which is
+        // not zeroed first, but for the holes of sparse files, which are
+        // not read at all
+        let want = (end - start) as usize;
+        let mut buf = BytesMut::with_capacity(want);
+        for data in sparse::data_in(&*f, start, end) {
+            buf.resize((data.start - start) as usize, 0);
+            f.seek(SeekFrom::Start(data.start))
+                .await
+                .or(Err(nfsstat3::NFS3ERR_IO))?;
+            let read_to = (data.end - start) as usize;
+            while buf.len() < read_to {
+                let read = (&mut *f)
+                    .take((read_to - buf.len()) as u64)
+                    .read_buf(&mut buf)
+                    .await
+                    .or(Err(nfsstat3::NFS3ERR_IO))?;
+                if read == 0 {
+                    return Err(nfsstat3::NFS3ERR_IO);
+                }
+            }
+        }
+        buf.resize(want, 0);
+        self.read_ahead.note(id, path, meta, start, want as u64);
+        Ok((buf.freeze(), eof))
+    }
+}
+
+#[async_trait]
+impl NFSFileSystem for EternalFS {
+    fn root_dir(&self) -> fileid3 {
+        0
+    }
+    fn capabilities(&self) -> VFSCapabilities {
+        if self.read_only.load(Ordering::Relaxed) {
+            VFSCapabilities::ReadOnly
+        } else {
+            VFSCapabilities::ReadWrite
+        }
+    }
+
+    async fn lookup(&self, dirid: fileid3, filename: &filename3) -> Result<fileid3, nfsstat3> {
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(id) = fsmap.xattr_lookup(dirid, filename).await? {
+            return Ok(id);
+        }
+        if let Some(id) = fsmap.generated_lookup(dirid, filename)? {
+            return Ok(id);
+        }
+        if let Some(id) = fsmap.virtual_child_lookup(dirid, filename)? {
+            return Ok(id);
+        }
+        let dirid = fsmap.resolve_id(dirid).await?;
+        fsmap.record_path(dirid).await;
+        let dirent = fsmap.find_entry(dirid)?;
+        self.check_access(&dirent.fsmeta, MAY_EXEC)?;
+        let game_path = fsmap
+            .game_path(&dirent.name)
+            .join(OsStr::from_bytes(filename));
+        if fsmap.game.is_hidden(&game_path) || is_state_path(&game_path) {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+        // spectators only watch the game, see `spectator.rs`
+        if !spectating() {
+            fsmap.game.on_lookup(&game_path)?;
+        }
+        if let Some(id) = fsmap.virtual_lookup(&game_path) {
+            return Ok(id);
+        }
+        if let Ok(id) = fsmap.find_child(dirid, filename).await {
+            if fsmap.id_to_path.contains_key(&id) {
+                fsmap.cache_stats.lookup_hits += 1;
+                return Ok(id);
+            }
+        }
+        fsmap.cache_stats.lookup_misses += 1;
+        // Optimize for negative lookups.
+        // See if the file actually exists on the filesystem
+        let dirent = fsmap.find_entry(dirid)?;
+        let mut path = fsmap.sym_to_path(&dirent.name).await;
+        let objectname_osstr = OsStr::from_bytes(filename).to_os_string();
+        path.push(&objectname_osstr);
+        if !exists_no_traverse(&path) {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+        // ok the file actually exists.
+        // that means something changed under me probably.
+        // refresh.
+
+        if let RefreshResult::Delete = fsmap.refresh_entry(dirid).await? {
+            return Err(nfsstat3::NFS3ERR_STALE);
+        }
+        let _ = fsmap.refresh_dir_list(dirid).await;
+
+        fsmap.find_child(dirid, filename).await
+        //debug!("lookup({:?}, {:?})", dirid, filename);
+
+        //debug!(" -- lookup result {:?}", res);
+    }
+
+    async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
+        //debug!("Stat query {:?}", id);
+        self.attrs(id, true).await
+    }
+
+    async fn wcc_attr(&self, id: fileid3) -> Result<wcc_attr, nfsstat3> {
+        // whatever is cached, clients are told what is on the host
+        let attr = self.attrs(id, false).await?;
+        Ok(wcc_attr {
+            size: attr.size,
+            mtime: attr.mtime,
+            ctime: attr.ctime,
+        })
+    }
+
+    async fn read(&self, id: fileid3, offset: u64, count: u32) -> Result<(Bytes, bool), nfsstat3> {
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(node) = fsmap.xattrs.get(id) {
+            let (data, eof) = fsmap.xattr_read(&node, offset, count).await?;
+            return Ok((data.into(), eof));
+        }
+        if let Some(node) = fsmap.generated.get(id) {
+            let (data, eof) = fsmap.generated_read(&node, offset, count)?;
+            return Ok((data.into(), eof));
+        }
+        if let Some(path) = fsmap.virtual_files.get(id) {
+            let (data, eof) = fsmap.virtual_read(&path, offset, count)?;
+            return Ok((data.into(), eof));
+        }
+        let id = fsmap.resolve_id(id).await?;
+        fsmap.record_path(id).await;
+        let ent = fsmap.find_entry(id)?;
+        self.check_access(&ent.fsmeta, MAY_READ)?;
+        let game_path = fsmap.game_path(&ent.name);
+        if !spectating() {
+            fsmap.game.on_observe(&game_path);
+        }
+        if let Some(contents) = fsmap.game_contents(&ent.name) {
+            let start = (offset as usize).min(contents.len());
+            let end = start.saturating_add(count as usize).min(contents.len());
+            let eof = end >= contents.len();
+            return Ok((Bytes::from(contents).slice(start..end), eof));
+        }
+        let path = fsmap.sym_to_path(&ent.name).await;
+        if fsmap.physics.jitters_sizes(&fsmap.stage_of(&ent.name)) && !spectating() {
+            fsmap.physics.observe(id);
+        }
+        let atime = fsmap.atime;
+        drop(fsmap);
+        self.delayed_writes.flush_due(id).await;
+        let handle = self
+            .handles
+            .get(id, &path, false)
+            .await
+            .or(Err(nfsstat3::NFS3ERR_STALE))?;
+        let mut f = handle.lock().await;
+        let meta = f.metadata().await.or(Err(nfsstat3::NFS3ERR_NOENT))?;
+        let read = match self.read_ahead.serve(id, &meta, offset, count) {
+            Some((data, eof)) => {
+                self.read_ahead
+                    .note(id, &path, &meta, offset, data.len() as u64);
+                (data, eof)
+            }
+            None => {
+                self.read_host(&mut f, id, &path, &meta, offset, count)
+                    .await?
+            }
+        };
+        let touched = atime.updates(&meta) && !spectating() && atime::touch(&*f).is_ok();
+        drop(f);
+        if touched {
+            let mut fsmap = self.fsmap.lock().await;
+            if let Some(entry) = fsmap.id_to_path.get_mut(&id) {
+                entry.fsmeta.atime = to_nfstime(SystemTime::now());
+            }
+        }
+        Ok(read)
+    }
+
+    async fn readdir(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<ReadDirResult, nfsstat3> {
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(node) = fsmap.xattrs.get(dirid) {
+            let (entries, end) = fsmap.xattr_list(&node, start_after, max_entries).await?;
+            let entries = entries
+                .into_iter()
+                .map(|(fileid, name, attr)| DirEntry {
+                    fileid,
+                    name: name.as_bytes().into(),
+                    attr,
+                })
+                .collect();
+            return Ok(ReadDirResult { entries, end });
+        }
+        if let Some(node) = fsmap.generated.get(dirid) {
+            let (entries, end) = fsmap.generated_list(&node, start_after, max_entries)?;
+            let entries = entries
+                .into_iter()
+                .map(|(fileid, name, attr)| DirEntry {
+                    fileid,
+                    name: name.as_bytes().into(),
+                    attr,
+                })
+                .collect();
+            return Ok(ReadDirResult { entries, end });
+        }
+        if let Some(dir) = fsmap.virtual_files.get(dirid) {
+            let (entries, end) = fsmap.virtual_list(&dir, start_after, max_entries)?;
+            let entries = entries
+                .into_iter()
+                .map(|(fileid, name, attr)| DirEntry {
+                    fileid,
+                    name: name.as_bytes().into(),
+                    attr,
+                })
+                .collect();
+            return Ok(ReadDirResult { entries, end });
+        }
+        let dirid = fsmap.resolve_id(dirid).await?;
+        fsmap.record_path(dirid).await;
+        self.check_access(&fsmap.find_entry(dirid)?.fsmeta, MAY_READ)?;
+        let dir_path = fsmap.game_path(&fsmap.find_entry(dirid)?.name);
+        if !spectating() {
+            fsmap.game.on_list(&dir_path);
+        }
+        // whatever the game laid out changed the directory
+        if let RefreshResult::Delete = fsmap.refresh_entry(dirid).await? {
+            return Err(nfsstat3::NFS3ERR_STALE);
+        }
+        fsmap.refresh_dir_list(dirid).await?;
+
+        // what the game adds follows the host entries, and its cookies are
+        // not cookies of the host listing
+        let past_host = fsmap.generated.get(start_after).is_some()
+            || fsmap.virtual_files.get(start_after).is_some();
+        if !past_host {
+            fsmap.start_chaotic_listing(dirid, start_after).await?;
+        }
+        let virtual_children = fsmap.virtual_children(&dir_path);
+        let (mut entries, mut end) = if past_host {
+            (Vec::new(), true)
+        } else {
+            fsmap.list_dir(dirid, start_after, max_entries, |fileid, name, ent| {
+                let path = dir_path.join(name);
+                if fsmap.game.is_hidden(&path)
+                    || is_state_path(&path)
+                    || virtual_children.contains(&path)
+                {
+                    return None;
+                }
+                let mut attr = ent.fsmeta;
+                fsmap.game_attrs(&ent.name, &mut attr);
+                Some(DirEntry {
+                    fileid,
+                    name: name.as_bytes().into(),
+                    attr,
+                })
+            })?
+        };
+        if end && !past_host {
+            if let Some((fileid, name)) = fsmap.chaotic_phantom(dirid, start_after) {
+                // a phantom looks like an empty file nobody can read
+                let dir = fsmap.find_entry(dirid)?;
+                entries.push(DirEntry {
+                    fileid,
+                    name: name.as_bytes().into(),
+                    attr: fattr3 {
+                        ftype: ftype3::NF3REG,
+                        mode: 0o444,
+                        nlink: 1,
+                        size: 0,
+                        used: 0,
+                        fileid,
+                        ..dir.fsmeta
+                    },
+                });
+            }
+        }
+        if end && entries.len() < max_entries {
+            let (added, added_end) =
+                fsmap.game_list(&dir_path, start_after, max_entries - entries.len())?;
+            entries.extend(added.into_iter().map(|(fileid, name, attr)| DirEntry {
+                fileid,
+                name: name.as_bytes().into(),
+                attr,
+            }));
+            end = added_end;
+        }
+        debug!(
+            "readdir({:?}, {:?}) -> {} entries, end {}",
+            dirid,
+            start_after,
+            entries.len(),
+            end
+        );
+        Ok(ReadDirResult { entries, end })
+    }
+
+    async fn readdir_simple_paged(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        count: usize,
+    ) -> Result<ReadDirSimpleResult, nfsstat3> {
+        // READDIR does not need attributes, so do not build them
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(node) = fsmap.xattrs.get(dirid) {
+            let (entries, end) = fsmap.xattr_list(&node, start_after, count).await?;
+            let entries = entries
+                .into_iter()
+                .map(|(fileid, name, _)| DirEntrySimple {
+                    fileid,
+                    name: name.as_bytes().into(),
+                })
+                .collect();
+            return Ok(ReadDirSimpleResult { entries, end });
+        }
+        if let Some(node) = fsmap.generated.get(dirid) {
+            let (entries, end) = fsmap.generated_list(&node, start_after, count)?;
+            let entries = entries
+                .into_iter()
+                .map(|(fileid, name, _)| DirEntrySimple {
+                    fileid,
+                    name: name.as_bytes().into(),
+                })
+                .collect();
+            return Ok(ReadDirSimpleResult { entries, end });
+        }
+        if let Some(dir) = fsmap.virtual_files.get(dirid) {
+            let (entries, end) = fsmap.virtual_list(&dir, start_after, count)?;
+            let entries = entries
+                .into_iter()
+                .map(|(fileid, name, _)| DirEntrySimple {
+                    fileid,
+                    name: name.as_bytes().into(),
+                })
+                .collect();
+            return Ok(ReadDirSimpleResult { entries, end });
+        }
+        let dirid = fsmap.resolve_id(dirid).await?;
+        fsmap.record_path(dirid).await;
+        self.check_access(&fsmap.find_entry(dirid)?.fsmeta, MAY_READ)?;
+        let dir_path = fsmap.game_path(&fsmap.find_entry(dirid)?.name);
+        if !spectating() {
+            fsmap.game.on_list(&dir_path);
+        }
+        // whatever the game laid out changed the directory
+        if let RefreshResult::Delete = fsmap.refresh_entry(dirid).await? {
+            return Err(nfsstat3::NFS3ERR_STALE);
+        }
+        fsmap.refresh_dir_list(dirid).await?;
+
+        let past_host = fsmap.generated.get(start_after).is_some()
+            || fsmap.virtual_files.get(start_after).is_some();
+        if !past_host {
+            fsmap.start_chaotic_listing(dirid, start_after).await?;
+        }
+        let virtual_children = fsmap.virtual_children(&dir_path);
+        let (mut entries, mut end) = if past_host {
+            (Vec::new(), true)
+        } else {
+            fsmap.list_dir(dirid, start_after, count, |fileid, name, _| {
+                let path = dir_path.join(name);
+                if fsmap.game.is_hidden(&path)
+                    || is_state_path(&path)
+                    || virtual_children.contains(&path)
+                {
+                    return None;
+                }
+                Some(DirEntrySimple {
+                    fileid,
+                    name: name.as_bytes().into(),
+                })
+            })?
+        };
+        if end && !past_host {
+            if let Some((fileid, name)) = fsmap.chaotic_phantom(dirid, start_after) {
+                entries.push(DirEntrySimple {
+                    fileid,
+                    name: name.as_bytes().into(),
+                });
+            }
+        }
+        if end && entries.len() < count {
+            let (added, added_end) =
+                fsmap.game_list(&dir_path, start_after, count - entries.len())?;
+            entries.extend(added.into_iter().map(|(fileid, name, _)| DirEntrySimple {
+                fileid,
+                name: name.as_bytes().into(),
+            }));
+            end = added_end;
+        }
+        Ok(ReadDirSimpleResult { entries, end })
+    }
+
+    async fn setattr(&self, id: fileid3, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        self.check_writable()?;
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(node) = fsmap.xattrs.get(id) {
+            return fsmap.xattr_setattr(id, &node, &setattr).await;
+        }
+        if fsmap.generated.get(id).is_some() {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        if let Some(path) = fsmap.virtual_files.get(id) {
+            return fsmap.virtual_setattr(id, &path);
+        }
+        let resolved = fsmap.resolve_to_change(id).await?;
+        fsmap.record_path(resolved).await;
+        let entry = fsmap.find_entry(resolved)?;
+        self.check_setattr(&entry.fsmeta, &setattr)?;
+        let path = fsmap.sym_to_path(&entry.name).await;
+        if let set_size3::size(size) = setattr.size {
+            self.read_ahead.forget(resolved);
+            // writes still delayed land before the file is cut short, not
+            // after it
+            self.delayed_writes.flush(resolved).await;
+            if let Some(unshared) = fsmap.unshare(&entry.name) {
+                self.handles.evict(unshared).await;
+            }
+            let game_path = fsmap.game_path(&entry.name);
+            fsmap.preserve_snapshots(&game_path);
+            fsmap.keep_version(&game_path, size);
+            fsmap.game.on_truncate(&game_path, size);
+        }
+        path_setattr(&path, &setattr).await?;
+
+        // the attributes are read again and kept, so that neither the
+        // reply nor a GETATTR within the attribute cache TTL gives the
+        // size the file had before
+        let metadata = path.symlink_metadata().map_err(io_error_to_nfsstat3)?;
+        fsmap.set_attrs(resolved, &metadata);
+        let mut attr = metadata_to_fattr3(id, &metadata);
+        fsmap.game_attrs(&entry.name, &mut attr);
+        Ok(attr)
+    }
+    async fn write(&self, id: fileid3, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        Ok(self.write_at(id, offset, data, true).await?.0)
+    }
+
+    async fn write_unstable(
+        &self,
+        id: fileid3,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(fattr3, stable_how), nfsstat3> {
+        self.write_at(id, offset, data, false).await
+    }
+
+    async fn commit(&self, id: fileid3, _offset: u64, _count: u32) -> Result<fattr3, nfsstat3> {
+        let mut fsmap = self.lock_for_call().await;
+        if fsmap.xattrs.get(id).is_some()
+            || fsmap.generated.get(id).is_some()
+            || fsmap.virtual_files.get(id).is_some()
+        {
+            // nothing of these is ever left unstable
+            drop(fsmap);
+            return self.getattr(id).await;
+        }
+        let resolved = fsmap.resolve_id(id).await?;
+        let ent = fsmap.find_entry(resolved)?;
+        let path = fsmap.sym_to_path(&ent.name).await;
+        drop(fsmap);
+        // delayed writes are committed by landing early
+        self.delayed_writes.flush(resolved).await;
+        self.handles.sync(resolved, &path).await.map_err(|e| {
+            debug!("Unable to sync {:?} {:?}", path, e);
+            io_error_to_nfsstat3(e)
+        })?;
+        let meta = path.symlink_metadata().map_err(io_error_to_nfsstat3)?;
+        Ok(metadata_to_fattr3(id, &meta))
+    }
+
+    async fn create(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+        setattr: sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.create_fs_object(dirid, filename, &CreateFSObject::File(setattr))
+            .await
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+    ) -> Result<fileid3, nfsstat3> {
+        Ok(self
+            .create_fs_object(dirid, filename, &CreateFSObject::Exclusive)
+            .await?
+            .0)
+    }
+
+    async fn remove(&self, dirid: fileid3, filename: &filename3) -> Result<(), nfsstat3> {
+        self.check_writable()?;
+        check_filename(filename)?;
+        let mut fsmap = self.lock_for_call().await;
+        if let Some(node) = fsmap.xattrs.get(dirid) {
+            return fsmap.xattr_remove(&node, filename).await;
+        }
+        if fsmap.generated.get(dirid).is_some()
+            || fsmap.virtual_files.get(dirid).is_some()
+            || (dirid == 0 && fsmap.is_generated_dir(filename))
+        {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        let dirid = fsmap.resolve_to_change(dirid).await?;
+        fsmap.record_path(dirid).await;
+        let ent = fsmap.find_entry(dirid)?;
+        self.check_access(&ent.fsmeta, MAY_WRITE | MAY_EXEC)?;
+        if is_state_path(&fsmap.game_path(&ent.name).join(OsStr::from_bytes(filename))) {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        if fsmap.is_virtual_child(dirid, filename) {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        let mut path = fsmap.sym_to_path(&ent.name).await;
+        path.push(OsStr::from_bytes(filename));
+        if let Ok(meta) = path.symlink_metadata() {
+            let removed = fsmap.game_path(&ent.name).join(OsStr::from_bytes(filename));
+            fsmap.preserve_snapshots(&removed);
+            if meta.is_dir() {
+                tokio::fs::remove_dir(&path)
+                    .await
+                    .map_err(io_error_to_nfsstat3)?;
+            } else if fsmap.keeps_trash(&removed) {
+                fsmap
+                    .move_to_trash(&removed)
+                    .map_err(io_error_to_nfsstat3)?;
+            } else {
+                tokio::fs::remove_file(&path)
+                    .await
+                    .map_err(io_error_to_nfsstat3)?;
+            }
+            self.events.publish(EventKind::FileRemoved {
+                path: removed.to_string_lossy().into_owned(),
+            });
+
+            let filesym = fsmap
+                .intern
+                .intern(OsStr::from_bytes(filename).to_os_string())
+                .unwrap();
+            let mut sympath = ent.name.clone();
+            sympath.push(filesym);
+            if let Some(fileid) = fsmap.path_to_id.get(&sympath).as_deref().copied() {
+                self.handles.evict(fileid).await;
+                // update the fileid -> path
+                // and the path -> fileid mappings for the deleted name.
+                // The file lives on if it has other hard links.
+                fsmap.unlink_path(&sympath);
+                // we need to update the children listing for the directories
+                if !fsmap.has_name_in(fileid, &ent.name) {
+                    if let Ok(dirent_mut) = fsmap.find_entry_mut(dirid) {
+                        if let Some(ref mut fromch) = dirent_mut.children {
+                            fromch.remove(&fileid);
+                        }
+                    }
+                }
+            }
+
+            let _ = fsmap.refresh_entry(dirid).await;
+        } else {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+
+        Ok(())
+    }
+
+    async fn rename(
+        &self,
+        from_dirid: fileid3,
+        from_filename: &filename3,
+        to_dirid: fileid3,
+        to_filename: &filename3,
+    ) -> Result<(), nfsstat3> {
+        self.check_writable()?;
+        check_filename(from_filename)?;
+        check_filename(to_filename)?;
+        let mut fsmap = self.lock_for_call().await;
+        if fsmap.xattrs.get(from_dirid).is_some() || fsmap.xattrs.get(to_dirid).is_some() {
+            return Err(nfsstat3::NFS3ERR_NOTSUPP);
+        }
+        if fsmap.generated.get(from_dirid).is_some()
+            || fsmap.generated.get(to_dirid).is_some()
+            || fsmap.virtual_files.get(from_dirid).is_some()
+            || fsmap.virtual_files.get(to_dirid).is_some()
+            || (from_dirid == 0 && fsmap.is_generated_dir(from_filename))
+        {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        if to_filename.as_ref() == XATTR_DIR.as_bytes()
+            || (to_dirid == 0 && fsmap.is_generated_dir(to_filename))
+        {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        let from_dirid = fsmap.resolve_to_change(from_dirid).await?;
+        fsmap.record_path(from_dirid).await;
+        let to_dirid = fsmap.resolve_to_change(to_dirid).await?;
+        if fsmap.is_virtual_child(from_dirid, from_filename) {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        if fsmap.is_virtual_child(to_dirid, to_filename) {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+
+        let from_dirent = fsmap.find_entry(from_dirid)?;
+        self.check_access(&from_dirent.fsmeta, MAY_WRITE | MAY_EXEC)?;
+        let mut from_path = fsmap.sym_to_path(&from_dirent.name).await;
+        from_path.push(OsStr::from_bytes(from_filename));
+
+        let to_dirent = fsmap.find_entry(to_dirid)?;
+        self.check_access(&to_dirent.fsmeta, MAY_WRITE | MAY_EXEC)?;
+        let mut to_path = fsmap.sym_to_path(&to_dirent.name).await;
+        to_path.push(OsStr::from_bytes(to_filename));
+        let from_game_path = fsmap
+            .game_path(&from_dirent.name)
+            .join(OsStr::from_bytes(from_filename));
+        let to_game_path = fsmap
+            .game_path(&to_dirent.name)
+            .join(OsStr::from_bytes(to_filename));
+        if is_state_path(&from_game_path) || is_state_path(&to_game_path) {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+
+        // src path must exist
+        if !exists_no_traverse(&from_path) {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+        debug!("Rename {:?} to {:?}", from_path, to_path);
+        // the snapshots keep what is moved away and what is replaced, and
+        // so do the versions of what is replaced
+        fsmap.preserve_snapshots(&from_game_path);
+        fsmap.preserve_snapshots(&to_game_path);
+        if from_game_path != to_game_path {
+            fsmap.keep_version(&to_game_path, 0);
+        }
+        tokio::fs::rename(&from_path, &to_path)
+            .await
+            .map_err(io_error_to_nfsstat3)?;
+        self.events.publish(EventKind::FileRenamed {
+            from: from_path
+                .strip_prefix(&fsmap.root)
+                .unwrap_or(&from_path)
+                .to_string_lossy()
+                .into_owned(),
+            to: to_path
+                .strip_prefix(&fsmap.root)
+                .unwrap_or(&to_path)
+                .to_string_lossy()
+                .into_owned(),
+        });
+
+        let oldsym = fsmap
+            .intern
+            .intern(OsStr::from_bytes(from_filename).to_os_string())
+            .unwrap();
+        let newsym = fsmap
+            .intern
+            .intern(OsStr::from_bytes(to_filename).to_os_string())
+            .unwrap();
+
+        let mut from_sympath = from_dirent.name.clone();
+        from_sympath.push(oldsym);
+        let mut to_sympath = to_dirent.name.clone();
+        to_sympath.push(newsym);
+        let moved = fsmap.path_to_id.get(&from_sympath).as_deref().copied();
+        let replaced = fsmap.path_to_id.get(&to_sympath).as_deref().copied();
+        if moved.is_some() && moved == replaced {
+            // both names are hard links of the same file, which makes
+            // the rename a no-op
+            return Ok(());
+        }
+        if let Some(replaced) = replaced {
+            // the rename clobbered an existing file
+            self.handles.evict(replaced).await;
+            fsmap.unlink_path(&to_sympath);
+            if !fsmap.has_name_in(replaced, &to_dirent.name) {
+                if let Ok(to_dirent_mut) = fsmap.find_entry_mut(to_dirid) {
+                    if let Some(ref mut toch) = to_dirent_mut.children {
+                        toch.remove(&replaced);
+                    }
+                }
+            }
+        }
+        if let Some(fileid) = moved {
+            // update the fileid -> path
+            // and the path -> fileid mappings for the new file
+            let ent = fsmap.id_to_path.get_mut(&fileid).unwrap();
+            if ent.name == from_sympath {
+                ent.name = to_sympath.clone();
+            } else {
+                for link in ent.links.iter_mut().filter(|l| **l == from_sympath) {
+                    *link = to_sympath.clone();
+                }
+            }
+            fsmap.remove_path(&from_sympath);
+            fsmap.insert_path(to_sympath, fileid);
+            fsmap.attrs_read.remove(&fileid);
+            if to_dirid != from_dirid {
+                // moving across directories.
+                // we need to update the children listing for the directories
+                if !fsmap.has_name_in(fileid, &from_dirent.name) {
+                    if let Ok(from_dirent_mut) = fsmap.find_entry_mut(from_dirid) {
+                        if let Some(ref mut fromch) = from_dirent_mut.children {
+                            fromch.remove(&fileid);
+                        }
+                    }
+                }
+                if let Ok(to_dirent_mut) = fsmap.find_entry_mut(to_dirid) {
+                    if let Some(ref mut toch) = to_dirent_mut.children {
+                        toch.insert(fileid);
+                    }
+                }
+            }
+        }
+        let _ = fsmap.refresh_entry(from_dirid).await;
+        if to_dirid != from_dirid {
+            let _ = fsmap.refresh_entry(to_dirid).await;
+        }
+
+        Ok(())
+    }
+    async fn link(
+        &self,
+        fileid: fileid3,
+        linkdirid: fileid3,
+        linkname: &filename3,
+    ) -> Result<(), nfsstat3> {
+        self.check_writable()?;
+        check_filename(linkname)?;
+        let mut fsmap = self.lock_for_call().await;
+        if fsmap.xattrs.get(fileid).is_some() || fsmap.xattrs.get(linkdirid).is_some() {
+            return Err(nfsstat3::NFS3ERR_NOTSUPP);
+        }
+        if fsmap.generated.get(fileid).is_some()
+            || fsmap.generated.get(linkdirid).is_some()
+            || fsmap.virtual_files.get(fileid).is_some()
+            || fsmap.virtual_files.get(linkdirid).is_some()
+        {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        if linkname.as_ref() == XATTR_DIR.as_bytes()
+            || (linkdirid == 0 && fsmap.is_generated_dir(linkname))
+        {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        let fileid = fsmap.resolve_to_change(fileid).await?;
+        fsmap.record_path(fileid).await;
+        let linkdirid = fsmap.resolve_to_change(linkdirid).await?;
+        if fsmap.is_virtual_child(linkdirid, linkname) {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+
+        let ent = fsmap.find_entry(fileid)?;
+        let from_path = fsmap.sym_to_path(&ent.name).await;
+        let dirent = fsmap.find_entry(linkdirid)?;
+        self.check_access(&dirent.fsmeta, MAY_WRITE | MAY_EXEC)?;
+        if is_state_path(&fsmap.game_path(&dirent.name).join(OsStr::from_bytes(linkname))) {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        let mut to_path = fsmap.sym_to_path(&dirent.name).await;
+        to_path.push(OsStr::from_bytes(linkname));
+
+        debug!("Link {:?} to {:?}", to_path, from_path);
+        tokio::fs::hard_link(&from_path, &to_path)
+            .await
+            .map_err(io_error_to_nfsstat3)?;
+
+        let _ = fsmap.refresh_entry(linkdirid).await;
+        let sym = fsmap
+            .intern
+            .intern(OsStr::from_bytes(linkname).to_os_string())
+            .unwrap();
+        let mut name = dirent.name.clone();
+        name.push(sym);
+        let meta = to_path.symlink_metadata().map_err(io_error_to_nfsstat3)?;
+        // the new name resolves to the existing fileid through its inode
+        let id = fsmap.create_entry(&name, meta).await;
+        if let Some(ref mut children) = fsmap
+            .id_to_path
+            .get_mut(&linkdirid)
+            .ok_or(nfsstat3::NFS3ERR_NOENT)?
+            .children
+        {
+            children.insert(id);
+        }
+        Ok(())
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.mkdir_with_attrs(dirid, dirname, &sattr3::default())
+            .await
+    }
+
+    async fn mkdir_with_attrs(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.create_fs_object(dirid, dirname, &CreateFSObject::Directory(*attr))
+            .await
+    }
+
+    async fn symlink(
+        &self,
+        dirid: fileid3,
+        linkname: &filename3,
+        symlink: &nfspath3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.create_fs_object(
+            dirid,
+            linkname,
+            &CreateFSObject::Symlink((*attr, symlink.clone())),
+        )
+        .await
+    }
+    async fn fsstat(&self, fileid: fileid3) -> Result<fsstat3, nfsstat3> {
+        let (root, progress) = {
+            let fsmap = self.fsmap.lock().await;
+            (fsmap.root.clone(), fsmap.game.progress_report())
+        };
+        let (total, answered) = (progress.total, progress.answered);
+        let obj_attributes = match self.getattr(fileid).await {
+            Ok(v) => post_op_attr::attributes(v),
+            Err(_) => post_op_attr::Void,
+        };
+        let stat = statvfs(&root).map_err(|e| {
+            debug!("Unable to statvfs {:?}: {:?}", root, e);
+            nfsstat3::NFS3ERR_IO
+        })?;
+        let frsize = stat.fragment_size() as u64;
+        // Space is reported from the host, but the inodes are inodes of
+        // wisdom: one per question, freed as the questions are answered.
+        let wisdom_remaining = total.saturating_sub(answered);
+        Ok(fsstat3 {
+            obj_attributes,
+            tbytes: stat.blocks() as u64 * frsize,
+            fbytes: stat.blocks_free() as u64 * frsize,
+            abytes: stat.blocks_available() as u64 * frsize,
+            tfiles: total,
+            ffiles: wisdom_remaining,
+            afiles: wisdom_remaining,
+            // the numbers change with every answer
+            invarsec: 0,
+        })
+    }
+
+    async fn mknod(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+        ftype: ftype3,
+        _spec: specdata3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        let object = match ftype {
+            ftype3::NF3FIFO => CreateFSObject::Fifo(*attr),
+            ftype3::NF3SOCK => CreateFSObject::Socket(*attr),
+            // device nodes are not served from the world
+            ftype3::NF3CHR | ftype3::NF3BLK => return Err(nfsstat3::NFS3ERR_NOTSUPP),
+            _ => return Err(nfsstat3::NFS3ERR_BADTYPE),
+        };
+        self.create_fs_object(dirid, filename, &object).await
+    }
+
+    async fn readlink(&self, id: fileid3) -> Result<nfspath3, nfsstat3> {
+        let mut fsmap = self.lock_for_call().await;
+        if fsmap.xattrs.get(id).is_some()
+            || fsmap.generated.get(id).is_some()
+            || fsmap.virtual_files.get(id).is_some()
+        {
+            return Err(nfsstat3::NFS3ERR_BADTYPE);
+        }
+        let id = fsmap.resolve_id(id).await?;
+        fsmap.record_path(id).await;
+        let ent = fsmap.find_entry(id)?;
+        let path = fsmap.sym_to_path(&ent.name).await;
+        let seen = match path.is_symlink() {
+            true => fsmap.game.on_readlink(&fsmap.game_path(&ent.name)),
+            false => None,
+        };
+        drop(fsmap);
+        if let Some(target) = seen {
+            Ok(target.as_os_str().as_bytes().into())
+        } else if path.is_symlink() {
+            if let Ok(target) = path.read_link() {
+                Ok(target.as_os_str().as_bytes().into())
+            } else {
+                Err(nfsstat3::NFS3ERR_IO)
+            }
+        } else {
+            Err(nfsstat3::NFS3ERR_BADTYPE)
+        }
+    }
+
+    async fn shutdown(&self) {
+        self.flush().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ephemeral::EphemeralRoot;
+
+    /// A world played in a directory of its own, with its state directory
+    /// laid out as the server leaves it
+    fn world() -> (EphemeralRoot, EternalFS) {
+        let root = EphemeralRoot::create().unwrap();
+        let statedir = root.path().join(STATE_DIR);
+        std::fs::create_dir_all(statedir.join("packs")).unwrap();
+        let world = World::load(&statedir).unwrap();
+        let fs = EternalFS::new(root.path().to_path_buf(), world);
+        (root, fs)
+    }
+
+    fn name(name: &str) -> filename3 {
+        name.as_bytes().into()
+    }
+
+    #[tokio::test]
+    async fn clients_do_not_see_the_state_directory() {
+        let (_root, fs) = world();
+        let listed = fs.readdir(0, 0, usize::MAX).await.unwrap();
+        assert!(listed
+            .entries
+            .iter()
+            .all(|entry| entry.name.as_ref() != STATE_DIR.as_bytes()));
+        assert!(matches!(
+            fs.lookup(0, &name(STATE_DIR)).await,
+            Err(nfsstat3::NFS3ERR_NOENT)
+        ));
+    }
+
+    #[tokio::test]
+    async fn clients_can_not_drop_packs_into_the_state_directory() {
+        let (root, fs) = world();
+        let packs = root.path().join(STATE_DIR).join("packs");
+        // a handle to the state directory, however a client came by it
+        fs.readdir(0, 0, usize::MAX).await.unwrap();
+        let statedir = fs
+            .fsmap
+            .lock()
+            .await
+            .find_child(0, STATE_DIR.as_bytes())
+            .await
+            .unwrap();
+        assert!(matches!(
+            fs.lookup(statedir, &name("packs")).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert!(matches!(
+            fs.create(statedir, &name("x.toml"), sattr3::default()).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert!(matches!(
+            fs.mkdir(0, &name(STATE_DIR)).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert!(matches!(
+            fs.remove(0, &name(STATE_DIR)).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+
+        // nor by moving a pack in from elsewhere
+        let (pack, _) = fs.create(0, &name("x.toml"), sattr3::default()).await.unwrap();
+        assert!(matches!(
+            fs.rename(0, &name("x.toml"), statedir, &name("x.toml")).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert!(matches!(
+            fs.link(pack, statedir, &name("x.toml")).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert!(matches!(
+            fs.rename(0, &name("x.toml"), 0, &name(STATE_DIR)).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert!(!packs.join("x.toml").exists());
+        assert_eq!(std::fs::read_dir(&packs).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn clients_can_not_read_or_rewrite_the_journeys_of_others() {
+        let (root, fs) = world();
+        let saved = root.path().join(STATE_DIR).join("game.toml");
+        std::fs::write(&saved, "[players]\n").unwrap();
+        // handles into the state directory, as a client might guess them
+        fs.readdir(0, 0, usize::MAX).await.unwrap();
+        let game = {
+            let mut fsmap = fs.fsmap.lock().await;
+            let statedir = fsmap.find_child(0, STATE_DIR.as_bytes()).await.unwrap();
+            fsmap.refresh_dir_list(statedir).await.unwrap();
+            fsmap.find_child(statedir, b"game.toml").await.unwrap()
+        };
+        assert!(matches!(fs.getattr(game).await, Err(nfsstat3::NFS3ERR_ACCES)));
+        assert!(matches!(
+            fs.read(game, 0, 4096).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert!(matches!(
+            fs.write(game, 0, b"[players.mallory]\n").await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert!(matches!(
+            fs.setattr(game, sattr3::default()).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+        assert_eq!(std::fs::read_to_string(&saved).unwrap(), "[players]\n");
+    }
+}
@@ -0,0 +1,897 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "archive")]
+use eternal_fs::archive::ArchiveFS;
+use eternal_fs::audit::{AuditLayer, DEFAULT_AUDIT_MAX_MB};
+use eternal_fs::config::GameConfig;
+#[cfg(feature = "http")]
+use eternal_fs::http::HttpApi;
+use eternal_fs::metadata::{open_metadata_store, DEFAULT_HOT_ENTRIES};
+use eternal_fs::overlay::OverlayFS;
+use eternal_fs::prelude::*;
+#[cfg(feature = "s3")]
+use eternal_fs::s3::{Bucket, S3FS};
+use eternal_fs::spectator::Spectator;
+use eternal_fs::{AtimePolicy, FileidStrategy, Philosophy, PlayerIdentity};
+use nfsserve::access::AccessList;
+use nfsserve::auth::IdMapping;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use nfsserve::fuse::FuseMount;
+use nfsserve::locks::LockManager;
+use nfsserve::tcp::NFSTcpListener;
+use nfsserve::throttle::RateLimit;
+use nfsserve::udp::NFSUdpListener;
+use nfsserve::vfs::NFSFileSystem;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+const HOSTPORT: u16 = 11111;
+/// Directory holding the worlds of `--worlds`, one directory each
+const WORLDS_DIR: &str = "worlds";
+/// Where clients ask rpcbind which port a program is served on
+const PORTMAP_PORT: u16 = 111;
+
+/// Settings applied to every world the server opens
+#[derive(Clone)]
+struct WorldOptions {
+    stale_grace: bool,
+    read_only: bool,
+    act_as_caller: bool,
+    players: PlayerIdentity,
+    handle_idle_timeout: Option<Duration>,
+    /// Bytes read ahead of clients, if not as by default
+    read_ahead: Option<u64>,
+    /// How long attributes read from the host are served as they are
+    attr_ttl: Option<Duration>,
+    /// When reading a file changes its access time
+    atime: AtimePolicy,
+    /// How the objects of the host are given fileids
+    fileids: FileidStrategy,
+    /// Snapshots kept of the world, none if 0
+    snapshots: usize,
+    /// How long removed files are kept in the trash, if they are
+    trash_retention: Option<Duration>,
+    /// Versions kept of every file, none if 0
+    versions: usize,
+    /// Whether what is kept of the game is compressed
+    compress: bool,
+    /// `memory`, `sled` or `sqlite`
+    metadata_store: String,
+    /// Objects whose metadata stays in memory with a store
+    metadata_cache: usize,
+    game: GameConfig,
+}
+
+/// Opens the world in a directory and starts its scheduler
+async fn open_world(path: PathBuf, options: &WorldOptions) -> EternalFS {
+    let world = options
+        .game
+        .compose(&path.join(STATE_DIR))
+        .expect("unable to compose the world");
+    let mut game = Philosophy::new(world);
+    game.set_player_identity(options.players);
+    game.set_evaluator(
+        options
+            .game
+            .evaluator
+            .build()
+            .expect("unable to set up the evaluator"),
+    );
+    game.set_store(
+        options
+            .game
+            .store
+            .build(&path.join(STATE_DIR))
+            .expect("unable to open the saved game"),
+    );
+    game.set_game_config(options.game.clone());
+    game.set_compression(options.compress);
+    game.set_state_key(
+        options
+            .game
+            .store
+            .key()
+            .expect("unable to read the key of the saved game"),
+    );
+    let store_path = path
+        .join(STATE_DIR)
+        .join(format!("metadata.{}", options.metadata_store));
+    let store = open_metadata_store(&options.metadata_store, &store_path)
+        .expect("unable to open the metadata store");
+    let mut fs = EternalFS::with_engine(path, Box::new(game));
+    if let Some(store) = store {
+        fs.set_metadata_store(store, options.metadata_cache).await;
+    }
+    if let Some(timeout) = options.handle_idle_timeout {
+        fs.set_handle_idle_timeout(timeout);
+    }
+    if let Some(window) = options.read_ahead {
+        fs.set_read_ahead(window);
+    }
+    if let Some(ttl) = options.attr_ttl {
+        fs.set_attr_ttl(ttl).await;
+    }
+    fs.set_atime_policy(options.atime).await;
+    fs.set_fileid_strategy(options.fileids).await;
+    fs.set_stale_grace(options.stale_grace).await;
+    fs.set_snapshots(options.snapshots).await;
+    fs.set_trash(options.trash_retention).await;
+    fs.set_versions(options.versions).await;
+    fs.set_compression(options.compress).await;
+    fs.set_read_only(options.read_only);
+    fs.set_act_as_caller(options.act_as_caller);
+    fs.start_scheduler().await;
+    fs.watch_content().await;
+    fs
+}
+
+/// The url of the bucket `path` names, if it is `s3://BUCKET/PREFIX`
+fn bucket_url(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| path.starts_with("s3://"))
+}
+
+/// Whether `path` is an archive to serve rather than a world's directory
+fn is_archive(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    path.is_file()
+        && [".zip", ".tar", ".tar.gz", ".tgz"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+}
+
+/// Opens the bucket `url` names and starts sending what is written to it
+/// up
+#[cfg(feature = "s3")]
+fn open_bucket(url: &str, options: &WorldOptions) -> S3FS {
+    let mut fs = S3FS::new(Bucket::from_url(url).expect("unable to open the bucket"));
+    fs.set_read_only(options.read_only);
+    fs.start_flusher();
+    fs
+}
+
+/// Exports every world cloned from `world` as it is cloned, see
+/// `clones.rs`, and those cloned from the clones in turn
+fn export_clones(world: &EternalFS, exports: Arc<Exports>, options: WorldOptions) {
+    let mut events = world.events().subscribe();
+    tokio::spawn(async move {
+        loop {
+            let (name, dir) = match events.recv().await {
+                Ok(Event {
+                    kind: EventKind::WorldCloned { name, dir },
+                    ..
+                }) => (name, dir),
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            let clone = Arc::new(open_world(PathBuf::from(dir), &options).await);
+            export_clones(&clone, exports.clone(), options.clone());
+            match exports.add(&name, clone) {
+                Ok(()) => tracing::info!("Exported the clone {}", name),
+                Err(e) => tracing::warn!("Unable to export the clone {}: {}", name, e),
+            }
+        }
+    });
+}
+
+/// Lays the directory `upper` over the pristine world `lower`, making it
+/// if there is none yet
+fn open_overlay(lower: &Path, upper: PathBuf, options: &WorldOptions) -> OverlayFS {
+    std::fs::create_dir_all(&upper).expect("unable to make the upper layer");
+    let mut fs = OverlayFS::new(lower.to_path_buf(), upper);
+    fs.set_read_only(options.read_only);
+    fs
+}
+
+/// Parses `NAME=PATH`, or a plain `PATH` exported under its directory name
+fn parse_export(spec: &str) -> (String, PathBuf) {
+    match spec.split_once('=') {
+        Some((name, path)) => (name.to_string(), PathBuf::from(path)),
+        None => {
+            let path = PathBuf::from(spec);
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            (name, path)
+        }
+    }
+}
+
+/// Parses `NAME=WORLD`, or `NAME=WORLD@PLAYER` to watch one player
+fn parse_spectator(spec: &str) -> (String, String, Option<String>) {
+    let (name, world) = spec
+        .split_once('=')
+        .unwrap_or_else(|| panic!("{:?} does not name a spectator and a world", spec));
+    match world.split_once('@') {
+        Some((world, player)) => (
+            name.to_string(),
+            world.to_string(),
+            Some(player.to_string()),
+        ),
+        None => (name.to_string(), world.to_string(), None),
+    }
+}
+
+/// The worlds in `dir`, every directory in it exported under its name
+fn worlds_in(dir: &Path) -> Vec<(String, PathBuf)> {
+    let entries = std::fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("unable to list the worlds in {:?}: {}", dir, e));
+    let mut worlds: Vec<(String, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            (!name.starts_with('.')).then(|| (name, entry.path()))
+        })
+        .collect();
+    worlds.sort();
+    worlds
+}
+
+/// Completes once the server is asked to stop with SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("unable to handle SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+/// How the world is served
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Frontend {
+    /// Over the network, to NFS clients
+    Nfs,
+    /// Mounted at the mount point on this machine, through FUSE
+    #[cfg(all(feature = "fuse", target_os = "linux"))]
+    Fuse(PathBuf),
+}
+
+impl Frontend {
+    /// The frontend named `name`, `nfs` or `fuse`, which mounts the world
+    /// at `mountpoint`
+    fn new(name: &str, mountpoint: Option<PathBuf>) -> Result<Frontend, String> {
+        match name {
+            "nfs" => Ok(Frontend::Nfs),
+            "fuse" => match mountpoint {
+                None => Err("--frontend fuse needs --mountpoint".to_string()),
+                #[cfg(all(feature = "fuse", target_os = "linux"))]
+                Some(mountpoint) => Ok(Frontend::Fuse(mountpoint)),
+                #[cfg(not(all(feature = "fuse", target_os = "linux")))]
+                Some(_) => Err(
+                    "--frontend fuse needs eternal-fs to be built with the fuse feature, on Linux"
+                        .to_string(),
+                ),
+            },
+            _ => Err(format!("unknown frontend {:?}", name)),
+        }
+    }
+}
+
+/// How the server is reached
+struct ServeOptions {
+    frontend: Frontend,
+    hostport: String,
+    /// The same address on the portmapper port, which the lock manager
+    /// of a client asks which port to take locks on, if served
+    portmap: Option<String>,
+    transport: String,
+    id_mapping: IdMapping,
+    access: AccessList,
+    rate_limit: RateLimit,
+}
+
+/// Serves the file system over TCP, UDP or both, or through FUSE, until
+/// asked to stop. Every listener is bound before anything is served, and
+/// the file system is shut down once all of them stopped.
+async fn serve<T: NFSFileSystem + Send + Sync + 'static>(
+    options: &ServeOptions,
+    fs: Arc<T>,
+) -> std::io::Result<()> {
+    match &options.frontend {
+        Frontend::Nfs => {}
+        #[cfg(all(feature = "fuse", target_os = "linux"))]
+        Frontend::Fuse(mountpoint) => return mount(options, mountpoint, fs).await,
+    }
+    let hostport = options.hostport.as_str();
+    let (tcp, udp) = match options.transport.as_str() {
+        "tcp" => (true, false),
+        "udp" => (false, true),
+        "both" => (true, true),
+        transport => panic!("unknown transport {:?}", transport),
+    };
+    // clients over either transport, and on either port, see each
+    // other's locks
+    let locks = Arc::new(LockManager::new());
+    let mut tcp_listeners = Vec::new();
+    let mut udp_listeners = Vec::new();
+    if tcp {
+        tcp_listeners.push(bind_tcp(options, hostport, fs.clone(), locks.clone()).await?);
+    }
+    if udp {
+        udp_listeners.push(bind_udp(options, hostport, fs.clone(), locks.clone()).await?);
+    }
+    if let Some(portmap) = &options.portmap {
+        let portmapped = async {
+            if tcp {
+                tcp_listeners.push(bind_tcp(options, portmap, fs.clone(), locks.clone()).await?);
+            }
+            if udp {
+                udp_listeners.push(bind_udp(options, portmap, fs.clone(), locks.clone()).await?);
+            }
+            std::io::Result::Ok(())
+        };
+        if let Err(e) = portmapped.await {
+            tracing::warn!("{}, so clients can not lock", e);
+        }
+    }
+
+    // a single signal stops every listener, and each finishes the calls
+    // in flight before the file system is shut down
+    let (stop, _) = watch::channel(());
+    let stopped = || {
+        let mut stopped = stop.subscribe();
+        async move {
+            let _ = stopped.changed().await;
+        }
+    };
+    let served = futures::future::join(
+        futures::future::join_all(tcp_listeners.iter().map(|l| l.serve_until(stopped()))),
+        futures::future::join_all(udp_listeners.iter().map(|l| l.serve_until(stopped()))),
+    );
+    tokio::pin!(served);
+    let (over_tcp, over_udp) = tokio::select! {
+        served = &mut served => served,
+        _ = shutdown_signal() => {
+            let _ = stop.send(());
+            served.await
+        }
+    };
+    tracing::info!("Shutting down");
+    fs.shutdown().await;
+    over_tcp.into_iter().chain(over_udp).collect()
+}
+
+/// Binds the file system to `hostport` over TCP, to be served there
+async fn bind_tcp<T: NFSFileSystem + Send + Sync + 'static>(
+    options: &ServeOptions,
+    hostport: &str,
+    fs: Arc<T>,
+    locks: Arc<LockManager>,
+) -> std::io::Result<NFSTcpListener<T>> {
+    let mut listener = NFSTcpListener::bind_shared(hostport, fs)
+        .await
+        .map_err(|e| unable(e, format!("Unable to serve on {} over TCP", hostport)))?;
+    listener.with_id_mapping(options.id_mapping.clone());
+    listener.with_access_list(options.access.clone());
+    listener.with_rate_limit(options.rate_limit);
+    listener.with_lock_manager(locks);
+    Ok(listener)
+}
+
+/// Binds the file system to `hostport` over UDP, to be served there
+async fn bind_udp<T: NFSFileSystem + Send + Sync + 'static>(
+    options: &ServeOptions,
+    hostport: &str,
+    fs: Arc<T>,
+    locks: Arc<LockManager>,
+) -> std::io::Result<NFSUdpListener<T>> {
+    let mut listener = NFSUdpListener::bind_shared(hostport, fs)
+        .await
+        .map_err(|e| unable(e, format!("Unable to serve on {} over UDP", hostport)))?;
+    listener.with_id_mapping(options.id_mapping.clone());
+    listener.with_access_list(options.access.clone());
+    listener.with_rate_limit(options.rate_limit);
+    listener.with_lock_manager(locks);
+    Ok(listener)
+}
+
+/// `e`, telling what was being done as it happened
+fn unable(e: std::io::Error, doing: String) -> std::io::Error {
+    std::io::Error::new(e.kind(), format!("{}: {}", doing, e))
+}
+
+/// What the server exits with once it stopped serving, saying why it
+/// could not serve if it could not
+fn exit_code(served: std::io::Result<()>) -> ExitCode {
+    match served {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            tracing::error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Mounts the file system at `mountpoint` through FUSE until asked to
+/// stop. Mounted by root, every local user may use it, as anyone else
+/// mounts it for themselves.
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+async fn mount<T: NFSFileSystem + Send + Sync + 'static>(
+    options: &ServeOptions,
+    mountpoint: &Path,
+    fs: Arc<T>,
+) -> std::io::Result<()> {
+    let mut mount = FuseMount::new_shared(mountpoint, fs);
+    mount.with_id_mapping(options.id_mapping.clone());
+    if nix::unistd::geteuid().is_root() {
+        mount.with_allow_other();
+    }
+    mount
+        .handle_until(shutdown_signal())
+        .await
+        .map_err(|e| unable(e, format!("Unable to mount at {:?}", mountpoint)))
+}
+
+/// Serves the HTTP inspection API of `worlds` on `addr`, if given, until
+/// asked to stop
+#[cfg(feature = "http")]
+fn inspect(addr: Option<&str>, worlds: Vec<(String, Arc<EternalFS>)>) {
+    let Some(addr) = addr.map(str::to_string) else {
+        return;
+    };
+    let mut api = HttpApi::new();
+    for (name, world) in worlds {
+        api.add(&name, world);
+    }
+    if api.is_empty() {
+        tracing::warn!("No world with a game is served, so there is nothing to inspect over HTTP");
+        return;
+    }
+    tokio::spawn(async move {
+        if let Err(e) = api.serve(&addr, shutdown_signal()).await {
+            tracing::error!("Unable to serve the inspection API on {}: {}", addr, e);
+        }
+    });
+}
+
+#[cfg(not(feature = "http"))]
+fn inspect(_addr: Option<&str>, _worlds: Vec<(String, Arc<EternalFS>)>) {}
+
+/// Logs to stderr as plain text, or as one JSON object per line for log
+/// collectors
+fn init_logging(format: &str, audit: Option<AuditLayer>) {
+    let logs = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    let logs = match format {
+        "text" => logs.boxed(),
+        "json" => logs.json().boxed(),
+        _ => panic!("unknown log format {:?}", format),
+    };
+    tracing_subscriber::registry()
+        .with(logs.with_filter(LevelFilter::DEBUG))
+        .with(audit.map(|audit| audit.with_filter(LevelFilter::INFO)))
+        .init();
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut stale_grace = false;
+    let mut read_only = false;
+    let mut compress = false;
+    let mut ephemeral = false;
+    let mut speedrun = false;
+    let mut race = false;
+    let mut coop = None;
+    let mut observer_effect = false;
+    let mut root_squash = false;
+    let mut all_squash = false;
+    let mut portmap = false;
+    let mut bind = None;
+    let mut port = None;
+    let mut config = None;
+    let mut log_format = None;
+    let mut metadata_store = None;
+    let mut transport = None;
+    let mut frontend = None;
+    let mut mountpoint = None;
+    let mut players = None;
+    let mut allow = Vec::new();
+    let mut ops_per_sec = None;
+    let mut bytes_per_sec = None;
+    let mut deny = Vec::new();
+    let mut exports = Vec::new();
+    let mut path = None;
+    let mut lower = None;
+    let mut worlds = None;
+    let mut spectate = Vec::new();
+    let mut http = None;
+    let mut snapshots = None;
+    let mut trash_days = None;
+    let mut versions = None;
+    let mut audit_log = None;
+    let mut audit_max_mb = None;
+    let mut acttl = None;
+    let mut atime = None;
+    let mut fileids = None;
+    let mut replay_audit = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stale-grace" => stale_grace = true,
+            "--read-only" => read_only = true,
+            "--compress" => compress = true,
+            "--ephemeral" => ephemeral = true,
+            "--speedrun" => speedrun = true,
+            "--race" => race = true,
+            "--observer-effect" => observer_effect = true,
+            "--root-squash" => root_squash = true,
+            "--all-squash" => all_squash = true,
+            "--portmap" => portmap = true,
+            "--spectate" => spectate.push(args.next().expect("--spectate needs NAME=WORLD")),
+            "--worlds" => {
+                worlds = Some(PathBuf::from(
+                    args.next().expect("--worlds needs a directory"),
+                ))
+            }
+            "--lower" => {
+                lower = Some(PathBuf::from(
+                    args.next().expect("--lower needs a directory"),
+                ))
+            }
+            "--coop" => {
+                let value = args.next().expect("--coop needs a number of clients");
+                coop = Some(value.parse::<usize>().expect("invalid number of clients"));
+            }
+            "--acttl" => acttl = Some(args.next().expect("--acttl needs a duration")),
+            "--fileids" => {
+                fileids = Some(args.next().expect("--fileids needs counter, path or inode"))
+            }
+            "--atime" => {
+                atime = Some(
+                    args.next()
+                        .expect("--atime needs noatime, relatime or strictatime"),
+                )
+            }
+            "--http" => http = Some(args.next().expect("--http needs an address")),
+            "--trash" => {
+                let value = args.next().expect("--trash needs a number of days");
+                trash_days = Some(value.parse::<u64>().expect("invalid number of days"));
+            }
+            "--audit-log" => {
+                audit_log = Some(PathBuf::from(
+                    args.next().expect("--audit-log needs a file"),
+                ))
+            }
+            "--audit-max-mb" => {
+                let value = args.next().expect("--audit-max-mb needs a size");
+                audit_max_mb = Some(value.parse::<u64>().expect("invalid size"));
+            }
+            "--replay-audit" => {
+                replay_audit = Some(PathBuf::from(
+                    args.next().expect("--replay-audit needs a file"),
+                ))
+            }
+            "--versions" => {
+                let value = args.next().expect("--versions needs a number");
+                versions = Some(value.parse::<usize>().expect("invalid number of versions"));
+            }
+            "--snapshots" => {
+                let value = args.next().expect("--snapshots needs a number");
+                snapshots = Some(value.parse::<usize>().expect("invalid number of snapshots"));
+            }
+            "--bind" => bind = Some(args.next().expect("--bind needs an address")),
+            "--port" => {
+                let value = args.next().expect("--port needs a port");
+                port = Some(value.parse::<u16>().expect("invalid port"));
+            }
+            "--transport" => {
+                transport = Some(args.next().expect("--transport needs tcp, udp or both"))
+            }
+            "--frontend" => frontend = Some(args.next().expect("--frontend needs fuse or nfs")),
+            "--mountpoint" => {
+                mountpoint = Some(PathBuf::from(
+                    args.next().expect("--mountpoint needs a directory"),
+                ))
+            }
+            "--allow" => allow.push(args.next().expect("--allow needs a network")),
+            "--deny" => deny.push(args.next().expect("--deny needs a network")),
+            "--ops-per-sec" => {
+                let value = args.next().expect("--ops-per-sec needs a rate");
+                ops_per_sec = Some(value.parse::<u32>().expect("invalid rate"));
+            }
+            "--bytes-per-sec" => {
+                let value = args.next().expect("--bytes-per-sec needs a rate");
+                bytes_per_sec = Some(value.parse::<u64>().expect("invalid rate"));
+            }
+            "--players" => {
+                players = Some(args.next().expect("--players needs shared, address or uid"))
+            }
+            "--metadata-store" => {
+                metadata_store = Some(
+                    args.next()
+                        .expect("--metadata-store needs memory, sled or sqlite"),
+                )
+            }
+            "--log-format" => {
+                log_format = Some(args.next().expect("--log-format needs text or json"))
+            }
+            "--config" => {
+                let file = args.next().expect("--config needs a file");
+                config = Some(Config::load(&PathBuf::from(file)).expect("unable to configure"));
+            }
+            "--export" => {
+                let spec = args.next().expect("--export needs a directory");
+                exports.push(parse_export(&spec));
+            }
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    // the command line takes precedence over the configuration file
+    let mut config: Config = config.unwrap_or_default();
+    config.server.ops_per_sec = ops_per_sec.or(config.server.ops_per_sec);
+    config.server.bytes_per_sec = bytes_per_sec.or(config.server.bytes_per_sec);
+    config.server.trash_days = trash_days.or(config.server.trash_days);
+    config.server.acttl = acttl.or(config.server.acttl);
+    if !allow.is_empty() {
+        config.server.allow = allow;
+    }
+    if !deny.is_empty() {
+        config.server.deny = deny;
+    }
+    if !spectate.is_empty() {
+        config.server.spectate = spectate;
+    }
+    if speedrun {
+        config.game.speedrun = Some(true);
+    }
+    if race {
+        config.game.race = Some(true);
+    }
+    config.game.coop = coop.or(config.game.coop);
+    if observer_effect {
+        config.game.observer_effect = Some(true);
+    }
+    if root_squash || all_squash {
+        let auth = config.auth.get_or_insert_with(Default::default);
+        auth.root_squash |= root_squash;
+        auth.all_squash |= all_squash;
+    }
+    if let Some(log) = replay_audit {
+        eternal_fs::audit::replay(&log, &mut std::io::stdout().lock())
+            .expect("unable to replay the audit log");
+        return ExitCode::SUCCESS;
+    }
+    let audit = audit_log.or(config.server.audit_log.clone()).map(|log| {
+        let max_mb = audit_max_mb
+            .or(config.server.audit_max_mb)
+            .unwrap_or(DEFAULT_AUDIT_MAX_MB);
+        AuditLayer::open(&log, max_mb).expect("unable to open the audit log")
+    });
+    init_logging(
+        log_format
+            .or(config.server.log_format.clone())
+            .as_deref()
+            .unwrap_or("text"),
+        audit,
+    );
+    let bind = bind.or(config.server.bind.clone());
+    let lower = lower.or(config.server.lower.clone());
+    if let Some(dir) = worlds.or(config.server.worlds.clone()) {
+        let found = worlds_in(&dir.join(WORLDS_DIR));
+        assert!(
+            !found.is_empty(),
+            "there is no world in {:?}",
+            dir.join(WORLDS_DIR)
+        );
+        exports.extend(found);
+    }
+    let http = http.or(config.server.http.clone());
+    let port = port.or(config.server.port).unwrap_or(HOSTPORT);
+    let bind = bind.as_deref().unwrap_or("127.0.0.1");
+    let portmap = portmap || config.server.portmap.unwrap_or(false);
+    let serving = ServeOptions {
+        frontend: Frontend::new(
+            frontend
+                .or(config.server.frontend.clone())
+                .as_deref()
+                .unwrap_or("nfs"),
+            mountpoint.or(config.server.mountpoint.clone()),
+        )
+        .expect("unable to tell how to serve the world"),
+        hostport: format!("{}:{}", bind, port),
+        portmap: (portmap && port != PORTMAP_PORT).then(|| format!("{}:{}", bind, PORTMAP_PORT)),
+        transport: transport
+            .or(config.server.transport.clone())
+            .unwrap_or_else(|| "tcp".to_string()),
+        id_mapping: config
+            .auth
+            .as_ref()
+            .map(|auth| auth.id_mapping())
+            .unwrap_or_default(),
+        access: config
+            .server
+            .access_list()
+            .expect("unable to restrict access"),
+        rate_limit: config.server.rate_limit(),
+    };
+    let options = WorldOptions {
+        stale_grace: stale_grace || config.server.stale_grace.unwrap_or(false),
+        read_only: read_only || config.server.read_only.unwrap_or(false),
+        act_as_caller: config.auth.is_some(),
+        players: players
+            .or(config.game.players.clone())
+            .map_or(PlayerIdentity::Shared, |players| {
+                players.parse().expect("unable to tell players apart")
+            }),
+        handle_idle_timeout: config.server.handle_idle_timeout(),
+        read_ahead: config.server.read_ahead(),
+        attr_ttl: config
+            .server
+            .attr_ttl()
+            .expect("invalid attribute cache TTL"),
+        atime: atime
+            .or(config.server.atime.clone())
+            .map_or(AtimePolicy::NoAtime, |atime| {
+                atime
+                    .parse()
+                    .expect("unable to tell when to change access times")
+            }),
+        fileids: fileids
+            .or(config.server.fileids.clone())
+            .map_or(FileidStrategy::Counter, |fileids| {
+                fileids.parse().expect("unable to tell how to give fileids")
+            }),
+        snapshots: snapshots.or(config.server.snapshots).unwrap_or(0),
+        trash_retention: config.server.trash_retention(),
+        versions: versions.or(config.server.versions).unwrap_or(0),
+        compress: compress || config.server.compress.unwrap_or(false),
+        metadata_store: metadata_store
+            .or(config.server.metadata_store.clone())
+            .unwrap_or_else(|| "memory".to_string()),
+        metadata_cache: config.server.metadata_cache.unwrap_or(DEFAULT_HOT_ENTRIES),
+        game: config.game,
+    };
+    assert!(
+        options.players != PlayerIdentity::Shared || !options.game.race.unwrap_or(false),
+        "a race needs players told apart, with --players address or uid"
+    );
+
+    #[cfg(not(feature = "s3"))]
+    if let Some(url) = path
+        .iter()
+        .chain(exports.iter().map(|(_, path)| path))
+        .find_map(|path| bucket_url(path))
+    {
+        panic!(
+            "serving {} needs eternal-fs to be built with the s3 feature",
+            url
+        );
+    }
+
+    #[cfg(not(feature = "archive"))]
+    if let Some(archive) = path
+        .iter()
+        .chain(exports.iter().map(|(_, path)| path))
+        .find(|path| is_archive(path))
+    {
+        panic!(
+            "serving {:?} needs eternal-fs to be built with the archive feature",
+            archive
+        );
+    }
+
+    #[cfg(not(feature = "compress"))]
+    assert!(
+        !options.compress,
+        "--compress needs eternal-fs to be built with the compress feature"
+    );
+
+    #[cfg(not(feature = "http"))]
+    assert!(
+        http.is_none(),
+        "the inspection API needs eternal-fs to be built with the http feature"
+    );
+
+    #[cfg(not(feature = "git"))]
+    assert!(
+        !options.game.git.unwrap_or(false),
+        "keeping the journey in git needs eternal-fs to be built with the git feature"
+    );
+
+    // an ephemeral world is removed as the server stops, and `scratch`
+    // with it
+    let scratch = match ephemeral || config.server.ephemeral.unwrap_or(false) {
+        true => {
+            assert!(
+                path.is_none() && exports.is_empty(),
+                "--ephemeral serves a world of its own, without a directory"
+            );
+            Some(EphemeralRoot::create().expect("unable to make room for the world"))
+        }
+        false => None,
+    };
+
+    if exports.is_empty() {
+        assert!(
+            config.server.spectate.is_empty(),
+            "spectators watch worlds exported with --export or --worlds"
+        );
+        let path = path
+            .or_else(|| scratch.as_ref().map(|root| root.path().to_path_buf()))
+            .expect("must supply directory to mirror");
+        #[cfg(feature = "s3")]
+        if let Some(url) = bucket_url(&path) {
+            return exit_code(serve(&serving, Arc::new(open_bucket(url, &options))).await);
+        }
+        #[cfg(feature = "archive")]
+        if is_archive(&path) {
+            let archive = ArchiveFS::open(&path).expect("unable to open the archive");
+            return exit_code(serve(&serving, Arc::new(archive)).await);
+        }
+        if let Some(lower) = &lower {
+            let overlay = open_overlay(lower, path, &options);
+            return exit_code(serve(&serving, Arc::new(overlay)).await);
+        }
+        let world = Arc::new(open_world(path, &options).await);
+        inspect(http.as_deref(), vec![("/".to_string(), world.clone())]);
+        return exit_code(serve(&serving, world).await);
+    }
+    assert!(
+        path.is_none(),
+        "a directory can not be mixed with --export or --worlds"
+    );
+
+    // A directory holds one world, so exporting it under several names
+    // shares its game state. Every other export plays on its own, and a
+    // bucket, an archive or an overlay is served without a game.
+    let mut worlds: HashMap<PathBuf, Arc<EternalFS>> = HashMap::new();
+    let mut inspected = Vec::new();
+    let fs = Arc::new(Exports::new());
+    for (name, path) in exports {
+        #[cfg(feature = "s3")]
+        if let Some(url) = bucket_url(&path) {
+            fs.add(&name, Arc::new(open_bucket(url, &options)))
+                .expect("unable to export");
+            continue;
+        }
+        #[cfg(feature = "archive")]
+        if is_archive(&path) {
+            let archive = ArchiveFS::open(&path).expect("unable to open the archive");
+            fs.add(&name, Arc::new(archive)).expect("unable to export");
+            continue;
+        }
+        if let Some(lower) = &lower {
+            fs.add(&name, Arc::new(open_overlay(lower, path, &options)))
+                .expect("unable to export");
+            continue;
+        }
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let world = match worlds.get(&key) {
+            Some(world) => world.clone(),
+            None => {
+                let world = Arc::new(open_world(path, &options).await);
+                worlds.insert(key, world.clone());
+                world
+            }
+        };
+        inspected.push((name.clone(), world.clone()));
+        fs.add(&name, world).expect("unable to export");
+    }
+    for world in worlds.values() {
+        export_clones(world, fs.clone(), options.clone());
+    }
+    for spec in &config.server.spectate {
+        let (name, watched, player) = parse_spectator(spec);
+        let world = inspected
+            .iter()
+            .find(|(name, _)| *name == watched)
+            .map(|(_, world)| world.clone())
+            .unwrap_or_else(|| panic!("there is no world exported as {:?} to watch", watched));
+        fs.add(&name, Arc::new(Spectator::new(world, player)))
+            .expect("unable to export");
+    }
+    inspect(http.as_deref(), inspected);
+    exit_code(serve(&serving, fs).await)
+}
+// Test with
+// mount -t nfs -o vers=3,tcp,port=12000,mountport=12000,soft 127.0.0.1:/ eternal
@@ -0,0 +1,188 @@
+//! The map of the journey drawn at the top of `progress.txt`.
+//!
+//! Every core stage is a room, followed by enlightenment, laid out along a
+//! winding corridor in the order they are reached. Rooms are marked as the
+//! player stands with them, and corridors the player walked are drawn
+//! double.
+//!
+//! ```text
+//! +-------------+    +-------------+    +-------------+
+//! |    logic    |====|   emotion   |----|  identity   |
+//! |      *      |    |      @      |    |      #      |
+//! +-------------+    +-------------+    +-------------+
+//! ```
+
+use crate::packs::World;
+use crate::philosophy::GameStage;
+use crate::players::PlayerState;
+
+/// Rooms drawn side by side before the corridor turns
+const ROOMS_PER_ROW: usize = 4;
+
+/// The width inside the walls of a room
+const ROOM_WIDTH: usize = 13;
+
+/// The width of a corridor between two rooms
+const CORRIDOR_WIDTH: usize = 4;
+
+const LEGEND: &str = "* answered  @ you are here  o open  . visited  # locked";
+
+/// How the player stands with a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoomState {
+    Answered,
+    /// The stage the player is at
+    Current,
+    /// A stage which may be answered as well
+    Open,
+    /// A locked stage the player looked into
+    Visited,
+    Locked,
+}
+
+impl RoomState {
+    fn mark(self) -> char {
+        match self {
+            RoomState::Answered => '*',
+            RoomState::Current => '@',
+            RoomState::Open => 'o',
+            RoomState::Visited => '.',
+            RoomState::Locked => '#',
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            RoomState::Answered => "answered",
+            RoomState::Current => "current",
+            RoomState::Open => "open",
+            RoomState::Visited => "visited",
+            RoomState::Locked => "locked",
+        }
+    }
+}
+
+/// A stage as drawn on the map
+struct Room {
+    name: String,
+    state: RoomState,
+}
+
+impl PlayerState {
+    /// The rooms of the map, in the order they are reached
+    fn rooms(&self, world: &World) -> Vec<Room> {
+        let open: Vec<&str> = self
+            .open_stages(world)
+            .iter()
+            .map(|stage| stage.dir.as_str())
+            .collect();
+        let state_of = |dir: &str| {
+            if self.completed_questions.contains(dir) {
+                RoomState::Answered
+            } else if open.first() == Some(&dir) {
+                RoomState::Current
+            } else if open.contains(&dir) {
+                RoomState::Open
+            } else if self.visited.contains(dir) {
+                RoomState::Visited
+            } else {
+                RoomState::Locked
+            }
+        };
+        let mut rooms: Vec<Room> = world
+            .core_stages()
+            .map(|stage| Room {
+                name: stage.dir.clone(),
+                state: state_of(&stage.dir),
+            })
+            .collect();
+        let enlightenment = match self.current_stage {
+            GameStage::Enlightened => RoomState::Answered,
+            _ if open.is_empty() => RoomState::Current,
+            _ => RoomState::Locked,
+        };
+        rooms.push(Room {
+            name: "enlightenment".to_string(),
+            state: enlightenment,
+        });
+        rooms
+    }
+
+    /// The map of the journey of the player
+    pub(crate) fn map(&self, world: &World) -> String {
+        render(&self.rooms(world))
+    }
+
+    /// The rooms of the map by name, in the order they are reached, with
+    /// how the player stands with them
+    pub(crate) fn map_rooms(&self, world: &World) -> Vec<(String, &'static str)> {
+        self.rooms(world)
+            .into_iter()
+            .map(|room| (room.name, room.state.name()))
+            .collect()
+    }
+}
+
+/// Writes `text` into `line` from the column `at`
+fn put(line: &mut Vec<char>, at: usize, text: &str) {
+    for (i, c) in text.chars().enumerate() {
+        if line.len() <= at + i {
+            line.resize(at + i + 1, ' ');
+        }
+        line[at + i] = c;
+    }
+}
+
+/// Draws `rooms` along a corridor winding back and forth across rows
+fn render(rooms: &[Room]) -> String {
+    let wall = format!("+{}+", "-".repeat(ROOM_WIDTH));
+    let step = ROOM_WIDTH + 2 + CORRIDOR_WIDTH;
+    let rows: Vec<&[Room]> = rooms.chunks(ROOMS_PER_ROW).collect();
+    let mut out = String::new();
+    for (r, row) in rows.iter().enumerate() {
+        // every other row is walked from right to left
+        let column = |i: usize| {
+            if r % 2 == 0 {
+                i
+            } else {
+                ROOMS_PER_ROW - 1 - i
+            }
+        };
+        let mut lines = vec![Vec::new(); 4];
+        for (i, room) in row.iter().enumerate() {
+            let x = column(i) * step;
+            let name: String = room.name.chars().take(ROOM_WIDTH).collect();
+            put(&mut lines[0], x, &wall);
+            put(&mut lines[1], x, &format!("|{:^w$}|", name, w = ROOM_WIDTH));
+            put(
+                &mut lines[2],
+                x,
+                &format!("|{:^w$}|", room.state.mark(), w = ROOM_WIDTH),
+            );
+            put(&mut lines[3], x, &wall);
+            if i + 1 < row.len() {
+                let walked = room.state == RoomState::Answered;
+                let corridor = if walked { "=" } else { "-" }.repeat(CORRIDOR_WIDTH);
+                // the corridor leaves a room towards the next one
+                let from = column(i).min(column(i + 1)) * step + ROOM_WIDTH + 2;
+                put(&mut lines[1], from, &corridor);
+            }
+        }
+        for line in lines {
+            out.push_str(line.iter().collect::<String>().trim_end());
+            out.push('\n');
+        }
+        if let (Some(last), Some(_)) = (row.last(), rows.get(r + 1)) {
+            let x = column(row.len() - 1) * step + ROOM_WIDTH / 2;
+            let corridor = if last.state == RoomState::Answered {
+                "||"
+            } else {
+                " |"
+            };
+            out.push_str(&format!("{}{}\n", " ".repeat(x), corridor));
+        }
+    }
+    out.push_str(LEGEND);
+    out.push('\n');
+    out
+}
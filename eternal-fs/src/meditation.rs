@@ -0,0 +1,138 @@
+//! Meditation, which reveals a clue to those who wait.
+//!
+//! Reading `meditation.txt` sits the player down to meditate on the stage
+//! they are at. Later reads tell how long the meditation has left, and
+//! once it is over the file reveals the next hint of the stage, without a
+//! hint token being spent. A stage is meditated on once, the next stage
+//! starts a new meditation.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::packs::World;
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The file meditated on
+pub(crate) const MEDITATION_FILE: &str = "meditation.txt";
+
+/// How long a meditation lasts, unless configured otherwise
+pub const DEFAULT_MEDITATION_SECS: u64 = 300;
+
+/// A meditation on a stage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Meditation {
+    /// The stage meditated on
+    stage: String,
+    started: SystemTime,
+    /// The clue revealed, once the meditation is over
+    clue: Option<String>,
+}
+
+impl PlayerState {
+    /// Sits down to meditate on the stage the player is at, unless they
+    /// are meditating on it already, and reveals the clue once `duration`
+    /// has passed
+    fn meditate(&mut self, world: &World, duration: Duration) {
+        let (stage, ladder) = self.hints_at(world);
+        match &self.meditation {
+            Some(meditation) if meditation.stage == stage => {}
+            _ => {
+                info!("Meditating on {}", stage);
+                self.meditation = Some(Meditation {
+                    stage,
+                    started: SystemTime::now(),
+                    clue: None,
+                });
+                return;
+            }
+        }
+        let Some(meditation) = self.meditation.as_mut() else {
+            return;
+        };
+        if meditation.clue.is_some() || meditation.started.elapsed().unwrap_or_default() < duration
+        {
+            return;
+        }
+        let taken = self.hints_taken.get(&stage).copied().unwrap_or(0);
+        let clue = match ladder.get(taken).or(ladder.last()) {
+            Some(clue) => clue.to_string(),
+            None => format!("Nothing rises. The path of {} must be found alone.", stage),
+        };
+        if taken < ladder.len() {
+            // the clue is a hint like any other, request.txt goes on from it
+            self.hints_taken.insert(stage.clone(), taken + 1);
+        }
+        info!("Meditation on {} revealed a clue", stage);
+        meditation.clue = Some(clue);
+    }
+
+    /// What meditation.txt shows the player
+    fn meditation(&self, duration: Duration) -> String {
+        let Some(meditation) = &self.meditation else {
+            return format!(
+                "Meditation\n\
+                 ==========\n\
+                 Read this file to sit down and meditate for {}.\n",
+                describe_duration(duration)
+            );
+        };
+        if let Some(clue) = &meditation.clue {
+            return format!(
+                "Meditation\n\
+                 ==========\n\
+                 Your meditation on {} is over. A clue rises:\n\
+                 \n\
+                 {}\n",
+                meditation.stage, clue
+            );
+        }
+        let remaining = duration.saturating_sub(meditation.started.elapsed().unwrap_or_default());
+        format!(
+            "Meditation\n\
+             ==========\n\
+             Breathe. Let the question of {} settle.\n\
+             \n\
+             Remaining: {}\n",
+            meditation.stage,
+            describe_duration(remaining)
+        )
+    }
+}
+
+/// `duration` in minutes and seconds
+fn describe_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}m {:02}s", secs / 60, secs % 60)
+}
+
+impl Philosophy {
+    /// How long a meditation lasts
+    fn meditation_duration(&self) -> Duration {
+        Duration::from_secs(
+            self.config
+                .meditation_secs
+                .unwrap_or(DEFAULT_MEDITATION_SECS),
+        )
+    }
+
+    /// Meditates for the player making the call, as they read
+    /// meditation.txt
+    pub(crate) fn observe_meditation(&mut self) {
+        let duration = self.meditation_duration();
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        player.meditate(&self.world, duration);
+    }
+
+    /// What meditation.txt shows the player making the call
+    pub(crate) fn meditation(&self) -> String {
+        let newcomer = PlayerState::default();
+        self.players
+            .get(&self.player_identity.current())
+            .unwrap_or(&newcomer)
+            .meditation(self.meditation_duration())
+    }
+}
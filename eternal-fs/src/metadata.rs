@@ -0,0 +1,548 @@
+//! Where the file system keeps what it knows of the host.
+//!
+//! Every object the file system has seen has an entry, found by fileid,
+//! and a fileid, found by path. For a world of a few thousand files they
+//! are all kept in memory. An export of millions of entries would need
+//! gigabytes for them, so a [`MetadataStore`] can be set to keep them in
+//! instead: a sled database with the `sled` feature, or an SQLite file
+//! with the `sqlite` feature. Only the entries used last are then kept in
+//! memory, the others are written to the store and read back as they are
+//! needed again. What a store holds is only good for the run which wrote
+//! it, so it is emptied as it is opened.
+
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::io::{self, Cursor};
+use std::path::Path;
+use std::sync::Arc;
+
+use intaglio::Symbol;
+use nfsserve::nfs::fattr3;
+use nfsserve::xdr::XDR;
+use tracing::warn;
+
+use crate::FSEntry;
+
+/// Entries kept in memory by default when they are kept in a store
+pub const DEFAULT_HOT_ENTRIES: usize = 100_000;
+
+/// Keeps entries of the file system which are not kept in memory, by key
+pub trait MetadataStore: Debug + Send + Sync {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()>;
+
+    fn remove(&self, key: &[u8]) -> io::Result<()>;
+
+    /// Puts every one of `entries`, at once if the store can
+    fn put_all(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> io::Result<()> {
+        entries
+            .iter()
+            .try_for_each(|(key, value)| self.put(key, value))
+    }
+}
+
+/// Opens the store of the kind `kind`, `memory`, `sled` or `sqlite`, at
+/// `path`. None for `memory`, as entries are then kept in memory anyway.
+#[cfg_attr(
+    not(any(feature = "sled", feature = "sqlite")),
+    allow(unused_variables)
+)]
+pub fn open_metadata_store(
+    kind: &str,
+    path: &Path,
+) -> Result<Option<Arc<dyn MetadataStore>>, String> {
+    match kind {
+        "memory" => Ok(None),
+        #[cfg(feature = "sled")]
+        "sled" => {
+            Ok(Some(Arc::new(SledMetadata::open(path).map_err(|e| {
+                format!("unable to open {:?}: {}", path, e)
+            })?)))
+        }
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            Ok(Some(Arc::new(SqliteMetadata::open(path).map_err(|e| {
+                format!("unable to open {:?}: {}", path, e)
+            })?)))
+        }
+        #[cfg(not(feature = "sled"))]
+        "sled" => {
+            Err("the sled metadata store needs eternal-fs to be built with the sled feature".into())
+        }
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => Err(
+            "the sqlite metadata store needs eternal-fs to be built with the sqlite feature".into(),
+        ),
+        kind => Err(format!("unknown metadata store {:?}", kind)),
+    }
+}
+
+/// What can be written to a [`MetadataStore`]
+pub(crate) trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+/// What can be written to a [`MetadataStore`] and read back
+pub(crate) trait Stored: Encode + Sized {
+    fn decode(src: &mut Cursor<&[u8]>) -> io::Result<Self>;
+}
+
+impl Encode for u64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Stored for u64 {
+    fn decode(src: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        let mut value = 0;
+        value.deserialize(src)?;
+        Ok(value)
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Stored for u32 {
+    fn decode(src: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        let mut value = 0;
+        value.deserialize(src)?;
+        Ok(value)
+    }
+}
+
+impl Encode for [Symbol] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        for sym in self {
+            sym.id().encode(out);
+        }
+    }
+}
+
+impl Encode for Vec<Symbol> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self[..].encode(out);
+    }
+}
+
+impl Stored for Vec<Symbol> {
+    fn decode(src: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        let len = u32::decode(src)?;
+        (0..len)
+            .map(|_| u32::decode(src).map(Symbol::new))
+            .collect()
+    }
+}
+
+impl Encode for fattr3 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        // serializing into memory can not fail
+        let _ = self.serialize(out);
+    }
+}
+
+impl Stored for fattr3 {
+    fn decode(src: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        let mut attr = fattr3::default();
+        attr.deserialize(src)?;
+        Ok(attr)
+    }
+}
+
+impl Encode for FSEntry {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.name.encode(out);
+        self.ino.encode(out);
+        (self.links.len() as u32).encode(out);
+        for link in self.links.iter() {
+            link.encode(out);
+        }
+        self.fsmeta.encode(out);
+        self.children_meta.encode(out);
+        match &self.children {
+            Some(children) => {
+                (children.len() as u64 + 1).encode(out);
+                for child in children {
+                    child.encode(out);
+                }
+            }
+            None => 0u64.encode(out),
+        }
+    }
+}
+
+impl Stored for FSEntry {
+    fn decode(src: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        let name = Vec::decode(src)?;
+        let ino = u64::decode(src)?;
+        let links = (0..u32::decode(src)?)
+            .map(|_| Vec::decode(src))
+            .collect::<io::Result<_>>()?;
+        let fsmeta = fattr3::decode(src)?;
+        let children_meta = fattr3::decode(src)?;
+        let children = match u64::decode(src)? {
+            0 => None,
+            len => Some(
+                (1..len)
+                    .map(|_| u64::decode(src))
+                    .collect::<io::Result<_>>()?,
+            ),
+        };
+        Ok(FSEntry {
+            name,
+            ino,
+            links,
+            fsmeta,
+            children_meta,
+            children,
+        })
+    }
+}
+
+/// A map kept in memory, or with a store set, partly in memory and the
+/// rest in the store. Entries in memory take precedence over what the
+/// store holds for them.
+#[derive(Debug)]
+pub(crate) struct CachedMap<K, V> {
+    /// What the keys of this map start with in the store
+    tag: u8,
+    /// The entries in memory, with when they were last used
+    hot: HashMap<K, (V, u64)>,
+    /// Counts the uses of entries
+    tick: u64,
+    /// Entries in memory and in the store
+    len: usize,
+    store: Option<Arc<dyn MetadataStore>>,
+    /// Entries kept in memory at most, with a store
+    capacity: usize,
+}
+
+impl<K, V> CachedMap<K, V>
+where
+    K: Hash + Eq + Clone + Stored,
+    V: Clone + Stored,
+{
+    pub(crate) fn new(tag: u8) -> Self {
+        CachedMap {
+            tag,
+            hot: HashMap::new(),
+            tick: 0,
+            len: 0,
+            store: None,
+            capacity: usize::MAX,
+        }
+    }
+
+    /// Keeps all but the `capacity` entries used last in `store` from now
+    /// on
+    pub(crate) fn set_store(&mut self, store: Arc<dyn MetadataStore>, capacity: usize) {
+        self.store = Some(store);
+        self.capacity = capacity.max(1);
+        self.evict();
+    }
+
+    fn key<Q: Encode + ?Sized>(&self, key: &Q) -> Vec<u8> {
+        let mut bytes = vec![self.tag];
+        key.encode(&mut bytes);
+        bytes
+    }
+
+    /// The entry of `key` in the store
+    fn load<Q: Encode + ?Sized>(&self, key: &Q) -> Option<V> {
+        let store = self.store.as_ref()?;
+        match store.get(&self.key(key)) {
+            Ok(Some(bytes)) => match V::decode(&mut Cursor::new(&bytes[..])) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    warn!("Unreadable metadata in the store: {:?}", e);
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Unable to read metadata from the store: {:?}", e);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<Cow<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Encode + ?Sized,
+    {
+        match self.hot.get(key) {
+            Some((value, _)) => Some(Cow::Borrowed(value)),
+            None => self.load(key).map(Cow::Owned),
+        }
+    }
+
+    /// The entry of `key`, brought into memory if it is only in the store
+    pub(crate) fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Encode + ToOwned<Owned = K> + ?Sized,
+    {
+        self.tick += 1;
+        if !self.hot.contains_key(key) {
+            let value = self.load(key)?;
+            self.evict();
+            self.hot.insert(key.to_owned(), (value, self.tick));
+        }
+        let (value, used) = self.hot.get_mut(key)?;
+        *used = self.tick;
+        Some(value)
+    }
+
+    /// Brings the entry of `key` into memory, as it is about to be used
+    pub(crate) fn touch<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Encode + ToOwned<Owned = K> + ?Sized,
+    {
+        self.get_mut(key);
+    }
+
+    pub(crate) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Encode + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub(crate) fn insert(&mut self, key: K, value: V) {
+        if !self.contains_key(&key) {
+            self.len += 1;
+        }
+        self.tick += 1;
+        if !self.hot.contains_key(&key) {
+            self.evict();
+        }
+        self.hot.insert(key, (value, self.tick));
+    }
+
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Encode + ?Sized,
+    {
+        let removed = match self.hot.remove(key) {
+            Some((value, _)) => Some(value),
+            None => self.load(key),
+        };
+        if let Some(store) = self.store.as_ref() {
+            if let Err(e) = store.remove(&self.key(key)) {
+                warn!("Unable to remove metadata from the store: {:?}", e);
+            }
+        }
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Entries in memory and in the store
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether entries are kept in a store
+    pub(crate) fn has_store(&self) -> bool {
+        self.store.is_some()
+    }
+
+    /// Replaces every entry with what `f` makes of it. Only for a map
+    /// without a store, which has every entry in memory.
+    pub(crate) fn rewrite(&mut self, mut f: impl FnMut(K, V) -> (K, V)) {
+        debug_assert!(self.store.is_none());
+        self.hot = std::mem::take(&mut self.hot)
+            .into_iter()
+            .map(|(key, (value, used))| {
+                let (key, value) = f(key, value);
+                (key, (value, used))
+            })
+            .collect();
+    }
+
+    /// Writes the entries used longest ago to the store once memory is
+    /// full, leaving room for a quarter more
+    fn evict(&mut self) {
+        let Some(store) = self.store.clone() else {
+            return;
+        };
+        if self.hot.len() < self.capacity {
+            return;
+        }
+        let keep = self.capacity - self.capacity / 4;
+        let mut used: Vec<(u64, K)> = self
+            .hot
+            .iter()
+            .map(|(key, (_, used))| (*used, key.clone()))
+            .collect();
+        used.sort_unstable_by_key(|(used, _)| *used);
+        let evicted: Vec<K> = used
+            .into_iter()
+            .take(self.hot.len().saturating_sub(keep))
+            .map(|(_, key)| key)
+            .collect();
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = evicted
+            .iter()
+            .filter_map(|key| {
+                let (value, _) = self.hot.get(key)?;
+                let mut bytes = Vec::new();
+                value.encode(&mut bytes);
+                Some((self.key(key), bytes))
+            })
+            .collect();
+        // entries stay in memory unless the store has them
+        if let Err(e) = store.put_all(&entries) {
+            warn!("Unable to write metadata to the store: {:?}", e);
+            return;
+        }
+        for key in evicted {
+            self.hot.remove(&key);
+        }
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use sled_metadata::SledMetadata;
+
+#[cfg(feature = "sled")]
+mod sled_metadata {
+    use std::io;
+    use std::path::Path;
+
+    use super::MetadataStore;
+
+    /// Keeps entries in a sled database
+    #[derive(Debug)]
+    pub struct SledMetadata {
+        db: sled::Db,
+    }
+
+    impl SledMetadata {
+        pub fn open(path: &Path) -> sled::Result<SledMetadata> {
+            let db = sled::open(path)?;
+            db.clear()?;
+            Ok(SledMetadata { db })
+        }
+    }
+
+    impl MetadataStore for SledMetadata {
+        fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+            let value = self.db.get(key).map_err(io::Error::other)?;
+            Ok(value.map(|value| value.to_vec()))
+        }
+
+        fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+            self.db.insert(key, value).map_err(io::Error::other)?;
+            Ok(())
+        }
+
+        fn remove(&self, key: &[u8]) -> io::Result<()> {
+            self.db.remove(key).map_err(io::Error::other)?;
+            Ok(())
+        }
+
+        fn put_all(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> io::Result<()> {
+            let mut batch = sled::Batch::default();
+            for (key, value) in entries {
+                batch.insert(&key[..], &value[..]);
+            }
+            self.db.apply_batch(batch).map_err(io::Error::other)
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite_metadata::SqliteMetadata;
+
+#[cfg(feature = "sqlite")]
+mod sqlite_metadata {
+    use std::io;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use rusqlite::{Connection, OptionalExtension};
+
+    use super::MetadataStore;
+
+    // nothing kept is needed after a crash, so nothing is synced
+    const SCHEMA: &str = "\
+        PRAGMA journal_mode = OFF;
+        PRAGMA synchronous = OFF;
+        DROP TABLE IF EXISTS metadata;
+        CREATE TABLE metadata (key BLOB PRIMARY KEY, value BLOB NOT NULL) WITHOUT ROWID;";
+
+    /// Keeps entries in an SQLite file
+    #[derive(Debug)]
+    pub struct SqliteMetadata {
+        conn: Mutex<Connection>,
+    }
+
+    impl SqliteMetadata {
+        pub fn open(path: &Path) -> rusqlite::Result<SqliteMetadata> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).ok();
+            }
+            let conn = Connection::open(path)?;
+            conn.execute_batch(SCHEMA)?;
+            Ok(SqliteMetadata {
+                conn: Mutex::new(conn),
+            })
+        }
+
+        fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+            self.conn.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    impl MetadataStore for SqliteMetadata {
+        fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+            self.conn()
+                .prepare_cached("SELECT value FROM metadata WHERE key = ?1")
+                .and_then(|mut query| query.query_row([key], |row| row.get(0)).optional())
+                .map_err(io::Error::other)
+        }
+
+        fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+            self.conn()
+                .prepare_cached("INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)")
+                .and_then(|mut insert| insert.execute((key, value)))
+                .map(|_| ())
+                .map_err(io::Error::other)
+        }
+
+        fn remove(&self, key: &[u8]) -> io::Result<()> {
+            self.conn()
+                .prepare_cached("DELETE FROM metadata WHERE key = ?1")
+                .and_then(|mut delete| delete.execute([key]))
+                .map(|_| ())
+                .map_err(io::Error::other)
+        }
+
+        fn put_all(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> io::Result<()> {
+            let mut conn = self.conn();
+            let tx = conn.transaction().map_err(io::Error::other)?;
+            {
+                let mut insert = tx
+                    .prepare_cached("INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)")
+                    .map_err(io::Error::other)?;
+                for (key, value) in entries {
+                    insert.execute((key, value)).map_err(io::Error::other)?;
+                }
+            }
+            tx.commit().map_err(io::Error::other)
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! The hall of mirrors of the identity path.
+//!
+//! Once a player reaches the identity stage, `identity/mirrors` opens: a
+//! hall of mirrors, each a symlink to another. It is laid out from a seed
+//! drawn when it first opens. One way through, never passing a mirror
+//! twice, ends at the player's own face. Every other mirror leads into a
+//! circle of mirrors showing one another. A client following those would
+//! go round until it gives up, so a mirror which leads back to itself
+//! reads as leading to the end of the hall instead, where the player
+//! learns they walked in a circle.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use tracing::{debug, warn};
+
+use crate::philosophy::Philosophy;
+
+/// The stage the hall is in
+const IDENTITY_DIR: &str = "identity";
+
+/// The hall, within the identity stage
+const MIRRORS_DIR: &str = "identity/mirrors";
+
+/// The key of the seed of the hall in the game state
+const SEED_KEY: &str = "mirrors_seed";
+
+/// The mirrors of the hall, by name
+const MIRRORS: &[&str] = &[
+    "the_child",
+    "the_youth",
+    "the_elder",
+    "the_stranger",
+    "the_mask",
+    "the_shadow",
+    "the_river",
+    "the_ship",
+    "the_name",
+    "the_memory",
+    "the_dream",
+    "the_twin",
+];
+
+/// How many mirrors the way to the player's face passes
+const WAY_LEN: usize = 5;
+
+/// How many mirrors show one another in a circle, at most
+const CIRCLE_LEN: usize = 3;
+
+/// Links followed at most to tell whether a mirror leads back to itself
+const MAX_HOPS: usize = 40;
+
+/// The file the way through the hall ends at
+const FACE_FILE: &str = "your_face.txt";
+
+/// The file a mirror leading back to itself reads as leading to
+const ENDLESS_FILE: &str = "endless_reflection.txt";
+
+/// What every mirror of the hall is a link to, laid out from `seed`: the
+/// way to the face first, then circles of the other mirrors, and the
+/// rest of them leading into those circles
+fn hall(seed: u64) -> Vec<(&'static str, &'static str)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut mirrors = MIRRORS.to_vec();
+    mirrors.shuffle(&mut rng);
+    let (way, astray) = mirrors.split_at(WAY_LEN);
+    let mut links: Vec<(&str, &str)> = way
+        .iter()
+        .zip(way[1..].iter().chain([&FACE_FILE]))
+        .map(|(mirror, ahead)| (*mirror, *ahead))
+        .collect();
+    let (circles, strays) = astray.split_at(astray.len() / 2);
+    for circle in circles.chunks(CIRCLE_LEN) {
+        for (i, mirror) in circle.iter().enumerate() {
+            links.push((*mirror, circle[(i + 1) % circle.len()]));
+        }
+    }
+    // a stray leads into a circle, or to a stray before it, so that it
+    // never finds the way to the face
+    for (i, mirror) in strays.iter().enumerate() {
+        let ahead = match rng.gen_range(0..circles.len() + i) {
+            n if n < circles.len() => circles[n],
+            n => strays[n - circles.len()],
+        };
+        links.push((*mirror, ahead));
+    }
+    links
+}
+
+impl Philosophy {
+    /// Whether `path` is in the hall and the player making the call has
+    /// not reached it yet
+    pub(crate) fn mirrors_hidden(&self, path: &Path) -> bool {
+        path.starts_with(MIRRORS_DIR) && !self.has_reached(IDENTITY_DIR)
+    }
+
+    /// The seed the hall is laid out from, drawn as it first opens
+    fn mirrors_seed(&mut self) -> u64 {
+        if let Some(seed) = self
+            .game_state
+            .get(SEED_KEY)
+            .and_then(|seed| seed.parse().ok())
+        {
+            return seed;
+        }
+        let seed: u64 = self.rng.gen();
+        self.game_state
+            .insert(SEED_KEY.to_string(), seed.to_string());
+        seed
+    }
+
+    /// Lays out the hall on the host as the player walks up to it, in the
+    /// identity stage or the hall itself, unless it already is
+    pub(crate) fn enter_mirrors(&mut self, path: &Path) {
+        if path != Path::new(IDENTITY_DIR) && !path.starts_with(MIRRORS_DIR) {
+            return;
+        }
+        if !self.has_reached(IDENTITY_DIR) {
+            return;
+        }
+        let seed = self.mirrors_seed();
+        if let Err(e) = self.hang_mirrors(seed) {
+            warn!("Unable to lay out the hall of mirrors: {:?}", e);
+        }
+    }
+
+    /// Hangs the mirrors of the hall laid out from `seed` on the host,
+    /// with the files they lead to
+    fn hang_mirrors(&self, seed: u64) -> std::io::Result<()> {
+        let dir = self.root.join(MIRRORS_DIR);
+        std::fs::create_dir_all(&dir)?;
+        let readme = dir.join("README.txt");
+        if !readme.exists() {
+            std::fs::write(
+                readme,
+                "The Hall of Mirrors\n\
+                 ===================\n\
+                 Every mirror here shows another. Only one way through the hall\n\
+                 ends at your own face, the others turn back on themselves.\n\
+                 Look into the mirrors, and at what each of them shows.\n",
+            )?;
+        }
+        let face = dir.join(FACE_FILE);
+        if !face.exists() {
+            let asked = self
+                .world
+                .stage(IDENTITY_DIR)
+                .map(|stage| stage.def.question.clone())
+                .unwrap_or_default();
+            std::fs::write(
+                face,
+                format!(
+                    "Your Face\n\
+                     =========\n\
+                     Every mirror you passed showed you otherwise, and still it was\n\
+                     you who walked through them.\n\
+                     \n\
+                     {}\n\
+                     \n\
+                     Answer it in {}/{}.\n",
+                    asked,
+                    IDENTITY_DIR,
+                    self.answer_file()
+                ),
+            )?;
+        }
+        let endless = dir.join(ENDLESS_FILE);
+        if !endless.exists() {
+            std::fs::write(
+                endless,
+                "An Endless Reflection\n\
+                 =====================\n\
+                 The mirror shows a mirror showing a mirror, and somewhere\n\
+                 behind them all the one you started from. You walked in a\n\
+                 circle. Go back, and look into another mirror.\n",
+            )?;
+        }
+        for (mirror, ahead) in hall(seed) {
+            let link = dir.join(mirror);
+            if link.symlink_metadata().is_err() {
+                std::os::unix::fs::symlink(ahead, link)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the symlink at `path` leads back to itself on the host,
+    /// however many links it takes
+    fn leads_back(&self, path: &Path) -> bool {
+        let start = self.root.join(path);
+        let mut at = start.clone();
+        let mut passed = HashSet::new();
+        for _ in 0..MAX_HOPS {
+            let Some(ahead) = at
+                .read_link()
+                .ok()
+                .and_then(|target| Some(at.parent()?.join(target)))
+            else {
+                return false;
+            };
+            if ahead == start {
+                return true;
+            }
+            // a circle which does not pass `path` is one it leads into
+            if !passed.insert(ahead.clone()) {
+                return false;
+            }
+            at = ahead;
+        }
+        false
+    }
+
+    /// What the mirror at `path` reads as leading to, in place of its
+    /// target on the host, if it leads back to itself
+    pub(crate) fn reflection(&self, path: &Path) -> Option<PathBuf> {
+        if path.parent()? != Path::new(MIRRORS_DIR) || !self.leads_back(path) {
+            return None;
+        }
+        Some(PathBuf::from(ENDLESS_FILE))
+    }
+
+    /// Marks the hall solved for the player making the call, if `path` is
+    /// the face the way through it ends at
+    pub(crate) fn look_into_mirrors(&mut self, path: &Path) {
+        if path != Path::new(MIRRORS_DIR).join(FACE_FILE) {
+            return;
+        }
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        if player
+            .philosophical_state
+            .solved_puzzles
+            .insert("mirrors".to_string())
+        {
+            debug!("The way through the hall of mirrors was found");
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Contents kept once, under `.eternal/objects/`.
+//!
+//! The earlier versions of answers (see `history.rs`) and of files (see
+//! `versions.rs`), and the copies snapshots take (see `snapshots.rs`),
+//! mostly hold what was kept before: an answer written back as it was, a
+//! file no one changed between two snapshots. Whatever they keep is
+//! stored once in the object store, named after its SHA-256, and the files
+//! keeping it are hard links to it. They are read like any other file, and
+//! take no room of their own.
+//!
+//! A file linked with the store which a client changes in place through
+//! `.eternal/` is copied apart first, see [`FSMap::unshare`](crate::FSMap). An object is removed
+//! with the last file keeping it, and those left behind by files removed
+//! otherwise as the server starts.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::STATE_DIR;
+
+/// Where objects are kept, within the state directory
+const OBJECT_STORE: &str = "objects";
+
+/// How files written to the store before they are named start
+const PARTIAL: &str = "partial.";
+
+/// Tells the partial files of the store apart
+static NEXT_PARTIAL: AtomicU64 = AtomicU64::new(0);
+
+fn object_store(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join(OBJECT_STORE)
+}
+
+/// The path of the object of the world at `root` with the hash `digest`
+fn object_path(root: &Path, digest: &[u8]) -> PathBuf {
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    object_store(root).join(&hex[..2]).join(&hex[2..])
+}
+
+/// A file to write what is kept to, named once it is all written
+fn partial(root: &Path) -> io::Result<(PathBuf, File)> {
+    let store = object_store(root);
+    std::fs::create_dir_all(&store)?;
+    let path = store.join(format!(
+        "{}{}.{}",
+        PARTIAL,
+        std::process::id(),
+        NEXT_PARTIAL.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = File::create(&path)?;
+    Ok((path, file))
+}
+
+/// Makes the partial file `written` the object `object`, unless there is
+/// one already, and links `to` to it. A new object is given `mtime`.
+fn place(written: &Path, object: &Path, to: &Path, mtime: Option<SystemTime>) -> io::Result<()> {
+    if object.exists() {
+        std::fs::remove_file(written)?;
+    } else {
+        std::fs::create_dir_all(object.parent().unwrap())?;
+        std::fs::rename(written, object)?;
+        if let Some(mtime) = mtime {
+            File::options()
+                .write(true)
+                .open(object)?
+                .set_modified(mtime)?;
+        }
+    }
+    if let Some(dir) = to.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // a copy of its own if the object has as many links as the host
+    // allows, or the host has none
+    std::fs::hard_link(object, to).or_else(|_| std::fs::copy(object, to).map(|_| ()))
+}
+
+/// Keeps `contents` as the file `to` of the world at `root`, which must
+/// not exist yet. The object is given `mtime` if it is new.
+pub(crate) fn keep(
+    root: &Path,
+    contents: &[u8],
+    to: &Path,
+    mtime: Option<SystemTime>,
+) -> io::Result<()> {
+    let (written, mut file) = partial(root)?;
+    let placed = file.write_all(contents).and_then(|_| {
+        drop(file);
+        place(
+            &written,
+            &object_path(root, &Sha256::digest(contents)),
+            to,
+            mtime,
+        )
+    });
+    if placed.is_err() {
+        let _ = std::fs::remove_file(&written);
+    }
+    placed
+}
+
+/// Keeps what the file `from` holds as the file `to` of the world at
+/// `root`, which must not exist yet. The object is given `mtime` if it is
+/// new.
+pub(crate) fn keep_file(
+    root: &Path,
+    from: &Path,
+    to: &Path,
+    mtime: Option<SystemTime>,
+) -> io::Result<()> {
+    let (written, mut file) = partial(root)?;
+    let placed = (|| {
+        let mut source = File::open(from)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = source.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            file.write_all(&buf[..read])?;
+        }
+        drop(file);
+        place(&written, &object_path(root, &hasher.finalize()), to, mtime)
+    })();
+    if placed.is_err() {
+        let _ = std::fs::remove_file(&written);
+    }
+    placed
+}
+
+/// The object of the world at `root` the file at `path`, with `meta`, is
+/// a link to, if it is one
+pub(crate) fn object_of(root: &Path, path: &Path, meta: &std::fs::Metadata) -> Option<PathBuf> {
+    if !meta.is_file() || meta.nlink() < 2 {
+        return None;
+    }
+    let mut source = File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut source, &mut hasher).ok()?;
+    let object = object_path(root, &hasher.finalize());
+    let linked = object.symlink_metadata().ok()?;
+    (linked.dev() == meta.dev() && linked.ino() == meta.ino()).then_some(object)
+}
+
+/// Removes the kept file at `path` of the world at `root`, and its object
+/// if no other file keeps it
+pub(crate) fn release(root: &Path, path: &Path) -> io::Result<()> {
+    let meta = path.symlink_metadata()?;
+    let object = object_of(root, path, &meta);
+    std::fs::remove_file(path)?;
+    if let Some(object) = object {
+        if object
+            .symlink_metadata()
+            .is_ok_and(|meta| meta.nlink() == 1)
+        {
+            debug!("Removed the object {:?}, kept no longer", object);
+            std::fs::remove_file(&object)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the directory `dir` of the world at `root` with the files kept
+/// in it, and the objects no other file keeps
+pub(crate) fn release_dir(root: &Path, dir: &Path) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            release_dir(root, &entry.path())?;
+        } else {
+            release(root, &entry.path())?;
+        }
+    }
+    std::fs::remove_dir(dir)
+}
+
+/// Removes the objects of the world at `root` no file keeps any longer,
+/// and what was left partially written
+pub(crate) fn sweep(root: &Path) {
+    let Ok(entries) = std::fs::read_dir(object_store(root)) else {
+        return;
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with(PARTIAL) {
+            let _ = std::fs::remove_file(&path);
+            continue;
+        }
+        for object in std::fs::read_dir(&path).into_iter().flatten().flatten() {
+            let unkept = object.metadata().is_ok_and(|meta| meta.nlink() == 1);
+            if unkept && std::fs::remove_file(object.path()).is_ok() {
+                removed += 1;
+            }
+        }
+        let _ = std::fs::remove_dir(&path);
+    }
+    if removed > 0 {
+        debug!("Removed {} objects kept no longer", removed);
+    }
+}
@@ -0,0 +1,177 @@
+//! The observer effect, on the question of the perception path.
+//!
+//! With `--observer-effect`, or `observer_effect = true` under `[game]`,
+//! `perception/question.txt` is served from memory, and reading it
+//! changes it: once a player read it to the end, the next read shows the
+//! question put anew, with some of its words swapped for others meaning
+//! the same and its clauses in another order. What it asks stays the
+//! same, and the answer is judged as it would be otherwise. Every player
+//! observes a question of their own.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use nfsserve::nfs::nfsstat3;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use regex::Regex;
+
+use crate::engine::{read_at, VirtualAttrs, VirtualFile};
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The stage whose question changes as it is observed
+const OBSERVED_STAGE: &str = "perception";
+
+/// The file of the question in the stage directory
+pub(crate) const QUESTION_FILE: &str = "question.txt";
+
+/// Words of the same meaning, any of which may stand for another
+const SYNONYMS: &[&[&str]] = &[
+    &["reality", "existence", "world"],
+    &["only", "sole", "single"],
+    &["real", "true", "genuine"],
+    &["see", "perceive", "observe"],
+    &["know", "tell", "be sure"],
+    &["think", "believe"],
+];
+
+/// Clauses which may be added to the question, before or after it
+const ASIDES: &[&str] = &[
+    "now that you look at it",
+    "as you observe it",
+    "while you are watching",
+    "seen from where you stand",
+];
+
+/// `word` with the case of its first letter as in `like`
+fn with_case_of(word: &str, like: &str) -> String {
+    match like.chars().next().is_some_and(char::is_uppercase) {
+        true => {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        false => word.to_string(),
+    }
+}
+
+/// `question` put anew: some of its words swapped for synonyms, and its
+/// clauses, with maybe an aside added, in an order drawn from `rng`
+fn rephrase(question: &str, rng: &mut impl Rng) -> String {
+    let words = Regex::new(r"[A-Za-z]+").expect("the word pattern is valid");
+    let swapped = words.replace_all(question.trim(), |found: &regex::Captures| {
+        let word = &found[0];
+        SYNONYMS
+            .iter()
+            .find(|group| group.iter().any(|w| w.eq_ignore_ascii_case(word)))
+            .and_then(|group| group.choose(rng))
+            .map_or_else(|| word.to_string(), |synonym| with_case_of(synonym, word))
+    });
+    let (body, mark) = match swapped.strip_suffix(['?', '.', '!']) {
+        Some(body) => (body.to_string(), &swapped[swapped.len() - 1..]),
+        None => (swapped.to_string(), ""),
+    };
+    let mut clauses: Vec<String> = body.split(", ").map(str::to_string).collect();
+    if rng.gen_bool(0.5) {
+        if let Some(aside) = ASIDES.choose(rng) {
+            clauses.push(aside.to_string());
+        }
+    }
+    clauses.shuffle(rng);
+    let clauses: Vec<String> = clauses
+        .iter()
+        .enumerate()
+        .map(|(i, clause)| match i {
+            0 => with_case_of(clause, "A"),
+            _ if clause.starts_with("I ") => clause.clone(),
+            _ => {
+                let mut chars = clause.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_lowercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+        })
+        .collect();
+    format!("{}{}", clauses.join(", "), mark)
+}
+
+impl Philosophy {
+    /// Whether the question of the perception path changes as it is read
+    fn observer_effect(&self) -> bool {
+        self.config.observer_effect.unwrap_or(false)
+    }
+
+    /// The question files served from memory, so that they can change
+    pub(crate) fn observed_question_paths(&self) -> Vec<PathBuf> {
+        if !self.observer_effect() || self.world.stage(OBSERVED_STAGE).is_none() {
+            return Vec::new();
+        }
+        vec![Path::new(OBSERVED_STAGE).join(QUESTION_FILE)]
+    }
+
+    /// The question file at `path`, as the player making the call observes
+    /// it
+    pub(crate) fn open_observed_question(
+        &mut self,
+        path: &Path,
+    ) -> Option<Box<dyn VirtualFile + '_>> {
+        if path != Path::new(OBSERVED_STAGE).join(QUESTION_FILE) {
+            return None;
+        }
+        let question = self.world.stage(OBSERVED_STAGE)?.def.question.clone();
+        Some(Box::new(ObservedQuestion {
+            game: self,
+            question,
+        }))
+    }
+}
+
+/// The question of the perception path, as the player making the call
+/// observes it
+struct ObservedQuestion<'a> {
+    game: &'a mut Philosophy,
+    /// The question as the stage asks it
+    question: String,
+}
+
+impl ObservedQuestion<'_> {
+    fn contents(&self) -> String {
+        let newcomer = PlayerState::default();
+        let observed = self
+            .game
+            .players
+            .get(&self.game.player_identity.current())
+            .unwrap_or(&newcomer)
+            .observed_question
+            .clone();
+        format!("{}\n", observed.unwrap_or_else(|| self.question.clone()))
+    }
+}
+
+impl VirtualFile for ObservedQuestion<'_> {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let (data, eof) = read_at(self.contents().as_bytes(), offset, count);
+        if eof {
+            // observed to the end, the question is no longer what it was
+            let rephrased = rephrase(&self.question, &mut self.game.rng);
+            let id = self.game.player_identity.current();
+            self.game.players.entry(id).or_default().observed_question = Some(rephrased);
+        }
+        Ok((data, eof))
+    }
+
+    fn size(&self) -> u64 {
+        self.contents().len() as u64
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o444,
+            mtime: SystemTime::now(),
+        }
+    }
+}
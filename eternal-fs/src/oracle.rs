@@ -0,0 +1,147 @@
+//! The oracle, which answers what it is asked in `oracle.txt`.
+//!
+//! A player writes a question to the oracle and reads the answer back from
+//! the same file. The oracle answers with a saying on what the question is
+//! about, and with the player's own words from an earlier answer.
+
+use rand::seq::SliceRandom;
+use tracing::debug;
+
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The file the oracle is consulted in
+pub(crate) const ORACLE_FILE: &str = "oracle.txt";
+
+/// What the oracle says, by what a question is about. The words of a
+/// question are looked for among the themes, and the last theme is for
+/// questions about anything else.
+const SAYINGS: &[(&[&str], &[&str])] = &[
+    (
+        &["truth", "true", "false", "real", "lie"],
+        &[
+            "What is true does not need you to believe it, yet it waits for you all the same.",
+            "The truth you seek is folded inside the question you did not ask.",
+        ],
+    ),
+    (
+        &["time", "future", "past", "when", "tomorrow"],
+        &[
+            "The river does not ask when it will reach the sea.",
+            "What was and what will be meet only in you, and only now.",
+        ],
+    ),
+    (
+        &["love", "heart", "friend", "alone", "lonely"],
+        &[
+            "The heart is answered by the heart, never by the oracle.",
+            "Whoever you are waiting for is waiting for you to arrive first.",
+        ],
+    ),
+    (
+        &["death", "die", "end", "ending"],
+        &[
+            "Every door that closes is also a wall that was never there.",
+            "Endings are beginnings that have not yet turned around.",
+        ],
+    ),
+    (
+        &["who", "self", "myself", "identity", "become"],
+        &[
+            "You are the question, asked again each morning.",
+            "The one who asks is already changing into the one who knows.",
+        ],
+    ),
+    (
+        &[],
+        &[
+            "The answer is near, nearer than the question.",
+            "Look again at what you already know.",
+            "Not every question is for answering. Some are for carrying.",
+        ],
+    ),
+];
+
+const INVITATION: &str = "\
+    The Oracle\n\
+    ==========\n\
+    Write your question to this file, then read it again for the answer.\n";
+
+impl PlayerState {
+    /// Something the player wrote before that shares a word with
+    /// `question`, or anything they wrote if none does
+    fn own_words(&self, question: &str, rng: &mut impl rand::Rng) -> Option<String> {
+        let sentences: Vec<&str> = self
+            .answer_archive
+            .values()
+            .flatten()
+            .flat_map(|answer| answer.text.split(['.', '!', '?', '\n']))
+            .map(str::trim)
+            .filter(|sentence| sentence.split_whitespace().count() >= 3)
+            .collect();
+        let asked: Vec<String> = question
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|word| word.len() > 3)
+            .map(str::to_lowercase)
+            .collect();
+        let related: Vec<&str> = sentences
+            .iter()
+            .filter(|sentence| {
+                let sentence = sentence.to_lowercase();
+                asked.iter().any(|word| sentence.contains(word.as_str()))
+            })
+            .copied()
+            .collect();
+        let pool = if related.is_empty() {
+            &sentences
+        } else {
+            &related
+        };
+        pool.choose(rng).map(|sentence| sentence.to_string())
+    }
+}
+
+impl Philosophy {
+    /// Answers `question` for the player making the call
+    pub(crate) fn consult_oracle(&mut self, question: &str) {
+        let question = question.trim();
+        if question.is_empty() {
+            return;
+        }
+        let lowered = question.to_lowercase();
+        let words: Vec<&str> = lowered.split(|c: char| !c.is_alphabetic()).collect();
+        let (_, sayings) = SAYINGS
+            .iter()
+            .find(|(theme, _)| theme.is_empty() || theme.iter().any(|t| words.contains(t)))
+            .unwrap_or(&SAYINGS[SAYINGS.len() - 1]);
+        let saying = sayings.choose(&mut self.rng).unwrap_or(&sayings[0]);
+
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        let echo = match player.own_words(question, &mut self.rng) {
+            Some(words) => format!("You once wrote: \"{}\". Listen to yourself.\n", words),
+            None => "You have not yet spoken on the paths. The oracle waits for your words.\n"
+                .to_string(),
+        };
+        let answer = format!(
+            "The Oracle\n\
+             ==========\n\
+             You asked: {}\n\
+             \n\
+             {}\n\
+             \n\
+             {}",
+            question, saying, echo
+        );
+        debug!("The oracle was asked {:?}", question);
+        player.oracle = Some(answer);
+    }
+
+    /// What oracle.txt shows the player making the call
+    pub(crate) fn oracle(&self) -> String {
+        self.players
+            .get(&self.player_identity.current())
+            .and_then(|player| player.oracle.clone())
+            .unwrap_or_else(|| INVITATION.to_string())
+    }
+}
@@ -0,0 +1,662 @@
+//! Directories laid over a pristine world.
+//!
+//! With `--lower PRISTINE` (or `lower` under `[server]`) the directory
+//! served, and every directory exported, is the upper layer of an overlay:
+//! what it holds is laid over `PRISTINE`, which is only ever read, so that
+//! one world can be handed to many players without a copy for each. A file
+//! of the lower layer is copied up as it is first changed. Whatever of the
+//! lower layer is removed is hidden by a whiteout, an empty `.wh.NAME`
+//! beside where it was in the upper layer, and a directory made anew where
+//! one was removed holds `.wh..wh..opq`, which hides what the lower layer
+//! has in it. Neither is ever shown.
+//!
+//! As with overlayfs, a directory of the lower layer can not be renamed,
+//! `NFS3ERR_XDEV` has `mv` copy it instead. The game is not played in an
+//! overlay: what it serves is the world as it was laid out, and what the
+//! players make of it.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs::Metadata;
+use std::io::SeekFrom;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::debug;
+
+use nfsserve::fs_util::{create_setattr, io_error_to_nfsstat3, metadata_to_fattr3, path_setattr};
+use nfsserve::nfs::*;
+use nfsserve::vfs::{DirEntry, NFSFileSystem, ReadDirResult, VFSCapabilities};
+
+use crate::xattrs::paginate;
+
+/// What a whiteout is named after the name it hides
+const WHITEOUT_PREFIX: &[u8] = b".wh.";
+
+/// Hides what the lower layer has in the directory of the upper one which
+/// holds it
+const OPAQUE: &str = ".wh..wh..opq";
+
+/// The fileid of the root
+const ROOT: fileid3 = 1;
+
+/// The whiteout hiding `name`
+fn whiteout(name: &OsStr) -> OsString {
+    let mut whiteout = OsString::from(OsStr::from_bytes(WHITEOUT_PREFIX));
+    whiteout.push(name);
+    whiteout
+}
+
+/// `filename` as a name of the overlay. Whiteouts can neither be seen nor
+/// made.
+fn overlay_name(filename: &filename3) -> Result<&OsStr, nfsstat3> {
+    match &filename[..] {
+        b"" | b"." | b".." => Err(nfsstat3::NFS3ERR_INVAL),
+        name if name.contains(&b'/') => Err(nfsstat3::NFS3ERR_INVAL),
+        name if name.starts_with(WHITEOUT_PREFIX) => Err(nfsstat3::NFS3ERR_ACCES),
+        name => Ok(OsStr::from_bytes(name)),
+    }
+}
+
+fn exists(path: &Path) -> bool {
+    path.symlink_metadata().is_ok()
+}
+
+/// Where an object of the overlay is on the host
+struct Found {
+    /// Whether it is in the upper layer, and so can be changed in place
+    upper: bool,
+    host: PathBuf,
+    meta: Metadata,
+    /// The directory of the lower layer which shows through it, if it is a
+    /// directory
+    lower_dir: Option<PathBuf>,
+}
+
+/// The fileids handed out, by path within the overlay, and back
+#[derive(Debug)]
+struct Paths {
+    ids: HashMap<PathBuf, fileid3>,
+    paths: HashMap<fileid3, PathBuf>,
+    next: fileid3,
+}
+
+impl Paths {
+    fn new() -> Paths {
+        Paths {
+            ids: HashMap::from([(PathBuf::new(), ROOT)]),
+            paths: HashMap::from([(ROOT, PathBuf::new())]),
+            next: ROOT,
+        }
+    }
+
+    fn id(&mut self, path: &Path) -> fileid3 {
+        if let Some(id) = self.ids.get(path) {
+            return *id;
+        }
+        self.next += 1;
+        self.ids.insert(path.to_path_buf(), self.next);
+        self.paths.insert(self.next, path.to_path_buf());
+        self.next
+    }
+
+    fn path(&self, id: fileid3) -> Result<PathBuf, nfsstat3> {
+        self.paths.get(&id).cloned().ok_or(nfsstat3::NFS3ERR_STALE)
+    }
+
+    /// Forgets `path` and everything below it
+    fn forget(&mut self, path: &Path) {
+        let gone: Vec<PathBuf> = self
+            .ids
+            .keys()
+            .filter(|p| p.starts_with(path))
+            .cloned()
+            .collect();
+        for p in gone {
+            if let Some(id) = self.ids.remove(&p) {
+                self.paths.remove(&id);
+            }
+        }
+    }
+
+    /// Keeps the fileids of `from` and everything below it for where they
+    /// were moved to
+    fn moved(&mut self, from: &Path, to: &Path) {
+        self.forget(to);
+        let moved: Vec<(PathBuf, fileid3)> = self
+            .ids
+            .iter()
+            .filter(|(p, _)| p.starts_with(from))
+            .map(|(p, id)| (p.clone(), *id))
+            .collect();
+        for (p, id) in moved {
+            self.ids.remove(&p);
+            let rest = p.strip_prefix(from).expect("moved paths are below from");
+            let path = match rest.as_os_str().is_empty() {
+                true => to.to_path_buf(),
+                false => to.join(rest),
+            };
+            self.ids.insert(path.clone(), id);
+            self.paths.insert(id, path);
+        }
+    }
+}
+
+/// Serves a directory laid over a pristine one as a file system
+#[derive(Debug, Clone)]
+pub struct OverlayFS {
+    lower: PathBuf,
+    upper: PathBuf,
+    paths: Arc<Mutex<Paths>>,
+    read_only: bool,
+}
+
+impl OverlayFS {
+    /// Lays `upper` over `lower`. Only `upper` is ever changed.
+    pub fn new(lower: PathBuf, upper: PathBuf) -> OverlayFS {
+        OverlayFS {
+            lower,
+            upper,
+            paths: Arc::new(Mutex::new(Paths::new())),
+            read_only: false,
+        }
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    fn path(&self, id: fileid3) -> Result<PathBuf, nfsstat3> {
+        self.paths.lock().unwrap().path(id)
+    }
+
+    fn id(&self, path: &Path) -> fileid3 {
+        self.paths.lock().unwrap().id(path)
+    }
+
+    /// Finds `rel` in the layers, the upper one first. The lower layer
+    /// shows through directories of the upper one unless it is hidden by a
+    /// whiteout or an opaque directory.
+    fn locate(&self, rel: &Path) -> Result<Found, nfsstat3> {
+        let opaque = |dir: &Path| exists(&dir.join(OPAQUE));
+        let mut upper = Some(self.upper.clone());
+        let mut lower = Some(self.lower.clone()).filter(|_| !opaque(&self.upper));
+        let names: Vec<&OsStr> = rel
+            .components()
+            .filter_map(|part| match part {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        if names.is_empty() {
+            let meta = self.upper.metadata().map_err(io_error_to_nfsstat3)?;
+            return Ok(Found {
+                upper: true,
+                host: self.upper.clone(),
+                meta,
+                lower_dir: lower,
+            });
+        }
+        for (i, name) in names.iter().enumerate() {
+            let hidden = upper
+                .as_ref()
+                .is_some_and(|dir| exists(&dir.join(whiteout(name))));
+            let up = upper.as_ref().map(|dir| dir.join(name));
+            let low = lower.as_ref().filter(|_| !hidden).map(|dir| dir.join(name));
+            let up_meta = up.as_ref().and_then(|p| p.symlink_metadata().ok());
+            let low_meta = low.as_ref().and_then(|p| p.symlink_metadata().ok());
+            let low_dir = low
+                .clone()
+                .filter(|_| low_meta.as_ref().is_some_and(|meta| meta.is_dir()));
+            let (found, meta, is_upper) = match (up, up_meta, low_meta) {
+                (Some(up), Some(meta), _) => {
+                    // a file of the upper layer, or an opaque directory,
+                    // hides the lower layer below it
+                    upper = Some(up.clone());
+                    lower = low_dir.filter(|_| meta.is_dir() && !opaque(&up));
+                    (up, meta, true)
+                }
+                (_, None, Some(meta)) => {
+                    upper = None;
+                    lower = low_dir;
+                    (low.expect("found in the lower layer"), meta, false)
+                }
+                _ => return Err(nfsstat3::NFS3ERR_NOENT),
+            };
+            if i == names.len() - 1 {
+                return Ok(Found {
+                    upper: is_upper,
+                    host: found,
+                    meta,
+                    lower_dir: lower,
+                });
+            }
+            if !meta.is_dir() {
+                return Err(nfsstat3::NFS3ERR_NOTDIR);
+            }
+        }
+        unreachable!("the last name returns")
+    }
+
+    /// The names in the directory `rel`, of both layers
+    fn names(&self, rel: &Path) -> Result<BTreeSet<OsString>, nfsstat3> {
+        let found = self.locate(rel)?;
+        if !found.meta.is_dir() {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        let mut names = BTreeSet::new();
+        let mut hidden = HashSet::new();
+        if found.upper {
+            for entry in std::fs::read_dir(&found.host).map_err(io_error_to_nfsstat3)? {
+                let name = entry.map_err(io_error_to_nfsstat3)?.file_name();
+                match name.as_bytes().strip_prefix(WHITEOUT_PREFIX) {
+                    Some(hides) => {
+                        hidden.insert(OsStr::from_bytes(hides).to_os_string());
+                    }
+                    None => {
+                        names.insert(name);
+                    }
+                }
+            }
+        }
+        if let Some(lower) = found.lower_dir.filter(|_| found.upper) {
+            for entry in std::fs::read_dir(lower).map_err(io_error_to_nfsstat3)? {
+                let name = entry.map_err(io_error_to_nfsstat3)?.file_name();
+                if !hidden.contains(&name) {
+                    names.insert(name);
+                }
+            }
+        } else if !found.upper {
+            for entry in std::fs::read_dir(&found.host).map_err(io_error_to_nfsstat3)? {
+                names.insert(entry.map_err(io_error_to_nfsstat3)?.file_name());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Copies `rel`, and the directories above it, up to the upper layer
+    /// unless it is there already, and returns where it is there
+    fn copy_up(&self, rel: &Path) -> Result<PathBuf, nfsstat3> {
+        let found = self.locate(rel)?;
+        let up = self.upper.join(rel);
+        if found.upper {
+            return Ok(up);
+        }
+        if let Some(parent) = rel.parent() {
+            self.copy_up(parent)?;
+        }
+        let copied = if found.meta.is_dir() {
+            std::fs::create_dir(&up)
+                .and_then(|_| std::fs::set_permissions(&up, found.meta.permissions()))
+        } else if found.meta.is_symlink() {
+            std::fs::read_link(&found.host)
+                .and_then(|target| std::os::unix::fs::symlink(target, &up))
+        } else {
+            std::fs::copy(&found.host, &up).map(|_| ())
+        };
+        copied.map_err(io_error_to_nfsstat3)?;
+        debug!("Copied {:?} up", rel);
+        Ok(up)
+    }
+
+    /// Makes room for a new `name` in the directory `dir`: the directory is
+    /// copied up, and a whiteout hiding `name` is removed. Returns the new
+    /// path, where it is in the upper layer, and whether a whiteout was
+    /// removed.
+    fn make_room(
+        &self,
+        dirid: fileid3,
+        name: &OsStr,
+    ) -> Result<(PathBuf, PathBuf, bool), nfsstat3> {
+        if self.read_only {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        let dir = self.path(dirid)?;
+        if !self.locate(&dir)?.meta.is_dir() {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        let rel = dir.join(name);
+        if self.locate(&rel).is_ok() {
+            return Err(nfsstat3::NFS3ERR_EXIST);
+        }
+        let updir = self.copy_up(&dir)?;
+        let whiteout = updir.join(whiteout(name));
+        let whited_out = exists(&whiteout);
+        if whited_out {
+            std::fs::remove_file(&whiteout).map_err(io_error_to_nfsstat3)?;
+        }
+        Ok((rel, updir.join(name), whited_out))
+    }
+
+    /// Removes `rel`, and hides it with a whiteout if the lower layer would
+    /// show through
+    fn remove_path(&self, rel: &Path) -> Result<(), nfsstat3> {
+        let found = self.locate(rel)?;
+        if found.meta.is_dir() && !self.names(rel)?.is_empty() {
+            return Err(nfsstat3::NFS3ERR_NOTEMPTY);
+        }
+        if found.upper {
+            let removed = match found.meta.is_dir() {
+                // all it can hold are whiteouts
+                true => std::fs::remove_dir_all(&found.host),
+                false => std::fs::remove_file(&found.host),
+            };
+            removed.map_err(io_error_to_nfsstat3)?;
+        }
+        if self.locate(rel).is_ok() {
+            let (dir, name) = match (rel.parent(), rel.file_name()) {
+                (Some(dir), Some(name)) => (dir, name),
+                _ => return Err(nfsstat3::NFS3ERR_INVAL),
+            };
+            let updir = self.copy_up(dir)?;
+            std::fs::write(updir.join(whiteout(name)), b"").map_err(io_error_to_nfsstat3)?;
+        }
+        self.paths.lock().unwrap().forget(rel);
+        Ok(())
+    }
+
+    fn attr(&self, id: fileid3, rel: &Path) -> Result<fattr3, nfsstat3> {
+        Ok(metadata_to_fattr3(id, &self.locate(rel)?.meta))
+    }
+
+    /// Runs `work` on the layers off the async runtime, as walking them and
+    /// copying up block on the host
+    async fn blocking<T: Send + 'static>(
+        &self,
+        work: impl FnOnce(&OverlayFS) -> Result<T, nfsstat3> + Send + 'static,
+    ) -> Result<T, nfsstat3> {
+        let fs = self.clone();
+        tokio::task::spawn_blocking(move || work(&fs))
+            .await
+            .map_err(|_| nfsstat3::NFS3ERR_IO)?
+    }
+}
+
+#[async_trait]
+impl NFSFileSystem for OverlayFS {
+    fn capabilities(&self) -> VFSCapabilities {
+        match self.read_only {
+            true => VFSCapabilities::ReadOnly,
+            false => VFSCapabilities::ReadWrite,
+        }
+    }
+
+    fn root_dir(&self) -> fileid3 {
+        ROOT
+    }
+
+    async fn lookup(&self, dirid: fileid3, filename: &filename3) -> Result<fileid3, nfsstat3> {
+        let dir = self.path(dirid)?;
+        let rel = match &filename[..] {
+            b"." => Ok(dir.clone()),
+            b".." => Ok(dir.parent().unwrap_or(&dir).to_path_buf()),
+            _ => overlay_name(filename)
+                .map(|name| dir.join(name))
+                .map_err(|_| nfsstat3::NFS3ERR_NOENT),
+        };
+        let rel = self
+            .blocking(move |fs| {
+                if !fs.locate(&dir)?.meta.is_dir() {
+                    return Err(nfsstat3::NFS3ERR_NOTDIR);
+                }
+                let rel = rel?;
+                fs.locate(&rel)?;
+                Ok(rel)
+            })
+            .await?;
+        Ok(self.id(&rel))
+    }
+
+    async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
+        let rel = self.path(id)?;
+        self.blocking(move |fs| fs.attr(id, &rel)).await
+    }
+
+    async fn setattr(&self, id: fileid3, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        if self.read_only {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        let rel = self.path(id)?;
+        let copied = rel.clone();
+        let up = self.blocking(move |fs| fs.copy_up(&copied)).await?;
+        path_setattr(&up, &setattr).await?;
+        self.blocking(move |fs| fs.attr(id, &rel)).await
+    }
+
+    async fn read(&self, id: fileid3, offset: u64, count: u32) -> Result<(Bytes, bool), nfsstat3> {
+        let rel = self.path(id)?;
+        let found = self.blocking(move |fs| fs.locate(&rel)).await?;
+        if found.meta.is_dir() {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        }
+        let mut file = tokio::fs::File::open(&found.host)
+            .await
+            .map_err(io_error_to_nfsstat3)?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(io_error_to_nfsstat3)?;
+        let mut buf = Vec::with_capacity(count as usize);
+        file.take(count as u64)
+            .read_to_end(&mut buf)
+            .await
+            .map_err(io_error_to_nfsstat3)?;
+        let eof = offset + buf.len() as u64 >= found.meta.len();
+        Ok((buf.into(), eof))
+    }
+
+    async fn write(&self, id: fileid3, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        if self.read_only {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        let rel = self.path(id)?;
+        let copied = rel.clone();
+        let up = self.blocking(move |fs| fs.copy_up(&copied)).await?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(&up)
+            .await
+            .map_err(io_error_to_nfsstat3)?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(io_error_to_nfsstat3)?;
+        file.write_all(data).await.map_err(io_error_to_nfsstat3)?;
+        file.flush().await.map_err(io_error_to_nfsstat3)?;
+        self.blocking(move |fs| fs.attr(id, &rel)).await
+    }
+
+    async fn create(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+        attr: sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        let name = overlay_name(filename)?.to_os_string();
+        self.blocking(move |fs| {
+            let (rel, up, _) = fs.make_room(dirid, &name)?;
+            std::fs::File::create(&up).map_err(io_error_to_nfsstat3)?;
+            create_setattr(&up, &attr)?;
+            let id = fs.id(&rel);
+            Ok((id, fs.attr(id, &rel)?))
+        })
+        .await
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+    ) -> Result<fileid3, nfsstat3> {
+        let name = overlay_name(filename)?.to_os_string();
+        self.blocking(move |fs| {
+            let (rel, up, _) = fs.make_room(dirid, &name)?;
+            std::fs::File::create_new(&up).map_err(io_error_to_nfsstat3)?;
+            Ok(fs.id(&rel))
+        })
+        .await
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.mkdir_with_attrs(dirid, dirname, &sattr3::default())
+            .await
+    }
+
+    async fn mkdir_with_attrs(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        let name = overlay_name(dirname)?.to_os_string();
+        let attr = *attr;
+        self.blocking(move |fs| {
+            let (rel, up, whited_out) = fs.make_room(dirid, &name)?;
+            std::fs::create_dir(&up).map_err(io_error_to_nfsstat3)?;
+            if whited_out {
+                // what the lower layer had here was removed, and stays so
+                std::fs::write(up.join(OPAQUE), b"").map_err(io_error_to_nfsstat3)?;
+            }
+            create_setattr(&up, &attr)?;
+            let id = fs.id(&rel);
+            Ok((id, fs.attr(id, &rel)?))
+        })
+        .await
+    }
+
+    async fn remove(&self, dirid: fileid3, filename: &filename3) -> Result<(), nfsstat3> {
+        if self.read_only {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        let name = overlay_name(filename).map_err(|_| nfsstat3::NFS3ERR_NOENT)?;
+        let rel = self.path(dirid)?.join(name);
+        self.blocking(move |fs| fs.remove_path(&rel)).await
+    }
+
+    async fn rename(
+        &self,
+        from_dirid: fileid3,
+        from_filename: &filename3,
+        to_dirid: fileid3,
+        to_filename: &filename3,
+    ) -> Result<(), nfsstat3> {
+        if self.read_only {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        let from = self.path(from_dirid)?.join(overlay_name(from_filename)?);
+        let to_dir = self.path(to_dirid)?;
+        let to_name = overlay_name(to_filename)?.to_os_string();
+        let to = to_dir.join(&to_name);
+        if from == to {
+            return Ok(());
+        }
+        self.blocking(move |fs| {
+            let source = fs.locate(&from)?;
+            if source.meta.is_dir() && source.lower_dir.is_some() {
+                return Err(nfsstat3::NFS3ERR_XDEV);
+            }
+            if to.starts_with(&from) {
+                return Err(nfsstat3::NFS3ERR_INVAL);
+            }
+            if let Ok(target) = fs.locate(&to) {
+                match (source.meta.is_dir(), target.meta.is_dir()) {
+                    (true, false) => return Err(nfsstat3::NFS3ERR_NOTDIR),
+                    (false, true) => return Err(nfsstat3::NFS3ERR_ISDIR),
+                    _ => fs.remove_path(&to)?,
+                }
+            }
+            let up_from = fs.copy_up(&from)?;
+            let (_, up_to, whited_out) = fs.make_room(to_dirid, &to_name)?;
+            std::fs::rename(&up_from, &up_to).map_err(io_error_to_nfsstat3)?;
+            if whited_out && source.meta.is_dir() {
+                std::fs::write(up_to.join(OPAQUE), b"").map_err(io_error_to_nfsstat3)?;
+            }
+            // the lower layer may still have what was moved away
+            if fs.locate(&from).is_ok() {
+                if let (Some(dir), Some(name)) = (from.parent(), from.file_name()) {
+                    let updir = fs.copy_up(dir)?;
+                    std::fs::write(updir.join(whiteout(name)), b"")
+                        .map_err(io_error_to_nfsstat3)?;
+                }
+            }
+            fs.paths.lock().unwrap().moved(&from, &to);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn readdir(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<ReadDirResult, nfsstat3> {
+        let dir = self.path(dirid)?;
+        let entries = self
+            .blocking(move |fs| {
+                let mut entries = Vec::new();
+                for name in fs.names(&dir)? {
+                    let rel = dir.join(&name);
+                    let id = fs.id(&rel);
+                    // gone since it was listed
+                    let Ok(attr) = fs.attr(id, &rel) else {
+                        continue;
+                    };
+                    entries.push((id, (name, attr)));
+                }
+                Ok(entries)
+            })
+            .await?;
+        let (page, end) = paginate(entries, start_after, max_entries);
+        let entries = page
+            .into_iter()
+            .map(|(fileid, (name, attr))| DirEntry {
+                fileid,
+                name: name.as_bytes().into(),
+                attr,
+            })
+            .collect();
+        Ok(ReadDirResult { entries, end })
+    }
+
+    async fn symlink(
+        &self,
+        dirid: fileid3,
+        linkname: &filename3,
+        symlink: &nfspath3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        let name = overlay_name(linkname)?.to_os_string();
+        let target = OsStr::from_bytes(symlink).to_os_string();
+        let attr = *attr;
+        self.blocking(move |fs| {
+            let (rel, up, _) = fs.make_room(dirid, &name)?;
+            std::os::unix::fs::symlink(target, &up).map_err(io_error_to_nfsstat3)?;
+            create_setattr(&up, &attr)?;
+            let id = fs.id(&rel);
+            Ok((id, fs.attr(id, &rel)?))
+        })
+        .await
+    }
+
+    async fn readlink(&self, id: fileid3) -> Result<nfspath3, nfsstat3> {
+        let rel = self.path(id)?;
+        let target = self
+            .blocking(move |fs| {
+                let found = fs.locate(&rel)?;
+                if !found.meta.is_symlink() {
+                    return Err(nfsstat3::NFS3ERR_INVAL);
+                }
+                std::fs::read_link(&found.host).map_err(io_error_to_nfsstat3)
+            })
+            .await?;
+        Ok(target.as_os_str().as_bytes().into())
+    }
+}
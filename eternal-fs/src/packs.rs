@@ -0,0 +1,1029 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use tracing::{debug, info};
+
+use crate::physics::Physics;
+use crate::quests::QUESTS_DIR;
+use crate::scoring::{Difficulty, ScoreRule, Threshold};
+
+/// Names at the root of the world which belong to the game itself and
+/// can not be claimed by a stage.
+const RESERVED_NAMES: &[&str] = &[
+    ".eternal",
+    "progress.txt",
+    "quantum_state.txt",
+    "perception.txt",
+    "timeline.txt",
+    "leaderboard.txt",
+    "oracle.txt",
+    "meditation.txt",
+    ".control",
+    ".status",
+    "koan.txt",
+    "daily_challenge.txt",
+    "chronicle.md",
+    "dreams",
+    "letters",
+    "achievements",
+    "inventory",
+    "journal",
+    "hints",
+    "quests",
+    "toolbox",
+    "rebirth",
+    "race.txt",
+    "winner.md",
+];
+
+/// Files of every stage directory, which no character can be talked to in
+const STAGE_FILES: &[&str] = &[
+    "README.txt",
+    "question.txt",
+    "answer.txt",
+    "system_response.txt",
+    "riddle.txt",
+    "clues",
+];
+
+/// How long an answer has to be, unless configured otherwise
+pub const DEFAULT_MIN_ANSWER_LENGTH: usize = 50;
+
+/// The stages every world has shipped with, in the order they are answered
+const CORE_PACK: &str = include_str!("../packs/core.toml");
+
+/// Reply to an accepted answer, unless the stage has one of its own
+const DEFAULT_REPLY: &str = "Your answer settles into the world.";
+
+/// Hint tokens a side quest grants, unless it says otherwise
+const DEFAULT_QUEST_TOKENS: u32 = 1;
+
+/// A single stage directory as declared by a pack
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageDef {
+    pub name: String,
+    pub question: String,
+    /// Stages of the same pack, by name, to be answered before this one.
+    /// Unless given, that is the stage declared before it if the pack is
+    /// sequential, and none if it is free.
+    pub after: Option<Vec<String>>,
+    /// Words an answer scores 1 for containing, in any case
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Patterns an answer scores for matching
+    #[serde(default)]
+    pub patterns: Vec<ScoreRule>,
+    /// The score an answer needs to be accepted, by default that of every
+    /// keyword and pattern
+    pub threshold: Option<Threshold>,
+    /// Answers to compare with when answers are judged by their meaning
+    #[serde(default)]
+    pub exemplars: Vec<String>,
+    /// Similarity to an example an answer needs, if not the evaluator's
+    pub similarity: Option<f32>,
+    /// What the world says when the answer is accepted
+    #[serde(default = "default_reply")]
+    pub reply: String,
+    /// What progress.txt asks of the player while the stage is next
+    #[serde(default)]
+    pub challenge: String,
+    #[serde(default)]
+    pub hint: String,
+    /// Hints a player can spend tokens on, each more specific than the
+    /// one before, see [`hint_ladder`](StageDef::hint_ladder)
+    #[serde(default)]
+    pub hints: Vec<String>,
+    /// The artifact a player is granted for answering the stage
+    pub grants: Option<ArtifactDef>,
+    /// An artifact, by name, the player has to use in the stage before
+    /// it can be answered
+    pub uses: Option<String>,
+    /// The characters met in the stage
+    #[serde(default)]
+    pub npcs: Vec<NpcDef>,
+    /// The riddles the stage sets every player one of, to be solved
+    /// before it can be answered
+    pub riddle: Option<RiddleSet>,
+    /// Files of the stage kept encrypted until unsealed with a passphrase
+    #[serde(default)]
+    pub sealed: Vec<SealedDef>,
+    /// A clue hidden in the attributes of the files in `clues/`
+    pub clues: Option<ClueDef>,
+    /// How the file system behaves below the stage directory
+    #[serde(default)]
+    pub physics: Vec<Physics>,
+}
+
+/// A clue of a stage, which only the attributes of the files in its
+/// `clues/` directory tell: the size of each numbered file is the ASCII
+/// code of a letter of `word`, and the modification times of the files
+/// named after hemispheres are the coordinates of `place`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClueDef {
+    pub word: String,
+    /// Latitude and longitude, in degrees
+    pub place: Option<(f64, f64)>,
+}
+
+/// A file of a stage whose contents are kept encrypted, until a player
+/// writes its passphrase to `<file>.key` beside it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SealedDef {
+    /// The name of the file in the stage directory
+    pub file: String,
+    /// The words it is sealed with, in any case
+    pub passphrase: String,
+    pub contents: String,
+}
+
+/// A side quest as declared by a pack: a question beside the stages, open
+/// at any point of the journey
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuestDef {
+    pub name: String,
+    pub question: String,
+    /// Words an answer has to contain every one of, in any case
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// What the world says when the answer is accepted
+    #[serde(default = "default_reply")]
+    pub reply: String,
+    /// Hint tokens the player is granted for the answer
+    #[serde(default = "default_quest_tokens")]
+    pub hint_tokens: u32,
+}
+
+/// An easter egg as declared by a pack: something hidden, found by
+/// creating a file or directory of its name anywhere in the world
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EggDef {
+    /// The name which finds it, in any case
+    pub name: String,
+    /// What the created file holds, or the README.txt of the created
+    /// directory
+    pub says: String,
+}
+
+impl QuestDef {
+    /// Whether an answer contains every keyword
+    pub fn accepts(&self, response: &str) -> bool {
+        let lowered = response.to_lowercase();
+        self.keywords
+            .iter()
+            .all(|k| lowered.contains(&k.to_lowercase()))
+    }
+}
+
+/// The riddles a stage can set, made up anew for every journey
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiddleSet {
+    /// Orderings, liars and sums
+    Logic,
+    /// Ferrymen, gods and things which speak of themselves
+    Myth,
+}
+
+/// A character players talk to through a file in the directory of its
+/// stage. What it says next depends on what it is answered.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NpcDef {
+    pub name: String,
+    /// The node the conversation starts at, by id
+    pub start: String,
+    pub nodes: Vec<DialogueNode>,
+}
+
+impl NpcDef {
+    /// The name of the file the character is talked to through
+    pub fn file_name(&self) -> String {
+        file_name_of(&self.name)
+    }
+
+    pub fn node(&self, id: &str) -> Option<&DialogueNode> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+}
+
+/// Something a character says, and where the conversation goes from there
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DialogueNode {
+    pub id: String,
+    pub says: String,
+    /// Where an answer leads, the first branch it takes first. The
+    /// conversation ends at a node without branches.
+    #[serde(default)]
+    pub replies: Vec<DialogueBranch>,
+}
+
+/// A way the conversation can go on
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DialogueBranch {
+    /// Words of which an answer has to contain one, any answer will do
+    /// without them
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// The emotional state the player has to be in
+    pub mood: Option<String>,
+    /// The node the branch leads to, by id
+    pub next: String,
+}
+
+/// Something a player carries in their inventory once a stage granted it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArtifactDef {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl ArtifactDef {
+    /// The name of the artifact's file
+    pub fn file_name(&self) -> String {
+        file_name_of(&self.name)
+    }
+}
+
+/// The name of the file for something named `name`, "Hourglass of Now"
+/// as `hourglass_of_now.txt`
+fn file_name_of(name: &str) -> String {
+    let slug: String = name
+        .chars()
+        .map(|c| match c.is_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect();
+    format!("{}.txt", slug)
+}
+
+fn default_reply() -> String {
+    DEFAULT_REPLY.to_string()
+}
+
+fn default_quest_tokens() -> u32 {
+    DEFAULT_QUEST_TOKENS
+}
+
+impl StageDef {
+    /// What an answer is worth: the weight of every keyword it contains
+    /// and every pattern it matches
+    pub fn score(&self, response: &str) -> f64 {
+        let lowered = response.to_lowercase();
+        let keywords = self
+            .keywords
+            .iter()
+            .filter(|k| lowered.contains(&k.to_lowercase()))
+            .count() as f64;
+        let patterns: f64 = self
+            .patterns
+            .iter()
+            .filter(|rule| rule.pattern.is_match(response))
+            .map(|rule| rule.weight)
+            .sum();
+        keywords + patterns
+    }
+
+    /// The hints a player can spend tokens on, in order. Without hints of
+    /// its own a stage only has the hint shown in progress.txt.
+    pub fn hint_ladder(&self) -> Vec<&str> {
+        match self.hints.is_empty() {
+            false => self.hints.iter().map(String::as_str).collect(),
+            true if self.hint.is_empty() => Vec::new(),
+            true => vec![self.hint.as_str()],
+        }
+    }
+
+    /// The score an answer needs at `difficulty`
+    pub fn threshold(&self, difficulty: Difficulty) -> f64 {
+        match self.threshold {
+            Some(threshold) => threshold.at(difficulty),
+            None => {
+                let patterns: f64 = self.patterns.iter().map(|rule| rule.weight.max(0.0)).sum();
+                self.keywords.len() as f64 + patterns
+            }
+        }
+    }
+}
+
+/// How the stages of a pack are to be answered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Advancement {
+    /// In any order, at any point of the journey
+    #[default]
+    Free,
+    /// One after the other, in the order the pack declares them
+    Sequential,
+}
+
+/// A named set of stages that can be mixed into a world.
+///
+/// Packs are read from `.eternal/packs/<name>.toml`:
+///
+/// ```text
+/// # Questions from the Stoa
+/// name = "stoicism"
+/// prefix = "stoa"
+/// requires = ["core"]
+/// advancement = "sequential"
+///
+/// [[stages]]
+/// name = "dichotomy"
+/// question = "What is up to you, and what is not?"
+/// keywords = ["control", "choice"]
+/// threshold = { easy = 1, normal = 2, hard = 3 }
+/// reply = "You have drawn the line between what is yours and what is not."
+/// hint = "Epictetus began with this."
+/// hints = ["Some things are yours to decide.", "Speak of control and choice."]
+/// grants = { name = "Stoic Compass", description = "It points at what is yours." }
+///
+/// [[stages.patterns]]
+/// pattern = "\\bnot (up )?to (me|us)\\b"
+/// weight = 1.5
+///
+/// [[stages]]
+/// name = "assent"
+/// question = "What do you agree to, before you act?"
+/// after = ["dichotomy"]
+/// uses = "Stoic Compass"
+///
+/// [[quests]]
+/// name = "the-view-from-above"
+/// question = "Look down on your life as from the stars. What is left of it?"
+/// keywords = ["small"]
+/// hint_tokens = 2
+///
+/// [[eggs]]
+/// name = "ataraxia"
+/// says = "Nothing disturbs you here. You found a hidden place."
+/// ```
+///
+/// Packs in the older line based format are read from
+/// `.eternal/packs/<name>.pack`:
+///
+/// ```text
+/// pack stoicism
+/// prefix stoa
+/// requires core
+/// advancement sequential
+/// stage dichotomy What is up to you, and what is not?
+/// keywords control choice
+/// reply You have drawn the line between what is yours and what is not.
+/// ```
+///
+/// `after`, `keywords`, `pattern <weight> <regex>`, `threshold`, `exemplar`,
+/// `hint`, `reply`, `grants <artifact>`, `uses <artifact>` and
+/// `riddle <logic|myth>` describe the stage declared just before them.
+/// Every `hint` adds a rung to the stage's hint ladder. Characters, sealed
+/// files, side quests and easter eggs can only be declared in TOML.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorldPack {
+    pub name: String,
+    /// Prepended to the directory of every stage as `<prefix>-<stage>`
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub requires: Vec<String>,
+    #[serde(default)]
+    pub advancement: Advancement,
+    pub stages: Vec<StageDef>,
+    /// Side quests, each in a directory of its own under `quests/`
+    #[serde(default)]
+    pub quests: Vec<QuestDef>,
+    /// Easter eggs, found by creating files or directories of their names
+    #[serde(default)]
+    pub eggs: Vec<EggDef>,
+}
+
+impl WorldPack {
+    /// The built in pack holding the original stages. Unlike the stages
+    /// of other packs they have to be answered one after the other, and
+    /// each of them moves the player to the next [`crate::GameStage`].
+    pub fn core() -> WorldPack {
+        WorldPack::parse_toml(CORE_PACK).expect("the core pack is invalid")
+    }
+
+    /// Parses a pack in TOML
+    pub fn parse_toml(contents: &str) -> Result<WorldPack, String> {
+        toml::from_str(contents).map_err(|e| e.to_string())
+    }
+
+    /// Parses a pack in the line based format
+    pub fn parse(contents: &str) -> Result<WorldPack, String> {
+        let mut name = None;
+        let mut prefix = None;
+        let mut requires = Vec::new();
+        let mut advancement = Advancement::default();
+        let mut stages: Vec<StageDef> = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "pack" => name = Some(value.to_string()),
+                "prefix" => prefix = Some(value.to_string()),
+                "requires" => requires.extend(value.split_whitespace().map(String::from)),
+                "advancement" => {
+                    advancement = match value {
+                        "free" => Advancement::Free,
+                        "sequential" => Advancement::Sequential,
+                        _ => return Err(format!("unknown advancement {:?}", value)),
+                    }
+                }
+                "stage" => {
+                    let (stage, question) = value
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| format!("stage without a question: {:?}", line))?;
+                    stages.push(StageDef {
+                        name: stage.to_string(),
+                        question: question.trim().to_string(),
+                        after: None,
+                        keywords: Vec::new(),
+                        patterns: Vec::new(),
+                        threshold: None,
+                        exemplars: Vec::new(),
+                        similarity: None,
+                        reply: default_reply(),
+                        challenge: String::new(),
+                        hint: String::new(),
+                        hints: Vec::new(),
+                        grants: None,
+                        uses: None,
+                        npcs: Vec::new(),
+                        riddle: None,
+                        sealed: Vec::new(),
+                        clues: None,
+                        physics: Vec::new(),
+                    });
+                }
+                "after" | "keywords" | "pattern" | "threshold" | "exemplar" | "hint" | "reply"
+                | "grants" | "uses" | "riddle" => {
+                    let stage = stages
+                        .last_mut()
+                        .ok_or_else(|| format!("{} before any stage", key))?;
+                    match key {
+                        "after" => {
+                            stage.after = Some(value.split_whitespace().map(String::from).collect())
+                        }
+                        "keywords" => {
+                            stage.keywords = value.split_whitespace().map(String::from).collect()
+                        }
+                        "pattern" => {
+                            let (weight, pattern) = value
+                                .split_once(char::is_whitespace)
+                                .ok_or_else(|| format!("pattern without a weight: {:?}", line))?;
+                            stage.patterns.push(ScoreRule {
+                                weight: weight
+                                    .parse()
+                                    .map_err(|_| format!("invalid weight in {:?}", line))?,
+                                pattern: pattern.trim().parse()?,
+                            });
+                        }
+                        "threshold" => {
+                            stage.threshold = Some(Threshold::Fixed(
+                                value
+                                    .parse()
+                                    .map_err(|_| format!("invalid threshold in {:?}", line))?,
+                            ))
+                        }
+                        "exemplar" => stage.exemplars.push(value.to_string()),
+                        "hint" => stage.hints.push(value.to_string()),
+                        "grants" => {
+                            stage.grants = Some(ArtifactDef {
+                                name: value.to_string(),
+                                description: String::new(),
+                            })
+                        }
+                        "uses" => stage.uses = Some(value.to_string()),
+                        "riddle" => {
+                            stage.riddle = Some(match value {
+                                "logic" => RiddleSet::Logic,
+                                "myth" => RiddleSet::Myth,
+                                _ => return Err(format!("unknown riddles {:?}", value)),
+                            })
+                        }
+                        _ => stage.reply = value.to_string(),
+                    }
+                }
+                _ => return Err(format!("unknown pack directive {:?}", key)),
+            }
+        }
+        Ok(WorldPack {
+            name: name.ok_or("pack has no name")?,
+            prefix,
+            requires,
+            advancement,
+            stages,
+            quests: Vec::new(),
+            eggs: Vec::new(),
+        })
+    }
+}
+
+/// A stage of the composed world
+#[derive(Debug, Clone)]
+pub struct Stage {
+    /// Directory of the stage under the root
+    pub dir: String,
+    /// The pack the stage came from
+    pub pack: String,
+    /// How the stages of its pack are answered
+    pub advancement: Advancement,
+    /// Which of the stage's thresholds applies
+    pub difficulty: Difficulty,
+    pub def: StageDef,
+}
+
+impl Stage {
+    /// Whether an answer scores enough to be accepted
+    pub fn accepts(&self, response: &str) -> bool {
+        self.def.score(response) >= self.def.threshold(self.difficulty)
+    }
+}
+
+/// A side quest of the composed world
+#[derive(Debug, Clone)]
+pub struct Quest {
+    /// Directory of the quest under the root, within `quests/`
+    pub dir: String,
+    /// The pack the quest came from
+    pub pack: String,
+    pub def: QuestDef,
+}
+
+/// The stages of all packs of a world, in load order
+#[derive(Debug, Clone)]
+pub struct World {
+    pub stages: Vec<Stage>,
+    /// The side quests of all packs, in load order
+    pub quests: Vec<Quest>,
+    /// The easter eggs of all packs, in load order
+    pub eggs: Vec<EggDef>,
+    /// Answers have to be longer than this to be considered
+    pub min_answer_length: usize,
+}
+
+/// The path, modification time and size of every file a world is
+/// composed from, to tell when one of them changed
+pub type ContentStamp = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+impl World {
+    /// Takes stock of the pack and world files in `statedir`
+    pub fn stamp(statedir: &Path) -> ContentStamp {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(statedir.join("packs"))
+            .map(|listing| listing.filter_map(|e| Some(e.ok()?.path())).collect())
+            .unwrap_or_default();
+        paths.push(statedir.join("world"));
+        paths.sort();
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let meta = std::fs::metadata(&path).ok()?;
+                Some((path, meta.modified().ok(), meta.len()))
+            })
+            .collect()
+    }
+
+    /// Composes the world described in `<statedir>/world`, which names one
+    /// pack per line, optionally followed by a prefix overriding the
+    /// pack's own. Without a world file every available pack is used.
+    pub fn load(statedir: &Path) -> Result<World, String> {
+        let mut available = HashMap::new();
+        available.insert("core".to_string(), WorldPack::core());
+        if let Ok(listing) = std::fs::read_dir(statedir.join("packs")) {
+            let mut paths: Vec<_> = listing.filter_map(|e| Some(e.ok()?.path())).collect();
+            paths.sort();
+            for path in paths {
+                let parse = match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("toml") => WorldPack::parse_toml,
+                    Some("pack") => WorldPack::parse,
+                    _ => continue,
+                };
+                let contents = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("unable to read {:?}: {}", path, e))?;
+                let pack = parse(&contents).map_err(|e| format!("in {:?}: {}", path, e))?;
+                debug!("Found world pack {:?} in {:?}", pack.name, path);
+                if available.insert(pack.name.clone(), pack).is_some() {
+                    return Err(format!("pack in {:?} reuses an existing name", path));
+                }
+            }
+        }
+
+        let packs = match std::fs::read_to_string(statedir.join("world")) {
+            Ok(contents) => {
+                let mut packs = Vec::new();
+                for line in contents.lines().map(str::trim) {
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let mut fields = line.split_whitespace();
+                    let name = fields.next().unwrap_or_default();
+                    let mut pack = available
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| format!("unknown pack {:?}", name))?;
+                    if let Some(prefix) = fields.next() {
+                        pack.prefix = Some(prefix.to_string());
+                    }
+                    packs.push(pack);
+                }
+                packs
+            }
+            Err(_) => {
+                let mut packs: Vec<WorldPack> = available.into_values().collect();
+                packs.sort_by(|a, b| a.name.cmp(&b.name));
+                packs
+            }
+        };
+        World::compose(packs)
+    }
+
+    /// Orders packs so that every pack loads after the packs it requires
+    /// and lays out their stages and side quests, refusing worlds in which
+    /// a dependency is missing or circular, a pack appears twice, two
+    /// stages or two side quests would share a directory, two easter eggs
+    /// share a name, stages wait for
+    /// each other, an artifact is used which no other stage grants, a
+    /// conversation leads nowhere, a sealed file takes the name of another
+    /// file, or a clue can not be told.
+    pub fn compose(packs: Vec<WorldPack>) -> Result<World, String> {
+        let mut by_name = HashMap::new();
+        let mut order = Vec::new();
+        for pack in packs {
+            if by_name.contains_key(&pack.name) {
+                return Err(format!("pack {:?} is used twice", pack.name));
+            }
+            order.push(pack.name.clone());
+            by_name.insert(pack.name.clone(), pack);
+        }
+
+        let mut loaded = Vec::new();
+        let mut visiting = HashSet::new();
+        for name in order.iter() {
+            load_after_requirements(name, &by_name, &mut visiting, &mut loaded)?;
+        }
+
+        let mut stages: Vec<Stage> = Vec::new();
+        let mut quests: Vec<Quest> = Vec::new();
+        let mut eggs: Vec<EggDef> = Vec::new();
+        let mut claimed: HashMap<String, String> = HashMap::new();
+        let mut hidden: HashMap<String, String> = HashMap::new();
+        for name in loaded {
+            let pack = &by_name[&name];
+            for def in pack.stages.iter() {
+                let dir = match pack.prefix {
+                    Some(ref prefix) => format!("{}-{}", prefix, def.name),
+                    None => def.name.clone(),
+                };
+                if dir.is_empty() || dir.contains('/') || dir.starts_with('.') {
+                    return Err(format!("pack {:?} has an invalid stage {:?}", name, dir));
+                }
+                if RESERVED_NAMES.contains(&dir.as_str()) {
+                    return Err(format!("stage {:?} of pack {:?} is reserved", dir, name));
+                }
+                if let Some(other) = claimed.insert(dir.clone(), name.clone()) {
+                    return Err(format!(
+                        "packs {:?} and {:?} both declare stage {:?}",
+                        other, name, dir
+                    ));
+                }
+                stages.push(Stage {
+                    dir,
+                    pack: name.clone(),
+                    advancement: pack.advancement,
+                    difficulty: Difficulty::default(),
+                    def: def.clone(),
+                });
+            }
+            for def in pack.quests.iter() {
+                let dir = match pack.prefix {
+                    Some(ref prefix) => format!("{}-{}", prefix, def.name),
+                    None => def.name.clone(),
+                };
+                if dir.is_empty() || dir.contains('/') || dir.starts_with('.') {
+                    return Err(format!(
+                        "pack {:?} has an invalid side quest {:?}",
+                        name, dir
+                    ));
+                }
+                let dir = format!("{}/{}", QUESTS_DIR, dir);
+                if let Some(other) = claimed.insert(dir.clone(), name.clone()) {
+                    return Err(format!(
+                        "packs {:?} and {:?} both declare side quest {:?}",
+                        other, name, dir
+                    ));
+                }
+                quests.push(Quest {
+                    dir,
+                    pack: name.clone(),
+                    def: def.clone(),
+                });
+            }
+            for def in pack.eggs.iter() {
+                let egg = def.name.to_ascii_lowercase();
+                if egg.is_empty() || egg.contains('/') || egg == "." || egg == ".." {
+                    return Err(format!(
+                        "pack {:?} hides an easter egg with an invalid name {:?}",
+                        name, def.name
+                    ));
+                }
+                if let Some(other) = hidden.insert(egg, name.clone()) {
+                    return Err(format!(
+                        "packs {:?} and {:?} both hide an easter egg named {:?}",
+                        other, name, def.name
+                    ));
+                }
+                eggs.push(def.clone());
+            }
+            info!("Loaded world pack {:?}", name);
+        }
+        let world = World {
+            stages,
+            quests,
+            eggs,
+            min_answer_length: DEFAULT_MIN_ANSWER_LENGTH,
+        };
+        world.check_prerequisites()?;
+        world.check_artifacts()?;
+        world.check_dialogues()?;
+        world.check_seals()?;
+        world.check_clues()?;
+        Ok(world)
+    }
+
+    /// Moves the named stages to the front, in the given order. Stages
+    /// which are not named keep their load order after them.
+    pub fn reorder(&mut self, order: &[String]) -> Result<(), String> {
+        let mut ordered = Vec::with_capacity(self.stages.len());
+        for dir in order {
+            let index = self
+                .stages
+                .iter()
+                .position(|s| &s.dir == dir)
+                .ok_or_else(|| {
+                    format!("stage {:?} can not be ordered, it is not in the world", dir)
+                })?;
+            ordered.push(self.stages.remove(index));
+        }
+        ordered.append(&mut self.stages);
+        self.stages = ordered;
+        // the stage before a stage of a sequential pack may have changed
+        self.check_prerequisites()
+    }
+
+    pub fn stage(&self, dir: &str) -> Option<&Stage> {
+        self.stages.iter().find(|s| s.dir == dir)
+    }
+
+    /// The side quest in the directory `dir`, `quests/<name>`
+    pub fn quest(&self, dir: &str) -> Option<&Quest> {
+        self.quests.iter().find(|q| q.dir == dir)
+    }
+
+    /// The easter egg found by creating a file or directory named `name`
+    pub fn egg(&self, name: &str) -> Option<&EggDef> {
+        self.eggs
+            .iter()
+            .find(|egg| egg.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Holds the answers to every stage to its thresholds at `difficulty`
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        for stage in self.stages.iter_mut() {
+            stage.difficulty = difficulty;
+        }
+    }
+
+    /// The stages of the core pack, in the order they are answered
+    pub fn core_stages(&self) -> impl Iterator<Item = &Stage> {
+        self.stages.iter().filter(|s| s.pack == "core")
+    }
+
+    /// The stages to be answered before `stage`. Names which are not
+    /// stages of its pack are left out, see [`World::check_prerequisites`].
+    pub fn prerequisites(&self, stage: &Stage) -> Vec<&Stage> {
+        let pack = self.stages.iter().filter(|s| s.pack == stage.pack);
+        match (&stage.def.after, stage.advancement) {
+            (Some(names), _) => pack.filter(|s| names.contains(&s.def.name)).collect(),
+            (None, Advancement::Sequential) => pack
+                .take_while(|s| s.dir != stage.dir)
+                .last()
+                .into_iter()
+                .collect(),
+            (None, Advancement::Free) => Vec::new(),
+        }
+    }
+
+    /// Fails if a stage is to be answered after a stage its pack does not
+    /// have, or if stages wait for each other so that none can be answered
+    pub fn check_prerequisites(&self) -> Result<(), String> {
+        for stage in self.stages.iter() {
+            for name in stage.def.after.iter().flatten() {
+                if !self
+                    .stages
+                    .iter()
+                    .any(|s| s.pack == stage.pack && &s.def.name == name)
+                {
+                    return Err(format!(
+                        "stage {:?} comes after {:?}, which pack {:?} does not have",
+                        stage.dir, name, stage.pack
+                    ));
+                }
+            }
+        }
+        // peel off stages whose prerequisites are all peeled off, what
+        // remains waits on itself
+        let mut answerable: HashSet<&str> = HashSet::new();
+        loop {
+            let before = answerable.len();
+            for stage in self.stages.iter() {
+                if self
+                    .prerequisites(stage)
+                    .iter()
+                    .all(|s| answerable.contains(s.dir.as_str()))
+                {
+                    answerable.insert(&stage.dir);
+                }
+            }
+            if answerable.len() == before {
+                break;
+            }
+        }
+        match self
+            .stages
+            .iter()
+            .find(|s| !answerable.contains(s.dir.as_str()))
+        {
+            Some(stage) => Err(format!(
+                "stage {:?} can never be answered, its prerequisites wait on each other",
+                stage.dir
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails if two stages grant artifacts of the same name, or a stage
+    /// uses an artifact no other stage grants
+    pub fn check_artifacts(&self) -> Result<(), String> {
+        let mut granted: HashMap<&str, &str> = HashMap::new();
+        for stage in self.stages.iter() {
+            let Some(artifact) = &stage.def.grants else {
+                continue;
+            };
+            if let Some(other) = granted.insert(&artifact.name, &stage.dir) {
+                return Err(format!(
+                    "stages {:?} and {:?} both grant {:?}",
+                    other, stage.dir, artifact.name
+                ));
+            }
+        }
+        for stage in self.stages.iter() {
+            let Some(name) = &stage.def.uses else {
+                continue;
+            };
+            match granted.get(name.as_str()) {
+                Some(dir) if *dir != stage.dir => {}
+                _ => {
+                    return Err(format!(
+                        "stage {:?} uses {:?}, which no other stage grants",
+                        stage.dir, name
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if a character of a stage shares its file with another, or
+    /// its conversation starts or leads to a node it does not have
+    pub fn check_dialogues(&self) -> Result<(), String> {
+        for stage in self.stages.iter() {
+            let mut files = HashSet::new();
+            for npc in stage.def.npcs.iter() {
+                if STAGE_FILES.contains(&npc.file_name().as_str()) || !files.insert(npc.file_name())
+                {
+                    return Err(format!(
+                        "a character of stage {:?} would be talked to in {:?}, which is taken",
+                        stage.dir,
+                        npc.file_name()
+                    ));
+                }
+                let leads = npc
+                    .nodes
+                    .iter()
+                    .flat_map(|node| node.replies.iter().map(|branch| &branch.next));
+                for id in std::iter::once(&npc.start).chain(leads) {
+                    if npc.node(id).is_none() {
+                        return Err(format!(
+                            "{:?} of stage {:?} has no node {:?}",
+                            npc.name, stage.dir, id
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if a sealed file of a stage has an invalid name, or one taken
+    /// by another file of the stage
+    pub fn check_seals(&self) -> Result<(), String> {
+        for stage in self.stages.iter() {
+            let mut files: HashSet<String> = stage.def.npcs.iter().map(NpcDef::file_name).collect();
+            for sealed in stage.def.sealed.iter() {
+                let name = sealed.file.as_str();
+                if name.is_empty() || name.contains('/') || name.starts_with('.') {
+                    return Err(format!(
+                        "stage {:?} has an invalid sealed file {:?}",
+                        stage.dir, name
+                    ));
+                }
+                if STAGE_FILES.contains(&name) || !files.insert(name.to_string()) {
+                    return Err(format!(
+                        "a sealed file of stage {:?} would be kept in {:?}, which is taken",
+                        stage.dir, name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if the clue of a stage has no word, one which is not ASCII
+    /// letters, or a place off the globe
+    pub fn check_clues(&self) -> Result<(), String> {
+        for stage in self.stages.iter() {
+            let Some(clues) = &stage.def.clues else {
+                continue;
+            };
+            if clues.word.is_empty() || !clues.word.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(format!(
+                    "stage {:?} hides a clue {:?} which is not a word",
+                    stage.dir, clues.word
+                ));
+            }
+            if let Some((latitude, longitude)) = clues.place {
+                if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                    return Err(format!(
+                        "stage {:?} hides a place off the globe, at {}, {}",
+                        stage.dir, latitude, longitude
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Every stage, plus enlightenment itself
+    pub fn total_questions(&self) -> u64 {
+        self.stages.len() as u64 + 1
+    }
+
+    /// The physics of every stage which declares any, by stage directory
+    pub fn physics(&self) -> HashMap<OsString, Vec<Physics>> {
+        self.stages
+            .iter()
+            .filter(|stage| !stage.def.physics.is_empty())
+            .map(|stage| (stage.dir.as_str().into(), stage.def.physics.clone()))
+            .collect()
+    }
+}
+
+fn load_after_requirements(
+    name: &str,
+    packs: &HashMap<String, WorldPack>,
+    visiting: &mut HashSet<String>,
+    loaded: &mut Vec<String>,
+) -> Result<(), String> {
+    if loaded.iter().any(|n| n == name) {
+        return Ok(());
+    }
+    if !visiting.insert(name.to_string()) {
+        return Err(format!("pack {:?} depends on itself", name));
+    }
+    let pack = &packs[name];
+    for dep in pack.requires.iter() {
+        if !packs.contains_key(dep) {
+            return Err(format!("pack {:?} requires missing pack {:?}", name, dep));
+        }
+        load_after_requirements(dep, packs, visiting, loaded)?;
+    }
+    visiting.remove(name);
+    loaded.push(name.to_string());
+    Ok(())
+}
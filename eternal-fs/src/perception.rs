@@ -0,0 +1,170 @@
+//! Perception filters, through which a player sees the world.
+//!
+//! A player chooses their filters by writing their names to
+//! `perception.txt`. The Truth Lens reveals the `.truth.txt` of every
+//! stage, which is hidden otherwise, Quantum Vision holds the wobbling
+//! sizes of the quantum path still, and Temporal Sight tells the times in
+//! what the world generates as days of the player's journey.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use tracing::info;
+
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The file filters are chosen in
+pub(crate) const PERCEPTION_FILE: &str = "perception.txt";
+
+/// The file in every stage only the Truth Lens reveals
+const TRUTH_FILE: &str = ".truth.txt";
+
+const TRUTH_LENS: &str = "Truth Lens";
+const QUANTUM_VISION: &str = "Quantum Vision";
+const TEMPORAL_SIGHT: &str = "Temporal Sight";
+
+/// The filters there are, with what they do
+const FILTERS: &[(&str, &str)] = &[
+    (TRUTH_LENS, "hidden things are revealed"),
+    (QUANTUM_VISION, "uncertain sizes hold still"),
+    (TEMPORAL_SIGHT, "times are told in the days of your journey"),
+];
+
+/// Seeing the world as it is
+const DEFAULT_REALITY: &str = "Default Reality";
+
+impl PlayerState {
+    /// Whether the player looks through the filter `name`
+    pub(crate) fn perceives(&self, name: &str) -> bool {
+        self.philosophical_state.perception_filters.contains(name)
+    }
+
+    /// Looks through the filters named in `text`, one per line or
+    /// separated by commas, and through no others
+    fn choose_filters(&mut self, text: &str) {
+        let filters = &mut self.philosophical_state.perception_filters;
+        filters.clear();
+        for wanted in text.split([',', '\n']).map(str::trim) {
+            if let Some((name, _)) = FILTERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(wanted))
+            {
+                filters.insert(name.to_string());
+            }
+        }
+    }
+
+    /// When `time` was, as the player sees it
+    pub(crate) fn describe_time(&self, time: SystemTime) -> String {
+        if !self.perceives(TEMPORAL_SIGHT) {
+            return DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string();
+        }
+        let since = time.duration_since(self.started).unwrap_or_default();
+        let hours = since.as_secs() / 3600;
+        format!(
+            "day {} of your journey, hour {}",
+            hours / 24 + 1,
+            hours % 24 + 1
+        )
+    }
+
+    /// What perception.txt shows the player
+    pub(crate) fn perception(&self) -> String {
+        let mut active: Vec<&str> = FILTERS
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| self.perceives(name))
+            .collect();
+        if active.is_empty() {
+            active.push(DEFAULT_REALITY);
+        }
+        let available: Vec<String> = FILTERS
+            .iter()
+            .map(|(name, effect)| format!("- {}: {}\n", name, effect))
+            .collect();
+        format!(
+            "Perception Filters\n\
+             =================\n\
+             Your perception shapes the reality of this filesystem.\n\
+             \n\
+             Active Filters:\n\
+             - {}\n\
+             \n\
+             Available Filters:\n\
+             {}\n\
+             Write the names of the filters to look through to this file,\n\
+             or \"{}\" to see the world as it is.\n",
+            active.join("\n- "),
+            available.concat(),
+            DEFAULT_REALITY
+        )
+    }
+}
+
+impl Philosophy {
+    /// Writes perception.txt and the hidden truth of every stage
+    pub(crate) fn create_perception(&mut self) {
+        for stage in self.world.stages.iter() {
+            let truth = if stage.def.keywords.is_empty() {
+                format!(
+                    "On the path of {} the world listens for what your answer means.\n",
+                    stage.dir
+                )
+            } else {
+                format!(
+                    "On the path of {} the world listens for: {}\n",
+                    stage.dir,
+                    stage.def.keywords.join(", ")
+                )
+            };
+            let _ = std::fs::write(self.root.join(&stage.dir).join(TRUTH_FILE), truth);
+        }
+        self.update_perception_file();
+    }
+
+    /// Looks through the filters named in `text` for the player making the
+    /// call
+    pub(crate) fn process_perception(&mut self, text: &str) {
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        player.choose_filters(text);
+        info!(
+            "Perceiving through {:?}",
+            player.philosophical_state.perception_filters
+        );
+        self.update_perception_file();
+    }
+
+    /// Writes perception.txt of the player making the call. When every
+    /// player has a journey of their own it is only written to tell
+    /// clients it changed, the contents are the player's.
+    fn update_perception_file(&mut self) {
+        let id = self.player_identity.current();
+        let contents = self.players.entry(id).or_default().perception();
+        let _ = std::fs::write(self.root.join(PERCEPTION_FILE), contents);
+    }
+
+    /// The player making the call, unless they have not played yet
+    fn perceiving_player(&self) -> Option<&PlayerState> {
+        self.players.get(&self.player_identity.current())
+    }
+
+    /// Whether `path` is hidden from the player making the call
+    pub(crate) fn hidden(&self, path: &Path) -> bool {
+        path.file_name().is_some_and(|name| name == TRUTH_FILE)
+            && !self
+                .perceiving_player()
+                .is_some_and(|player| player.perceives(TRUTH_LENS))
+    }
+
+    /// Whether the player making the call sees through the blur of the
+    /// physics of a stage
+    pub(crate) fn sees_through(&self) -> bool {
+        self.perceiving_player()
+            .is_some_and(|player| player.perceives(QUANTUM_VISION))
+    }
+}
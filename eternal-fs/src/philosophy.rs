@@ -0,0 +1,1268 @@
+//! The philosophical journey, the game the Eternal Filesystem is known for.
+//!
+//! Every stage of the [`World`] is a directory with a question. Players
+//! answer it in `answer.txt`, find the reply in `system_response.txt` and
+//! follow their journey in `progress.txt`.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use nfsserve::nfs::nfsstat3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use tracing::{debug, info, warn};
+
+use crate::achievements::ACHIEVEMENTS_DIR;
+use crate::config::GameConfig;
+use crate::control::{is_operator, CONTROL_FILE};
+use crate::coop::Round;
+use crate::daily::DAILY_FILE;
+use crate::dreams::DREAMS_DIR;
+use crate::encryption::StateKey;
+use crate::engine::{
+    read_at, GameEngine, GeneratedFile, ProgressReport, SyntheticAttrs, VirtualAttrs, VirtualFile,
+    WriteAction,
+};
+use crate::entanglement::QUANTUM_DIR;
+use crate::evaluator::{Evaluation, KeywordEvaluator, ResponseEvaluator};
+use crate::events::{EventKind, Events};
+use crate::hints::HINTS_DIR;
+use crate::history::HISTORY_DIR;
+use crate::inventory::INVENTORY_DIR;
+use crate::meditation::MEDITATION_FILE;
+use crate::observer::QUESTION_FILE;
+use crate::oracle::ORACLE_FILE;
+use crate::packs::{Stage, World};
+use crate::perception::PERCEPTION_FILE;
+use crate::physics::Physics;
+use crate::players::{PlayerIdentity, PlayerState, LEADERBOARD_FILE, SHARED_PLAYER};
+use crate::race::{RACE_FILE, WINNER_FILE};
+use crate::riddles::RIDDLE_FILE;
+use crate::scheduler::WorldEvent;
+use crate::seals::KEY_EXTENSION;
+use crate::speedrun::format_run_time;
+use crate::store::StateStore;
+use crate::timeline::{JourneySnapshot, TIMELINE_FILE};
+use crate::toolbox::TOOLBOX_DIR;
+#[cfg(feature = "git")]
+use crate::versioning::Versioning;
+use crate::STATE_DIR;
+
+/// The file the journey is followed in
+const PROGRESS_FILE: &str = "progress.txt";
+
+/// The file every stage is answered in, unless configured otherwise
+pub(crate) const ANSWER_FILE: &str = "answer.txt";
+
+/// The file which collapses into another state whenever it is written,
+/// unless configured otherwise
+const QUANTUM_STATE_FILE: &str = "quantum_state.txt";
+
+/// What quantum_state.txt shows before it was first observed
+pub(crate) const QUANTUM_SUPERPOSITION: &str = "\
+    Quantum State Observation Log\n\
+    ==========================\n\
+    This file exists in a superposition of states.\n\
+    Each read may collapse it into a different reality.\n\
+    \n\
+    Current State: [SUPERPOSITION]\n\
+    Probability Field: Active\n\
+    Observer Effect: Enabled\
+";
+
+/// The files at the root served from memory rather than from the host
+const VIRTUAL_FILES: &[&str] = &[
+    PROGRESS_FILE,
+    QUANTUM_STATE_FILE,
+    LEADERBOARD_FILE,
+    ORACLE_FILE,
+    MEDITATION_FILE,
+    CONTROL_FILE,
+    DAILY_FILE,
+];
+
+/// Koans rotated through koan.txt by the scheduler
+const KOANS: &[&str] = &[
+    "What was your original face before your parents were born?",
+    "Two hands clap and there is a sound. What is the sound of one hand?",
+    "If you meet the Buddha on the road, what do you do?",
+    "The flag does not move, the wind does not move. What moves?",
+    "When the many are reduced to one, to what is the one reduced?",
+];
+
+/// The stages of the journey, in the order they are reached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum GameStage {
+    Beginning,
+    Logic,      // New: Logic puzzles and rationality
+    Emotion,    // New: Emotional exploration
+    Identity,   // New: Self-discovery
+    Time,       // New: Temporal mechanics
+    Creation,   // New: Creative forces
+    History,    // New: Past reflections
+    Myth,       // New: Mythological understanding
+    Perception, // New: Reality questioning
+    Quantum,    // New: Uncertainty principles
+    Chaos,      // New: Unpredictability
+    Enlightened,
+}
+
+impl GameStage {
+    /// The stage reached after this one, if any
+    pub fn next(&self) -> Option<GameStage> {
+        match self {
+            GameStage::Beginning => Some(GameStage::Logic),
+            GameStage::Logic => Some(GameStage::Emotion),
+            GameStage::Emotion => Some(GameStage::Identity),
+            GameStage::Identity => Some(GameStage::Time),
+            GameStage::Time => Some(GameStage::Creation),
+            GameStage::Creation => Some(GameStage::History),
+            GameStage::History => Some(GameStage::Myth),
+            GameStage::Myth => Some(GameStage::Perception),
+            GameStage::Perception => Some(GameStage::Quantum),
+            GameStage::Quantum => Some(GameStage::Chaos),
+            GameStage::Chaos => Some(GameStage::Enlightened),
+            GameStage::Enlightened => None,
+        }
+    }
+
+    /// The core stage whose answer brings the player here
+    pub(crate) fn stage_name(&self) -> Option<&'static str> {
+        match self {
+            GameStage::Logic => Some("logic"),
+            GameStage::Emotion => Some("emotion"),
+            GameStage::Identity => Some("identity"),
+            GameStage::Time => Some("time"),
+            GameStage::Creation => Some("creation"),
+            GameStage::History => Some("history"),
+            GameStage::Myth => Some("myth"),
+            GameStage::Perception => Some("perception"),
+            GameStage::Quantum => Some("quantum"),
+            GameStage::Chaos => Some("chaos"),
+            GameStage::Beginning | GameStage::Enlightened => None,
+        }
+    }
+
+    /// The stage reached by answering a core stage
+    pub(crate) fn reached_by(name: &str) -> Option<GameStage> {
+        let mut stage = GameStage::Beginning;
+        while let Some(next) = stage.next() {
+            if next.stage_name() == Some(name) {
+                return Some(next);
+            }
+            stage = next;
+        }
+        None
+    }
+}
+
+/// What the final answer is completed and archived as
+pub(crate) const ENLIGHTENMENT: &str = "enlightenment";
+
+/// Words the final answer has to contain
+const ENLIGHTENMENT_KEYWORDS: &[&str] = &["understanding", "wisdom"];
+
+/// How many events the timeline of a player keeps, the earliest being
+/// dropped first
+const MAX_TIMELINE_EVENTS: usize = 1000;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub(crate) struct PhilosophicalState {
+    pub(crate) emotional_state: String,
+    pub(crate) perception_filters: HashSet<String>,
+    pub(crate) quantum_states: HashMap<String, bool>,
+    pub(crate) created_elements: Vec<String>,
+    pub(crate) timeline_events: Vec<(SystemTime, String)>,
+    /// The journey before every accepted answer, the latest last, to
+    /// rewind to
+    pub(crate) snapshots: Vec<JourneySnapshot>,
+    pub(crate) solved_puzzles: HashSet<String>,
+}
+
+impl PhilosophicalState {
+    /// Notes `event`, which happened `at`, in the timeline, dropping the
+    /// earliest events past [`MAX_TIMELINE_EVENTS`]
+    pub(crate) fn note_event(&mut self, at: SystemTime, event: String) {
+        self.timeline_events.push((at, event));
+        let excess = self
+            .timeline_events
+            .len()
+            .saturating_sub(MAX_TIMELINE_EVENTS);
+        self.timeline_events.drain(..excess);
+    }
+}
+
+/// A previously accepted answer, kept so later edits can be compared against it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ArchivedAnswer {
+    pub(crate) text: String,
+    pub(crate) archived_at: SystemTime,
+}
+
+/// The philosophical journey through the stages of a world
+#[derive(Debug)]
+pub struct Philosophy {
+    pub(crate) root: PathBuf,
+    /// The stages composed from the loaded world packs
+    pub(crate) world: World,
+    pub(crate) game_state: HashMap<String, String>,
+    /// The journey of every player, by player
+    pub(crate) players: HashMap<String, PlayerState>,
+    pub(crate) player_identity: PlayerIdentity,
+    /// The settings the world is composed with again when its packs change
+    pub(crate) config: GameConfig,
+    /// Judges the answers to the stages
+    evaluator: Box<dyn ResponseEvaluator>,
+    /// Keeps the saved game, opened as it is first needed unless set
+    pub(crate) store: Option<Box<dyn StateStore>>,
+    pub(crate) rng: StdRng,
+    /// What quantum_state.txt shows, collapsed anew whenever it is written
+    pub(crate) quantum_state: String,
+    /// The results of the commands written to .control, the latest last
+    pub(crate) control_log: Vec<String>,
+    /// Where answers accepted, stages advanced and the quantum state
+    /// collapsing are published
+    pub(crate) events: Events,
+    /// Whether earlier answers and the journal archive are kept
+    /// compressed, see `compression.rs`
+    pub(crate) compress: bool,
+    /// What the earlier answers and the journal archive are encrypted
+    /// with, if they are, see `encryption.rs`
+    pub(crate) key: Option<StateKey>,
+    /// The repository the journey is committed to, if it is kept in git
+    #[cfg(feature = "git")]
+    pub(crate) versioning: Option<Versioning>,
+}
+
+impl Philosophy {
+    pub fn new(world: World) -> Philosophy {
+        Philosophy {
+            root: PathBuf::new(),
+            world,
+            game_state: HashMap::new(),
+            players: HashMap::from([(SHARED_PLAYER.to_string(), PlayerState::default())]),
+            player_identity: PlayerIdentity::Shared,
+            config: GameConfig::default(),
+            evaluator: Box::new(KeywordEvaluator),
+            store: None,
+            rng: StdRng::from_entropy(),
+            quantum_state: QUANTUM_SUPERPOSITION.to_string(),
+            control_log: Vec::new(),
+            events: Events::default(),
+            compress: false,
+            key: None,
+            #[cfg(feature = "git")]
+            versioning: None,
+        }
+    }
+
+    /// Sets how clients are told apart as players, each with a journey of
+    /// their own. By default everyone shares one journey.
+    pub fn set_player_identity(&mut self, identity: PlayerIdentity) {
+        self.player_identity = identity;
+    }
+
+    /// Keeps earlier answers and the archive of the journal compressed,
+    /// see `compression.rs`
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// Encrypts earlier answers and the archive of the journal with `key`,
+    /// see `encryption.rs`. The saved game is encrypted by its store.
+    pub fn set_state_key(&mut self, key: Option<StateKey>) {
+        self.key = key;
+    }
+
+    /// Sets the game settings applied to the world when it is composed
+    /// again after its packs changed
+    pub fn set_game_config(&mut self, config: GameConfig) {
+        self.config = config;
+    }
+
+    /// Sets how answers are judged. By default they have to contain the
+    /// keywords of their stage.
+    pub fn set_evaluator(&mut self, evaluator: Box<dyn ResponseEvaluator>) {
+        self.evaluator = evaluator;
+    }
+
+    /// Sets where the game is saved. By default it is kept in the store
+    /// the game settings configure.
+    pub fn set_store(&mut self, store: Box<dyn StateStore>) {
+        self.store = Some(store);
+    }
+
+    /// The stage the player furthest along has reached
+    pub fn current_stage(&self) -> GameStage {
+        self.leading_player()
+            .map_or(GameStage::Beginning, |player| player.current_stage.clone())
+    }
+
+    /// The name of the stage the player making the call has reached
+    fn caller_stage(&self) -> String {
+        let stage = self
+            .players
+            .get(&self.player_identity.current())
+            .map_or(GameStage::Beginning, |player| player.current_stage.clone());
+        format!("{:?}", stage)
+    }
+
+    /// The name of the file every stage is answered in
+    pub(crate) fn answer_file(&self) -> &str {
+        self.config.answer_file.as_deref().unwrap_or(ANSWER_FILE)
+    }
+
+    /// Where the file of the journey `name` is served, by the name it is
+    /// configured to have
+    fn journey_path<'a>(&'a self, name: &'a str) -> &'a Path {
+        match name {
+            QUANTUM_STATE_FILE => self
+                .config
+                .quantum_state_file
+                .as_deref()
+                .unwrap_or(QUANTUM_STATE_FILE)
+                .as_ref(),
+            name => name.as_ref(),
+        }
+    }
+
+    fn create_philosophical_directory(&mut self, name: &str, question: &str) {
+        let dir_path = self.root.join(name);
+        if std::fs::create_dir_all(&dir_path).is_err() {
+            warn!("Unable to create the stage directory {:?}", dir_path);
+            return;
+        }
+        let _ = std::fs::write(dir_path.join("question.txt"), question);
+        let readme_content = format!(
+            "Welcome to {}.\n\
+             This is a space for philosophical contemplation.\n\
+             Read the question in question.txt and create your response in {}.\n\
+             The system will respond to your thoughts in system_response.txt.\n\
+             Remember: There are no wrong answers, only unexplored thoughts.",
+            name,
+            self.answer_file()
+        );
+        let _ = std::fs::write(dir_path.join("README.txt"), readme_content);
+    }
+
+    /// Lays out the directories and files of the world at its root, as a
+    /// journey starts out in them
+    pub(crate) fn lay_out(&mut self) {
+        // Create the directories of every stage with their questions
+        let stages: Vec<(String, String)> = self
+            .world
+            .stages
+            .iter()
+            .map(|s| (s.dir.clone(), s.def.question.clone()))
+            .collect();
+        for (name, question) in stages {
+            self.create_philosophical_directory(&name, &question);
+        }
+        self.create_quest_directories();
+        self.create_sealed_files();
+        self.create_clue_files();
+
+        // Create special files
+        self.create_perception();
+        self.create_timeline_tracker();
+        self.create_journal();
+        self.create_hints();
+        self.create_entangled_pair();
+        self.create_daily_challenge();
+        self.create_letter();
+    }
+
+    /// Judges an answer of the player making the call and returns the reply
+    async fn process_philosophical_response(&mut self, location: &str, response: &str) -> String {
+        let evaluation = match self.world.stage(location) {
+            Some(stage) if response.len() > self.world.min_answer_length => {
+                Some(self.evaluator.evaluate(stage, response).await)
+            }
+            _ => None,
+        };
+        if let Some(reply) = self.final_path_closed(location, response) {
+            let id = self.player_identity.current();
+            let player = self.players.entry(id).or_default();
+            player.responses.insert(location.to_string(), reply.clone());
+            return reply;
+        }
+        let coop = self.coop();
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        player.feel(response);
+        if location == QUANTUM_DIR
+            && !player.observed_entanglement()
+            && !player.completed_questions.contains(QUANTUM_DIR)
+        {
+            let reply = "The quantum path asks you to observe before you answer. \
+                         Read particle.txt or wave.txt first."
+                .to_string();
+            player.responses.insert(location.to_string(), reply.clone());
+            return reply;
+        }
+        let open = player
+            .open_stages(&self.world)
+            .iter()
+            .any(|stage| stage.dir == location);
+        if let Some(artifact) = player
+            .missing_artifact(&self.world, location)
+            .filter(|_| open)
+        {
+            let reply = format!(
+                "The {} path asks you to use the {}. Copy it from {}/ into {}/ first.",
+                location, artifact, INVENTORY_DIR, location
+            );
+            player.responses.insert(location.to_string(), reply.clone());
+            return reply;
+        }
+        if open && player.unsolved_riddle(&self.world, location) {
+            let reply = format!(
+                "The {} path sets you a riddle. Solve it in {}/{} first.",
+                location, location, RIDDLE_FILE
+            );
+            player.responses.insert(location.to_string(), reply.clone());
+            return reply;
+        }
+        let passed = evaluation.as_ref().is_some_and(|e| e.passed);
+        let round = coop.and_then(|coop| {
+            player.answer_together(&self.world, &coop, location, response, passed)
+        });
+        let summary = match round {
+            Some(Round::Waiting(reply)) => {
+                player.responses.insert(location.to_string(), reply.clone());
+                return reply;
+            }
+            Some(Round::Complete(summary)) => Some(summary),
+            None => None,
+        };
+        let answered = player.completed_questions.len();
+        let before = player.snapshot();
+        let mut reply = player.respond(&self.world, location, response, evaluation);
+        if let Some(summary) = summary {
+            reply.push_str(&format!("\n\n{}", summary));
+        }
+        let completed = player.completed_questions.len() > answered;
+        if completed {
+            player.last_completed = Some(SystemTime::now());
+            player.philosophical_state.snapshots.push(before);
+            if let Some(time) = player.split(location) {
+                info!("The speedrun finished in {}", format_run_time(time));
+                reply.push_str(&format!("\n\nFinal time: {}", format_run_time(time)));
+            }
+        }
+        player.award_answer(response, completed);
+        player.responses.insert(location.to_string(), reply.clone());
+        self.write_chronicle();
+        self.finish_race();
+        reply
+    }
+
+    /// Composes the world again from its packs and brings the stage
+    /// directories up to date. Directories of stages which are gone are
+    /// left in place with the answers in them.
+    fn reload_world(&mut self) {
+        let world = match self.config.compose(&self.root.join(STATE_DIR)) {
+            Ok(world) => world,
+            Err(e) => {
+                warn!("Keeping the current world, the packs are invalid: {}", e);
+                return;
+            }
+        };
+        let (mut added, mut changed) = (0, 0);
+        for stage in world.stages.iter() {
+            match self.world.stage(&stage.dir) {
+                Some(old) if old.def == stage.def => continue,
+                Some(_) => changed += 1,
+                None => added += 1,
+            }
+            self.create_philosophical_directory(&stage.dir, &stage.def.question);
+        }
+        let removed = self
+            .world
+            .stages
+            .iter()
+            .filter(|old| world.stage(&old.dir).is_none())
+            .count();
+        self.world = world;
+        self.create_quest_directories();
+        self.create_sealed_files();
+        self.create_clue_files();
+        self.create_perception();
+        info!(
+            "Reloaded the world: {} stages added, {} changed, {} removed",
+            added, changed, removed
+        );
+    }
+
+    pub(crate) fn create_special_file(
+        &mut self,
+        filename: &str,
+        content: &str,
+    ) -> Result<(), std::io::Error> {
+        std::fs::write(self.root.join(filename), content)
+    }
+
+    fn create_timeline_tracker(&mut self) {
+        let content = "\
+            Timeline Tracker\n\
+            ===============\n\
+            Past, present, and future converge in this space.\n\
+            \n\
+            Current Timeline: Alpha\n\
+            Temporal Stability: 100%\n\
+            \n\
+            Recent Events:\n\
+            - Timeline initialized\n\
+            - Quantum fluctuations detected\n\
+            - Reality matrix stable\
+        ";
+
+        let _ = self.create_special_file(TIMELINE_FILE, content);
+    }
+
+    // Add helper method to update special files
+    async fn update_special_file(&mut self, filename: &str, new_content: &str) {
+        let mut file_path = self.root.clone();
+        file_path.push(filename);
+        let _ = tokio::fs::write(&file_path, new_content).await;
+    }
+
+    // Add method to update quantum state randomly
+    fn update_quantum_state(&mut self) {
+        let state = {
+            if self.rng.gen_bool(0.5) {
+                "COLLAPSED: PARTICLE"
+            } else {
+                "COLLAPSED: WAVE"
+            }
+        };
+
+        let content = format!(
+            "\
+            Quantum State Observation Log\n\
+            ==========================\n\
+            State collapsed by observation.\n\
+            \n\
+            Current State: [{}]\n\
+            Last Observation: {:?}\n\
+            Coherence: {:.2}%\
+        ",
+            state,
+            SystemTime::now(),
+            self.rng.gen_range(0.0..100.0)
+        );
+
+        self.quantum_state = content;
+        self.events.publish(EventKind::QuantumCollapse {
+            state: state.trim_start_matches("COLLAPSED: ").to_lowercase(),
+        });
+    }
+
+    /// Applies a scheduled world event to the world
+    async fn apply_world_event(&mut self, event: WorldEvent) {
+        debug!("Applying world event {:?}", event);
+        let description = match event {
+            WorldEvent::RotateKoan => {
+                let index = self
+                    .game_state
+                    .get("koan_index")
+                    .and_then(|i| i.parse::<usize>().ok())
+                    .map_or(0, |i| (i + 1) % KOANS.len());
+                self.game_state
+                    .insert("koan_index".to_string(), index.to_string());
+                let content = format!("Koan of the Day\n===============\n\n{}\n", KOANS[index]);
+                self.update_special_file("koan.txt", &content).await;
+                "A new koan appears"
+            }
+            WorldEvent::OpenDreams => {
+                let content = "\
+                    The Dream Directory\n\
+                    ===================\n\
+                    Night has fallen and the dreams are open to you.\n\
+                    What you find here may not be there in the morning.\n";
+                let _ = tokio::fs::create_dir_all(self.root.join(DREAMS_DIR)).await;
+                self.update_special_file("dreams/README.txt", content).await;
+                "The dream directory opens"
+            }
+            WorldEvent::ShiftDreams => {
+                // far too frequent to be noted in the timelines
+                self.shift_dreams().await;
+                return;
+            }
+            WorldEvent::CompanionLetter => {
+                let date = chrono::Local::now().format("%Y-%m-%d");
+                // with several players the letter is for the one furthest along
+                let leader = self.leading_player().cloned().unwrap_or_default();
+                let content = format!(
+                    "Dear traveller,\n\n\
+                     Another week has passed. You stand in the {:?} stage, \
+                     having answered {} of the questions.\n\
+                     {}\n\n\
+                     Your companion on the path\n",
+                    leader.current_stage,
+                    leader.completed_questions.len(),
+                    leader.get_current_hint(&self.world)
+                );
+                let _ = tokio::fs::create_dir_all(self.root.join("letters")).await;
+                self.update_special_file(&format!("letters/letter-{}.txt", date), &content)
+                    .await;
+                "A letter from your companion arrives"
+            }
+            WorldEvent::DailyChallenge => {
+                self.rotate_daily_challenge();
+                "A new daily challenge appears"
+            }
+        };
+        self.record_event(description);
+    }
+}
+
+#[async_trait]
+impl GameEngine for Philosophy {
+    fn set_events(&mut self, events: Events) {
+        self.events = events;
+    }
+
+    fn setup(&mut self, root: &Path) {
+        self.root = root.to_path_buf();
+        self.restore_game();
+
+        self.lay_out();
+        self.move_history();
+
+        self.open_versioning();
+        self.commit_journey("eternal-fs", "Lay out the world", &[]);
+    }
+
+    async fn on_write(&mut self, path: &Path, offset: u64, data: &[u8]) -> WriteAction {
+        if path == Path::new(HINTS_DIR).join("request.txt") {
+            self.process_hint_request();
+            // asking is all there is to it, nothing is kept
+            return WriteAction::Consumed;
+        }
+        self.use_artifact(path, offset, data);
+        if offset == 0 {
+            self.keep_history(path, Some(data));
+        }
+        self.seal(path);
+        if self.disentangle(path) {
+            // the pair is prepared anew, whatever was written
+            return WriteAction::Consumed;
+        }
+        if Self::is_journal_entry(path) {
+            if let Err(e) = self.write_journal(path, offset, data).await {
+                warn!("Unable to write the journal entry {:?}: {:?}", path, e);
+            }
+            return WriteAction::Consumed;
+        }
+        if path == Path::new(PERCEPTION_FILE) {
+            self.process_perception(&String::from_utf8_lossy(data));
+            return WriteAction::Consumed;
+        }
+        if path == Path::new(TIMELINE_FILE) {
+            if std::str::from_utf8(data).is_ok_and(|s| s.trim() == "rewind") {
+                self.process_rewind();
+                return WriteAction::Consumed;
+            }
+            return WriteAction::Write;
+        }
+        // names on the host need not be UTF-8, and an answer written in a
+        // directory whose name is not is taken all the same
+        match path.file_name() {
+            Some(name) if name == OsStr::new(self.answer_file()) => {
+                let content = String::from_utf8_lossy(data);
+                let content = content.as_ref();
+                let location = path.parent().unwrap_or(Path::new("")).to_string_lossy();
+                let location = location.as_ref();
+                let accepted = self.accepted_answers();
+                let stage = self.caller_stage();
+                let response = match self.answer_quest(location, content) {
+                    Some(reply) => reply,
+                    None => self.process_philosophical_response(location, content).await,
+                };
+
+                // Create system_response.txt in the same directory. When
+                // every player has a journey of their own it is only
+                // written to tell clients it changed, the contents are
+                // the player's.
+                let response_path = self.root.join(path).with_file_name("system_response.txt");
+                tokio::fs::write(&response_path, &response).await.ok();
+                if self.accepted_answers() > accepted {
+                    let player = self.player_identity.current();
+                    self.events.publish(EventKind::AnswerAccepted {
+                        player: player.clone(),
+                        location: location.to_string(),
+                    });
+                    if self.caller_stage() != stage {
+                        self.events.publish(EventKind::StageAdvanced {
+                            player: player.clone(),
+                            from: stage,
+                            to: self.caller_stage(),
+                        });
+                    }
+                    let message = format!(
+                        "Answer {}\n\nThe journey is at {:?}.\n",
+                        location, self.players[&player].current_stage
+                    );
+                    self.commit_journey(&player, &message, &[(path, data)]);
+                }
+                WriteAction::Write
+            }
+            _ => WriteAction::Write,
+        }
+    }
+
+    fn is_hidden(&self, path: &Path) -> bool {
+        self.hidden(path)
+            || self.labyrinth_hidden(path)
+            || self.mirrors_hidden(path)
+            || self.chronicle_hidden(path)
+    }
+
+    fn sees_true_size(&self, _path: &Path) -> bool {
+        self.sees_through()
+    }
+
+    fn synthetic_attrs(&self, path: &Path) -> Option<SyntheticAttrs> {
+        self.clue_attrs(path)
+    }
+
+    fn on_truncate(&mut self, path: &Path, size: u64) {
+        let shrinks = std::fs::metadata(self.root.join(path)).is_ok_and(|meta| meta.len() > size);
+        if shrinks {
+            self.keep_history(path, None);
+        }
+    }
+
+    fn on_create(&mut self, path: &Path, dir: bool) {
+        self.find_egg(path, dir);
+        self.perform_rebirth(path, dir);
+    }
+
+    fn on_observe(&mut self, path: &Path) {
+        self.observe_entangled(path);
+        self.look_into_mirrors(path);
+    }
+
+    fn on_read(&self, path: &Path) -> Option<Vec<u8>> {
+        self.sealed_contents(path)
+            .or_else(|| self.unsealed_contents(path))
+            .or_else(|| self.entangled_contents(path))
+            .or_else(|| self.personal_contents(path))
+    }
+
+    fn on_readlink(&self, path: &Path) -> Option<PathBuf> {
+        self.reflection(path)
+    }
+
+    fn on_lookup(&mut self, path: &Path) -> Result<(), nfsstat3> {
+        self.start_speedrun();
+        self.record_visit(path);
+        self.explore_labyrinth(path);
+        self.enter_mirrors(path);
+        Ok(())
+    }
+
+    fn on_list(&mut self, dir: &Path) {
+        self.explore_labyrinth(dir);
+        self.enter_mirrors(dir);
+    }
+
+    fn generated_dirs(&self) -> Vec<String> {
+        vec![ACHIEVEMENTS_DIR.to_string(), INVENTORY_DIR.to_string()]
+    }
+
+    fn generated_files(&self, dir: &str) -> Vec<GeneratedFile> {
+        match dir {
+            ACHIEVEMENTS_DIR => self.achievements(),
+            INVENTORY_DIR => self.inventory(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn virtual_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = VIRTUAL_FILES
+            .iter()
+            .map(|name| self.journey_path(name).to_path_buf())
+            .collect();
+        files.extend(self.npc_paths());
+        files.extend(self.riddle_paths());
+        files.extend(self.seal_key_paths());
+        files.extend(self.toolbox_paths());
+        files.extend(self.observed_question_paths());
+        files.extend(self.history_paths());
+        files.extend(self.race_paths());
+        files
+    }
+
+    fn open_virtual(&mut self, path: &Path) -> Option<Box<dyn VirtualFile + '_>> {
+        match VIRTUAL_FILES
+            .iter()
+            .find(|name| path == self.journey_path(name))
+        {
+            Some(name) => Some(Box::new(JourneyFile { game: self, name })),
+            None if path.parent().and_then(|dir| dir.file_name()) == Some(HISTORY_DIR.as_ref()) => {
+                self.open_history(path)
+            }
+            None if path.file_name() == Some(RIDDLE_FILE.as_ref()) => self.open_riddle(path),
+            None if path.file_name() == Some(QUESTION_FILE.as_ref()) => {
+                self.open_observed_question(path)
+            }
+            None if path.extension() == Some(KEY_EXTENSION.as_ref()) => self.open_seal_key(path),
+            None if path.starts_with(TOOLBOX_DIR) => self.open_tool(path),
+            None if self.is_race_file(path) => {
+                let name = if path == Path::new(RACE_FILE) {
+                    RACE_FILE
+                } else {
+                    WINNER_FILE
+                };
+                Some(Box::new(JourneyFile { game: self, name }))
+            }
+            None => self.open_npc(path),
+        }
+    }
+
+    fn reload(&mut self) {
+        self.reload_world();
+    }
+
+    fn stage_physics(&self) -> HashMap<OsString, Vec<Physics>> {
+        self.world.physics()
+    }
+
+    fn progress_report(&self) -> ProgressReport {
+        let newcomer = PlayerState::default();
+        let player = self
+            .players
+            .get(&self.player_identity.current())
+            .unwrap_or(&newcomer);
+        ProgressReport {
+            answered: player.completed_questions.len() as u64,
+            total: self.world.total_questions(),
+            text: player.progress(&self.world),
+        }
+    }
+
+    fn status_report(&self) -> String {
+        self.game_status()
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self.saved_game()).unwrap_or_default()
+    }
+
+    fn dashboard(&self) -> serde_json::Value {
+        self.dashboard_status()
+    }
+
+    fn splits_report(&self) -> String {
+        self.speedrun_splits()
+    }
+
+    async fn on_event(&mut self, event: WorldEvent) {
+        self.apply_world_event(event).await;
+    }
+
+    fn save(&mut self) -> std::io::Result<()> {
+        self.save_game()
+    }
+}
+
+/// A file of the journey served from memory, as the player making the
+/// call sees it
+struct JourneyFile<'a> {
+    game: &'a mut Philosophy,
+    name: &'static str,
+}
+
+impl JourneyFile<'_> {
+    fn contents(&self) -> String {
+        let game = &*self.game;
+        match self.name {
+            PROGRESS_FILE => game.progress_report().text,
+            QUANTUM_STATE_FILE => game.quantum_state.clone(),
+            LEADERBOARD_FILE => game.leaderboard(),
+            ORACLE_FILE => game.oracle(),
+            MEDITATION_FILE => game.meditation(),
+            CONTROL_FILE => game.control(),
+            DAILY_FILE => game.daily(),
+            RACE_FILE => game.race(),
+            WINNER_FILE => game.winner_chronicle(),
+            _ => String::new(),
+        }
+    }
+}
+
+impl VirtualFile for JourneyFile<'_> {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        if self.name == CONTROL_FILE && !is_operator() {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+        if self.name == MEDITATION_FILE {
+            self.game.observe_meditation();
+        }
+        Ok(read_at(self.contents().as_bytes(), offset, count))
+    }
+
+    fn write(&mut self, _offset: u64, data: &[u8]) -> Result<(), nfsstat3> {
+        match self.name {
+            // the quantum state is randomly generated, not written
+            QUANTUM_STATE_FILE => self.game.update_quantum_state(),
+            ORACLE_FILE => self.game.consult_oracle(&String::from_utf8_lossy(data)),
+            CONTROL_FILE if is_operator() => {
+                self.game.process_control(&String::from_utf8_lossy(data))
+            }
+            CONTROL_FILE => return Err(nfsstat3::NFS3ERR_ACCES),
+            DAILY_FILE => self.game.answer_daily(&String::from_utf8_lossy(data)),
+            _ => return Err(nfsstat3::NFS3ERR_ROFS),
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.contents().len() as u64
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        let mode = match self.name {
+            QUANTUM_STATE_FILE | ORACLE_FILE | DAILY_FILE => 0o644,
+            CONTROL_FILE => 0o600,
+            _ => 0o444,
+        };
+        // the contents are made up anew for every call
+        VirtualAttrs {
+            mode,
+            mtime: SystemTime::now(),
+        }
+    }
+}
+
+impl PlayerState {
+    /// Judges an answer given at `location` and returns the reply. The
+    /// evaluation is that of the stage at `location`, if the answer is
+    /// long enough to be judged.
+    fn respond(
+        &mut self,
+        world: &World,
+        location: &str,
+        response: &str,
+        evaluation: Option<Evaluation>,
+    ) -> String {
+        let response_quality = response.len() > world.min_answer_length;
+        let passed = evaluation.as_ref().is_some_and(|e| e.passed);
+        let reply_to = |stage: &Stage| {
+            evaluation
+                .as_ref()
+                .and_then(|e| e.reply.clone())
+                .unwrap_or_else(|| stage.def.reply.clone())
+        };
+
+        if let Some(stage) = world.stage(location).filter(|s| s.pack != "core") {
+            return self.process_pack_response(world, stage, response, passed, reply_to(stage));
+        }
+
+        let open = self.open_stages(world);
+        let answered = open.iter().find(|stage| stage.dir == location).copied();
+        let (reply, should_advance) = match answered {
+            // Response too short
+            _ if !response_quality => (too_short(world, response), false),
+            // A core stage whose prerequisites have all been answered
+            Some(stage) if passed => {
+                self.completed_questions.insert(stage.dir.clone());
+                (reply_to(stage), true)
+            }
+            // Enlightenment Path (Final Stage)
+            _ if open.is_empty() && self.enlightened_by(response) => {
+                self.completed_questions.insert(ENLIGHTENMENT.to_string());
+                (
+                    "You have reached enlightenment. All paths converge in understanding."
+                        .to_string(),
+                    true,
+                )
+            }
+            // Wrong stage or location
+            _ => (
+                format!(
+                    "You are currently in the {:?} stage. The path of {} is not yet ready for you.",
+                    self.current_stage, location
+                ),
+                false,
+            ),
+        };
+
+        // Advance stage if needed
+        if should_advance {
+            // the final answer is kept apart from the stage it was given in
+            let archived = answered.map_or(ENLIGHTENMENT, |stage| stage.dir.as_str());
+            self.archive_answer(archived, response);
+            self.current_stage = match answered {
+                Some(stage) => {
+                    GameStage::reached_by(&stage.def.name).unwrap_or(self.current_stage.clone())
+                }
+                None => GameStage::Enlightened,
+            };
+        } else if self.answer_archive.contains_key(location) {
+            // The player reopened an answer that was already accepted
+            return self.process_answer_revision(location, response);
+        }
+
+        reply
+    }
+
+    /// Whether `response`, given once no core stage is left to answer,
+    /// reaches enlightenment
+    pub(crate) fn enlightened_by(&self, response: &str) -> bool {
+        !matches!(self.current_stage, GameStage::Enlightened)
+            && ENLIGHTENMENT_KEYWORDS
+                .iter()
+                .all(|k| response.to_lowercase().contains(k))
+    }
+
+    /// The core stages the player may answer now: those not answered yet
+    /// whose prerequisites all are. None once all of them have been
+    /// answered and only enlightenment is left, or reached.
+    pub(crate) fn open_stages<'w>(&self, world: &'w World) -> Vec<&'w Stage> {
+        world
+            .core_stages()
+            .filter(|stage| !self.completed_questions.contains(&stage.dir))
+            .filter(|stage| self.missing_prerequisites(world, stage).is_empty())
+            .collect()
+    }
+
+    /// The stages to be answered before `stage` which have not been
+    pub(crate) fn missing_prerequisites<'w>(
+        &self,
+        world: &'w World,
+        stage: &Stage,
+    ) -> Vec<&'w Stage> {
+        world
+            .prerequisites(stage)
+            .into_iter()
+            .filter(|s| !self.completed_questions.contains(&s.dir))
+            .collect()
+    }
+
+    /// Takes up a judged answer to a stage from a world pack and returns
+    /// `reply` if it passed. Pack stages do not move the player to another
+    /// stage.
+    fn process_pack_response(
+        &mut self,
+        world: &World,
+        stage: &Stage,
+        response: &str,
+        passed: bool,
+        reply: String,
+    ) -> String {
+        let missing = self.missing_prerequisites(world, stage);
+        if !missing.is_empty() {
+            let dirs: Vec<&str> = missing.iter().map(|s| s.dir.as_str()).collect();
+            return format!(
+                "The {} stage is not yet ready for you. Answer {} first.",
+                stage.dir,
+                dirs.join(" and ")
+            );
+        }
+        if passed {
+            self.completed_questions.insert(stage.dir.clone());
+            self.archive_answer(&stage.dir, response);
+            reply
+        } else if self.answer_archive.contains_key(&stage.dir) {
+            self.process_answer_revision(&stage.dir, response)
+        } else if response.len() <= world.min_answer_length {
+            too_short(world, response)
+        } else {
+            format!("The {} stage awaits a deeper answer.", stage.dir)
+        }
+    }
+
+    fn archive_answer(&mut self, location: &str, response: &str) {
+        self.answer_archive
+            .entry(location.to_string())
+            .or_default()
+            .push(ArchivedAnswer {
+                text: response.to_string(),
+                archived_at: SystemTime::now(),
+            });
+    }
+
+    /// Compares an edited answer against the last archived version and
+    /// lets the narrator comment on what changed. Both versions are kept.
+    fn process_answer_revision(&mut self, location: &str, response: &str) -> String {
+        let Some(previous) = self
+            .answer_archive
+            .get(location)
+            .and_then(|versions| versions.last())
+            .cloned()
+        else {
+            return String::new();
+        };
+
+        if previous.text.trim() == response.trim() {
+            return format!(
+                "You return to the path of {} and write the very same words. \
+                 Some convictions do not waver.",
+                location
+            );
+        }
+
+        let commentary = narrate_revision(&previous.text, response);
+        self.archive_answer(location, response);
+        self.philosophical_state
+            .note_event(SystemTime::now(), format!("Answer in {} revised", location));
+        let revisions = self.answer_archive.get(location).map_or(0, Vec::len);
+
+        let elapsed = SystemTime::now()
+            .duration_since(previous.archived_at)
+            .unwrap_or_default()
+            .as_secs();
+        format!(
+            "You have returned to your answer on the path of {} after {} seconds.\n\
+             {}\n\
+             Both versions are kept in the archive of your thoughts (revision {}).",
+            location, elapsed, commentary, revisions
+        )
+    }
+
+    /// What progress.txt tells the player
+    pub(crate) fn progress(&self, world: &World) -> String {
+        format!(
+            "Journey Progress\n\
+            ===============\n\n\
+            {}\n\
+            Current Stage: {:?}\n\
+            Progress: {}/{}\n{}\n\
+            Active Challenge: {}\n\
+            Next Stage: {}\n{}\n\
+            Hint: {}\n",
+            self.map(world),
+            self.current_stage,
+            self.completed_questions.len(),
+            world.total_questions(),
+            self.side_quests(world),
+            self.get_current_challenge(world),
+            self.get_next_stage_name(world),
+            self.get_open_paths(world),
+            self.get_current_hint(world)
+        )
+    }
+
+    /// How many of the side quests of the world the player completed, if
+    /// it has any
+    fn side_quests(&self, world: &World) -> String {
+        match world.quests.len() {
+            0 => String::new(),
+            total => format!("Side Quests: {}/{}\n", self.quests_completed.len(), total),
+        }
+    }
+
+    fn get_current_challenge(&self, world: &World) -> String {
+        match (&self.current_stage, self.open_stages(world).first()) {
+            (GameStage::Enlightened, _) => "You have completed all challenges".to_string(),
+            (_, Some(stage)) => stage.def.challenge.clone(),
+            (_, None) => "Achieve enlightenment through understanding".to_string(),
+        }
+    }
+
+    /// The stages which may be answered now, as the player sees them
+    fn get_next_stage_name(&self, world: &World) -> String {
+        let open = self.open_stages(world);
+        match &self.current_stage {
+            GameStage::Enlightened => "Complete".to_string(),
+            _ if open.is_empty() => "Enlightenment".to_string(),
+            _ => {
+                let names: Vec<String> = open
+                    .iter()
+                    .map(|stage| {
+                        let mut chars = stage.def.name.chars();
+                        chars
+                            .next()
+                            .map(|first| first.to_uppercase().chain(chars).collect())
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                names.join(", ")
+            }
+        }
+    }
+
+    /// The challenge of every path open at once, when the journey branches
+    fn get_open_paths(&self, world: &World) -> String {
+        let open = self.open_stages(world);
+        if open.len() < 2 {
+            return String::new();
+        }
+        let paths: String = open
+            .iter()
+            .map(|stage| format!("  {}: {}\n", stage.dir, stage.def.challenge))
+            .collect();
+        format!("Open Paths:\n{}", paths)
+    }
+
+    fn get_current_hint(&self, world: &World) -> String {
+        match (&self.current_stage, self.open_stages(world).first()) {
+            (GameStage::Enlightened, _) => "Reflect on your journey".to_string(),
+            (_, Some(stage)) => stage.def.hint.clone(),
+            (_, None) => "What patterns do you see in randomness?".to_string(),
+        }
+    }
+}
+
+pub(crate) fn too_short(world: &World, response: &str) -> String {
+    format!(
+        "Your response must be more thoughtful (>{} characters). Current length: {}",
+        world.min_answer_length,
+        response.len()
+    )
+}
+
+/// Describes, in the narrator's voice, how an answer changed between two versions
+fn narrate_revision(previous: &str, revised: &str) -> String {
+    let diff = TextDiff::from_words(previous, revised);
+    let mut removed: Vec<String> = Vec::new();
+    let mut added: Vec<String> = Vec::new();
+    for op in diff.ops() {
+        let mut old_phrase = Vec::new();
+        let mut new_phrase = Vec::new();
+        for change in diff.iter_changes(op) {
+            let word = change.value().trim();
+            if word.is_empty() {
+                continue;
+            }
+            match change.tag() {
+                ChangeTag::Delete => old_phrase.push(word),
+                ChangeTag::Insert => new_phrase.push(word),
+                ChangeTag::Equal => {}
+            }
+        }
+        if !old_phrase.is_empty() {
+            removed.push(old_phrase.join(" "));
+        }
+        if !new_phrase.is_empty() {
+            added.push(new_phrase.join(" "));
+        }
+    }
+
+    let mut lines = Vec::new();
+    let similarity = diff.ratio();
+    lines.push(if similarity > 0.8 {
+        "Only a subtle shift ripples through your thoughts.".to_string()
+    } else if similarity > 0.4 {
+        "Your thinking has moved since you last wrote here.".to_string()
+    } else {
+        "Your former self would hardly recognize these words.".to_string()
+    });
+    if let Some(phrase) = removed.first() {
+        lines.push(format!("You no longer believe that \"{}\"?", phrase));
+    }
+    if let Some(phrase) = added.first() {
+        lines.push(format!("Now you speak of \"{}\".", phrase));
+    }
+    lines.join("\n")
+}
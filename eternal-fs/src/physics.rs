@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::io::SeekFrom;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer};
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::debug;
+
+use nfsserve::auth::client_ip;
+use nfsserve::nfs::fileid3;
+
+/// A modification of the normal filesystem behaviour inside one stage
+/// directory, declared by the stage in its pack, as in
+/// `physics = [{ delayed_writes = 30 }, "jittered_sizes"]`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Physics {
+    /// Writes only reach the host file after the given delay, in seconds
+    /// in a pack
+    DelayedWrites(#[serde(deserialize_with = "seconds")] Duration),
+    /// Reported file sizes wobble by a byte until the file is first read
+    JitteredSizes,
+    /// New entries get unpredictable fileids, so directory listings no
+    /// longer follow creation order
+    ShuffledCreation,
+    /// The stage directory, unlike those below it, lists its entries in a
+    /// new order every time, now and then with a phantom among them which
+    /// is gone once looked up
+    ChaoticListings,
+}
+
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+/// Names phantom entries of chaotic listings go by
+pub const PHANTOMS: &[&str] = &[
+    "echo",
+    "whisper.txt",
+    "not_here",
+    "flicker",
+    "mirage.txt",
+    "the_answer.txt",
+];
+
+/// How likely a listing of a chaotic directory is to show a phantom
+pub const PHANTOM_CHANCE: f64 = 0.3;
+
+/// The order a client lists a chaotic directory in. It is drawn when the
+/// client starts listing the directory and kept for the rest of that
+/// listing, so that every entry is listed once however the listing is
+/// split into pages.
+#[derive(Debug, Clone, Default)]
+pub struct ChaoticListing {
+    /// The fileids of the entries, in the order they are listed
+    pub order: Vec<fileid3>,
+    /// The fileid and name of the phantom listed after them, if any
+    pub phantom: Option<(fileid3, &'static str)>,
+}
+
+impl ChaoticListing {
+    /// The fileids listed after the cookie `start_after`
+    pub fn after(&self, start_after: fileid3) -> &[fileid3] {
+        if start_after == 0 {
+            return &self.order;
+        }
+        if self.phantom.is_some_and(|(id, _)| id == start_after) {
+            return &[];
+        }
+        match self.order.iter().position(|&id| id == start_after) {
+            Some(i) => &self.order[i + 1..],
+            // a cookie of an earlier listing, better listed twice than lost
+            None => &self.order,
+        }
+    }
+}
+
+/// The physics the stages of the world declare, by stage subtree,
+/// together with the small amount of state the rules need.
+///
+/// Stages are identified by the name of their top level directory, so
+/// `time` applies to everything below `/time/`.
+#[derive(Debug, Default)]
+pub struct StagePhysics {
+    rules: HashMap<OsString, Vec<Physics>>,
+    /// fileids that have been read and have therefore collapsed
+    observed: HashSet<fileid3>,
+    /// The listings of chaotic directories going on, by directory and
+    /// client address
+    listings: HashMap<(fileid3, Option<IpAddr>), ChaoticListing>,
+}
+
+impl StagePhysics {
+    pub fn new(rules: HashMap<OsString, Vec<Physics>>) -> StagePhysics {
+        StagePhysics {
+            rules,
+            observed: HashSet::new(),
+            listings: HashMap::new(),
+        }
+    }
+
+    /// Takes up the physics of a world whose stages changed, keeping what
+    /// was observed under the old ones
+    pub fn declare(&mut self, rules: HashMap<OsString, Vec<Physics>>) {
+        self.rules = rules;
+    }
+
+    pub fn rules_for(&self, stage: &OsStr) -> &[Physics] {
+        self.rules.get(stage).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns how long writes are held back in a stage, if they are
+    pub fn write_delay(&self, stage: &OsStr) -> Option<Duration> {
+        self.rules_for(stage).iter().find_map(|p| match p {
+            Physics::DelayedWrites(delay) => Some(*delay),
+            _ => None,
+        })
+    }
+
+    pub fn jitters_sizes(&self, stage: &OsStr) -> bool {
+        self.rules_for(stage).contains(&Physics::JitteredSizes)
+    }
+
+    pub fn shuffles_creation(&self, stage: &OsStr) -> bool {
+        self.rules_for(stage).contains(&Physics::ShuffledCreation)
+    }
+
+    pub fn chaotic_listings(&self, stage: &OsStr) -> bool {
+        self.rules_for(stage).contains(&Physics::ChaoticListings)
+    }
+
+    /// Notes the order the calling client starts listing the chaotic
+    /// directory `dir` in
+    pub fn start_listing(&mut self, dir: fileid3, listing: ChaoticListing) {
+        self.listings.insert((dir, client_ip()), listing);
+    }
+
+    /// The order the calling client lists the chaotic directory `dir` in,
+    /// once it started listing it
+    pub fn listing(&self, dir: fileid3) -> Option<&ChaoticListing> {
+        self.listings.get(&(dir, client_ip()))
+    }
+
+    /// Records that a file has been read
+    pub fn observe(&mut self, id: fileid3) {
+        self.observed.insert(id);
+    }
+
+    pub fn is_observed(&self, id: fileid3) -> bool {
+        self.observed.contains(&id)
+    }
+}
+
+#[derive(Debug)]
+struct PendingWrite {
+    path: PathBuf,
+    offset: u64,
+    data: Vec<u8>,
+    due: Instant,
+}
+
+/// Writes that have been accepted but are not yet visible on the host.
+///
+/// Each scheduled write spawns a timer which flushes every write of that
+/// fileid that is due, so writes always land in the order they were made.
+/// Until then they are unstable, and land early once a client commits
+/// them.
+#[derive(Debug, Default)]
+pub struct DelayedWrites {
+    pending: Mutex<HashMap<fileid3, VecDeque<PendingWrite>>>,
+}
+
+impl DelayedWrites {
+    pub async fn schedule(
+        self: &Arc<Self>,
+        id: fileid3,
+        path: PathBuf,
+        offset: u64,
+        data: &[u8],
+        delay: Duration,
+    ) {
+        debug!("delaying write to {:?} by {:?}", path, delay);
+        self.pending
+            .lock()
+            .await
+            .entry(id)
+            .or_default()
+            .push_back(PendingWrite {
+                path,
+                offset,
+                data: data.to_vec(),
+                due: Instant::now() + delay,
+            });
+        let this = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            this.flush_due(id).await;
+        });
+    }
+
+    /// Where the furthest of the writes pending to `id` ends, if any are
+    pub async fn pending_end(&self, id: fileid3) -> Option<u64> {
+        self.pending
+            .lock()
+            .await
+            .get(&id)?
+            .iter()
+            .map(|w| w.offset + w.data.len() as u64)
+            .max()
+    }
+
+    /// Applies all writes to `id` whose delay has elapsed
+    pub async fn flush_due(&self, id: fileid3) {
+        let mut pending = self.pending.lock().await;
+        let Some(queue) = pending.get_mut(&id) else {
+            return;
+        };
+        let now = Instant::now();
+        while queue.front().is_some_and(|w| w.due <= now) {
+            let write = queue.pop_front().unwrap();
+            if let Err(e) = apply(&write).await {
+                debug!("Unable to apply delayed write to {:?}: {:?}", write.path, e);
+            }
+        }
+        if queue.is_empty() {
+            pending.remove(&id);
+        }
+    }
+
+    /// Applies every pending write to `id` right away, due or not
+    pub async fn flush(&self, id: fileid3) {
+        let mut pending = self.pending.lock().await;
+        let Some(queue) = pending.remove(&id) else {
+            return;
+        };
+        for write in queue {
+            if let Err(e) = apply(&write).await {
+                debug!("Unable to apply delayed write to {:?}: {:?}", write.path, e);
+            }
+        }
+    }
+
+    /// Applies every pending write right away, due or not
+    pub async fn flush_all(&self) {
+        let mut pending = self.pending.lock().await;
+        for (_, queue) in pending.drain() {
+            for write in queue {
+                if let Err(e) = apply(&write).await {
+                    debug!("Unable to apply delayed write to {:?}: {:?}", write.path, e);
+                }
+            }
+        }
+    }
+}
+
+async fn apply(write: &PendingWrite) -> std::io::Result<()> {
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&write.path)
+        .await?;
+    f.seek(SeekFrom::Start(write.offset)).await?;
+    f.write_all(&write.data).await?;
+    f.sync_all().await
+}
@@ -0,0 +1,255 @@
+//! Several people can play in the same world. Every player has a journey
+//! of their own: their stage, their answers, and what the world replies
+//! to them in `progress.txt` and `system_response.txt`. How they stand
+//! against each other is shown to everyone in `leaderboard.txt`. What is
+//! saved of every journey stays in the state directory, out of reach of
+//! the players themselves.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use nfsserve::auth::{caller, client_ip};
+
+use crate::chronicle::CHRONICLE_FILE;
+use crate::coop::Contribution;
+use crate::dialogue::Conversation;
+use crate::hints::HINTS_DIR;
+use crate::meditation::Meditation;
+use crate::perception::PERCEPTION_FILE;
+use crate::philosophy::{ArchivedAnswer, GameStage, PhilosophicalState, Philosophy};
+use crate::riddles::RiddleAttempt;
+use crate::spectator::{spectating, watched_player};
+use crate::speedrun::Speedrun;
+use crate::timeline::TIMELINE_FILE;
+
+/// Player the journey of a world is kept for when it is shared, and for
+/// anything happening outside of a call
+pub(crate) const SHARED_PLAYER: &str = "shared";
+
+/// The standings of the players, made up whenever it is read
+pub(crate) const LEADERBOARD_FILE: &str = "leaderboard.txt";
+
+/// How the clients of a world are told apart as players
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlayerIdentity {
+    /// Everyone plays the same journey
+    #[default]
+    Shared,
+    /// Every client address plays a journey of its own
+    Address,
+    /// Every AUTH_SYS uid plays a journey of its own
+    Uid,
+}
+
+impl FromStr for PlayerIdentity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shared" => Ok(PlayerIdentity::Shared),
+            "address" => Ok(PlayerIdentity::Address),
+            "uid" => Ok(PlayerIdentity::Uid),
+            _ => Err(format!("unknown player identity {:?}", s)),
+        }
+    }
+}
+
+impl PlayerIdentity {
+    /// The player making the call being served, or watched by the
+    /// spectator making it
+    pub(crate) fn current(&self) -> String {
+        let player = match self {
+            PlayerIdentity::Shared => None,
+            _ if spectating() => watched_player(),
+            PlayerIdentity::Address => client_ip().map(|ip| ip.to_string()),
+            PlayerIdentity::Uid => caller().map(|caller| format!("uid:{}", caller.uid)),
+        };
+        player.unwrap_or_else(|| SHARED_PLAYER.to_string())
+    }
+}
+
+/// The journey of one player
+#[derive(Debug, Clone)]
+pub(crate) struct PlayerState {
+    pub(crate) current_stage: GameStage,
+    pub(crate) completed_questions: HashSet<String>,
+    /// Every accepted version of an answer, oldest first, keyed by location
+    pub(crate) answer_archive: HashMap<String, Vec<ArchivedAnswer>>,
+    /// The last reply to an answer, by location
+    pub(crate) responses: HashMap<String, String>,
+    pub(crate) philosophical_state: PhilosophicalState,
+    /// When every achievement the player has was earned, by achievement
+    pub(crate) achievements: BTreeMap<String, SystemTime>,
+    /// The stage directories the player has been to
+    pub(crate) visited: HashSet<String>,
+    /// When the journey began
+    pub(crate) started: SystemTime,
+    /// When the player last completed a stage
+    pub(crate) last_completed: Option<SystemTime>,
+    /// Hint tokens spent so far
+    pub(crate) hint_tokens_spent: u32,
+    /// How many hints the player took for a stage, by stage
+    pub(crate) hints_taken: BTreeMap<String, usize>,
+    /// What hints/latest.txt shows the player, once they asked for a hint
+    pub(crate) latest_hint: Option<String>,
+    /// The oracle's answer to the player's last question
+    pub(crate) oracle: Option<String>,
+    /// What the player last wrote to the ciphers of the toolbox, by cipher
+    pub(crate) toolbox: BTreeMap<String, Vec<u8>>,
+    /// The question of the perception path, as the player last observed it
+    pub(crate) observed_question: Option<String>,
+    /// The player's meditation, once they sat down to one
+    pub(crate) meditation: Option<Meditation>,
+    /// The artifacts the player used, by stage
+    pub(crate) used_artifacts: BTreeMap<String, BTreeSet<String>>,
+    /// The player's conversations with the characters, by file
+    pub(crate) conversations: BTreeMap<String, Conversation>,
+    /// What the riddles of the journey are made up from
+    pub(crate) riddle_seed: u32,
+    /// How the player fares with the riddles, by stage
+    pub(crate) riddles: BTreeMap<String, RiddleAttempt>,
+    /// The days the player met the daily challenge of
+    pub(crate) daily_completed: BTreeSet<String>,
+    /// The chronicle of the journey, once it reached enlightenment
+    pub(crate) chronicle: Option<String>,
+    /// The speedrun of the journey, once its clock started
+    pub(crate) speedrun: Option<Speedrun>,
+    /// The side quests completed, with the hint tokens they granted
+    pub(crate) quests_completed: BTreeMap<String, u32>,
+    /// The sealed files unsealed, by path
+    pub(crate) unsealed: BTreeSet<String>,
+    /// The easter eggs found, by name
+    pub(crate) eggs_found: BTreeSet<String>,
+    /// The answers waiting for more contributors, by stage
+    pub(crate) coop: BTreeMap<String, Vec<Contribution>>,
+}
+
+impl Default for PlayerState {
+    fn default() -> Self {
+        PlayerState {
+            current_stage: GameStage::Beginning,
+            completed_questions: HashSet::new(),
+            answer_archive: HashMap::new(),
+            responses: HashMap::new(),
+            philosophical_state: PhilosophicalState {
+                emotional_state: "neutral".to_string(),
+                perception_filters: HashSet::new(),
+                quantum_states: HashMap::new(),
+                created_elements: Vec::new(),
+                timeline_events: Vec::new(),
+                snapshots: Vec::new(),
+                solved_puzzles: HashSet::new(),
+            },
+            achievements: BTreeMap::new(),
+            visited: HashSet::new(),
+            started: SystemTime::now(),
+            last_completed: None,
+            hint_tokens_spent: 0,
+            hints_taken: BTreeMap::new(),
+            latest_hint: None,
+            oracle: None,
+            toolbox: BTreeMap::new(),
+            observed_question: None,
+            meditation: None,
+            used_artifacts: BTreeMap::new(),
+            conversations: BTreeMap::new(),
+            riddle_seed: rand::random(),
+            riddles: BTreeMap::new(),
+            daily_completed: BTreeSet::new(),
+            chronicle: None,
+            speedrun: None,
+            quests_completed: BTreeMap::new(),
+            unsealed: BTreeSet::new(),
+            eggs_found: BTreeSet::new(),
+            coop: BTreeMap::new(),
+        }
+    }
+}
+
+impl PlayerState {
+    /// How long the player took to get as far as they are
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.last_completed
+            .and_then(|at| at.duration_since(self.started).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Philosophy {
+    /// The player furthest along their journey
+    pub(crate) fn leading_player(&self) -> Option<&PlayerState> {
+        self.players
+            .values()
+            .max_by_key(|player| player.completed_questions.len())
+    }
+
+    /// The contents of a file every player sees differently, if the object
+    /// at `path` is one. Nothing is personal when the journey is shared,
+    /// the host files are read then.
+    pub(crate) fn personal_contents(&self, path: &Path) -> Option<Vec<u8>> {
+        if self.player_identity == PlayerIdentity::Shared {
+            return None;
+        }
+        let newcomer = PlayerState::default();
+        let player = self
+            .players
+            .get(&self.player_identity.current())
+            .unwrap_or(&newcomer);
+        let location = path.parent()?.to_str()?;
+        let contents = match (location, path.file_name()?.to_str()?) {
+            (location, "system_response.txt")
+                if !location.contains('/') || self.world.quest(location).is_some() =>
+            {
+                player.responses.get(location).cloned().unwrap_or_default()
+            }
+            (HINTS_DIR, "latest.txt") => player.hint_text(self.hint_tokens()),
+            ("", TIMELINE_FILE) => player.timeline(),
+            ("", PERCEPTION_FILE) => player.perception(),
+            ("", CHRONICLE_FILE) => player.chronicle.clone().unwrap_or_default(),
+            _ => return None,
+        };
+        Some(contents.into_bytes())
+    }
+
+    /// The standings of every player who completed a stage: most stages
+    /// first, and of those the quickest to complete them
+    pub(crate) fn leaderboard(&self) -> String {
+        let mut standings: Vec<(&String, usize, Duration)> = self
+            .players
+            .iter()
+            .filter(|(_, player)| !player.completed_questions.is_empty())
+            .map(|(id, player)| (id, player.completed_questions.len(), player.elapsed()))
+            .collect();
+        standings.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)).then(a.0.cmp(b.0)));
+
+        let mut contents = String::from("Leaderboard\n===========\n\n");
+        if standings.is_empty() {
+            contents.push_str("No one has completed a stage yet.\n");
+        }
+        for (rank, (id, completed, elapsed)) in standings.into_iter().enumerate() {
+            let minutes = elapsed.as_secs() / 60;
+            contents.push_str(&format!(
+                "{:>3}. {:<24} {:>3}/{} stages  {}h {:02}m\n",
+                rank + 1,
+                id,
+                completed,
+                self.world.total_questions(),
+                minutes / 60,
+                minutes % 60
+            ));
+        }
+        contents
+    }
+
+    /// Notes an event in the timeline of every player
+    pub(crate) fn record_event(&mut self, event: &str) {
+        let now = SystemTime::now();
+        for player in self.players.values_mut() {
+            player
+                .philosophical_state
+                .note_event(now, event.to_string());
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! The types most integrations need, for glob importing:
+//!
+//! ```ignore
+//! use eternal_fs::prelude::*;
+//! ```
+//!
+//! Only items which are meant to stay stable across minor releases are
+//! re-exported here. Everything else remains reachable through its module.
+
+pub use crate::config::Config;
+pub use crate::engine::{GameEngine, ProgressReport, WriteAction};
+pub use crate::ephemeral::EphemeralRoot;
+pub use crate::events::{Event, EventKind};
+pub use crate::exports::Exports;
+pub use crate::packs::{StageDef, World, WorldPack};
+pub use crate::physics::Physics;
+pub use crate::scheduler::WorldEvent;
+pub use crate::{EternalFS, GameStage, Philosophy, STATE_DIR};
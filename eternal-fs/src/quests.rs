@@ -0,0 +1,98 @@
+//! Side quests, beside the stages of the journey.
+//!
+//! A pack can declare side quests, see
+//! [`WorldPack::quests`](crate::packs::WorldPack::quests). Every one has a
+//! directory of its own under `quests/` with its question, and is answered
+//! in its `answer.txt` like a stage. Unlike the stages, a side quest is
+//! open at any point of the journey: it waits on no stage, and no stage
+//! waits on it. An accepted answer grants the player hint tokens and earns
+//! achievements. The side quests a player completed are kept apart from
+//! the stages, so they neither move the player along the journey nor
+//! count towards enlightenment.
+
+use std::time::SystemTime;
+
+use tracing::{info, warn};
+
+use crate::philosophy::{too_short, Philosophy};
+use crate::players::PlayerState;
+
+/// The directory the side quests have theirs in
+pub(crate) const QUESTS_DIR: &str = "quests";
+
+impl PlayerState {
+    /// Hint tokens granted by the side quests completed
+    pub(crate) fn quest_hint_tokens(&self) -> u32 {
+        self.quests_completed.values().sum()
+    }
+}
+
+impl Philosophy {
+    /// Creates the directory of every side quest with its question
+    pub(crate) fn create_quest_directories(&mut self) {
+        for quest in self.world.quests.iter() {
+            let dir = self.root.join(&quest.dir);
+            let created = std::fs::create_dir_all(&dir)
+                .and_then(|_| std::fs::write(dir.join("question.txt"), &quest.def.question))
+                .and_then(|_| {
+                    std::fs::write(
+                        dir.join("README.txt"),
+                        format!(
+                            "The side quest of {}.\n\
+                             It lies off the path, and is open whenever you wish to take it.\n\
+                             Read the question in question.txt and answer it in {}.\n\
+                             Answering it well grants you hint tokens.",
+                            quest.def.name,
+                            self.answer_file()
+                        ),
+                    )
+                });
+            if let Err(e) = created {
+                warn!("Unable to create the side quest {:?}: {:?}", dir, e);
+            }
+        }
+    }
+
+    /// Takes `response` as the answer of the player making the call to the
+    /// side quest at `location`, and returns the reply. None if there is
+    /// no side quest there.
+    pub(crate) fn answer_quest(&mut self, location: &str, response: &str) -> Option<String> {
+        let quest = self.world.quest(location)?.clone();
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        player.feel(response);
+        let reply = if player.quests_completed.contains_key(location) {
+            format!(
+                "You have completed the side quest of {} already. {}",
+                quest.def.name, quest.def.reply
+            )
+        } else if response.len() <= self.world.min_answer_length {
+            too_short(&self.world, response)
+        } else if !quest.def.accepts(response) {
+            format!(
+                "The side quest of {} awaits another answer.",
+                quest.def.name
+            )
+        } else {
+            info!("The side quest of {} was completed", quest.def.name);
+            player
+                .quests_completed
+                .insert(location.to_string(), quest.def.hint_tokens);
+            player.philosophical_state.note_event(
+                SystemTime::now(),
+                format!("Completed the side quest of {}", quest.def.name),
+            );
+            player.award_quest(&self.world);
+            format!(
+                "{}\n\nYou were granted {} hint token{}.",
+                quest.def.reply,
+                quest.def.hint_tokens,
+                if quest.def.hint_tokens == 1 { "" } else { "s" }
+            )
+        };
+        player.responses.insert(location.to_string(), reply.clone());
+        // the granted tokens show in hints/latest.txt
+        self.update_latest_hint();
+        Some(reply)
+    }
+}
@@ -0,0 +1,153 @@
+//! Races to enlightenment.
+//!
+//! With `--race`, or `race = true` under `[game]`, the players of a world,
+//! each with a journey of their own, race each other through the stages.
+//! `race.txt` at the root shows the standings as they are whenever it is
+//! read. The first player to reach enlightenment wins the race, and the
+//! final path closes behind them: everyone else can still answer every
+//! stage, but not the final answer. The chronicle of the winner is then
+//! served to everyone in `winner.md`, read-only, for the other players and
+//! the spectators of the world to follow the journey which won.
+
+use std::path::{Path, PathBuf};
+
+use tracing::info;
+
+use crate::philosophy::{GameStage, Philosophy};
+use crate::players::SHARED_PLAYER;
+
+/// The standings of the race, made up whenever it is read
+pub(crate) const RACE_FILE: &str = "race.txt";
+
+/// The chronicle of the winner, once the race is won
+pub(crate) const WINNER_FILE: &str = "winner.md";
+
+/// The player who won the race, kept with the state of the world
+const WINNER_KEY: &str = "race_winner";
+
+impl Philosophy {
+    /// Whether the players race each other to enlightenment
+    pub(crate) fn racing(&self) -> bool {
+        self.config.race.unwrap_or(false)
+    }
+
+    /// The player who reached enlightenment first, once one has
+    fn race_winner(&self) -> Option<&String> {
+        self.game_state.get(WINNER_KEY).filter(|_| self.racing())
+    }
+
+    /// The files of the race, as far as it got
+    pub(crate) fn race_paths(&self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if self.racing() {
+            paths.push(PathBuf::from(RACE_FILE));
+        }
+        if self.race_winner().is_some() {
+            paths.push(PathBuf::from(WINNER_FILE));
+        }
+        paths
+    }
+
+    /// Whether `path` is a file of the race which is there
+    pub(crate) fn is_race_file(&self, path: &Path) -> bool {
+        self.race_paths().iter().any(|race| race == path)
+    }
+
+    /// The reply to an answer of the player making the call which would
+    /// have reached enlightenment, had someone else not won the race
+    pub(crate) fn final_path_closed(&self, location: &str, response: &str) -> Option<String> {
+        let winner = self.race_winner()?;
+        let id = self.player_identity.current();
+        let player = self.players.get(&id).filter(|_| *winner != id)?;
+        let pack_stage = self
+            .world
+            .stage(location)
+            .is_some_and(|stage| stage.pack != "core");
+        if pack_stage
+            || response.len() <= self.world.min_answer_length
+            || !player.open_stages(&self.world).is_empty()
+            || !player.enlightened_by(response)
+        {
+            return None;
+        }
+        Some(format!(
+            "The race is won: {} reached enlightenment first, and the final path \
+             closed behind them. Read the chronicle of their journey in {}.",
+            winner, WINNER_FILE
+        ))
+    }
+
+    /// Makes the player making the call the winner, if they just reached
+    /// enlightenment and no one did before them
+    pub(crate) fn finish_race(&mut self) {
+        if !self.racing() || self.game_state.contains_key(WINNER_KEY) {
+            return;
+        }
+        let id = self.player_identity.current();
+        let enlightened = self
+            .players
+            .get(&id)
+            .is_some_and(|player| matches!(player.current_stage, GameStage::Enlightened));
+        if !enlightened {
+            return;
+        }
+        info!("{} wins the race", id);
+        self.record_event(&format!("{} wins the race", id));
+        self.game_state.insert(WINNER_KEY.to_string(), id);
+    }
+
+    /// What `race.txt` shows: every player, furthest along first, and of
+    /// those the quickest to get there
+    pub(crate) fn race(&self) -> String {
+        let winner = self.race_winner();
+        let mut standings: Vec<_> = self
+            .players
+            .iter()
+            .filter(|(id, _)| id.as_str() != SHARED_PLAYER)
+            .map(|(id, player)| (id, player, player.completed_questions.len()))
+            .collect();
+        standings.sort_by(|a, b| {
+            (Some(b.0) == winner)
+                .cmp(&(Some(a.0) == winner))
+                .then(b.2.cmp(&a.2))
+                .then(a.1.elapsed().cmp(&b.1.elapsed()))
+                .then(a.0.cmp(b.0))
+        });
+
+        let mut contents = String::from("Race\n====\n\n");
+        contents.push_str(&match winner {
+            Some(winner) => format!(
+                "Won by {}. The final path is closed, and the chronicle of the \
+                 winning journey is in {}.\n\n",
+                winner, WINNER_FILE
+            ),
+            None => "The race is on: the first to reach enlightenment wins it.\n\n".to_string(),
+        });
+        if standings.is_empty() {
+            contents.push_str("No one has set out yet.\n");
+        }
+        for (rank, (id, player, completed)) in standings.into_iter().enumerate() {
+            let minutes = player.elapsed().as_secs() / 60;
+            contents.push_str(&format!(
+                "{:>3}. {:<24} {:>3}/{} stages  {:<12} {}h {:02}m{}\n",
+                rank + 1,
+                id,
+                completed,
+                self.world.total_questions(),
+                format!("{:?}", player.current_stage),
+                minutes / 60,
+                minutes % 60,
+                if Some(id) == winner { "  winner" } else { "" }
+            ));
+        }
+        contents
+    }
+
+    /// What `winner.md` shows: the chronicle of the winner
+    pub(crate) fn winner_chronicle(&self) -> String {
+        self.race_winner()
+            .and_then(|winner| self.players.get(winner))
+            .and_then(|player| player.chronicle.clone())
+            .unwrap_or_default()
+    }
+}
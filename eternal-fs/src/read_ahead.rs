@@ -0,0 +1,257 @@
+//! Files read in order, read ahead of the client.
+//!
+//! Once a client reads a host file from the start, or close to where its
+//! last read of it ended, as clients reading ahead themselves send their
+//! reads out of order, the chunks after that read are read from the host
+//! in the background, each as large as the read or larger, until
+//! `read_ahead_kib` under `[server]` lie ahead of it. The next READ is then
+//! served from memory, which over a link with a long round trip keeps a
+//! stream going rather than waiting on the disk between every call. How
+//! often it is shows in `.status/cache`.
+//!
+//! What was read ahead is only served while the host file is the one it
+//! was read from, as large and as recently modified, and is forgotten as
+//! a client writes to the file. Only so many files are followed at once,
+//! the ones read last.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, Metadata, OpenOptions};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+use bytes::Bytes;
+use tracing::debug;
+
+use nfsserve::nfs::fileid3;
+
+use crate::atime;
+use crate::sparse;
+
+/// Bytes read ahead of a client, unless configured otherwise
+pub const DEFAULT_READ_AHEAD: u64 = 4 * 1024 * 1024;
+
+/// Files followed at once
+const FOLLOWED_FILES: usize = 64;
+
+/// Chunks are read ahead no smaller than this, however small the reads
+const MIN_CHUNK: u64 = 128 * 1024;
+
+/// What tells whether a host file changed since it was read ahead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    dev: u64,
+    ino: u64,
+    len: u64,
+    mtime: Option<SystemTime>,
+}
+
+impl Stamp {
+    fn of(meta: &Metadata) -> Stamp {
+        Stamp {
+            dev: meta.dev(),
+            ino: meta.ino(),
+            len: meta.len(),
+            mtime: meta.modified().ok(),
+        }
+    }
+}
+
+/// How a client reads a file
+#[derive(Debug)]
+struct Stream {
+    /// Where its reads got to
+    next: u64,
+    /// The host file, as it was when it was last read ahead
+    file: Option<(Arc<File>, Stamp)>,
+    /// What was read ahead, by where it starts
+    chunks: BTreeMap<u64, Bytes>,
+    /// Where reading ahead got to, chunks still being read included
+    ahead: u64,
+    last_used: Instant,
+}
+
+/// The files clients read in order, and what is read ahead of them
+#[derive(Debug)]
+pub struct ReadAhead {
+    window: AtomicU64,
+    streams: Arc<Mutex<HashMap<fileid3, Stream>>>,
+    /// Reads of host files served from what was read ahead
+    pub(crate) hits: AtomicU64,
+    /// Reads of host files which had to read from the host
+    pub(crate) misses: AtomicU64,
+}
+
+impl Default for ReadAhead {
+    fn default() -> Self {
+        ReadAhead::new(DEFAULT_READ_AHEAD)
+    }
+}
+
+impl ReadAhead {
+    /// Reads up to `window` bytes ahead of every client, nothing if 0
+    pub fn new(window: u64) -> Self {
+        ReadAhead {
+            window: AtomicU64::new(window),
+            streams: Arc::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Reads up to `window` bytes ahead from now on, nothing if 0
+    pub fn set_window(&self, window: u64) {
+        self.window.store(window, Ordering::Relaxed);
+        if window == 0 {
+            if let Ok(mut streams) = self.streams.lock() {
+                streams.clear();
+            }
+        }
+    }
+
+    /// What was read ahead of `count` bytes from `offset` of the file
+    /// `id`, whose host file is now `meta`, and whether that is its end
+    pub fn serve(
+        &self,
+        id: fileid3,
+        meta: &Metadata,
+        offset: u64,
+        count: u32,
+    ) -> Option<(Bytes, bool)> {
+        let served = self.find(id, meta, offset, count);
+        let counter = match served {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        served
+    }
+
+    fn find(&self, id: fileid3, meta: &Metadata, offset: u64, count: u32) -> Option<(Bytes, bool)> {
+        let mut streams = self.streams.lock().ok()?;
+        let stream = streams.get_mut(&id)?;
+        let stamp = Stamp::of(meta);
+        if stream.file.as_ref().is_some_and(|(_, read)| *read != stamp) {
+            debug!("Forgot what was read ahead of {:?}, changed since", id);
+            streams.remove(&id);
+            return None;
+        }
+        let (&start, chunk) = stream.chunks.range(..=offset).next_back()?;
+        let end = offset.saturating_add(count as u64).min(stamp.len);
+        let chunk_end = start + chunk.len() as u64;
+        if end > chunk_end || offset > end {
+            return None;
+        }
+        let data = chunk.slice((offset - start) as usize..(end - start) as usize);
+        Some((data, end >= stamp.len))
+    }
+
+    /// Notes that `read` bytes were read from `offset` of the file `id` at
+    /// `path`, whose host file is `meta`, and reads ahead of the client if
+    /// it reads the file in order
+    pub fn note(&self, id: fileid3, path: &Path, meta: &Metadata, offset: u64, read: u64) {
+        let window = self.window.load(Ordering::Relaxed);
+        if window == 0 || read == 0 {
+            return;
+        }
+        let Ok(mut streams) = self.streams.lock() else {
+            return;
+        };
+        let end = offset + read;
+        if !streams.contains_key(&id) && streams.len() >= FOLLOWED_FILES {
+            let oldest = streams
+                .iter()
+                .min_by_key(|(_, stream)| stream.last_used)
+                .map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                streams.remove(&oldest);
+            }
+        }
+        let stream = streams.entry(id).or_insert_with(|| Stream::at(end));
+        if offset.abs_diff(stream.next) > window {
+            // read elsewhere, the client is followed from there
+            *stream = Stream::at(end);
+            if offset != 0 {
+                return;
+            }
+        }
+        stream.next = stream.next.max(end);
+        stream.last_used = Instant::now();
+        // what lies well behind the client will not be read again
+        let behind = stream.next.saturating_sub(window);
+        while let Some((&start, chunk)) = stream.chunks.first_key_value() {
+            if start + chunk.len() as u64 > behind {
+                break;
+            }
+            stream.chunks.pop_first();
+        }
+        let stamp = Stamp::of(meta);
+        let file = match &stream.file {
+            Some((file, read)) if *read == stamp => file.clone(),
+            _ => {
+                let Ok(file) = atime::open(OpenOptions::new().read(true), path) else {
+                    return;
+                };
+                let file = Arc::new(file);
+                stream.file = Some((file.clone(), stamp));
+                stream.chunks.clear();
+                stream.ahead = end;
+                file
+            }
+        };
+        stream.ahead = stream.ahead.max(stream.next);
+        let chunk = read.max(MIN_CHUNK);
+        while stream.ahead < stream.next + window && stream.ahead < stamp.len {
+            let at = stream.ahead;
+            stream.ahead += chunk;
+            self.fetch(id, file.clone(), stamp, at, chunk);
+        }
+    }
+
+    /// Reads `len` bytes from `at` of the host file `file` in the
+    /// background, kept for `id` if it is still `stamp` by then
+    fn fetch(&self, id: fileid3, file: Arc<File>, stamp: Stamp, at: u64, len: u64) {
+        let streams = self.streams.clone();
+        tokio::task::spawn_blocking(move || {
+            let len = len.min(stamp.len.saturating_sub(at)) as usize;
+            let mut buf = vec![0; len];
+            let read = sparse::read_exact_at(&file, &mut buf, at);
+            let unchanged = file.metadata().is_ok_and(|meta| Stamp::of(&meta) == stamp);
+            let Ok(mut streams) = streams.lock() else {
+                return;
+            };
+            let Some(stream) = streams.get_mut(&id) else {
+                return;
+            };
+            let current = stream.file.as_ref().is_some_and(|(_, read)| *read == stamp);
+            if read.is_ok() && unchanged && current {
+                stream.chunks.insert(at, Bytes::from(buf));
+            } else if current {
+                // to be read ahead again
+                stream.ahead = stream.ahead.min(at);
+            }
+        });
+    }
+
+    /// Forgets what was read ahead of the file `id`, as it is written to
+    pub fn forget(&self, id: fileid3) {
+        if let Ok(mut streams) = self.streams.lock() {
+            streams.remove(&id);
+        }
+    }
+}
+
+impl Stream {
+    /// A client whose reads got to `next`
+    fn at(next: u64) -> Stream {
+        Stream {
+            next,
+            file: None,
+            chunks: BTreeMap::new(),
+            ahead: next,
+            last_used: Instant::now(),
+        }
+    }
+}
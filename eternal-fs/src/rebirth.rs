@@ -0,0 +1,164 @@
+//! Rebirth, the ritual which starts the world over.
+//!
+//! Creating a directory named `rebirth` at the root, or writing
+//! `begin again` to `.control`, ends the run being played: every journey
+//! and the state of the world are forgotten, and the world is laid out
+//! anew as it was before anyone set out. Nothing of the past life is
+//! lost. Its saved game, the answers and replies of every stage and side
+//! quest, the chronicle and the journal entries are moved to
+//! `.eternal/past_lives/<time>/`, named after when it ended. Where the
+//! players are told apart, a player can not end the journeys of everyone
+//! else, so only root can create the directory. It stays where it was
+//! created, and is removed and created again to be reborn once more.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use tracing::{info, warn};
+
+use crate::chronicle::CHRONICLE_FILE;
+use crate::control::is_operator;
+use crate::encryption;
+use crate::journal::JOURNAL_DIR;
+use crate::philosophy::{Philosophy, QUANTUM_SUPERPOSITION};
+use crate::players::{PlayerIdentity, PlayerState, SHARED_PLAYER};
+use crate::STATE_DIR;
+
+/// The directory whose creation at the root performs the ritual
+const REBIRTH_DIR: &str = "rebirth";
+
+/// Where past lives are kept in the state directory
+const PAST_LIVES_DIR: &str = "past_lives";
+
+/// The saved game of a past life, within its directory
+const SAVED_GAME_FILE: &str = "game.json";
+
+/// Moves `from` below the root to the same path below `to`, if it is
+/// there, and returns whether it was
+fn keep(root: &Path, from: &Path, to: &Path) -> io::Result<bool> {
+    let source = root.join(from);
+    if source.symlink_metadata().is_err() {
+        return Ok(false);
+    }
+    let target = to.join(from);
+    if let Some(dir) = target.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::rename(source, target)?;
+    Ok(true)
+}
+
+/// Moves the files `kept` below `to` back below the root, and removes
+/// `to` once all of them are back
+fn restore(root: &Path, kept: &[PathBuf], to: &Path) {
+    let mut restored = true;
+    for file in kept.iter().rev() {
+        if let Err(e) = std::fs::rename(to.join(file), root.join(file)) {
+            warn!("Unable to put {:?} back: {:?}", file, e);
+            restored = false;
+        }
+    }
+    if restored {
+        let _ = std::fs::remove_dir_all(to);
+    }
+}
+
+impl Philosophy {
+    /// Performs the ritual if `path`, created by the caller, calls for it
+    pub(crate) fn perform_rebirth(&mut self, path: &Path, dir: bool) {
+        if !dir || path != Path::new(REBIRTH_DIR) {
+            return;
+        }
+        if self.player_identity != PlayerIdentity::Shared && !is_operator() {
+            info!("Only root may be reborn where the players are told apart");
+            return;
+        }
+        info!("{}", self.rebirth());
+    }
+
+    /// The files of the run which a player wrote, below the root
+    fn past_life_files(&self) -> Vec<PathBuf> {
+        let dirs = self
+            .world
+            .stages
+            .iter()
+            .map(|stage| &stage.dir)
+            .chain(self.world.quests.iter().map(|quest| &quest.dir));
+        let mut files: Vec<PathBuf> = dirs
+            .flat_map(|dir| {
+                [self.answer_file(), "system_response.txt"].map(|name| Path::new(dir).join(name))
+            })
+            .collect();
+        files.push(PathBuf::from(CHRONICLE_FILE));
+        if let Ok(entries) = std::fs::read_dir(self.root.join(JOURNAL_DIR)) {
+            files.extend(
+                entries
+                    .flatten()
+                    .map(|entry| Path::new(JOURNAL_DIR).join(entry.file_name()))
+                    .filter(|path| Self::is_journal_entry(path)),
+            );
+        }
+        files
+    }
+
+    /// Keeps the run being played as a past life and returns where. The
+    /// run is kept whole or not at all: should a file fail to move, those
+    /// already moved are put back where they were.
+    fn keep_past_life(&self) -> io::Result<PathBuf> {
+        let lives = self.root.join(STATE_DIR).join(PAST_LIVES_DIR);
+        let ended = Local::now().format("%Y-%m-%dT%H-%M-%S").to_string();
+        let mut dir = lives.join(&ended);
+        let mut n = 1;
+        while dir.exists() {
+            n += 1;
+            dir = lives.join(format!("{}-{}", ended, n));
+        }
+        let saved = serde_json::to_string_pretty(&self.saved_game()).map_err(io::Error::other)?;
+        std::fs::create_dir_all(&dir)?;
+        let mut kept = Vec::new();
+        let result = std::fs::write(
+            dir.join(SAVED_GAME_FILE),
+            encryption::seal(self.key.as_ref(), saved.as_bytes()),
+        )
+        .and_then(|()| {
+            for file in self.past_life_files() {
+                if keep(&self.root, &file, &dir)? {
+                    kept.push(file);
+                }
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => Ok(dir),
+            Err(e) => {
+                restore(&self.root, &kept, &dir);
+                Err(e)
+            }
+        }
+    }
+
+    /// Ends the run being played and starts the world over, and returns
+    /// what became of it
+    pub(crate) fn rebirth(&mut self) -> String {
+        let past = match self.keep_past_life() {
+            Ok(past) => past,
+            Err(e) => {
+                warn!("Unable to keep the past life: {:?}", e);
+                return format!("Unable to keep the past life, the world goes on: {}", e);
+            }
+        };
+        self.players.clear();
+        self.players
+            .insert(SHARED_PLAYER.to_string(), PlayerState::default());
+        self.game_state.clear();
+        self.quantum_state = QUANTUM_SUPERPOSITION.to_string();
+        self.lay_out();
+        if let Err(e) = self.save_game() {
+            warn!("Unable to save the game after the rebirth: {:?}", e);
+        }
+        let past = past.strip_prefix(&self.root).unwrap_or(&past);
+        self.commit_journey("eternal-fs", "Begin again", &[]);
+        format!("The world is reborn. The past life rests in {:?}.", past)
+    }
+}
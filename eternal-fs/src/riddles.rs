@@ -0,0 +1,382 @@
+//! Riddles set by the stages, made up anew for every journey.
+//!
+//! A stage of a pack can set a riddle, see
+//! [`StageDef::riddle`](crate::packs::StageDef::riddle), shown in
+//! `riddle.txt` of its directory: the logic and myth paths do. A riddle is
+//! one of a few templates, filled with names, numbers and things drawn
+//! from a word bank by a generator seeded for the journey of the player,
+//! so that every journey solves different riddles. The answer written to
+//! the file is checked against the solution the riddle was made up with,
+//! and the stage can not be answered before it is solved.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use nfsserve::nfs::nfsstat3;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::engine::{read_at, VirtualAttrs, VirtualFile};
+use crate::labyrinth::fnv1a;
+use crate::packs::{RiddleSet, World};
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The file of a stage its riddle is shown in and answered through
+pub(crate) const RIDDLE_FILE: &str = "riddle.txt";
+
+/// People of the logic riddles
+const NAMES: &[&str] = &[
+    "Ada", "Basil", "Cyra", "Dorian", "Elin", "Fenna", "Gideon", "Hale", "Iris", "Jonas",
+];
+
+/// What people of the logic riddles are compared by, and who comes first
+const COMPARISONS: &[(&str, &str)] = &[
+    ("taller", "tallest"),
+    ("older", "eldest"),
+    ("faster", "fastest"),
+    ("wiser", "wisest"),
+];
+
+/// What people of the logic riddles keep
+const POSSESSIONS: &[&str] = &["sheep", "lanterns", "books", "candles", "coins"];
+
+/// Gods of the myth riddles
+const GODS: &[&str] = &[
+    "Aten", "Brigid", "Coatl", "Dagda", "Enki", "Freyja", "Geb", "Hestia", "Inti", "Juno",
+];
+
+/// Where the gods of the myth riddles dwell
+const REALMS: &[&str] = &["the sky", "the sea", "the underworld", "the forest"];
+
+/// Heroes of the myth riddles
+const HEROES: &[&str] = &[
+    "Orpheus",
+    "Psyche",
+    "Gilgamesh",
+    "Inanna",
+    "Savitri",
+    "Odysseus",
+];
+
+/// Where the heroes of the myth riddles journey to
+const DESTINATIONS: &[&str] = &[
+    "the land of the dead",
+    "the end of the world",
+    "the mountain of the gods",
+    "the island of the blessed",
+];
+
+/// Things which speak of themselves in the myth riddles, with what they
+/// say
+const THINGS: &[(&str, &[&str])] = &[
+    (
+        "shadow",
+        &[
+            "I follow you all day and leave you at night",
+            "I grow longer as the sun sinks",
+            "I am born of light but made of none",
+        ],
+    ),
+    (
+        "echo",
+        &[
+            "I answer without being asked",
+            "I speak only the words of others",
+            "a nymph was turned into me for talking too much",
+        ],
+    ),
+    (
+        "river",
+        &[
+            "I have a bed but never sleep",
+            "I have a mouth but never eat",
+            "no one steps into me twice",
+        ],
+    ),
+    (
+        "time",
+        &[
+            "I devour birds, beasts and kings",
+            "I am never seen but always spent",
+            "even the gods could not hold me still",
+        ],
+    ),
+    (
+        "silence",
+        &[
+            "say my name and I am gone",
+            "I am kept by the wise and broken by fools",
+            "I am what is left when the story ends",
+        ],
+    ),
+    (
+        "fire",
+        &[
+            "I was stolen from the gods",
+            "feed me and I live, give me water and I die",
+            "I have no mouth, yet I devour",
+        ],
+    ),
+];
+
+/// A riddle, and the solution it was made up with
+struct Riddle {
+    text: String,
+    solution: String,
+}
+
+/// How the player fares with the riddle of a stage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct RiddleAttempt {
+    /// The player's last answer
+    answer: String,
+    solved: bool,
+}
+
+/// Who of `names` comes first by `comparison`, told pairwise in any order
+fn ordering(rng: &mut StdRng, names: &[&str], comparison: (&str, &str)) -> (String, String) {
+    let mut order: Vec<&str> = names.choose_multiple(rng, 4).copied().collect();
+    order.shuffle(rng);
+    let mut facts: Vec<String> = order
+        .windows(2)
+        .map(|pair| format!("{} is {} than {}.", pair[0], comparison.0, pair[1]))
+        .collect();
+    facts.shuffle(rng);
+    (facts.join(" "), order[0].to_string())
+}
+
+/// Makes up a riddle of `set` with `rng`
+fn make_riddle(set: RiddleSet, rng: &mut StdRng) -> Riddle {
+    let (text, solution) = match (set, rng.gen_range(0..3)) {
+        (RiddleSet::Logic, 0) => {
+            let comparison = *COMPARISONS.choose(rng).unwrap_or(&COMPARISONS[0]);
+            let (facts, first) = ordering(rng, NAMES, comparison);
+            (format!("{} Who is the {}?", facts, comparison.1), first)
+        }
+        (RiddleSet::Logic, 1) => {
+            let pair: Vec<&str> = NAMES.choose_multiple(rng, 2).copied().collect();
+            // only the liar can claim to be like the one who tells the
+            // truth, and only the one who tells the truth to be unlike the
+            // liar
+            let alike = rng.gen_bool(0.5);
+            let claim = if alike { "are alike" } else { "are not alike" };
+            let liar = if alike { pair[0] } else { pair[1] };
+            (
+                format!(
+                    "Of {a} and {b}, one always lies and the other always tells the \
+                     truth. {a} says: \"{b} and I {claim}.\" Who is the liar?",
+                    a = pair[0],
+                    b = pair[1],
+                    claim = claim
+                ),
+                liar.to_string(),
+            )
+        }
+        (RiddleSet::Logic, _) => {
+            let name = NAMES.choose(rng).unwrap_or(&NAMES[0]);
+            let things = POSSESSIONS.choose(rng).unwrap_or(&POSSESSIONS[0]);
+            let kept: u32 = rng.gen_range(10..20);
+            let lost: u32 = rng.gen_range(1..4);
+            let found: u32 = rng.gen_range(lost + 1..lost + 5);
+            let nights: u32 = rng.gen_range(2..6);
+            (
+                format!(
+                    "{name} keeps {kept} {things}. Every night {lost} of them are lost and \
+                     {found} more are found. How many {things} does {name} keep after \
+                     {nights} nights?"
+                ),
+                (kept + nights * (found - lost)).to_string(),
+            )
+        }
+        (RiddleSet::Myth, 0) => {
+            let hero = HEROES.choose(rng).unwrap_or(&HEROES[0]);
+            let destination = DESTINATIONS.choose(rng).unwrap_or(&DESTINATIONS[0]);
+            let rivers: u32 = rng.gen_range(2..6);
+            let fare: u32 = rng.gen_range(2..7);
+            let left: u32 = rng.gen_range(1..11);
+            (
+                format!(
+                    "{hero} set out for {destination} with a purse of coins. At each of \
+                     the {rivers} rivers on the way the ferryman took {fare} coins, and \
+                     {hero} arrived with {left} coins left. How many coins were in the \
+                     purse at the start?"
+                ),
+                (left + rivers * fare).to_string(),
+            )
+        }
+        (RiddleSet::Myth, 1) => {
+            let realm = REALMS.choose(rng).unwrap_or(&REALMS[0]);
+            let (facts, eldest) = ordering(rng, GODS, ("older", "eldest"));
+            (
+                format!(
+                    "Four gods ruled over {}. {} Who is the eldest of them?",
+                    realm, facts
+                ),
+                eldest,
+            )
+        }
+        (RiddleSet::Myth, _) => {
+            let (thing, sayings) = THINGS.choose(rng).unwrap_or(&THINGS[0]);
+            let sayings: Vec<&str> = sayings.choose_multiple(rng, 2).copied().collect();
+            (
+                format!(
+                    "Every myth speaks of me. {}, and {}. What am I?",
+                    capitalize(sayings[0]),
+                    sayings[1]
+                ),
+                thing.to_string(),
+            )
+        }
+    };
+    Riddle { text, solution }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Whether `answer` gives `solution`, as one of its words
+fn solves(answer: &str, solution: &str) -> bool {
+    answer
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word == solution.to_lowercase())
+}
+
+impl PlayerState {
+    /// The riddle the stage `dir` sets the player, if it sets one
+    fn riddle(&self, world: &World, dir: &str) -> Option<Riddle> {
+        let set = world.stage(dir)?.def.riddle?;
+        let mut rng = StdRng::seed_from_u64(self.riddle_seed as u64 ^ fnv1a(dir));
+        Some(make_riddle(set, &mut rng))
+    }
+
+    /// Whether the stage `dir` sets the player a riddle they have not
+    /// solved yet
+    pub(crate) fn unsolved_riddle(&self, world: &World, dir: &str) -> bool {
+        world
+            .stage(dir)
+            .is_some_and(|stage| stage.def.riddle.is_some())
+            && !self.riddles.get(dir).is_some_and(|attempt| attempt.solved)
+    }
+
+    /// What `riddle.txt` of the stage `dir` shows the player
+    fn riddle_file(&self, world: &World, dir: &str) -> String {
+        let Some(riddle) = self.riddle(world, dir) else {
+            return String::new();
+        };
+        let mut contents = format!("A Riddle\n========\n{}\n\n", riddle.text);
+        match self.riddles.get(dir) {
+            Some(attempt) if attempt.solved => contents.push_str(&format!(
+                "You answered \"{}\", and so it is. The {} path can now be answered.\n",
+                attempt.answer, dir
+            )),
+            Some(attempt) => contents.push_str(&format!(
+                "You answered \"{}\", and so it is not. Write another answer to this file.\n",
+                attempt.answer
+            )),
+            None => contents.push_str("Write your answer to this file.\n"),
+        }
+        contents
+    }
+
+    /// Answers the riddle of the stage `dir` with `answer`
+    fn answer_riddle(&mut self, world: &World, dir: &str, answer: &str) {
+        let Some(riddle) = self.riddle(world, dir) else {
+            return;
+        };
+        if self.riddles.get(dir).is_some_and(|attempt| attempt.solved) {
+            return;
+        }
+        let solved = solves(answer, &riddle.solution);
+        if solved {
+            info!("The riddle of {} was solved", dir);
+            self.philosophical_state
+                .note_event(SystemTime::now(), format!("Solved the riddle of {}", dir));
+        }
+        self.riddles.insert(
+            dir.to_string(),
+            RiddleAttempt {
+                answer: answer.to_string(),
+                solved,
+            },
+        );
+    }
+}
+
+impl Philosophy {
+    /// The files the riddles of the world are shown in
+    pub(crate) fn riddle_paths(&self) -> Vec<PathBuf> {
+        self.world
+            .stages
+            .iter()
+            .filter(|stage| stage.def.riddle.is_some())
+            .map(|stage| Path::new(&stage.dir).join(RIDDLE_FILE))
+            .collect()
+    }
+
+    /// The riddle file at `path`, as the player making the call sees it
+    pub(crate) fn open_riddle(&mut self, path: &Path) -> Option<Box<dyn VirtualFile + '_>> {
+        let dir = path.parent()?.to_str()?.to_string();
+        self.world.stage(&dir)?.def.riddle?;
+        // the riddle is made up from the seed of the journey, which has
+        // to be drawn before it is shown
+        let id = self.player_identity.current();
+        self.players.entry(id).or_default();
+        Some(Box::new(RiddleFile { game: self, dir }))
+    }
+}
+
+/// The riddle of a stage, as the player making the call sees it
+struct RiddleFile<'a> {
+    game: &'a mut Philosophy,
+    dir: String,
+}
+
+impl RiddleFile<'_> {
+    fn contents(&self) -> String {
+        let newcomer = PlayerState::default();
+        self.game
+            .players
+            .get(&self.game.player_identity.current())
+            .unwrap_or(&newcomer)
+            .riddle_file(&self.game.world, &self.dir)
+    }
+}
+
+impl VirtualFile for RiddleFile<'_> {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        Ok(read_at(self.contents().as_bytes(), offset, count))
+    }
+
+    fn write(&mut self, _offset: u64, data: &[u8]) -> Result<(), nfsstat3> {
+        let answer = String::from_utf8_lossy(data);
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(());
+        }
+        let id = self.game.player_identity.current();
+        let player = self.game.players.entry(id).or_default();
+        player.answer_riddle(&self.game.world, &self.dir, answer);
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.contents().len() as u64
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o644,
+            mtime: SystemTime::now(),
+        }
+    }
+}
@@ -0,0 +1,1395 @@
+//! Buckets of an S3 object store, served in place of a host directory.
+//!
+//! `eternal_fs s3://BUCKET/PREFIX`, or `--export NAME=s3://BUCKET/PREFIX`
+//! next to worlds, serves the objects of a bucket under a prefix. Keys
+//! are split at `/` into directories: a directory is listed with a
+//! delimited list-objects, a file is read with ranged GETs, and what is
+//! written to a file is held in memory and goes up in parts of a
+//! multipart upload as it grows, or in one PUT if it stays small, once
+//! writes to it stop. Empty directories are kept as `DIR/` marker
+//! objects. Symlinks, hard links and special files have no place in a
+//! bucket and are refused.
+//!
+//! Objects get fileids from the same map as the files of a world. The
+//! game lays its world out on the host, so a bucket is served as it is,
+//! without it.
+//!
+//! The bucket is reached as the AWS tools reach it, with
+//! `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_SESSION_TOKEN` and
+//! `AWS_REGION` from the environment, and `AWS_ENDPOINT_URL` to use an
+//! S3 compatible store instead of AWS. Without keys requests go
+//! unsigned, as public buckets take them.
+
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use intaglio::osstr::SymbolTable;
+use intaglio::Symbol;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use nfsserve::nfs::*;
+use nfsserve::vfs::{DirEntry, NFSFileSystem, ReadDirResult, VFSCapabilities};
+
+use crate::generated::to_nfstime;
+use crate::metadata::CachedMap;
+use crate::FSEntry;
+
+/// Size of the parts of a multipart upload. Every part but the last must
+/// be 5 MiB at least.
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// How long the listing of a directory is trusted before the bucket is
+/// listed again
+const LIST_TTL: Duration = Duration::from_secs(1);
+
+/// How long a file is not written to before what was written goes up
+const FLUSH_IDLE: Duration = Duration::from_secs(2);
+
+/// How often files no longer written to are looked for
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a request to the bucket may take
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Keys to sign requests with
+#[derive(Clone)]
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key", &self.access_key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An object of a listing
+#[derive(Debug)]
+struct Object {
+    key: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// A page of list-objects and all pages after it
+#[derive(Debug, Default)]
+struct Listing {
+    objects: Vec<Object>,
+    /// Prefixes up to the next delimiter, if listed with one
+    prefixes: Vec<String>,
+}
+
+/// A bucket of an S3 object store, and the prefix of the keys served
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    name: String,
+    /// Empty, or ending in `/`
+    prefix: String,
+    region: String,
+    /// Scheme and host of the store
+    endpoint: String,
+    /// Whether the bucket is named in the path rather than the host, as S3
+    /// compatible stores expect
+    path_style: bool,
+    credentials: Option<Credentials>,
+    agent: ureq::Agent,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// `text` percent-encoded as S3 signs it, with `/` kept if `keep_slash`
+fn uri_encode(text: &str, keep_slash: bool) -> String {
+    let mut encoded = String::new();
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(b as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+/// A key of a listing asked for with `encoding-type=url`
+fn url_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    Some(b) => {
+                        decoded.push(b);
+                        i += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// What `tag` holds in `xml`, the first time it is found
+fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..end])
+}
+
+impl Bucket {
+    /// The bucket `url` names, `s3://BUCKET/PREFIX`, reached as the
+    /// environment says
+    pub fn from_url(url: &str) -> Result<Bucket, String> {
+        let rest = url
+            .strip_prefix("s3://")
+            .ok_or_else(|| format!("{:?} is not an s3:// url", url))?;
+        let (name, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if name.is_empty() {
+            return Err(format!("{:?} names no bucket", url));
+        }
+        let prefix = match prefix.trim_matches('/') {
+            "" => String::new(),
+            prefix => format!("{}/", prefix),
+        };
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let (endpoint, path_style) =
+            match var("AWS_ENDPOINT_URL_S3").or_else(|| var("AWS_ENDPOINT_URL")) {
+                Some(endpoint) => (endpoint.trim_end_matches('/').to_string(), true),
+                None => (format!("https://s3.{}.amazonaws.com", region), false),
+            };
+        let credentials = match (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            (Some(access_key), Some(secret_key)) => Some(Credentials {
+                access_key,
+                secret_key,
+                session_token: var("AWS_SESSION_TOKEN"),
+            }),
+            _ => None,
+        };
+        Ok(Bucket {
+            name: name.to_string(),
+            prefix,
+            region,
+            endpoint,
+            path_style,
+            credentials,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        })
+    }
+
+    /// Sends a request on `key`, or on the bucket if `key` is empty,
+    /// signed with Signature Version 4 if there are keys
+    fn send(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<ureq::Response, nfsstat3> {
+        let (scheme, host) = self
+            .endpoint
+            .split_once("://")
+            .unwrap_or(("https", &self.endpoint));
+        let (host, path) = match self.path_style {
+            true => (
+                host.to_string(),
+                format!("/{}/{}", self.name, uri_encode(key, true)),
+            ),
+            false => (
+                format!("{}.{}", self.name, host),
+                format!("/{}", uri_encode(key, true)),
+            ),
+        };
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, false), uri_encode(v, false)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+        let url = match query.is_empty() {
+            true => format!("{}://{}{}", scheme, host, path),
+            false => format!("{}://{}{}?{}", scheme, host, path, query),
+        };
+        let mut request = self.agent.request(method, &url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        if let Some(credentials) = &self.credentials {
+            let now = Utc::now();
+            let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+            let date = now.format("%Y%m%d").to_string();
+            let payload = hex(&Sha256::digest(body));
+            let mut signed = vec![
+                ("host".to_string(), host.clone()),
+                ("x-amz-content-sha256".to_string(), payload.clone()),
+                ("x-amz-date".to_string(), amz_date.clone()),
+            ];
+            if let Some(token) = &credentials.session_token {
+                signed.push(("x-amz-security-token".to_string(), token.clone()));
+            }
+            signed.extend(
+                headers
+                    .iter()
+                    .filter(|(name, _)| name.starts_with("x-amz-"))
+                    .map(|(name, value)| (name.to_string(), value.trim().to_string())),
+            );
+            signed.sort();
+            let names = signed
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(";");
+            let canonical = format!(
+                "{}\n{}\n{}\n{}\n{}\n{}",
+                method,
+                path,
+                query,
+                signed
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}\n", name, value))
+                    .collect::<String>(),
+                names,
+                payload
+            );
+            let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+            let to_sign = format!(
+                "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                amz_date,
+                scope,
+                hex(&Sha256::digest(canonical.as_bytes()))
+            );
+            let key = [self.region.as_str(), "s3", "aws4_request"].iter().fold(
+                hmac(format!("AWS4{}", credentials.secret_key).as_bytes(), &date),
+                |key, part| hmac(&key, part),
+            );
+            request = request
+                .set("x-amz-content-sha256", &payload)
+                .set("x-amz-date", &amz_date)
+                .set(
+                    "Authorization",
+                    &format!(
+                        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                        credentials.access_key,
+                        scope,
+                        names,
+                        hex(&hmac(&key, &to_sign))
+                    ),
+                );
+            if let Some(token) = &credentials.session_token {
+                request = request.set("x-amz-security-token", token);
+            }
+        }
+        match request.send_bytes(body) {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(404, _)) => Err(nfsstat3::NFS3ERR_NOENT),
+            Err(ureq::Error::Status(status, response)) => {
+                warn!(
+                    "{} {:?} in bucket {} failed with {}: {}",
+                    method,
+                    key,
+                    self.name,
+                    status,
+                    response.into_string().unwrap_or_default()
+                );
+                match status {
+                    401 | 403 => Err(nfsstat3::NFS3ERR_ACCES),
+                    _ => Err(nfsstat3::NFS3ERR_IO),
+                }
+            }
+            Err(e) => {
+                warn!("{} {:?} in bucket {} failed: {}", method, key, self.name, e);
+                Err(nfsstat3::NFS3ERR_IO)
+            }
+        }
+    }
+
+    fn body(response: ureq::Response) -> Result<Vec<u8>, nfsstat3> {
+        let mut body = Vec::new();
+        response.into_reader().read_to_end(&mut body).map_err(|e| {
+            warn!("Unable to read a reply of the bucket: {}", e);
+            nfsstat3::NFS3ERR_IO
+        })?;
+        Ok(body)
+    }
+
+    fn text(response: ureq::Response) -> Result<String, nfsstat3> {
+        Ok(String::from_utf8_lossy(&Self::body(response)?).into_owned())
+    }
+
+    /// The objects with keys starting with `prefix`, and with `delimited`
+    /// the prefixes up to the next `/` in place of the objects below them
+    fn list(&self, prefix: &str, delimited: bool) -> Result<Listing, nfsstat3> {
+        let contents = Regex::new(r"(?s)<Contents>(.*?)</Contents>").expect("valid pattern");
+        let prefixes =
+            Regex::new(r"(?s)<CommonPrefixes>(.*?)</CommonPrefixes>").expect("valid pattern");
+        let mut listing = Listing::default();
+        let mut token = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2"),
+                ("prefix", prefix),
+                ("encoding-type", "url"),
+            ];
+            if delimited {
+                query.push(("delimiter", "/"));
+            }
+            if let Some(token) = token.as_deref() {
+                query.push(("continuation-token", token));
+            }
+            let page = Self::text(self.send("GET", "", &query, &[], &[])?)?;
+            for found in contents.captures_iter(&page) {
+                let (Some(key), Some(size)) =
+                    (xml_value(&found[1], "Key"), xml_value(&found[1], "Size"))
+                else {
+                    continue;
+                };
+                let modified = xml_value(&found[1], "LastModified")
+                    .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                    .map_or(SystemTime::UNIX_EPOCH, SystemTime::from);
+                listing.objects.push(Object {
+                    key: url_decode(key),
+                    size: size.parse().unwrap_or(0),
+                    modified,
+                });
+            }
+            for found in prefixes.captures_iter(&page) {
+                if let Some(prefix) = xml_value(&found[1], "Prefix") {
+                    listing.prefixes.push(url_decode(prefix));
+                }
+            }
+            token = match xml_value(&page, "IsTruncated") {
+                Some("true") => xml_value(&page, "NextContinuationToken").map(str::to_string),
+                _ => None,
+            };
+            if token.is_none() {
+                return Ok(listing);
+            }
+        }
+    }
+
+    /// `count` bytes of `key` from `offset` on
+    fn get_range(&self, key: &str, offset: u64, count: u64) -> Result<Vec<u8>, nfsstat3> {
+        let range = format!("bytes={}-{}", offset, offset + count - 1);
+        Self::body(self.send("GET", key, &[], &[("Range", &range)], &[])?)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, nfsstat3> {
+        Self::body(self.send("GET", key, &[], &[], &[])?)
+    }
+
+    fn put(&self, key: &str, body: &[u8]) -> Result<(), nfsstat3> {
+        self.send("PUT", key, &[], &[], body).map(drop)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), nfsstat3> {
+        self.send("DELETE", key, &[], &[], &[]).map(drop)
+    }
+
+    fn copy(&self, from: &str, to: &str) -> Result<(), nfsstat3> {
+        let source = format!("/{}/{}", self.name, uri_encode(from, true));
+        self.send("PUT", to, &[], &[("x-amz-copy-source", &source)], &[])
+            .map(drop)
+    }
+
+    /// Starts a multipart upload of `key`, returning its id
+    fn start_upload(&self, key: &str) -> Result<String, nfsstat3> {
+        let reply = Self::text(self.send("POST", key, &[("uploads", "")], &[], &[])?)?;
+        xml_value(&reply, "UploadId")
+            .map(str::to_string)
+            .ok_or(nfsstat3::NFS3ERR_IO)
+    }
+
+    /// Uploads part `number` of an upload, returning its ETag
+    fn upload_part(
+        &self,
+        key: &str,
+        upload: &str,
+        number: usize,
+        body: &[u8],
+    ) -> Result<String, nfsstat3> {
+        let number = number.to_string();
+        let query = [("partNumber", number.as_str()), ("uploadId", upload)];
+        let response = self.send("PUT", key, &query, &[], body)?;
+        response
+            .header("ETag")
+            .map(str::to_string)
+            .ok_or(nfsstat3::NFS3ERR_IO)
+    }
+
+    /// Puts the parts of an upload, with the ETags `parts`, together
+    fn complete_upload(&self, key: &str, upload: &str, parts: &[String]) -> Result<(), nfsstat3> {
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+                .iter()
+                .enumerate()
+                .map(|(i, etag)| format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    i + 1,
+                    etag
+                ))
+                .collect::<String>()
+        );
+        let reply =
+            Self::text(self.send("POST", key, &[("uploadId", upload)], &[], body.as_bytes())?)?;
+        // the upload may fail after the reply started
+        if reply.contains("<Error>") {
+            warn!("Unable to complete the upload of {:?}: {}", key, reply);
+            return Err(nfsstat3::NFS3ERR_IO);
+        }
+        Ok(())
+    }
+
+    fn abort_upload(&self, key: &str, upload: &str) -> Result<(), nfsstat3> {
+        self.send("DELETE", key, &[("uploadId", upload)], &[], &[])
+            .map(drop)
+    }
+}
+
+/// What was written to a file and did not go up yet
+#[derive(Debug)]
+struct Upload {
+    key: String,
+    /// Whether the object is still to be fetched, as it is written to
+    /// somewhere else than its start
+    fetch: bool,
+    /// The multipart upload, once the file grew past a part
+    upload: Option<String>,
+    /// ETags of the parts which went up
+    parts: Vec<String>,
+    /// Bytes which went up in parts
+    sent: u64,
+    /// The bytes after those
+    tail: Vec<u8>,
+    /// Ranges past the sent bytes which were not written yet, as writes
+    /// may come out of order
+    holes: Vec<(u64, u64)>,
+    touched: Instant,
+}
+
+impl Upload {
+    fn new(key: String, size: u64) -> Upload {
+        Upload {
+            key,
+            fetch: size > 0,
+            upload: None,
+            parts: Vec::new(),
+            sent: 0,
+            tail: Vec::new(),
+            holes: Vec::new(),
+            touched: Instant::now(),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.sent + self.tail.len() as u64
+    }
+
+    fn write(&mut self, offset: u64, data: &[u8]) {
+        let end = offset + data.len() as u64;
+        if offset > self.size() {
+            self.holes.push((self.size(), offset));
+        }
+        if end > self.size() {
+            self.tail.resize((end - self.sent) as usize, 0);
+        }
+        let start = (offset - self.sent) as usize;
+        self.tail[start..start + data.len()].copy_from_slice(data);
+        self.holes = self
+            .holes
+            .iter()
+            .flat_map(|&(from, to)| {
+                [(from, to.min(offset)), (from.max(end), to)]
+                    .into_iter()
+                    .filter(|(from, to)| from < to)
+            })
+            .collect();
+    }
+
+    /// Bytes of the tail which are written through and can go up
+    fn ready(&self) -> usize {
+        let written = self
+            .holes
+            .iter()
+            .map(|(from, _)| *from)
+            .min()
+            .unwrap_or(self.size());
+        (written - self.sent) as usize
+    }
+}
+
+/// The objects of the bucket known so far, by fileid
+#[derive(Debug)]
+struct BucketMap {
+    prefix: String,
+    next_fileid: fileid3,
+    intern: SymbolTable,
+    id_to_path: CachedMap<fileid3, FSEntry>,
+    path_to_id: CachedMap<Vec<Symbol>, fileid3>,
+    /// When each directory was last listed
+    listed: HashMap<fileid3, Instant>,
+    uid: u32,
+    gid: u32,
+}
+
+impl BucketMap {
+    fn attr(&self, id: fileid3, ftype: ftype3, size: u64, modified: SystemTime) -> fattr3 {
+        let (mode, nlink) = match ftype {
+            ftype3::NF3DIR => (0o755, 2),
+            _ => (0o644, 1),
+        };
+        let time = to_nfstime(modified);
+        fattr3 {
+            ftype,
+            mode,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            size,
+            used: size,
+            rdev: specdata3::default(),
+            fsid: 0,
+            fileid: id,
+            atime: time,
+            mtime: time,
+            ctime: time,
+        }
+    }
+
+    fn find(&self, id: fileid3) -> Result<FSEntry, nfsstat3> {
+        Ok(self
+            .id_to_path
+            .get(&id)
+            .ok_or(nfsstat3::NFS3ERR_STALE)?
+            .into_owned())
+    }
+
+    /// The key of the object at `name`, ending in `/` for a directory, or
+    /// the prefix served for the root
+    fn key(&self, name: &[Symbol], dir: bool) -> String {
+        let mut key = self.prefix.clone();
+        for sym in name {
+            key.push_str(&self.intern.get(*sym).unwrap_or_default().to_string_lossy());
+            key.push('/');
+        }
+        if !dir {
+            key.pop();
+        }
+        key
+    }
+
+    fn child_path(&mut self, dir: &[Symbol], name: &str) -> Vec<Symbol> {
+        let sym = self.intern.intern(OsStr::new(name).to_os_string()).unwrap();
+        let mut path = dir.to_vec();
+        path.push(sym);
+        path
+    }
+
+    fn child(&self, dir: &[Symbol], name: &str) -> Option<fileid3> {
+        let sym = self.intern.check_interned(OsStr::new(name))?;
+        let mut path = dir.to_vec();
+        path.push(sym);
+        self.path_to_id.get(&path).as_deref().copied()
+    }
+
+    /// The fileid of the object at `path`, with its attributes brought up
+    /// to date unless `pending` leaves them to what is being written
+    fn place(
+        &mut self,
+        path: Vec<Symbol>,
+        ftype: ftype3,
+        size: u64,
+        modified: SystemTime,
+        pending: bool,
+    ) -> fileid3 {
+        if let Some(id) = self.path_to_id.get(&path).as_deref().copied() {
+            let attr = self.attr(id, ftype, size, modified);
+            if let Some(entry) = self.id_to_path.get_mut(&id) {
+                match entry.fsmeta.ftype as u32 == ftype as u32 {
+                    true if pending || matches!(ftype, ftype3::NF3DIR) => return id,
+                    true => {
+                        entry.fsmeta = attr;
+                        return id;
+                    }
+                    false => self.forget(id),
+                }
+            }
+        }
+        let id = self.next_fileid;
+        self.next_fileid += 1;
+        let attr = self.attr(id, ftype, size, modified);
+        self.path_to_id.insert(path.clone(), id);
+        self.id_to_path.insert(
+            id,
+            FSEntry {
+                name: path,
+                ino: 0,
+                links: Vec::new(),
+                fsmeta: attr,
+                children_meta: attr,
+                children: None,
+            },
+        );
+        id
+    }
+
+    /// Forgets `id` and everything below it
+    fn forget(&mut self, id: fileid3) {
+        let Some(entry) = self.id_to_path.remove(&id) else {
+            return;
+        };
+        self.path_to_id.remove(&entry.name);
+        self.listed.remove(&id);
+        for child in entry.children.into_iter().flatten() {
+            self.forget(child);
+        }
+    }
+
+    /// Adds or drops `id` from the children of `dirid`, which changed it
+    fn change_children(&mut self, dirid: fileid3, id: fileid3, added: bool) {
+        if let Some(dir) = self.id_to_path.get_mut(&dirid) {
+            if let Some(children) = dir.children.as_mut() {
+                match added {
+                    true => children.insert(id),
+                    false => children.remove(&id),
+                };
+            }
+            dir.fsmeta.mtime = to_nfstime(SystemTime::now());
+            dir.fsmeta.ctime = dir.fsmeta.mtime;
+        }
+    }
+
+    /// Takes in the listing of `dirid`, forgetting what is gone from it
+    /// but files still being written
+    fn apply_listing(
+        &mut self,
+        dirid: fileid3,
+        listing: Listing,
+        pending: &BTreeSet<fileid3>,
+    ) -> Result<(), nfsstat3> {
+        let dir = self.find(dirid)?;
+        let dir_key = self.key(&dir.name, true);
+        let mut children = BTreeSet::new();
+        let now = SystemTime::now();
+        let mut dirs = Vec::new();
+        for prefix in listing.prefixes {
+            let Some(name) = prefix
+                .strip_prefix(&dir_key)
+                .and_then(|name| name.strip_suffix('/'))
+                .filter(|name| !name.is_empty() && !name.contains('/'))
+            else {
+                continue;
+            };
+            let path = self.child_path(&dir.name, name);
+            children.insert(self.place(path, ftype3::NF3DIR, 0, now, false));
+            dirs.push(name.to_string());
+        }
+        for object in listing.objects {
+            let Some(name) = object
+                .key
+                .strip_prefix(&dir_key)
+                .filter(|name| !name.is_empty() && !name.contains('/'))
+            else {
+                // the marker of the directory itself
+                continue;
+            };
+            if dirs.iter().any(|dir| dir == name) {
+                debug!("{:?} is hidden by the directory of its name", object.key);
+                continue;
+            }
+            let path = self.child_path(&dir.name, name);
+            let id = self.path_to_id.get(&path).as_deref().copied();
+            let pending = id.is_some_and(|id| pending.contains(&id));
+            children.insert(self.place(
+                path,
+                ftype3::NF3REG,
+                object.size,
+                object.modified,
+                pending,
+            ));
+        }
+        let known = dir.children.unwrap_or_default();
+        let gone: Vec<fileid3> = known.difference(&children).copied().collect();
+        for id in gone {
+            if pending.contains(&id) {
+                children.insert(id);
+            } else {
+                self.forget(id);
+            }
+        }
+        let changed = dir.fsmeta.mtime.seconds == 0 || known != children;
+        if let Some(entry) = self.id_to_path.get_mut(&dirid) {
+            entry.children = Some(children);
+            if changed {
+                entry.fsmeta.mtime = to_nfstime(now);
+                entry.fsmeta.ctime = entry.fsmeta.mtime;
+            }
+        }
+        self.listed.insert(dirid, Instant::now());
+        Ok(())
+    }
+}
+
+/// Serves a bucket of an S3 object store as a file system
+#[derive(Debug, Clone)]
+pub struct S3FS {
+    bucket: Arc<Bucket>,
+    map: Arc<Mutex<BucketMap>>,
+    uploads: Arc<Mutex<HashMap<fileid3, Upload>>>,
+    read_only: bool,
+}
+
+/// `filename` as a part of a key
+fn key_name(filename: &filename3) -> Result<&str, nfsstat3> {
+    match std::str::from_utf8(filename) {
+        Ok(name) if !name.is_empty() && name != "." && name != ".." => Ok(name),
+        _ => Err(nfsstat3::NFS3ERR_INVAL),
+    }
+}
+
+impl S3FS {
+    pub fn new(bucket: Bucket) -> S3FS {
+        let mut map = BucketMap {
+            prefix: bucket.prefix.clone(),
+            next_fileid: 1,
+            intern: SymbolTable::new(),
+            id_to_path: CachedMap::new(b'e'),
+            path_to_id: CachedMap::new(b'p'),
+            listed: HashMap::new(),
+            uid: nix::unistd::getuid().as_raw(),
+            gid: nix::unistd::getgid().as_raw(),
+        };
+        let attr = map.attr(0, ftype3::NF3DIR, 0, SystemTime::UNIX_EPOCH);
+        map.path_to_id.insert(Vec::new(), 0);
+        map.id_to_path.insert(
+            0,
+            FSEntry {
+                name: Vec::new(),
+                ino: 0,
+                links: Vec::new(),
+                fsmeta: attr,
+                children_meta: attr,
+                children: None,
+            },
+        );
+        S3FS {
+            bucket: Arc::new(bucket),
+            map: Arc::new(Mutex::new(map)),
+            uploads: Arc::new(Mutex::new(HashMap::new())),
+            read_only: false,
+        }
+    }
+
+    /// Refuse every modification of the bucket
+    pub fn set_read_only(&mut self, enabled: bool) {
+        self.read_only = enabled;
+    }
+
+    /// Sends what was written to files no longer written to up to the
+    /// bucket, until the server stops
+    pub fn start_flusher(&self) {
+        let fs = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let idle: Vec<fileid3> = fs
+                    .uploads
+                    .lock()
+                    .await
+                    .iter()
+                    .filter(|(_, upload)| upload.touched.elapsed() >= FLUSH_IDLE)
+                    .map(|(id, _)| *id)
+                    .collect();
+                for id in idle {
+                    if let Err(e) = fs.flush(id).await {
+                        warn!("Unable to upload fileid {}: {:?}", id, e);
+                    }
+                }
+            }
+        });
+    }
+
+    fn check_writable(&self) -> Result<(), nfsstat3> {
+        match self.read_only {
+            true => Err(nfsstat3::NFS3ERR_ROFS),
+            false => Ok(()),
+        }
+    }
+
+    /// Runs `request` on the bucket off the async runtime
+    async fn call<T: Send + 'static>(
+        &self,
+        request: impl FnOnce(&Bucket) -> Result<T, nfsstat3> + Send + 'static,
+    ) -> Result<T, nfsstat3> {
+        let bucket = self.bucket.clone();
+        tokio::task::spawn_blocking(move || request(&bucket))
+            .await
+            .map_err(|_| nfsstat3::NFS3ERR_IO)?
+    }
+
+    /// Lists `dirid` from the bucket, unless it was listed just now
+    async fn refresh_dir(&self, dirid: fileid3) -> Result<(), nfsstat3> {
+        let key = {
+            let map = self.map.lock().await;
+            let dir = map.find(dirid)?;
+            if !matches!(dir.fsmeta.ftype, ftype3::NF3DIR) {
+                return Err(nfsstat3::NFS3ERR_NOTDIR);
+            }
+            if map
+                .listed
+                .get(&dirid)
+                .is_some_and(|at| at.elapsed() < LIST_TTL)
+            {
+                return Ok(());
+            }
+            map.key(&dir.name, true)
+        };
+        let listing = self.call(move |bucket| bucket.list(&key, true)).await?;
+        let pending = self.uploads.lock().await.keys().copied().collect();
+        self.map
+            .lock()
+            .await
+            .apply_listing(dirid, listing, &pending)
+    }
+
+    /// Finds `filename` in `dirid`, listing it from the bucket if needed
+    async fn find_child(&self, dirid: fileid3, filename: &str) -> Result<fileid3, nfsstat3> {
+        self.refresh_dir(dirid).await?;
+        let map = self.map.lock().await;
+        let dir = map.find(dirid)?;
+        map.child(&dir.name, filename)
+            .ok_or(nfsstat3::NFS3ERR_NOENT)
+    }
+
+    /// Sends what was written to `id` up to the bucket
+    async fn flush(&self, id: fileid3) -> Result<(), nfsstat3> {
+        let mut uploads = self.uploads.lock().await;
+        let Some(mut upload) = uploads.remove(&id) else {
+            return Ok(());
+        };
+        let size = upload.size();
+        let key = upload.key.clone();
+        if upload.fetch {
+            // nothing was written, and the object is as it was
+            return Ok(());
+        }
+        let tail = std::mem::take(&mut upload.tail);
+        let done = match upload.upload.take() {
+            None => self.call(move |bucket| bucket.put(&key, &tail)).await,
+            Some(id) => {
+                let mut parts = upload.parts;
+                self.call(move |bucket| {
+                    if !tail.is_empty() {
+                        parts.push(bucket.upload_part(&key, &id, parts.len() + 1, &tail)?);
+                    }
+                    bucket.complete_upload(&key, &id, &parts).inspect_err(|_| {
+                        let _ = bucket.abort_upload(&key, &id);
+                    })
+                })
+                .await
+            }
+        };
+        drop(uploads);
+        done?;
+        debug!("Uploaded {:?}, {} bytes", upload.key, size);
+        let mut map = self.map.lock().await;
+        if let Some(entry) = map.id_to_path.get_mut(&id) {
+            entry.fsmeta.size = size;
+            entry.fsmeta.used = size;
+        }
+        Ok(())
+    }
+
+    /// Drops what was written to `id`, as it is gone
+    async fn discard(&self, id: fileid3) {
+        let Some(upload) = self.uploads.lock().await.remove(&id) else {
+            return;
+        };
+        if let Some(upload_id) = upload.upload {
+            let key = upload.key;
+            let _ = self
+                .call(move |bucket| bucket.abort_upload(&key, &upload_id))
+                .await;
+        }
+    }
+
+    /// Sends the parts of `upload` which are complete up to the bucket
+    async fn send_parts(&self, upload: &mut Upload) -> Result<(), nfsstat3> {
+        while upload.ready() >= PART_SIZE {
+            let key = upload.key.clone();
+            let upload_id = match &upload.upload {
+                Some(id) => id.clone(),
+                None => {
+                    let key = key.clone();
+                    let id = self.call(move |bucket| bucket.start_upload(&key)).await?;
+                    upload.upload = Some(id.clone());
+                    id
+                }
+            };
+            let part = upload.tail[..PART_SIZE].to_vec();
+            let number = upload.parts.len() + 1;
+            let etag = self
+                .call(move |bucket| bucket.upload_part(&key, &upload_id, number, &part))
+                .await?;
+            upload.parts.push(etag);
+            upload.tail.drain(..PART_SIZE);
+            upload.sent += PART_SIZE as u64;
+        }
+        Ok(())
+    }
+
+    async fn attr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
+        let mut attr = self.map.lock().await.find(id)?.fsmeta;
+        if let Some(upload) = self.uploads.lock().await.get(&id) {
+            if !upload.fetch {
+                attr.size = upload.size();
+                attr.used = attr.size;
+            }
+        }
+        Ok(attr)
+    }
+
+    /// Puts an empty object, or a directory marker, named `filename` in
+    /// `dirid`
+    async fn create_object(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+        ftype: ftype3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.check_writable()?;
+        let name = key_name(filename)?;
+        let key = {
+            let map = self.map.lock().await;
+            let dir = map.find(dirid)?;
+            if !matches!(dir.fsmeta.ftype, ftype3::NF3DIR) {
+                return Err(nfsstat3::NFS3ERR_NOTDIR);
+            }
+            let mut key = map.key(&dir.name, true);
+            key.push_str(name);
+            if matches!(ftype, ftype3::NF3DIR) {
+                key.push('/');
+            }
+            key
+        };
+        self.call(move |bucket| bucket.put(&key, &[])).await?;
+        let mut map = self.map.lock().await;
+        let dir = map.find(dirid)?;
+        let path = map.child_path(&dir.name, name);
+        let id = map.place(path, ftype, 0, SystemTime::now(), false);
+        if matches!(ftype, ftype3::NF3DIR) {
+            // a directory made just now is known to be empty
+            if let Some(entry) = map.id_to_path.get_mut(&id) {
+                entry.children = Some(BTreeSet::new());
+            }
+            map.listed.insert(id, Instant::now());
+        }
+        map.change_children(dirid, id, true);
+        Ok((id, map.find(id)?.fsmeta))
+    }
+
+    /// The keys of the object at `key`, or of every object below it if it
+    /// is a directory
+    async fn keys_below(&self, key: &str, ftype: ftype3) -> Result<Vec<String>, nfsstat3> {
+        if !matches!(ftype, ftype3::NF3DIR) {
+            return Ok(vec![key.to_string()]);
+        }
+        let prefix = key.to_string();
+        let listing = self.call(move |bucket| bucket.list(&prefix, false)).await?;
+        Ok(listing.objects.into_iter().map(|o| o.key).collect())
+    }
+}
+
+#[async_trait]
+impl NFSFileSystem for S3FS {
+    fn capabilities(&self) -> VFSCapabilities {
+        match self.read_only {
+            true => VFSCapabilities::ReadOnly,
+            false => VFSCapabilities::ReadWrite,
+        }
+    }
+
+    fn root_dir(&self) -> fileid3 {
+        0
+    }
+
+    async fn lookup(&self, dirid: fileid3, filename: &filename3) -> Result<fileid3, nfsstat3> {
+        match &filename[..] {
+            b"." => return Ok(dirid),
+            b".." => {
+                let map = self.map.lock().await;
+                let mut name = map.find(dirid)?.name;
+                name.pop();
+                return map
+                    .path_to_id
+                    .get(&name)
+                    .as_deref()
+                    .copied()
+                    .ok_or(nfsstat3::NFS3ERR_STALE);
+            }
+            _ => {}
+        }
+        let name = key_name(filename).map_err(|_| nfsstat3::NFS3ERR_NOENT)?;
+        self.find_child(dirid, name).await
+    }
+
+    async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
+        self.attr(id).await
+    }
+
+    async fn setattr(&self, id: fileid3, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let set_size3::size(size) = setattr.size else {
+            // a bucket keeps no modes, owners or times
+            return self.attr(id).await;
+        };
+        self.check_writable()?;
+        self.flush(id).await?;
+        let entry = self.map.lock().await.find(id)?;
+        if !matches!(entry.fsmeta.ftype, ftype3::NF3REG) {
+            return Err(nfsstat3::NFS3ERR_INVAL);
+        }
+        if entry.fsmeta.size != size {
+            let key = self.map.lock().await.key(&entry.name, false);
+            let old = entry.fsmeta.size;
+            self.call(move |bucket| {
+                let mut data = match (size, old) {
+                    (0, _) | (_, 0) => Vec::new(),
+                    _ => bucket.get_range(&key, 0, size.min(old))?,
+                };
+                data.resize(size as usize, 0);
+                bucket.put(&key, &data)
+            })
+            .await?;
+            let mut map = self.map.lock().await;
+            if let Some(entry) = map.id_to_path.get_mut(&id) {
+                entry.fsmeta.size = size;
+                entry.fsmeta.used = size;
+                entry.fsmeta.mtime = to_nfstime(SystemTime::now());
+                entry.fsmeta.ctime = entry.fsmeta.mtime;
+            }
+        }
+        self.attr(id).await
+    }
+
+    async fn read(&self, id: fileid3, offset: u64, count: u32) -> Result<(Bytes, bool), nfsstat3> {
+        {
+            // what is written and still held whole in memory is read from
+            // there, anything else goes up first
+            let uploads = self.uploads.lock().await;
+            if let Some(upload) = uploads.get(&id).filter(|u| !u.fetch && u.upload.is_none()) {
+                let start = (offset as usize).min(upload.tail.len());
+                let end = start.saturating_add(count as usize).min(upload.tail.len());
+                let eof = end == upload.tail.len();
+                return Ok((Bytes::copy_from_slice(&upload.tail[start..end]), eof));
+            }
+        }
+        self.flush(id).await?;
+        let (key, size) = {
+            let map = self.map.lock().await;
+            let entry = map.find(id)?;
+            if !matches!(entry.fsmeta.ftype, ftype3::NF3REG) {
+                return Err(nfsstat3::NFS3ERR_ISDIR);
+            }
+            (map.key(&entry.name, false), entry.fsmeta.size)
+        };
+        if offset >= size {
+            return Ok((Bytes::new(), true));
+        }
+        let count = (count as u64).min(size - offset);
+        let data = self
+            .call(move |bucket| bucket.get_range(&key, offset, count))
+            .await?;
+        let eof = offset + data.len() as u64 >= size;
+        Ok((data.into(), eof))
+    }
+
+    async fn write(&self, id: fileid3, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        self.check_writable()?;
+        let (key, size) = {
+            let map = self.map.lock().await;
+            let entry = map.find(id)?;
+            if !matches!(entry.fsmeta.ftype, ftype3::NF3REG) {
+                return Err(nfsstat3::NFS3ERR_ISDIR);
+            }
+            (map.key(&entry.name, false), entry.fsmeta.size)
+        };
+        let mut uploads = self.uploads.lock().await;
+        let upload = uploads
+            .entry(id)
+            .or_insert_with(|| Upload::new(key.clone(), size));
+        if offset < upload.sent {
+            // what went up already can not be changed, so it is put
+            // together and taken up again
+            drop(uploads);
+            self.flush(id).await?;
+            uploads = self.uploads.lock().await;
+        }
+        let upload = uploads
+            .entry(id)
+            .or_insert_with(|| Upload::new(key.clone(), size));
+        if upload.fetch {
+            let key = key.clone();
+            upload.tail = self.call(move |bucket| bucket.get(&key)).await?;
+            upload.fetch = false;
+        }
+        upload.write(offset, data);
+        upload.touched = Instant::now();
+        self.send_parts(upload).await?;
+        let size = upload.size();
+        drop(uploads);
+        let mut map = self.map.lock().await;
+        let entry = map.id_to_path.get_mut(&id).ok_or(nfsstat3::NFS3ERR_STALE)?;
+        entry.fsmeta.size = size;
+        entry.fsmeta.used = size;
+        entry.fsmeta.mtime = to_nfstime(SystemTime::now());
+        entry.fsmeta.ctime = entry.fsmeta.mtime;
+        Ok(entry.fsmeta)
+    }
+
+    async fn create(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+        _attr: sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.create_object(dirid, filename, ftype3::NF3REG).await
+    }
+
+    async fn create_exclusive(
+        &self,
+        dirid: fileid3,
+        filename: &filename3,
+    ) -> Result<fileid3, nfsstat3> {
+        self.check_writable()?;
+        match self.find_child(dirid, key_name(filename)?).await {
+            Ok(_) => return Err(nfsstat3::NFS3ERR_EXIST),
+            Err(nfsstat3::NFS3ERR_NOENT) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(self.create_object(dirid, filename, ftype3::NF3REG).await?.0)
+    }
+
+    async fn mkdir(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.check_writable()?;
+        match self.find_child(dirid, key_name(dirname)?).await {
+            Ok(_) => return Err(nfsstat3::NFS3ERR_EXIST),
+            Err(nfsstat3::NFS3ERR_NOENT) => {}
+            Err(e) => return Err(e),
+        }
+        self.create_object(dirid, dirname, ftype3::NF3DIR).await
+    }
+
+    async fn remove(&self, dirid: fileid3, filename: &filename3) -> Result<(), nfsstat3> {
+        self.check_writable()?;
+        let id = self.find_child(dirid, key_name(filename)?).await?;
+        let entry = self.map.lock().await.find(id)?;
+        let is_dir = matches!(entry.fsmeta.ftype, ftype3::NF3DIR);
+        let key = self.map.lock().await.key(&entry.name, is_dir);
+        self.discard(id).await;
+        self.call(move |bucket| {
+            if is_dir {
+                let listing = bucket.list(&key, true)?;
+                if !listing.prefixes.is_empty() || listing.objects.iter().any(|o| o.key != key) {
+                    return Err(nfsstat3::NFS3ERR_NOTEMPTY);
+                }
+            }
+            bucket.delete(&key)
+        })
+        .await?;
+        let mut map = self.map.lock().await;
+        map.forget(id);
+        map.change_children(dirid, id, false);
+        Ok(())
+    }
+
+    async fn rename(
+        &self,
+        from_dirid: fileid3,
+        from_filename: &filename3,
+        to_dirid: fileid3,
+        to_filename: &filename3,
+    ) -> Result<(), nfsstat3> {
+        self.check_writable()?;
+        let from_name = key_name(from_filename)?;
+        let to_name = key_name(to_filename)?;
+        let id = self.find_child(from_dirid, from_name).await?;
+        self.flush(id).await?;
+        let target = match self.find_child(to_dirid, to_name).await {
+            Ok(target) if target == id => return Ok(()),
+            Ok(target) => Some(target),
+            Err(nfsstat3::NFS3ERR_NOENT) => None,
+            Err(e) => return Err(e),
+        };
+        let (entry, from_key, to_path, to_key, target) = {
+            let mut map = self.map.lock().await;
+            let entry = map.find(id)?;
+            let is_dir = matches!(entry.fsmeta.ftype, ftype3::NF3DIR);
+            let to_dir = map.find(to_dirid)?;
+            let to_path = map.child_path(&to_dir.name, to_name);
+            let target = match target {
+                Some(target) => Some((target, map.find(target)?.fsmeta.ftype)),
+                None => None,
+            };
+            match target {
+                Some((_, ftype3::NF3DIR)) if !is_dir => return Err(nfsstat3::NFS3ERR_ISDIR),
+                Some((_, ftype)) if !matches!(ftype, ftype3::NF3DIR) && is_dir => {
+                    return Err(nfsstat3::NFS3ERR_NOTDIR)
+                }
+                _ => {}
+            }
+            let from_key = map.key(&entry.name, is_dir);
+            let to_key = map.key(&to_path, is_dir);
+            (entry, from_key, to_path, to_key, target)
+        };
+        if let Some((target, ftype)) = target {
+            self.discard(target).await;
+            if matches!(ftype, ftype3::NF3DIR) {
+                // only an empty directory is renamed over
+                self.remove(to_dirid, to_filename).await?;
+            }
+        }
+        let keys = self.keys_below(&from_key, entry.fsmeta.ftype).await?;
+        let (from, to) = (from_key.clone(), to_key.clone());
+        self.call(move |bucket| {
+            for key in keys.iter() {
+                let moved = format!("{}{}", to, &key[from.len()..]);
+                bucket.copy(key, &moved)?;
+            }
+            for key in keys.iter() {
+                bucket.delete(key)?;
+            }
+            Ok(())
+        })
+        .await?;
+        debug!("Renamed {:?} to {:?}", from_key, to_key);
+
+        let mut map = self.map.lock().await;
+        if let Some((target, _)) = target {
+            map.forget(target);
+            map.change_children(to_dirid, target, false);
+        }
+        // everything below keeps its fileid under the new name
+        let mut below = vec![id];
+        while let Some(next) = below.pop() {
+            let Some(moved) = map.id_to_path.get_mut(&next) else {
+                continue;
+            };
+            let name = [&to_path[..], &moved.name[entry.name.len()..]].concat();
+            let old = std::mem::replace(&mut moved.name, name.clone());
+            below.extend(moved.children.iter().flatten());
+            map.path_to_id.remove(&old);
+            map.path_to_id.insert(name, next);
+        }
+        map.change_children(from_dirid, id, false);
+        map.change_children(to_dirid, id, true);
+        Ok(())
+    }
+
+    async fn readdir(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<ReadDirResult, nfsstat3> {
+        self.refresh_dir(dirid).await?;
+        let map = self.map.lock().await;
+        let dir = map.find(dirid)?;
+        let children = dir.children.ok_or(nfsstat3::NFS3ERR_IO)?;
+        let mut entries = Vec::new();
+        let mut remaining = children.range(start_after + 1..).peekable();
+        while let Some(id) = remaining.peek() {
+            if entries.len() >= max_entries {
+                break;
+            }
+            if let Some(child) = map.id_to_path.get(id) {
+                let name = child
+                    .name
+                    .last()
+                    .and_then(|sym| map.intern.get(*sym))
+                    .unwrap_or_default();
+                entries.push(DirEntry {
+                    fileid: **id,
+                    name: name.as_bytes().into(),
+                    attr: child.fsmeta,
+                });
+            }
+            remaining.next();
+        }
+        Ok(ReadDirResult {
+            entries,
+            end: remaining.peek().is_none(),
+        })
+    }
+
+    async fn symlink(
+        &self,
+        _dirid: fileid3,
+        _linkname: &filename3,
+        _symlink: &nfspath3,
+        _attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    async fn readlink(&self, _id: fileid3) -> Result<nfspath3, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
+    async fn shutdown(&self) {
+        let pending: Vec<fileid3> = self.uploads.lock().await.keys().copied().collect();
+        for id in pending {
+            if let Err(e) = self.flush(id).await {
+                warn!("Unable to upload fileid {}: {:?}", id, e);
+            }
+        }
+    }
+}
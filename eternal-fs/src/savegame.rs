@@ -0,0 +1,336 @@
+//! The players' progress is kept in the [`StateStore`] of the world,
+//! `.eternal/game.toml` under the root unless configured, so that a
+//! restarted server picks every journey up where it was left, encrypted
+//! with a key, see `encryption.rs`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::coop::Contribution;
+use crate::dialogue::Conversation;
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+use crate::encryption::{self, StateKey};
+use crate::meditation::Meditation;
+use crate::philosophy::{ArchivedAnswer, GameStage, Philosophy};
+use crate::players::{PlayerState, SHARED_PLAYER};
+use crate::riddles::RiddleAttempt;
+use crate::speedrun::Speedrun;
+use crate::store::StateStore;
+use crate::STATE_DIR;
+
+/// The progress of every player, as a [`StateStore`] keeps it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedGame {
+    /// The shared journey
+    #[serde(flatten)]
+    shared: SavedJourney,
+    #[serde(default)]
+    state: BTreeMap<String, String>,
+    /// The journeys of the other players, by player
+    #[serde(default)]
+    players: BTreeMap<String, SavedJourney>,
+}
+
+/// A saved game as records of their own, for stores keeping it that way
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SavedRecords {
+    /// Every journey in JSON, the shared one too, by player
+    pub(crate) journeys: BTreeMap<String, String>,
+    /// The game state, by key
+    pub(crate) state: BTreeMap<String, String>,
+}
+
+impl Default for SavedGame {
+    fn default() -> Self {
+        SavedGame {
+            shared: SavedJourney::new(&PlayerState::default()),
+            state: BTreeMap::new(),
+            players: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+impl SavedGame {
+    /// The saved game as records of their own
+    pub(crate) fn records(&self) -> io::Result<SavedRecords> {
+        let mut journeys = BTreeMap::new();
+        let shared = (SHARED_PLAYER.to_string(), &self.shared);
+        let everyone = self
+            .players
+            .iter()
+            .map(|(id, journey)| (id.clone(), journey));
+        for (id, journey) in everyone.chain([shared]) {
+            let journey = serde_json::to_string(journey).map_err(io::Error::other)?;
+            journeys.insert(id, journey);
+        }
+        Ok(SavedRecords {
+            journeys,
+            state: self.state.clone(),
+        })
+    }
+
+    /// The saved game kept as `records`
+    pub(crate) fn from_records(records: SavedRecords) -> io::Result<SavedGame> {
+        let mut game = SavedGame {
+            state: records.state,
+            ..SavedGame::default()
+        };
+        for (id, journey) in records.journeys {
+            let journey: SavedJourney = serde_json::from_str(&journey)
+                .map_err(|e| io::Error::other(format!("the journey of {}: {}", id, e)))?;
+            match id == SHARED_PLAYER {
+                true => game.shared = journey,
+                false => {
+                    game.players.insert(id, journey);
+                }
+            }
+        }
+        Ok(game)
+    }
+}
+
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+impl SavedRecords {
+    /// The records with their values encrypted with `key`, if there is one
+    pub(crate) fn seal(self, key: Option<&StateKey>) -> SavedRecords {
+        let Some(key) = key else {
+            return self;
+        };
+        let seal = |records: BTreeMap<String, String>| {
+            records
+                .into_iter()
+                .map(|(id, value)| (id, key.seal_text(&value)))
+                .collect()
+        };
+        SavedRecords {
+            journeys: seal(self.journeys),
+            state: seal(self.state),
+        }
+    }
+
+    /// The records with their values decrypted with `key`, failing for
+    /// any encrypted without one
+    pub(crate) fn open(self, key: Option<&StateKey>) -> io::Result<SavedRecords> {
+        let open = |records: BTreeMap<String, String>| {
+            records
+                .into_iter()
+                .map(|(id, value)| Ok((id, encryption::open_text(key, &value)?)))
+                .collect::<io::Result<_>>()
+        };
+        Ok(SavedRecords {
+            journeys: open(self.journeys)?,
+            state: open(self.state)?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedJourney {
+    stage: GameStage,
+    #[serde(default)]
+    completed: BTreeSet<String>,
+    /// Every accepted version of an answer, by location
+    #[serde(default)]
+    answers: BTreeMap<String, Vec<ArchivedAnswer>>,
+    /// The last reply to an answer, by location
+    #[serde(default)]
+    responses: BTreeMap<String, String>,
+    /// When every achievement was earned, by achievement
+    #[serde(default)]
+    achievements: BTreeMap<String, SystemTime>,
+    /// The stage directories visited
+    #[serde(default)]
+    visited: BTreeSet<String>,
+    /// When the journey began, now if it was saved without
+    started: Option<SystemTime>,
+    /// When a stage was last completed
+    last_completed: Option<SystemTime>,
+    #[serde(default)]
+    hint_tokens_spent: u32,
+    /// Hints taken, by stage
+    #[serde(default)]
+    hints_taken: BTreeMap<String, usize>,
+    latest_hint: Option<String>,
+    meditation: Option<Meditation>,
+    /// The artifacts used, by stage
+    #[serde(default)]
+    used_artifacts: BTreeMap<String, BTreeSet<String>>,
+    /// The emotional state, neutral if it was saved without
+    mood: Option<String>,
+    /// The conversations with the characters, by file
+    #[serde(default)]
+    conversations: BTreeMap<String, Conversation>,
+    /// What the riddles are made up from, drawn anew if it was saved
+    /// without
+    riddle_seed: Option<u32>,
+    /// How the riddles were answered, by stage
+    #[serde(default)]
+    riddles: BTreeMap<String, RiddleAttempt>,
+    /// The days the daily challenge was met of
+    #[serde(default)]
+    daily_completed: BTreeSet<String>,
+    /// The chronicle of the journey, once written
+    chronicle: Option<String>,
+    /// The speedrun of the journey, with the time it was stopped at
+    speedrun: Option<Speedrun>,
+    /// The side quests completed, with the hint tokens they granted
+    #[serde(default)]
+    quests: BTreeMap<String, u32>,
+    /// The sealed files unsealed, by path
+    #[serde(default)]
+    unsealed: BTreeSet<String>,
+    /// The easter eggs found, by name
+    #[serde(default)]
+    eggs: BTreeSet<String>,
+    /// The answers waiting for more contributors, by stage
+    #[serde(default)]
+    coop: BTreeMap<String, Vec<Contribution>>,
+}
+
+impl SavedJourney {
+    fn new(player: &PlayerState) -> Self {
+        SavedJourney {
+            stage: player.current_stage.clone(),
+            completed: player.completed_questions.iter().cloned().collect(),
+            answers: player
+                .answer_archive
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            responses: player
+                .responses
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            achievements: player.achievements.clone(),
+            visited: player.visited.iter().cloned().collect(),
+            started: Some(player.started),
+            last_completed: player.last_completed,
+            hint_tokens_spent: player.hint_tokens_spent,
+            hints_taken: player.hints_taken.clone(),
+            latest_hint: player.latest_hint.clone(),
+            meditation: player.meditation.clone(),
+            used_artifacts: player.used_artifacts.clone(),
+            mood: Some(player.philosophical_state.emotional_state.clone()),
+            conversations: player.conversations.clone(),
+            riddle_seed: Some(player.riddle_seed),
+            riddles: player.riddles.clone(),
+            daily_completed: player.daily_completed.clone(),
+            chronicle: player.chronicle.clone(),
+            speedrun: player.speedrun.as_ref().map(Speedrun::paused),
+            quests: player.quests_completed.clone(),
+            unsealed: player.unsealed.clone(),
+            eggs: player.eggs_found.clone(),
+            coop: player.coop.clone(),
+        }
+    }
+
+    fn restore(self) -> PlayerState {
+        let mut player = PlayerState {
+            current_stage: self.stage,
+            completed_questions: self.completed.into_iter().collect(),
+            answer_archive: self.answers.into_iter().collect(),
+            responses: self.responses.into_iter().collect(),
+            achievements: self.achievements,
+            visited: self.visited.into_iter().collect(),
+            started: self.started.unwrap_or_else(SystemTime::now),
+            last_completed: self.last_completed,
+            hint_tokens_spent: self.hint_tokens_spent,
+            hints_taken: self.hints_taken,
+            latest_hint: self.latest_hint,
+            meditation: self.meditation,
+            used_artifacts: self.used_artifacts,
+            conversations: self.conversations,
+            riddle_seed: self.riddle_seed.unwrap_or_else(rand::random),
+            riddles: self.riddles,
+            daily_completed: self.daily_completed,
+            chronicle: self.chronicle,
+            speedrun: self.speedrun,
+            quests_completed: self.quests,
+            unsealed: self.unsealed,
+            eggs_found: self.eggs,
+            coop: self.coop,
+            ..PlayerState::default()
+        };
+        if let Some(mood) = self.mood {
+            player.philosophical_state.emotional_state = mood;
+        }
+        player
+    }
+}
+
+impl Philosophy {
+    /// The store the game is kept in, the configured one unless another
+    /// was set
+    fn store(&mut self) -> Result<&dyn StateStore, String> {
+        if self.store.is_none() {
+            self.store = Some(self.config.store.build(&self.root.join(STATE_DIR))?);
+        }
+        Ok(self.store.as_deref().expect("the store was just opened"))
+    }
+
+    /// The progress of the game, as it is saved
+    pub(crate) fn saved_game(&self) -> SavedGame {
+        SavedGame {
+            shared: SavedJourney::new(&self.players[SHARED_PLAYER]),
+            state: self
+                .game_state
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            players: self
+                .players
+                .iter()
+                .filter(|(id, _)| *id != SHARED_PLAYER)
+                .map(|(id, player)| (id.clone(), SavedJourney::new(player)))
+                .collect(),
+        }
+    }
+
+    /// Writes the progress of the game to its store
+    pub(crate) fn save_game(&mut self) -> io::Result<()> {
+        let saved = self.saved_game();
+        self.store().map_err(io::Error::other)?.save(saved)
+    }
+
+    /// Restores the progress saved by a previous run, if any
+    pub(crate) fn restore_game(&mut self) {
+        let loaded = match self.store() {
+            Ok(store) => store.load(),
+            Err(e) => {
+                warn!("Unable to open the saved game: {}", e);
+                return;
+            }
+        };
+        let saved = match loaded {
+            Ok(Some(saved)) => saved,
+            Ok(None) => {
+                debug!("No saved game to resume");
+                return;
+            }
+            Err(e) => {
+                warn!("Ignoring unreadable saved game: {}", e);
+                return;
+            }
+        };
+        debug!(
+            "Resuming the journey at {:?}, and {} more",
+            saved.shared.stage,
+            saved.players.len()
+        );
+        self.players = saved
+            .players
+            .into_iter()
+            .map(|(id, journey)| (id, journey.restore()))
+            .collect();
+        self.players
+            .insert(SHARED_PLAYER.to_string(), saved.shared.restore());
+        self.game_state = saved.state.into_iter().collect();
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Local, TimeZone, Timelike};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// How far back missed runs are looked for after downtime
+const MAX_CATCH_UP: chrono::Duration = chrono::Duration::days(31);
+
+/// The schedule used when the operator has not provided one
+const DEFAULT_SCHEDULE: &str = "\
+# min hour day-of-month month day-of-week event
+0 0 * * * rotate-koan
+0 0 * * * daily-challenge
+0 23 * * * open-dreams
+*/15 * * * * shift-dreams
+0 9 * * 1 companion-letter
+";
+
+/// Something that happens to the world on a schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WorldEvent {
+    /// Replace koan.txt with the next koan
+    RotateKoan,
+    /// Open the dreams/ directory
+    OpenDreams,
+    /// Dream a new dream in dreams/ and let the older ones fade
+    ShiftDreams,
+    /// Deliver a letter from the companion into letters/
+    CompanionLetter,
+    /// Draw a new challenge for daily_challenge.txt
+    DailyChallenge,
+}
+
+impl FromStr for WorldEvent {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rotate-koan" => Ok(WorldEvent::RotateKoan),
+            "open-dreams" => Ok(WorldEvent::OpenDreams),
+            "shift-dreams" => Ok(WorldEvent::ShiftDreams),
+            "companion-letter" => Ok(WorldEvent::CompanionLetter),
+            "daily-challenge" => Ok(WorldEvent::DailyChallenge),
+            _ => Err(format!("unknown world event {:?}", s)),
+        }
+    }
+}
+
+/// A standard 5 field cron expression: minute, hour, day of month,
+/// month and day of week. Each field accepts `*`, numbers, ranges
+/// (`1-5`), lists (`1,3,5`) and steps (`*/15`, `0-30/10`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// cron matches either day field if both are restricted
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .map_err(|_| format!("invalid step in {:?}", part))?,
+            ),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("zero step in {:?}", part));
+        }
+        let (lo, hi) = if range == "*" {
+            (min, max)
+        } else if let Some((lo, hi)) = range.split_once('-') {
+            let lo = lo.parse().map_err(|_| format!("invalid value {:?}", lo))?;
+            let hi = hi.parse().map_err(|_| format!("invalid value {:?}", hi))?;
+            (lo, hi)
+        } else {
+            let v = range
+                .parse()
+                .map_err(|_| format!("invalid value {:?}", range))?;
+            (v, if part.contains('/') { max } else { v })
+        };
+        if lo < min || hi > max || lo > hi {
+            return Err(format!("{:?} out of range {}-{}", part, min, max));
+        }
+        for v in (lo..=hi).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("expected 5 cron fields, got {:?}", s));
+        }
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        // both 0 and 7 are Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    pub fn matches<Tz: TimeZone>(&self, t: &DateTime<Tz>) -> bool {
+        let bit = |mask: u64, v: u32| mask & (1 << v) != 0;
+        let dom = bit(self.days_of_month, t.day());
+        let dow = bit(self.days_of_week, t.weekday().num_days_from_sunday());
+        let day = match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            _ => dom && dow,
+        };
+        bit(self.minutes, t.minute())
+            && bit(self.hours, t.hour())
+            && bit(self.months, t.month())
+            && day
+    }
+
+    /// Returns true if the schedule fired at any minute in (after, until]
+    pub fn fired_between<Tz: TimeZone>(&self, after: &DateTime<Tz>, until: &DateTime<Tz>) -> bool {
+        let start = (until.clone() - MAX_CATCH_UP).max(after.clone());
+        let mut t = truncate_to_minute(&start) + chrono::Duration::minutes(1);
+        while t <= *until {
+            if self.matches(&t) {
+                return true;
+            }
+            t += chrono::Duration::minutes(1);
+        }
+        false
+    }
+}
+
+fn truncate_to_minute<Tz: TimeZone>(t: &DateTime<Tz>) -> DateTime<Tz> {
+    t.clone()
+        .with_second(0)
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or_else(|| t.clone())
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduledJob {
+    /// The crontab line, which also identifies the job in the state file
+    pub line: String,
+    pub schedule: CronSchedule,
+    pub event: WorldEvent,
+}
+
+/// Parses a crontab style schedule. Empty lines and lines starting
+/// with `#` are ignored, every other line is five cron fields followed
+/// by the name of a world event.
+pub fn parse_crontab(contents: &str) -> Result<Vec<ScheduledJob>, String> {
+    let mut jobs = Vec::new();
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 6 {
+            return Err(format!("expected 5 cron fields and an event: {:?}", line));
+        }
+        jobs.push(ScheduledJob {
+            line: fields.join(" "),
+            schedule: fields[..5].join(" ").parse()?,
+            event: fields[5].parse()?,
+        });
+    }
+    Ok(jobs)
+}
+
+/// Runs the world events of a crontab, publishing each event as it fires.
+///
+/// The time each job last ran is persisted so that after downtime every
+/// job that should have fired in the meantime runs once on startup.
+#[derive(Debug)]
+pub struct Scheduler {
+    jobs: Vec<ScheduledJob>,
+    state_path: PathBuf,
+    last_runs: HashMap<String, i64>,
+}
+
+impl Scheduler {
+    /// Loads the operator schedule from `<statedir>/schedule`, falling
+    /// back to the default schedule if it is missing or malformed.
+    pub fn load(statedir: &Path) -> Scheduler {
+        let jobs = match std::fs::read_to_string(statedir.join("schedule")) {
+            Ok(contents) => parse_crontab(&contents).unwrap_or_else(|e| {
+                warn!("Ignoring invalid schedule: {}", e);
+                parse_crontab(DEFAULT_SCHEDULE).unwrap()
+            }),
+            Err(_) => parse_crontab(DEFAULT_SCHEDULE).unwrap(),
+        };
+        let state_path = statedir.join("schedule.state");
+        let last_runs = std::fs::read_to_string(&state_path)
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(|l| l.rsplit_once('\t'))
+                    .filter_map(|(job, ts)| Some((job.to_string(), ts.parse().ok()?)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Scheduler {
+            jobs,
+            state_path,
+            last_runs,
+        }
+    }
+
+    fn save(&self) {
+        let contents: String = self
+            .last_runs
+            .iter()
+            .map(|(job, ts)| format!("{}\t{}\n", job, ts))
+            .collect();
+        if let Some(dir) = self.state_path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = std::fs::write(&self.state_path, contents) {
+            warn!("Unable to persist scheduler state {:?}", e);
+        }
+    }
+
+    async fn fire(&mut self, job: usize, now: &DateTime<Local>, events: &mpsc::Sender<WorldEvent>) {
+        let job = &self.jobs[job];
+        debug!("Scheduled event {:?} firing", job.line);
+        let _ = events.send(job.event).await;
+        self.last_runs.insert(job.line.clone(), now.timestamp());
+    }
+
+    /// Runs every job that should have fired while the server was down
+    async fn catch_up(&mut self, events: &mpsc::Sender<WorldEvent>) {
+        let now = Local::now();
+        for i in 0..self.jobs.len() {
+            let job = &self.jobs[i];
+            let Some(last) = self
+                .last_runs
+                .get(&job.line)
+                .and_then(|ts| Local.timestamp_opt(*ts, 0).single())
+            else {
+                // never ran before, start counting from now
+                self.last_runs.insert(job.line.clone(), now.timestamp());
+                continue;
+            };
+            if job.schedule.fired_between(&last, &now) {
+                info!("Catching up on missed event {:?}", job.line);
+                self.fire(i, &now, events).await;
+            }
+        }
+        self.save();
+    }
+
+    pub async fn run(mut self, events: mpsc::Sender<WorldEvent>) {
+        self.catch_up(&events).await;
+        loop {
+            let now = Local::now();
+            let until_next_minute = 60 - now.second() as u64;
+            tokio::time::sleep(Duration::from_secs(until_next_minute)).await;
+
+            let now = truncate_to_minute(&Local::now());
+            let mut fired = false;
+            for i in 0..self.jobs.len() {
+                let job = &self.jobs[i];
+                let already_ran = self.last_runs.get(&job.line) == Some(&now.timestamp());
+                if !already_ran && job.schedule.matches(&now) {
+                    self.fire(i, &now, &events).await;
+                    fired = true;
+                }
+            }
+            if fired {
+                self.save();
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! How answers are scored.
+//!
+//! A stage scores an answer by the words and patterns in it, each worth a
+//! weight, and passes it once the score reaches the stage's threshold.
+//! Keywords are worth 1 each. Unless a stage sets a threshold, an answer
+//! has to contain every keyword and match every pattern worth something.
+
+use std::fmt;
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer};
+
+/// How demanding the world is, chosen by the operator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("unknown difficulty {:?}", s)),
+        }
+    }
+}
+
+/// A regular expression an answer is matched against, in any case
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn is_match(&self, response: &str) -> bool {
+        self.0.is_match(response)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RegexBuilder::new(s)
+            .case_insensitive(true)
+            .build()
+            .map(Pattern)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Something an answer scores for
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoreRule {
+    pub pattern: Pattern,
+    /// What a match is worth, 1 unless given. A negative weight counts
+    /// against the answer.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// The score an answer needs, either at every difficulty or at each
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Threshold {
+    Fixed(f64),
+    /// Easy and hard fall back to the normal threshold if not given
+    PerDifficulty {
+        easy: Option<f64>,
+        normal: f64,
+        hard: Option<f64>,
+    },
+}
+
+impl Threshold {
+    /// The score needed at `difficulty`
+    pub fn at(&self, difficulty: Difficulty) -> f64 {
+        match *self {
+            Threshold::Fixed(score) => score,
+            Threshold::PerDifficulty { easy, normal, hard } => match difficulty {
+                Difficulty::Easy => easy.unwrap_or(normal),
+                Difficulty::Normal => normal,
+                Difficulty::Hard => hard.unwrap_or(normal),
+            },
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Files sealed with a passphrase.
+//!
+//! A stage of a pack can have sealed files, see
+//! [`StageDef::sealed`](crate::packs::StageDef::sealed). Their contents are
+//! kept encrypted in the stage directory, so that reading them shows only
+//! the ciphertext, until the player writes the passphrase to `<file>.key`
+//! beside them. From then on the file reads as what it holds. The
+//! passphrase is meant to be found elsewhere in the world: the creation
+//! path of the core pack tells the one of the scroll in `history/`. Every
+//! player unseals files for themselves.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use nfsserve::nfs::nfsstat3;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::engine::{read_at, VirtualAttrs, VirtualFile};
+use crate::packs::SealedDef;
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The extension of the file the passphrase of a sealed file is written to
+pub(crate) const KEY_EXTENSION: &str = "key";
+
+/// The first line of a sealed file
+const BEGIN: &str = "-----BEGIN SEALED FILE-----";
+
+/// The last line of a sealed file
+const END: &str = "-----END SEALED FILE-----";
+
+/// What the key of a sealed file is derived from, besides the passphrase
+const KEY_CONTEXT: &[u8] = b"eternal-fs sealed file\0";
+
+/// Bytes of the nonce every sealed file starts with
+const NONCE_LEN: usize = 12;
+
+/// Characters of the ciphertext on a line
+const LINE_LEN: usize = 64;
+
+/// `passphrase` in lower case, with its words single spaced
+fn normalize(passphrase: &str) -> String {
+    passphrase
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The cipher of the files sealed with `passphrase`
+fn cipher(passphrase: &str) -> ChaCha20Poly1305 {
+    let mut hasher = Sha256::new();
+    hasher.update(KEY_CONTEXT);
+    hasher.update(normalize(passphrase).as_bytes());
+    ChaCha20Poly1305::new(Key::from_slice(&hasher.finalize()))
+}
+
+/// `contents` sealed with `passphrase`, as it is kept on the host
+fn seal(contents: &str, passphrase: &str) -> String {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let mut sealed = nonce.to_vec();
+    // encrypting a plain buffer in memory can not fail
+    sealed.extend(
+        cipher(passphrase)
+            .encrypt(Nonce::from_slice(&nonce), contents.as_bytes())
+            .unwrap_or_default(),
+    );
+    let hex: String = sealed.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let lines: Vec<&str> = hex
+        .as_bytes()
+        .chunks(LINE_LEN)
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .collect();
+    format!("{}\n{}\n{}\n", BEGIN, lines.join("\n"), END)
+}
+
+/// What the sealed file `armored` holds, if `passphrase` unseals it
+fn unseal(armored: &[u8], passphrase: &str) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(armored).ok()?.trim();
+    let hex: String = text
+        .strip_prefix(BEGIN)?
+        .strip_suffix(END)?
+        .split_whitespace()
+        .collect();
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    if bytes.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    cipher(passphrase)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .ok()
+}
+
+impl Philosophy {
+    /// The sealed file kept at `path`, if there is one
+    fn sealed_at(&self, path: &Path) -> Option<SealedDef> {
+        let stage = self.world.stage(path.parent()?.to_str()?)?;
+        let name = path.file_name()?.to_str()?;
+        stage
+            .def
+            .sealed
+            .iter()
+            .find(|sealed| sealed.file == name)
+            .cloned()
+    }
+
+    /// Seals the files of every stage into its directory, unless they are
+    /// sealed there already
+    pub(crate) fn create_sealed_files(&mut self) {
+        for stage in self.world.stages.iter() {
+            for sealed in stage.def.sealed.iter() {
+                let host = self.root.join(&stage.dir).join(&sealed.file);
+                let kept = std::fs::read(&host)
+                    .ok()
+                    .and_then(|armored| unseal(&armored, &sealed.passphrase));
+                if kept.is_some_and(|kept| kept == sealed.contents.as_bytes()) {
+                    continue;
+                }
+                if let Err(e) = std::fs::write(&host, seal(&sealed.contents, &sealed.passphrase)) {
+                    warn!("Unable to seal {:?}: {:?}", host, e);
+                }
+            }
+        }
+    }
+
+    /// The files the passphrases of the sealed files are written to
+    pub(crate) fn seal_key_paths(&self) -> Vec<PathBuf> {
+        self.world
+            .stages
+            .iter()
+            .flat_map(|stage| {
+                stage.def.sealed.iter().map(|sealed| {
+                    Path::new(&stage.dir).join(format!("{}.{}", sealed.file, KEY_EXTENSION))
+                })
+            })
+            .collect()
+    }
+
+    /// What the sealed file at `path` holds, if the player making the call
+    /// unsealed it
+    pub(crate) fn unsealed_contents(&self, path: &Path) -> Option<Vec<u8>> {
+        let sealed = self.sealed_at(path)?;
+        let name = path.to_str()?;
+        self.players
+            .get(&self.player_identity.current())
+            .filter(|player| player.unsealed.contains(name))?;
+        let armored = std::fs::read(self.root.join(path)).ok()?;
+        unseal(&armored, &sealed.passphrase)
+    }
+
+    /// The key file at `path`, as the player making the call sees it
+    pub(crate) fn open_seal_key(&mut self, path: &Path) -> Option<Box<dyn VirtualFile + '_>> {
+        // the key of `scroll.txt` is `scroll.txt.key`
+        let file = path
+            .to_str()?
+            .strip_suffix(KEY_EXTENSION)?
+            .strip_suffix('.')?
+            .to_string();
+        self.sealed_at(Path::new(&file))?;
+        Some(Box::new(SealKeyFile { game: self, file }))
+    }
+}
+
+/// The key of a sealed file, as the player making the call sees it
+struct SealKeyFile<'a> {
+    game: &'a mut Philosophy,
+    /// The path of the sealed file
+    file: String,
+}
+
+impl SealKeyFile<'_> {
+    fn contents(&self) -> String {
+        let newcomer = PlayerState::default();
+        let player = self
+            .game
+            .players
+            .get(&self.game.player_identity.current())
+            .unwrap_or(&newcomer);
+        let name = Path::new(&self.file)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match player.unsealed.contains(&self.file) {
+            true => format!("{} is unsealed. Read it.\n", name),
+            false => format!("{} is sealed. Write its passphrase to this file.\n", name),
+        }
+    }
+}
+
+impl VirtualFile for SealKeyFile<'_> {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        Ok(read_at(self.contents().as_bytes(), offset, count))
+    }
+
+    fn write(&mut self, _offset: u64, data: &[u8]) -> Result<(), nfsstat3> {
+        let passphrase = String::from_utf8_lossy(data);
+        let armored = std::fs::read(self.game.root.join(&self.file)).unwrap_or_default();
+        if unseal(&armored, &passphrase).is_none() {
+            return Ok(());
+        }
+        let id = self.game.player_identity.current();
+        let player = self.game.players.entry(id).or_default();
+        if player.unsealed.insert(self.file.clone()) {
+            info!("{} was unsealed", self.file);
+            player
+                .philosophical_state
+                .note_event(SystemTime::now(), format!("Unsealed {}", self.file));
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.contents().len() as u64
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o644,
+            mtime: SystemTime::now(),
+        }
+    }
+}
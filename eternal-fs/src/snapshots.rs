@@ -0,0 +1,390 @@
+//! The world as it was, under `.snapshots/`.
+//!
+//! With `--snapshots N` (or `snapshots = N` under `[server]`) a snapshot
+//! of the world is taken as it is first served and whenever a player
+//! reaches another stage, and the `N` most recent are kept. Each is shown
+//! read-only as `.snapshots/<time>/`, the time it was taken, through the
+//! [virtual files](crate::virtual_files), so that players can look back at
+//! what they wrote along the way.
+//!
+//! Snapshots are kept under `.eternal/snapshots/`, each with the list of
+//! the files it holds in `snapshot.json`. Small files, the answers among
+//! them, are copied as the snapshot is taken, as the game rewrites its
+//! own files without going through the file system. Larger ones are
+//! shared with the world until a client changes, removes or renames them,
+//! and only copied then. One changed on the host in the meantime can no
+//! longer be read from the snapshot. Directories which held no file are
+//! not kept. What is copied is only kept once, however many snapshots
+//! hold it, see `objects.rs`, and compressed with `--compress`, see
+//! `compression.rs`.
+
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use nfsserve::nfs::nfsstat3;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tracing::{debug, info, warn};
+
+use crate::compression::{self, Kept};
+use crate::engine::{VirtualAttrs, VirtualFile};
+use crate::events::{Event, EventKind};
+use crate::objects;
+use crate::trash::TRASH_DIR;
+use crate::{FSMap, STATE_DIR};
+
+/// The directory snapshots are shown in, at the root of the world
+pub(crate) const SNAPSHOTS_DIR: &str = ".snapshots";
+
+/// Where snapshots are kept, within the state directory
+const SNAPSHOT_STORE: &str = "snapshots";
+
+/// The list of the files of a snapshot, within its directory
+const MANIFEST: &str = "snapshot.json";
+
+/// Where the files copied to a snapshot are kept, within its directory
+const COPIES: &str = "files";
+
+/// Files up to this size are copied as a snapshot is taken
+const COPY_AT_ONCE: u64 = 64 * 1024;
+
+/// A file as it was when the snapshot was taken
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeptFile {
+    size: u64,
+    mtime: SystemTime,
+    /// Whether it was copied to the snapshot, rather than still shared
+    /// with the world
+    copied: bool,
+    /// Whether its copy is compressed
+    #[serde(default)]
+    compressed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    /// What the snapshot was taken for
+    reason: String,
+    /// The files of the world, by path
+    files: BTreeMap<PathBuf, KeptFile>,
+}
+
+#[derive(Debug)]
+struct Snapshot {
+    /// The time it was taken, which it is shown under
+    name: String,
+    manifest: Manifest,
+}
+
+/// The snapshots of the world
+#[derive(Debug, Default)]
+pub(crate) struct Snapshots {
+    /// How many are kept
+    keep: usize,
+    /// The oldest first
+    taken: Vec<Snapshot>,
+    /// The events of the world, to take a snapshot as a stage is
+    /// reached, if snapshots are taken
+    events: Option<broadcast::Receiver<Event>>,
+}
+
+/// A file of a snapshot, read from its copy or the world it still shares
+pub(crate) struct SnapshotFile {
+    source: PathBuf,
+    kept: KeptFile,
+}
+
+impl VirtualFile for SnapshotFile {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let mut kept =
+            Kept::open(&self.source, self.kept.compressed).or(Err(nfsstat3::NFS3ERR_STALE))?;
+        let buf = kept.read(offset, count).or(Err(nfsstat3::NFS3ERR_IO))?;
+        let eof = offset + buf.len() as u64 >= self.kept.size;
+        Ok((buf, eof))
+    }
+
+    fn size(&self) -> u64 {
+        self.kept.size
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o444,
+            mtime: self.kept.mtime,
+        }
+    }
+}
+
+/// Whether the file of the host with `meta` is still as it was kept
+fn unchanged(kept: &KeptFile, meta: &Metadata) -> bool {
+    meta.len() == kept.size && meta.modified().ok() == Some(kept.mtime)
+}
+
+/// Adds the files below `dir` of the host, relative to `root`, to `files`.
+/// The state of the server and the trash are not part of the world.
+fn walk(root: &Path, dir: &Path, files: &mut Vec<(PathBuf, Metadata)>) {
+    let Ok(entries) = std::fs::read_dir(root.join(dir)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = dir.join(entry.file_name());
+        if path == Path::new(STATE_DIR) || path == Path::new(".git") || path == Path::new(TRASH_DIR)
+        {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            walk(root, &path, files);
+        } else if meta.is_file() {
+            files.push((path, meta));
+        }
+    }
+}
+
+impl FSMap {
+    /// The directory the snapshots are kept in
+    fn snapshot_store(&self) -> PathBuf {
+        self.root.join(STATE_DIR).join(SNAPSHOT_STORE)
+    }
+
+    /// Keeps the `keep` most recent snapshots, and takes the first if
+    /// there is none yet. No snapshot is taken, nor any removed, if `keep`
+    /// is 0.
+    pub(crate) fn set_snapshots(&mut self, keep: usize) {
+        if keep == 0 {
+            return;
+        }
+        self.snapshots.keep = keep;
+        self.snapshots.events = Some(self.events.subscribe());
+        let mut names: Vec<String> = std::fs::read_dir(self.snapshot_store())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        // the names sort by the time the snapshots were taken
+        names.sort();
+        self.snapshots.taken = names
+            .into_iter()
+            .filter_map(|name| {
+                let manifest = self.snapshot_store().join(&name).join(MANIFEST);
+                let manifest = std::fs::read(&manifest)
+                    .ok()
+                    .and_then(|json| serde_json::from_slice(&json).ok());
+                if manifest.is_none() {
+                    warn!("Unable to read the snapshot {}", name);
+                }
+                Some(Snapshot {
+                    name,
+                    manifest: manifest?,
+                })
+            })
+            .collect();
+        if self.snapshots.taken.is_empty() {
+            self.take_snapshot("the world was first served".to_string());
+        }
+        self.prune_snapshots();
+    }
+
+    /// Takes a snapshot if a player reached another stage since the last
+    /// call
+    pub(crate) fn take_due_snapshots(&mut self) {
+        let Some(events) = &mut self.snapshots.events else {
+            return;
+        };
+        let mut reached = Vec::new();
+        loop {
+            match events.try_recv() {
+                Ok(Event {
+                    kind: EventKind::StageAdvanced { player, to, .. },
+                    ..
+                }) => reached.push(format!("{} reached {}", player, to)),
+                Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                Err(_) => break,
+            }
+        }
+        if !reached.is_empty() {
+            self.take_snapshot(reached.join(", "));
+        }
+    }
+
+    /// Takes a snapshot of the world as it is on the host now
+    fn take_snapshot(&mut self, reason: String) {
+        let now = DateTime::<Local>::from(SystemTime::now())
+            .format("%Y-%m-%dT%H-%M-%S")
+            .to_string();
+        // two snapshots in the same second are told apart
+        let mut name = now.clone();
+        for n in 2.. {
+            if !self.snapshots.taken.iter().any(|s| s.name == name) {
+                break;
+            }
+            name = format!("{}-{}", now, n);
+        }
+        let dir = self.snapshot_store().join(&name);
+        let mut files = Vec::new();
+        walk(&self.root, Path::new(""), &mut files);
+        let mut manifest = Manifest {
+            reason,
+            files: BTreeMap::new(),
+        };
+        for (path, meta) in files {
+            let mut kept = KeptFile {
+                size: meta.len(),
+                mtime: meta.modified().unwrap_or_else(|_| SystemTime::now()),
+                copied: false,
+                compressed: false,
+            };
+            if kept.size <= COPY_AT_ONCE {
+                let copy_to = dir.join(COPIES).join(&path);
+                let host = self.root.join(&path);
+                match compression::keep_file(&self.root, &host, &copy_to, None, self.compress) {
+                    Ok(compressed) => kept.compressed = compressed,
+                    Err(e) => {
+                        warn!(
+                            "Unable to copy {:?} to the snapshot {}: {:?}",
+                            path, name, e
+                        );
+                        continue;
+                    }
+                }
+                kept.copied = true;
+            }
+            manifest.files.insert(path, kept);
+        }
+        let snapshot = Snapshot { name, manifest };
+        if let Err(e) = self.save_snapshot(&snapshot) {
+            warn!("Unable to take the snapshot {}: {:?}", snapshot.name, e);
+            let _ = objects::release_dir(&self.root, &dir);
+            return;
+        }
+        info!(
+            "Took the snapshot {} as {}",
+            snapshot.name, snapshot.manifest.reason
+        );
+        self.snapshots.taken.push(snapshot);
+        self.prune_snapshots();
+    }
+
+    fn save_snapshot(&self, snapshot: &Snapshot) -> std::io::Result<()> {
+        let dir = self.snapshot_store().join(&snapshot.name);
+        std::fs::create_dir_all(&dir)?;
+        let json = serde_json::to_vec_pretty(&snapshot.manifest)?;
+        std::fs::write(dir.join(MANIFEST), json)
+    }
+
+    /// Removes the oldest snapshots beyond those kept
+    fn prune_snapshots(&mut self) {
+        let excess = self
+            .snapshots
+            .taken
+            .len()
+            .saturating_sub(self.snapshots.keep);
+        for snapshot in self.snapshots.taken.drain(..excess).collect::<Vec<_>>() {
+            let dir = self.snapshot_store().join(&snapshot.name);
+            if let Err(e) = objects::release_dir(&self.root, &dir) {
+                warn!("Unable to remove the snapshot {}: {:?}", snapshot.name, e);
+            }
+        }
+    }
+
+    /// The paths of the files of every snapshot the player making the call
+    /// may see
+    pub(crate) fn snapshot_paths(&self) -> Vec<PathBuf> {
+        self.snapshots
+            .taken
+            .iter()
+            .flat_map(|snapshot| {
+                let dir = Path::new(SNAPSHOTS_DIR).join(&snapshot.name);
+                snapshot
+                    .manifest
+                    .files
+                    .keys()
+                    .filter(|path| !self.game.is_hidden(path))
+                    .map(move |path| dir.join(path))
+            })
+            .collect()
+    }
+
+    /// The file of a snapshot at `path`, if it is one
+    pub(crate) fn open_snapshot(&self, path: &Path) -> Option<Result<SnapshotFile, nfsstat3>> {
+        let mut components = path.strip_prefix(SNAPSHOTS_DIR).ok()?.components();
+        let name = components.next()?.as_os_str();
+        let path = components.as_path();
+        let snapshot = self.snapshots.taken.iter().find(|s| *s.name == *name)?;
+        let kept = snapshot.manifest.files.get(path)?.clone();
+        if kept.copied {
+            let mut source = self
+                .snapshot_store()
+                .join(&snapshot.name)
+                .join(COPIES)
+                .join(path);
+            if kept.compressed {
+                source = compression::compressed(&source);
+            }
+            return Some(Ok(SnapshotFile { source, kept }));
+        }
+        let source = self.root.join(path);
+        match source.symlink_metadata() {
+            Ok(meta) if unchanged(&kept, &meta) => Some(Ok(SnapshotFile { source, kept })),
+            _ => Some(Err(nfsstat3::NFS3ERR_STALE)),
+        }
+    }
+
+    /// Copies what the snapshots still share of the world at or below
+    /// `path` to them, before a client changes, removes or renames it
+    pub(crate) fn preserve_snapshots(&mut self, path: &Path) {
+        let store = self.snapshot_store();
+        let mut changed = Vec::new();
+        for (i, snapshot) in self.snapshots.taken.iter_mut().enumerate() {
+            let shared: Vec<PathBuf> = snapshot
+                .manifest
+                .files
+                .iter()
+                .filter(|(file, kept)| !kept.copied && file.starts_with(path))
+                .map(|(file, _)| file.clone())
+                .collect();
+            for file in shared {
+                let host = self.root.join(&file);
+                let copy_to = store.join(&snapshot.name).join(COPIES).join(&file);
+                let kept = snapshot.manifest.files.get_mut(&file).unwrap();
+                let preserved = match host.symlink_metadata() {
+                    Ok(meta) if unchanged(kept, &meta) => {
+                        compression::keep_file(&self.root, &host, &copy_to, None, self.compress)
+                    }
+                    _ => Err(std::io::ErrorKind::NotFound.into()),
+                };
+                match preserved {
+                    Ok(compressed) => {
+                        debug!("Copied {:?} to the snapshot {}", file, snapshot.name);
+                        kept.copied = true;
+                        kept.compressed = compressed;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Unable to keep {:?} in the snapshot {}: {:?}",
+                            file, snapshot.name, e
+                        );
+                        snapshot.manifest.files.remove(&file);
+                    }
+                }
+                if !changed.contains(&i) {
+                    changed.push(i);
+                }
+            }
+        }
+        for i in changed {
+            if let Err(e) = self.save_snapshot(&self.snapshots.taken[i]) {
+                warn!(
+                    "Unable to save the snapshot {}: {:?}",
+                    self.snapshots.taken[i].name, e
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Sparse host files.
+//!
+//! Disk images and database files are often sparse: they have holes no
+//! block was ever written to, which read as zeros. Their attributes tell
+//! how much of them is on disk rather than how large they are, so that a
+//! client copying one can tell it is sparse and keep its copy so. Their
+//! holes are not read from the host, which would only fill the page cache
+//! with pages of zeros, but filled with zeros in place. Where the file
+//! system can not tell where the holes of a file are, it is read whole.
+
+use std::fs::File;
+use std::ops::Range;
+use std::os::fd::AsFd;
+use std::os::unix::fs::FileExt;
+
+use nix::errno::Errno;
+use nix::unistd::{lseek, Whence};
+
+/// The ranges of `start..end` of the open file `fd` which hold data, in
+/// order. Whatever lies between them is a hole.
+pub(crate) fn data_in(fd: impl AsFd, start: u64, end: u64) -> Vec<Range<u64>> {
+    let fd = fd.as_fd();
+    let mut ranges = Vec::new();
+    let mut pos = start;
+    while pos < end {
+        let data = match lseek(fd, pos as _, Whence::SeekData) {
+            Ok(data) => data as u64,
+            // nothing but a hole up to the end of the file
+            Err(Errno::ENXIO) => break,
+            Err(_) => {
+                ranges.push(pos..end);
+                break;
+            }
+        };
+        if data >= end {
+            break;
+        }
+        let hole =
+            lseek(fd, data as _, Whence::SeekHole).map_or(end, |hole| (hole as u64).min(end));
+        ranges.push(data..hole);
+        pos = hole;
+    }
+    ranges
+}
+
+/// Reads exactly `buf.len()` bytes from `offset` of `file` into `buf`,
+/// which is zeroed, leaving the zeros where the file has holes
+pub(crate) fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    for data in data_in(file, offset, offset + buf.len() as u64) {
+        let within = (data.start - offset) as usize..(data.end - offset) as usize;
+        file.read_exact_at(&mut buf[within], data.start)?;
+    }
+    Ok(())
+}
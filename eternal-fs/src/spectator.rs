@@ -0,0 +1,193 @@
+//! Spectators of a world.
+//!
+//! With `--spectate NAME=WORLD` (or `spectate` under `[server]`) the world
+//! exported as `WORLD` is exported once more as `NAME`, read-only, for a
+//! facilitator to watch a player's progress live without being able to
+//! change anything: every call which would is refused with
+//! `NFS3ERR_ROFS`. Watching is not playing. A spectator looking around does
+//! not visit stages, start the speedrun clock, collapse what is entangled
+//! or step into the labyrinth and the mirrors, and reading leaves access
+//! times alone. Where the world tells its players apart, `NAME=WORLD@PLAYER`
+//! shows the journey of `PLAYER`, named as on the leaderboard, and the
+//! shared one otherwise.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use nfsserve::nfs::*;
+use nfsserve::vfs::{NFSFileSystem, ReadDirResult, ReadDirSimpleResult, VFSCapabilities};
+
+tokio::task_local! {
+    /// Set while a spectator's call is served, to the player watched
+    static WATCHING: Option<String>;
+}
+
+/// Whether the call being served is a spectator's
+pub(crate) fn spectating() -> bool {
+    WATCHING.try_with(|_| ()).is_ok()
+}
+
+/// The player whose journey the spectator making the call watches, if
+/// any
+pub(crate) fn watched_player() -> Option<String> {
+    WATCHING.try_with(|player| player.clone()).ok().flatten()
+}
+
+/// A world served read-only to a spectator
+pub struct Spectator {
+    fs: Arc<dyn NFSFileSystem + Send + Sync>,
+    player: Option<String>,
+}
+
+impl Spectator {
+    /// Watches `fs`, and the journey of `player` in it if given
+    pub fn new(fs: Arc<dyn NFSFileSystem + Send + Sync>, player: Option<String>) -> Spectator {
+        Spectator { fs, player }
+    }
+
+    /// Serves `call` as a call of this spectator
+    async fn watch<F: Future>(&self, call: F) -> F::Output {
+        WATCHING.scope(self.player.clone(), call).await
+    }
+}
+
+#[async_trait]
+impl NFSFileSystem for Spectator {
+    fn root_dir(&self) -> fileid3 {
+        self.fs.root_dir()
+    }
+
+    fn capabilities(&self) -> VFSCapabilities {
+        VFSCapabilities::ReadOnly
+    }
+
+    async fn lookup(&self, dirid: fileid3, filename: &filename3) -> Result<fileid3, nfsstat3> {
+        self.watch(self.fs.lookup(dirid, filename)).await
+    }
+
+    async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
+        self.watch(self.fs.getattr(id)).await
+    }
+
+    async fn wcc_attr(&self, id: fileid3) -> Result<wcc_attr, nfsstat3> {
+        self.watch(self.fs.wcc_attr(id)).await
+    }
+
+    async fn setattr(&self, _id: fileid3, _setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn read(&self, id: fileid3, offset: u64, count: u32) -> Result<(Bytes, bool), nfsstat3> {
+        self.watch(self.fs.read(id, offset, count)).await
+    }
+
+    async fn write(&self, _id: fileid3, _offset: u64, _data: &[u8]) -> Result<fattr3, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn create(
+        &self,
+        _dirid: fileid3,
+        _filename: &filename3,
+        _attr: sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn create_exclusive(
+        &self,
+        _dirid: fileid3,
+        _filename: &filename3,
+    ) -> Result<fileid3, nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn mkdir(
+        &self,
+        _dirid: fileid3,
+        _dirname: &filename3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn remove(&self, _dirid: fileid3, _filename: &filename3) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn rename(
+        &self,
+        _from_dirid: fileid3,
+        _from_filename: &filename3,
+        _to_dirid: fileid3,
+        _to_filename: &filename3,
+    ) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn link(
+        &self,
+        _fileid: fileid3,
+        _linkdirid: fileid3,
+        _linkname: &filename3,
+    ) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn readdir(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<ReadDirResult, nfsstat3> {
+        self.watch(self.fs.readdir(dirid, start_after, max_entries))
+            .await
+    }
+
+    async fn readdir_simple_paged(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        count: usize,
+    ) -> Result<ReadDirSimpleResult, nfsstat3> {
+        self.watch(self.fs.readdir_simple_paged(dirid, start_after, count))
+            .await
+    }
+
+    async fn symlink(
+        &self,
+        _dirid: fileid3,
+        _linkname: &filename3,
+        _symlink: &nfspath3,
+        _attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn mknod(
+        &self,
+        _dirid: fileid3,
+        _filename: &filename3,
+        _ftype: ftype3,
+        _spec: specdata3,
+        _attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_ROFS)
+    }
+
+    async fn readlink(&self, id: fileid3) -> Result<nfspath3, nfsstat3> {
+        self.watch(self.fs.readlink(id)).await
+    }
+
+    async fn fsstat(&self, fileid: fileid3) -> Result<fsstat3, nfsstat3> {
+        self.watch(self.fs.fsstat(fileid)).await
+    }
+
+    fn serverid(&self) -> cookieverf3 {
+        self.fs.serverid()
+    }
+
+    // the world is shut down by the export it is played in
+}
@@ -0,0 +1,174 @@
+//! Speedruns, timed stage by stage.
+//!
+//! With `--speedrun`, or `speedrun = true` under `[game]`, every journey is
+//! timed as a run. Its clock starts with the first call of the player, and
+//! every stage they complete is a split, noted with the time of the run.
+//! `.status/splits` shows the split table of the player making the call,
+//! and reaching enlightenment stops the clock at the final time. The run
+//! is kept with the journey, and its clock stops with the server and only
+//! runs again with the next call of the player, so an interrupted run
+//! resumes with the time it was stopped at.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::philosophy::{Philosophy, ENLIGHTENMENT};
+use crate::players::PlayerState;
+
+/// A speedrun of a journey
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Speedrun {
+    /// The time of the run before its clock was last resumed
+    elapsed: Duration,
+    /// When the clock was last resumed, None while it is stopped
+    #[serde(skip)]
+    resumed: Option<SystemTime>,
+    /// The stages completed, in order
+    splits: Vec<Split>,
+    /// The final time, once the run is over
+    finished: Option<Duration>,
+}
+
+/// A stage completed in a speedrun
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Split {
+    stage: String,
+    /// The time of the run it was completed at
+    at: Duration,
+}
+
+/// `time` as a speedrun timer shows it
+pub(crate) fn format_run_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        time.subsec_millis()
+    )
+}
+
+impl Speedrun {
+    fn new() -> Self {
+        Speedrun {
+            elapsed: Duration::ZERO,
+            resumed: None,
+            splits: Vec::new(),
+            finished: None,
+        }
+    }
+
+    /// The time of the run so far
+    fn time(&self) -> Duration {
+        let running = self
+            .resumed
+            .and_then(|resumed| resumed.elapsed().ok())
+            .unwrap_or_default();
+        self.finished.unwrap_or(self.elapsed + running)
+    }
+
+    /// Starts the clock again, unless it runs
+    fn resume(&mut self) {
+        self.resumed.get_or_insert_with(SystemTime::now);
+    }
+
+    /// The run as it is to be kept, stopped at its time so far
+    pub(crate) fn paused(&self) -> Self {
+        Speedrun {
+            elapsed: self.time(),
+            resumed: None,
+            ..self.clone()
+        }
+    }
+}
+
+impl PlayerState {
+    /// Notes the completion of the stage `location` in the speedrun of the
+    /// player, and returns the final time if it ended the run
+    pub(crate) fn split(&mut self, location: &str) -> Option<Duration> {
+        let run = self
+            .speedrun
+            .as_mut()
+            .filter(|run| run.finished.is_none())?;
+        let at = run.time();
+        // the final answer may be given anywhere, it completes the journey
+        let over = self.completed_questions.contains(ENLIGHTENMENT);
+        let stage = if over { ENLIGHTENMENT } else { location };
+        run.splits.push(Split {
+            stage: stage.to_string(),
+            at,
+        });
+        if !over {
+            return None;
+        }
+        run.finished = Some(at);
+        self.philosophical_state.note_event(
+            SystemTime::now(),
+            format!("Finished the speedrun in {}", format_run_time(at)),
+        );
+        Some(at)
+    }
+
+    /// The split table of the speedrun of the player
+    fn splits(&self) -> String {
+        let Some(run) = &self.speedrun else {
+            return "The run starts with your first step.\n".to_string();
+        };
+        let mut out = format!("{:<4}{:<16}{:>14}{:>14}\n", "#", "Stage", "Split", "Time");
+        let mut previous = Duration::ZERO;
+        for (i, split) in run.splits.iter().enumerate() {
+            out.push_str(&format!(
+                "{:<4}{:<16}{:>14}{:>14}\n",
+                i + 1,
+                split.stage,
+                format_run_time(split.at.saturating_sub(previous)),
+                format_run_time(split.at)
+            ));
+            previous = split.at;
+        }
+        out.push_str(&match run.finished {
+            Some(time) => format!("\nFinal time: {}\n", format_run_time(time)),
+            None => format!("\nRunning: {}\n", format_run_time(run.time())),
+        });
+        out
+    }
+}
+
+impl Philosophy {
+    /// Whether every journey is timed as a speedrun
+    fn speedrunning(&self) -> bool {
+        self.config.speedrun.unwrap_or(false)
+    }
+
+    /// Starts the clock of the speedrun of the player making the call, or
+    /// resumes it after the server was restarted
+    pub(crate) fn start_speedrun(&mut self) {
+        if !self.speedrunning() {
+            return;
+        }
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        player
+            .speedrun
+            .get_or_insert_with(|| {
+                info!("The speedrun starts");
+                Speedrun::new()
+            })
+            .resume();
+    }
+
+    /// What `.status/splits` shows the player making the call
+    pub(crate) fn speedrun_splits(&self) -> String {
+        if !self.speedrunning() {
+            return "No run is timed. Start the server with --speedrun to time one.\n".to_string();
+        }
+        let newcomer = PlayerState::default();
+        self.players
+            .get(&self.player_identity.current())
+            .unwrap_or(&newcomer)
+            .splits()
+    }
+}
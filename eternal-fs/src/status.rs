@@ -0,0 +1,270 @@
+//! The `.status` directory, in the manner of `/proc`.
+//!
+//! Its files tell operators how the server is doing: how many objects it
+//! knows and how often it finds them without asking the host, who has
+//! been calling, how long it has been up, where the game stands and what
+//! version is running. They are made up from memory whenever they are
+//! read, through the [virtual files](crate::virtual_files), and never
+//! touch the host.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use nfsserve::auth::{caller, client_ip};
+use serde_json::{json, Value};
+
+use crate::philosophy::Philosophy;
+use crate::FSMap;
+
+/// The status directory at the root of the world
+pub(crate) const STATUS_DIR: &str = ".status";
+
+/// The files of the status directory
+const STATUS_FILES: &[&str] = &["cache", "clients", "uptime", "game", "splits", "version"];
+
+/// Timeline events of all players the dashboard shows, the most recent
+/// ones
+const DASHBOARD_EVENTS: usize = 20;
+
+/// How often the known objects were enough to answer a call
+#[derive(Debug, Default)]
+pub(crate) struct CacheStats {
+    /// Lookups answered from the known entries of a directory
+    pub(crate) lookup_hits: u64,
+    /// Lookups which had to ask the host
+    pub(crate) lookup_misses: u64,
+    /// Entries found unchanged on the host when refreshed
+    pub(crate) refresh_hits: u64,
+    /// Entries found changed or gone on the host when refreshed
+    pub(crate) refresh_misses: u64,
+    /// Directories listed anew from the host
+    pub(crate) relists: u64,
+}
+
+/// What is known about a client which called
+#[derive(Debug, Clone)]
+pub(crate) struct ClientSeen {
+    first_seen: SystemTime,
+    last_seen: SystemTime,
+    /// The uid of its last call, if it sent credentials
+    uid: Option<u32>,
+    calls: u64,
+}
+
+/// `hits` of `hits` and `misses` in percent
+fn hit_rate(hits: u64, misses: u64) -> String {
+    match hits + misses {
+        0 => "-".to_string(),
+        total => format!("{:.1}%", hits as f64 * 100.0 / total as f64),
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+impl FSMap {
+    /// The paths of the status files
+    pub(crate) fn status_paths(&self) -> Vec<PathBuf> {
+        STATUS_FILES
+            .iter()
+            .map(|name| Path::new(STATUS_DIR).join(name))
+            .collect()
+    }
+
+    /// Notes a call of the client being served
+    pub(crate) fn note_client(&mut self) {
+        let Some(ip) = client_ip() else {
+            return;
+        };
+        let now = SystemTime::now();
+        let seen = self.clients.entry(ip).or_insert(ClientSeen {
+            first_seen: now,
+            last_seen: now,
+            uid: None,
+            calls: 0,
+        });
+        seen.last_seen = now;
+        seen.uid = caller().map(|caller| caller.uid);
+        seen.calls += 1;
+    }
+
+    /// The contents of the status file at `path`, if it is one
+    pub(crate) fn status_contents(&self, path: &Path) -> Option<String> {
+        if path.parent() != Some(Path::new(STATUS_DIR)) {
+            return None;
+        }
+        let contents = match path.file_name()?.to_str()? {
+            "cache" => self.cache_status(),
+            "clients" => clients_status(&self.clients),
+            "uptime" => {
+                let up = self.started.elapsed().unwrap_or_default().as_secs();
+                format!(
+                    "started: {}\nuptime: {}d {:02}:{:02}:{:02}\nseconds: {}\n",
+                    format_time(self.started),
+                    up / 86400,
+                    up / 3600 % 24,
+                    up / 60 % 60,
+                    up % 60,
+                    up
+                )
+            }
+            "game" => self.game.status_report(),
+            "splits" => self.game.splits_report(),
+            "version" => format!("{} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            _ => return None,
+        };
+        Some(contents)
+    }
+
+    fn cache_status(&self) -> String {
+        let stats = &self.cache_stats;
+        let read_ahead_hits = self.read_ahead.hits.load(Ordering::Relaxed);
+        let read_ahead_misses = self.read_ahead.misses.load(Ordering::Relaxed);
+        format!(
+            "entries: {}\n\
+             paths: {}\n\
+             tombstones: {}\n\
+             symbols: {} in use, {} interned\n\
+             lookups: {} hits, {} misses, {} hit rate\n\
+             refreshes: {} unchanged, {} changed, {} hit rate\n\
+             relists: {}\n\
+             reads: {} read ahead, {} from the host, {} hit rate\n",
+            self.id_to_path.len(),
+            self.path_to_id.len(),
+            self.tombstones.len(),
+            self.intern.held(),
+            self.intern.len(),
+            stats.lookup_hits,
+            stats.lookup_misses,
+            hit_rate(stats.lookup_hits, stats.lookup_misses),
+            stats.refresh_hits,
+            stats.refresh_misses,
+            hit_rate(stats.refresh_hits, stats.refresh_misses),
+            stats.relists,
+            read_ahead_hits,
+            read_ahead_misses,
+            hit_rate(read_ahead_hits, read_ahead_misses)
+        )
+    }
+}
+
+fn clients_status(clients: &HashMap<IpAddr, ClientSeen>) -> String {
+    let mut clients: Vec<(&IpAddr, &ClientSeen)> = clients.iter().collect();
+    clients.sort_by_key(|(_, seen)| std::cmp::Reverse(seen.last_seen));
+    if clients.is_empty() {
+        return "No client has called yet.\n".to_string();
+    }
+    clients
+        .iter()
+        .map(|(ip, seen)| {
+            let uid = seen
+                .uid
+                .map_or_else(|| "-".to_string(), |uid| uid.to_string());
+            format!(
+                "{} uid {} calls {} first {} last {}\n",
+                ip,
+                uid,
+                seen.calls,
+                format_time(seen.first_seen),
+                format_time(seen.last_seen)
+            )
+        })
+        .collect()
+}
+
+impl Philosophy {
+    /// Where every player stands and the puzzles they solved
+    pub(crate) fn game_status(&self) -> String {
+        let mut ids: Vec<&String> = self.players.keys().collect();
+        ids.sort();
+        let mut puzzles: Vec<&String> = self
+            .players
+            .values()
+            .flat_map(|player| &player.philosophical_state.solved_puzzles)
+            .collect();
+        puzzles.sort();
+        puzzles.dedup();
+        let mut contents = format!(
+            "stages: {}\nplayers: {}\npuzzles solved: {}\n",
+            self.world.total_questions(),
+            ids.len(),
+            if puzzles.is_empty() {
+                "-".to_string()
+            } else {
+                puzzles
+                    .iter()
+                    .map(|puzzle| puzzle.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        );
+        for id in ids {
+            let player = &self.players[id];
+            contents.push_str(&format!(
+                "player {} stage {:?} answered {} puzzles {}\n",
+                id,
+                player.current_stage,
+                player.completed_questions.len(),
+                player.philosophical_state.solved_puzzles.len()
+            ));
+        }
+        contents
+    }
+
+    /// Where every player stands on the map, with their latest hint, and
+    /// the recent events of all their timelines, for the web dashboard
+    pub(crate) fn dashboard_status(&self) -> Value {
+        let mut ids: Vec<&String> = self.players.keys().collect();
+        ids.sort();
+        let players: Vec<Value> = ids
+            .iter()
+            .map(|id| {
+                let player = &self.players[*id];
+                let rooms: Vec<Value> = player
+                    .map_rooms(&self.world)
+                    .into_iter()
+                    .map(|(name, state)| json!({ "name": name, "state": state }))
+                    .collect();
+                json!({
+                    "player": id,
+                    "stage": format!("{:?}", player.current_stage),
+                    "answered": player.completed_questions.len(),
+                    "rooms": rooms,
+                    "hint": player.latest_hint,
+                    "mood": player.philosophical_state.emotional_state,
+                })
+            })
+            .collect();
+        let mut events: Vec<(&SystemTime, &String, &String)> = self
+            .players
+            .iter()
+            .flat_map(|(id, player)| {
+                player
+                    .philosophical_state
+                    .timeline_events
+                    .iter()
+                    .map(move |(at, event)| (at, id, event))
+            })
+            .collect();
+        events.sort();
+        let events: Vec<Value> = events
+            .iter()
+            .rev()
+            .take(DASHBOARD_EVENTS)
+            .rev()
+            .map(|(at, id, event)| json!({ "at": format_time(**at), "player": id, "event": event }))
+            .collect();
+        json!({
+            "stages": self.world.total_questions(),
+            "players": players,
+            "events": events,
+        })
+    }
+}
@@ -0,0 +1,396 @@
+//! Where the saved game is kept.
+//!
+//! A [`StateStore`] keeps the progress of every player between runs, see
+//! [`StoreConfig`] for choosing one. By default the game is kept in
+//! `.eternal/game.toml`, which suits a single server on a laptop. A JSON
+//! file is kept the same way. Built with the `sled` or `sqlite` feature,
+//! the game can be kept in a sled database or an SQLite file instead, with
+//! a record for every player, changed in one transaction. With a key,
+//! what is saved is encrypted, see `encryption.rs`.
+
+use std::fmt::Debug;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Deserialize;
+
+use crate::encryption::{self, StateKey};
+pub use crate::savegame::SavedGame;
+
+/// Keeps the saved game of a world between runs
+pub trait StateStore: Debug + Send + Sync {
+    /// The game saved last, if one was
+    fn load(&self) -> io::Result<Option<SavedGame>>;
+
+    /// Reads the saved game, or an empty one if none was saved, lets
+    /// `change` change it and saves it, with no other change made to it
+    /// in between. `change` is called once.
+    fn transact(&self, change: &mut dyn FnMut(&mut SavedGame)) -> io::Result<()>;
+
+    /// Saves `game` in place of the game saved before
+    fn save(&self, game: SavedGame) -> io::Result<()> {
+        let mut game = Some(game);
+        self.transact(&mut |saved| {
+            if let Some(game) = game.take() {
+                *saved = game;
+            }
+        })
+    }
+}
+
+/// How the saved game is kept, under `[game.store]`:
+///
+/// ```text
+/// [game.store]
+/// kind = "sqlite"
+/// path = "/var/lib/eternal-fs/game.sqlite"
+/// key_env = "ETERNAL_FS_KEY"
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// `toml`, `json`, `sled` or `sqlite`
+    pub kind: Option<String>,
+    /// Where the game is kept, relative to the state directory of the
+    /// world. `game.toml`, `game.json`, `game.sled` or `game.sqlite` unless
+    /// given.
+    pub path: Option<String>,
+    /// Environment variable holding the key the saved game is encrypted
+    /// with, see `encryption.rs`. `ETERNAL_FS_KEY` unless given, where an
+    /// unset variable keeps the game unencrypted.
+    pub key_env: Option<String>,
+}
+
+impl StoreConfig {
+    /// The key what is saved of the players is encrypted with, if there
+    /// is one
+    pub fn key(&self) -> Result<Option<StateKey>, String> {
+        StateKey::from_env(self.key_env.as_deref())
+    }
+
+    /// Opens the configured store of the world whose state directory is
+    /// `statedir`
+    pub fn build(&self, statedir: &Path) -> Result<Box<dyn StateStore>, String> {
+        let kind = self.kind.as_deref().unwrap_or("toml");
+        let key = self.key()?;
+        let path = statedir.join(
+            self.path
+                .clone()
+                .unwrap_or_else(|| format!("game.{}", kind)),
+        );
+        match kind {
+            "toml" => Ok(Box::new(FileStore::new(path, FileFormat::Toml, key))),
+            "json" => Ok(Box::new(FileStore::new(path, FileFormat::Json, key))),
+            #[cfg(feature = "sled")]
+            "sled" => Ok(Box::new(
+                sled_store::SledStore::open(&path, key)
+                    .map_err(|e| format!("unable to open {:?}: {}", path, e))?,
+            )),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Box::new(
+                sqlite::SqliteStore::open(&path, key)
+                    .map_err(|e| format!("unable to open {:?}: {}", path, e))?,
+            )),
+            #[cfg(not(feature = "sled"))]
+            "sled" => {
+                Err("the sled store needs eternal-fs to be built with the sled feature".into())
+            }
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => {
+                Err("the sqlite store needs eternal-fs to be built with the sqlite feature".into())
+            }
+            kind => Err(format!("unknown store {:?}", kind)),
+        }
+    }
+}
+
+/// The formats a [`FileStore`] writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Toml,
+    Json,
+}
+
+/// Keeps the saved game in a single file, replaced whole on every save
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    format: FileFormat,
+    /// What the file is encrypted with, if it is
+    key: Option<StateKey>,
+    /// Held from reading the file to replacing it
+    lock: Mutex<()>,
+}
+
+impl FileStore {
+    pub fn new(path: PathBuf, format: FileFormat, key: Option<StateKey>) -> FileStore {
+        FileStore {
+            path,
+            format,
+            key,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read(&self) -> io::Result<Option<SavedGame>> {
+        let contents = match std::fs::read(&self.path) {
+            Ok(contents) => encryption::open(self.key.as_ref(), &contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let contents = String::from_utf8(contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let saved = match self.format {
+            FileFormat::Toml => toml::from_str(&contents).map_err(io::Error::other)?,
+            FileFormat::Json => serde_json::from_str(&contents).map_err(io::Error::other)?,
+        };
+        Ok(Some(saved))
+    }
+
+    fn write(&self, game: &SavedGame) -> io::Result<()> {
+        let contents = match self.format {
+            FileFormat::Toml => toml::to_string(game).map_err(io::Error::other)?,
+            FileFormat::Json => serde_json::to_string_pretty(game).map_err(io::Error::other)?,
+        };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // replace the previous save in one step so a crash never leaves
+        // half of it behind
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        std::fs::write(
+            &partial,
+            encryption::seal(self.key.as_ref(), contents.as_bytes()),
+        )?;
+        std::fs::rename(&partial, &self.path)
+    }
+}
+
+impl StateStore for FileStore {
+    fn load(&self) -> io::Result<Option<SavedGame>> {
+        let _held = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.read()
+    }
+
+    fn transact(&self, change: &mut dyn FnMut(&mut SavedGame)) -> io::Result<()> {
+        let _held = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut game = self.read()?.unwrap_or_default();
+        change(&mut game);
+        self.write(&game)
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use sled_store::SledStore;
+
+#[cfg(feature = "sled")]
+mod sled_store {
+    use std::io;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use sled::transaction::{ConflictableTransactionError, TransactionError};
+    use sled::Transactional;
+
+    use super::StateStore;
+    use crate::encryption::StateKey;
+    use crate::savegame::{SavedGame, SavedRecords};
+
+    /// Keeps the saved game in a sled database, the journey of every
+    /// player and every entry of the game state a record of its own
+    #[derive(Debug)]
+    pub struct SledStore {
+        db: sled::Db,
+        journeys: sled::Tree,
+        state: sled::Tree,
+        /// What the records are encrypted with, if they are
+        key: Option<StateKey>,
+        /// Held from reading the records to writing them
+        lock: Mutex<()>,
+    }
+
+    impl SledStore {
+        pub fn open(path: &Path, key: Option<StateKey>) -> sled::Result<SledStore> {
+            let db = sled::open(path)?;
+            Ok(SledStore {
+                journeys: db.open_tree("journeys")?,
+                state: db.open_tree("state")?,
+                db,
+                key,
+                lock: Mutex::new(()),
+            })
+        }
+
+        fn read(&self) -> io::Result<SavedRecords> {
+            let text = |bytes: sled::IVec| String::from_utf8_lossy(&bytes).into_owned();
+            let mut records = SavedRecords::default();
+            for entry in self.journeys.iter() {
+                let (player, journey) = entry.map_err(io::Error::other)?;
+                records.journeys.insert(text(player), text(journey));
+            }
+            for entry in self.state.iter() {
+                let (key, value) = entry.map_err(io::Error::other)?;
+                records.state.insert(text(key), text(value));
+            }
+            records.open(self.key.as_ref())
+        }
+    }
+
+    impl StateStore for SledStore {
+        fn load(&self) -> io::Result<Option<SavedGame>> {
+            let _held = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            let records = self.read()?;
+            if records.journeys.is_empty() {
+                return Ok(None);
+            }
+            SavedGame::from_records(records).map(Some)
+        }
+
+        fn transact(&self, change: &mut dyn FnMut(&mut SavedGame)) -> io::Result<()> {
+            let _held = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            let before = self.read()?;
+            let mut game = match before.journeys.is_empty() {
+                true => SavedGame::default(),
+                false => SavedGame::from_records(before.clone())?,
+            };
+            change(&mut game);
+            let after = game.records()?.seal(self.key.as_ref());
+            (&self.journeys, &self.state)
+                .transaction(|(journeys, state)| {
+                    for player in before.journeys.keys() {
+                        if !after.journeys.contains_key(player) {
+                            journeys.remove(player.as_bytes())?;
+                        }
+                    }
+                    for key in before.state.keys() {
+                        if !after.state.contains_key(key) {
+                            state.remove(key.as_bytes())?;
+                        }
+                    }
+                    for (player, journey) in after.journeys.iter() {
+                        journeys.insert(player.as_bytes(), journey.as_bytes())?;
+                    }
+                    for (key, value) in after.state.iter() {
+                        state.insert(key.as_bytes(), value.as_bytes())?;
+                    }
+                    Ok::<_, ConflictableTransactionError<()>>(())
+                })
+                .map_err(|e: TransactionError<()>| io::Error::other(format!("{:?}", e)))?;
+            self.db.flush().map_err(io::Error::other)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::io;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use rusqlite::{Connection, Transaction, TransactionBehavior};
+
+    use super::StateStore;
+    use crate::encryption::StateKey;
+    use crate::savegame::{SavedGame, SavedRecords};
+
+    const SCHEMA: &str = "\
+        CREATE TABLE IF NOT EXISTS journeys (player TEXT PRIMARY KEY, journey TEXT NOT NULL);
+        CREATE TABLE IF NOT EXISTS state (key TEXT PRIMARY KEY, value TEXT NOT NULL);";
+
+    /// Keeps the saved game in an SQLite file, the journey of every player
+    /// and every entry of the game state a row of its own
+    #[derive(Debug)]
+    pub struct SqliteStore {
+        conn: Mutex<Connection>,
+        /// What the rows are encrypted with, if they are
+        key: Option<StateKey>,
+    }
+
+    impl SqliteStore {
+        pub fn open(path: &Path, key: Option<StateKey>) -> rusqlite::Result<SqliteStore> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).ok();
+            }
+            let conn = Connection::open(path)?;
+            conn.execute_batch(SCHEMA)?;
+            Ok(SqliteStore {
+                conn: Mutex::new(conn),
+                key,
+            })
+        }
+    }
+
+    /// The rows of the saved game read within `tx`
+    fn read(tx: &Transaction) -> rusqlite::Result<SavedRecords> {
+        let mut records = SavedRecords::default();
+        let mut journeys = tx.prepare("SELECT player, journey FROM journeys")?;
+        for row in journeys.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            let (player, journey) = row?;
+            records.journeys.insert(player, journey);
+        }
+        let mut state = tx.prepare("SELECT key, value FROM state")?;
+        for row in state.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            let (key, value) = row?;
+            records.state.insert(key, value);
+        }
+        Ok(records)
+    }
+
+    /// Replaces the rows of the saved game with `records` within `tx`
+    fn write(tx: &Transaction, records: &SavedRecords) -> rusqlite::Result<()> {
+        tx.execute("DELETE FROM journeys", [])?;
+        tx.execute("DELETE FROM state", [])?;
+        for (player, journey) in records.journeys.iter() {
+            tx.execute(
+                "INSERT INTO journeys (player, journey) VALUES (?1, ?2)",
+                (player, journey),
+            )?;
+        }
+        for (key, value) in records.state.iter() {
+            tx.execute(
+                "INSERT INTO state (key, value) VALUES (?1, ?2)",
+                (key, value),
+            )?;
+        }
+        Ok(())
+    }
+
+    impl StateStore for SqliteStore {
+        fn load(&self) -> io::Result<Option<SavedGame>> {
+            let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+            let tx = conn.transaction().map_err(io::Error::other)?;
+            let records = read(&tx)
+                .map_err(io::Error::other)?
+                .open(self.key.as_ref())?;
+            if records.journeys.is_empty() {
+                return Ok(None);
+            }
+            SavedGame::from_records(records).map(Some)
+        }
+
+        fn transact(&self, change: &mut dyn FnMut(&mut SavedGame)) -> io::Result<()> {
+            let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+            // taking the write lock from the start keeps other processes
+            // from changing the game between reading and writing it
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(io::Error::other)?;
+            let records = read(&tx)
+                .map_err(io::Error::other)?
+                .open(self.key.as_ref())?;
+            let mut game = match records.journeys.is_empty() {
+                true => SavedGame::default(),
+                false => SavedGame::from_records(records)?,
+            };
+            change(&mut game);
+            write(&tx, &game.records()?.seal(self.key.as_ref())).map_err(io::Error::other)?;
+            tx.commit().map_err(io::Error::other)
+        }
+    }
+}
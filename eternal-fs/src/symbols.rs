@@ -0,0 +1,121 @@
+//! The names paths are made of.
+//!
+//! Every path the file system knows is a list of symbols, one for the
+//! name of each component, so that a name many paths share is kept once.
+//! A table of names only grows, though, and on an export where files come
+//! and go it would keep every name ever looked up. The names are counted
+//! as the paths the file system knows, and those of deleted fileids, hold
+//! them, and once most of the table is names none holds any more it is
+//! compacted: the names still in use are interned afresh and every path
+//! is renumbered. How many there are shows in `.status/cache`.
+//!
+//! The table is compacted as a client call starts, when no path is held
+//! outside the maps, and never while a metadata store is set, as the
+//! paths written to it can not be renumbered.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+
+use intaglio::osstr::SymbolTable;
+use intaglio::{Symbol, SymbolOverflowError};
+
+/// Tables of fewer names than this are left as they are
+const MIN_COMPACTED: usize = 4096;
+
+/// The names paths are made of, and how many paths hold each
+#[derive(Debug, Default)]
+pub(crate) struct Symbols {
+    table: SymbolTable,
+    /// How many paths hold each name, by symbol
+    refs: Vec<u32>,
+    /// Names some path holds
+    held: usize,
+}
+
+/// Paths being renumbered as the table is compacted
+pub(crate) struct Renumbering {
+    old: SymbolTable,
+    renumbered: HashMap<Symbol, Symbol>,
+}
+
+impl Symbols {
+    pub(crate) fn intern(&mut self, name: OsString) -> Result<Symbol, SymbolOverflowError> {
+        self.table.intern(name)
+    }
+
+    pub(crate) fn get(&self, sym: Symbol) -> Option<&OsStr> {
+        self.table.get(sym)
+    }
+
+    pub(crate) fn check_interned(&self, name: &OsStr) -> Option<Symbol> {
+        self.table.check_interned(name)
+    }
+
+    /// Counts the names of `path` as held by one more path
+    pub(crate) fn retain(&mut self, path: &[Symbol]) {
+        for sym in path {
+            let index = sym.id() as usize;
+            if index >= self.refs.len() {
+                self.refs.resize(index + 1, 0);
+            }
+            if self.refs[index] == 0 {
+                self.held += 1;
+            }
+            self.refs[index] += 1;
+        }
+    }
+
+    /// Counts the names of `path` as held by one path less
+    pub(crate) fn release(&mut self, path: &[Symbol]) {
+        for sym in path {
+            let Some(refs) = self.refs.get_mut(sym.id() as usize) else {
+                continue;
+            };
+            if *refs == 1 {
+                self.held -= 1;
+            }
+            *refs = refs.saturating_sub(1);
+        }
+    }
+
+    /// Names in the table
+    pub(crate) fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Names some path holds
+    pub(crate) fn held(&self) -> usize {
+        self.held
+    }
+
+    /// Whether most of the table is names no path holds any more
+    pub(crate) fn wasteful(&self) -> bool {
+        self.table.len() >= MIN_COMPACTED && self.held * 2 < self.table.len()
+    }
+
+    /// Starts over with an empty table, into which the paths still known
+    /// are then renumbered, and retained again as they are
+    pub(crate) fn compact(&mut self) -> Renumbering {
+        self.refs.clear();
+        self.held = 0;
+        Renumbering {
+            old: std::mem::take(&mut self.table),
+            renumbered: HashMap::new(),
+        }
+    }
+
+    /// Renumbers `path` into the compacted table
+    pub(crate) fn renumber(&mut self, renumbering: &mut Renumbering, path: &mut [Symbol]) {
+        for sym in path.iter_mut() {
+            *sym = match renumbering.renumbered.get(sym) {
+                Some(new) => *new,
+                None => {
+                    let name = renumbering.old.get(*sym).unwrap_or_default();
+                    let new = self.table.intern(name.to_os_string()).unwrap();
+                    renumbering.renumbered.insert(*sym, new);
+                    new
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! The timeline of a journey, and rewinding it.
+//!
+//! Before an answer is accepted the journey is kept as it was, so that a
+//! player writing `rewind` to `timeline.txt` can undo the last accepted
+//! answer. Every rewind is a temporal anomaly, noted in the timeline.
+
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use tracing::info;
+
+use crate::philosophy::{ArchivedAnswer, GameStage, Philosophy};
+use crate::players::PlayerState;
+
+/// The file the timeline is shown in, and rewound through
+pub(crate) const TIMELINE_FILE: &str = "timeline.txt";
+
+/// Events timeline.txt shows, the most recent ones
+const RECENT_EVENTS: usize = 10;
+
+/// A journey as it was before an answer was accepted
+#[derive(Debug, Clone)]
+pub(crate) struct JourneySnapshot {
+    stage: GameStage,
+    completed_questions: HashSet<String>,
+    answer_archive: HashMap<String, Vec<ArchivedAnswer>>,
+}
+
+impl PlayerState {
+    /// The journey as it is now, to rewind to later
+    pub(crate) fn snapshot(&self) -> JourneySnapshot {
+        JourneySnapshot {
+            stage: self.current_stage.clone(),
+            completed_questions: self.completed_questions.clone(),
+            answer_archive: self.answer_archive.clone(),
+        }
+    }
+
+    /// Undoes the last accepted answer. Returns what was undone, if there
+    /// was anything to.
+    fn rewind(&mut self) -> Option<String> {
+        let snapshot = self.philosophical_state.snapshots.pop()?;
+        let mut undone: Vec<&String> = self
+            .completed_questions
+            .difference(&snapshot.completed_questions)
+            .collect();
+        undone.sort();
+        let event = format!(
+            "Temporal anomaly: the answer to {} was undone",
+            undone
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>()
+                .join(" and ")
+        );
+        self.current_stage = snapshot.stage;
+        self.completed_questions = snapshot.completed_questions;
+        self.answer_archive = snapshot.answer_archive;
+        self.philosophical_state
+            .note_event(SystemTime::now(), event.clone());
+        Some(event)
+    }
+
+    /// What timeline.txt tells the player
+    pub(crate) fn timeline(&self) -> String {
+        let events = &self.philosophical_state.timeline_events;
+        let anomalies = events
+            .iter()
+            .filter(|(_, event)| event.starts_with("Temporal anomaly"))
+            .count();
+        let mut recent = String::new();
+        for (at, event) in events.iter().rev().take(RECENT_EVENTS).rev() {
+            let at = self.describe_time(*at);
+            recent.push_str(&format!("- {}: {}\n", at, event));
+        }
+        if recent.is_empty() {
+            recent.push_str("- Timeline initialized\n");
+        }
+        format!(
+            "Timeline Tracker\n\
+             ===============\n\
+             Past, present, and future converge in this space.\n\
+             \n\
+             Current Timeline: {}\n\
+             Temporal Stability: {}%\n\
+             \n\
+             Recent Events:\n\
+             {}\n\
+             Write \"rewind\" here to undo your last accepted answer.\n",
+            timeline_name(anomalies),
+            100usize.saturating_sub(anomalies * 10),
+            recent
+        )
+    }
+}
+
+/// Every anomaly branches off another timeline
+fn timeline_name(anomalies: usize) -> String {
+    const NAMES: &[&str] = &["Alpha", "Beta", "Gamma", "Delta", "Epsilon", "Zeta"];
+    match NAMES.get(anomalies) {
+        Some(name) => name.to_string(),
+        None => format!("Omega-{}", anomalies + 1 - NAMES.len()),
+    }
+}
+
+impl Philosophy {
+    /// Rewinds the journey of the player making the call by one accepted
+    /// answer and brings timeline.txt up to date
+    pub(crate) fn process_rewind(&mut self) {
+        let id = self.player_identity.current();
+        let player = self.players.entry(id).or_default();
+        let rewound = player.rewind();
+        match &rewound {
+            Some(event) => info!("{}", event),
+            None => info!("Nothing to rewind"),
+        }
+        let timeline = player.timeline();
+        let _ = std::fs::write(self.root.join(TIMELINE_FILE), timeline);
+        if let Some(event) = rewound {
+            let player = self.player_identity.current();
+            self.commit_journey(&player, &format!("Rewind\n\n{}.\n", event), &[]);
+        }
+    }
+}
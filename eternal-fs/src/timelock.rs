@@ -0,0 +1,121 @@
+//! Files sealed until a time to come.
+//!
+//! Writing to a time-locked file seals it: until its time passes, reading
+//! it shows only when it opens again, not what was written. The letter in
+//! `time/letter_to_future_self.txt` opens a day after it is written; which
+//! files are time-locked, and for how long, can be configured. When every
+//! file opens is kept in the game state, so a sealed file stays sealed
+//! however often the world is restarted. Once open, writing to the file
+//! again seals it anew.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+use tracing::{info, warn};
+
+use crate::philosophy::Philosophy;
+
+/// The letter the player writes to their future self
+pub(crate) const LETTER_FILE: &str = "time/letter_to_future_self.txt";
+
+/// Seconds the letter stays sealed, unless configured otherwise
+pub const DEFAULT_LETTER_SECS: u64 = 24 * 60 * 60;
+
+/// The prefix of the keys of when the files open in the game state
+const UNLOCK_KEY: &str = "unlock:";
+
+impl Philosophy {
+    /// The seconds every time-locked file stays sealed once written, by
+    /// path
+    fn time_locks(&self) -> BTreeMap<String, u64> {
+        self.config
+            .time_locks
+            .clone()
+            .unwrap_or_else(|| BTreeMap::from([(LETTER_FILE.to_string(), DEFAULT_LETTER_SECS)]))
+    }
+
+    /// When the file at `path` opens, if it was ever sealed
+    fn unlocks_at(&self, path: &str) -> Option<SystemTime> {
+        let secs: u64 = self
+            .game_state
+            .get(&format!("{}{}", UNLOCK_KEY, path))?
+            .parse()
+            .ok()?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// When the file at `path` opens, if it is sealed now
+    fn sealed_until(&self, path: &Path) -> Option<SystemTime> {
+        self.unlocks_at(path.to_str()?)
+            .filter(|&at| at > SystemTime::now())
+    }
+
+    /// Leaves the letter to be written in the time path, unless it is
+    /// there already
+    pub(crate) fn create_letter(&mut self) {
+        let letter = self.root.join(LETTER_FILE);
+        let Some(secs) = self.time_locks().get(LETTER_FILE).copied() else {
+            return;
+        };
+        if letter.exists() || !letter.parent().is_some_and(Path::is_dir) {
+            return;
+        }
+        let opens = match secs {
+            DEFAULT_LETTER_SECS => "a day later".to_string(),
+            secs => format!("{} seconds later", secs),
+        };
+        let content = format!(
+            "Write a letter to your future self in this file.\n\
+             Once written it is sealed, and only opens again {}.\n",
+            opens
+        );
+        if let Err(e) = std::fs::write(&letter, content) {
+            warn!("Unable to create the letter {:?}: {:?}", letter, e);
+        }
+    }
+
+    /// Seals the file at `path` as it is written, if it is time-locked and
+    /// not sealed already
+    pub(crate) fn seal(&mut self, path: &Path) {
+        let Some(name) = path.to_str() else {
+            return;
+        };
+        let Some(secs) = self.time_locks().get(name).copied() else {
+            return;
+        };
+        if self.sealed_until(path).is_some() {
+            return;
+        }
+        let opens = SystemTime::now() + Duration::from_secs(secs);
+        let since_epoch = opens
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        info!("{} is sealed for {} seconds", name, secs);
+        self.game_state.insert(
+            format!("{}{}", UNLOCK_KEY, name),
+            since_epoch.as_secs().to_string(),
+        );
+    }
+
+    /// What the file at `path` shows in place of what was written, while
+    /// it is sealed
+    pub(crate) fn sealed_contents(&self, path: &Path) -> Option<Vec<u8>> {
+        let opens = self.sealed_until(path)?;
+        let opens = DateTime::<Local>::from(opens).format("%Y-%m-%d at %H:%M:%S");
+        let contents = match path == Path::new(LETTER_FILE) {
+            true => format!(
+                "Not yet.\n\nYour letter is sealed until {}.\n\
+                 Come back then to read what you wrote.\n",
+                opens
+            ),
+            false => format!(
+                "Not yet.\n\nThis file is sealed until {}.\n\
+                 Come back then to read what was written.\n",
+                opens
+            ),
+        };
+        Some(contents.into_bytes())
+    }
+}
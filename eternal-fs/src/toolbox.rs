@@ -0,0 +1,243 @@
+//! Ciphers to solve puzzles with.
+//!
+//! `toolbox/` at the root holds a file for every cipher: `rot13`,
+//! `base64` and `morse`. Writing text to one makes it read back as that
+//! text run through the cipher, encoded or decoded as the text calls for:
+//! text which is Base64 or Morse code already is decoded, any other text
+//! encoded, and ROT13 undoes itself. The ciphers keep nothing but what
+//! every player last wrote to them, and that only while the server runs.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use nfsserve::nfs::nfsstat3;
+
+use crate::engine::{read_at, VirtualAttrs, VirtualFile};
+use crate::philosophy::Philosophy;
+use crate::players::PlayerState;
+
+/// The directory the ciphers are in
+pub(crate) const TOOLBOX_DIR: &str = "toolbox";
+
+const ROT13: &str = "rot13";
+const BASE64: &str = "base64";
+const MORSE: &str = "morse";
+
+/// The ciphers of the toolbox, by file name
+const TOOLS: &[&str] = &[ROT13, BASE64, MORSE];
+
+/// Bytes a cipher takes at most
+const MAX_INPUT: usize = 64 * 1024;
+
+/// The letters of Morse code
+const MORSE_CODE: &[(char, &str)] = &[
+    ('a', ".-"),
+    ('b', "-..."),
+    ('c', "-.-."),
+    ('d', "-.."),
+    ('e', "."),
+    ('f', "..-."),
+    ('g', "--."),
+    ('h', "...."),
+    ('i', ".."),
+    ('j', ".---"),
+    ('k', "-.-"),
+    ('l', ".-.."),
+    ('m', "--"),
+    ('n', "-."),
+    ('o', "---"),
+    ('p', ".--."),
+    ('q', "--.-"),
+    ('r', ".-."),
+    ('s', "..."),
+    ('t', "-"),
+    ('u', "..-"),
+    ('v', "...-"),
+    ('w', ".--"),
+    ('x', "-..-"),
+    ('y', "-.--"),
+    ('z', "--.."),
+    ('0', "-----"),
+    ('1', ".----"),
+    ('2', "..---"),
+    ('3', "...--"),
+    ('4', "....-"),
+    ('5', "....."),
+    ('6', "-...."),
+    ('7', "--..."),
+    ('8', "---.."),
+    ('9', "----."),
+    ('.', ".-.-.-"),
+    (',', "--..--"),
+    ('?', "..--.."),
+    ('\'', ".----."),
+    ('!', "-.-.--"),
+    (':', "---..."),
+    ('=', "-...-"),
+];
+
+/// `text` with every letter moved thirteen places along the alphabet
+fn rot13(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'a'..='m' | 'A'..='M' => (c as u8 + 13) as char,
+            'n'..='z' | 'N'..='Z' => (c as u8 - 13) as char,
+            c => c,
+        })
+        .collect()
+}
+
+/// What `text` says, if it is Base64 of printable text, or else `text`
+/// in Base64
+fn base64(text: &str) -> String {
+    let packed: String = text.split_whitespace().collect();
+    let decoded = STANDARD
+        .decode(&packed)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .filter(|decoded| {
+            !decoded.is_empty()
+                && decoded
+                    .chars()
+                    .all(|c| !c.is_control() || c.is_whitespace())
+        });
+    decoded.unwrap_or_else(|| STANDARD.encode(text))
+}
+
+/// What `text` says, if it is Morse code, with words split by `/`, or
+/// else `text` in Morse code. Characters without a code are left out of
+/// the code, and codes without a character read as `?`.
+fn morse(text: &str) -> String {
+    let is_code = text
+        .chars()
+        .all(|c| matches!(c, '.' | '-' | '/') || c.is_whitespace());
+    if is_code {
+        return text
+            .split('/')
+            .map(|word| {
+                word.split_whitespace()
+                    .map(|code| {
+                        MORSE_CODE
+                            .iter()
+                            .find(|(_, c)| *c == code)
+                            .map_or('?', |(letter, _)| *letter)
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    text.split_whitespace()
+        .map(|word| {
+            word.to_lowercase()
+                .chars()
+                .filter_map(|letter| {
+                    MORSE_CODE
+                        .iter()
+                        .find(|(l, _)| *l == letter)
+                        .map(|(_, code)| *code)
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+impl PlayerState {
+    /// What the cipher `tool` shows the player
+    fn tool_output(&self, tool: &str) -> String {
+        let Some(input) = self.toolbox.get(tool) else {
+            return match tool {
+                ROT13 => "Write text here, and read it back with every letter moved thirteen \
+                          places along the alphabet.\n"
+                    .to_string(),
+                BASE64 => "Write text here to read it back in Base64, or Base64 to read back \
+                           what it says.\n"
+                    .to_string(),
+                _ => "Write text here to read it back in Morse code, or Morse code, with words \
+                      split by /, to read back what it says.\n"
+                    .to_string(),
+            };
+        };
+        let text = String::from_utf8_lossy(input);
+        let text = text.trim();
+        let output = match tool {
+            ROT13 => rot13(text),
+            BASE64 => base64(text),
+            _ => morse(text),
+        };
+        format!("{}\n", output)
+    }
+}
+
+impl Philosophy {
+    /// The files of the ciphers
+    pub(crate) fn toolbox_paths(&self) -> Vec<PathBuf> {
+        TOOLS
+            .iter()
+            .map(|tool| Path::new(TOOLBOX_DIR).join(tool))
+            .collect()
+    }
+
+    /// The cipher at `path`, as the player making the call sees it
+    pub(crate) fn open_tool(&mut self, path: &Path) -> Option<Box<dyn VirtualFile + '_>> {
+        if path.parent()? != Path::new(TOOLBOX_DIR) {
+            return None;
+        }
+        let name = path.file_name()?.to_str()?;
+        let tool = TOOLS.iter().find(|tool| **tool == name)?;
+        Some(Box::new(ToolFile { game: self, tool }))
+    }
+}
+
+/// A cipher of the toolbox, as the player making the call sees it
+struct ToolFile<'a> {
+    game: &'a mut Philosophy,
+    tool: &'static str,
+}
+
+impl ToolFile<'_> {
+    fn contents(&self) -> String {
+        let newcomer = PlayerState::default();
+        self.game
+            .players
+            .get(&self.game.player_identity.current())
+            .unwrap_or(&newcomer)
+            .tool_output(self.tool)
+    }
+}
+
+impl VirtualFile for ToolFile<'_> {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        Ok(read_at(self.contents().as_bytes(), offset, count))
+    }
+
+    fn write(&mut self, offset: u64, data: &[u8]) -> Result<(), nfsstat3> {
+        let id = self.game.player_identity.current();
+        let player = self.game.players.entry(id).or_default();
+        let input = player.toolbox.entry(self.tool.to_string()).or_default();
+        // a write from the start replaces the text, one further on goes on
+        // with it, as long texts are written in pieces
+        let offset = usize::try_from(offset).map_err(|_| nfsstat3::NFS3ERR_FBIG)?;
+        if offset.saturating_add(data.len()) > MAX_INPUT {
+            return Err(nfsstat3::NFS3ERR_FBIG);
+        }
+        input.truncate(offset);
+        input.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.contents().len() as u64
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o644,
+            mtime: SystemTime::now(),
+        }
+    }
+}
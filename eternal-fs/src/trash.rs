@@ -0,0 +1,144 @@
+//! Removed files kept in `.trash/` for a while.
+//!
+//! With `--trash DAYS` (or `trash_days = DAYS` under `[server]`) a file a
+//! client removes is not unlinked but moved to `.trash/<time>/` at the
+//! root, under the path it had, so that an answer removed by mistake can
+//! be had back. Directories are still removed, as only empty ones can be.
+//! What has been in the trash for longer than `DAYS` days is removed for
+//! good as more is thrown away, and so is whatever a client removes from
+//! the trash itself.
+//!
+//! `restore <path>` in `.control` moves the file most recently removed
+//! from `<path>` back where it was.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use tracing::{debug, warn};
+
+use crate::philosophy::Philosophy;
+use crate::FSMap;
+
+/// The directory removed files are kept in, at the root of the world
+pub(crate) const TRASH_DIR: &str = ".trash";
+
+/// The times files were removed at in the trash of the world at `root`,
+/// each a directory holding them, the oldest first
+fn removals(root: &Path) -> Vec<PathBuf> {
+    let mut removals: Vec<PathBuf> = std::fs::read_dir(root.join(TRASH_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    // the names sort by the time the files were removed
+    removals.sort();
+    removals
+}
+
+/// Removes the directories from `dir` up to `top`, not included, for as
+/// long as they are empty
+fn remove_empty(mut dir: &Path, top: &Path) {
+    while dir != top && dir.starts_with(top) && std::fs::remove_dir(dir).is_ok() {
+        let Some(parent) = dir.parent() else {
+            return;
+        };
+        dir = parent;
+    }
+}
+
+impl FSMap {
+    /// Whether removing the file at `path` moves it to the trash
+    pub(crate) fn keeps_trash(&self, path: &Path) -> bool {
+        self.trash_retention.is_some() && !path.starts_with(TRASH_DIR)
+    }
+
+    /// Moves the file at `path` to the trash, and removes what has been
+    /// there for too long
+    pub(crate) fn move_to_trash(&mut self, path: &Path) -> io::Result<()> {
+        let now = DateTime::<Local>::from(SystemTime::now())
+            .format("%Y-%m-%dT%H-%M-%S")
+            .to_string();
+        let trash = self.root.join(TRASH_DIR);
+        // a path removed twice in the same second is kept twice
+        let mut removal = trash.join(&now);
+        for n in 2.. {
+            if removal.join(path).symlink_metadata().is_err() {
+                break;
+            }
+            removal = trash.join(format!("{}-{}", now, n));
+        }
+        let to = removal.join(path);
+        if let Some(dir) = to.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::rename(self.root.join(path), &to)?;
+        debug!("Moved {:?} to the trash as {:?}", path, to);
+        self.empty_trash();
+        Ok(())
+    }
+
+    /// Removes what has been in the trash for longer than it is kept
+    pub(crate) fn empty_trash(&self) {
+        let Some(retention) = self.trash_retention else {
+            return;
+        };
+        for removal in removals(&self.root) {
+            let removed = removal
+                .metadata()
+                .and_then(|meta| meta.modified())
+                .unwrap_or_else(|_| SystemTime::now());
+            if removed.elapsed().unwrap_or_default() < retention {
+                continue;
+            }
+            match std::fs::remove_dir_all(&removal) {
+                Ok(()) => debug!("Emptied {:?} from the trash", removal),
+                Err(e) => warn!("Unable to empty {:?} from the trash: {:?}", removal, e),
+            }
+        }
+    }
+}
+
+impl Philosophy {
+    /// Moves the file most recently removed from `path` back from the
+    /// trash, and returns what was done
+    pub(crate) fn restore(&mut self, path: &str) -> String {
+        let path = Path::new(path);
+        if path.is_absolute() || path.components().any(|c| c.as_os_str() == "..") {
+            return format!("{:?} is not a path of the world.", path);
+        }
+        let to = self.root.join(path);
+        if to.symlink_metadata().is_ok() {
+            return format!("There is a {} already.", path.display());
+        }
+        let Some(removal) = removals(&self.root)
+            .into_iter()
+            .rev()
+            .find(|removal| removal.join(path).symlink_metadata().is_ok())
+        else {
+            return format!("There is no {} in the trash.", path.display());
+        };
+        let from = removal.join(path);
+        let restored = to
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::rename(&from, &to));
+        if let Err(e) = restored {
+            return format!("Unable to restore {}: {}", path.display(), e);
+        }
+        if let Some(dir) = from.parent() {
+            remove_empty(dir, &self.root.join(TRASH_DIR));
+        }
+        format!(
+            "Restored {} as it was removed at {}.",
+            path.display(),
+            removal
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default()
+        )
+    }
+}
@@ -0,0 +1,203 @@
+//! The journey kept in git.
+//!
+//! With `git = true` under `[game]`, and eternal-fs built with the `git`
+//! feature, the root of the world is made a git repository as the server
+//! starts, unless it is one already. Every accepted answer, every rewind
+//! and every command an operator writes to `.control` saves the game and
+//! commits the whole world, answers and saved game alike, by the player
+//! who made it. `git log` then tells the journey, and it is rolled back
+//! with `rollback <commit>` in `.control`, or with `git reset --hard`
+//! while the server is stopped.
+
+use std::path::Path;
+
+use crate::philosophy::Philosophy;
+
+#[cfg(feature = "git")]
+pub(crate) use repository::Versioning;
+
+#[cfg(feature = "git")]
+mod repository {
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use git2::{IndexAddOption, Oid, Repository, ResetType, Signature};
+
+    /// Files of the world which are not committed: the metadata store
+    /// changes with every call and is rebuilt from the host anyway, and
+    /// the snapshots, versions and the objects they keep are of the world
+    /// committed already
+    const EXCLUDED: &str =
+        "/.eternal/metadata.*\n/.eternal/snapshots/\n/.eternal/versions/\n/.eternal/objects/\n";
+
+    /// The git repository at the root of the world
+    pub(crate) struct Versioning {
+        /// Only ever used by one call at a time, as the game is
+        repo: Mutex<Repository>,
+    }
+
+    impl std::fmt::Debug for Versioning {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Versioning")
+                .field("path", &self.repo.lock().unwrap().path())
+                .finish()
+        }
+    }
+
+    impl Versioning {
+        /// Opens the repository at `root`, making one if there is none
+        pub(crate) fn open(root: &Path) -> Result<Versioning, git2::Error> {
+            let repo = match Repository::open(root) {
+                Ok(repo) => repo,
+                Err(_) => {
+                    let repo = Repository::init(root)?;
+                    let exclude = repo.path().join("info").join("exclude");
+                    let _ = std::fs::create_dir_all(repo.path().join("info"));
+                    let _ = std::fs::write(exclude, EXCLUDED);
+                    repo
+                }
+            };
+            Ok(Versioning {
+                repo: Mutex::new(repo),
+            })
+        }
+
+        /// Commits the world as it is on the host, but for the `written`
+        /// files, committed as they will be once written. Returns the
+        /// commit, or none if nothing changed since the last one.
+        pub(crate) fn commit(
+            &self,
+            author: &str,
+            message: &str,
+            written: &[(&Path, &[u8])],
+        ) -> Result<Option<Oid>, git2::Error> {
+            let repo = self.repo.lock().unwrap();
+            let mut index = repo.index()?;
+            index.add_all(["*"], IndexAddOption::DEFAULT, None)?;
+            index.update_all(["*"], None)?;
+            for (path, contents) in written {
+                if let Some(mut entry) = index.get_path(path, 0) {
+                    entry.file_size = contents.len() as u32;
+                    index.add_frombuffer(&entry, contents)?;
+                }
+            }
+            index.write()?;
+            let tree = repo.find_tree(index.write_tree()?)?;
+            let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+            if parent
+                .as_ref()
+                .is_some_and(|parent| parent.tree_id() == tree.id())
+            {
+                return Ok(None);
+            }
+            let author = Signature::now(author, &format!("{}@eternal-fs", author))?;
+            let committer = Signature::now("eternal-fs", "eternal-fs@localhost")?;
+            let parents: Vec<_> = parent.iter().collect();
+            repo.commit(Some("HEAD"), &author, &committer, message, &tree, &parents)
+                .map(Some)
+        }
+
+        /// Puts the world back as it was at `rev`, dropping the commits
+        /// after it
+        pub(crate) fn rollback(&self, rev: &str) -> Result<Oid, git2::Error> {
+            let repo = self.repo.lock().unwrap();
+            let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+            repo.reset(commit.as_object(), ResetType::Hard, None)?;
+            Ok(commit.id())
+        }
+    }
+}
+
+impl Philosophy {
+    /// Answers of the player making the call accepted so far, to stages
+    /// and side quests alike
+    pub(crate) fn accepted_answers(&self) -> usize {
+        self.players
+            .get(&self.player_identity.current())
+            .map_or(0, |player| {
+                player.completed_questions.len() + player.quests_completed.len()
+            })
+    }
+}
+
+#[cfg(feature = "git")]
+impl Philosophy {
+    /// Makes the root a git repository if the journey is kept in one
+    pub(crate) fn open_versioning(&mut self) {
+        if !self.config.git.unwrap_or(false) {
+            return;
+        }
+        match Versioning::open(&self.root) {
+            Ok(versioning) => self.versioning = Some(versioning),
+            Err(e) => tracing::warn!("Unable to keep the journey in git: {}", e),
+        }
+    }
+
+    /// Saves the game and commits the world, with the `written` files as
+    /// they will be once written
+    pub(crate) fn commit_journey(
+        &mut self,
+        author: &str,
+        message: &str,
+        written: &[(&Path, &[u8])],
+    ) {
+        if self.versioning.is_none() {
+            return;
+        }
+        if let Err(e) = self.save_game() {
+            tracing::warn!("Unable to save the game before committing it: {:?}", e);
+        }
+        let versioning = self
+            .versioning
+            .as_ref()
+            .expect("the journey is kept in git");
+        match versioning.commit(author, message, written) {
+            Ok(Some(commit)) => tracing::debug!(
+                "Committed {} as {}",
+                message.lines().next().unwrap_or(""),
+                commit
+            ),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Unable to commit the journey: {}", e),
+        }
+    }
+
+    /// Puts the world and the journeys in it back as they were at the
+    /// commit `rev`, and returns what was done
+    pub(crate) fn rollback(&mut self, rev: &str) -> String {
+        let Some(versioning) = &self.versioning else {
+            return "The journey is not kept in git.".to_string();
+        };
+        // a database can not be reset under the store which has it open
+        if !matches!(
+            self.config.store.kind.as_deref(),
+            None | Some("toml" | "json")
+        ) {
+            return "Rolling back needs the game kept in game.toml or game.json.".to_string();
+        }
+        match versioning.rollback(rev) {
+            Ok(commit) => {
+                self.restore_game();
+                format!("Rolled the journey back to {}.", commit)
+            }
+            Err(e) => format!("Unable to roll back to {}: {}", rev, e.message()),
+        }
+    }
+}
+
+#[cfg(not(feature = "git"))]
+impl Philosophy {
+    pub(crate) fn open_versioning(&mut self) {}
+
+    pub(crate) fn commit_journey(
+        &mut self,
+        _author: &str,
+        _message: &str,
+        _written: &[(&Path, &[u8])],
+    ) {
+    }
+
+    pub(crate) fn rollback(&mut self, _rev: &str) -> String {
+        "The journey is not kept in git.".to_string()
+    }
+}
@@ -0,0 +1,195 @@
+//! Earlier versions of every file, as `<name>@v1`, `<name>@v2`, ...
+//!
+//! With `--versions N` (or `versions = N` under `[server]`) what a file
+//! holds is kept before a client writes over it, truncates it, creates it
+//! anew or renames another file over it, and the `N` most recent versions
+//! of every file are kept. They are not listed, but looking `<name>@v<n>`
+//! up in the directory of the file finds the `n`th version kept,
+//! read-only, through the [virtual files](crate::virtual_files).
+//! Versions are numbered as they are kept, the oldest first, and keep
+//! their number as older ones are removed. They stay with the path they
+//! were kept for, so that a file removed or renamed away can still be
+//! read as it was.
+//!
+//! A file is kept as it was before the first of a run of changes: a file
+//! changed within the last few seconds is taken to be still being written,
+//! and no other version of it is kept. Versions are kept under
+//! `.eternal/versions/`, by the path of their file, and what they hold
+//! only once, see `objects.rs`: versions holding the same show the time
+//! it was first kept. With `--compress` they are kept compressed, see
+//! `compression.rs`.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use nfsserve::nfs::nfsstat3;
+use tracing::{debug, warn};
+
+use crate::compression::{self, uncompressed, Kept};
+use crate::engine::{VirtualAttrs, VirtualFile};
+use crate::objects;
+use crate::trash::TRASH_DIR;
+use crate::{FSMap, STATE_DIR};
+
+/// Where versions are kept, within the state directory
+const VERSION_STORE: &str = "versions";
+
+/// What comes between the name of a file and the number of its version
+const VERSION_MARK: &str = "@v";
+
+/// A file changed more recently than this is still being written
+const STILL_WRITING: Duration = Duration::from_secs(2);
+
+/// An earlier version of a file, read from where it is kept
+pub(crate) struct VersionFile {
+    kept: Kept,
+    size: u64,
+    mtime: SystemTime,
+}
+
+impl VirtualFile for VersionFile {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let buf = self
+            .kept
+            .read(offset, count)
+            .or(Err(nfsstat3::NFS3ERR_IO))?;
+        let eof = offset + buf.len() as u64 >= self.size;
+        Ok((buf, eof))
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o444,
+            mtime: self.mtime,
+        }
+    }
+}
+
+/// The path of the file and the number of the version `path` names, if
+/// it names one
+fn split_version(path: &Path) -> Option<(PathBuf, u64)> {
+    let name = path.file_name()?.to_str()?;
+    let (file, number) = name.rsplit_once(VERSION_MARK)?;
+    if file.is_empty() || number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((path.with_file_name(file), number.parse().ok()?))
+}
+
+/// `<path>@v<number>`
+fn versioned(path: &Path, number: u64) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!("{}{}", VERSION_MARK, number));
+    PathBuf::from(name)
+}
+
+impl FSMap {
+    /// The directory the versions are kept in
+    fn version_store(&self) -> PathBuf {
+        self.root.join(STATE_DIR).join(VERSION_STORE)
+    }
+
+    /// The numbers of the versions kept of the file at `path`, the oldest
+    /// first
+    fn versions_of(&self, path: &Path) -> Vec<u64> {
+        let (Some(dir), Some(file)) = (path.parent(), path.file_name()) else {
+            return Vec::new();
+        };
+        let mut numbers: Vec<u64> = std::fs::read_dir(self.version_store().join(dir))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let (of, number) = split_version(Path::new(uncompressed(&name)))?;
+                (of == file).then_some(number)
+            })
+            .collect();
+        numbers.sort();
+        numbers
+    }
+
+    /// Where the version `number` of the file at `path` is kept, if it is,
+    /// and whether it is kept compressed
+    fn find_version(&self, path: &Path, number: u64) -> Option<(PathBuf, bool)> {
+        compression::find(&self.version_store().join(versioned(path, number)))
+    }
+
+    /// Keeps what the file at `path` holds before what it holds from
+    /// `from` on is written over or cut off, unless it is still being
+    /// written
+    pub(crate) fn keep_version(&self, path: &Path, from: u64) {
+        if self.versions_kept == 0 || path.starts_with(STATE_DIR) || path.starts_with(TRASH_DIR) {
+            return;
+        }
+        let host = self.root.join(path);
+        let Ok(meta) = host.symlink_metadata() else {
+            return;
+        };
+        // nothing which was there changes
+        if !meta.is_file() || meta.len() <= from {
+            return;
+        }
+        let mtime = meta.modified().unwrap_or_else(|_| SystemTime::now());
+        if mtime.elapsed().is_ok_and(|age| age < STILL_WRITING) {
+            return;
+        }
+        let numbers = self.versions_of(path);
+        let number = numbers.last().map_or(1, |last| last + 1);
+        let kept = self.version_store().join(versioned(path, number));
+        if let Err(e) = compression::keep_file(&self.root, &host, &kept, Some(mtime), self.compress)
+        {
+            warn!("Unable to keep {:?} as version {}: {:?}", path, number, e);
+            let _ = std::fs::remove_file(&kept);
+            let _ = std::fs::remove_file(compression::compressed(&kept));
+            return;
+        }
+        debug!("Kept {:?} as version {}", path, number);
+        let excess = (numbers.len() + 1).saturating_sub(self.versions_kept);
+        for old in numbers.into_iter().take(excess) {
+            let Some((old, _)) = self.find_version(path, old) else {
+                continue;
+            };
+            if let Err(e) = objects::release(&self.root, &old) {
+                warn!("Unable to remove the version {:?}: {:?}", old, e);
+            }
+        }
+    }
+
+    /// Whether `path` names a version kept of a file the player making
+    /// the call may see
+    pub(crate) fn is_version(&self, path: &Path) -> bool {
+        if self.versions_kept == 0 {
+            return false;
+        }
+        let Some((file, number)) = split_version(path) else {
+            return false;
+        };
+        !self.game.is_hidden(&file) && self.find_version(&file, number).is_some()
+    }
+
+    /// The version at `path`, if it names one
+    pub(crate) fn open_version(&self, path: &Path) -> Option<Result<VersionFile, nfsstat3>> {
+        if !self.is_version(path) {
+            return None;
+        }
+        let (file, number) = split_version(path)?;
+        let (kept, compressed) = self.find_version(&file, number)?;
+        let opened = Kept::open(&kept, compressed)
+            .and_then(|opened| {
+                let mtime = kept.metadata()?.modified()?;
+                Ok(VersionFile {
+                    size: opened.size()?,
+                    kept: opened,
+                    mtime,
+                })
+            })
+            .or(Err(nfsstat3::NFS3ERR_STALE));
+        Some(opened)
+    }
+}
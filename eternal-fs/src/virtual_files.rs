@@ -0,0 +1,292 @@
+//! Files the game serves from memory.
+//!
+//! A [`GameEngine`](crate::engine::GameEngine) can name files it serves
+//! itself, see [`virtual_files`](crate::engine::GameEngine::virtual_files).
+//! They are never written to the host: every call on them is handed to a
+//! [`VirtualFile`] the game opens for it. They are listed after the host
+//! entries of their directory, which hide any host file of the same name.
+//! Directories above them which are not on the host are made up as well,
+//! such as the [status directory](crate::status) and the
+//! [snapshots](crate::snapshots). The [versions](crate::versions) of files
+//! are served from memory too, but only found by looking them up.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use nfsserve::nfs::*;
+
+use crate::engine::{read_at, VirtualAttrs, VirtualFile};
+use crate::generated::{flatten, to_nfstime, Listing};
+use crate::xattrs::paginate;
+use crate::FSMap;
+
+/// Fileids handed out for virtual files, by path. They are drawn from the
+/// same counter as real fileids and, like them, never reused.
+#[derive(Debug, Default)]
+pub(crate) struct VirtualNodes {
+    by_id: HashMap<fileid3, PathBuf>,
+    by_path: HashMap<PathBuf, fileid3>,
+}
+
+impl VirtualNodes {
+    /// The path of the virtual file behind a fileid, if it is one
+    pub(crate) fn get(&self, id: fileid3) -> Option<PathBuf> {
+        self.by_id.get(&id).cloned()
+    }
+
+    fn id_of(&mut self, path: &Path, next_fileid: &AtomicU64) -> fileid3 {
+        if let Some(id) = self.by_path.get(path) {
+            return *id;
+        }
+        let id = next_fileid.fetch_add(1, Ordering::Relaxed);
+        self.by_id.insert(id, path.to_path_buf());
+        self.by_path.insert(path.to_path_buf(), id);
+        id
+    }
+}
+
+/// Derives the attributes of a virtual file from those of the root
+fn virtual_fattr(base: fattr3, id: fileid3, size: u64, attrs: VirtualAttrs) -> fattr3 {
+    let mtime = to_nfstime(attrs.mtime);
+    fattr3 {
+        ftype: ftype3::NF3REG,
+        mode: attrs.mode,
+        nlink: 1,
+        size,
+        used: size,
+        rdev: specdata3::default(),
+        fileid: id,
+        atime: mtime,
+        mtime,
+        ctime: mtime,
+        ..base
+    }
+}
+
+/// A file served from memory which can only be read
+struct MemoryFile {
+    contents: Vec<u8>,
+}
+
+impl VirtualFile for MemoryFile {
+    fn read(&mut self, offset: u64, count: u32) -> Result<(Vec<u8>, bool), nfsstat3> {
+        Ok(read_at(&self.contents, offset, count))
+    }
+
+    fn size(&self) -> u64 {
+        self.contents.len() as u64
+    }
+
+    fn attrs(&self) -> VirtualAttrs {
+        VirtualAttrs {
+            mode: 0o444,
+            mtime: SystemTime::now(),
+        }
+    }
+}
+
+impl FSMap {
+    /// The paths of every virtual file, those of the game, the status
+    /// files and those of the snapshots
+    fn virtual_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.game.virtual_files();
+        paths.extend(self.status_paths());
+        paths.extend(self.snapshot_paths());
+        paths
+    }
+
+    /// Whether the file at `path` is served from memory. The versions of
+    /// files are, though they are not listed.
+    pub(crate) fn is_virtual(&self, path: &Path) -> bool {
+        self.virtual_paths().iter().any(|p| p == path) || self.is_version(path)
+    }
+
+    /// Whether `path` is a directory made up to hold virtual files. A
+    /// directory of the host holding some, such as a stage directory, is
+    /// not.
+    fn is_virtual_dir(&self, path: &Path) -> bool {
+        path != Path::new("")
+            && !self.root.join(path).is_dir()
+            && self
+                .virtual_paths()
+                .iter()
+                .any(|p| p != path && p.starts_with(path))
+    }
+
+    /// The fileid of the virtual file or directory at `path`, if there is
+    /// one
+    pub(crate) fn virtual_lookup(&mut self, path: &Path) -> Option<fileid3> {
+        if !self.is_virtual(path) && !self.is_virtual_dir(path) {
+            return None;
+        }
+        Some(self.virtual_files.id_of(path, &self.next_fileid))
+    }
+
+    /// Looks `filename` up in the virtual directory with the fileid
+    /// `dirid`. Returns None if `dirid` is not virtual.
+    pub(crate) fn virtual_child_lookup(
+        &mut self,
+        dirid: fileid3,
+        filename: &[u8],
+    ) -> Result<Option<fileid3>, nfsstat3> {
+        let Some(dir) = self.virtual_files.get(dirid) else {
+            return Ok(None);
+        };
+        if !self.is_virtual_dir(&dir) {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        self.virtual_lookup(&dir.join(OsStr::from_bytes(filename)))
+            .map(Some)
+            .ok_or(nfsstat3::NFS3ERR_NOENT)
+    }
+
+    /// The virtual file at `path`, as the calling player sees it
+    fn open_virtual(&mut self, path: &Path) -> Result<Box<dyn VirtualFile + '_>, nfsstat3> {
+        if self.is_virtual_dir(path) {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        }
+        if let Some(contents) = self.status_contents(path) {
+            return Ok(Box::new(MemoryFile {
+                contents: contents.into_bytes(),
+            }));
+        }
+        if let Some(file) = self.open_snapshot(path) {
+            return Ok(Box::new(file?));
+        }
+        if let Some(file) = self.open_version(path) {
+            return Ok(Box::new(file?));
+        }
+        self.game.open_virtual(path).ok_or(nfsstat3::NFS3ERR_STALE)
+    }
+
+    pub(crate) fn virtual_getattr(&mut self, id: fileid3, path: &Path) -> Result<fattr3, nfsstat3> {
+        let root = self.find_entry(0)?.fsmeta;
+        if self.is_virtual_dir(path) {
+            let attrs = VirtualAttrs {
+                mode: 0o555,
+                mtime: self.started,
+            };
+            return Ok(fattr3 {
+                ftype: ftype3::NF3DIR,
+                nlink: 2,
+                ..virtual_fattr(root, id, 0, attrs)
+            });
+        }
+        let file = self.open_virtual(path)?;
+        Ok(virtual_fattr(root, id, file.size(), file.attrs()))
+    }
+
+    pub(crate) fn virtual_read(
+        &mut self,
+        path: &Path,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        self.open_virtual(path)?.read(offset, count)
+    }
+
+    pub(crate) fn virtual_write(
+        &mut self,
+        id: fileid3,
+        path: &Path,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<fattr3, nfsstat3> {
+        self.open_virtual(path)?.write(offset, data)?;
+        self.virtual_getattr(id, path)
+    }
+
+    /// Virtual files keep the attributes the game gives them. Setting
+    /// them is accepted for files which can be written, as clients
+    /// truncate files they open for writing, and changes nothing.
+    pub(crate) fn virtual_setattr(&mut self, id: fileid3, path: &Path) -> Result<fattr3, nfsstat3> {
+        let attr = self.virtual_getattr(id, path)?;
+        if attr.mode & 0o222 == 0 {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
+        Ok(attr)
+    }
+
+    /// Lists the virtual directory at `dir` from the cookie `start_after`
+    pub(crate) fn virtual_list(
+        &mut self,
+        dir: &Path,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<Listing, nfsstat3> {
+        if !self.is_virtual_dir(dir) {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        }
+        self.game_list(dir, start_after, max_entries)
+    }
+
+    /// Lists what the game adds to the host entries of the directory at
+    /// `dir` from the cookie `start_after`: the virtual files and
+    /// directories in it and, at the root, the generated directories
+    pub(crate) fn game_list(
+        &mut self,
+        dir: &Path,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<Listing, nfsstat3> {
+        let (mut entries, mut end) = (Vec::new(), true);
+        // the generated directories follow the virtual files
+        if self.generated.get(start_after).is_none() {
+            let mut files = Vec::new();
+            for path in self.virtual_children(dir) {
+                let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+                    continue;
+                };
+                let name = name.to_string();
+                let id = self.virtual_files.id_of(&path, &self.next_fileid);
+                let attr = self.virtual_getattr(id, &path)?;
+                files.push((id, (name, attr)));
+            }
+            // the host entries were listed before, whatever their cookies
+            let start_after = if self.virtual_files.get(start_after).is_some() {
+                start_after
+            } else {
+                0
+            };
+            let (page, page_end) = paginate(files, start_after, max_entries);
+            (entries, end) = flatten(page, page_end);
+        }
+        if dir == Path::new("") && end && entries.len() < max_entries {
+            let (dirs, dirs_end) =
+                self.generated_root_list(start_after, max_entries - entries.len())?;
+            entries.extend(dirs);
+            end = dirs_end;
+        }
+        Ok((entries, end))
+    }
+
+    /// The paths of the virtual files and directories directly in `dir`
+    pub(crate) fn virtual_children(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut children: Vec<PathBuf> = self
+            .virtual_paths()
+            .iter()
+            .filter_map(|path| {
+                let name = path.strip_prefix(dir).ok()?.components().next()?;
+                Some(dir.join(name))
+            })
+            // the host directories on the way are listed from the host
+            .filter(|child| self.is_virtual(child) || self.is_virtual_dir(child))
+            .collect();
+        children.sort();
+        children.dedup();
+        children
+    }
+
+    /// Whether `filename` in the directory with the fileid `dirid` is a
+    /// virtual file or directory
+    pub(crate) fn is_virtual_child(&self, dirid: fileid3, filename: &[u8]) -> bool {
+        self.id_to_path.get(&dirid).is_some_and(|dir| {
+            let path = self.game_path(&dir.name).join(OsStr::from_bytes(filename));
+            self.is_virtual(&path) || self.is_virtual_dir(&path)
+        })
+    }
+}
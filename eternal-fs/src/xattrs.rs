@@ -0,0 +1,355 @@
+//! Extended attributes of host files, served through a shadow namespace.
+//!
+//! NFSv3 has no way to carry extended attributes, so every directory of
+//! the world holds a hidden `.xattr` directory. It contains a directory
+//! for each entry of its parent, and in there every attribute of the
+//! entry is a file:
+//!
+//! ```text
+//! logic/.xattr/answer.txt/user.mood
+//! ```
+//!
+//! Reading the file reads the attribute and writing it sets the
+//! attribute. Creating and removing files adds and removes attributes.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nfsserve::fs_util::io_error_to_nfsstat3;
+use nfsserve::nfs::*;
+use tracing::debug;
+
+use crate::FSMap;
+
+/// Name of the shadow directory present in every directory
+pub const XATTR_DIR: &str = ".xattr";
+
+/// Largest attribute value the host accepts
+const MAX_VALUE_SIZE: u64 = 64 * 1024;
+
+/// An object of the shadow namespace
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum XattrNode {
+    /// The `.xattr` directory of a directory
+    Dir(fileid3),
+    /// The directory holding the attributes of an entry
+    Entry(fileid3),
+    /// One attribute of an entry
+    Attr(fileid3, OsString),
+}
+
+impl XattrNode {
+    /// The real entry the node is about
+    fn target(&self) -> fileid3 {
+        match self {
+            XattrNode::Dir(id) | XattrNode::Entry(id) | XattrNode::Attr(id, _) => *id,
+        }
+    }
+}
+
+/// Fileids handed out for shadow objects. They are drawn from the same
+/// counter as real fileids and, like them, never reused.
+#[derive(Debug, Default)]
+pub(crate) struct XattrNodes {
+    by_id: HashMap<fileid3, XattrNode>,
+    by_node: HashMap<XattrNode, fileid3>,
+}
+
+impl XattrNodes {
+    /// The shadow object behind a fileid, if it is one
+    pub(crate) fn get(&self, id: fileid3) -> Option<XattrNode> {
+        self.by_id.get(&id).cloned()
+    }
+
+    fn id_of(&mut self, node: XattrNode, next_fileid: &AtomicU64) -> fileid3 {
+        if let Some(id) = self.by_node.get(&node) {
+            return *id;
+        }
+        let id = next_fileid.fetch_add(1, Ordering::Relaxed);
+        self.by_id.insert(id, node.clone());
+        self.by_node.insert(node, id);
+        id
+    }
+
+    fn forget(&mut self, node: &XattrNode) {
+        if let Some(id) = self.by_node.remove(node) {
+            self.by_id.remove(&id);
+        }
+    }
+}
+
+/// Reads an attribute value. A missing attribute means the handle
+/// refers to something which no longer exists.
+fn read_value(path: &Path, name: &OsStr) -> Result<Vec<u8>, nfsstat3> {
+    xattr::get(path, name)
+        .map_err(io_error_to_nfsstat3)?
+        .ok_or(nfsstat3::NFS3ERR_STALE)
+}
+
+/// Derives the attributes of a shadow object from those of its entry.
+/// Attributes are files the size of their value, everything else is a
+/// directory.
+fn shadow_fattr(base: fattr3, id: fileid3, value_len: Option<u64>) -> fattr3 {
+    let (ftype, mode, nlink, size) = match value_len {
+        Some(len) => (ftype3::NF3REG, 0o644, 1, len),
+        None => (ftype3::NF3DIR, 0o755, 2, 0),
+    };
+    fattr3 {
+        ftype,
+        mode,
+        nlink,
+        size,
+        used: size,
+        rdev: specdata3::default(),
+        fileid: id,
+        ..base
+    }
+}
+
+/// Keeps the entries after the cookie `start_after`, ordered by fileid,
+/// without splitting the names of one fileid across pages.
+pub(crate) fn paginate<T>(
+    mut entries: Vec<(fileid3, T)>,
+    start_after: fileid3,
+    max_entries: usize,
+) -> (Vec<(fileid3, T)>, bool) {
+    entries.sort_by_key(|(id, _)| *id);
+    let mut ret: Vec<(fileid3, T)> = Vec::new();
+    for (id, item) in entries.into_iter().filter(|(id, _)| *id > start_after) {
+        let boundary = ret.last().is_none_or(|(last, _)| *last != id);
+        if boundary && ret.len() >= max_entries {
+            return (ret, false);
+        }
+        ret.push((id, item));
+    }
+    (ret, true)
+}
+
+impl FSMap {
+    /// Host path of the entry a shadow object is about
+    async fn xattr_target_path(&mut self, node: &XattrNode) -> Result<PathBuf, nfsstat3> {
+        let target = self.resolve_id(node.target()).await?;
+        let ent = self.find_entry(target)?;
+        Ok(self.sym_to_path(&ent.name).await)
+    }
+
+    /// Looks up `filename` in `dirid` if the lookup leads into the shadow
+    /// namespace. Returns None for lookups of real objects.
+    pub(crate) async fn xattr_lookup(
+        &mut self,
+        dirid: fileid3,
+        filename: &[u8],
+    ) -> Result<Option<fileid3>, nfsstat3> {
+        let node = match self.xattrs.get(dirid) {
+            None if filename == XATTR_DIR.as_bytes() => {
+                let dirid = self.resolve_id(dirid).await?;
+                if !matches!(self.find_entry(dirid)?.fsmeta.ftype, ftype3::NF3DIR) {
+                    return Err(nfsstat3::NFS3ERR_NOTDIR);
+                }
+                XattrNode::Dir(dirid)
+            }
+            None => return Ok(None),
+            Some(XattrNode::Dir(dir)) => {
+                let dir = self.resolve_id(dir).await?;
+                self.refresh_dir_list(dir).await?;
+                XattrNode::Entry(self.find_child(dir, filename).await?)
+            }
+            Some(node @ XattrNode::Entry(target)) => {
+                let path = self.xattr_target_path(&node).await?;
+                let name = OsStr::from_bytes(filename);
+                if xattr::get(&path, name)
+                    .map_err(io_error_to_nfsstat3)?
+                    .is_none()
+                {
+                    return Err(nfsstat3::NFS3ERR_NOENT);
+                }
+                XattrNode::Attr(target, name.to_os_string())
+            }
+            Some(XattrNode::Attr(..)) => return Err(nfsstat3::NFS3ERR_NOTDIR),
+        };
+        Ok(Some(self.xattrs.id_of(node, &self.next_fileid)))
+    }
+
+    pub(crate) async fn xattr_getattr(
+        &mut self,
+        id: fileid3,
+        node: &XattrNode,
+    ) -> Result<fattr3, nfsstat3> {
+        let target = self.resolve_id(node.target()).await?;
+        let ent = self.find_entry(target)?;
+        let value_len = match node {
+            XattrNode::Attr(_, name) => {
+                let path = self.sym_to_path(&ent.name).await;
+                Some(read_value(&path, name)?.len() as u64)
+            }
+            _ => None,
+        };
+        Ok(shadow_fattr(ent.fsmeta, id, value_len))
+    }
+
+    pub(crate) async fn xattr_read(
+        &mut self,
+        node: &XattrNode,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let XattrNode::Attr(_, name) = node else {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        };
+        let path = self.xattr_target_path(node).await?;
+        let value = read_value(&path, name)?;
+        let start = offset.min(value.len() as u64) as usize;
+        let end = start.saturating_add(count as usize).min(value.len());
+        Ok((value[start..end].to_vec(), end >= value.len()))
+    }
+
+    /// Writes into an attribute value. Values are replaced as a whole on
+    /// the host, so the write is applied to the current value first.
+    pub(crate) async fn xattr_write(
+        &mut self,
+        id: fileid3,
+        node: &XattrNode,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<fattr3, nfsstat3> {
+        let XattrNode::Attr(_, name) = node else {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        };
+        let end = offset.saturating_add(data.len() as u64);
+        if end > MAX_VALUE_SIZE {
+            return Err(nfsstat3::NFS3ERR_FBIG);
+        }
+        let path = self.xattr_target_path(node).await?;
+        let mut value = read_value(&path, name)?;
+        if value.len() < end as usize {
+            value.resize(end as usize, 0);
+        }
+        value[offset as usize..end as usize].copy_from_slice(data);
+        debug!("setxattr {:?} {:?} {} bytes", path, name, value.len());
+        xattr::set(&path, name, &value).map_err(io_error_to_nfsstat3)?;
+        self.xattr_getattr(id, node).await
+    }
+
+    /// Only the size of an attribute can be set, which truncates or
+    /// extends its value. Other attributes are taken from the entry.
+    pub(crate) async fn xattr_setattr(
+        &mut self,
+        id: fileid3,
+        node: &XattrNode,
+        setattr: &sattr3,
+    ) -> Result<fattr3, nfsstat3> {
+        if let (XattrNode::Attr(_, name), set_size3::size(size)) = (node, setattr.size) {
+            if size > MAX_VALUE_SIZE {
+                return Err(nfsstat3::NFS3ERR_FBIG);
+            }
+            let path = self.xattr_target_path(node).await?;
+            let mut value = read_value(&path, name)?;
+            value.resize(size as usize, 0);
+            xattr::set(&path, name, &value).map_err(io_error_to_nfsstat3)?;
+        }
+        self.xattr_getattr(id, node).await
+    }
+
+    /// Lists a shadow directory, returning the fileid, name and attributes
+    /// of each entry and whether the end of the directory was reached.
+    pub(crate) async fn xattr_list(
+        &mut self,
+        node: &XattrNode,
+        start_after: fileid3,
+        max_entries: usize,
+    ) -> Result<(Vec<(fileid3, OsString, fattr3)>, bool), nfsstat3> {
+        let mut entries = Vec::new();
+        match node {
+            XattrNode::Dir(dir) => {
+                let dir = self.resolve_id(*dir).await?;
+                self.refresh_dir_list(dir).await?;
+                let (children, _) = self.list_dir(dir, 0, usize::MAX, |fileid, name, ent| {
+                    Some((fileid, name.to_os_string(), ent.fsmeta))
+                })?;
+                for (fileid, name, fsmeta) in children {
+                    let id = self
+                        .xattrs
+                        .id_of(XattrNode::Entry(fileid), &self.next_fileid);
+                    entries.push((id, (name, shadow_fattr(fsmeta, id, None))));
+                }
+            }
+            XattrNode::Entry(target) => {
+                let path = self.xattr_target_path(node).await?;
+                let fsmeta = self.find_entry(*target)?.fsmeta;
+                let names = xattr::list(&path).map_err(io_error_to_nfsstat3)?;
+                for name in names {
+                    // the attribute may be removed while we list
+                    let Ok(Some(value)) = xattr::get(&path, &name) else {
+                        continue;
+                    };
+                    let id = self
+                        .xattrs
+                        .id_of(XattrNode::Attr(*target, name.clone()), &self.next_fileid);
+                    let attr = shadow_fattr(fsmeta, id, Some(value.len() as u64));
+                    entries.push((id, (name, attr)));
+                }
+            }
+            XattrNode::Attr(..) => return Err(nfsstat3::NFS3ERR_NOTDIR),
+        }
+        let (page, end) = paginate(entries, start_after, max_entries);
+        let page = page
+            .into_iter()
+            .map(|(id, (name, attr))| (id, name, attr))
+            .collect();
+        Ok((page, end))
+    }
+
+    /// Creates an attribute with an empty value. Creating an attribute
+    /// which already exists keeps its value unless `exclusive` is set.
+    pub(crate) async fn xattr_create(
+        &mut self,
+        node: &XattrNode,
+        filename: &[u8],
+        exclusive: bool,
+        setattr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        let XattrNode::Entry(target) = node else {
+            return Err(nfsstat3::NFS3ERR_NOTSUPP);
+        };
+        let path = self.xattr_target_path(node).await?;
+        let name = OsStr::from_bytes(filename);
+        let existing = xattr::get(&path, name).map_err(io_error_to_nfsstat3)?;
+        match existing {
+            Some(_) if exclusive => return Err(nfsstat3::NFS3ERR_EXIST),
+            Some(_) => {}
+            None => {
+                debug!("setxattr {:?} {:?} created", path, name);
+                xattr::set(&path, name, &[]).map_err(io_error_to_nfsstat3)?;
+            }
+        }
+        let attr = XattrNode::Attr(*target, name.to_os_string());
+        let id = self.xattrs.id_of(attr.clone(), &self.next_fileid);
+        Ok((id, self.xattr_setattr(id, &attr, setattr).await?))
+    }
+
+    pub(crate) async fn xattr_remove(
+        &mut self,
+        node: &XattrNode,
+        filename: &[u8],
+    ) -> Result<(), nfsstat3> {
+        let XattrNode::Entry(target) = node else {
+            return Err(nfsstat3::NFS3ERR_NOTSUPP);
+        };
+        let path = self.xattr_target_path(node).await?;
+        let name = OsStr::from_bytes(filename);
+        if xattr::get(&path, name)
+            .map_err(io_error_to_nfsstat3)?
+            .is_none()
+        {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+        debug!("removexattr {:?} {:?}", path, name);
+        xattr::remove(&path, name).map_err(io_error_to_nfsstat3)?;
+        self.xattrs
+            .forget(&XattrNode::Attr(*target, name.to_os_string()));
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use std::sync::Mutex;
 use std::time::SystemTime;
 
 use async_trait::async_trait;
+use bytes::Bytes;
 
 use nfsserve::{
     nfs::{
@@ -266,7 +267,7 @@ impl NFSFileSystem for DemoFS {
         id: fileid3,
         offset: u64,
         count: u32,
-    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+    ) -> Result<(Bytes, bool), nfsstat3> {
         let fs = self.fs.lock().unwrap();
         let entry = fs.get(id as usize).ok_or(nfsstat3::NFS3ERR_NOENT)?;
         if let FSContents::Directory(_) = entry.contents {
@@ -281,7 +282,7 @@ impl NFSFileSystem for DemoFS {
             if end > bytes.len() {
                 end = bytes.len();
             }
-            return Ok((bytes[start..end].to_vec(), eof));
+            return Ok((Bytes::copy_from_slice(&bytes[start..end]), eof));
         }
         Err(nfsstat3::NFS3ERR_NOENT)
     }
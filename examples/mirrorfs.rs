@@ -8,6 +8,7 @@ use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use async_trait::async_trait;
+use bytes::Bytes;
 use intaglio::osstr::SymbolTable;
 use intaglio::Symbol;
 use tokio::fs::{File, OpenOptions};
@@ -202,7 +203,7 @@ impl FSMap {
             self.id_to_path
                 .get_mut(&id)
                 .ok_or(nfsstat3::NFS3ERR_NOENT)?
-                .children = Some(BTreeSet::from_iter(new_children.into_iter()));
+                .children = Some(BTreeSet::from_iter(new_children));
         }
 
         Ok(())
@@ -239,8 +240,8 @@ pub struct MirrorFS {
 
 /// Enumeration for the create_fs_object method
 enum CreateFSObject {
-    /// Creates a directory
-    Directory,
+    /// Creates a directory with a set of attributes
+    Directory(sattr3),
     /// Creates a file with a set of attributes
     File(sattr3),
     /// Creates an exclusive file with a set of attributes
@@ -263,6 +264,7 @@ impl MirrorFS {
         objectname: &filename3,
         object: &CreateFSObject,
     ) -> Result<(fileid3, fattr3), nfsstat3> {
+        check_filename(objectname)?;
         let mut fsmap = self.fsmap.lock().await;
         let ent = fsmap.find_entry(dirid)?;
         let mut path = fsmap.sym_to_path(&ent.name).await;
@@ -270,7 +272,7 @@ impl MirrorFS {
         path.push(&objectname_osstr);
 
         match object {
-            CreateFSObject::Directory => {
+            CreateFSObject::Directory(setattr) => {
                 debug!("mkdir {:?}", path);
                 if exists_no_traverse(&path) {
                     return Err(nfsstat3::NFS3ERR_EXIST);
@@ -278,6 +280,7 @@ impl MirrorFS {
                 tokio::fs::create_dir(&path)
                     .await
                     .map_err(|_| nfsstat3::NFS3ERR_IO)?;
+                let _ = create_setattr(&path, setattr);
             }
             CreateFSObject::File(setattr) => {
                 debug!("create {:?}", path);
@@ -382,7 +385,7 @@ impl NFSFileSystem for MirrorFS {
         id: fileid3,
         offset: u64,
         count: u32,
-    ) -> Result<(Vec<u8>, bool), nfsstat3> {
+    ) -> Result<(Bytes, bool), nfsstat3> {
         let fsmap = self.fsmap.lock().await;
         let ent = fsmap.find_entry(id)?;
         let path = fsmap.sym_to_path(&ent.name).await;
@@ -403,7 +406,7 @@ impl NFSFileSystem for MirrorFS {
             .or(Err(nfsstat3::NFS3ERR_IO))?;
         let mut buf = vec![0; (end - start) as usize];
         f.read_exact(&mut buf).await.or(Err(nfsstat3::NFS3ERR_IO))?;
-        Ok((buf, eof))
+        Ok((buf.into(), eof))
     }
 
     async fn readdir(
@@ -528,6 +531,7 @@ impl NFSFileSystem for MirrorFS {
     }
 
     async fn remove(&self, dirid: fileid3, filename: &filename3) -> Result<(), nfsstat3> {
+        check_filename(filename)?;
         let mut fsmap = self.fsmap.lock().await;
         let ent = fsmap.find_entry(dirid)?;
         let mut path = fsmap.sym_to_path(&ent.name).await;
@@ -577,6 +581,8 @@ impl NFSFileSystem for MirrorFS {
         to_dirid: fileid3,
         to_filename: &filename3,
     ) -> Result<(), nfsstat3> {
+        check_filename(from_filename)?;
+        check_filename(to_filename)?;
         let mut fsmap = self.fsmap.lock().await;
 
         let from_dirent = fsmap.find_entry(from_dirid)?;
@@ -646,7 +652,17 @@ impl NFSFileSystem for MirrorFS {
         dirid: fileid3,
         dirname: &filename3,
     ) -> Result<(fileid3, fattr3), nfsstat3> {
-        self.create_fs_object(dirid, dirname, &CreateFSObject::Directory)
+        self.mkdir_with_attrs(dirid, dirname, &sattr3::default())
+            .await
+    }
+
+    async fn mkdir_with_attrs(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+        attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.create_fs_object(dirid, dirname, &CreateFSObject::Directory(*attr))
             .await
     }
 
@@ -0,0 +1,173 @@
+//! Which client addresses may use the server.
+//!
+//! NFS has no transport security of its own, so a listener can be limited
+//! to the networks its clients are on with an [`AccessList`].
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A network in CIDR notation, for instance `10.0.0.0/8` or `fd00::/8`.
+/// A plain address is a network of that address alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Whether `ip` is within the network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(net).into(), u32::from(ip).into(), 32, self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(net), u128::from(ip), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether the top `prefix` of `bits` bits of `net` and `ip` agree
+fn prefix_matches(net: u128, ip: u128, bits: u8, prefix: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - prefix;
+    (net >> shift) == (ip >> shift)
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| format!("invalid address in {:?}", s))?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("invalid prefix length in {:?}", s))?,
+            None => bits,
+        };
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Networks allowed and denied to use the server.
+///
+/// A client is let in if its address is in none of the denied networks
+/// and, unless no networks are allowed explicitly, in one of the allowed
+/// ones. The default list lets everyone in.
+#[derive(Clone, Debug, Default)]
+pub struct AccessList {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+impl AccessList {
+    /// Whether a client at `ip` may use the server
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|net| net.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_networks_and_plain_addresses() {
+        assert_eq!(cidr("10.0.0.0/8").to_string(), "10.0.0.0/8");
+        assert_eq!(cidr("192.168.1.7").to_string(), "192.168.1.7/32");
+        assert_eq!(cidr("fd00::/8").to_string(), "fd00::/8");
+        assert_eq!(cidr("::1").to_string(), "::1/128");
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("fd00::/129".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("example.com".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn contains_addresses_within_the_prefix() {
+        let net = cidr("10.1.0.0/16");
+        assert!(net.contains(ip("10.1.0.0")));
+        assert!(net.contains(ip("10.1.255.255")));
+        assert!(!net.contains(ip("10.2.0.0")));
+        assert!(!net.contains(ip("10.0.255.255")));
+        assert!(cidr("10.1.2.3").contains(ip("10.1.2.3")));
+        assert!(!cidr("10.1.2.3").contains(ip("10.1.2.4")));
+        assert!(cidr("fd00::/8").contains(ip("fdab::1")));
+        assert!(!cidr("fd00::/8").contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn zero_prefix_contains_its_whole_family() {
+        assert!(cidr("0.0.0.0/0").contains(ip("203.0.113.9")));
+        assert!(cidr("::/0").contains(ip("2001:db8::1")));
+        assert!(!cidr("0.0.0.0/0").contains(ip("2001:db8::1")));
+        assert!(!cidr("::/0").contains(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn v4_mapped_v6_addresses_are_matched_as_v4() {
+        assert!(cidr("10.0.0.0/8").contains(ip("::ffff:10.1.2.3")));
+        assert!(!cidr("10.0.0.0/8").contains(ip("::ffff:11.1.2.3")));
+        assert!(cidr("127.0.0.1").contains(ip("::ffff:127.0.0.1")));
+    }
+
+    #[test]
+    fn default_list_permits_everyone() {
+        let access = AccessList::default();
+        assert!(access.permits(ip("203.0.113.9")));
+        assert!(access.permits(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn deny_is_checked_before_allow() {
+        let access = AccessList {
+            allow: vec![cidr("10.0.0.0/8")],
+            deny: vec![cidr("10.1.0.0/16")],
+        };
+        assert!(access.permits(ip("10.2.3.4")));
+        assert!(!access.permits(ip("10.1.3.4")));
+        assert!(!access.permits(ip("::ffff:10.1.3.4")));
+        assert!(!access.permits(ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn deny_alone_permits_everyone_else() {
+        let access = AccessList {
+            allow: Vec::new(),
+            deny: vec![cidr("192.168.0.0/16")],
+        };
+        assert!(!access.permits(ip("192.168.1.1")));
+        assert!(access.permits(ip("10.0.0.1")));
+    }
+}
@@ -0,0 +1,128 @@
+//! Credentials of the client on whose behalf a call is made.
+//!
+//! The uid and gid a client sends with AUTH_SYS (AUTH_UNIX) are mapped by
+//! the listener's [`IdMapping`] and made available to the file system for
+//! the duration of the call through [`caller`], together with the address
+//! of the client through [`client_ip`].
+use crate::nfs::fattr3;
+use crate::rpc::auth_unix;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// The uid nobody, given to squashed and anonymous callers by default
+pub const NOBODY: u32 = 65534;
+
+/// Permission bits, as taken by [`Credentials::may`]
+pub const MAY_READ: u32 = 4;
+pub const MAY_WRITE: u32 = 2;
+pub const MAY_EXEC: u32 = 1;
+
+/// The uid and groups a call is made with
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups
+    pub gids: Vec<u32>,
+}
+
+/// Mapping applied to the credentials of every call before the file
+/// system sees them.
+///
+/// The default mapping passes credentials through unchanged. Calls without
+/// AUTH_SYS credentials are always made as the anonymous user.
+#[derive(Clone, Debug)]
+pub struct IdMapping {
+    /// Makes calls by uid 0 as the anonymous user
+    pub root_squash: bool,
+    /// Makes every call as the anonymous user
+    pub all_squash: bool,
+    pub anon_uid: u32,
+    pub anon_gid: u32,
+    /// Client uids replaced by host uids
+    pub uids: HashMap<u32, u32>,
+    /// Client gids replaced by host gids
+    pub gids: HashMap<u32, u32>,
+}
+
+impl Default for IdMapping {
+    fn default() -> Self {
+        Self {
+            root_squash: false,
+            all_squash: false,
+            anon_uid: NOBODY,
+            anon_gid: NOBODY,
+            uids: HashMap::new(),
+            gids: HashMap::new(),
+        }
+    }
+}
+
+impl IdMapping {
+    fn anonymous(&self) -> Credentials {
+        Credentials {
+            uid: self.anon_uid,
+            gid: self.anon_gid,
+            gids: Vec::new(),
+        }
+    }
+
+    /// Maps the AUTH_SYS credentials of a call, if it has any
+    pub(crate) fn map(&self, auth: Option<&auth_unix>) -> Credentials {
+        let Some(auth) = auth else {
+            return self.anonymous();
+        };
+        if self.all_squash || (self.root_squash && auth.uid == 0) {
+            return self.anonymous();
+        }
+        let gid = |gid: &u32| *self.gids.get(gid).unwrap_or(gid);
+        Credentials {
+            uid: *self.uids.get(&auth.uid).unwrap_or(&auth.uid),
+            gid: gid(&auth.gid),
+            gids: auth.gids.iter().map(gid).collect(),
+        }
+    }
+}
+
+impl Credentials {
+    /// Whether the mode bits of `attr` grant all of `want`, a combination of
+    /// MAY_READ, MAY_WRITE and MAY_EXEC. uid 0 is granted anything.
+    pub fn may(&self, attr: &fattr3, want: u32) -> bool {
+        if self.uid == 0 {
+            return true;
+        }
+        let shift = if attr.uid == self.uid {
+            6
+        } else if attr.gid == self.gid || self.gids.contains(&attr.gid) {
+            3
+        } else {
+            0
+        };
+        (attr.mode >> shift) & want == want
+    }
+}
+
+tokio::task_local! {
+    static CALLER: Credentials;
+    static CLIENT_IP: Option<IpAddr>;
+}
+
+/// The mapped credentials of the call being served, if any
+pub fn caller() -> Option<Credentials> {
+    CALLER.try_with(|caller| caller.clone()).ok()
+}
+
+/// The address of the client whose call is being served, if known
+pub fn client_ip() -> Option<IpAddr> {
+    CLIENT_IP.try_with(|ip| *ip).ok().flatten()
+}
+
+/// Serves `f` on behalf of `caller`, a client at `client_addr`
+pub(crate) async fn serve_as<F: std::future::Future>(
+    caller: Credentials,
+    client_addr: &str,
+    f: F,
+) -> F::Output {
+    let ip = client_addr.parse::<SocketAddr>().ok().map(|addr| addr.ip());
+    CLIENT_IP.scope(ip, CALLER.scope(caller, f)).await
+}
@@ -0,0 +1,119 @@
+//! Calls the server makes to clients, as the lock manager does to tell a
+//! client a lock it waited for is held.
+//!
+//! Calls are made over UDP, to the port the rpcbind of the client tells,
+//! and sent again until a reply comes.
+
+use crate::portmap;
+use crate::rpc::*;
+use crate::xdr::*;
+use std::io;
+use std::io::Cursor;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::{timeout_at, Instant};
+
+/// How long a reply is waited for before the call is sent again
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many times a call is sent before giving up on a reply
+const TRIES: usize = 3;
+
+/// The port of rpcbind
+const RPCBIND_PORT: u16 = 111;
+
+/// Calls `proc` of version `vers` of the program `prog` served at `addr`
+/// with `args`, returning the result
+pub async fn call<A: XDR, R: XDR + Default>(
+    addr: SocketAddr,
+    prog: u32,
+    vers: u32,
+    proc: u32,
+    args: &A,
+) -> io::Result<R> {
+    let socket = match addr {
+        SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0").await?,
+        SocketAddr::V6(_) => UdpSocket::bind("[::]:0").await?,
+    };
+    let xid = rand::random::<u32>();
+    let msg = rpc_msg {
+        xid,
+        body: rpc_body::CALL(call_body {
+            rpcvers: 2,
+            prog,
+            vers,
+            proc,
+            cred: opaque_auth::default(),
+            verf: opaque_auth::default(),
+        }),
+    };
+    let mut message = Vec::new();
+    msg.serialize(&mut message)?;
+    args.serialize(&mut message)?;
+
+    let mut buf = vec![0; 65536];
+    for _ in 0..TRIES {
+        socket.send_to(&message, addr).await?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        // anything but the reply to this call is ignored
+        while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            let (len, from) = received?;
+            if from != addr {
+                continue;
+            }
+            let mut reply = Cursor::new(&buf[..len]);
+            let mut msg = rpc_msg::default();
+            msg.deserialize(&mut reply)?;
+            if msg.xid != xid {
+                continue;
+            }
+            return match msg.body {
+                rpc_body::REPLY(reply_body::MSG_ACCEPTED(accepted_reply {
+                    reply_data: accept_body::SUCCESS,
+                    ..
+                })) => {
+                    let mut res = R::default();
+                    res.deserialize(&mut reply)?;
+                    Ok(res)
+                }
+                body => Err(io::Error::other(format!(
+                    "call to {} refused: {:?}",
+                    addr, body
+                ))),
+            };
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("no reply from {}", addr),
+    ))
+}
+
+/// The port version `vers` of the program `prog` is served on over UDP
+/// by `host`, as its rpcbind tells
+pub async fn port_of(host: IpAddr, prog: u32, vers: u32) -> io::Result<u16> {
+    const PMAPPROC_GETPORT: u32 = 3;
+    let mapping = portmap::mapping {
+        prog,
+        vers,
+        prot: portmap::IPPROTO_UDP,
+        port: 0,
+    };
+    let rpcbind = SocketAddr::new(host, RPCBIND_PORT);
+    let port: u32 = call(
+        rpcbind,
+        portmap::PROGRAM,
+        portmap::VERSION,
+        PMAPPROC_GETPORT,
+        &mapping,
+    )
+    .await?;
+    match u16::try_from(port) {
+        Ok(port) if port != 0 => Ok(port),
+        _ => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not serve program {} version {}", host, prog, vers),
+        )),
+    }
+}
@@ -9,10 +9,20 @@ pub struct RPCContext {
     pub local_port: u16,
     pub client_addr: String,
     pub auth: crate::rpc::auth_unix,
+    /// The credentials of the call after the id mapping
+    pub caller: crate::auth::Credentials,
+    pub id_mapping: Arc<crate::auth::IdMapping>,
     pub vfs: Arc<dyn NFSFileSystem + Send + Sync>,
     pub mount_signal: Option<mpsc::Sender<bool>>,
     pub export_name: Arc<String>,
     pub transaction_tracker: Arc<TransactionTracker>,
+    /// Holds the client to its rate limit
+    pub throttle: Arc<crate::throttle::Throttle>,
+    /// The advisory locks clients hold on the files
+    pub locks: Arc<crate::locks::LockManager>,
+    /// The most data a READ or READDIR reply may carry, if the transport
+    /// limits the size of replies as a UDP datagram does
+    pub max_transfer: Option<u32>,
 }
 
 impl fmt::Debug for RPCContext {
@@ -21,6 +31,8 @@ impl fmt::Debug for RPCContext {
             .field("local_port", &self.local_port)
             .field("client_addr", &self.client_addr)
             .field("auth", &self.auth)
+            .field("caller", &self.caller)
+            .field("max_transfer", &self.max_transfer)
             .finish()
     }
 }
@@ -3,7 +3,7 @@ use std::fs::Metadata;
 use std::fs::Permissions;
 
 #[cfg(unix)]
-use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
 use std::path::Path;
 use tokio::fs::OpenOptions;
 use tracing::debug;
@@ -21,6 +21,7 @@ pub fn fattr3_differ(lhs: &fattr3, rhs: &fattr3) -> bool {
         || lhs.mtime.seconds != rhs.mtime.seconds
         || lhs.mtime.nseconds != rhs.mtime.nseconds
         || lhs.size != rhs.size
+        || lhs.nlink != rhs.nlink
         || lhs.ftype as u32 != rhs.ftype as u32
 }
 
@@ -31,6 +32,44 @@ pub fn exists_no_traverse(path: &Path) -> bool {
     path.symlink_metadata().is_ok()
 }
 
+/// Checks that a name a client gave is that of an entry in the directory
+/// it was given with. A name which is a path, such as `../../etc/passwd`,
+/// would otherwise reach out of the export once joined to the directory.
+pub fn check_filename(name: &filename3) -> Result<(), nfsstat3> {
+    match &name[..] {
+        b"" | b"." | b".." => Err(nfsstat3::NFS3ERR_INVAL),
+        name if name.contains(&b'/') || name.contains(&0) => Err(nfsstat3::NFS3ERR_ACCES),
+        _ => Ok(()),
+    }
+}
+
+/// Translates a host io::Error to the closest NFS status so that clients
+/// see e.g. a full disk as NFS3ERR_NOSPC rather than a generic NFS3ERR_IO
+pub fn io_error_to_nfsstat3(err: std::io::Error) -> nfsstat3 {
+    use std::io::ErrorKind;
+    match err.kind() {
+        ErrorKind::NotFound => nfsstat3::NFS3ERR_NOENT,
+        ErrorKind::PermissionDenied => nfsstat3::NFS3ERR_ACCES,
+        ErrorKind::AlreadyExists => nfsstat3::NFS3ERR_EXIST,
+        ErrorKind::StorageFull => nfsstat3::NFS3ERR_NOSPC,
+        ErrorKind::QuotaExceeded => nfsstat3::NFS3ERR_DQUOT,
+        ErrorKind::DirectoryNotEmpty => nfsstat3::NFS3ERR_NOTEMPTY,
+        ErrorKind::InvalidFilename => nfsstat3::NFS3ERR_NAMETOOLONG,
+        ErrorKind::ReadOnlyFilesystem => nfsstat3::NFS3ERR_ROFS,
+        ErrorKind::NotADirectory => nfsstat3::NFS3ERR_NOTDIR,
+        ErrorKind::IsADirectory => nfsstat3::NFS3ERR_ISDIR,
+        ErrorKind::FileTooLarge => nfsstat3::NFS3ERR_FBIG,
+        ErrorKind::CrossesDevices => nfsstat3::NFS3ERR_XDEV,
+        ErrorKind::TooManyLinks => nfsstat3::NFS3ERR_MLINK,
+        ErrorKind::StaleNetworkFileHandle => nfsstat3::NFS3ERR_STALE,
+        ErrorKind::Unsupported => nfsstat3::NFS3ERR_NOTSUPP,
+        _ => {
+            debug!("Unmapped io error {:?}", err);
+            nfsstat3::NFS3ERR_IO
+        }
+    }
+}
+
 fn mode_unmask(mode: u32) -> u32 {
     // it is possible to create a file we cannot write to.
     // we force writable always.
@@ -43,81 +82,46 @@ fn mode_unmask(mode: u32) -> u32 {
 pub fn metadata_to_fattr3(fid: fileid3, meta: &Metadata) -> fattr3 {
     let size = meta.size();
     let file_mode = mode_unmask(meta.mode());
-    if meta.is_file() {
-        fattr3 {
-            ftype: ftype3::NF3REG,
-            mode: file_mode,
-            nlink: 1,
-            uid: meta.uid(),
-            gid: meta.gid(),
-            size,
-            used: size,
-            rdev: specdata3::default(),
-            fsid: 0,
-            fileid: fid,
-            atime: nfstime3 {
-                seconds: meta.atime() as u32,
-                nseconds: meta.atime_nsec() as u32,
-            },
-            mtime: nfstime3 {
-                seconds: meta.mtime() as u32,
-                nseconds: meta.mtime_nsec() as u32,
-            },
-            ctime: nfstime3 {
-                seconds: meta.ctime() as u32,
-                nseconds: meta.ctime_nsec() as u32,
-            },
-        }
-    } else if meta.is_symlink() {
-        fattr3 {
-            ftype: ftype3::NF3LNK,
-            mode: file_mode,
-            nlink: 1,
-            uid: meta.uid(),
-            gid: meta.gid(),
-            size,
-            used: size,
-            rdev: specdata3::default(),
-            fsid: 0,
-            fileid: fid,
-            atime: nfstime3 {
-                seconds: meta.atime() as u32,
-                nseconds: meta.atime_nsec() as u32,
-            },
-            mtime: nfstime3 {
-                seconds: meta.mtime() as u32,
-                nseconds: meta.mtime_nsec() as u32,
-            },
-            ctime: nfstime3 {
-                seconds: meta.ctime() as u32,
-                nseconds: meta.ctime_nsec() as u32,
-            },
-        }
+    let file_type = meta.file_type();
+    let ftype = if file_type.is_file() {
+        ftype3::NF3REG
+    } else if file_type.is_symlink() {
+        ftype3::NF3LNK
+    } else if file_type.is_fifo() {
+        ftype3::NF3FIFO
+    } else if file_type.is_socket() {
+        ftype3::NF3SOCK
+    } else if file_type.is_char_device() {
+        ftype3::NF3CHR
+    } else if file_type.is_block_device() {
+        ftype3::NF3BLK
     } else {
-        fattr3 {
-            ftype: ftype3::NF3DIR,
-            mode: file_mode,
-            nlink: 2,
-            uid: meta.uid(),
-            gid: meta.gid(),
-            size,
-            used: size,
-            rdev: specdata3::default(),
-            fsid: 0,
-            fileid: fid,
-            atime: nfstime3 {
-                seconds: meta.atime() as u32,
-                nseconds: meta.atime_nsec() as u32,
-            },
-            mtime: nfstime3 {
-                seconds: meta.mtime() as u32,
-                nseconds: meta.mtime_nsec() as u32,
-            },
-            ctime: nfstime3 {
-                seconds: meta.ctime() as u32,
-                nseconds: meta.ctime_nsec() as u32,
-            },
-        }
+        ftype3::NF3DIR
+    };
+    fattr3 {
+        ftype,
+        mode: file_mode,
+        nlink: meta.nlink() as u32,
+        uid: meta.uid(),
+        gid: meta.gid(),
+        size,
+        // what is on disk, which is less for sparse files
+        used: meta.blocks() * 512,
+        rdev: specdata3::default(),
+        fsid: 0,
+        fileid: fid,
+        atime: nfstime3 {
+            seconds: meta.atime() as u32,
+            nseconds: meta.atime_nsec() as u32,
+        },
+        mtime: nfstime3 {
+            seconds: meta.mtime() as u32,
+            nseconds: meta.mtime_nsec() as u32,
+        },
+        ctime: nfstime3 {
+            seconds: meta.ctime() as u32,
+            nseconds: meta.ctime_nsec() as u32,
+        },
     }
 }
 
@@ -159,9 +163,72 @@ pub async fn path_setattr(path: &Path, setattr: &sattr3) -> Result<(), nfsstat3>
             .truncate(false)
             .open(path)
             .await
-            .or(Err(nfsstat3::NFS3ERR_IO))?;
+            .map_err(io_error_to_nfsstat3)?;
         debug!(" -- set size {:?} {:?}", path, size3);
-        file.set_len(size3).await.or(Err(nfsstat3::NFS3ERR_IO))?;
+        file.set_len(size3).await.map_err(io_error_to_nfsstat3)?;
+    }
+    Ok(())
+}
+
+/// Sets the attributes requested for an object just created at `path`,
+/// without following it if it is a symlink. The owner and group are only
+/// changed where the server is permitted to give the object away, and
+/// are left as they are otherwise. The mode is not set on symlinks and
+/// the size only on regular files. Times are set last, so that setting
+/// the size does not change them again.
+pub fn create_setattr(path: &Path, setattr: &sattr3) -> Result<(), nfsstat3> {
+    let meta = path.symlink_metadata().map_err(io_error_to_nfsstat3)?;
+    let uid = match setattr.uid {
+        set_uid3::uid(uid) => Some(uid),
+        set_uid3::Void => None,
+    };
+    let gid = match setattr.gid {
+        set_gid3::gid(gid) => Some(gid),
+        set_gid3::Void => None,
+    };
+    if uid.is_some() || gid.is_some() {
+        match std::os::unix::fs::lchown(path, uid, gid) {
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                debug!(" -- not permitted to give {:?} to {:?}:{:?}", path, uid, gid);
+            }
+            res => res.map_err(io_error_to_nfsstat3)?,
+        }
+    }
+    if let set_mode3::mode(mode) = setattr.mode {
+        if !meta.file_type().is_symlink() {
+            debug!(" -- set permissions {:?} {:?}", path, mode);
+            std::fs::set_permissions(path, Permissions::from_mode(mode_unmask(mode)))
+                .map_err(io_error_to_nfsstat3)?;
+        }
+    }
+    if let set_size3::size(size) = setattr.size {
+        if meta.is_file() {
+            debug!(" -- set size {:?} {:?}", path, size);
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_len(size))
+                .map_err(io_error_to_nfsstat3)?;
+        }
+    }
+    let atime = match setattr.atime {
+        set_atime::SET_TO_SERVER_TIME => Some(filetime::FileTime::now()),
+        set_atime::SET_TO_CLIENT_TIME(time) => Some(time.into()),
+        set_atime::DONT_CHANGE => None,
+    };
+    let mtime = match setattr.mtime {
+        set_mtime::SET_TO_SERVER_TIME => Some(filetime::FileTime::now()),
+        set_mtime::SET_TO_CLIENT_TIME(time) => Some(time.into()),
+        set_mtime::DONT_CHANGE => None,
+    };
+    if atime.is_some() || mtime.is_some() {
+        let meta = path.symlink_metadata().map_err(io_error_to_nfsstat3)?;
+        filetime::set_symlink_file_times(
+            path,
+            atime.unwrap_or_else(|| filetime::FileTime::from_last_access_time(&meta)),
+            mtime.unwrap_or_else(|| filetime::FileTime::from_last_modification_time(&meta)),
+        )
+        .map_err(io_error_to_nfsstat3)?;
     }
     Ok(())
 }
@@ -175,7 +242,7 @@ pub async fn file_setattr(file: &std::fs::File, setattr: &sattr3) -> Result<(),
     }
     if let set_size3::size(size3) = setattr.size {
         debug!(" -- set size {:?}", size3);
-        file.set_len(size3).or(Err(nfsstat3::NFS3ERR_IO))?;
+        file.set_len(size3).map_err(io_error_to_nfsstat3)?;
     }
     Ok(())
 }
@@ -0,0 +1,678 @@
+//! Serves a NFSFileSystem through FUSE, mounted on the local machine.
+//!
+//! Where running a NFS client is inconvenient, in containers or on CI
+//! runners, the same file system can be mounted directly: FuseMount is a
+//! [`fuser::Filesystem`] which turns every request of the kernel into the
+//! NFSFileSystem call a NFS client would have made. Calls are served on
+//! behalf of the local user making them, after the same uid mapping as
+//! over NFS, and the kernel checks permissions against the mode bits.
+//!
+//! Root mounts the file system itself. Everyone else mounts it through
+//! the setuid `fusermount` helper, as any FUSE file system, which takes
+//! no privileges beyond access to `/dev/fuse`.
+use std::ffi::OsStr;
+use std::future::Future;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fuser::consts::FOPEN_DIRECT_IO;
+use fuser::{
+    FileAttr, FileType, Filesystem, KernelConfig, MountOption, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite, Request, Session,
+    TimeOrNow, FUSE_ROOT_ID,
+};
+use tracing::{debug, info, warn};
+
+use crate::auth::IdMapping;
+use crate::nfs::*;
+use crate::rpc::auth_unix;
+use crate::vfs::NFSFileSystem;
+
+/// The largest write the kernel sends at once
+const MAX_WRITE: u32 = 128 * 1024;
+
+/// Entries asked of the file system for every READDIR of the kernel
+const READDIR_ENTRIES: usize = 128;
+
+/// How long the kernel may cache entries and attributes: not at all, so
+/// that every caller is shown what is there for them
+const TTL: Duration = Duration::ZERO;
+
+/// The errno a NFS status stands for
+fn errno(stat: nfsstat3) -> i32 {
+    match stat {
+        nfsstat3::NFS3_OK => 0,
+        nfsstat3::NFS3ERR_PERM => libc::EPERM,
+        nfsstat3::NFS3ERR_NOENT => libc::ENOENT,
+        nfsstat3::NFS3ERR_NXIO => libc::ENXIO,
+        nfsstat3::NFS3ERR_ACCES => libc::EACCES,
+        nfsstat3::NFS3ERR_EXIST => libc::EEXIST,
+        nfsstat3::NFS3ERR_XDEV => libc::EXDEV,
+        nfsstat3::NFS3ERR_NODEV => libc::ENODEV,
+        nfsstat3::NFS3ERR_NOTDIR => libc::ENOTDIR,
+        nfsstat3::NFS3ERR_ISDIR => libc::EISDIR,
+        nfsstat3::NFS3ERR_INVAL => libc::EINVAL,
+        nfsstat3::NFS3ERR_FBIG => libc::EFBIG,
+        nfsstat3::NFS3ERR_NOSPC => libc::ENOSPC,
+        nfsstat3::NFS3ERR_ROFS => libc::EROFS,
+        nfsstat3::NFS3ERR_MLINK => libc::EMLINK,
+        nfsstat3::NFS3ERR_NAMETOOLONG => libc::ENAMETOOLONG,
+        nfsstat3::NFS3ERR_NOTEMPTY => libc::ENOTEMPTY,
+        nfsstat3::NFS3ERR_DQUOT => libc::EDQUOT,
+        nfsstat3::NFS3ERR_STALE => libc::ESTALE,
+        nfsstat3::NFS3ERR_NOTSUPP => libc::EOPNOTSUPP,
+        nfsstat3::NFS3ERR_JUKEBOX => libc::EAGAIN,
+        _ => libc::EIO,
+    }
+}
+
+/// The type of a file, as FUSE tells it
+fn file_type(ftype: ftype3) -> FileType {
+    match ftype {
+        ftype3::NF3REG => FileType::RegularFile,
+        ftype3::NF3DIR => FileType::Directory,
+        ftype3::NF3BLK => FileType::BlockDevice,
+        ftype3::NF3CHR => FileType::CharDevice,
+        ftype3::NF3LNK => FileType::Symlink,
+        ftype3::NF3SOCK => FileType::Socket,
+        ftype3::NF3FIFO => FileType::NamedPipe,
+    }
+}
+
+fn system_time(time: nfstime3) -> SystemTime {
+    UNIX_EPOCH + Duration::new(time.seconds as u64, time.nseconds)
+}
+
+fn nfs_time(time: SystemTime) -> nfstime3 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    nfstime3 {
+        seconds: since.as_secs() as u32,
+        nseconds: since.subsec_nanos(),
+    }
+}
+
+/// The attributes of the object `ino`, as FUSE tells them
+fn file_attr(ino: u64, attr: &fattr3) -> FileAttr {
+    FileAttr {
+        ino,
+        size: attr.size,
+        blocks: attr.used.div_ceil(512),
+        atime: system_time(attr.atime),
+        mtime: system_time(attr.mtime),
+        ctime: system_time(attr.ctime),
+        crtime: system_time(attr.ctime),
+        kind: file_type(attr.ftype),
+        perm: (attr.mode & 0o7777) as u16,
+        nlink: attr.nlink,
+        uid: attr.uid,
+        gid: attr.gid,
+        rdev: libc::makedev(attr.rdev.specdata1, attr.rdev.specdata2) as u32,
+        blksize: 4096,
+        flags: 0,
+    }
+}
+
+fn filename(name: &OsStr) -> filename3 {
+    name.as_bytes().to_vec().into()
+}
+
+/// The attributes a new object is created with, of the mode asked for
+fn create_mode(mode: u32, umask: u32) -> sattr3 {
+    sattr3 {
+        mode: set_mode3::mode(mode & !umask & 0o7777),
+        ..Default::default()
+    }
+}
+
+/// The supplementary groups of the process making a call, which FUSE
+/// does not pass along
+fn groups(pid: u32) -> Vec<u32> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .map(|groups| {
+            groups
+                .split_whitespace()
+                .filter_map(|gid| gid.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The root is inode 1, and every other fileid keeps its place around it
+#[derive(Debug, Clone, Copy)]
+struct Inodes {
+    root: fileid3,
+}
+
+impl Inodes {
+    fn ino(self, id: fileid3) -> u64 {
+        id.wrapping_sub(self.root).wrapping_add(FUSE_ROOT_ID)
+    }
+
+    fn id(self, ino: u64) -> fileid3 {
+        ino.wrapping_sub(FUSE_ROOT_ID).wrapping_add(self.root)
+    }
+
+    fn entry(self, reply: ReplyEntry, created: Result<(fileid3, fattr3), nfsstat3>) {
+        match created {
+            Ok((id, attr)) => reply.entry(&TTL, &file_attr(self.ino(id), &attr), 0),
+            Err(stat) => reply.error(errno(stat)),
+        }
+    }
+
+    fn attr(self, reply: ReplyAttr, id: fileid3, attr: Result<fattr3, nfsstat3>) {
+        match attr {
+            Ok(attr) => reply.attr(&TTL, &file_attr(self.ino(id), &attr)),
+            Err(stat) => reply.error(errno(stat)),
+        }
+    }
+}
+
+fn empty(reply: ReplyEmpty, result: Result<(), nfsstat3>) {
+    match result {
+        Ok(()) => reply.ok(),
+        Err(stat) => reply.error(errno(stat)),
+    }
+}
+
+/// The requests of the kernel, served by the file system on the runtime
+/// the mount was made from
+struct Adapter<T: NFSFileSystem + Send + Sync + 'static> {
+    fs: Arc<T>,
+    inodes: Inodes,
+    id_mapping: Arc<IdMapping>,
+    runtime: tokio::runtime::Handle,
+}
+
+impl<T: NFSFileSystem + Send + Sync + 'static> Adapter<T> {
+    /// Serves `call` on behalf of the local user making `req`, who is
+    /// replied to by the call itself once it is done
+    fn serve<F, Fut>(&self, req: &Request<'_>, call: F)
+    where
+        F: FnOnce(Arc<T>, Inodes) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut auth = auth_unix::default();
+        (auth.uid, auth.gid, auth.gids) = (req.uid(), req.gid(), groups(req.pid()));
+        let caller = self.id_mapping.map(Some(&auth));
+        let served = call(self.fs.clone(), self.inodes);
+        self.runtime
+            .spawn(crate::auth::serve_as(caller, "", served));
+    }
+}
+
+impl<T: NFSFileSystem + Send + Sync + 'static> Filesystem for Adapter<T> {
+    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), i32> {
+        if let Err(max) = config.set_max_write(MAX_WRITE) {
+            debug!("The kernel writes at most {} bytes at once", max);
+        }
+        Ok(())
+    }
+
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = filename(name);
+        self.serve(req, |fs, inodes| async move {
+            let found = async {
+                let id = fs.lookup(inodes.id(parent), &name).await?;
+                Ok((id, fs.getattr(id).await?))
+            };
+            inodes.entry(reply, found.await);
+        });
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        self.serve(req, |fs, inodes| async move {
+            let id = inodes.id(ino);
+            inodes.attr(reply, id, fs.getattr(id).await);
+        });
+    }
+
+    fn setattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let setattr = sattr3 {
+            mode: mode.map_or(set_mode3::Void, |mode| set_mode3::mode(mode & 0o7777)),
+            uid: uid.map_or(set_uid3::Void, set_uid3::uid),
+            gid: gid.map_or(set_gid3::Void, set_gid3::gid),
+            size: size.map_or(set_size3::Void, set_size3::size),
+            atime: match atime {
+                None => set_atime::DONT_CHANGE,
+                Some(TimeOrNow::Now) => set_atime::SET_TO_SERVER_TIME,
+                Some(TimeOrNow::SpecificTime(time)) => {
+                    set_atime::SET_TO_CLIENT_TIME(nfs_time(time))
+                }
+            },
+            mtime: match mtime {
+                None => set_mtime::DONT_CHANGE,
+                Some(TimeOrNow::Now) => set_mtime::SET_TO_SERVER_TIME,
+                Some(TimeOrNow::SpecificTime(time)) => {
+                    set_mtime::SET_TO_CLIENT_TIME(nfs_time(time))
+                }
+            },
+        };
+        self.serve(req, |fs, inodes| async move {
+            let id = inodes.id(ino);
+            inodes.attr(reply, id, fs.setattr(id, setattr).await);
+        });
+    }
+
+    fn readlink(&mut self, req: &Request<'_>, ino: u64, reply: ReplyData) {
+        self.serve(req, |fs, inodes| async move {
+            match fs.readlink(inodes.id(ino)).await {
+                Ok(target) => reply.data(&target),
+                Err(stat) => reply.error(errno(stat)),
+            }
+        });
+    }
+
+    fn mknod(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = filename(name);
+        let attr = create_mode(mode, umask);
+        let ftype = match mode & libc::S_IFMT {
+            libc::S_IFREG => ftype3::NF3REG,
+            libc::S_IFIFO => ftype3::NF3FIFO,
+            libc::S_IFSOCK => ftype3::NF3SOCK,
+            libc::S_IFCHR => ftype3::NF3CHR,
+            libc::S_IFBLK => ftype3::NF3BLK,
+            _ => return reply.error(libc::EINVAL),
+        };
+        let spec = specdata3 {
+            specdata1: libc::major(rdev as libc::dev_t),
+            specdata2: libc::minor(rdev as libc::dev_t),
+        };
+        self.serve(req, |fs, inodes| async move {
+            let dir = inodes.id(parent);
+            let created = match ftype {
+                ftype3::NF3REG => fs.create(dir, &name, attr).await,
+                _ => fs.mknod(dir, &name, ftype, spec, &attr).await,
+            };
+            inodes.entry(reply, created);
+        });
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = filename(name);
+        let attr = create_mode(mode, umask);
+        self.serve(req, |fs, inodes| async move {
+            inodes.entry(
+                reply,
+                fs.mkdir_with_attrs(inodes.id(parent), &name, &attr).await,
+            );
+        });
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let name = filename(name);
+        self.serve(req, |fs, inodes| async move {
+            empty(reply, fs.remove(inodes.id(parent), &name).await);
+        });
+    }
+
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        self.unlink(req, parent, name, reply);
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        target: &Path,
+        reply: ReplyEntry,
+    ) {
+        let name = filename(link_name);
+        let target: nfspath3 = target.as_os_str().as_bytes().to_vec().into();
+        self.serve(req, |fs, inodes| async move {
+            let created = fs
+                .symlink(inodes.id(parent), &name, &target, &sattr3::default())
+                .await;
+            inodes.entry(reply, created);
+        });
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        if flags != 0 {
+            // neither RENAME_NOREPLACE nor RENAME_EXCHANGE is known to NFS
+            return reply.error(libc::EINVAL);
+        }
+        let (from, to) = (filename(name), filename(newname));
+        self.serve(req, |fs, inodes| async move {
+            let renamed = fs
+                .rename(inodes.id(parent), &from, inodes.id(newparent), &to)
+                .await;
+            empty(reply, renamed);
+        });
+    }
+
+    fn link(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        let name = filename(newname);
+        self.serve(req, |fs, inodes| async move {
+            let id = inodes.id(ino);
+            let linked = async {
+                fs.link(id, inodes.id(newparent), &name).await?;
+                Ok((id, fs.getattr(id).await?))
+            };
+            inodes.entry(reply, linked.await);
+        });
+    }
+
+    fn open(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: ReplyOpen) {
+        // reads bypass the page cache, as what the file system serves may
+        // be another for every caller
+        reply.opened(0, FOPEN_DIRECT_IO);
+    }
+
+    fn read(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        self.serve(req, |fs, inodes| async move {
+            match fs.read(inodes.id(ino), offset as u64, size).await {
+                Ok((data, _)) => reply.data(&data),
+                Err(stat) => reply.error(errno(stat)),
+            }
+        });
+    }
+
+    fn write(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let data = data.to_vec();
+        self.serve(req, |fs, inodes| async move {
+            // synced as the file is released, or on fsync
+            match fs
+                .write_unstable(inodes.id(ino), offset as u64, &data)
+                .await
+            {
+                Ok(_) => reply.written(data.len() as u32),
+                Err(stat) => reply.error(errno(stat)),
+            }
+        });
+    }
+
+    fn flush(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _lock: u64, reply: ReplyEmpty) {
+        reply.ok();
+    }
+
+    fn release(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.serve(req, |fs, inodes| async move {
+            // nothing to report the error to as the file is closed
+            let id = inodes.id(ino);
+            if let Err(e) = fs.commit(id, 0, 0).await {
+                debug!("Unable to commit {:?} on release: {:?}", id, e);
+            }
+            reply.ok();
+        });
+    }
+
+    fn fsync(&mut self, req: &Request<'_>, ino: u64, _fh: u64, _data: bool, reply: ReplyEmpty) {
+        self.serve(req, |fs, inodes| async move {
+            empty(reply, fs.commit(inodes.id(ino), 0, 0).await.map(|_| ()));
+        });
+    }
+
+    fn readdir(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        self.serve(req, |fs, inodes| async move {
+            let listed = match fs
+                .readdir(inodes.id(ino), offset as u64, READDIR_ENTRIES)
+                .await
+            {
+                Ok(listed) => listed,
+                Err(stat) => return reply.error(errno(stat)),
+            };
+            for entry in listed.entries {
+                // the offset of an entry is where the listing goes on from
+                let full = reply.add(
+                    inodes.ino(entry.fileid),
+                    entry.fileid as i64,
+                    file_type(entry.attr.ftype),
+                    OsStr::from_bytes(&entry.name),
+                );
+                if full {
+                    break;
+                }
+            }
+            reply.ok();
+        });
+    }
+
+    fn fsyncdir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _data: bool,
+        reply: ReplyEmpty,
+    ) {
+        reply.ok();
+    }
+
+    fn statfs(&mut self, req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        self.serve(req, |fs, inodes| async move {
+            match fs.fsstat(inodes.root).await {
+                Ok(stat) => reply.statfs(
+                    stat.tbytes / 4096,
+                    stat.fbytes / 4096,
+                    stat.abytes / 4096,
+                    stat.tfiles,
+                    stat.ffiles,
+                    4096,
+                    255,
+                    4096,
+                ),
+                Err(stat) => reply.error(errno(stat)),
+            }
+        });
+    }
+
+    fn access(&mut self, _req: &Request<'_>, _ino: u64, _mask: i32, reply: ReplyEmpty) {
+        // the kernel checks the mode bits itself
+        reply.ok();
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let name = filename(name);
+        let attr = create_mode(mode, umask);
+        self.serve(req, |fs, inodes| async move {
+            let dir = inodes.id(parent);
+            let created = if flags & libc::O_EXCL == 0 {
+                fs.create(dir, &name, attr).await
+            } else {
+                match fs.create_exclusive(dir, &name).await {
+                    Ok(id) => fs.getattr(id).await.map(|attr| (id, attr)),
+                    Err(stat) => Err(stat),
+                }
+            };
+            match created {
+                Ok((id, attr)) => reply.created(
+                    &TTL,
+                    &file_attr(inodes.ino(id), &attr),
+                    0,
+                    0,
+                    FOPEN_DIRECT_IO,
+                ),
+                Err(stat) => reply.error(errno(stat)),
+            }
+        });
+    }
+}
+
+/// Serves a NFSFileSystem at a mount point through FUSE
+#[derive(Debug)]
+pub struct FuseMount<T: NFSFileSystem + Send + Sync + 'static> {
+    arcfs: Arc<T>,
+    mountpoint: PathBuf,
+    id_mapping: Arc<IdMapping>,
+    allow_other: bool,
+}
+
+impl<T: NFSFileSystem + Send + Sync + 'static> FuseMount<T> {
+    /// Serves fs at mountpoint, which has to be an empty directory
+    pub fn new(mountpoint: &Path, fs: T) -> FuseMount<T> {
+        Self::new_shared(mountpoint, Arc::new(fs))
+    }
+
+    /// Like new, for a file system which is also served elsewhere
+    pub fn new_shared(mountpoint: &Path, arcfs: Arc<T>) -> FuseMount<T> {
+        FuseMount {
+            arcfs,
+            mountpoint: mountpoint.to_path_buf(),
+            id_mapping: Arc::new(IdMapping::default()),
+            allow_other: false,
+        }
+    }
+
+    /// Maps the uids and gids of the local users making calls, as is done
+    /// for NFS clients
+    pub fn with_id_mapping(&mut self, mapping: IdMapping) {
+        self.id_mapping = Arc::new(mapping);
+    }
+
+    /// Lets every local user in, not only the one who mounts the file
+    /// system. Unless that is root, `user_allow_other` has to be set in
+    /// `/etc/fuse.conf`.
+    pub fn with_allow_other(&mut self) {
+        self.allow_other = true;
+    }
+
+    /// Mounts the file system and serves requests until `shutdown`
+    /// completes or the file system is unmounted. Then unmounts it and
+    /// shuts the file system down.
+    pub async fn handle_until<F>(&self, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()> + Send,
+    {
+        let adapter = Adapter {
+            fs: self.arcfs.clone(),
+            inodes: Inodes {
+                root: self.arcfs.root_dir(),
+            },
+            id_mapping: self.id_mapping.clone(),
+            runtime: tokio::runtime::Handle::current(),
+        };
+        let mut options = vec![
+            MountOption::FSName("eternal-fs".to_string()),
+            MountOption::Subtype("eternal-fs".to_string()),
+            MountOption::DefaultPermissions,
+            MountOption::NoSuid,
+            MountOption::NoDev,
+        ];
+        if self.allow_other {
+            options.push(MountOption::AllowOther);
+        }
+        let mut session = Session::new(adapter, &self.mountpoint, &options)?;
+        info!("Mounted at {:?}", self.mountpoint);
+        let mut unmounter = session.unmount_callable();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        std::thread::spawn(move || {
+            let _ = done_tx.send(session.run());
+        });
+        tokio::pin!(shutdown);
+        let result = tokio::select! {
+            _ = &mut shutdown => {
+                if let Err(e) = unmounter.unmount() {
+                    warn!("Unable to unmount {:?}: {}", self.mountpoint, e);
+                }
+                Ok(())
+            }
+            result = done_rx => result.unwrap_or(Ok(())),
+        };
+        info!("No longer serving {:?}, shutting down", self.mountpoint);
+        self.arcfs.shutdown().await;
+        result
+    }
+}
@@ -1,5 +1,7 @@
 #![cfg_attr(feature = "strict", deny(warnings))]
 
+pub mod access;
+pub mod auth;
 mod context;
 mod rpc;
 mod rpcwire;
@@ -12,12 +14,23 @@ mod mount_handlers;
 mod portmap;
 mod portmap_handlers;
 
+pub mod locks;
+mod callback;
+mod nlm;
+mod nlm_handlers;
+mod nsm;
+mod nsm_handlers;
+
 pub mod nfs;
 mod nfs_handlers;
 
 #[cfg(not(target_os = "windows"))]
 pub mod fs_util;
 
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+pub mod fuse;
 pub mod tcp;
+pub mod throttle;
+pub mod udp;
 pub mod vfs;
 mod transaction_tracker;
@@ -0,0 +1,511 @@
+//! Advisory byte range locks, as clients take them with the Network Lock
+//! Manager protocol when a process calls fcntl or flock on a file they
+//! mount.
+//!
+//! A lock is held by a process of a client, and any number of them may
+//! hold shared locks on a range while only one holds an exclusive lock.
+//! As with POSIX locks, a process locking a range it already holds locks
+//! on replaces them, and unlocking part of a range leaves the rest locked.
+//! Locks are only ever held in memory and only among clients: processes
+//! on the server do not see them, and they are gone once it restarts.
+//!
+//! A process may wait for a lock it is denied. It is then granted the
+//! lock as soon as the locks keeping it from being held are released, in
+//! the order processes started waiting, and told so by a call back.
+
+use crate::nfs::fileid3;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// The process of a client which holds a lock
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockOwner {
+    /// The name the client gives itself
+    pub client: Vec<u8>,
+    /// The process on the client
+    pub svid: i32,
+    /// What the client tells the owner by, handed back to it as a lock
+    /// it tests is held
+    pub handle: Vec<u8>,
+}
+
+/// A range of a file locked by a process
+#[derive(Clone, Debug)]
+pub struct Lock {
+    pub owner: LockOwner,
+    pub exclusive: bool,
+    pub start: u64,
+    /// Where the range ends, u64::MAX for a range which runs to the end of
+    /// the file however large it grows
+    pub end: u64,
+}
+
+impl Lock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    /// Whether `other`, held by another process, keeps this lock from
+    /// being held
+    fn conflicts(&self, other: &Lock) -> bool {
+        (self.exclusive || other.exclusive)
+            && !self.owner.same_process(&other.owner)
+            && self.overlaps(other.start, other.end)
+    }
+}
+
+impl LockOwner {
+    fn same_process(&self, other: &LockOwner) -> bool {
+        self.client == other.client && self.svid == other.svid
+    }
+}
+
+/// Called once a lock waited for is held, to tell the process waiting
+pub type Granted = Box<dyn FnOnce() + Send>;
+
+/// A process waiting for a lock
+struct Waiter {
+    lock: Lock,
+    granted: Granted,
+}
+
+impl fmt::Debug for Waiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Waiter").field("lock", &self.lock).finish()
+    }
+}
+
+/// The locks held on the files of a file system and those waited for, by
+/// fileid. A file only has entries while locks are held on it or waited
+/// for.
+#[derive(Debug, Default)]
+struct LockTable {
+    held: HashMap<fileid3, Vec<Lock>>,
+    waiting: HashMap<fileid3, Vec<Waiter>>,
+}
+
+impl LockTable {
+    fn lock(&mut self, id: fileid3, lock: Lock) -> Result<(), Lock> {
+        if let Some(held) = self.held.get_mut(&id) {
+            if let Some(conflict) = held.iter().find(|held| lock.conflicts(held)) {
+                return Err(conflict.clone());
+            }
+            release(held, &lock.owner, lock.start, lock.end);
+            held.push(lock);
+        } else {
+            self.held.insert(id, vec![lock]);
+        }
+        Ok(())
+    }
+
+    fn unlock(&mut self, id: fileid3, owner: &LockOwner, start: u64, end: u64) {
+        if let Some(held) = self.held.get_mut(&id) {
+            release(held, owner, start, end);
+            if held.is_empty() {
+                self.held.remove(&id);
+            }
+        }
+    }
+
+    /// Stops `owner` waiting for `start..end` of `id`
+    fn cancel(&mut self, id: fileid3, owner: &LockOwner, start: u64, end: u64) {
+        if let Some(waiting) = self.waiting.get_mut(&id) {
+            waiting.retain(|waiter| {
+                !(waiter.lock.owner.same_process(owner)
+                    && waiter.lock.start == start
+                    && waiter.lock.end == end)
+            });
+            if waiting.is_empty() {
+                self.waiting.remove(&id);
+            }
+        }
+    }
+
+    /// Holds the locks waited for on `id` which are no longer kept from
+    /// being held, returning who to tell
+    fn grant_waiting(&mut self, id: fileid3) -> Vec<Granted> {
+        let Some(waiting) = self.waiting.remove(&id) else {
+            return Vec::new();
+        };
+        let mut granted = Vec::new();
+        let mut still_waiting = Vec::new();
+        for waiter in waiting {
+            match self.lock(id, waiter.lock.clone()) {
+                Ok(()) => granted.push(waiter.granted),
+                Err(_) => still_waiting.push(waiter),
+            }
+        }
+        if !still_waiting.is_empty() {
+            self.waiting.insert(id, still_waiting);
+        }
+        granted
+    }
+}
+
+/// The locks held on the files of a file system, by fileid, and the
+/// processes waiting for them
+#[derive(Debug, Default)]
+pub struct LockManager {
+    table: Mutex<LockTable>,
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn table(&self) -> std::sync::MutexGuard<'_, LockTable> {
+        self.table.lock().expect("unable to lock the lock table")
+    }
+
+    /// A lock held on `id` which keeps `lock` from being held, if any
+    pub fn test(&self, id: fileid3, lock: &Lock) -> Option<Lock> {
+        let table = self.table();
+        table
+            .held
+            .get(&id)?
+            .iter()
+            .find(|held| lock.conflicts(held))
+            .cloned()
+    }
+
+    /// Holds `lock` on `id`, unless another process holds a lock which
+    /// keeps it from being held, which is returned instead
+    pub fn lock(&self, id: fileid3, lock: Lock) -> Result<(), Lock> {
+        self.table().lock(id, lock)
+    }
+
+    /// Like lock, but a process denied the lock waits for it instead:
+    /// `granted` is called once it is held. A process waiting for the
+    /// same range already only waits once, told by the latest `granted`.
+    pub fn lock_or_wait(&self, id: fileid3, lock: Lock, granted: Granted) -> Result<(), Lock> {
+        let mut table = self.table();
+        let held = table.lock(id, lock.clone());
+        table.cancel(id, &lock.owner, lock.start, lock.end);
+        let Err(conflict) = held else {
+            return Ok(());
+        };
+        table
+            .waiting
+            .entry(id)
+            .or_default()
+            .push(Waiter { lock, granted });
+        Err(conflict)
+    }
+
+    /// Stops `owner` waiting for `start..end` of `id`, if it does
+    pub fn cancel(&self, id: fileid3, owner: &LockOwner, start: u64, end: u64) {
+        self.table().cancel(id, owner, start, end);
+    }
+
+    /// Releases what `owner` holds of `start..end` of `id`, granting the
+    /// locks waited for which no longer conflict
+    pub fn unlock(&self, id: fileid3, owner: &LockOwner, start: u64, end: u64) {
+        let granted = {
+            let mut table = self.table();
+            table.unlock(id, owner, start, end);
+            table.grant_waiting(id)
+        };
+        // told once the table is no longer locked, as they may take or
+        // release locks themselves
+        granted.into_iter().for_each(|granted| granted());
+    }
+
+    /// Releases every lock the client named `client` holds and stops it
+    /// waiting for any, as it restarted
+    pub fn release_client(&self, client: &[u8]) {
+        let granted = {
+            let mut table = self.table();
+            table.waiting.retain(|_, waiting| {
+                waiting.retain(|waiter| waiter.lock.owner.client != client);
+                !waiting.is_empty()
+            });
+            let mut released = Vec::new();
+            table.held.retain(|id, held| {
+                let before = held.len();
+                held.retain(|lock| lock.owner.client != client);
+                if held.len() != before {
+                    released.push(*id);
+                }
+                !held.is_empty()
+            });
+            released
+                .into_iter()
+                .flat_map(|id| table.grant_waiting(id))
+                .collect::<Vec<_>>()
+        };
+        granted.into_iter().for_each(|granted| granted());
+    }
+
+    /// How many locks are held
+    pub fn held(&self) -> usize {
+        self.table().held.values().map(Vec::len).sum()
+    }
+}
+
+/// Cuts `start..end` out of the locks `owner` holds in `held`, keeping
+/// what lies on either side of it
+fn release(held: &mut Vec<Lock>, owner: &LockOwner, start: u64, end: u64) {
+    let mut kept = Vec::with_capacity(held.len());
+    for lock in held.drain(..) {
+        if !lock.owner.same_process(owner) || !lock.overlaps(start, end) {
+            kept.push(lock);
+            continue;
+        }
+        if lock.start < start {
+            kept.push(Lock {
+                end: start,
+                ..lock.clone()
+            });
+        }
+        if end < lock.end {
+            kept.push(Lock { start: end, ..lock });
+        }
+    }
+    *held = kept;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const FILE: fileid3 = 7;
+
+    fn owner(client: &str, svid: i32) -> LockOwner {
+        LockOwner {
+            client: client.as_bytes().to_vec(),
+            svid,
+            handle: Vec::new(),
+        }
+    }
+
+    fn lock(client: &str, svid: i32, exclusive: bool, start: u64, end: u64) -> Lock {
+        Lock {
+            owner: owner(client, svid),
+            exclusive,
+            start,
+            end,
+        }
+    }
+
+    /// The ranges held on FILE, in order
+    fn ranges(locks: &LockManager) -> Vec<(u64, u64, bool)> {
+        let table = locks.table();
+        let mut ranges: Vec<_> = table
+            .held
+            .get(&FILE)
+            .into_iter()
+            .flatten()
+            .map(|lock| (lock.start, lock.end, lock.exclusive))
+            .collect();
+        ranges.sort();
+        ranges
+    }
+
+    /// A Granted which counts how often it is called
+    fn counted() -> (Arc<AtomicUsize>, Granted) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let granted = Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        (calls, granted)
+    }
+
+    #[test]
+    fn shared_locks_are_held_together() {
+        let locks = LockManager::new();
+        assert!(locks.lock(FILE, lock("a", 1, false, 0, 10)).is_ok());
+        assert!(locks.lock(FILE, lock("b", 1, false, 5, 15)).is_ok());
+        assert!(locks.lock(FILE, lock("c", 1, true, 8, 9)).is_err());
+        assert_eq!(locks.held(), 2);
+    }
+
+    #[test]
+    fn exclusive_locks_conflict_with_other_processes_only() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 0, 10)).unwrap();
+        let conflict = locks.lock(FILE, lock("a", 2, false, 9, 20)).unwrap_err();
+        assert_eq!(conflict.owner, owner("a", 1));
+        assert!(locks.lock(FILE, lock("b", 1, false, 0, 1)).is_err());
+        // the same process may lock it again
+        assert!(locks.lock(FILE, lock("a", 1, true, 5, 15)).is_ok());
+    }
+
+    #[test]
+    fn adjacent_ranges_do_not_overlap() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 10, 20)).unwrap();
+        assert!(locks.lock(FILE, lock("b", 1, true, 0, 10)).is_ok());
+        assert!(locks.lock(FILE, lock("b", 1, true, 20, 30)).is_ok());
+        assert!(locks.test(FILE, &lock("c", 1, true, 19, 20)).is_some());
+    }
+
+    #[test]
+    fn unlocking_the_middle_splits_a_lock() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 0, 100)).unwrap();
+        locks.unlock(FILE, &owner("a", 1), 40, 60);
+        assert_eq!(ranges(&locks), vec![(0, 40, true), (60, 100, true)]);
+        assert!(locks.test(FILE, &lock("b", 1, true, 40, 60)).is_none());
+        assert!(locks.test(FILE, &lock("b", 1, true, 39, 40)).is_some());
+        assert!(locks.test(FILE, &lock("b", 1, true, 60, 61)).is_some());
+    }
+
+    #[test]
+    fn unlocking_either_end_trims_a_lock() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 10, 20)).unwrap();
+        locks.unlock(FILE, &owner("a", 1), 0, 12);
+        assert_eq!(ranges(&locks), vec![(12, 20, true)]);
+        locks.unlock(FILE, &owner("a", 1), 18, 30);
+        assert_eq!(ranges(&locks), vec![(12, 18, true)]);
+        locks.unlock(FILE, &owner("a", 1), 12, 18);
+        assert!(ranges(&locks).is_empty());
+        assert!(locks.table().held.is_empty());
+    }
+
+    #[test]
+    fn unlocking_only_releases_the_owners_locks() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, false, 0, 10)).unwrap();
+        locks.lock(FILE, lock("a", 2, false, 0, 10)).unwrap();
+        locks.unlock(FILE, &owner("a", 1), 0, u64::MAX);
+        assert_eq!(locks.held(), 1);
+        assert!(locks.test(FILE, &lock("b", 1, true, 0, 1)).is_some());
+    }
+
+    #[test]
+    fn relocking_part_of_a_range_replaces_that_part() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 0, 100)).unwrap();
+        locks.lock(FILE, lock("a", 1, false, 50, 60)).unwrap();
+        assert_eq!(
+            ranges(&locks),
+            vec![(0, 50, true), (50, 60, false), (60, 100, true)]
+        );
+        assert!(locks.lock(FILE, lock("b", 1, false, 55, 56)).is_ok());
+        assert!(locks.lock(FILE, lock("b", 1, false, 49, 50)).is_err());
+    }
+
+    #[test]
+    fn ranges_to_the_end_of_the_file_are_split_like_any_other() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 100, u64::MAX)).unwrap();
+        assert!(locks
+            .test(FILE, &lock("b", 1, true, u64::MAX - 1, u64::MAX))
+            .is_some());
+        locks.unlock(FILE, &owner("a", 1), 200, 300);
+        assert_eq!(
+            ranges(&locks),
+            vec![(100, 200, true), (300, u64::MAX, true)]
+        );
+        // unlocking the last byte which can be addressed keeps the rest
+        locks.unlock(FILE, &owner("a", 1), u64::MAX - 1, u64::MAX);
+        assert_eq!(
+            ranges(&locks),
+            vec![(100, 200, true), (300, u64::MAX - 1, true)]
+        );
+        assert!(locks
+            .test(FILE, &lock("b", 1, true, u64::MAX - 1, u64::MAX))
+            .is_none());
+        locks.unlock(FILE, &owner("a", 1), 0, u64::MAX);
+        assert!(locks.table().held.is_empty());
+    }
+
+    #[test]
+    fn denied_locks_leave_nothing_behind() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 0, 10)).unwrap();
+        assert!(locks.lock(FILE, lock("b", 1, true, 0, 10)).is_err());
+        locks.unlock(FILE, &owner("a", 1), 0, 10);
+        assert!(locks.table().held.is_empty());
+        assert!(locks.table().waiting.is_empty());
+    }
+
+    #[test]
+    fn releasing_a_client_releases_all_its_processes() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 0, 10)).unwrap();
+        locks.lock(FILE, lock("a", 2, true, 20, 30)).unwrap();
+        locks.lock(FILE + 1, lock("a", 1, true, 0, 10)).unwrap();
+        locks.lock(FILE, lock("b", 1, true, 40, 50)).unwrap();
+        locks.release_client(b"a");
+        assert_eq!(ranges(&locks), vec![(40, 50, true)]);
+        assert!(!locks.table().held.contains_key(&(FILE + 1)));
+    }
+
+    #[test]
+    fn waiting_locks_are_granted_once_free() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, false, 0, 10)).unwrap();
+        locks.lock(FILE, lock("c", 1, false, 5, 10)).unwrap();
+        let (calls, granted) = counted();
+        assert!(locks
+            .lock_or_wait(FILE, lock("b", 1, true, 0, 10), granted)
+            .is_err());
+        locks.unlock(FILE, &owner("a", 1), 0, 10);
+        // still kept from being held by c
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        locks.unlock(FILE, &owner("c", 1), 0, 10);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(ranges(&locks), vec![(0, 10, true)]);
+        assert!(locks.test(FILE, &lock("a", 1, false, 0, 1)).is_some());
+        assert!(locks.table().waiting.is_empty());
+    }
+
+    #[test]
+    fn locks_free_at_once_are_not_waited_for() {
+        let locks = LockManager::new();
+        let (calls, granted) = counted();
+        assert!(locks
+            .lock_or_wait(FILE, lock("b", 1, true, 0, 10), granted)
+            .is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(locks.table().waiting.is_empty());
+    }
+
+    #[test]
+    fn waiting_again_for_a_range_waits_once() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 0, 10)).unwrap();
+        let (first, granted) = counted();
+        let _ = locks.lock_or_wait(FILE, lock("b", 1, true, 0, 10), granted);
+        let (second, granted) = counted();
+        let _ = locks.lock_or_wait(FILE, lock("b", 1, true, 0, 10), granted);
+        locks.unlock(FILE, &owner("a", 1), 0, 10);
+        assert_eq!(first.load(Ordering::SeqCst), 0);
+        assert_eq!(second.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn cancelled_locks_are_not_granted() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 0, 10)).unwrap();
+        let (calls, granted) = counted();
+        let _ = locks.lock_or_wait(FILE, lock("b", 1, true, 0, 10), granted);
+        locks.cancel(FILE, &owner("b", 1), 0, 10);
+        locks.unlock(FILE, &owner("a", 1), 0, 10);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(locks.table().held.is_empty());
+        assert!(locks.table().waiting.is_empty());
+    }
+
+    #[test]
+    fn restarted_clients_free_their_locks_for_those_waiting() {
+        let locks = LockManager::new();
+        locks.lock(FILE, lock("a", 1, true, 0, 10)).unwrap();
+        let (waited_on_a, granted) = counted();
+        let _ = locks.lock_or_wait(FILE, lock("b", 1, true, 0, 10), granted);
+        let (a_waited, granted) = counted();
+        let _ = locks.lock_or_wait(FILE, lock("a", 2, true, 0, 10), granted);
+        locks.release_client(b"a");
+        assert_eq!(waited_on_a.load(Ordering::SeqCst), 1);
+        assert_eq!(a_waited.load(Ordering::SeqCst), 0);
+        assert_eq!(ranges(&locks), vec![(0, 10, true)]);
+        assert!(locks.table().waiting.is_empty());
+    }
+}
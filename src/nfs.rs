@@ -219,6 +219,22 @@ pub enum ftype3 {
     NF3FIFO = 7,
 }
 XDREnumSerde!(ftype3);
+
+/// How far written data has reached stable storage
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum stable_how {
+    /// Not yet: it is lost if the server crashes before a COMMIT
+    #[default]
+    UNSTABLE = 0,
+    /// The data, but not all the metadata of the file
+    DATA_SYNC = 1,
+    /// The data and the metadata
+    FILE_SYNC = 2,
+}
+XDREnumSerde!(stable_how);
+
 /// Device Number information. Ex: Major / Minor device
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug, Default)]
@@ -332,6 +348,30 @@ XDRStruct!(
     properties
 );
 
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default)]
+pub struct fsstat3 {
+    pub obj_attributes: post_op_attr,
+    pub tbytes: size3,
+    pub fbytes: size3,
+    pub abytes: size3,
+    pub tfiles: size3,
+    pub ffiles: size3,
+    pub afiles: size3,
+    pub invarsec: u32,
+}
+XDRStruct!(
+    fsstat3,
+    obj_attributes,
+    tbytes,
+    fbytes,
+    abytes,
+    tfiles,
+    ffiles,
+    afiles,
+    invarsec
+);
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug, Default)]
 pub struct wcc_attr {
@@ -6,10 +6,12 @@ use crate::rpc::*;
 use crate::vfs::VFSCapabilities;
 use crate::xdr::*;
 use byteorder::{ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::cast::FromPrimitive;
 use std::io::{Read, Write};
-use tracing::{debug, error, trace, warn};
+use std::time::Instant;
+use tracing::{debug, error, field, info_span, trace, warn, Instrument, Span};
 /*
 program NFS_PROGRAM {
  version NFS_V3 {
@@ -113,12 +115,104 @@ enum NFSProgram {
     INVALID = 22,
 }
 
+/// Bytes at the start of an accepted reply up to the status of the call:
+/// xid, message type, reply status, an empty verifier and accept status
+const REPLY_HEAD: usize = 28;
+
+/// Passes a reply on, keeping its start to tell the status of the call
+struct ReplyHead<'a, W> {
+    output: &'a mut W,
+    head: Vec<u8>,
+}
+
+impl<W: Write> Write for ReplyHead<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.output.write(buf)?;
+        let keep = written.min(REPLY_HEAD - self.head.len());
+        self.head.extend_from_slice(&buf[..keep]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<W> ReplyHead<'_, W> {
+    /// The status the call was answered with, if it was answered with one
+    fn status(&self) -> Option<nfs::nfsstat3> {
+        let word = |at: usize| {
+            let bytes = self.head.get(at..at + 4)?;
+            Some(u32::from_be_bytes(bytes.try_into().ok()?))
+        };
+        // accepted, with an empty verifier, and served
+        if word(8)? != 0 || word(16)? != 0 || word(20)? != 0 {
+            return None;
+        }
+        nfs::nfsstat3::from_u32(word(24)?)
+    }
+}
+
+/// Handles a NFS call within a span naming the operation and the client.
+///
+/// The span has a `fileid` field, filled in once the file handle has been
+/// decoded, a `path` field which is left for the file system to fill in
+/// with `Span::current().record("path", ..)`, and a `status` field with
+/// the status the call was answered with, once it has been.
 pub async fn handle_nfs(
     xid: u32,
     call: call_body,
     input: &mut impl Read,
     output: &mut impl Write,
     context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    let prog = NFSProgram::from_u32(call.proc).unwrap_or(NFSProgram::INVALID);
+    let span = info_span!(
+        "nfs",
+        op = ?prog,
+        xid,
+        client = %context.client_addr,
+        uid = context.caller.uid,
+        fileid = field::Empty,
+        path = field::Empty,
+        status = field::Empty,
+    );
+    let start = Instant::now();
+    let mut output = ReplyHead {
+        output,
+        head: Vec::with_capacity(REPLY_HEAD),
+    };
+    let dispatch = dispatch_nfs(xid, call, input, &mut output, context);
+    let result = crate::auth::serve_as(context.caller.clone(), &context.client_addr, dispatch)
+        .instrument(span.clone())
+        .await;
+    if let Some(status) = output.status() {
+        span.record("status", field::debug(status));
+    }
+    span.in_scope(|| {
+        debug!(
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "nfs call complete"
+        )
+    });
+    result
+}
+
+/// Decodes a file handle, recording its fileid on the span of the call
+fn fh_to_id(context: &RPCContext, fh: &nfs::nfs_fh3) -> Result<nfs::fileid3, nfs::nfsstat3> {
+    let id = context.vfs.fh_to_id(fh);
+    if let Ok(id) = id {
+        Span::current().record("fileid", id);
+    }
+    id
+}
+
+async fn dispatch_nfs(
+    xid: u32,
+    call: call_body,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
 ) -> Result<(), anyhow::Error> {
     if call.vers != nfs::VERSION {
         warn!(
@@ -150,16 +244,16 @@ pub async fn handle_nfs(
         NFSProgram::NFSPROC3_REMOVE => nfsproc3_remove(xid, input, output, context).await?,
         NFSProgram::NFSPROC3_RMDIR => nfsproc3_remove(xid, input, output, context).await?,
         NFSProgram::NFSPROC3_RENAME => nfsproc3_rename(xid, input, output, context).await?,
+        NFSProgram::NFSPROC3_LINK => nfsproc3_link(xid, input, output, context).await?,
+        NFSProgram::NFSPROC3_MKNOD => nfsproc3_mknod(xid, input, output, context).await?,
         NFSProgram::NFSPROC3_MKDIR => nfsproc3_mkdir(xid, input, output, context).await?,
         NFSProgram::NFSPROC3_SYMLINK => nfsproc3_symlink(xid, input, output, context).await?,
         NFSProgram::NFSPROC3_READLINK => nfsproc3_readlink(xid, input, output, context).await?,
+        NFSProgram::NFSPROC3_COMMIT => nfsproc3_commit(xid, input, output, context).await?,
         _ => {
             warn!("Unimplemented message {:?}", prog);
             proc_unavail_reply_message(xid).serialize(output)?;
         } /*
-          NFSPROC3_MKNOD,
-          NFSPROC3_LINK,
-          NFSPROC3_COMMIT,
           INVALID*/
     }
     Ok(())
@@ -203,7 +297,7 @@ pub async fn nfsproc3_getattr(
     handle.deserialize(input)?;
     debug!("nfsproc3_getattr({:?},{:?}) ", xid, handle);
 
-    let id = context.vfs.fh_to_id(&handle);
+    let id = fh_to_id(context, &handle);
     // fail if unable to convert file handle
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
@@ -262,7 +356,7 @@ pub async fn nfsproc3_lookup(
     dirops.deserialize(input)?;
     debug!("nfsproc3_lookup({:?},{:?}) ", xid, dirops);
 
-    let dirid = context.vfs.fh_to_id(&dirops.dir);
+    let dirid = fh_to_id(context, &dirops.dir);
     // fail if unable to convert file handle
     if let Err(stat) = dirid {
         make_success_reply(xid).serialize(output)?;
@@ -315,7 +409,7 @@ struct READ3resok {
     file_attributes: nfs::post_op_attr,
     count: nfs::count3,
     eof: bool,
-    data: Vec<u8>,
+    data: Bytes,
 }
 XDRStruct!(READ3resok, file_attributes, count, eof, data);
 /*
@@ -355,7 +449,7 @@ pub async fn nfsproc3_read(
     args.deserialize(input)?;
     debug!("nfsproc3_read({:?},{:?}) ", xid, args);
 
-    let id = context.vfs.fh_to_id(&args.file);
+    let id = fh_to_id(context, &args.file);
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
         stat.serialize(output)?;
@@ -368,7 +462,8 @@ pub async fn nfsproc3_read(
         Ok(v) => nfs::post_op_attr::attributes(v),
         Err(_) => nfs::post_op_attr::Void,
     };
-    match context.vfs.read(id, args.offset, args.count).await {
+    let count = context.max_transfer.map_or(args.count, |max| args.count.min(max));
+    match context.vfs.read(id, args.offset, count).await {
         Ok((bytes, eof)) => {
             let res = READ3resok {
                 file_attributes: obj_attr,
@@ -439,7 +534,7 @@ pub async fn nfsproc3_fsinfo(
     handle.deserialize(input)?;
     debug!("nfsproc3_fsinfo({:?},{:?}) ", xid, handle);
 
-    let id = context.vfs.fh_to_id(&handle);
+    let id = fh_to_id(context, &handle);
     // fail if unable to convert file handle
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
@@ -450,7 +545,20 @@ pub async fn nfsproc3_fsinfo(
     let id = id.unwrap();
 
     match context.vfs.fsinfo(id).await {
-        Ok(fsinfo) => {
+        Ok(mut fsinfo) => {
+            if let Some(max) = context.max_transfer {
+                for size in [
+                    &mut fsinfo.rtmax,
+                    &mut fsinfo.rtpref,
+                    &mut fsinfo.rtmult,
+                    &mut fsinfo.wtmax,
+                    &mut fsinfo.wtpref,
+                    &mut fsinfo.wtmult,
+                    &mut fsinfo.dtpref,
+                ] {
+                    *size = (*size).min(max);
+                }
+            }
             debug!(" {:?} --> {:?}", xid, fsinfo);
             make_success_reply(xid).serialize(output)?;
             nfs::nfsstat3::NFS3_OK.serialize(output)?;
@@ -510,7 +618,7 @@ pub async fn nfsproc3_access(
     access.deserialize(input)?;
     debug!("nfsproc3_access({:?},{:?},{:?})", xid, handle, access);
 
-    let id = context.vfs.fh_to_id(&handle);
+    let id = fh_to_id(context, &handle);
     // fail if unable to convert file handle
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
@@ -596,7 +704,7 @@ pub async fn nfsproc3_pathconf(
     handle.deserialize(input)?;
     debug!("nfsproc3_pathconf({:?},{:?})", xid, handle);
 
-    let id = context.vfs.fh_to_id(&handle);
+    let id = fh_to_id(context, &handle);
     // fail if unable to convert file handle
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
@@ -626,30 +734,6 @@ pub async fn nfsproc3_pathconf(
     Ok(())
 }
 
-#[allow(non_camel_case_types)]
-#[derive(Debug, Default)]
-struct FSSTAT3resok {
-    obj_attributes: nfs::post_op_attr,
-    tbytes: nfs::size3,
-    fbytes: nfs::size3,
-    abytes: nfs::size3,
-    tfiles: nfs::size3,
-    ffiles: nfs::size3,
-    afiles: nfs::size3,
-    invarsec: u32,
-}
-XDRStruct!(
-    FSSTAT3resok,
-    obj_attributes,
-    tbytes,
-    fbytes,
-    abytes,
-    tfiles,
-    ffiles,
-    afiles,
-    invarsec
-);
-
 /*
  FSSTAT3res NFSPROC3_FSSTAT(FSSTAT3args) = 18;
 
@@ -690,7 +774,7 @@ pub async fn nfsproc3_fsstat(
     let mut handle = nfs::nfs_fh3::default();
     handle.deserialize(input)?;
     debug!("nfsproc3_fsstat({:?},{:?}) ", xid, handle);
-    let id = context.vfs.fh_to_id(&handle);
+    let id = fh_to_id(context, &handle);
     // fail if unable to convert file handle
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
@@ -700,24 +784,24 @@ pub async fn nfsproc3_fsstat(
     }
     let id = id.unwrap();
 
-    let obj_attr = match context.vfs.getattr(id).await {
-        Ok(v) => nfs::post_op_attr::attributes(v),
-        Err(_) => nfs::post_op_attr::Void,
-    };
-    let res = FSSTAT3resok {
-        obj_attributes: obj_attr,
-        tbytes: 1024 * 1024 * 1024 * 1024,
-        fbytes: 1024 * 1024 * 1024 * 1024,
-        abytes: 1024 * 1024 * 1024 * 1024,
-        tfiles: 1024 * 1024 * 1024,
-        ffiles: 1024 * 1024 * 1024,
-        afiles: 1024 * 1024 * 1024,
-        invarsec: u32::MAX,
-    };
-    make_success_reply(xid).serialize(output)?;
-    nfs::nfsstat3::NFS3_OK.serialize(output)?;
-    debug!(" {:?} ---> {:?}", xid, res);
-    res.serialize(output)?;
+    match context.vfs.fsstat(id).await {
+        Ok(res) => {
+            debug!(" {:?} ---> {:?}", xid, res);
+            make_success_reply(xid).serialize(output)?;
+            nfs::nfsstat3::NFS3_OK.serialize(output)?;
+            res.serialize(output)?;
+        }
+        Err(stat) => {
+            error!("fsstat error {:?} --> {:?}", xid, stat);
+            let obj_attr = match context.vfs.getattr(id).await {
+                Ok(v) => nfs::post_op_attr::attributes(v),
+                Err(_) => nfs::post_op_attr::Void,
+            };
+            make_success_reply(xid).serialize(output)?;
+            stat.serialize(output)?;
+            obj_attr.serialize(output)?;
+        }
+    }
     Ok(())
 }
 
@@ -811,8 +895,12 @@ pub async fn nfsproc3_readdirplus(
     let mut args = READDIRPLUS3args::default();
     args.deserialize(input)?;
     debug!("nfsproc3_readdirplus({:?},{:?}) ", xid, args);
+    if let Some(max) = context.max_transfer {
+        args.dircount = args.dircount.min(max);
+        args.maxcount = args.maxcount.min(max);
+    }
 
-    let dirid = context.vfs.fh_to_id(&args.dir);
+    let dirid = fh_to_id(context, &args.dir);
     // fail if unable to convert file handle
     if let Err(stat) = dirid {
         make_success_reply(xid).serialize(output)?;
@@ -1002,9 +1090,12 @@ pub async fn nfsproc3_readdir(
 ) -> Result<(), anyhow::Error> {
     let mut args = READDIR3args::default();
     args.deserialize(input)?;
-    debug!("nfsproc3_readdirplus({:?},{:?}) ", xid, args);
+    debug!("nfsproc3_readdir({:?},{:?}) ", xid, args);
+    if let Some(max) = context.max_transfer {
+        args.dircount = args.dircount.min(max);
+    }
 
-    let dirid = context.vfs.fh_to_id(&args.dir);
+    let dirid = fh_to_id(context, &args.dir);
     // fail if unable to convert file handle
     if let Err(stat) = dirid {
         make_success_reply(xid).serialize(output)?;
@@ -1037,7 +1128,7 @@ pub async fn nfsproc3_readdir(
     let mut ctr = 0;
     match context
         .vfs
-        .readdir_simple(dirid, estimated_max_results as usize)
+        .readdir_simple_paged(dirid, args.cookie, estimated_max_results as usize)
         .await
     {
         Ok(result) => {
@@ -1114,17 +1205,6 @@ pub async fn nfsproc3_readdir(
     Ok(())
 }
 
-#[allow(non_camel_case_types)]
-#[derive(Copy, Clone, Debug, Default, FromPrimitive, ToPrimitive)]
-#[repr(u32)]
-pub enum stable_how {
-    #[default]
-    UNSTABLE = 0,
-    DATA_SYNC = 1,
-    FILE_SYNC = 2,
-}
-XDREnumSerde!(stable_how);
-
 #[allow(non_camel_case_types)]
 #[derive(Debug, Default)]
 struct WRITE3args {
@@ -1141,7 +1221,7 @@ XDRStruct!(WRITE3args, file, offset, count, stable, data);
 struct WRITE3resok {
     file_wcc: nfs::wcc_data,
     count: nfs::count3,
-    committed: stable_how,
+    committed: nfs::stable_how,
     verf: nfs::writeverf3,
 }
 XDRStruct!(WRITE3resok, file_wcc, count, committed, verf);
@@ -1206,7 +1286,7 @@ pub async fn nfsproc3_write(
         return Ok(());
     }
 
-    let id = context.vfs.fh_to_id(&args.file);
+    let id = fh_to_id(context, &args.file);
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
         stat.serialize(output)?;
@@ -1216,28 +1296,32 @@ pub async fn nfsproc3_write(
     let id = id.unwrap();
 
     // get the object attributes before the write
-    let pre_obj_attr = match context.vfs.getattr(id).await {
-        Ok(v) => {
-            let wccattr = nfs::wcc_attr {
-                size: v.size,
-                mtime: v.mtime,
-                ctime: v.ctime,
-            };
-            nfs::pre_op_attr::attributes(wccattr)
-        }
+    let pre_obj_attr = match context.vfs.wcc_attr(id).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
         Err(_) => nfs::pre_op_attr::Void,
     };
 
-    match context.vfs.write(id, args.offset, &args.data).await {
-        Ok(fattr) => {
-            debug!("write success {:?} --> {:?}", xid, fattr);
+    // what the client allows to be left unstable is, until it commits
+    let written = match nfs::stable_how::from_u32(args.stable) {
+        Some(nfs::stable_how::UNSTABLE) => {
+            context.vfs.write_unstable(id, args.offset, &args.data).await
+        }
+        _ => context
+            .vfs
+            .write(id, args.offset, &args.data)
+            .await
+            .map(|fattr| (fattr, nfs::stable_how::FILE_SYNC)),
+    };
+    match written {
+        Ok((fattr, committed)) => {
+            debug!("write success {:?} --> {:?} {:?}", xid, fattr, committed);
             let res = WRITE3resok {
                 file_wcc: nfs::wcc_data {
                     before: pre_obj_attr,
                     after: nfs::post_op_attr::attributes(fattr),
                 },
                 count: args.count,
-                committed: stable_how::FILE_SYNC,
+                committed,
                 verf: context.vfs.serverid(),
             };
             make_success_reply(xid).serialize(output)?;
@@ -1248,7 +1332,103 @@ pub async fn nfsproc3_write(
             error!("write error {:?} --> {:?}", xid, stat);
             make_success_reply(xid).serialize(output)?;
             stat.serialize(output)?;
-            nfs::wcc_data::default().serialize(output)?;
+            nfs::wcc_data {
+                before: pre_obj_attr,
+                after: nfs::post_op_attr::Void,
+            }
+            .serialize(output)?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default)]
+struct COMMIT3args {
+    file: nfs::nfs_fh3,
+    offset: nfs::offset3,
+    count: nfs::count3,
+}
+XDRStruct!(COMMIT3args, file, offset, count);
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default)]
+struct COMMIT3resok {
+    file_wcc: nfs::wcc_data,
+    verf: nfs::writeverf3,
+}
+XDRStruct!(COMMIT3resok, file_wcc, verf);
+/*
+struct COMMIT3args {
+    nfs_fh3 file;
+    offset3 offset;
+    count3 count;
+};
+
+struct COMMIT3resok {
+    wcc_data file_wcc;
+    writeverf3 verf;
+};
+
+struct COMMIT3resfail {
+    wcc_data file_wcc;
+};
+
+union COMMIT3res switch (nfsstat3 status) {
+    case NFS3_OK:
+        COMMIT3resok resok;
+    default:
+        COMMIT3resfail resfail;
+};
+ */
+pub async fn nfsproc3_commit(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    let mut args = COMMIT3args::default();
+    args.deserialize(input)?;
+    debug!("nfsproc3_commit({:?},{:?}) ", xid, args);
+
+    let id = fh_to_id(context, &args.file);
+    if let Err(stat) = id {
+        make_success_reply(xid).serialize(output)?;
+        stat.serialize(output)?;
+        nfs::wcc_data::default().serialize(output)?;
+        return Ok(());
+    }
+    let id = id.unwrap();
+
+    // get the object attributes before the commit
+    let pre_obj_attr = match context.vfs.wcc_attr(id).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
+        Err(_) => nfs::pre_op_attr::Void,
+    };
+
+    match context.vfs.commit(id, args.offset, args.count).await {
+        Ok(fattr) => {
+            debug!("commit success {:?} --> {:?}", xid, fattr);
+            let res = COMMIT3resok {
+                file_wcc: nfs::wcc_data {
+                    before: pre_obj_attr,
+                    after: nfs::post_op_attr::attributes(fattr),
+                },
+                verf: context.vfs.serverid(),
+            };
+            make_success_reply(xid).serialize(output)?;
+            nfs::nfsstat3::NFS3_OK.serialize(output)?;
+            res.serialize(output)?;
+        }
+        Err(stat) => {
+            error!("commit error {:?} --> {:?}", xid, stat);
+            make_success_reply(xid).serialize(output)?;
+            stat.serialize(output)?;
+            nfs::wcc_data {
+                before: pre_obj_attr,
+                after: nfs::post_op_attr::Void,
+            }
+            .serialize(output)?;
         }
     }
     Ok(())
@@ -1328,7 +1508,7 @@ pub async fn nfsproc3_create(
 
     // find the directory we are supposed to create the
     // new file in
-    let dirid = context.vfs.fh_to_id(&dirops.dir);
+    let dirid = fh_to_id(context, &dirops.dir);
     if let Err(stat) = dirid {
         // directory does not exist
         make_success_reply(xid).serialize(output)?;
@@ -1341,15 +1521,8 @@ pub async fn nfsproc3_create(
     let dirid = dirid.unwrap();
 
     // get the object attributes before the write
-    let pre_dir_attr = match context.vfs.getattr(dirid).await {
-        Ok(v) => {
-            let wccattr = nfs::wcc_attr {
-                size: v.size,
-                mtime: v.mtime,
-                ctime: v.ctime,
-            };
-            nfs::pre_op_attr::attributes(wccattr)
-        }
+    let pre_dir_attr = match context.vfs.wcc_attr(dirid).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
         Err(stat) => {
             error!("Cannot stat directory");
             make_success_reply(xid).serialize(output)?;
@@ -1514,7 +1687,7 @@ pub async fn nfsproc3_setattr(
     args.deserialize(input)?;
     debug!("nfsproc3_setattr({:?},{:?}) ", xid, args);
 
-    let id = context.vfs.fh_to_id(&args.object);
+    let id = fh_to_id(context, &args.object);
     // fail if unable to convert file handle
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
@@ -1525,14 +1698,9 @@ pub async fn nfsproc3_setattr(
 
     let ctime;
 
-    let pre_op_attr = match context.vfs.getattr(id).await {
-        Ok(v) => {
-            let wccattr = nfs::wcc_attr {
-                size: v.size,
-                mtime: v.mtime,
-                ctime: v.ctime,
-            };
-            ctime = v.ctime;
+    let pre_op_attr = match context.vfs.wcc_attr(id).await {
+        Ok(wccattr) => {
+            ctime = wccattr.ctime;
             nfs::pre_op_attr::attributes(wccattr)
         }
         Err(stat) => {
@@ -1549,7 +1717,12 @@ pub async fn nfsproc3_setattr(
             if c.seconds != ctime.seconds || c.nseconds != ctime.nseconds {
                 make_success_reply(xid).serialize(output)?;
                 nfs::nfsstat3::NFS3ERR_NOT_SYNC.serialize(output)?;
-                nfs::wcc_data::default().serialize(output)?;
+                nfs::wcc_data {
+                    before: pre_op_attr,
+                    after: nfs::post_op_attr::Void,
+                }
+                .serialize(output)?;
+                return Ok(());
             }
         }
     }
@@ -1569,7 +1742,11 @@ pub async fn nfsproc3_setattr(
             error!("setattr error {:?} --> {:?}", xid, stat);
             make_success_reply(xid).serialize(output)?;
             stat.serialize(output)?;
-            nfs::wcc_data::default().serialize(output)?;
+            nfs::wcc_data {
+                before: pre_op_attr,
+                after: nfs::post_op_attr::Void,
+            }
+            .serialize(output)?;
         }
     }
     Ok(())
@@ -1621,7 +1798,7 @@ pub async fn nfsproc3_remove(
     debug!("nfsproc3_remove({:?}, {:?}) ", xid, dirops);
 
     // find the directory with the file
-    let dirid = context.vfs.fh_to_id(&dirops.dir);
+    let dirid = fh_to_id(context, &dirops.dir);
     if let Err(stat) = dirid {
         // directory does not exist
         make_success_reply(xid).serialize(output)?;
@@ -1633,15 +1810,8 @@ pub async fn nfsproc3_remove(
     let dirid = dirid.unwrap();
 
     // get the object attributes before the write
-    let pre_dir_attr = match context.vfs.getattr(dirid).await {
-        Ok(v) => {
-            let wccattr = nfs::wcc_attr {
-                size: v.size,
-                mtime: v.mtime,
-                ctime: v.ctime,
-            };
-            nfs::pre_op_attr::attributes(wccattr)
-        }
+    let pre_dir_attr = match context.vfs.wcc_attr(dirid).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
         Err(stat) => {
             error!("Cannot stat directory");
             make_success_reply(xid).serialize(output)?;
@@ -1735,7 +1905,7 @@ pub async fn nfsproc3_rename(
     );
 
     // find the from directory
-    let from_dirid = context.vfs.fh_to_id(&fromdirops.dir);
+    let from_dirid = fh_to_id(context, &fromdirops.dir);
     if let Err(stat) = from_dirid {
         // directory does not exist
         make_success_reply(xid).serialize(output)?;
@@ -1746,7 +1916,7 @@ pub async fn nfsproc3_rename(
     }
 
     // find the to directory
-    let to_dirid = context.vfs.fh_to_id(&todirops.dir);
+    let to_dirid = fh_to_id(context, &todirops.dir);
     if let Err(stat) = to_dirid {
         // directory does not exist
         make_success_reply(xid).serialize(output)?;
@@ -1761,15 +1931,8 @@ pub async fn nfsproc3_rename(
     let to_dirid = to_dirid.unwrap();
 
     // get the object attributes before the write
-    let pre_from_dir_attr = match context.vfs.getattr(from_dirid).await {
-        Ok(v) => {
-            let wccattr = nfs::wcc_attr {
-                size: v.size,
-                mtime: v.mtime,
-                ctime: v.ctime,
-            };
-            nfs::pre_op_attr::attributes(wccattr)
-        }
+    let pre_from_dir_attr = match context.vfs.wcc_attr(from_dirid).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
         Err(stat) => {
             error!("Cannot stat directory");
             make_success_reply(xid).serialize(output)?;
@@ -1780,15 +1943,8 @@ pub async fn nfsproc3_rename(
     };
 
     // get the object attributes before the write
-    let pre_to_dir_attr = match context.vfs.getattr(to_dirid).await {
-        Ok(v) => {
-            let wccattr = nfs::wcc_attr {
-                size: v.size,
-                mtime: v.mtime,
-                ctime: v.ctime,
-            };
-            nfs::pre_op_attr::attributes(wccattr)
-        }
+    let pre_to_dir_attr = match context.vfs.wcc_attr(to_dirid).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
         Err(stat) => {
             error!("Cannot stat directory");
             make_success_reply(xid).serialize(output)?;
@@ -1844,6 +2000,271 @@ pub async fn nfsproc3_rename(
     Ok(())
 }
 
+/*
+ MKNOD3res NFSPROC3_MKNOD(MKNOD3args) = 11;
+
+      struct devicedata3 {
+           sattr3     dev_attributes;
+           specdata3  spec;
+      };
+
+      union mknoddata3 switch (ftype3 type) {
+      case NF3CHR:
+      case NF3BLK:
+           devicedata3  device;
+      case NF3SOCK:
+      case NF3FIFO:
+           sattr3       pipe_attributes;
+      default:
+           void;
+      };
+
+      struct MKNOD3args {
+           diropargs3   where;
+           mknoddata3   what;
+      };
+
+      struct MKNOD3resok {
+           post_op_fh3   obj;
+           post_op_attr  obj_attributes;
+           wcc_data      dir_wcc;
+      };
+
+      struct MKNOD3resfail {
+           wcc_data      dir_wcc;
+      };
+
+      union MKNOD3res switch (nfsstat3 status) {
+      case NFS3_OK:
+           MKNOD3resok   resok;
+      default:
+           MKNOD3resfail resfail;
+      };
+*/
+
+pub async fn nfsproc3_mknod(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    // if we do not have write capabilities
+    if !matches!(context.vfs.capabilities(), VFSCapabilities::ReadWrite) {
+        warn!("No write capabilities.");
+        make_success_reply(xid).serialize(output)?;
+        nfs::nfsstat3::NFS3ERR_ROFS.serialize(output)?;
+        nfs::wcc_data::default().serialize(output)?;
+        return Ok(());
+    }
+
+    let mut dirops = nfs::diropargs3::default();
+    dirops.deserialize(input)?;
+    let mut ftype = nfs::ftype3::default();
+    ftype.deserialize(input)?;
+    let mut attr = nfs::sattr3::default();
+    let mut spec = nfs::specdata3::default();
+    let valid_type = match ftype {
+        nfs::ftype3::NF3CHR | nfs::ftype3::NF3BLK => {
+            attr.deserialize(input)?;
+            spec.deserialize(input)?;
+            true
+        }
+        nfs::ftype3::NF3SOCK | nfs::ftype3::NF3FIFO => {
+            attr.deserialize(input)?;
+            true
+        }
+        _ => false,
+    };
+
+    debug!(
+        "nfsproc3_mknod({:?}, {:?}, {:?}, {:?}, {:?}) ",
+        xid, dirops, ftype, attr, spec
+    );
+
+    // find the directory we are supposed to create the
+    // new node in
+    let dirid = fh_to_id(context, &dirops.dir);
+    if let Err(stat) = dirid {
+        // directory does not exist
+        make_success_reply(xid).serialize(output)?;
+        stat.serialize(output)?;
+        nfs::wcc_data::default().serialize(output)?;
+        error!("Directory does not exist");
+        return Ok(());
+    }
+    // found the directory, get the attributes
+    let dirid = dirid.unwrap();
+
+    // get the object attributes before the write
+    let pre_dir_attr = match context.vfs.wcc_attr(dirid).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
+        Err(stat) => {
+            error!("Cannot stat directory");
+            make_success_reply(xid).serialize(output)?;
+            stat.serialize(output)?;
+            nfs::wcc_data::default().serialize(output)?;
+            return Ok(());
+        }
+    };
+
+    // regular files, directories and symlinks have their own procedures
+    let res = if valid_type {
+        context
+            .vfs
+            .mknod(dirid, &dirops.name, ftype, spec, &attr)
+            .await
+    } else {
+        Err(nfs::nfsstat3::NFS3ERR_BADTYPE)
+    };
+
+    // Re-read dir attributes for post op attr
+    let post_dir_attr = match context.vfs.getattr(dirid).await {
+        Ok(v) => nfs::post_op_attr::attributes(v),
+        Err(_) => nfs::post_op_attr::Void,
+    };
+    let wcc_res = nfs::wcc_data {
+        before: pre_dir_attr,
+        after: post_dir_attr,
+    };
+
+    match res {
+        Ok((fid, fattr)) => {
+            debug!("mknod success --> {:?}, {:?}", fid, fattr);
+            make_success_reply(xid).serialize(output)?;
+            nfs::nfsstat3::NFS3_OK.serialize(output)?;
+            // serialize MKNOD3resok
+            let fh = context.vfs.id_to_fh(fid);
+            nfs::post_op_fh3::handle(fh).serialize(output)?;
+            nfs::post_op_attr::attributes(fattr).serialize(output)?;
+            wcc_res.serialize(output)?;
+        }
+        Err(e) => {
+            error!("mknod error --> {:?}", e);
+            // serialize MKNOD3resfail
+            make_success_reply(xid).serialize(output)?;
+            e.serialize(output)?;
+            wcc_res.serialize(output)?;
+        }
+    }
+
+    Ok(())
+}
+
+/*
+ LINK3res NFSPROC3_LINK(LINK3args) = 15;
+
+      struct LINK3args {
+           nfs_fh3          file;
+           diropargs3       link;
+      };
+
+      struct LINK3resok {
+           post_op_attr   file_attributes;
+           wcc_data       linkdir_wcc;
+      };
+
+      struct LINK3resfail {
+           post_op_attr   file_attributes;
+           wcc_data       linkdir_wcc;
+      };
+
+      union LINK3res switch (nfsstat3 status) {
+      case NFS3_OK:
+           LINK3resok    resok;
+      default:
+           LINK3resfail  resfail;
+      };
+*/
+
+pub async fn nfsproc3_link(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    // if we do not have write capabilities
+    if !matches!(context.vfs.capabilities(), VFSCapabilities::ReadWrite) {
+        warn!("No write capabilities.");
+        make_success_reply(xid).serialize(output)?;
+        nfs::nfsstat3::NFS3ERR_ROFS.serialize(output)?;
+        nfs::post_op_attr::Void.serialize(output)?;
+        nfs::wcc_data::default().serialize(output)?;
+        return Ok(());
+    }
+
+    let mut handle = nfs::nfs_fh3::default();
+    let mut linkdirops = nfs::diropargs3::default();
+    handle.deserialize(input)?;
+    linkdirops.deserialize(input)?;
+
+    debug!("nfsproc3_link({:?}, {:?}, {:?}) ", xid, handle, linkdirops);
+
+    // find the file and the directory to link it into
+    let ids = fh_to_id(context, &handle)
+        .and_then(|id| Ok((id, fh_to_id(context, &linkdirops.dir)?)));
+    let (fileid, linkdirid) = match ids {
+        Ok(ids) => ids,
+        Err(stat) => {
+            make_success_reply(xid).serialize(output)?;
+            stat.serialize(output)?;
+            nfs::post_op_attr::Void.serialize(output)?;
+            nfs::wcc_data::default().serialize(output)?;
+            error!("File or directory does not exist");
+            return Ok(());
+        }
+    };
+
+    // get the directory attributes before the link
+    let pre_dir_attr = match context.vfs.wcc_attr(linkdirid).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
+        Err(stat) => {
+            error!("Cannot stat directory");
+            make_success_reply(xid).serialize(output)?;
+            stat.serialize(output)?;
+            nfs::post_op_attr::Void.serialize(output)?;
+            nfs::wcc_data::default().serialize(output)?;
+            return Ok(());
+        }
+    };
+
+    let res = context
+        .vfs
+        .link(fileid, linkdirid, &linkdirops.name)
+        .await;
+
+    // Re-read attributes for post op attr. The link count of the file
+    // has changed.
+    let file_attr = match context.vfs.getattr(fileid).await {
+        Ok(v) => nfs::post_op_attr::attributes(v),
+        Err(_) => nfs::post_op_attr::Void,
+    };
+    let post_dir_attr = match context.vfs.getattr(linkdirid).await {
+        Ok(v) => nfs::post_op_attr::attributes(v),
+        Err(_) => nfs::post_op_attr::Void,
+    };
+    let wcc_res = nfs::wcc_data {
+        before: pre_dir_attr,
+        after: post_dir_attr,
+    };
+
+    match res {
+        Ok(()) => {
+            debug!("link success");
+            make_success_reply(xid).serialize(output)?;
+            nfs::nfsstat3::NFS3_OK.serialize(output)?;
+        }
+        Err(e) => {
+            error!("link error {:?} --> {:?}", xid, e);
+            make_success_reply(xid).serialize(output)?;
+            e.serialize(output)?;
+        }
+    }
+    file_attr.serialize(output)?;
+    wcc_res.serialize(output)?;
+
+    Ok(())
+}
+
 /*
      MKDIR3res NFSPROC3_MKDIR(MKDIR3args) = 9;
 
@@ -1900,7 +2321,7 @@ pub async fn nfsproc3_mkdir(
 
     // find the directory we are supposed to create the
     // new file in
-    let dirid = context.vfs.fh_to_id(&args.dirops.dir);
+    let dirid = fh_to_id(context, &args.dirops.dir);
     if let Err(stat) = dirid {
         // directory does not exist
         make_success_reply(xid).serialize(output)?;
@@ -1913,15 +2334,8 @@ pub async fn nfsproc3_mkdir(
     let dirid = dirid.unwrap();
 
     // get the object attributes before the write
-    let pre_dir_attr = match context.vfs.getattr(dirid).await {
-        Ok(v) => {
-            let wccattr = nfs::wcc_attr {
-                size: v.size,
-                mtime: v.mtime,
-                ctime: v.ctime,
-            };
-            nfs::pre_op_attr::attributes(wccattr)
-        }
+    let pre_dir_attr = match context.vfs.wcc_attr(dirid).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
         Err(stat) => {
             error!("Cannot stat directory");
             make_success_reply(xid).serialize(output)?;
@@ -1931,7 +2345,10 @@ pub async fn nfsproc3_mkdir(
         }
     };
 
-    let res = context.vfs.mkdir(dirid, &args.dirops.name).await;
+    let res = context
+        .vfs
+        .mkdir_with_attrs(dirid, &args.dirops.name, &args.attributes)
+        .await;
 
     // Re-read dir attributes for post op attr
     let post_dir_attr = match context.vfs.getattr(dirid).await {
@@ -2026,7 +2443,7 @@ pub async fn nfsproc3_symlink(
 
     // find the directory we are supposed to create the
     // new file in
-    let dirid = context.vfs.fh_to_id(&args.dirops.dir);
+    let dirid = fh_to_id(context, &args.dirops.dir);
     if let Err(stat) = dirid {
         // directory does not exist
         make_success_reply(xid).serialize(output)?;
@@ -2039,15 +2456,8 @@ pub async fn nfsproc3_symlink(
     let dirid = dirid.unwrap();
 
     // get the object attributes before the write
-    let pre_dir_attr = match context.vfs.getattr(dirid).await {
-        Ok(v) => {
-            let wccattr = nfs::wcc_attr {
-                size: v.size,
-                mtime: v.mtime,
-                ctime: v.ctime,
-            };
-            nfs::pre_op_attr::attributes(wccattr)
-        }
+    let pre_dir_attr = match context.vfs.wcc_attr(dirid).await {
+        Ok(wccattr) => nfs::pre_op_attr::attributes(wccattr),
         Err(stat) => {
             error!("Cannot stat directory");
             make_success_reply(xid).serialize(output)?;
@@ -2134,7 +2544,7 @@ pub async fn nfsproc3_readlink(
     handle.deserialize(input)?;
     debug!("nfsproc3_readlink({:?},{:?}) ", xid, handle);
 
-    let id = context.vfs.fh_to_id(&handle);
+    let id = fh_to_id(context, &handle);
     // fail if unable to convert file handle
     if let Err(stat) = id {
         make_success_reply(xid).serialize(output)?;
@@ -0,0 +1,160 @@
+// this is just a complete enumeration of everything in the specification
+#![allow(dead_code)]
+// And its nice to keep the original names and case
+#![allow(non_camel_case_types)]
+
+use crate::xdr::*;
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::cast::FromPrimitive;
+use std::io::{Read, Write};
+// Transcribed from the Network Lock Manager protocol, version 4, as given
+// in X/Open XNFS and RFC 1813 Section 6.1
+
+pub const PROGRAM: u32 = 100021;
+pub const VERSION: u32 = 4;
+
+pub const LM_MAXSTRLEN: u32 = 1024; /* Maximum bytes in a caller name */
+
+pub type netobj = Vec<u8>;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum nlm4_stats {
+    #[default]
+    NLM4_GRANTED = 0,             /* the lock is held, or was released */
+    NLM4_DENIED = 1,              /* another holds a conflicting lock */
+    NLM4_DENIED_NOLOCKS = 2,      /* no more locks can be held */
+    NLM4_BLOCKED = 3,             /* the lock is granted once it is free */
+    NLM4_DENIED_GRACE_PERIOD = 4, /* locks are only reclaimed for now */
+    NLM4_DEADLCK = 5,             /* waiting would deadlock */
+    NLM4_ROFS = 6,                /* the file system is read-only */
+    NLM4_STALE_FH = 7,            /* the file handle is stale */
+    NLM4_FBIG = 8,                /* the range is too large */
+    NLM4_FAILED = 9,              /* anything else */
+}
+XDREnumSerde!(nlm4_stats);
+
+/// Who holds a lock which conflicts with the one tested
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_holder {
+    pub exclusive: bool,
+    pub svid: i32,
+    pub oh: netobj,
+    pub l_offset: u64,
+    pub l_len: u64,
+}
+XDRStruct!(nlm4_holder, exclusive, svid, oh, l_offset, l_len);
+
+/// The range of a file a process of a client locks, a length of 0 running
+/// to the end of the file however large it grows
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_lock {
+    pub caller_name: Vec<u8>,
+    pub fh: netobj,
+    pub oh: netobj,
+    pub svid: i32,
+    pub l_offset: u64,
+    pub l_len: u64,
+}
+XDRStruct!(nlm4_lock, caller_name, fh, oh, svid, l_offset, l_len);
+
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_lockargs {
+    pub cookie: netobj,
+    pub block: bool,
+    pub exclusive: bool,
+    pub alock: nlm4_lock,
+    pub reclaim: bool,
+    pub state: i32,
+}
+XDRStruct!(nlm4_lockargs, cookie, block, exclusive, alock, reclaim, state);
+
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_cancargs {
+    pub cookie: netobj,
+    pub block: bool,
+    pub exclusive: bool,
+    pub alock: nlm4_lock,
+}
+XDRStruct!(nlm4_cancargs, cookie, block, exclusive, alock);
+
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_testargs {
+    pub cookie: netobj,
+    pub exclusive: bool,
+    pub alock: nlm4_lock,
+}
+XDRStruct!(nlm4_testargs, cookie, exclusive, alock);
+
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_unlockargs {
+    pub cookie: netobj,
+    pub alock: nlm4_lock,
+}
+XDRStruct!(nlm4_unlockargs, cookie, alock);
+
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_res {
+    pub cookie: netobj,
+    pub stat: nlm4_stats,
+}
+XDRStruct!(nlm4_res, cookie, stat);
+
+/// union nlm4_testrply switch (nlm4_stats stat) {
+///     case NLM4_DENIED:
+///         nlm4_holder holder;
+///     default:
+///         void;
+/// };
+#[derive(Clone, Debug)]
+pub enum nlm4_testrply {
+    denied(nlm4_holder),
+    stat(nlm4_stats),
+}
+
+impl Default for nlm4_testrply {
+    fn default() -> nlm4_testrply {
+        nlm4_testrply::stat(nlm4_stats::NLM4_GRANTED)
+    }
+}
+
+impl XDR for nlm4_testrply {
+    fn serialize<R: Write>(&self, dest: &mut R) -> std::io::Result<()> {
+        match self {
+            nlm4_testrply::denied(holder) => {
+                nlm4_stats::NLM4_DENIED.serialize(dest)?;
+                holder.serialize(dest)
+            }
+            nlm4_testrply::stat(stat) => stat.serialize(dest),
+        }
+    }
+    fn deserialize<R: Read>(&mut self, src: &mut R) -> std::io::Result<()> {
+        let mut stat = nlm4_stats::default();
+        stat.deserialize(src)?;
+        *self = if stat == nlm4_stats::NLM4_DENIED {
+            let mut holder = nlm4_holder::default();
+            holder.deserialize(src)?;
+            nlm4_testrply::denied(holder)
+        } else {
+            nlm4_testrply::stat(stat)
+        };
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_testres {
+    pub cookie: netobj,
+    pub test_stat: nlm4_testrply,
+}
+XDRStruct!(nlm4_testres, cookie, test_stat);
+
+/// Sent as the client named restarts, and so holds none of its locks
+#[derive(Clone, Debug, Default)]
+pub struct nlm4_notify {
+    pub name: Vec<u8>,
+    pub state: i32,
+}
+XDRStruct!(nlm4_notify, name, state);
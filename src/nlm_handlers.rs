@@ -0,0 +1,372 @@
+use crate::callback;
+use crate::context::RPCContext;
+use crate::locks::{Granted, Lock, LockOwner};
+use crate::nfs::{fileid3, nfs_fh3};
+use crate::nlm::*;
+use crate::rpc::*;
+use crate::xdr::*;
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::cast::FromPrimitive;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use tracing::{debug, warn};
+
+/*
+ From X/Open XNFS and RFC 1813 Section 6.1
+
+ program NLM_PROG {
+    version NLM4_VERS {
+       void         NLMPROC4_NULL(void)                  = 0;
+       nlm4_testres NLMPROC4_TEST(nlm4_testargs)         = 1;
+       nlm4_res     NLMPROC4_LOCK(nlm4_lockargs)         = 2;
+       nlm4_res     NLMPROC4_CANCEL(nlm4_cancargs)       = 3;
+       nlm4_res     NLMPROC4_UNLOCK(nlm4_unlockargs)     = 4;
+       nlm4_res     NLMPROC4_GRANTED(nlm4_testargs)      = 5;
+       void         NLMPROC4_TEST_MSG(nlm4_testargs)     = 6;
+       void         NLMPROC4_LOCK_MSG(nlm4_lockargs)     = 7;
+       void         NLMPROC4_CANCEL_MSG(nlm4_cancargs)   = 8;
+       void         NLMPROC4_UNLOCK_MSG(nlm4_unlockargs) = 9;
+       void         NLMPROC4_GRANTED_MSG(nlm4_testargs)  = 10;
+       void         NLMPROC4_TEST_RES(nlm4_testres)      = 11;
+       void         NLMPROC4_LOCK_RES(nlm4_res)          = 12;
+       void         NLMPROC4_CANCEL_RES(nlm4_res)        = 13;
+       void         NLMPROC4_UNLOCK_RES(nlm4_res)        = 14;
+       void         NLMPROC4_GRANTED_RES(nlm4_res)       = 15;
+       nlm4_shareres NLMPROC4_SHARE(nlm4_shareargs)      = 20;
+       nlm4_shareres NLMPROC4_UNSHARE(nlm4_shareargs)    = 21;
+       nlm4_res     NLMPROC4_NM_LOCK(nlm4_lockargs)      = 22;
+       void         NLMPROC4_FREE_ALL(nlm4_notify)       = 23;
+    } = 4;
+ } = 100021;
+
+ Only the calls answered in the reply are served. The _MSG calls expect
+ their result to be sent back as a call to the client. The GRANTED calls
+ are made by servers to clients, not the other way around: a blocked lock
+ is granted as soon as it is free, and the lock manager of the client
+ told so with a GRANTED call.
+*/
+
+#[allow(non_camel_case_types)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, FromPrimitive, ToPrimitive)]
+enum NLMProgram {
+    NLMPROC4_NULL = 0,
+    NLMPROC4_TEST = 1,
+    NLMPROC4_LOCK = 2,
+    NLMPROC4_CANCEL = 3,
+    NLMPROC4_UNLOCK = 4,
+    NLMPROC4_NM_LOCK = 22,
+    NLMPROC4_FREE_ALL = 23,
+    INVALID,
+}
+
+pub async fn handle_nlm(
+    xid: u32,
+    call: call_body,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    if call.vers != VERSION {
+        debug!("Invalid NLM Version number {} != {}", call.vers, VERSION);
+        prog_mismatch_reply_message(xid, VERSION).serialize(output)?;
+        return Ok(());
+    }
+    let prog = NLMProgram::from_u32(call.proc).unwrap_or(NLMProgram::INVALID);
+
+    match prog {
+        NLMProgram::NLMPROC4_NULL => nlmproc4_null(xid, input, output)?,
+        NLMProgram::NLMPROC4_TEST => nlmproc4_test(xid, input, output, context).await?,
+        NLMProgram::NLMPROC4_LOCK | NLMProgram::NLMPROC4_NM_LOCK => {
+            nlmproc4_lock(xid, input, output, context).await?
+        }
+        NLMProgram::NLMPROC4_CANCEL => nlmproc4_cancel(xid, input, output, context).await?,
+        NLMProgram::NLMPROC4_UNLOCK => nlmproc4_unlock(xid, input, output, context).await?,
+        NLMProgram::NLMPROC4_FREE_ALL => nlmproc4_free_all(xid, input, output, context)?,
+        _ => {
+            proc_unavail_reply_message(xid).serialize(output)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn nlmproc4_null(
+    xid: u32,
+    _: &mut impl Read,
+    output: &mut impl Write,
+) -> Result<(), anyhow::Error> {
+    debug!("nlmproc4_null({:?}) ", xid);
+    let msg = make_success_reply(xid);
+    debug!("\t{:?} --> {:?}", xid, msg);
+    msg.serialize(output)?;
+    Ok(())
+}
+
+/// The file `lock` is of, if it is still there
+async fn locked_file(lock: &nlm4_lock, context: &RPCContext) -> Result<fileid3, nlm4_stats> {
+    let fh = nfs_fh3 {
+        data: lock.fh.clone(),
+    };
+    let id = context
+        .vfs
+        .fh_to_id(&fh)
+        .map_err(|_| nlm4_stats::NLM4_STALE_FH)?;
+    context
+        .vfs
+        .getattr(id)
+        .await
+        .map_err(|_| nlm4_stats::NLM4_STALE_FH)?;
+    Ok(id)
+}
+
+fn owner_of(lock: &nlm4_lock) -> LockOwner {
+    LockOwner {
+        client: lock.caller_name.clone(),
+        svid: lock.svid,
+        handle: lock.oh.clone(),
+    }
+}
+
+/// Where the range `lock` locks ends
+fn end_of(lock: &nlm4_lock) -> u64 {
+    match lock.l_len {
+        0 => u64::MAX,
+        len => lock.l_offset.saturating_add(len),
+    }
+}
+
+fn to_lock(lock: &nlm4_lock, exclusive: bool) -> Lock {
+    Lock {
+        owner: owner_of(lock),
+        exclusive,
+        start: lock.l_offset,
+        end: end_of(lock),
+    }
+}
+
+fn to_holder(lock: &Lock) -> nlm4_holder {
+    nlm4_holder {
+        exclusive: lock.exclusive,
+        svid: lock.owner.svid,
+        oh: lock.owner.handle.clone(),
+        l_offset: lock.start,
+        l_len: match lock.end {
+            u64::MAX => 0,
+            end => end - lock.start,
+        },
+    }
+}
+
+/// Tells the lock manager of the client calling that the lock `args`
+/// waits for on `id` is granted, once it is. A client which answers it
+/// no longer waits for the lock gets it released again, while one which
+/// does not answer keeps it, and finds it held as it asks again.
+fn call_back_granted(context: &RPCContext, id: fileid3, args: &nlm4_lockargs) -> Granted {
+    let locks = context.locks.clone();
+    let client = context.client_addr.parse::<SocketAddr>().map(|addr| addr.ip());
+    let granted = nlm4_testargs {
+        cookie: args.cookie.clone(),
+        exclusive: args.exclusive,
+        alock: args.alock.clone(),
+    };
+    Box::new(move || {
+        let Ok(client) = client else {
+            return;
+        };
+        tokio::spawn(async move {
+            match call_granted(client, &granted).await {
+                Ok(nlm4_stats::NLM4_GRANTED) => {
+                    debug!("{} took the lock granted to it", client);
+                }
+                Ok(stat) => {
+                    debug!("{} no longer waits for its lock: {:?}", client, stat);
+                    let lock = &granted.alock;
+                    locks.unlock(id, &owner_of(lock), lock.l_offset, end_of(lock));
+                }
+                Err(e) => warn!("Unable to tell {} its lock is granted: {}", client, e),
+            }
+        });
+    })
+}
+
+/// Calls NLMPROC4_GRANTED on the lock manager of `client`
+async fn call_granted(client: IpAddr, args: &nlm4_testargs) -> std::io::Result<nlm4_stats> {
+    const NLMPROC4_GRANTED: u32 = 5;
+    let port = callback::port_of(client, PROGRAM, VERSION).await?;
+    let res: nlm4_res = callback::call(
+        SocketAddr::new(client, port),
+        PROGRAM,
+        VERSION,
+        NLMPROC4_GRANTED,
+        args,
+    )
+    .await?;
+    Ok(res.stat)
+}
+
+/*
+ nlm4_testres NLMPROC4_TEST(nlm4_testargs) = 1;
+
+ Tests whether the lock could be held, and if not tells who holds one
+ keeping it from being held.
+*/
+pub async fn nlmproc4_test(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    let mut args = nlm4_testargs::default();
+    args.deserialize(input)?;
+    debug!("nlmproc4_test({:?},{:?}) ", xid, args);
+    let test_stat = match locked_file(&args.alock, context).await {
+        Ok(id) => match context
+            .locks
+            .test(id, &to_lock(&args.alock, args.exclusive))
+        {
+            Some(held) => nlm4_testrply::denied(to_holder(&held)),
+            None => nlm4_testrply::stat(nlm4_stats::NLM4_GRANTED),
+        },
+        Err(stat) => nlm4_testrply::stat(stat),
+    };
+    let res = nlm4_testres {
+        cookie: args.cookie,
+        test_stat,
+    };
+    debug!("\t{:?} --> {:?}", xid, res);
+    make_success_reply(xid).serialize(output)?;
+    res.serialize(output)?;
+    Ok(())
+}
+
+/*
+ nlm4_res NLMPROC4_LOCK(nlm4_lockargs) = 2;
+
+ Holds the lock if no other process holds one keeping it from being
+ held. A client which asked to wait for it is told it is blocked, and
+ called back once it is granted, one which did not is denied it.
+*/
+pub async fn nlmproc4_lock(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    let mut args = nlm4_lockargs::default();
+    args.deserialize(input)?;
+    debug!("nlmproc4_lock({:?},{:?}) ", xid, args);
+    let lock = to_lock(&args.alock, args.exclusive);
+    let stat = match locked_file(&args.alock, context).await {
+        Ok(id) if args.block => {
+            let granted = call_back_granted(context, id, &args);
+            match context.locks.lock_or_wait(id, lock, granted) {
+                Ok(()) => nlm4_stats::NLM4_GRANTED,
+                Err(_) => nlm4_stats::NLM4_BLOCKED,
+            }
+        }
+        Ok(id) => match context.locks.lock(id, lock) {
+            Ok(()) => nlm4_stats::NLM4_GRANTED,
+            Err(_) => nlm4_stats::NLM4_DENIED,
+        },
+        Err(stat) => stat,
+    };
+    let res = nlm4_res {
+        cookie: args.cookie,
+        stat,
+    };
+    debug!("\t{:?} --> {:?}", xid, res);
+    make_success_reply(xid).serialize(output)?;
+    res.serialize(output)?;
+    Ok(())
+}
+
+/*
+ nlm4_res NLMPROC4_CANCEL(nlm4_cancargs) = 3;
+
+ Cancels a blocked lock, which the client no longer waits for.
+ Cancelling a lock which is not waited for succeeds.
+*/
+pub async fn nlmproc4_cancel(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    let mut args = nlm4_cancargs::default();
+    args.deserialize(input)?;
+    debug!("nlmproc4_cancel({:?},{:?}) ", xid, args);
+    let stat = match locked_file(&args.alock, context).await {
+        Ok(id) => {
+            context.locks.cancel(
+                id,
+                &owner_of(&args.alock),
+                args.alock.l_offset,
+                end_of(&args.alock),
+            );
+            nlm4_stats::NLM4_GRANTED
+        }
+        Err(stat) => stat,
+    };
+    let res = nlm4_res {
+        cookie: args.cookie,
+        stat,
+    };
+    make_success_reply(xid).serialize(output)?;
+    res.serialize(output)?;
+    Ok(())
+}
+
+/*
+ nlm4_res NLMPROC4_UNLOCK(nlm4_unlockargs) = 4;
+
+ Releases the range. Releasing a range which is not locked succeeds.
+*/
+pub async fn nlmproc4_unlock(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    let mut args = nlm4_unlockargs::default();
+    args.deserialize(input)?;
+    debug!("nlmproc4_unlock({:?},{:?}) ", xid, args);
+    let stat = match locked_file(&args.alock, context).await {
+        Ok(id) => {
+            context.locks.unlock(
+                id,
+                &owner_of(&args.alock),
+                args.alock.l_offset,
+                end_of(&args.alock),
+            );
+            nlm4_stats::NLM4_GRANTED
+        }
+        Err(stat) => stat,
+    };
+    let res = nlm4_res {
+        cookie: args.cookie,
+        stat,
+    };
+    debug!("\t{:?} --> {:?}", xid, res);
+    make_success_reply(xid).serialize(output)?;
+    res.serialize(output)?;
+    Ok(())
+}
+
+/*
+ void NLMPROC4_FREE_ALL(nlm4_notify) = 23;
+
+ Releases every lock of a client which restarted.
+*/
+pub fn nlmproc4_free_all(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    let mut args = nlm4_notify::default();
+    args.deserialize(input)?;
+    debug!("nlmproc4_free_all({:?},{:?}) ", xid, args);
+    context.locks.release_client(&args.name);
+    make_success_reply(xid).serialize(output)?;
+    Ok(())
+}
@@ -0,0 +1,23 @@
+// this is just a complete enumeration of everything in the specification
+#![allow(dead_code)]
+// And its nice to keep the original names and case
+#![allow(non_camel_case_types)]
+
+use crate::xdr::*;
+use std::io::{Read, Write};
+// Transcribed from the Network Status Monitor protocol, version 1, as
+// given in X/Open XNFS
+
+pub const PROGRAM: u32 = 100024;
+pub const VERSION: u32 = 1;
+
+pub const SM_MAXSTRLEN: u32 = 1024; /* Maximum bytes in a host name */
+
+/// Sent by the status monitor of a host as it restarts, and so no longer
+/// holds the locks it held
+#[derive(Clone, Debug, Default)]
+pub struct stat_chge {
+    pub mon_name: Vec<u8>,
+    pub state: i32,
+}
+XDRStruct!(stat_chge, mon_name, state);
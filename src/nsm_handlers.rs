@@ -0,0 +1,94 @@
+use crate::context::RPCContext;
+use crate::nsm::*;
+use crate::rpc::*;
+use crate::xdr::*;
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::cast::FromPrimitive;
+use std::io::{Read, Write};
+use tracing::{debug, info};
+
+/*
+ From X/Open XNFS
+
+ program SM_PROG {
+    version SM_VERS {
+       void         SM_NULL(void)                        = 0;
+       sm_stat_res  SM_STAT(sm_name)                     = 1;
+       sm_stat_res  SM_MON(mon)                          = 2;
+       sm_stat      SM_UNMON(mon_id)                     = 3;
+       sm_stat      SM_UNMON_ALL(my_id)                  = 4;
+       void         SM_SIMU_CRASH(void)                  = 5;
+       void         SM_NOTIFY(stat_chge)                 = 6;
+    } = 1;
+ } = 100024;
+
+ Only SM_NOTIFY is served, which the status monitor of a client calls as
+ the client restarts, so that the locks it held before are released. The
+ other calls are made by a lock manager to the status monitor on its own
+ host, which the server does not ask to watch its clients.
+*/
+
+#[allow(non_camel_case_types)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, FromPrimitive, ToPrimitive)]
+enum NSMProgram {
+    SM_NULL = 0,
+    SM_NOTIFY = 6,
+    INVALID,
+}
+
+pub fn handle_nsm(
+    xid: u32,
+    call: call_body,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    if call.vers != VERSION {
+        debug!("Invalid NSM Version number {} != {}", call.vers, VERSION);
+        prog_mismatch_reply_message(xid, VERSION).serialize(output)?;
+        return Ok(());
+    }
+    let prog = NSMProgram::from_u32(call.proc).unwrap_or(NSMProgram::INVALID);
+
+    match prog {
+        NSMProgram::SM_NULL => sm_null(xid, input, output)?,
+        NSMProgram::SM_NOTIFY => sm_notify(xid, input, output, context)?,
+        _ => {
+            proc_unavail_reply_message(xid).serialize(output)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn sm_null(xid: u32, _: &mut impl Read, output: &mut impl Write) -> Result<(), anyhow::Error> {
+    debug!("sm_null({:?}) ", xid);
+    let msg = make_success_reply(xid);
+    debug!("\t{:?} --> {:?}", xid, msg);
+    msg.serialize(output)?;
+    Ok(())
+}
+
+/*
+ void SM_NOTIFY(stat_chge) = 6;
+
+ Releases every lock of a client which restarted, as its lock manager
+ holds none of them any longer.
+*/
+pub fn sm_notify(
+    xid: u32,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    context: &RPCContext,
+) -> Result<(), anyhow::Error> {
+    let mut args = stat_chge::default();
+    args.deserialize(input)?;
+    debug!("sm_notify({:?},{:?}) ", xid, args);
+    info!(
+        "{} restarted, releasing its locks",
+        String::from_utf8_lossy(&args.mon_name)
+    );
+    context.locks.release_client(&args.mon_name);
+    make_success_reply(xid).serialize(output)?;
+    Ok(())
+}
@@ -102,9 +102,9 @@ XDREnumSerde!(auth_flavor);
 pub struct auth_unix {
     stamp: u32,
     machinename: Vec<u8>,
-    uid: u32,
-    gid: u32,
-    gids: Vec<u32>,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) gids: Vec<u32>,
 }
 XDRStruct!(auth_unix, stamp, machinename, uid, gid, gids);
 
@@ -268,7 +268,6 @@ XDRStruct!(call_body, rpcvers, prog, vers, proc, cred, verf);
 #[allow(non_camel_case_types)]
 #[derive(Clone, Debug)]
 #[repr(u32)]
-
 pub enum reply_body {
     MSG_ACCEPTED(accepted_reply),
     MSG_DENIED(rejected_reply),
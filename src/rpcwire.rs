@@ -1,6 +1,8 @@
 use anyhow::anyhow;
+use bytes::Buf;
 use std::io::Cursor;
 use std::io::{Read, Write};
+use std::sync::Mutex;
 use tracing::{debug, error, trace, warn};
 
 use crate::context::RPCContext;
@@ -13,6 +15,11 @@ use crate::mount_handlers;
 use crate::nfs;
 use crate::nfs_handlers;
 
+use crate::nlm;
+use crate::nlm_handlers;
+use crate::nsm;
+use crate::nsm_handlers;
+
 use crate::portmap;
 use crate::portmap_handlers;
 use tokio::io::AsyncReadExt;
@@ -27,6 +34,39 @@ const NFS_ACL_PROGRAM: u32 = 100227;
 const NFS_ID_MAP_PROGRAM: u32 = 100270;
 const NFS_METADATA_PROGRAM: u32 = 200024;
 
+/// Reply buffers kept once their reply is sent, so that replies to large
+/// reads are written into a buffer large enough already, rather than one
+/// grown, and copied, as the data is written
+static REPLY_BUFFERS: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// How many reply buffers are kept at most
+const KEPT_REPLY_BUFFERS: usize = 32;
+
+/// Larger reply buffers are not kept
+const KEPT_REPLY_CAPACITY: usize = 4 * 1024 * 1024;
+
+/// A reply buffer, empty
+fn reply_buffer() -> Vec<u8> {
+    REPLY_BUFFERS
+        .lock()
+        .ok()
+        .and_then(|mut kept| kept.pop())
+        .unwrap_or_default()
+}
+
+/// Keeps the buffer of a reply which was sent, for another reply
+pub fn recycle_reply(mut buf: Vec<u8>) {
+    if buf.capacity() > KEPT_REPLY_CAPACITY {
+        return;
+    }
+    buf.clear();
+    if let Ok(mut kept) = REPLY_BUFFERS.lock() {
+        if kept.len() < KEPT_REPLY_BUFFERS {
+            kept.push(buf);
+        }
+    }
+}
+
 async fn handle_rpc(
     input: &mut impl Read,
     output: &mut impl Write,
@@ -39,7 +79,10 @@ async fn handle_rpc(
         if let auth_flavor::AUTH_UNIX = call.cred.flavor {
             let mut auth = auth_unix::default();
             auth.deserialize(&mut Cursor::new(&call.cred.body))?;
+            context.caller = context.id_mapping.map(Some(&auth));
             context.auth = auth;
+        } else {
+            context.caller = context.id_mapping.map(None);
         }
         if call.rpcvers != 2 {
             warn!("Invalid RPC version {} != 2", call.rpcvers);
@@ -61,6 +104,10 @@ async fn handle_rpc(
                 portmap_handlers::handle_portmap(xid, call, input, output, &context)
             } else if call.prog == mount::PROGRAM {
                 mount_handlers::handle_mount(xid, call, input, output, &context).await
+            } else if call.prog == nlm::PROGRAM {
+                nlm_handlers::handle_nlm(xid, call, input, output, &context).await
+            } else if call.prog == nsm::PROGRAM {
+                nsm_handlers::handle_nsm(xid, call, input, output, &context)
             } else if call.prog == NFS_ACL_PROGRAM
                 || call.prog == NFS_ID_MAP_PROGRAM
                 || call.prog == NFS_METADATA_PROGRAM
@@ -127,19 +174,37 @@ async fn read_fragment(
 
 pub async fn write_fragment(
     socket: &mut tokio::net::TcpStream,
-    buf: &Vec<u8>,
+    buf: &[u8],
 ) -> Result<(), anyhow::Error> {
     // TODO: split into many fragments
     assert!(buf.len() < (1 << 31));
     // set the last flag
     let fragment_header = buf.len() as u32 + (1 << 31);
     let header_buf = u32::to_be_bytes(fragment_header);
-    socket.write_all(&header_buf).await?;
     trace!("Writing fragment length:{}", buf.len());
-    socket.write_all(buf).await?;
+    // the header and the reply go out together, without copying them
+    // together first
+    let mut fragment = Buf::chain(&header_buf[..], buf);
+    socket.write_all_buf(&mut fragment).await?;
     Ok(())
 }
 
+/// Handles one complete RPC message, returning the reply to send if there
+/// is one to send
+pub async fn handle_message(
+    message: Vec<u8>,
+    context: RPCContext,
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    let mut write_buf = reply_buffer();
+    let mut write_cursor = Cursor::new(&mut write_buf);
+    if handle_rpc(&mut Cursor::new(message), &mut write_cursor, context).await? {
+        let _ = std::io::Write::flush(&mut write_cursor);
+        Ok(Some(write_buf))
+    } else {
+        Ok(None)
+    }
+}
+
 pub type SocketMessageType = Result<Vec<u8>, anyhow::Error>;
 
 /// The Socket Message Handler reads from a TcpStream and spawns off
@@ -182,23 +247,22 @@ impl SocketMessageHandler {
             read_fragment(&mut self.socket_receive_channel, &mut self.cur_fragment).await?;
         if is_last {
             let fragment = std::mem::take(&mut self.cur_fragment);
+            // a throttled client is not read from until it may go on
+            self.context.throttle.admit(fragment.len()).await;
             let context = self.context.clone();
             let send = self.reply_send_channel.clone();
             tokio::spawn(async move {
-                let mut write_buf: Vec<u8> = Vec::new();
-                let mut write_cursor = Cursor::new(&mut write_buf);
-                let maybe_reply =
-                    handle_rpc(&mut Cursor::new(fragment), &mut write_cursor, context).await;
-                match maybe_reply {
+                let throttle = context.throttle.clone();
+                match handle_message(fragment, context).await {
                     Err(e) => {
                         error!("RPC Error: {:?}", e);
                         let _ = send.send(Err(e));
                     }
-                    Ok(true) => {
-                        let _ = std::io::Write::flush(&mut write_cursor);
-                        let _ = send.send(Ok(write_buf));
+                    Ok(Some(reply)) => {
+                        throttle.charge(reply.len());
+                        let _ = send.send(Ok(reply));
                     }
-                    Ok(false) => {
+                    Ok(None) => {
                         // do not reply
                     }
                 }
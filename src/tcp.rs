@@ -1,16 +1,22 @@
+use crate::access::AccessList;
+use crate::auth::IdMapping;
+use crate::throttle::{RateLimit, Throttle};
 use crate::context::RPCContext;
+use crate::locks::LockManager;
 use crate::rpcwire::*;
 use crate::vfs::NFSFileSystem;
 use anyhow;
 use async_trait::async_trait;
 use std::net::SocketAddr;
+use std::future::Future;
 use std::sync::Arc;
 use std::{io, net::IpAddr};
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
-use tokio::sync::mpsc;
-use tracing::{debug, error, info};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
 use crate::transaction_tracker::TransactionTracker;
 
 /// A NFS Tcp Connection Handler
@@ -21,6 +27,10 @@ pub struct NFSTcpListener<T: NFSFileSystem + Send + Sync + 'static> {
     mount_signal: Option<mpsc::Sender<bool>>,
     export_name: Arc<String>,
     transaction_tracker: Arc<TransactionTracker>,
+    id_mapping: Arc<IdMapping>,
+    access: AccessList,
+    rate_limit: RateLimit,
+    locks: Arc<LockManager>,
 }
 
 pub fn generate_host_ip(hostnum: u16) -> String {
@@ -31,10 +41,29 @@ pub fn generate_host_ip(hostnum: u16) -> String {
     )
 }
 
-/// processes an established socket
+/// Splits a ipstr of the form [ip address]:port
+pub(crate) fn split_hostport(ipstr: &str) -> io::Result<(&str, u16)> {
+    let (ip, port) = ipstr.split_once(':').ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "IP Address must be of form ip:port",
+        )
+    })?;
+    let port = port.parse::<u16>().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "Port not in range 0..=65535",
+        )
+    })?;
+    Ok((ip, port))
+}
+
+/// processes an established socket until the client closes it or `stop`
+/// changes. Then answers the calls already read before returning.
 async fn process_socket(
     mut socket: tokio::net::TcpStream,
     context: RPCContext,
+    mut stop: watch::Receiver<()>,
 ) -> Result<(), anyhow::Error> {
     let (mut message_handler, mut socksend, mut msgrecvchan) = SocketMessageHandler::new(&context);
     let _ = socket.set_nodelay(true);
@@ -47,14 +76,15 @@ async fn process_socket(
             }
         }
     });
-    loop {
+    let result = loop {
         tokio::select! {
+            _ = stop.changed() => break Ok(()),
             _ = socket.readable() => {
                 let mut buf = [0; 128000];
 
                 match socket.try_read(&mut buf) {
                     Ok(0) => {
-                        return Ok(());
+                        break Ok(());
                     }
                     Ok(n) => {
                         let _ = socksend.write_all(&buf[..n]).await;
@@ -64,7 +94,7 @@ async fn process_socket(
                     }
                     Err(e) => {
                         debug!("Message handling closed : {:?}", e);
-                        return Err(e.into());
+                        break Err(e.into());
                     }
                 }
 
@@ -73,20 +103,33 @@ async fn process_socket(
                 match reply {
                     Some(Err(e)) => {
                         debug!("Message handling closed : {:?}", e);
-                        return Err(e);
+                        break Err(e);
                     }
                     Some(Ok(msg)) => {
                         if let Err(e) = write_fragment(&mut socket, &msg).await {
                             error!("Write error {:?}", e);
                         }
+                        recycle_reply(msg);
                     }
                     None => {
-                        return Err(anyhow::anyhow!("Unexpected socket context termination"));
+                        break Err(anyhow::anyhow!("Unexpected socket context termination"));
                     }
                 }
             }
         }
+    };
+    // no more calls are read, and those in flight are answered as they
+    // finish
+    drop(socksend);
+    while let Some(reply) = msgrecvchan.recv().await {
+        if let Ok(msg) = reply {
+            if let Err(e) = write_fragment(&mut socket, &msg).await {
+                debug!("Unable to reply as the connection closes: {:?}", e);
+            }
+            recycle_reply(msg);
+        }
     }
+    result
 }
 
 #[async_trait]
@@ -103,6 +146,13 @@ pub trait NFSTcp: Send + Sync {
 
     /// Loops forever and never returns handling all incoming connections.
     async fn handle_forever(&self) -> io::Result<()>;
+
+    /// Handles all incoming connections until `shutdown` completes. Then
+    /// stops accepting connections, lets the file system shut down and
+    /// returns.
+    async fn handle_until<F>(&self, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()> + Send;
 }
 
 impl<T: NFSFileSystem + Send + Sync + 'static> NFSTcpListener<T> {
@@ -110,20 +160,13 @@ impl<T: NFSFileSystem + Send + Sync + 'static> NFSTcpListener<T> {
     /// "127.0.0.1:12000". fs is an instance of an implementation
     /// of NFSFileSystem.
     pub async fn bind(ipstr: &str, fs: T) -> io::Result<NFSTcpListener<T>> {
-        let (ip, port) = ipstr.split_once(':').ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::AddrNotAvailable,
-                "IP Address must be of form ip:port",
-            )
-        })?;
-        let port = port.parse::<u16>().map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::AddrNotAvailable,
-                "Port not in range 0..=65535",
-            )
-        })?;
-
-        let arcfs: Arc<T> = Arc::new(fs);
+        Self::bind_shared(ipstr, Arc::new(fs)).await
+    }
+
+    /// Like bind, for a file system which is also served elsewhere, for
+    /// instance by a NFSUdpListener.
+    pub async fn bind_shared(ipstr: &str, arcfs: Arc<T>) -> io::Result<NFSTcpListener<T>> {
+        let (ip, port) = split_hostport(ipstr)?;
 
         if ip == "auto" {
             let mut num_tries_left = 32;
@@ -170,6 +213,10 @@ impl<T: NFSFileSystem + Send + Sync + 'static> NFSTcpListener<T> {
             mount_signal: None,
             export_name: Arc::from("/".to_string()),
             transaction_tracker: Arc::new(TransactionTracker::new(Duration::from_secs(60))),
+            id_mapping: Arc::new(IdMapping::default()),
+            access: AccessList::default(),
+            rate_limit: RateLimit::default(),
+            locks: Arc::new(LockManager::new()),
         })
     }
 
@@ -188,6 +235,32 @@ impl<T: NFSFileSystem + Send + Sync + 'static> NFSTcpListener<T> {
                 .trim_start_matches('/')
         ))
     }
+
+    /// Sets the mapping applied to the AUTH_SYS credentials of every call.
+    /// Credentials are passed through unchanged if not set.
+    pub fn with_id_mapping(&mut self, id_mapping: IdMapping) {
+        self.id_mapping = Arc::new(id_mapping);
+    }
+
+    /// Sets the networks which may connect. Connections from anywhere else
+    /// are closed as soon as they are accepted. Everyone may connect if
+    /// not set.
+    pub fn with_access_list(&mut self, access: AccessList) {
+        self.access = access;
+    }
+
+    /// Sets the rate every connection is held to. Connections are not
+    /// limited if not set.
+    pub fn with_rate_limit(&mut self, rate_limit: RateLimit) {
+        self.rate_limit = rate_limit;
+    }
+
+    /// Sets where the advisory locks clients take are held, for a file
+    /// system which is also served elsewhere, so that clients of either
+    /// see each other's locks. Locks are held by this listener if not set.
+    pub fn with_lock_manager(&mut self, locks: Arc<LockManager>) {
+        self.locks = locks;
+    }
 }
 
 #[async_trait]
@@ -211,22 +284,85 @@ impl<T: NFSFileSystem + Send + Sync + 'static> NFSTcp for NFSTcpListener<T> {
 
     /// Loops forever and never returns handling all incoming connections.
     async fn handle_forever(&self) -> io::Result<()> {
-        loop {
-            let (socket, _) = self.listener.accept().await?;
-            let context = RPCContext {
-                local_port: self.port,
-                client_addr: socket.peer_addr().unwrap().to_string(),
-                auth: crate::rpc::auth_unix::default(),
-                vfs: self.arcfs.clone(),
-                mount_signal: self.mount_signal.clone(),
-                export_name: self.export_name.clone(),
-                transaction_tracker: self.transaction_tracker.clone(),
-            };
-            info!("Accepting connection from {}", context.client_addr);
-            debug!("Accepting socket {:?} {:?}", socket, context);
-            tokio::spawn(async move {
-                let _ = process_socket(socket, context).await;
-            });
+        self.handle_until(std::future::pending()).await
+    }
+
+    /// Handles all incoming connections until `shutdown` completes. Then
+    /// stops accepting connections, finishes the calls in flight, lets
+    /// the file system shut down and returns.
+    async fn handle_until<F>(&self, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()> + Send,
+    {
+        self.serve_until(shutdown).await?;
+        info!("Shutting down");
+        self.arcfs.shutdown().await;
+        Ok(())
+    }
+}
+
+impl<T: NFSFileSystem + Send + Sync + 'static> NFSTcpListener<T> {
+    /// Handles all incoming connections until `shutdown` completes. Then
+    /// stops accepting connections, finishes the calls in flight and
+    /// returns, leaving the file system to be shut down by the caller,
+    /// for one which is also served elsewhere.
+    pub async fn serve_until<F>(&self, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()> + Send,
+    {
+        let (stop, _) = watch::channel(());
+        let mut connections = JoinSet::new();
+        tokio::pin!(shutdown);
+        let result = loop {
+            tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((socket, peer)) => {
+                        self.spawn_connection(&mut connections, &stop, socket, peer);
+                    }
+                    Err(e) => break Err(e),
+                },
+                Some(_) = connections.join_next() => {}
+                _ = &mut shutdown => break Ok(()),
+            }
+        };
+        info!("No longer accepting connections, finishing the calls in flight");
+        let _ = stop.send(());
+        while connections.join_next().await.is_some() {}
+        result
+    }
+
+    /// Serves an accepted connection in the background, among
+    /// `connections`, until `stop` changes
+    fn spawn_connection(
+        &self,
+        connections: &mut JoinSet<()>,
+        stop: &watch::Sender<()>,
+        socket: tokio::net::TcpStream,
+        peer: SocketAddr,
+    ) {
+        if !self.access.permits(peer.ip()) {
+            warn!("Refusing connection from {}", peer);
+            return;
         }
+        let context = RPCContext {
+            local_port: self.port,
+            client_addr: peer.to_string(),
+            auth: crate::rpc::auth_unix::default(),
+            caller: crate::auth::Credentials::default(),
+            id_mapping: self.id_mapping.clone(),
+            vfs: self.arcfs.clone(),
+            mount_signal: self.mount_signal.clone(),
+            export_name: self.export_name.clone(),
+            transaction_tracker: self.transaction_tracker.clone(),
+            throttle: Arc::new(Throttle::new(&self.rate_limit)),
+            locks: self.locks.clone(),
+            max_transfer: None,
+        };
+        info!("Accepting connection from {}", context.client_addr);
+        debug!("Accepting socket {:?} {:?}", socket, context);
+        let stop = stop.subscribe();
+        connections.spawn(async move {
+            let _ = process_socket(socket, context, stop).await;
+        });
     }
 }
@@ -0,0 +1,195 @@
+//! Limits on how much of the server one client may use.
+//!
+//! Every client gets token buckets for its requests and for the bytes it
+//! sends and is sent. A client which runs out has its requests held back
+//! until the buckets have refilled, so a client copying a whole export
+//! can not starve everyone else.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The rates one client is held to. Rates which are not set are not
+/// limited.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimit {
+    /// Requests per second
+    pub ops_per_sec: Option<u32>,
+    /// Bytes of requests and replies per second
+    pub bytes_per_sec: Option<u64>,
+}
+
+/// Tokens refilled at a steady rate, up to a second's worth.
+///
+/// The balance may go negative: a large reply is sent right away and the
+/// debt is paid by waiting before the next request.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+
+    /// Takes `n` tokens and returns how long to wait until they are paid for
+    fn take(&mut self, n: f64) -> Duration {
+        self.refill();
+        self.tokens -= n;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.rate
+    }
+}
+
+/// The token buckets of one client
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    ops: Option<Mutex<TokenBucket>>,
+    bytes: Option<Mutex<TokenBucket>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(limit: &RateLimit) -> Self {
+        Self {
+            ops: limit
+                .ops_per_sec
+                .filter(|rate| *rate > 0)
+                .map(|rate| Mutex::new(TokenBucket::new(rate as f64))),
+            bytes: limit
+                .bytes_per_sec
+                .filter(|rate| *rate > 0)
+                .map(|rate| Mutex::new(TokenBucket::new(rate as f64))),
+        }
+    }
+
+    /// Waits until the client may make a request of `len` bytes
+    pub(crate) async fn admit(&self, len: usize) {
+        let mut wait = Duration::ZERO;
+        if let Some(ops) = &self.ops {
+            wait = wait.max(ops.lock().expect("unable to lock throttle").take(1.0));
+        }
+        if let Some(bytes) = &self.bytes {
+            wait = wait.max(bytes.lock().expect("unable to lock throttle").take(len as f64));
+        }
+        if !wait.is_zero() {
+            tracing::trace!("Throttling request for {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Charges the client for a reply of `len` bytes
+    pub(crate) fn charge(&self, len: usize) {
+        if let Some(bytes) = &self.bytes {
+            bytes.lock().expect("unable to lock throttle").take(len as f64);
+        }
+    }
+
+    /// Whether the client has not used any of its allowance lately, so
+    /// that forgetting the throttle changes nothing
+    pub(crate) fn is_idle(&self) -> bool {
+        let full = |bucket: &Option<Mutex<TokenBucket>>| {
+            bucket
+                .as_ref()
+                .is_none_or(|b| b.lock().expect("unable to lock throttle").is_full())
+        };
+        full(&self.ops) && full(&self.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Moves the last refill of `bucket` `by` into the past, as if that
+    /// much time had gone by
+    fn age(bucket: &mut TokenBucket, by: Duration) {
+        bucket.updated -= by;
+    }
+
+    #[test]
+    fn full_bucket_admits_a_seconds_worth_at_once() {
+        let mut bucket = TokenBucket::new(10.0);
+        for _ in 0..10 {
+            assert_eq!(bucket.take(1.0), Duration::ZERO);
+        }
+        assert!(bucket.take(1.0) > Duration::ZERO);
+    }
+
+    #[test]
+    fn debt_is_waited_off_at_the_rate() {
+        let mut bucket = TokenBucket::new(10.0);
+        // a large reply goes out at once and leaves the bucket in debt
+        assert_eq!(bucket.take(10.0), Duration::ZERO);
+        let wait = bucket.take(15.0);
+        assert!(bucket.tokens < 0.0);
+        assert!(wait > Duration::from_millis(1400) && wait <= Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn refills_up_to_a_seconds_worth() {
+        let mut bucket = TokenBucket::new(10.0);
+        bucket.take(25.0);
+        assert!(!bucket.is_full());
+        age(&mut bucket, Duration::from_secs(2));
+        bucket.refill();
+        assert!(bucket.tokens > 4.9 && bucket.tokens < 5.1);
+        age(&mut bucket, Duration::from_secs(60));
+        assert!(bucket.is_full());
+        assert_eq!(bucket.tokens, 10.0);
+    }
+
+    #[test]
+    fn unset_and_zero_rates_are_not_limited() {
+        let throttle = Throttle::new(&RateLimit {
+            ops_per_sec: Some(0),
+            bytes_per_sec: None,
+        });
+        assert!(throttle.ops.is_none());
+        assert!(throttle.bytes.is_none());
+        throttle.charge(usize::MAX);
+        assert!(throttle.is_idle());
+    }
+
+    #[test]
+    fn charged_replies_make_a_client_busy() {
+        let throttle = Throttle::new(&RateLimit {
+            ops_per_sec: Some(100),
+            bytes_per_sec: Some(1000),
+        });
+        assert!(throttle.is_idle());
+        throttle.charge(500);
+        assert!(!throttle.is_idle());
+    }
+
+    #[tokio::test]
+    async fn admits_at_once_within_the_allowance() {
+        let throttle = Throttle::new(&RateLimit {
+            ops_per_sec: Some(100),
+            bytes_per_sec: Some(1000),
+        });
+        let started = Instant::now();
+        throttle.admit(500).await;
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(!throttle.is_idle());
+    }
+}
@@ -0,0 +1,308 @@
+use crate::access::AccessList;
+use crate::auth::IdMapping;
+use crate::throttle::{RateLimit, Throttle};
+use crate::context::RPCContext;
+use crate::locks::LockManager;
+use crate::rpcwire::handle_message;
+use crate::tcp::split_hostport;
+use crate::transaction_tracker::TransactionTracker;
+use crate::vfs::NFSFileSystem;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::io;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tracing::{debug, error, info};
+
+/// Largest datagram accepted, which is the largest UDP payload
+const MAX_DATAGRAM: usize = 65536;
+
+/// The most data offered and returned per READ, WRITE or READDIR, so that
+/// a reply and its headers always fit in a datagram
+const MAX_TRANSFER: u32 = 32 * 1024;
+
+/// How long replies are kept for retransmitted requests
+const REPLY_RETENTION: Duration = Duration::from_secs(30);
+
+/// How many replies are kept for retransmitted requests at most
+const MAX_CACHED_REPLIES: usize = 1024;
+
+/// How many clients are tracked before the throttles of idle ones are
+/// forgotten
+const MAX_THROTTLED_CLIENTS: usize = 1024;
+
+/// Replies recently sent, by xid and client.
+///
+/// Over UDP a lost reply makes the client send the same request again.
+/// The transaction tracker keeps the request from being processed twice,
+/// so the reply it got the first time is sent once more instead.
+struct ReplyCache {
+    replies: Mutex<HashMap<(u32, SocketAddr), CachedReply>>,
+}
+
+struct CachedReply {
+    sent: Instant,
+    reply: Arc<Vec<u8>>,
+}
+
+impl ReplyCache {
+    fn new() -> Self {
+        Self {
+            replies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, xid: u32, client: SocketAddr) -> Option<Arc<Vec<u8>>> {
+        let replies = self.replies.lock().expect("unable to lock reply cache");
+        replies.get(&(xid, client)).map(|cached| cached.reply.clone())
+    }
+
+    fn insert(&self, xid: u32, client: SocketAddr, reply: Arc<Vec<u8>>) {
+        let mut replies = self.replies.lock().expect("unable to lock reply cache");
+        let now = Instant::now();
+        replies.retain(|_, cached| now.duration_since(cached.sent) < REPLY_RETENTION);
+        if replies.len() >= MAX_CACHED_REPLIES {
+            let oldest = replies
+                .iter()
+                .min_by_key(|(_, cached)| cached.sent)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                replies.remove(&oldest);
+            }
+        }
+        replies.insert((xid, client), CachedReply { sent: now, reply });
+    }
+}
+
+/// A NFS Udp Handler, for clients which only speak NFSv3 over UDP.
+///
+/// Every datagram holds exactly one RPC message, without the record
+/// marking used over TCP. Replies have to fit in a datagram as well, so
+/// FSINFO offers transfers of at most 32K, and READ and READDIR return no
+/// more than that whatever the client asks for.
+pub struct NFSUdpListener<T: NFSFileSystem + Send + Sync + 'static> {
+    socket: Arc<UdpSocket>,
+    port: u16,
+    arcfs: Arc<T>,
+    mount_signal: Option<mpsc::Sender<bool>>,
+    export_name: Arc<String>,
+    transaction_tracker: Arc<TransactionTracker>,
+    replies: Arc<ReplyCache>,
+    id_mapping: Arc<IdMapping>,
+    access: AccessList,
+    rate_limit: RateLimit,
+    locks: Arc<LockManager>,
+    /// Throttles by client, as there are no connections to keep them with
+    throttles: Mutex<HashMap<SocketAddr, Arc<Throttle>>>,
+}
+
+impl<T: NFSFileSystem + Send + Sync + 'static> NFSUdpListener<T> {
+    /// Binds to a ipstr of the form [ip address]:port. For instance
+    /// "127.0.0.1:12000". fs is an instance of an implementation
+    /// of NFSFileSystem.
+    pub async fn bind(ipstr: &str, fs: T) -> io::Result<NFSUdpListener<T>> {
+        Self::bind_shared(ipstr, Arc::new(fs)).await
+    }
+
+    /// Like bind, for a file system which is also served elsewhere, for
+    /// instance by a NFSTcpListener.
+    pub async fn bind_shared(ipstr: &str, arcfs: Arc<T>) -> io::Result<NFSUdpListener<T>> {
+        let (ip, port) = split_hostport(ipstr)?;
+        let ipstr = format!("{ip}:{port}");
+        let socket = UdpSocket::bind(&ipstr).await?;
+        info!("Listening on {:?} (udp)", &ipstr);
+        let port = socket.local_addr()?.port();
+        Ok(NFSUdpListener {
+            socket: Arc::new(socket),
+            port,
+            arcfs,
+            mount_signal: None,
+            export_name: Arc::from("/".to_string()),
+            transaction_tracker: Arc::new(TransactionTracker::new(REPLY_RETENTION)),
+            replies: Arc::new(ReplyCache::new()),
+            id_mapping: Arc::new(IdMapping::default()),
+            access: AccessList::default(),
+            rate_limit: RateLimit::default(),
+            locks: Arc::new(LockManager::new()),
+            throttles: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sets an optional NFS export name.
+    ///
+    /// - `export_name`: The desired export name without slashes.
+    ///
+    /// Example: Name `foo` results in the export path `/foo`.
+    /// Default path is `/` if not set.
+    pub fn with_export_name<S: AsRef<str>>(&mut self, export_name: S) {
+        self.export_name = Arc::new(format!(
+            "/{}",
+            export_name
+                .as_ref()
+                .trim_end_matches('/')
+                .trim_start_matches('/')
+        ))
+    }
+
+    /// Sets the mapping applied to the AUTH_SYS credentials of every call.
+    /// Credentials are passed through unchanged if not set.
+    pub fn with_id_mapping(&mut self, id_mapping: IdMapping) {
+        self.id_mapping = Arc::new(id_mapping);
+    }
+
+    /// Sets the networks which may send requests. Datagrams from anywhere
+    /// else are dropped unanswered. Everyone may send requests if not set.
+    pub fn with_access_list(&mut self, access: AccessList) {
+        self.access = access;
+    }
+
+    /// Sets the rate every client is held to. Clients are not limited if
+    /// not set.
+    pub fn with_rate_limit(&mut self, rate_limit: RateLimit) {
+        self.rate_limit = rate_limit;
+    }
+
+    /// Sets where the advisory locks clients take are held, for a file
+    /// system which is also served elsewhere, so that clients of either
+    /// see each other's locks. Locks are held by this listener if not set.
+    pub fn with_lock_manager(&mut self, locks: Arc<LockManager>) {
+        self.locks = locks;
+    }
+
+    /// Gets the true listening port. Useful if the bound port number is 0
+    pub fn get_listen_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Gets the true listening IP
+    pub fn get_listen_ip(&self) -> IpAddr {
+        self.socket.local_addr().unwrap().ip()
+    }
+
+    /// Sets a mount listener. A "true" signal will be sent on a mount
+    /// and a "false" will be sent on an unmount
+    pub fn set_mount_listener(&mut self, signal: mpsc::Sender<bool>) {
+        self.mount_signal = Some(signal);
+    }
+
+    /// Loops forever and never returns handling all incoming datagrams.
+    pub async fn handle_forever(&self) -> io::Result<()> {
+        self.handle_until(std::future::pending()).await
+    }
+
+    /// Handles all incoming datagrams until `shutdown` completes. Then
+    /// stops receiving, finishes the calls in flight, lets the file
+    /// system shut down and returns.
+    pub async fn handle_until<F>(&self, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()> + Send,
+    {
+        self.serve_until(shutdown).await?;
+        info!("Shutting down");
+        self.arcfs.shutdown().await;
+        Ok(())
+    }
+
+    /// Handles all incoming datagrams until `shutdown` completes. Then
+    /// stops receiving, finishes the calls in flight and returns, leaving
+    /// the file system to be shut down by the caller, for one which is
+    /// also served elsewhere.
+    pub async fn serve_until<F>(&self, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()> + Send,
+    {
+        let mut in_flight = JoinSet::new();
+        tokio::pin!(shutdown);
+        // datagrams are received into one buffer and copied out at their size
+        let mut buf = vec![0; MAX_DATAGRAM];
+        let result = loop {
+            tokio::select! {
+                received = self.socket.recv_from(&mut buf) => match received {
+                    Ok((len, client)) => {
+                        self.spawn_datagram(&mut in_flight, buf[..len].to_vec(), client);
+                    }
+                    Err(e) => break Err(e),
+                },
+                Some(_) = in_flight.join_next() => {}
+                _ = &mut shutdown => break Ok(()),
+            }
+        };
+        info!("No longer receiving datagrams, finishing the calls in flight");
+        while in_flight.join_next().await.is_some() {}
+        result
+    }
+
+    /// The throttle of a client
+    fn throttle(&self, client: SocketAddr) -> Arc<Throttle> {
+        let mut throttles = self.throttles.lock().expect("unable to lock throttles");
+        if throttles.len() >= MAX_THROTTLED_CLIENTS {
+            throttles.retain(|_, throttle| !throttle.is_idle());
+        }
+        throttles
+            .entry(client)
+            .or_insert_with(|| Arc::new(Throttle::new(&self.rate_limit)))
+            .clone()
+    }
+
+    /// Serves a received datagram in the background, among `in_flight`
+    fn spawn_datagram(&self, in_flight: &mut JoinSet<()>, message: Vec<u8>, client: SocketAddr) {
+        if !self.access.permits(client.ip()) {
+            debug!("Dropping datagram from {}", client);
+            return;
+        }
+        let xid = match message.get(0..4) {
+            Some(xid) => u32::from_be_bytes(xid.try_into().unwrap()),
+            None => {
+                debug!("Dropping runt datagram from {}", client);
+                return;
+            }
+        };
+        let socket = self.socket.clone();
+        if let Some(reply) = self.replies.get(xid, client) {
+            debug!("Resending reply to retransmitted xid {} from {}", xid, client);
+            in_flight.spawn(async move {
+                let _ = socket.send_to(&reply, client).await;
+            });
+            return;
+        }
+        let context = RPCContext {
+            local_port: self.port,
+            client_addr: client.to_string(),
+            auth: crate::rpc::auth_unix::default(),
+            caller: crate::auth::Credentials::default(),
+            id_mapping: self.id_mapping.clone(),
+            vfs: self.arcfs.clone(),
+            mount_signal: self.mount_signal.clone(),
+            export_name: self.export_name.clone(),
+            transaction_tracker: self.transaction_tracker.clone(),
+            throttle: self.throttle(client),
+            locks: self.locks.clone(),
+            max_transfer: Some(MAX_TRANSFER),
+        };
+        let replies = self.replies.clone();
+        in_flight.spawn(async move {
+            let throttle = context.throttle.clone();
+            throttle.admit(message.len()).await;
+            match handle_message(message, context).await {
+                Ok(Some(mut reply)) => {
+                    throttle.charge(reply.len());
+                    // kept for retransmissions, so no larger than it is
+                    reply.shrink_to_fit();
+                    let reply = Arc::new(reply);
+                    // a reply which could not be sent would fail the same
+                    // way for every retransmission, so it is not kept
+                    match socket.send_to(&reply, client).await {
+                        Ok(_) => replies.insert(xid, client, reply),
+                        Err(e) => error!("Unable to reply to {}: {:?}", client, e),
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => error!("RPC Error from {}: {:?}", client, e),
+            }
+        });
+    }
+}
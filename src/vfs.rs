@@ -1,6 +1,7 @@
 use crate::nfs::*;
 use crate::nfs;
 use async_trait::async_trait;
+use bytes::Bytes;
 use std::cmp::Ordering;
 use std::sync::Once;
 use std::time::SystemTime;
@@ -112,6 +113,20 @@ pub trait NFSFileSystem: Sync {
     /// This method should be fast as it is used very frequently.
     async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3>;
 
+    /// The attributes of an object just before a call changes it, which
+    /// replies report with its attributes after as weak cache consistency
+    /// data. Clients only keep what they cached of the object if these are
+    /// the attributes they saw last, so a file system whose getattr may be
+    /// answered from a cache should read them afresh here.
+    async fn wcc_attr(&self, id: fileid3) -> Result<wcc_attr, nfsstat3> {
+        let attr = self.getattr(id).await?;
+        Ok(wcc_attr {
+            size: attr.size,
+            mtime: attr.mtime,
+            ctime: attr.ctime,
+        })
+    }
+
     /// Sets the attributes of an id
     /// this should return Err(nfsstat3::NFS3ERR_ROFS) if readonly
     async fn setattr(&self, id: fileid3, setattr: sattr3) -> Result<fattr3, nfsstat3>;
@@ -120,8 +135,10 @@ pub trait NFSFileSystem: Sync {
     /// Note that offset/count may go past the end of the file and that
     /// in that case, all bytes till the end of file are returned.
     /// EOF must be flagged if the end of the file is reached by the read.
+    /// The bytes are sent as they are, so a buffer read into, or a slice
+    /// of contents held in memory, is best returned without copying it.
     async fn read(&self, id: fileid3, offset: u64, count: u32)
-        -> Result<(Vec<u8>, bool), nfsstat3>;
+        -> Result<(Bytes, bool), nfsstat3>;
 
     /// Writes the contents of a file returning (bytes, EOF)
     /// Note that offset/count may go past the end of the file and that
@@ -130,6 +147,27 @@ pub trait NFSFileSystem: Sync {
     /// this should return Err(nfsstat3::NFS3ERR_ROFS)
     async fn write(&self, id: fileid3, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3>;
 
+    /// Writes the contents of a file like write(), but need not have them
+    /// on stable storage before returning. Returns how far they did reach,
+    /// UNSTABLE if not until the next commit().
+    /// The default implementation calls write(), which is stable.
+    async fn write_unstable(
+        &self,
+        id: fileid3,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(fattr3, stable_how), nfsstat3> {
+        Ok((self.write(id, offset, data).await?, stable_how::FILE_SYNC))
+    }
+
+    /// Puts what was written to a file with write_unstable() on stable
+    /// storage, count bytes from offset on, all of it if count is 0.
+    /// The default implementation has nothing to do as every write is
+    /// stable.
+    async fn commit(&self, id: fileid3, _offset: u64, _count: u32) -> Result<fattr3, nfsstat3> {
+        self.getattr(id).await
+    }
+
     /// Creates a file with the following attributes.
     /// If not supported due to readonly file system
     /// this should return Err(nfsstat3::NFS3ERR_ROFS)
@@ -157,6 +195,18 @@ pub trait NFSFileSystem: Sync {
         dirname: &filename3,
     ) -> Result<(fileid3, fattr3), nfsstat3>;
 
+    /// Makes a directory with the attributes the client asked for.
+    /// Unless implemented, the directory is made by mkdir and the
+    /// attributes are left as they are.
+    async fn mkdir_with_attrs(
+        &self,
+        dirid: fileid3,
+        dirname: &filename3,
+        _attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        self.mkdir(dirid, dirname).await
+    }
+
     /// Removes a file.
     /// If not supported due to readonly file system
     /// this should return Err(nfsstat3::NFS3ERR_ROFS)
@@ -173,6 +223,18 @@ pub trait NFSFileSystem: Sync {
         to_filename: &filename3,
     ) -> Result<(), nfsstat3>;
 
+    /// Makes a hard link named linkname in linkdirid to the file fileid.
+    /// If not supported due to readonly file system
+    /// this should return Err(nfsstat3::NFS3ERR_ROFS)
+    async fn link(
+        &self,
+        _fileid: fileid3,
+        _linkdirid: fileid3,
+        _linkname: &filename3,
+    ) -> Result<(), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
     /// Returns the contents of a directory with pagination.
     /// Directory listing should be deterministic.
     /// Up to max_entries may be returned, and start_after is used
@@ -200,6 +262,24 @@ pub trait NFSFileSystem: Sync {
         ))
     }
 
+    /// Simple version of readdir, from where an earlier page ended.
+    /// Pagination works the same way as in readdir. Unless implemented,
+    /// the first page is listed by readdir_simple and the others by
+    /// readdir.
+    async fn readdir_simple_paged(
+        &self,
+        dirid: fileid3,
+        start_after: fileid3,
+        count: usize,
+    ) -> Result<ReadDirSimpleResult, nfsstat3> {
+        if start_after == 0 {
+            return self.readdir_simple(dirid, count).await;
+        }
+        Ok(ReadDirSimpleResult::from_readdir_result(
+            &self.readdir(dirid, start_after, count).await?,
+        ))
+    }
+
     /// Makes a symlink with the following attributes.
     /// If not supported due to readonly file system
     /// this should return Err(nfsstat3::NFS3ERR_ROFS)
@@ -211,6 +291,21 @@ pub trait NFSFileSystem: Sync {
         attr: &sattr3,
     ) -> Result<(fileid3, fattr3), nfsstat3>;
 
+    /// Makes a special file: a named pipe, a socket or a device. For
+    /// devices spec holds the major and minor device numbers.
+    /// If not supported due to readonly file system
+    /// this should return Err(nfsstat3::NFS3ERR_ROFS)
+    async fn mknod(
+        &self,
+        _dirid: fileid3,
+        _filename: &filename3,
+        _ftype: ftype3,
+        _spec: specdata3,
+        _attr: &sattr3,
+    ) -> Result<(fileid3, fattr3), nfsstat3> {
+        Err(nfsstat3::NFS3ERR_NOTSUPP)
+    }
+
     /// Reads a symlink
     async fn readlink(&self, id: fileid3) -> Result<nfspath3, nfsstat3>;
 
@@ -244,6 +339,27 @@ pub trait NFSFileSystem: Sync {
         Ok(res)
     }
 
+    /// Get dynamic file system information such as the total and free
+    /// space. The default implementation reports a very large, mostly
+    /// empty file system.
+    async fn fsstat(&self, fileid: fileid3) -> Result<fsstat3, nfsstat3> {
+        let obj_attr: nfs::post_op_attr = match self.getattr(fileid).await {
+            Ok(v) => nfs::post_op_attr::attributes(v),
+            Err(_) => nfs::post_op_attr::Void,
+        };
+        let res = fsstat3 {
+            obj_attributes: obj_attr,
+            tbytes: 1024 * 1024 * 1024 * 1024,
+            fbytes: 1024 * 1024 * 1024 * 1024,
+            abytes: 1024 * 1024 * 1024 * 1024,
+            tfiles: 1024 * 1024 * 1024,
+            ffiles: 1024 * 1024 * 1024,
+            afiles: 1024 * 1024 * 1024,
+            invarsec: u32::MAX,
+        };
+        Ok(res)
+    }
+
     /// Converts the fileid to an opaque NFS file handle. Optional.
     fn id_to_fh(&self, id: fileid3) -> nfs_fh3 {
         let gennum = get_generation_number();
@@ -280,8 +396,15 @@ pub trait NFSFileSystem: Sync {
         Ok(fid)
     }
 
+    /// The write verifier. It must change whenever what was written
+    /// unstable may have been lost, so that clients write it again: by
+    /// default as the server restarts.
     fn serverid(&self) -> cookieverf3 {
         let gennum = get_generation_number();
         gennum.to_le_bytes()
     }
+
+    /// Called once the server has stopped accepting connections, so that
+    /// pending writes can be flushed and state persisted.  Optional.
+    async fn shutdown(&self) {}
 }
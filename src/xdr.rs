@@ -1,11 +1,11 @@
 use byteorder::BigEndian;
 use byteorder::{ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
 use std::io::{Read, Write};
 pub type XDREndian = BigEndian;
 use crate::nfs::nfsstring;
 
 /// See https://datatracker.ietf.org/doc/html/rfc1014
-
 #[allow(clippy::upper_case_acronyms)]
 pub trait XDR {
     fn serialize<R: Write>(&self, dest: &mut R) -> std::io::Result<()>;
@@ -131,6 +131,29 @@ impl XDR for Vec<u8> {
     }
 }
 
+/// Opaque data held in a shared buffer, as read data is
+impl XDR for Bytes {
+    fn serialize<R: Write>(&self, dest: &mut R) -> std::io::Result<()> {
+        assert!(self.len() < u32::MAX as usize);
+        let length = self.len() as u32;
+        length.serialize(dest)?;
+        dest.write_all(self)?;
+        // write padding
+        let pad = ((4 - length % 4) % 4) as usize;
+        let zeros: [u8; 4] = [0, 0, 0, 0];
+        if pad > 0 {
+            dest.write_all(&zeros[..pad])?;
+        }
+        Ok(())
+    }
+    fn deserialize<R: Read>(&mut self, src: &mut R) -> std::io::Result<()> {
+        let mut data = Vec::new();
+        data.deserialize(src)?;
+        *self = Bytes::from(data);
+        Ok(())
+    }
+}
+
 impl XDR for nfsstring {
     fn serialize<R: Write>(&self, dest: &mut R) -> std::io::Result<()> {
         self.0.serialize(dest)